@@ -0,0 +1,190 @@
+//! Measures Base 64 and hex conversion throughput.
+//!
+//! Run via `cargo bench --bench codec`. The Base 64 benchmark decodes
+//! objects formatted like the content of publish elements in RRDP
+//! snapshots both via the crate and via a streaming decoder skipping
+//! white space, which is how the crate used to decode them. The hex
+//! benchmarks compare against conversion via the standard library’s
+//! formatting and parsing.
+
+use std::cmp;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+use base64::Engine;
+use rpki::util::{base64 as b64, hex};
+
+
+/// The number of objects decoded in the Base 64 benchmark.
+const OBJECTS: usize = 20_000;
+
+/// The size of each object in the Base 64 benchmark.
+const OBJECT_SIZE: usize = 2048;
+
+/// The number of hashes converted in the hex benchmark.
+const HASHES: usize = 1_000_000;
+
+/// The number of times each measurement is repeated.
+const RUNS: usize = 5;
+
+
+fn main() {
+    base64();
+    hex();
+}
+
+fn base64() {
+    let objects: Vec<(Vec<u8>, String)> = (0..OBJECTS).map(|i| {
+        let data: Vec<u8> = (0..OBJECT_SIZE).map(|x| {
+            (x * 7 + i) as u8
+        }).collect();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(
+            &data
+        );
+        let mut wrapped = String::with_capacity(encoded.len() * 2);
+        for line in encoded.as_bytes().chunks(76) {
+            wrapped.push_str("\n      ");
+            wrapped.push_str(std::str::from_utf8(line).unwrap());
+        }
+        (data, wrapped)
+    }).collect();
+
+    let (len, fast) = measure(|| {
+        objects.iter().map(|(data, wrapped)| {
+            let res = b64::Xml.decode(wrapped).unwrap();
+            assert_eq!(&res, data);
+            res.len()
+        }).sum::<usize>()
+    });
+    assert_eq!(len, OBJECTS * OBJECT_SIZE);
+    let (len, slow) = measure(|| {
+        objects.iter().map(|(data, wrapped)| {
+            let mut res = Vec::new();
+            base64::read::DecoderReader::new(
+                SkipWhitespace::new(wrapped),
+                &base64::engine::general_purpose::STANDARD
+            ).read_to_end(&mut res).unwrap();
+            assert_eq!(&res, data);
+            res.len()
+        }).sum::<usize>()
+    });
+    assert_eq!(len, OBJECTS * OBJECT_SIZE);
+    report("base64 decode", OBJECTS * OBJECT_SIZE, fast, slow);
+}
+
+fn hex() {
+    let hashes: Vec<[u8; 32]> = (0..HASHES).map(|x| {
+        let mut res = [0u8; 32];
+        res[..8].copy_from_slice(&(x as u64).to_be_bytes());
+        res
+    }).collect();
+
+    let (fast_out, fast) = measure(|| {
+        let mut buf = [0u8; 64];
+        let mut res = 0;
+        for hash in &hashes {
+            res += hex::encode_lower(hash, &mut buf).len();
+        }
+        res
+    });
+    let (slow_out, slow) = measure(|| {
+        let mut buf = String::with_capacity(64);
+        let mut res = 0;
+        for hash in &hashes {
+            buf.clear();
+            for ch in hash {
+                write!(buf, "{:02x}", ch).unwrap();
+            }
+            res += buf.len();
+        }
+        res
+    });
+    assert_eq!(fast_out, slow_out);
+    report("hex encode", HASHES * 32, fast, slow);
+
+    let encoded: Vec<String> = hashes.iter().map(|hash| {
+        let mut buf = [0u8; 64];
+        hex::encode_lower(hash, &mut buf).to_string()
+    }).collect();
+    let (_, fast) = measure(|| {
+        let mut buf = [0u8; 32];
+        for item in &encoded {
+            hex::decode(item.as_bytes(), &mut buf).unwrap();
+        }
+    });
+    let (_, slow) = measure(|| {
+        let mut buf = [0u8; 32];
+        for item in &encoded {
+            for (d, s) in buf.iter_mut().zip(item.as_bytes().chunks(2)) {
+                *d = u8::from_str_radix(
+                    std::str::from_utf8(s).unwrap(), 16
+                ).unwrap();
+            }
+        }
+    });
+    report("hex decode", HASHES * 32, fast, slow);
+}
+
+/// Runs `op` a few times and returns its result and the fastest time.
+fn measure<T>(mut op: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = Duration::MAX;
+    let mut res = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        res = Some(op());
+        best = cmp::min(best, start.elapsed());
+    }
+    (res.unwrap(), best)
+}
+
+fn report(name: &str, size: usize, fast: Duration, slow: Duration) {
+    let mib = size as f64 / (1024. * 1024.);
+    println!(
+        "{:<14} {:>8.1} MiB/s (naive {:>8.1} MiB/s, {:.1}x)",
+        name,
+        mib / fast.as_secs_f64(),
+        mib / slow.as_secs_f64(),
+        slow.as_secs_f64() / fast.as_secs_f64(),
+    );
+}
+
+/// A reader skipping white space.
+///
+/// This is the reader the crate used to decode Base 64 in XML with.
+struct SkipWhitespace<'a> {
+    splitter: std::str::SplitAsciiWhitespace<'a>,
+    current: &'a [u8],
+}
+
+impl<'a> SkipWhitespace<'a> {
+    fn new(s: &'a str) -> Self {
+        SkipWhitespace { splitter: s.split_ascii_whitespace(), current: b"" }
+    }
+}
+
+impl<'a> Read for SkipWhitespace<'a> {
+    fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, io::Error> {
+        let mut res = 0;
+        loop {
+            if self.current.is_empty() {
+                match self.splitter.next() {
+                    Some(data) => self.current = data.as_bytes(),
+                    None => return Ok(res)
+                }
+            }
+            if self.current.len() < buf.len() {
+                let len = self.current.len();
+                buf[..len].copy_from_slice(self.current);
+                res += len;
+                buf = &mut buf[len..];
+                self.current = b"";
+            }
+            else {
+                let (head, tail) = self.current.split_at(buf.len());
+                buf.copy_from_slice(head);
+                self.current = tail;
+                return Ok(res + head.len())
+            }
+        }
+    }
+}
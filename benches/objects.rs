@@ -0,0 +1,40 @@
+//! Measures parsing and validating throughput of repository objects.
+//!
+//! Run via `cargo bench --features bench`. The objects are taken from the
+//! directory given in the `RPKI_BENCH_CORPUS` environment variable or
+//! from the test data if it isn’t set. The number of iterations can be
+//! given in `RPKI_BENCH_ITERATIONS`.
+
+use std::env;
+use rpki::repository::bench::{Benchmark, Corpus};
+
+
+fn main() {
+    let path = env::var("RPKI_BENCH_CORPUS").unwrap_or_else(|_| {
+        "test-data/repository".into()
+    });
+    let iterations = match env::var("RPKI_BENCH_ITERATIONS") {
+        Ok(value) => match value.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                eprintln!("Invalid RPKI_BENCH_ITERATIONS: {}", value);
+                return
+            }
+        }
+        Err(_) => 1000,
+    };
+    let corpus = match Corpus::load_dir(&path) {
+        Ok(corpus) => corpus,
+        Err(err) => {
+            eprintln!("Can’t load corpus from {}: {}", path, err);
+            return
+        }
+    };
+    println!("Corpus: {} objects from {}", corpus.len(), path);
+
+    let mut bench = Benchmark::new();
+    bench.set_iterations(iterations);
+    println!("\nParsing:\n{}", bench.run(&corpus));
+    bench.set_validate(true);
+    println!("Validating:\n{}", bench.run(&corpus));
+}
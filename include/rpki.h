@@ -0,0 +1,129 @@
+/*
+ * rpki.h - C API for parsing and validating RPKI repository objects.
+ *
+ * This header declares the functions provided by the rpki crate when its
+ * "capi" feature is enabled. See the documentation of the crate's capi
+ * module for details.
+ *
+ * All objects are handed out as opaque pointers which must be released
+ * via the matching _free function. Functions returning a pointer return
+ * a null pointer if the operation failed. Times are given and returned as
+ * seconds since the Unix epoch.
+ */
+
+#ifndef RPKI_H
+#define RPKI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+
+/*------------ Certificates ------------------------------------------------*/
+
+/* A decoded resource certificate. */
+typedef struct RpkiCert RpkiCert;
+
+/* A validated resource certificate. */
+typedef struct RpkiResourceCert RpkiResourceCert;
+
+RpkiCert *rpki_cert_decode(const uint8_t *data, size_t len);
+void rpki_cert_free(RpkiCert *cert);
+bool rpki_cert_is_ca(const RpkiCert *cert);
+int64_t rpki_cert_not_before(const RpkiCert *cert);
+int64_t rpki_cert_not_after(const RpkiCert *cert);
+RpkiResourceCert *rpki_cert_validate_ta(
+    const RpkiCert *cert, bool strict, int64_t now
+);
+RpkiResourceCert *rpki_cert_validate_ca(
+    const RpkiCert *cert, const RpkiResourceCert *issuer,
+    bool strict, int64_t now
+);
+void rpki_resource_cert_free(RpkiResourceCert *cert);
+
+
+/*------------ ROAs --------------------------------------------------------*/
+
+/* A decoded ROA. */
+typedef struct RpkiRoa RpkiRoa;
+
+/* A prefix of a ROA. */
+typedef struct RpkiRoaPrefix {
+    /* The address family, 4 for IPv4 or 6 for IPv6. */
+    uint8_t family;
+
+    /* The address in network byte order. For IPv4, only the first four
+     * octets are used. */
+    uint8_t addr[16];
+
+    /* The length of the prefix. */
+    uint8_t prefix_len;
+
+    /* The maximum prefix length. */
+    uint8_t max_len;
+} RpkiRoaPrefix;
+
+RpkiRoa *rpki_roa_decode(const uint8_t *data, size_t len, bool strict);
+void rpki_roa_free(RpkiRoa *roa);
+uint32_t rpki_roa_as_id(const RpkiRoa *roa);
+size_t rpki_roa_prefix_count(const RpkiRoa *roa);
+bool rpki_roa_prefix(
+    const RpkiRoa *roa, size_t index, RpkiRoaPrefix *prefix
+);
+
+/* Returns 1 if the ROA is valid, 0 if it is invalid, and -1 if any of the
+ * arguments is invalid.
+ *
+ * If crl is not NULL, it must point to crl_len octets of the DER encoded
+ * CRL of the issuer and revocation of the ROA is checked against it. If it
+ * is NULL, revocation is not checked. */
+int rpki_roa_validate(
+    const RpkiRoa *roa, const RpkiResourceCert *issuer,
+    const uint8_t *crl, size_t crl_len, bool strict, int64_t now
+);
+
+
+/*------------ Manifests ---------------------------------------------------*/
+
+/* A decoded manifest. */
+typedef struct RpkiManifest RpkiManifest;
+
+/* An entry in the file list of a manifest.
+ *
+ * Both the name and the hash point into the manifest and remain valid
+ * until it is released. The name is not terminated by a null octet. */
+typedef struct RpkiManifestFile {
+    const uint8_t *name;
+    size_t name_len;
+    const uint8_t *hash;
+    size_t hash_len;
+} RpkiManifestFile;
+
+RpkiManifest *rpki_manifest_decode(
+    const uint8_t *data, size_t len, bool strict
+);
+void rpki_manifest_free(RpkiManifest *manifest);
+int64_t rpki_manifest_this_update(const RpkiManifest *manifest);
+int64_t rpki_manifest_next_update(const RpkiManifest *manifest);
+size_t rpki_manifest_file_count(const RpkiManifest *manifest);
+bool rpki_manifest_file(
+    const RpkiManifest *manifest, size_t index, RpkiManifestFile *file
+);
+
+/* Returns 1 if the manifest is valid, 0 if it is invalid, and -1 if any
+ * of the arguments is invalid. */
+int rpki_manifest_validate(
+    const RpkiManifest *manifest, const RpkiResourceCert *issuer,
+    bool strict, int64_t now
+);
+
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RPKI_H */
@@ -0,0 +1,444 @@
+//! An audit log for publication protocol messages.
+//!
+//! Publication servers and CAs may want to keep a history of all the
+//! publication protocol messages they have exchanged, for instance in
+//! order to be able to reconstruct what happened after an incident. This
+//! module defines a simple append-only format for such a log.
+//!
+//! The log is a sequence of records. Each record is preceded by its length
+//! as a 32 bit unsigned integer in network byte order and contains:
+//!
+//! * a format version, currently always 1, as a single octet,
+//! * the time the message was sent or received as milliseconds since the
+//!   Unix epoch as a 64 bit signed integer in network byte order,
+//! * the direction of the message as a single octet, 0 for received and
+//!   1 for sent messages,
+//! * the SHA-256 hash of the CMS object carrying the message, and
+//! * a human-readable summary of the message in UTF-8.
+//!
+//! Since the log doesn’t have a header, new records can simply be appended
+//! to an existing file. Records can be written via an [`AuditWriter`] and
+//! read back via an [`AuditReader`]. A record is an [`AuditRecord`] which
+//! can be created directly from a [`PublicationCms`].
+//!
+//! The log does not contain the messages themselves. If these are needed,
+//! the CMS objects can be stored separately, keyed by their hash.
+
+use std::{error, fmt, io};
+use chrono::{TimeZone, Utc};
+use crate::repository::x509::Time;
+use crate::rrdp::Hash;
+use super::publication::PublicationCms;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The format version of the records written.
+const VERSION: u8 = 1;
+
+/// The length of a record without its summary.
+const FIXED_LEN: usize = 1 + 8 + 1 + 32;
+
+/// The maximum length of a record.
+///
+/// Longer records are rejected by both reader and writer so that a broken
+/// length doesn’t lead to excessive allocations.
+pub const MAX_RECORD_LEN: usize = 1 << 20;
+
+
+//------------ Direction -----------------------------------------------------
+
+/// The direction of a logged message.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Direction {
+    /// The message was received from the peer.
+    Received,
+
+    /// The message was sent to the peer.
+    Sent,
+}
+
+impl Direction {
+    /// Returns the octet representing the direction.
+    fn to_u8(self) -> u8 {
+        match self {
+            Direction::Received => 0,
+            Direction::Sent => 1,
+        }
+    }
+
+    /// Returns the direction for an octet if it is valid.
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Direction::Received),
+            1 => Some(Direction::Sent),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Direction::Received => "received",
+            Direction::Sent => "sent",
+        })
+    }
+}
+
+
+//------------ AuditRecord ---------------------------------------------------
+
+/// A single record of the audit log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditRecord {
+    /// The time the message was sent or received.
+    time: Time,
+
+    /// The direction of the message.
+    direction: Direction,
+
+    /// The hash of the CMS object carrying the message.
+    hash: Hash,
+
+    /// A summary of the message.
+    summary: String,
+}
+
+impl AuditRecord {
+    /// Creates a new record from its components.
+    ///
+    /// Only the milliseconds of `time` are kept when the record is
+    /// written.
+    pub fn new(
+        time: Time, direction: Direction, hash: Hash, summary: String
+    ) -> Self {
+        AuditRecord { time, direction, hash, summary }
+    }
+
+    /// Creates a new record for a publication CMS object.
+    ///
+    /// The hash is taken over the encoded CMS object and the summary
+    /// describes the kind of message and its elements.
+    pub fn from_cms(
+        time: Time, direction: Direction, cms: &PublicationCms
+    ) -> Self {
+        Self::new(
+            time, direction,
+            Hash::from_data(cms.to_bytes().as_ref()),
+            cms.message().summary(),
+        )
+    }
+
+    /// Returns the time the message was sent or received.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Returns the direction of the message.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the hash of the CMS object carrying the message.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Returns the summary of the message.
+    pub fn summary(&self) -> &str {
+        &self.summary
+    }
+
+    /// Returns the length of the encoded record without the length prefix.
+    fn encoded_len(&self) -> usize {
+        FIXED_LEN + self.summary.len()
+    }
+
+    /// Parses the record from its encoded content.
+    ///
+    /// The content must be given without the length prefix.
+    fn parse(data: &[u8]) -> Result<Self, AuditError> {
+        if data.len() < FIXED_LEN {
+            return Err(AuditError::Malformed)
+        }
+        if data[0] != VERSION {
+            return Err(AuditError::UnknownVersion(data[0]))
+        }
+        let mut millis = [0u8; 8];
+        millis.copy_from_slice(&data[1..9]);
+        let millis = i64::from_be_bytes(millis);
+        let time = Utc.timestamp_opt(
+            millis.div_euclid(1000),
+            (millis.rem_euclid(1000) * 1_000_000) as u32
+        ).single().ok_or(AuditError::Malformed)?;
+        let direction = Direction::from_u8(
+            data[9]
+        ).ok_or(AuditError::Malformed)?;
+        let hash = Hash::try_from(
+            &data[10..FIXED_LEN]
+        ).map_err(|_| AuditError::Malformed)?;
+        let summary = String::from_utf8(
+            data[FIXED_LEN..].into()
+        ).map_err(|_| AuditError::Malformed)?;
+        Ok(AuditRecord { time: time.into(), direction, hash, summary })
+    }
+}
+
+
+//------------ AuditWriter ---------------------------------------------------
+
+/// A type writing audit records.
+///
+/// The writer writes each record in a single call to the underlying
+/// writer’s `write_all`. When using a file, it should be opened in append
+/// mode.
+#[derive(Debug)]
+pub struct AuditWriter<W> {
+    /// The underlying writer.
+    target: W,
+}
+
+impl<W: io::Write> AuditWriter<W> {
+    /// Creates a new audit writer writing to the given target.
+    pub fn new(target: W) -> Self {
+        AuditWriter { target }
+    }
+
+    /// Writes a record.
+    ///
+    /// Fails if the record’s summary is too long to fit into a record of
+    /// at most [`MAX_RECORD_LEN`] octets.
+    pub fn write(&mut self, record: &AuditRecord) -> Result<(), io::Error> {
+        let len = record.encoded_len();
+        if len > MAX_RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "audit record too long"
+            ))
+        }
+        let mut data = Vec::with_capacity(len + 4);
+        data.extend_from_slice(&(len as u32).to_be_bytes());
+        data.push(VERSION);
+        data.extend_from_slice(&record.time.timestamp_millis().to_be_bytes());
+        data.push(record.direction.to_u8());
+        data.extend_from_slice(record.hash.as_slice());
+        data.extend_from_slice(record.summary.as_bytes());
+        self.target.write_all(&data)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.target.flush()
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.target
+    }
+
+    /// Converts the audit writer into the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.target
+    }
+}
+
+
+//------------ AuditReader ---------------------------------------------------
+
+/// A type reading audit records.
+///
+/// Records can either be read one by one via [`read`][Self::read] or by
+/// using the reader as an iterator.
+#[derive(Debug)]
+pub struct AuditReader<R> {
+    /// The underlying reader.
+    source: R,
+
+    /// Has an error occured?
+    ///
+    /// If so, the iterator will not return any more records.
+    failed: bool,
+}
+
+impl<R: io::Read> AuditReader<R> {
+    /// Creates a new audit reader reading from the given source.
+    pub fn new(source: R) -> Self {
+        AuditReader { source, failed: false }
+    }
+
+    /// Reads the next record.
+    ///
+    /// Returns `Ok(None)` if the end of the log has been reached cleanly.
+    /// If the log ends in the middle of a record, which can happen if
+    /// writing was interrupted, returns [`AuditError::Truncated`].
+    pub fn read(&mut self) -> Result<Option<AuditRecord>, AuditError> {
+        let mut len = [0u8; 4];
+        match self.read_exact(&mut len)? {
+            0 => return Ok(None),
+            4 => { }
+            _ => return Err(AuditError::Truncated)
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(AuditError::Malformed)
+        }
+        let mut data = vec![0u8; len];
+        if self.read_exact(&mut data)? != len {
+            return Err(AuditError::Truncated)
+        }
+        AuditRecord::parse(&data).map(Some)
+    }
+
+    /// Reads as much of `buf` as possible.
+    ///
+    /// Returns the number of octets read which is less than the length of
+    /// `buf` only if the end of the source has been reached.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            match self.source.read(&mut buf[pos..]) {
+                Ok(0) => break,
+                Ok(read) => pos += read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(pos)
+    }
+
+    /// Converts the audit reader into the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+impl<R: io::Read> Iterator for AuditReader<R> {
+    type Item = Result<AuditRecord, AuditError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None
+        }
+        match self.read() {
+            Ok(res) => res.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ AuditError ----------------------------------------------------
+
+/// An error happened while reading an audit log.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AuditError {
+    /// Reading from the underlying source failed.
+    Io(io::Error),
+
+    /// The log ended in the middle of a record.
+    Truncated,
+
+    /// A record used an unknown format version.
+    UnknownVersion(u8),
+
+    /// A record was malformed.
+    Malformed,
+}
+
+impl From<io::Error> for AuditError {
+    fn from(err: io::Error) -> Self {
+        AuditError::Io(err)
+    }
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuditError::Io(ref err) => err.fmt(f),
+            AuditError::Truncated => f.write_str("truncated audit record"),
+            AuditError::UnknownVersion(version) => {
+                write!(f, "unknown audit record version {}", version)
+            }
+            AuditError::Malformed => f.write_str("malformed audit record"),
+        }
+    }
+}
+
+impl error::Error for AuditError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            AuditError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn record(summary: &str, direction: Direction) -> AuditRecord {
+        AuditRecord::new(
+            Time::from_str("2023-05-01T12:00:00.123Z").unwrap(),
+            direction,
+            Hash::from_data(summary.as_bytes()),
+            summary.into()
+        )
+    }
+
+    #[test]
+    fn write_and_read() {
+        let records = [
+            record("list query", Direction::Received),
+            record("list reply with 0 elements", Direction::Sent),
+            record("", Direction::Received),
+        ];
+        let mut writer = AuditWriter::new(Vec::new());
+        for item in &records {
+            writer.write(item).unwrap();
+        }
+        let data = writer.into_inner();
+        let read = AuditReader::new(data.as_slice()).collect::<Result<
+            Vec<_>, _
+        >>().unwrap();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut writer = AuditWriter::new(Vec::new());
+        writer.write(&record("list query", Direction::Received)).unwrap();
+        writer.write(&record("success reply", Direction::Sent)).unwrap();
+        let mut data = writer.into_inner();
+        data.truncate(data.len() - 3);
+
+        let mut reader = AuditReader::new(data.as_slice());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(), Some(Err(AuditError::Truncated))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut writer = AuditWriter::new(Vec::new());
+        writer.write(&record("list query", Direction::Received)).unwrap();
+        let mut data = writer.into_inner();
+        data[4] = 2;
+        assert!(matches!(
+            AuditReader::new(data.as_slice()).read(),
+            Err(AuditError::UnknownVersion(2))
+        ));
+    }
+}
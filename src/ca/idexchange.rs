@@ -6,7 +6,7 @@
 
 use std::borrow;
 use std::convert::{Infallible, TryFrom};
-use std::fmt;
+use std::{error, fmt};
 use std::io;
 use std::path::PathBuf;
 use std::str::from_utf8;
@@ -21,6 +21,7 @@ use crate::ca::publication::Base64;
 use crate::repository::error::ValidationError;
 use crate::repository::x509::Time;
 use crate::uri;
+use crate::util::intern::Interner;
 use crate::xml;
 use crate::xml::decode::{Error as XmlError, Name};
 
@@ -132,6 +133,11 @@ impl<T> Handle<T> {
         Handle::new(self.name)
     }
 
+    /// Returns a copy of the handle sharing memory via an interner.
+    pub fn intern(&self, interner: &mut Interner) -> Self {
+        Handle::new(interner.name(&self.name))
+    }
+
     /// We replace "/" with "+" and "\" with "=" to make file system
     /// safe names.
     pub fn to_path_buf(&self) -> PathBuf {
@@ -1228,6 +1234,7 @@ pub fn validate_idcert_at(
 //------------ Error ---------------------------------------------------------
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidXml(xml::decode::Error),
     InvalidVersion,
@@ -1254,6 +1261,18 @@ impl fmt::Display for Error {
     }
 }
 
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidXml(e) => Some(e),
+            Error::InvalidTaBase64(e) => Some(e),
+            Error::InvalidTaCertEncoding(e) => Some(e),
+            Error::InvalidUri(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<xml::decode::Error> for Error {
     fn from(e: xml::decode::Error) -> Self {
         Error::InvalidXml(e)
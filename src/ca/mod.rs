@@ -11,9 +11,17 @@
 #![cfg(feature = "ca")]
 
 
+pub mod audit;
 pub mod csr;
 pub mod idcert;
 pub mod idexchange;
 pub mod provisioning;
 pub mod publication;
+pub mod pubpoint;
+pub mod pubserver;
+pub mod quirks;
+pub mod receipt;
+pub mod reissue;
+pub mod server;
 pub mod sigmsg;
+pub mod trace;
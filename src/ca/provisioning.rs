@@ -1,20 +1,27 @@
 //! Support RFC 6492 Provisioning Protocol (aka up-down)
 
+use std::collections::HashMap;
 use std::convert::{Infallible, TryFrom};
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::{fmt, io};
+use std::{error, fmt, io};
 
 use bcder::decode::DecodeError;
 use bytes::Bytes;
 use chrono::{DateTime, SecondsFormat, Utc};
+use log::debug;
 use serde::de;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::crypto::{KeyIdentifier, PublicKey, Signer, SigningError};
+use crate::crypto::{
+    AlgorithmSuite, KeyIdentifier, PublicKey, Signer, SigningError
+};
 use crate::repository::cert::Cert;
 use crate::repository::error::ValidationError;
+use crate::repository::options::{
+    SigningTimeError, SigningTimeWindow, binary_time,
+};
 use crate::repository::resources::{
     AsBlocks, Ipv4Blocks, Ipv6Blocks, ResourceSet
 };
@@ -28,14 +35,16 @@ use crate::xml::encode;
 use super::csr::RpkiCaCsr;
 use super::idexchange::RecipientHandle;
 use super::idexchange::SenderHandle;
-use super::sigmsg::SignedMessage;
+use super::sigmsg::{EeCertPolicy, SignedMessage};
+
+/// The version of the provisioning protocol supported by this crate.
+pub const VERSION: &str = "1";
 
 // Constants for the RFC 6492 XML
-const VERSION: &str = "1";
 const NS: &[u8] = b"http://www.apnic.net/specs/rescerts/up-down/";
 
 // Content-type for HTTP(s) exchanges
-pub const CONTENT_TYPE: &str = "application/rpki-updown";
+pub const CONTENT_TYPE: &str = crate::util::media::RPKI_UPDOWN;
 
 //------------ ProvisioningCms -----------------------------------------------
 
@@ -68,6 +77,32 @@ impl ProvisioningCms {
         })
     }
 
+    /// Creates a provisioning CMS using the given EE certificate policy.
+    ///
+    /// This is the same as [`create`][Self::create] but allows reusing a
+    /// long-lived EE certificate instead of creating a one-off one.
+    pub fn create_with_policy<S: Signer>(
+        message: Message,
+        signing_key: &S::KeyId,
+        policy: &EeCertPolicy<S::KeyId>,
+        signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        let data = message.to_xml_bytes();
+        let validity = Validity::new(
+            Time::five_minutes_ago(), Time::five_minutes_from_now()
+        );
+
+        let signed_msg = SignedMessage::create_with_policy(
+            data, validity, signing_key, policy,
+            AlgorithmSuite::default(), signer
+        )?;
+
+        Ok(ProvisioningCms {
+            signed_msg,
+            message,
+        })
+    }
+
     /// Unpack into its SignedMessage and Message
     pub fn unpack(self) -> (SignedMessage, Message) {
         (self.signed_msg, self.message)
@@ -93,7 +128,14 @@ impl ProvisioningCms {
         ).map_err(Error::CmsDecode)?;
 
         let content = signed_msg.content().to_bytes();
-        let message = Message::decode(content.as_ref())?;
+        let message = Message::decode(content.as_ref()).map_err(|err| {
+            debug!("RFC 6492: cannot decode message: {}", err);
+            err
+        })?;
+        debug!(
+            "RFC 6492: received {} message from '{}' to '{}'",
+            message.payload.payload_type(), message.sender, message.recipient
+        );
 
         Ok(ProvisioningCms {
             signed_msg,
@@ -110,6 +152,98 @@ impl ProvisioningCms {
             .validate_at(issuer_key, when)
             .map_err(|e| e.into())
     }
+
+    /// Validates the CMS including its signing time.
+    ///
+    /// See [`SignedMessage::validate_with_signing_time_at`] for details.
+    /// To protect against replayed messages, use a [`SigningTimeTracker`]
+    /// after successful validation.
+    pub fn validate_with_signing_time_at(
+        &self, issuer_key: &PublicKey, window: &SigningTimeWindow, when: Time
+    ) -> Result<(), Error> {
+        self.signed_msg
+            .validate_with_signing_time_at(issuer_key, window, when)
+            .map_err(|e| e.into())
+    }
+
+    /// Returns the signing time of the CMS if available.
+    ///
+    /// This is the signing-time attribute if present or the
+    /// binary-signing-time attribute otherwise.
+    pub fn signing_time(&self) -> Option<Time> {
+        self.signed_msg.signing_time().or_else(|| {
+            self.signed_msg.binary_signing_time().and_then(|time| {
+                binary_time(time).ok()
+            })
+        })
+    }
+}
+
+
+//------------ SigningTimeTracker --------------------------------------------
+
+/// Keeps track of the signing times of provisioning messages.
+///
+/// RFC 6492 messages are not bound to a session, so an attacker who
+/// captured a message could send it again later as long as its EE
+/// certificate is valid. The tracker prevents this by remembering the
+/// signing time of the latest message for each pair of sender and
+/// recipient and only accepting messages signed strictly later.
+///
+/// Since signing times have a resolution of one second, this rejects a
+/// second message signed within the same second, which the sender will
+/// have to retry.
+#[derive(Clone, Debug, Default)]
+pub struct SigningTimeTracker {
+    latest: HashMap<(SenderHandle, RecipientHandle), Time>,
+}
+
+impl SigningTimeTracker {
+    /// Creates a new, empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a tracker from previously seen signing times.
+    ///
+    /// This can be used to restore the tracker’s state after a restart.
+    pub fn from_latest<I>(latest: I) -> Self
+    where I: IntoIterator<Item = (SenderHandle, RecipientHandle, Time)> {
+        SigningTimeTracker {
+            latest: latest.into_iter().map(|(sender, recipient, time)| {
+                ((sender, recipient), time)
+            }).collect()
+        }
+    }
+
+    /// Returns the latest signing time seen for the sender and recipient.
+    pub fn latest(
+        &self, sender: &SenderHandle, recipient: &RecipientHandle
+    ) -> Option<Time> {
+        self.latest.get(&(sender.clone(), recipient.clone())).copied()
+    }
+
+    /// Checks the CMS’s signing time and records it if acceptable.
+    ///
+    /// The CMS must have a signing time and it must be later than that of
+    /// the latest CMS accepted for the same sender and recipient. The CMS
+    /// should have been validated already, as otherwise a forged message
+    /// could be used to block legitimate ones.
+    pub fn check(
+        &mut self, cms: &ProvisioningCms
+    ) -> Result<(), SigningTimeError> {
+        let time = cms.signing_time().ok_or(SigningTimeError::Missing)?;
+        let key = (
+            cms.message().sender().clone(), cms.message().recipient().clone()
+        );
+        if let Some(latest) = self.latest.get(&key) {
+            if time <= *latest {
+                return Err(SigningTimeError::NotMonotonic(*latest))
+            }
+        }
+        self.latest.insert(key, time);
+        Ok(())
+    }
 }
 
 //------------ Message -------------------------------------------------------
@@ -270,24 +404,77 @@ impl Message {
 /// # Decoding from XML
 ///
 impl Message {
+    /// Parses an RFC 6492 <message /> received from an untrusted source.
+    ///
+    /// This is identical to [`decode`][Self::decode] but reads at most
+    /// `limit` octets from `reader`, failing if the message is larger. It is
+    /// safe to use on hostile input.
+    pub fn decode_untrusted<R: io::BufRead>(
+        reader: R, limit: usize
+    ) -> Result<Self, Error> {
+        xml::decode::decode_untrusted(reader, limit, |reader| {
+            Self::decode(reader)
+        })
+    }
+
+    /// Parses the attributes of an RFC 6492 <message />.
+    ///
+    /// Only the start of the message element is read, so this works for
+    /// messages of any version. This can be used to dispatch to different
+    /// decoders for different versions or to respond to messages with an
+    /// unsupported version via [`MessageHeader::version_error`].
+    pub fn decode_header<R: io::BufRead>(
+        reader: R
+    ) -> Result<MessageHeader, Error> {
+        let mut reader = xml::decode::Reader::new(reader);
+
+        let mut version: Option<String> = None;
+        let mut sender: Option<SenderHandle> = None;
+        let mut recipient: Option<RecipientHandle> = None;
+
+        reader.start(|element| {
+            if element.name().local() != b"message" {
+                return Err(XmlError::Malformed);
+            }
+
+            element.attributes(|name, value| {
+                match name {
+                    b"version" => version = Some(value.ascii_into()?),
+                    b"sender" => sender = Some(value.ascii_into()?),
+                    b"recipient" => recipient = Some(value.ascii_into()?),
+                    _ => { }
+                }
+                Ok(())
+            })
+        })?;
+
+        Ok(MessageHeader {
+            version,
+            sender: sender.ok_or(XmlError::Malformed)?,
+            recipient: recipient.ok_or(XmlError::Malformed)?,
+        })
+    }
+
     /// Parses an RFC 6492 <message />
+    ///
+    /// Messages with a version other than [`VERSION`] are rejected with
+    /// [`Error::UnsupportedVersion`].
     pub fn decode<R: io::BufRead>(reader: R) -> Result<Self, Error> {
         let mut reader = xml::decode::Reader::new(reader);
 
+        let mut version: Option<String> = None;
         let mut sender: Option<SenderHandle> = None;
         let mut recipient: Option<RecipientHandle> = None;
         let mut payload_type: Option<PayloadType> = None;
 
         let mut outer = reader.start(|element| {
             if element.name().local() != b"message" {
-                return Err(XmlError::Malformed);
+                return Err(Error::XmlError(XmlError::Malformed));
             }
 
             element.attributes(|name, value| match name {
                 b"version" => {
-                    if value.ascii_into::<String>()? != VERSION {
-                        return Err(XmlError::Malformed);
-                    }
+                    version = Some(value.ascii_into()?);
                     Ok(())
                 }
                 b"sender" => {
@@ -303,7 +490,13 @@ impl Message {
                     Ok(())
                 }
                 _ => Err(XmlError::Malformed),
-            })
+            })?;
+            match version.take() {
+                Some(version) if version != VERSION => {
+                    Err(Error::UnsupportedVersion(version))
+                }
+                _ => Ok(()),
+            }
         })?;
 
         // Get required attributes - return error if anything is missing.
@@ -326,6 +519,61 @@ impl Message {
     }
 }
 
+//------------ MessageHeader -------------------------------------------------
+
+/// The attributes of an RFC 6492 message.
+///
+/// This is returned by [`Message::decode_header`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MessageHeader {
+    /// The protocol version of the message if given.
+    version: Option<String>,
+
+    /// The sender of the message.
+    sender: SenderHandle,
+
+    /// The recipient of the message.
+    recipient: RecipientHandle,
+}
+
+impl MessageHeader {
+    /// Returns the protocol version of the message.
+    ///
+    /// Returns `None` if the message did not have a version attribute.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns whether the version of the message is supported.
+    pub fn is_supported(&self) -> bool {
+        self.version.as_deref() == Some(VERSION)
+    }
+
+    /// Returns the sender of the message.
+    pub fn sender(&self) -> &SenderHandle {
+        &self.sender
+    }
+
+    /// Returns the recipient of the message.
+    pub fn recipient(&self) -> &RecipientHandle {
+        &self.recipient
+    }
+
+    /// Creates the response to the message for a version mismatch.
+    ///
+    /// This is the not performed response with status 1102 defined in
+    /// section 3.6 of RFC 6492, sent from the recipient of the message
+    /// back to its sender.
+    pub fn version_error(&self) -> Message {
+        Message {
+            sender: self.recipient.convert(),
+            recipient: self.sender.convert(),
+            payload: Payload::ErrorResponse(NotPerformedResponse::err_1102()),
+        }
+    }
+}
+
+
 //------------ Payload -------------------------------------------------------
 
 /// Contains the query or reply payload of the message.
@@ -463,6 +711,8 @@ impl fmt::Display for PayloadTypeError {
     }
 }
 
+impl error::Error for PayloadTypeError { }
+
 //------------ IssuanceRequest -----------------------------------------------
 
 /// This type reflects the content of a Certificate Issuance Request
@@ -695,17 +945,17 @@ impl IssuanceResponse {
         let mut entitlements =
             ResourceClassEntitlements::decode_opt(content, reader)?.ok_or(XmlError::Malformed)?; // We MUST have 1
 
-        if entitlements.issued_certs.len() != 1 {
-            Err(Error::XmlError(XmlError::Malformed))
-        } else {
-            Ok(IssuanceResponse {
-                class_name: entitlements.class_name,
-                resource_set: entitlements.resource_set,
-                not_after: entitlements.not_after,
-                issued_cert: entitlements.issued_certs.pop().unwrap(),
-                signing_cert: entitlements.signing_cert,
-            })
-        }
+        let issued_cert = match entitlements.issued_certs.pop() {
+            Some(cert) if entitlements.issued_certs.is_empty() => cert,
+            _ => return Err(Error::XmlError(XmlError::Malformed))
+        };
+        Ok(IssuanceResponse {
+            class_name: entitlements.class_name,
+            resource_set: entitlements.resource_set,
+            not_after: entitlements.not_after,
+            issued_cert,
+            signing_cert: entitlements.signing_cert,
+        })
     }
 }
 
@@ -1764,6 +2014,7 @@ impl<'de> Deserialize<'de> for ResourceClassName {
 //------------ ProvisioningMessageError --------------------------------------
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidVersion,
     XmlError(XmlError),
@@ -1773,6 +2024,7 @@ pub enum Error {
     CmsDecode(DecodeError<Infallible>),
     Validation(ValidationError),
     Limit(ResourceSet, RequestResourceLimit),
+    UnsupportedVersion(String),
 }
 
 impl Error {
@@ -1806,6 +2058,21 @@ impl fmt::Display for Error {
                     limit, set
                 )
             }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version '{}'", version)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::XmlError(e) => Some(e),
+            Error::InvalidPayloadType(e) => Some(e),
+            Error::CmsDecode(e) => Some(e),
+            Error::Validation(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -1855,6 +2122,32 @@ mod tests {
         xml.to_string()
     }
 
+    #[test]
+    fn signing_time_tracker() {
+        let list = ProvisioningCms::decode(
+            include_bytes!("../../test-data/ca/rfc6492/list.der")
+        ).unwrap();
+        let issue = ProvisioningCms::decode(
+            include_bytes!("../../test-data/ca/rfc6492/issue.der")
+        ).unwrap();
+        let list_time = list.signing_time().unwrap();
+        assert!(list_time < issue.signing_time().unwrap());
+
+        let mut tracker = SigningTimeTracker::new();
+        tracker.check(&list).unwrap();
+        assert_eq!(
+            tracker.check(&list),
+            Err(SigningTimeError::NotMonotonic(list_time))
+        );
+        assert_eq!(
+            tracker.latest(
+                list.message().sender(), list.message().recipient()
+            ),
+            Some(list_time)
+        );
+        tracker.check(&issue).unwrap();
+    }
+
     #[test]
     fn parse_and_encode_list() {
         let xml = extract_xml(include_bytes!("../../test-data/ca/rfc6492/list.der"));
@@ -1862,6 +2155,31 @@ mod tests {
         assert_re_encode_equals(list);
     }
 
+    #[test]
+    fn unsupported_version() {
+        let xml = r#"<message
+            xmlns="http://www.apnic.net/specs/rescerts/up-down/"
+            version="2" sender="child" recipient="parent" type="list"/>"#;
+        assert!(matches!(
+            Message::decode(xml.as_bytes()),
+            Err(Error::UnsupportedVersion(ref version)) if version == "2"
+        ));
+        let header = Message::decode_header(xml.as_bytes()).unwrap();
+        assert_eq!(header.version(), Some("2"));
+        assert!(!header.is_supported());
+        let response = header.version_error();
+        assert_eq!(response.sender().as_str(), "parent");
+        assert_eq!(response.recipient().as_str(), "child");
+        assert_re_encode_equals(response);
+
+        let xml = extract_xml(
+            include_bytes!("../../test-data/ca/rfc6492/list.der")
+        );
+        assert!(
+            Message::decode_header(xml.as_bytes()).unwrap().is_supported()
+        );
+    }
+
     #[test]
     fn parse_apnic_response_sept_2022() {
         extract_xml(include_bytes!("../../test-data/ca/rfc6492/apnic-response.der"));
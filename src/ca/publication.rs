@@ -1,17 +1,18 @@
 //! Support for RFC 8181 Publication Messages
 
-use std::fmt;
-use std::io;
+use std::{error, fmt};
+use std::collections::{HashMap, HashSet};
+use std::{io, mem};
 use std::str::FromStr;
 use std::sync::Arc;
 
 use bytes::Bytes;
-use log::error;
+use log::{debug, error};
 use serde::{
     Deserialize, Deserializer, Serialize, Serializer
 };
 
-use crate::crypto::PublicKey;
+use crate::crypto::{AlgorithmSuite, PublicKey};
 use crate::repository::Cert;
 use crate::repository::Crl;
 use crate::repository::Manifest;
@@ -20,20 +21,26 @@ use crate::repository::aspa::Aspa;
 use crate::crypto::Signer;
 use crate::crypto::SigningError;
 use crate::repository::error::ValidationError;
+use crate::repository::options::SigningTimeWindow;
 use crate::repository::x509::{Time, Validity};
 use crate::rrdp;
 use crate::uri;
 use crate::util::base64;
+use crate::util::cancel::{CancelToken, Cancelled};
+use crate::util::intern::Interner;
 use crate::xml;
 use crate::xml::decode::{
-    Content, Error as XmlError
+    Content, Error as XmlError, UnknownElement, UnknownElements
 };
 use crate::xml::encode;
 
-use super::sigmsg::SignedMessage;
+use super::quirks::Quirks;
+use super::sigmsg::{EeCertPolicy, SignedMessage};
+
+/// The version of the publication protocol supported by this crate.
+pub const VERSION: &str = "4";
 
 // Constants for the RFC 8183 XML
-const VERSION: &str = "4";
 const NS: &[u8] = b"http://www.hactrn.net/uris/rpki/publication-spec/";
 
 const MSG: &[u8] = b"msg";
@@ -46,7 +53,7 @@ const ERROR_TEXT: &[u8] = b"error_text";
 const FAILED_PDU: &[u8] = b"failed_pdu";
 
 // Content-type for HTTP(s) exchanges
-pub const CONTENT_TYPE: &str = "application/rpki-publication";
+pub const CONTENT_TYPE: &str = crate::util::media::RPKI_PUBLICATION;
 
 //------------ PublicationCms ------------------------------------------------
 
@@ -84,11 +91,44 @@ impl PublicationCms {
         Ok(PublicationCms { signed_msg, message})
     }
 
+    /// Creates a publication CMS using the given EE certificate policy.
+    ///
+    /// This is the same as [`create`][Self::create] but allows reusing a
+    /// long-lived EE certificate instead of creating a one-off one.
+    pub fn create_with_policy<S: Signer>(
+        message: Message,
+        issuing_key_id: &S::KeyId,
+        policy: &EeCertPolicy<S::KeyId>,
+        signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        let data = message.to_xml_bytes();
+        let validity = Validity::new(
+            Time::five_minutes_ago(),
+            Time::five_minutes_from_now()
+        );
+
+        let signed_msg = SignedMessage::create_with_policy(
+            data,
+            validity,
+            issuing_key_id,
+            policy,
+            AlgorithmSuite::default(),
+            signer
+        )?;
+
+        Ok(PublicationCms { signed_msg, message})
+    }
+
     /// Unpack into its SignedMessage and Message
     pub fn unpack(self) -> (SignedMessage, Message) {
         (self.signed_msg, self.message)
     }
 
+    /// Returns a reference to the message.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
     pub fn into_message(self) -> Message {
         self.message
     }
@@ -99,14 +139,32 @@ impl PublicationCms {
     }
 
     /// Decodes the CMS and enclosed publication Message from the source.
+    ///
+    /// The message is decoded with the default options.
     pub fn decode(
         bytes: &[u8]
+    ) -> Result<Self, Error> {
+        Self::decode_with(bytes, &DecodeOptions::default())
+    }
+
+    /// Decodes the CMS and enclosed message with the given options.
+    ///
+    /// The message is decoded via [`Message::decode_with`]. A size limit
+    /// given in `options` applies to the content of the CMS.
+    pub fn decode_with(
+        bytes: &[u8], options: &DecodeOptions,
     ) -> Result<Self, Error> {
         let signed_msg = SignedMessage::decode(bytes, false)
             .map_err(|e| Error::CmsDecode(e.to_string()))?;
 
         let content = signed_msg.content().to_bytes();
-        let message = Message::decode(content.as_ref())?;
+        let message = Message::decode_with(
+            content.as_ref(), options
+        ).map_err(|err| {
+            debug!("RFC 8181: cannot decode message: {}", err);
+            err
+        })?.message;
+        debug!("RFC 8181: received {}", message.summary());
 
         Ok(PublicationCms { signed_msg, message })
     }
@@ -120,6 +178,17 @@ impl PublicationCms {
     ) -> Result<(), Error> {
         self.signed_msg.validate_at(issuer_key, when).map_err(|e| e.into())
     }
+
+    /// Validates the CMS including its signing time.
+    ///
+    /// See [`SignedMessage::validate_with_signing_time_at`] for details.
+    pub fn validate_with_signing_time_at(
+        &self, issuer_key: &PublicKey, window: &SigningTimeWindow, when: Time
+    ) -> Result<(), Error> {
+        self.signed_msg
+            .validate_with_signing_time_at(issuer_key, window, when)
+            .map_err(|e| e.into())
+    }
 }
 
 
@@ -127,6 +196,7 @@ impl PublicationCms {
 
 /// This type represents all Publication Messages defined in RFC8181
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Message {
     Query(Query),
     Reply(Reply),
@@ -147,6 +217,11 @@ impl Message {
         Message::Query(Query::Delta(delta))
     }
 
+    /// Creates a query withdrawing all objects listed in a list reply.
+    pub fn withdraw_all(list: ListReply) -> Self {
+        Message::delta(list.into_withdraw_delta())
+    }
+
     pub fn success() -> Self {
         Message::Reply(Reply::Success)
     }
@@ -172,6 +247,24 @@ impl Message {
             Message::Reply(_) => Err(Error::NotQuery),
         }
     }
+
+    /// Returns a short human-readable description of the message.
+    ///
+    /// The description contains the kind of message and, for lists and
+    /// deltas, the number of elements.
+    pub fn summary(&self) -> String {
+        match self {
+            Message::Query(Query::List) => "list query".into(),
+            Message::Query(Query::Delta(delta)) => {
+                format!("delta query with {} elements", delta.len())
+            }
+            Message::Reply(Reply::List(list)) => {
+                format!("list reply with {} elements", list.elements().len())
+            }
+            Message::Reply(Reply::Success) => "success reply".into(),
+            Message::Reply(Reply::ErrorReply(_)) => "error reply".into(),
+        }
+    }
 }
 
 /// # Encoding to XML
@@ -219,25 +312,99 @@ impl Message {
     }
 }
 
+/// # Quirks
+///
+impl Message {
+    /// Prepares the message for sending to a peer with the given quirks.
+    pub fn apply_quirks(&mut self, quirks: &Quirks) {
+        if quirks.hash_tags {
+            if let Message::Query(Query::Delta(delta)) = self {
+                delta.set_hash_tags()
+            }
+        }
+    }
+}
+
 /// # Decoding from XML
 /// 
 impl Message {
-    /// Parses an RFC 8181 <msg />
+    /// Parses an RFC 8181 <msg /> with the default options.
+    ///
+    /// Any unknown element will lead to an error. Known deviations from
+    /// RFC 8181 are accepted as with [`DecodeProfile::Compatible`]. Use
+    /// [`decode_with`][Self::decode_with] to learn about them or to
+    /// decode with other [`DecodeOptions`].
     pub fn decode<R: io::BufRead>(reader: R) -> Result<Self, Error> {
+        Self::decode_with(
+            reader, &DecodeOptions::default()
+        ).map(|decoded| decoded.message)
+    }
+
+    /// Parses an RFC 8181 <msg /> with the given options.
+    ///
+    /// Returns the message together with the deviations from RFC 8181 that
+    /// were accepted and the unknown elements that were skipped. Both are
+    /// also logged.
+    ///
+    /// The decoders of this crate only support [`VERSION`]. A message with
+    /// any other version fails with [`Error::UnsupportedVersion`] as soon
+    /// as the start of the message element has been read, so the error can
+    /// be used to dispatch to a different decoder. A message without a
+    /// version attribute is a [`Deviation::MissingVersion`].
+    pub fn decode_with<R: io::BufRead>(
+        reader: R, options: &DecodeOptions,
+    ) -> Result<Decoded, Error> {
+        let mut ctx = DecodeContext::new(options);
+        let message = match options.limit {
+            Some(limit) => {
+                xml::decode::decode_untrusted(reader, limit, |reader| {
+                    Self::decode_context(reader, &mut ctx)
+                })?
+            }
+            None => Self::decode_context(reader, &mut ctx)?
+        };
+        for deviation in &ctx.deviations {
+            debug!(
+                "RFC 8181: accepted {} in {}", deviation, message.summary()
+            );
+        }
+        for element in ctx.unknown.as_slice() {
+            debug!(
+                "RFC 8181: skipped {} in {}", element, message.summary()
+            );
+        }
+        Ok(Decoded {
+            message,
+            deviations: ctx.deviations,
+            unknown: ctx.unknown.into_vec(),
+        })
+    }
+
+    /// Parses an RFC 8181 <msg /> using the given decode context.
+    fn decode_context<R: io::BufRead>(
+        reader: R,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, Error> {
         let mut reader = xml::decode::Reader::new(reader);
 
         let mut kind: Option<MessageKind> = None;
+        let mut version: Option<String> = None;
 
         let mut outer = reader.start(|element| {
             if element.name().local() != MSG {
-                return Err(XmlError::Malformed)
+                return Err(Error::XmlError(XmlError::Malformed))
+            }
+            if element.name().namespace() != Some(NS) {
+                ctx.deviation(Deviation::Namespace(
+                    element.name().namespace().map(|ns| {
+                        String::from_utf8_lossy(ns).into_owned()
+                    })
+                ))?;
             }
             
             element.attributes(|name, value| match name {
                 b"version" => {
-                    if value.ascii_into::<String>()? != VERSION {
-                        return Err(XmlError::Malformed)
-                    }
+                    version = Some(value.ascii_into()?);
                     Ok(())
                 }
                 b"type" => {
@@ -249,16 +416,24 @@ impl Message {
                     Ok(())
                 }
                 _ => Err(XmlError::Malformed)
-            })
+            })?;
+            match version.take() {
+                Some(version) if version != VERSION => {
+                    return Err(Error::UnsupportedVersion(version))
+                }
+                Some(_) => { }
+                None => ctx.deviation(Deviation::MissingVersion)?,
+            }
+            Ok(())
         })?;
 
         // Dispatch to message kind for content parsing
         let msg = match kind.ok_or(XmlError::Malformed)? {
             MessageKind::Query => Message::Query(
-                Query::decode(&mut outer, &mut reader)?
+                Query::decode(&mut outer, &mut reader, ctx)?
             ),
             MessageKind::Reply => Message::Reply(
-                Reply::decode(&mut outer, &mut reader)?
+                Reply::decode(&mut outer, &mut reader, ctx)?
             )
         };
 
@@ -280,10 +455,264 @@ enum MessageKind {
     Reply
 }
 
+
+//------------ DecodeProfile -------------------------------------------------
+
+/// How strictly messages are checked against RFC 8181 when decoding.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DecodeProfile {
+    /// Any deviation from RFC 8181 is an error.
+    #[default]
+    Strict,
+
+    /// Known deviations of deployed implementations are accepted.
+    ///
+    /// The deviations encountered are recorded and returned with the
+    /// decoded message.
+    Compatible,
+}
+
+
+//------------ DecodeOptions -------------------------------------------------
+
+/// The options for decoding a message.
+///
+/// The default options are used by [`Message::decode`]: messages of any
+/// size are decoded with the [`DecodeProfile::Compatible`] profile and
+/// unknown elements are rejected.
+#[derive(Clone, Debug)]
+pub struct DecodeOptions {
+    /// The maximum size of the message in octets.
+    limit: Option<usize>,
+
+    /// The profile to decode with.
+    profile: DecodeProfile,
+
+    /// Whether unknown elements are skipped.
+    skip_unknown: bool,
+
+    /// The token for cancelling decoding.
+    cancel: Option<CancelToken>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            limit: None,
+            profile: DecodeProfile::Compatible,
+            skip_unknown: false,
+            cancel: None,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates options following the given quirks of a peer.
+    ///
+    /// See [`set_quirks`][Self::set_quirks] for details.
+    pub fn from_quirks(quirks: &Quirks) -> Self {
+        let mut res = Self::default();
+        res.set_quirks(quirks);
+        res
+    }
+
+    /// Sets the maximum size of a message.
+    ///
+    /// If a limit is given, at most `limit` octets are read and larger
+    /// messages are rejected with an [`XmlError::TooLarge`]. Together with
+    /// a limit, decoding is safe to use on input from hostile sources.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit
+    }
+
+    /// Sets the decode profile.
+    ///
+    /// With [`DecodeProfile::Strict`], any deviation from RFC 8181 leads to
+    /// an [`Error::Deviation`]. With [`DecodeProfile::Compatible`], known
+    /// deviations are accepted and returned alongside the message.
+    pub fn set_profile(&mut self, profile: DecodeProfile) {
+        self.profile = profile
+    }
+
+    /// Sets whether unknown elements are skipped.
+    ///
+    /// If `skip` is `true`, unknown elements inside the message and their
+    /// content are skipped rather than leading to an error. Their names
+    /// are returned alongside the message. The message element itself and
+    /// all attributes are still checked.
+    pub fn set_skip_unknown(&mut self, skip: bool) {
+        self.skip_unknown = skip
+    }
+
+    /// Sets the profile and the handling of unknown elements from quirks.
+    ///
+    /// Known deviations are accepted if `quirks.deviations` is set and
+    /// unknown elements are skipped if `quirks.unknown_elements` is set.
+    pub fn set_quirks(&mut self, quirks: &Quirks) {
+        self.profile = if quirks.deviations {
+            DecodeProfile::Compatible
+        }
+        else {
+            DecodeProfile::Strict
+        };
+        self.skip_unknown = quirks.unknown_elements;
+    }
+
+    /// Sets the token for cancelling decoding.
+    ///
+    /// The token is checked before each PDU of the message. Decoding fails
+    /// with [`Error::Cancelled`] once it has been cancelled.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = Some(cancel)
+    }
+}
+
+
+//------------ Decoded -------------------------------------------------------
+
+/// A message decoded via [`Message::decode_with`].
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    /// The message.
+    pub message: Message,
+
+    /// The deviations from RFC 8181 accepted while decoding.
+    pub deviations: Vec<Deviation>,
+
+    /// The unknown elements skipped while decoding.
+    pub unknown: Vec<UnknownElement>,
+}
+
+
+//------------ Deviation -----------------------------------------------------
+
+/// A deviation from RFC 8181 encountered while decoding a message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Deviation {
+    /// The message element was not in the RFC 8181 namespace.
+    ///
+    /// Contains the namespace of the element if it had one.
+    Namespace(Option<String>),
+
+    /// The message element was missing the version attribute.
+    MissingVersion,
+
+    /// An element had an attribute not defined for it.
+    UnexpectedAttribute {
+        element: &'static str,
+        attribute: &'static str,
+    },
+
+    /// A child element appeared out of order.
+    ElementOrder {
+        element: &'static str,
+        child: &'static str,
+    },
+
+    /// An element that may appear at most once appeared more than once.
+    DuplicateElement(&'static str),
+}
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Deviation::Namespace(Some(ns)) => {
+                write!(f, "message in wrong namespace '{}'", ns)
+            }
+            Deviation::Namespace(None) => {
+                write!(f, "message without namespace")
+            }
+            Deviation::MissingVersion => {
+                write!(f, "message without version attribute")
+            }
+            Deviation::UnexpectedAttribute { element, attribute } => {
+                write!(
+                    f, "unexpected attribute '{}' on <{}>", attribute, element
+                )
+            }
+            Deviation::ElementOrder { element, child } => {
+                write!(f, "<{}> out of order in <{}>", child, element)
+            }
+            Deviation::DuplicateElement(element) => {
+                write!(f, "duplicate <{}>", element)
+            }
+        }
+    }
+}
+
+
+//------------ DecodeContext -------------------------------------------------
+
+/// The state kept while decoding a message.
+struct DecodeContext {
+    /// The profile to decode with.
+    profile: DecodeProfile,
+
+    /// The handling of unknown elements.
+    unknown: UnknownElements,
+
+    /// The deviations accepted so far.
+    deviations: Vec<Deviation>,
+
+    /// The token for cancelling decoding.
+    cancel: Option<CancelToken>,
+}
+
+impl DecodeContext {
+    fn new(options: &DecodeOptions) -> Self {
+        DecodeContext {
+            profile: options.profile,
+            unknown: if options.skip_unknown {
+                UnknownElements::lenient()
+            }
+            else {
+                UnknownElements::strict()
+            },
+            deviations: Vec::new(),
+            cancel: options.cancel.clone(),
+        }
+    }
+
+    /// Returns an error if decoding has been cancelled.
+    fn check_cancel(&self) -> Result<(), Error> {
+        match self.cancel {
+            Some(ref cancel) => cancel.check().map_err(Into::into),
+            None => Ok(())
+        }
+    }
+
+    /// Processes a deviation.
+    ///
+    /// In strict mode, returns an error. Otherwise, records the deviation.
+    fn deviation(&mut self, deviation: Deviation) -> Result<(), Error> {
+        match self.profile {
+            DecodeProfile::Strict => Err(Error::Deviation(deviation)),
+            DecodeProfile::Compatible => {
+                self.deviations.push(deviation);
+                Ok(())
+            }
+        }
+    }
+
+    /// Processes an attribute not defined for an element.
+    fn unexpected_attribute(
+        &mut self, element: &'static str, attribute: &'static str
+    ) -> Result<(), Error> {
+        self.deviation(Deviation::UnexpectedAttribute { element, attribute })
+    }
+}
+
 //------------ QueryMessage --------------------------------------------------
 
 /// This type represents query type Publication Messages defined in RFC8181
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Query {
     List,
     Delta(PublishDelta),
@@ -317,13 +746,15 @@ impl Query {
     fn decode<R: io::BufRead>(
         content: &mut Content,
         reader: &mut xml::decode::Reader<R>,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, Error> {
         
         // First parse *all* PDUs, then we can decide what query type we had
         let mut pdus: Vec<QueryPdu> = vec![];
         loop {
+            ctx.check_cancel()?;
 
-            match QueryPdu::decode_opt(content, reader)? {
+            match QueryPdu::decode_opt(content, reader, ctx)? {
                 None => break,
                 Some(pdu) => {
                     if !pdus.is_empty() && pdu == QueryPdu::List {
@@ -386,6 +817,7 @@ pub enum QueryPduType {
 //------------ QueryPdu ------------------------------------------------------
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum QueryPdu {
     List,
     PublishDeltaElement(PublishDeltaElement)
@@ -396,128 +828,149 @@ impl QueryPdu {
     fn decode_opt<R: io::BufRead>(
         content: &mut Content,
         reader: &mut xml::decode::Reader<R>,
+        ctx: &mut DecodeContext,
     ) -> Result<Option<Self>, Error> {
-        let mut pdu_type = None;
+        loop {
+            let mut pdu_type = None;
 
-        // We need to do a two step analysis of elements. First we need
-        // to determine which type of element we are dealing with, and
-        // then we can evaluate the content. For <publish /> and
-        // <withdraw /> elements we will need to parse information from
-        // the element attributes *before* we can use the reader and
-        // inspect the content of a <publish /> element.
+            // We need to do a two step analysis of elements. First we need
+            // to determine which type of element we are dealing with, and
+            // then we can evaluate the content. For <publish /> and
+            // <withdraw /> elements we will need to parse information from
+            // the element attributes *before* we can use the reader and
+            // inspect the content of a <publish /> element.
 
-        // possible attributes
-        let mut tag: Option<String> = None;
-        let mut uri: Option<uri::Rsync> = None;
-        let mut hash: Option<rrdp::Hash> = None;
+            // possible attributes
+            let mut tag: Option<String> = None;
+            let mut uri: Option<uri::Rsync> = None;
+            let mut hash: Option<rrdp::Hash> = None;
 
-        let pdu_element = content.take_opt_element(reader, |element| {
-            // Determine the PDU type
-            pdu_type = Some(match element.name().local() {
-                LIST => Ok(QueryPduType::List),
-                PUBLISH => Ok(QueryPduType::Publish),
-                WITHDRAW => Ok(QueryPduType::Withdraw),
-                _ => Err(XmlError::Malformed)
-            }?);
+            let pdu_element = content.take_opt_element(reader, |element| {
+                // Determine the PDU type
+                pdu_type = Some(match element.name().local() {
+                    LIST => QueryPduType::List,
+                    PUBLISH => QueryPduType::Publish,
+                    WITHDRAW => QueryPduType::Withdraw,
+                    _ => return ctx.unknown.add(&element)
+                });
 
-            // parse element attributes - we treat them as optional
-            // at this point so it does not matter that not all attributes
-            // are applicable to all element types.
-            element.attributes(|name, value| match name {
-                b"tag" => {
-                    tag = Some(value.ascii_into()?);
-                    Ok(())
-                }
-                b"hash" => {
-                    let hex: String = value.ascii_into()?;
-                    if let Ok(hash_value) =rrdp::Hash::from_str(&hex) {
-                        hash = Some(hash_value);
+                // parse element attributes - we treat them as optional
+                // at this point so it does not matter that not all attributes
+                // are applicable to all element types.
+                element.attributes(|name, value| match name {
+                    b"tag" => {
+                        tag = Some(value.ascii_into()?);
                         Ok(())
-                    } else {
+                    }
+                    b"hash" => {
+                        let hex: String = value.ascii_into()?;
+                        if let Ok(hash_value) =rrdp::Hash::from_str(&hex) {
+                            hash = Some(hash_value);
+                            Ok(())
+                        } else {
+                            Err(XmlError::Malformed)
+                        }
+                    }
+                    b"uri" => {
+                        uri = Some(value.ascii_into()?);
+                        Ok(())
+                    }
+                    _ => {
                         Err(XmlError::Malformed)
                     }
-                }
-                b"uri" => {
-                    uri = Some(value.ascii_into()?);
-                    Ok(())
-                }
-                _ => {
-                    Err(XmlError::Malformed)
-                }
-            })
+                })
 
-        })?;
+            })?;
 
-        // Break out of loop if we got no element, get the
-        // actual element if we can.
-        let mut pdu_element = match pdu_element {
-            Some(inner) => inner,
-            None => return Ok(None)
-        };
+            // Break out of loop if we got no element, get the
+            // actual element if we can.
+            let mut pdu_element = match pdu_element {
+                Some(inner) => inner,
+                None => return Ok(None)
+            };
         
-        // We had an element so we can unwrap the type.
-        let pdu_type = pdu_type.unwrap(); 
+            // If we didn’t get a type, the element was unknown and has
+            // been recorded. Skip it and try the next one.
+            let pdu_type = match pdu_type {
+                Some(pdu_type) => pdu_type,
+                None => {
+                    pdu_element.skip(reader)?;
+                    continue
+                }
+            };
 
-        
-        let pdu: Result<QueryPdu, Error> = match pdu_type {
-            QueryPduType::List => {
-                Ok(QueryPdu::List)
-            },
-            QueryPduType::Publish => {
-                let uri = uri.ok_or(XmlError::Malformed)?;
+            // Attributes not defined for the PDU type are a deviation.
+            if pdu_type == QueryPduType::List {
+                if uri.is_some() {
+                    ctx.unexpected_attribute("list", "uri")?;
+                }
+                if hash.is_some() {
+                    ctx.unexpected_attribute("list", "hash")?;
+                }
+            }
+
+            let pdu: Result<QueryPdu, Error> = match pdu_type {
+                QueryPduType::List => {
+                    Ok(QueryPdu::List)
+                },
+                QueryPduType::Publish => {
+                    let uri = uri.ok_or(XmlError::Malformed)?;
                 
-                // even though we store the base64 as [`Base64`] which
-                // uses an inner `Arc<str>`, we decode it first to ensure
-                // that it can be parsed.
-                let bytes = pdu_element.take_text(reader, |text| {
-                    text.base64_decode()
-                })?;
+                    // even though we store the base64 as [`Base64`] which
+                    // uses an inner `Arc<str>`, we decode it first to ensure
+                    // that it can be parsed.
+                    let bytes = pdu_element.take_text(reader, |text| {
+                        text.base64_decode().map_err(|err| {
+                            Error::InvalidContent(uri.clone(), err)
+                        })
+                    })?;
                 
-                let content = Base64::from_content(&bytes);
+                    let content = Base64::from_content(&bytes);
                 
-                match hash {
-                    None => {
-                        Ok(QueryPdu::PublishDeltaElement(
-                            PublishDeltaElement::Publish(
-                                Publish {
-                                    tag,
-                                    uri,
-                                    content,
-                                }
-                            )
-                        ))
-                    },
-                    Some(hash) => {
-                        Ok(QueryPdu::PublishDeltaElement(
-                            PublishDeltaElement::Update(
-                                Update {
-                                    tag,
-                                    uri,
-                                    content,
-                                    hash,
-                                }
-                            )
-                        ))
+                    match hash {
+                        None => {
+                            Ok(QueryPdu::PublishDeltaElement(
+                                PublishDeltaElement::Publish(
+                                    Publish {
+                                        tag,
+                                        uri,
+                                        content,
+                                    }
+                                )
+                            ))
+                        },
+                        Some(hash) => {
+                            Ok(QueryPdu::PublishDeltaElement(
+                                PublishDeltaElement::Update(
+                                    Update {
+                                        tag,
+                                        uri,
+                                        content,
+                                        hash,
+                                    }
+                                )
+                            ))
+                        }
                     }
                 }
-            }
-            QueryPduType::Withdraw => {
-                let uri = uri.ok_or(XmlError::Malformed)?;
-                let hash = hash.ok_or(XmlError::Malformed)?;
+                QueryPduType::Withdraw => {
+                    let uri = uri.ok_or(XmlError::Malformed)?;
+                    let hash = hash.ok_or(XmlError::Malformed)?;
 
-                Ok(QueryPdu::PublishDeltaElement(
-                    PublishDeltaElement::Withdraw(
-                        Withdraw { tag, uri, hash }
-                    )
-                ))
-            }
-        };
+                    Ok(QueryPdu::PublishDeltaElement(
+                        PublishDeltaElement::Withdraw(
+                            Withdraw { tag, uri, hash }
+                        )
+                    ))
+                }
+            };
 
-        let pdu = pdu?;
+            let pdu = pdu?;
 
-        pdu_element.take_end(reader)?;
+            pdu_element.take_end(reader)?;
 
-        Ok(Some(pdu))
+            return Ok(Some(pdu))
+        }
     }
 
     fn write_xml<W: io::Write>(
@@ -541,6 +994,7 @@ impl QueryPdu {
 /// This type represents a multi element query as described in
 /// https://tools.ietf.org/html/rfc8181#section-3.7
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PublishDelta(Vec<PublishDeltaElement>);
 
 impl PublishDelta {
@@ -548,6 +1002,25 @@ impl PublishDelta {
         Self::default()
     }
 
+    /// Creates a delta withdrawing all the given objects.
+    ///
+    /// The objects are typically taken from a list reply, either via
+    /// [`ListReply::into_withdraw_delta`] or, for large replies, from a
+    /// [`ListReplyReader`]. Such a delta can be used to reset a
+    /// publisher’s content, e.g., when it moves to a different repository.
+    pub fn withdraw_all(
+        elements: impl IntoIterator<Item = ListElement>
+    ) -> Self {
+        PublishDelta(
+            elements.into_iter().map(|el| {
+                let (uri, hash) = el.unpack();
+                PublishDeltaElement::Withdraw(
+                    Withdraw::with_hash_tag(uri, hash)
+                )
+            }).collect()
+        )
+    }
+
     pub fn add_publish(&mut self, publish: Publish) {
         self.0.push(PublishDeltaElement::Publish(publish));
     }
@@ -571,6 +1044,231 @@ impl PublishDelta {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// Returns an iterator over the elements of the delta.
+    pub fn iter(&self) -> impl Iterator<Item = &PublishDeltaElement> + '_ {
+        self.0.iter()
+    }
+}
+
+/// # Splitting
+///
+/// Some servers limit the size of the requests they accept, which can
+/// make it impossible to send a large delta in a single query. The delta
+/// then has to be split into several queries sent one after another.
+impl PublishDelta {
+    /// Splits the delta into several deltas within the given limits.
+    ///
+    /// The resulting deltas have to be sent in order, each one only after
+    /// the previous one succeeded. Returns an empty vec if the delta is
+    /// empty.
+    ///
+    /// In order to keep the published state consistent between queries as
+    /// far as possible, the elements are reordered if no URI appears more
+    /// than once in the delta: new objects are published first, followed
+    /// by updates of existing objects, then manifests, and finally
+    /// withdrawals. This way, a manifest is only replaced once the objects
+    /// it lists are present and objects are only withdrawn once the new
+    /// manifest no longer lists them. If a URI appears more than once, the
+    /// order of the elements is kept.
+    ///
+    /// An element that alone exceeds the size limit ends up in a delta of
+    /// its own.
+    pub fn split(self, limits: &SplitLimits) -> Vec<PublishDelta> {
+        let mut elements = self.0;
+        let mut uris = HashSet::new();
+        if elements.iter().all(|element| uris.insert(element.uri())) {
+            elements.sort_by_key(PublishDeltaElement::split_phase);
+        }
+
+        let mut res = Vec::new();
+        let mut current = Vec::new();
+        let mut size = 0;
+        for element in elements {
+            let element_size = element.size_approx();
+            if !current.is_empty() && (
+                limits.max_elements.map(|max| {
+                    current.len() >= max
+                }).unwrap_or(false)
+                || limits.max_size.map(|max| {
+                    size + element_size > max
+                }).unwrap_or(false)
+            ) {
+                res.push(PublishDelta(mem::take(&mut current)));
+                size = 0;
+            }
+            size += element_size;
+            current.push(element);
+        }
+        if !current.is_empty() {
+            res.push(PublishDelta(current))
+        }
+        res
+    }
+}
+
+
+//------------ SplitLimits ---------------------------------------------------
+
+/// The limits for splitting a delta.
+///
+/// The default value doesn’t limit anything.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SplitLimits {
+    /// The maximum number of elements in a delta.
+    pub max_elements: Option<usize>,
+
+    /// The approximate maximum size of the elements of a delta.
+    ///
+    /// This is the size of the XML of the elements as estimated by
+    /// [`PublishDeltaElement::size_approx`]. It doesn’t include the
+    /// message element and the CMS wrapping the message, so there should
+    /// be a margin of a few kilobytes to the request size limit of the
+    /// server.
+    pub max_size: Option<usize>,
+}
+
+/// # Retrying
+///
+/// If a publish query times out, the client cannot know whether the
+/// server has applied the delta. Since RFC 8181 requires a server to apply
+/// a delta atomically, resending the same delta is safe but will fail with
+/// errors such as `object_already_present` if the first attempt did
+/// succeed. The methods here help with telling these cases apart.
+impl PublishDelta {
+    /// Sets a unique tag for each element of the delta.
+    ///
+    /// The tags are formed from `nonce` and the index of the element. The
+    /// nonce should be unique for each delta sent by the client, e.g., a
+    /// random value or a sequence number, so errors can be attributed to
+    /// the elements of a particular delta.
+    pub fn set_unique_tags(&mut self, nonce: &str) {
+        for (index, element) in self.0.iter_mut().enumerate() {
+            element.set_tag(Some(format!("{}-{}", nonce, index)));
+        }
+    }
+
+    /// Sets the tag of elements without one to the hash of their object.
+    ///
+    /// For publish and update elements, the hash of the new object is
+    /// used, for withdraw elements the hash of the withdrawn object.
+    pub fn set_hash_tags(&mut self) {
+        for element in &mut self.0 {
+            if element.tag().map(|tag| !tag.is_empty()).unwrap_or(false) {
+                continue
+            }
+            let hash = match element {
+                PublishDeltaElement::Publish(p) => p.content.to_hash(),
+                PublishDeltaElement::Update(u) => u.content.to_hash(),
+                PublishDeltaElement::Withdraw(w) => w.hash,
+            };
+            element.set_tag(Some(hash.to_string()));
+        }
+    }
+
+    /// Classifies the reply to a resent delta.
+    ///
+    /// The delta is considered to have been applied already if the reply
+    /// only contains errors that are expected for its elements if they have
+    /// been applied before: `object_already_present` for publishing a new
+    /// object, `no_object_present` for a withdraw, and
+    /// `no_object_matching_hash` for an update or withdraw. Errors are
+    /// matched to elements via their tag or, if that fails, the URI of the
+    /// failed PDU.
+    ///
+    /// Since a concurrent change by someone else can produce the same
+    /// errors, [`status_in`][Self::status_in] should be used with a fresh
+    /// list reply if certainty is required.
+    pub fn classify_retry(&self, reply: &Reply) -> RetryOutcome {
+        let errors = match reply {
+            Reply::Success => return RetryOutcome::Applied,
+            Reply::List(_) => return RetryOutcome::Failed,
+            Reply::ErrorReply(errors) => errors.errors(),
+        };
+        if errors.is_empty() {
+            return RetryOutcome::Failed
+        }
+        for error in errors {
+            let element = match self.element_for_error(error) {
+                Some(element) => element,
+                None => return RetryOutcome::Failed
+            };
+            let expected = matches!(
+                (element, error.error_code()),
+                (
+                    PublishDeltaElement::Publish(_),
+                    ReportErrorCode::ObjectAlreadyPresent
+                ) | (
+                    PublishDeltaElement::Update(_),
+                    ReportErrorCode::NoObjectMatchingHash
+                ) | (
+                    PublishDeltaElement::Withdraw(_),
+                    ReportErrorCode::NoObjectPresent
+                        | ReportErrorCode::NoObjectMatchingHash
+                )
+            );
+            if !expected {
+                return RetryOutcome::Failed
+            }
+        }
+        RetryOutcome::AlreadyApplied
+    }
+
+    /// Returns whether the delta is reflected in the given list reply.
+    ///
+    /// The delta is considered applied if all published and updated
+    /// objects are present with their new content and all withdrawn
+    /// objects are absent.
+    pub fn status_in(&self, list: &ListReply) -> DeltaStatus {
+        let current: HashMap<_, _> = list.elements().iter().map(|el| {
+            (el.uri(), el.hash())
+        }).collect();
+        let applied = self.0.iter().filter(|element| {
+            match element {
+                PublishDeltaElement::Publish(publish) => {
+                    current.get(publish.uri()) == Some(
+                        &&publish.content().to_hash()
+                    )
+                }
+                PublishDeltaElement::Update(update) => {
+                    current.get(update.uri()) == Some(
+                        &&update.content().to_hash()
+                    )
+                }
+                PublishDeltaElement::Withdraw(withdraw) => {
+                    !current.contains_key(withdraw.uri())
+                }
+            }
+        }).count();
+        if applied == self.0.len() {
+            DeltaStatus::Applied
+        }
+        else if applied == 0 {
+            DeltaStatus::NotApplied
+        }
+        else {
+            DeltaStatus::Partial
+        }
+    }
+
+    /// Returns the element an error refers to.
+    fn element_for_error(
+        &self, error: &ReportError
+    ) -> Option<&PublishDeltaElement> {
+        if let Some(tag) = error.tag() {
+            if let Some(element) = self.0.iter().find(|element| {
+                element.tag() == Some(tag)
+            }) {
+                return Some(element)
+            }
+        }
+        match error.failed_pdu() {
+            Some(QueryPdu::PublishDeltaElement(failed)) => {
+                self.0.iter().find(|element| element.uri() == failed.uri())
+            }
+            _ => None
+        }
+    }
 }
 
 impl std::ops::Add for PublishDelta {
@@ -584,17 +1282,115 @@ impl std::ops::Add for PublishDelta {
 }
 
 
+//------------ RetryOutcome --------------------------------------------------
+
+/// The outcome of resending a delta after an unanswered attempt.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RetryOutcome {
+    /// The resent delta was applied.
+    Applied,
+
+    /// The delta failed only because it had been applied already.
+    AlreadyApplied,
+
+    /// The delta failed for some other reason.
+    Failed,
+}
+
+
+//------------ DeltaStatus ---------------------------------------------------
+
+/// Whether a delta is reflected in the content of a publication point.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeltaStatus {
+    /// All elements of the delta have been applied.
+    Applied,
+
+    /// None of the elements of the delta have been applied.
+    NotApplied,
+
+    /// Some but not all elements are reflected in the content.
+    ///
+    /// Since deltas are applied atomically, this means that the content
+    /// was changed by someone else.
+    Partial,
+}
+
+
 //------------ PublishDeltaElement -------------------------------------------
 
 /// Represents the available options for publish elements that can occur in
 /// a delta.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum PublishDeltaElement {
     Publish(Publish),
     Update(Update),
     Withdraw(Withdraw),
 }
 
+/// # Data and Access
+///
+impl PublishDeltaElement {
+    /// Returns the tag of the element.
+    pub fn tag(&self) -> Option<&String> {
+        match self {
+            PublishDeltaElement::Publish(p) => p.tag(),
+            PublishDeltaElement::Update(u) => u.tag(),
+            PublishDeltaElement::Withdraw(w) => w.tag(),
+        }
+    }
+
+    /// Returns the URI of the object the element refers to.
+    pub fn uri(&self) -> &uri::Rsync {
+        match self {
+            PublishDeltaElement::Publish(p) => p.uri(),
+            PublishDeltaElement::Update(u) => u.uri(),
+            PublishDeltaElement::Withdraw(w) => w.uri(),
+        }
+    }
+
+    /// Returns the approximate size of the XML of the element.
+    pub fn size_approx(&self) -> usize {
+        // The element name, attribute names, quotes, and brackets.
+        const OVERHEAD: usize = 48;
+        let tag = self.tag().map(String::len).unwrap_or(0);
+        let uri = self.uri().as_str().len();
+        match self {
+            PublishDeltaElement::Publish(p) => {
+                OVERHEAD + tag + uri + p.content.as_str().len()
+            }
+            PublishDeltaElement::Update(u) => {
+                OVERHEAD + tag + uri + u.content.as_str().len() + 64 + 8
+            }
+            PublishDeltaElement::Withdraw(_) => {
+                OVERHEAD + tag + uri + 64 + 8
+            }
+        }
+    }
+
+    /// Returns in which phase of a split delta the element is sent.
+    fn split_phase(&self) -> u8 {
+        let manifest = self.uri().path().ends_with(".mft");
+        match self {
+            PublishDeltaElement::Publish(_) if manifest => 2,
+            PublishDeltaElement::Update(_) if manifest => 2,
+            PublishDeltaElement::Publish(_) => 0,
+            PublishDeltaElement::Update(_) => 1,
+            PublishDeltaElement::Withdraw(_) => 3,
+        }
+    }
+
+    /// Replaces the tag of the element.
+    fn set_tag(&mut self, tag: Option<String>) {
+        match self {
+            PublishDeltaElement::Publish(p) => p.tag = tag,
+            PublishDeltaElement::Update(u) => u.tag = tag,
+            PublishDeltaElement::Withdraw(w) => w.tag = tag,
+        }
+    }
+}
+
 /// # Encode to XML
 /// 
 impl PublishDeltaElement {
@@ -676,6 +1472,16 @@ impl Publish {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Publish {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(Publish::new(arbitrary_tag(u)?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+
 //------------ Update --------------------------------------------------------
 
 /// Represents a publish element, that replaces an existing object.
@@ -763,6 +1569,18 @@ impl Update {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Update {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(Update::new(
+            arbitrary_tag(u)?, u.arbitrary()?, u.arbitrary()?, u.arbitrary()?
+        ))
+    }
+}
+
+
 //------------ Withdraw ------------------------------------------------------
 
 /// Represents a withdraw element that removes an object.
@@ -826,10 +1644,21 @@ impl Withdraw {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Withdraw {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(Withdraw::new(arbitrary_tag(u)?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+
 //------------ ReplyMessage --------------------------------------------------
 
 /// This type represents query type Publication Messages defined in RFC8181
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Reply {
     List(ListReply),
     Success,
@@ -865,11 +1694,13 @@ impl Reply {
     fn decode<R: io::BufRead>(
         content: &mut Content,
         reader: &mut xml::decode::Reader<R>,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, Error> {
         
         // First parse *all* PDUs, then we can decide what reply type we had
         let mut pdus: Vec<ReplyPdu> = vec![];
         loop {
+            ctx.check_cancel()?;
             let mut pdu_type = None;
 
             // We need to do a two step analysis of elements. First we need
@@ -888,11 +1719,11 @@ impl Reply {
             let pdu_element = content.take_opt_element(reader, |element| {
                 // Determine the PDU type
                 pdu_type = Some(match element.name().local() {
-                    LIST => Ok(ReplyPduType::List),
-                    SUCCESS => Ok(ReplyPduType::Success),
-                    REPORT_ERROR => Ok(ReplyPduType::Error),
-                    _ => Err(XmlError::Malformed)
-                }?);
+                    LIST => ReplyPduType::List,
+                    SUCCESS => ReplyPduType::Success,
+                    REPORT_ERROR => ReplyPduType::Error,
+                    _ => return ctx.unknown.add(&element)
+                });
 
                 // parse element attributes - we treat them as optional
                 // at this point so it does not matter that not all attributes
@@ -933,8 +1764,33 @@ impl Reply {
                 None => break
             };
             
-            // We had an element so we can unwrap the type.
-            let pdu_type = pdu_type.unwrap(); 
+            // If we didn’t get a type, the element was unknown and has
+            // been recorded. Skip it and try the next one.
+            let pdu_type = match pdu_type {
+                Some(pdu_type) => pdu_type,
+                None => {
+                    pdu_element.skip(reader)?;
+                    continue
+                }
+            }; 
+
+            // Attributes not defined for the PDU type are a deviation.
+            let element = match pdu_type {
+                ReplyPduType::List => "list",
+                ReplyPduType::Success => "success",
+                ReplyPduType::Error => "report_error",
+            };
+            if pdu_type != ReplyPduType::List {
+                if uri.is_some() {
+                    ctx.unexpected_attribute(element, "uri")?;
+                }
+                if hash.is_some() {
+                    ctx.unexpected_attribute(element, "hash")?;
+                }
+            }
+            if pdu_type != ReplyPduType::Error && error_code.is_some() {
+                ctx.unexpected_attribute(element, "error_code")?;
+            }
 
             match pdu_type {
                 ReplyPduType::List => {
@@ -962,7 +1818,8 @@ impl Reply {
                             error_code.ok_or(XmlError::Malformed)?,
                             tag,
                             &mut pdu_element,
-                            reader
+                            reader,
+                            ctx,
                         )?;
                         
                         pdus.push(ReplyPdu::Error(error));
@@ -1064,6 +1921,7 @@ impl ReplyPdu {
 /// This type represents the list reply as described in
 /// https://tools.ietf.org/html/rfc8181#section-2.3
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ListReply {
     elements: Vec<ListElement>,
 }
@@ -1073,33 +1931,260 @@ impl ListReply {
         Self::default()
     }
 
-    pub fn new(elements: Vec<ListElement>) -> Self {
-        ListReply { elements }
-    }
+    pub fn new(elements: Vec<ListElement>) -> Self {
+        ListReply { elements }
+    }
+
+    pub fn add_element(&mut self, element: ListElement) {
+        self.elements.push(element);
+    }
+
+    pub fn elements(&self) -> &Vec<ListElement> {
+        &self.elements
+    }
+
+    pub fn into_elements(self) -> Vec<ListElement> {
+        self.elements
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> impl Iterator<Item = &ListElement> + '_ {
+        self.elements.iter()
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Returns whether the reply is empty.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Brings the reply into canonical form.
+    ///
+    /// The elements are sorted and duplicates are removed. Since servers
+    /// may return the elements in any order, two replies should only be
+    /// compared after both have been canonicalized.
+    pub fn canonicalize(&mut self) {
+        self.elements.sort();
+        self.elements.dedup();
+    }
+
+    /// Replaces the URIs of all elements with interned copies.
+    ///
+    /// This avoids an allocation per URI and saves memory if the same
+    /// URIs are kept elsewhere, too, for instance in a previous reply.
+    pub fn intern_uris(&mut self, interner: &mut Interner) {
+        for element in &mut self.elements {
+            element.uri = element.uri.intern(interner);
+        }
+    }
+
+    pub fn into_withdraw_delta(self) -> PublishDelta {
+        PublishDelta::withdraw_all(self.elements)
+    }
+}
+
+/// # Streaming
+///
+/// For publishers with many objects, list replies can become very large.
+/// These functions allow writing and reading them without having to
+/// keep all the elements in memory at the same time.
+impl ListReply {
+    /// Writes a list reply message with the given elements.
+    ///
+    /// The elements are taken from the iterator one by one and written
+    /// right away. If the iterator returns an error, writing stops and the
+    /// error is returned. In this case, the written message is incomplete.
+    pub fn write_xml_streaming<I, E>(
+        elements: I, writer: &mut impl io::Write
+    ) -> Result<(), E>
+    where
+        I: IntoIterator<Item = Result<ListElement, E>>,
+        E: From<io::Error>
+    {
+        let mut failed = None;
+        let mut writer = xml::encode::Writer::new(writer);
+        let res = writer.element(MSG.into())?
+            .attr("xmlns", NS)?
+            .attr("version", VERSION)?
+            .attr("type", "reply")?
+            .content(|content| {
+                for item in elements {
+                    match item {
+                        Ok(item) => item.write_xml(content)?,
+                        Err(err) => {
+                            failed = Some(err);
+                            return Err(io::Error::new(
+                                io::ErrorKind::Other, "element failed"
+                            ))
+                        }
+                    }
+                }
+                Ok(())
+            });
+        if let Some(err) = failed {
+            return Err(err)
+        }
+        res?;
+        writer.done()?;
+        Ok(())
+    }
+}
+
 
-    pub fn add_element(&mut self, element: ListElement) {
-        self.elements.push(element);
-    }
+//------------ ListReplyReader -----------------------------------------------
 
-    pub fn elements(&self) -> &Vec<ListElement> {
-        &self.elements
+/// A reader for list reply messages producing one element at a time.
+///
+/// The reader decodes the XML of a list reply message incrementally. It
+/// can be used as an iterator over the elements of the reply. Only the
+/// current element is kept in memory.
+///
+/// If the message turns out not to be a list reply, i.e., if it contains
+/// a success or error report element, the reader returns
+/// [`Error::NotListReply`]. Such messages should be decoded normally via
+/// [`Message::decode`].
+pub struct ListReplyReader<R: io::BufRead> {
+    /// The XML reader.
+    reader: xml::decode::Reader<R>,
+
+    /// The content of the message element.
+    content: Content,
+
+    /// Are we done?
+    ///
+    /// This is set once the end of the message was reached or an error
+    /// happened.
+    done: bool,
+
+    /// The token for cancelling reading.
+    cancel: Option<CancelToken>,
+}
+
+impl<R: io::BufRead> ListReplyReader<R> {
+    /// Creates a new reader by reading the start of the message.
+    ///
+    /// Fails if the source does not contain a reply message.
+    pub fn new(source: R) -> Result<Self, Error> {
+        let mut reader = xml::decode::Reader::new(source);
+        let mut kind = None;
+        let mut version: Option<String> = None;
+        let content = reader.start(|element| {
+            if element.name().local() != MSG {
+                return Err(XmlError::Malformed)
+            }
+            element.attributes(|name, value| match name {
+                b"version" => {
+                    version = Some(value.ascii_into()?);
+                    Ok(())
+                }
+                b"type" => {
+                    kind = Some(match value.ascii_into::<String>()?.as_str() {
+                        "query" => Ok(MessageKind::Query),
+                        "reply" => Ok(MessageKind::Reply),
+                        _ => Err(XmlError::Malformed)
+                    }?);
+                    Ok(())
+                }
+                _ => Err(XmlError::Malformed)
+            })
+        })?;
+        if let Some(version) = version {
+            if version != VERSION {
+                return Err(Error::UnsupportedVersion(version))
+            }
+        }
+        match kind.ok_or(XmlError::Malformed)? {
+            MessageKind::Reply => { }
+            MessageKind::Query => return Err(Error::NotReply)
+        }
+        Ok(ListReplyReader { reader, content, done: false, cancel: None })
     }
 
-    pub fn into_elements(self) -> Vec<ListElement> {
-        self.elements
+    /// Sets a token for cancelling the reader.
+    ///
+    /// Once the token has been cancelled, the reader returns
+    /// [`Error::Cancelled`] for the next element.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = Some(cancel)
     }
 
-    pub fn into_withdraw_delta(self) -> PublishDelta {
-        let mut delta = PublishDelta::empty();
-
-        for el in self.elements.into_iter() {
-            let (uri, hash) = el.unpack();
-            let withdraw = Withdraw::with_hash_tag(uri, hash);
-            delta.add_withdraw(withdraw);
+    /// Returns the next element of the list reply.
+    ///
+    /// Returns `Ok(None)` once the end of the message has been reached.
+    pub fn next_element(&mut self) -> Result<Option<ListElement>, Error> {
+        if self.done {
+            return Ok(None)
+        }
+        if let Some(ref cancel) = self.cancel {
+            if let Err(err) = cancel.check() {
+                self.done = true;
+                return Err(err.into())
+            }
+        }
+        let res = self.read_element();
+        if !matches!(res, Ok(Some(_))) {
+            self.done = true
+        }
+        res
+    }
 
+    /// Reads the next element.
+    fn read_element(&mut self) -> Result<Option<ListElement>, Error> {
+        let mut uri: Option<uri::Rsync> = None;
+        let mut hash: Option<rrdp::Hash> = None;
+        let element = self.content.take_opt_element(
+            &mut self.reader, |element| {
+                match element.name().local() {
+                    LIST => { }
+                    SUCCESS | REPORT_ERROR => {
+                        return Err(Error::NotListReply)
+                    }
+                    _ => return Err(XmlError::Malformed.into())
+                }
+                element.attributes(|name, value| match name {
+                    b"hash" => {
+                        let hex: String = value.ascii_into()?;
+                        hash = Some(
+                            rrdp::Hash::from_str(&hex).map_err(|_| {
+                                XmlError::Malformed
+                            })?
+                        );
+                        Ok(())
+                    }
+                    b"uri" => {
+                        uri = Some(value.ascii_into()?);
+                        Ok(())
+                    }
+                    _ => Err(XmlError::Malformed)
+                }).map_err(Error::XmlError)
+            }
+        )?;
+        match element {
+            Some(mut element) => {
+                element.take_end(&mut self.reader)?;
+                Ok(Some(ListElement {
+                    uri: uri.ok_or(XmlError::Malformed)?,
+                    hash: hash.ok_or(XmlError::Malformed)?,
+                }))
+            }
+            None => {
+                self.content.take_end(&mut self.reader)?;
+                self.reader.end()?;
+                Ok(None)
+            }
         }
+    }
+}
 
-        delta
+impl<R: io::BufRead> Iterator for ListReplyReader<R> {
+    type Item = Result<ListElement, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_element().transpose()
     }
 }
 
@@ -1108,7 +2193,12 @@ impl ListReply {
 
 /// This type represents a single object that is published at a publication
 /// server.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+///
+/// Elements are ordered by their URI first and their hash second.
+#[derive(
+    Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ListElement {
     uri: uri::Rsync,
     hash: rrdp::Hash,
@@ -1158,6 +2248,7 @@ impl ListElement {
 /// This type represents the error report as described in
 /// https://tools.ietf.org/html/rfc8181#section-3.5 and 3.6
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ErrorReply {
     errors: Vec<ReportError>,
 }
@@ -1222,6 +2313,62 @@ impl ReportError {
             failed_pdu: None,
         }
     }
+
+    /// Creates an entry for a failed element of a publish query.
+    ///
+    /// The entry will include the element as the failed PDU and its tag.
+    pub fn for_element(
+        error_code: ReportErrorCode,
+        element: PublishDeltaElement,
+    ) -> Self {
+        let mut res = Self::with_code(error_code);
+        res.tag = element.tag().cloned();
+        res.failed_pdu = Some(QueryPdu::PublishDeltaElement(element));
+        res
+    }
+
+    /// Creates an entry for a query using an unsupported version.
+    ///
+    /// Since RFC 8181 doesn’t define a specific error code for this case,
+    /// the entry uses the `xml_error` code and an error text stating the
+    /// received and supported versions.
+    pub fn unsupported_version(version: &str) -> Self {
+        let mut res = Self::with_code(ReportErrorCode::XmlError);
+        res.error_text = Some(format!(
+            "unsupported protocol version '{}', supported version is '{}'",
+            version, VERSION
+        ));
+        res
+    }
+
+    /// Sets the error text.
+    pub fn set_error_text(&mut self, text: Option<String>) {
+        self.error_text = text
+    }
+}
+
+/// # Data and Access
+///
+impl ReportError {
+    /// Returns the error code.
+    pub fn error_code(&self) -> &ReportErrorCode {
+        &self.error_code
+    }
+
+    /// Returns the tag of the failed PDU if present.
+    pub fn tag(&self) -> Option<&String> {
+        self.tag.as_ref()
+    }
+
+    /// Returns the error text if present.
+    pub fn error_text(&self) -> Option<&str> {
+        self.error_text.as_deref()
+    }
+
+    /// Returns the failed PDU if present.
+    pub fn failed_pdu(&self) -> Option<&QueryPdu> {
+        self.failed_pdu.as_ref()
+    }
 }
 
 /// # Encode to XML
@@ -1276,9 +2423,11 @@ impl ReportError {
         tag: Option<String>,
         report_error_element: &mut Content,
         reader: &mut xml::decode::Reader<R>,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, Error> {
         let mut error_text: Option<String> = None;
         let mut failed_pdu: Option<QueryPdu> = None;
+        let mut failed_pdu_seen = false;
         
         // if only we could look ahead to see if/what elements
         // are present then this would be easier..
@@ -1298,9 +2447,7 @@ impl ReportError {
                             failed_pdu_found = true;
                             Ok(())
                         }
-                        _ => {
-                            Err(XmlError::Malformed)
-                        }
+                        _ => ctx.unknown.add(&error_element)
                     }
                 }
             )?;
@@ -1312,6 +2459,14 @@ impl ReportError {
             };
             
             if error_text_found {
+                if error_text.is_some() {
+                    ctx.deviation(Deviation::DuplicateElement("error_text"))?;
+                }
+                if failed_pdu_seen {
+                    ctx.deviation(Deviation::ElementOrder {
+                        element: "report_error", child: "error_text"
+                    })?;
+                }
                 let text = el.take_text( reader, |text| {
                     text.to_ascii().map(|t| t.to_string())
                 })?;
@@ -1320,21 +2475,45 @@ impl ReportError {
             }
             
             if failed_pdu_found {
-                failed_pdu = QueryPdu::decode_opt(&mut el, reader)?;
+                if failed_pdu_seen {
+                    ctx.deviation(Deviation::DuplicateElement("failed_pdu"))?;
+                }
+                failed_pdu_seen = true;
+                failed_pdu = QueryPdu::decode_opt(&mut el, reader, ctx)?;
             }
 
-            // close element
-            el.take_end(reader)?;
+            // close element, skipping over anything unknown
+            if error_text_found || failed_pdu_found {
+                el.take_end(reader)?;
+            } else {
+                el.skip(reader)?;
+            }
         }
 
         Ok(ReportError { error_code, tag, error_text, failed_pdu })
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for ReportError {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(ReportError {
+            error_code: u.arbitrary()?,
+            tag: arbitrary_tag(u)?,
+            error_text: arbitrary_text(u)?,
+            failed_pdu: u.arbitrary()?,
+        })
+    }
+}
+
+
 //------------ ReportErrorCodes ----------------------------------------------
 
 /// The allowed error codes defined in RFC8181 section 2.5
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ReportErrorCode {
     XmlError,
     PermissionFailure,
@@ -1410,7 +2589,10 @@ impl Base64 {
 
     /// Decodes into bytes (e.g. for saving to disk for rsync)
     pub fn to_bytes(&self) -> Bytes {
-        Bytes::from(base64::Xml.decode(self.0.as_ref()).unwrap())
+        // All ways to create a value check that the content is valid.
+        Bytes::from(
+            base64::Xml.decode(self.0.as_ref()).expect("invalid Base 64")
+        )
     }
 
     /// Generates the rrdp::Hash for the base64 encoded content
@@ -1459,6 +2641,9 @@ impl<'de> Deserialize<'de> for Base64 {
         D: Deserializer<'de>,
     {
         let string = String::deserialize(deserializer)?;
+        if let Err(err) = base64::Xml.decode(&string) {
+            return Err(serde::de::Error::custom(err))
+        }
         Ok(Base64(string.into()))
     }
 }
@@ -1493,17 +2678,80 @@ impl From<&Crl> for Base64 {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Base64 {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        // Published objects are never empty.
+        let mut content: Vec<u8> = u.arbitrary()?;
+        if content.is_empty() {
+            content.push(u.arbitrary()?);
+        }
+        Ok(Base64::from_content(&content))
+    }
+}
+
+
+//------------ Arbitrary Helpers ---------------------------------------------
+
+/// Creates an arbitrary optional tag.
+///
+/// Tags are made of hexadecimal digits, similar to the tags created by
+/// the `with_hash_tag` functions, so they survive the trip through XML.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_tag(
+    u: &mut arbitrary::Unstructured
+) -> arbitrary::Result<Option<String>> {
+    if !u.arbitrary::<bool>()? {
+        return Ok(None)
+    }
+    arbitrary_ascii(u, b"0123456789ABCDEFabcdef").map(Some)
+}
+
+/// Creates an arbitrary optional error text.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_text(
+    u: &mut arbitrary::Unstructured
+) -> arbitrary::Result<Option<String>> {
+    if !u.arbitrary::<bool>()? {
+        return Ok(None)
+    }
+    arbitrary_ascii(
+        u, b" .,0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz"
+    ).map(Some)
+}
+
+/// Creates a non-empty string from the given characters.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_ascii(
+    u: &mut arbitrary::Unstructured, chars: &[u8]
+) -> arbitrary::Result<String> {
+    let len = u.int_in_range(1..=64)?;
+    let mut res = String::with_capacity(len);
+    for _ in 0..len {
+        res.push(char::from(*u.choose(chars)?));
+    }
+    Ok(res)
+}
+
 //------------ PublicationMessageError ---------------------------------------
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     InvalidVersion,
     XmlError(XmlError),
     InvalidErrorCode(String),
     CmsDecode(String),
     Validation(ValidationError),
+    InvalidContent(uri::Rsync, XmlError),
+    Deviation(Deviation),
+    UnsupportedVersion(String),
     NotQuery,
-    NotReply
+    NotReply,
+    NotListReply,
+    Cancelled,
 }
 
 impl fmt::Display for Error {
@@ -1520,12 +2768,36 @@ impl fmt::Display for Error {
             Error::Validation(e) => {
                 write!(f, "CMS is not valid: {}", e)
             }
+            Error::InvalidContent(uri, e) => {
+                write!(f, "Invalid content for {}: {}", uri, e)
+            }
+            Error::Deviation(deviation) => {
+                write!(f, "Not conforming to RFC 8181: {}", deviation)
+            }
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version '{}'", version)
+            }
             Error::NotQuery => {
                 write!(f, "was not a query message")
             }
             Error::NotReply => {
                 write!(f, "was not a reply message")
             }
+            Error::NotListReply => {
+                write!(f, "was not a list reply message")
+            }
+            Error::Cancelled => Cancelled.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::XmlError(e) => Some(e),
+            Error::Validation(e) => Some(e),
+            Error::InvalidContent(_, e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -1536,6 +2808,12 @@ impl From<XmlError> for Error {
     }
 }
 
+impl From<Cancelled> for Error {
+    fn from(_: Cancelled) -> Self {
+        Error::Cancelled
+    }
+}
+
 impl From<ValidationError> for Error {
     fn from(e: ValidationError) -> Self {
         Error::Validation(e)
@@ -1549,6 +2827,20 @@ mod tests {
 
     use super::*;
 
+    /// Returns decode options skipping unknown elements.
+    fn lenient() -> DecodeOptions {
+        let mut res = DecodeOptions::new();
+        res.set_skip_unknown(true);
+        res
+    }
+
+    /// Returns decode options using the given profile.
+    fn profile(profile: DecodeProfile) -> DecodeOptions {
+        let mut res = DecodeOptions::new();
+        res.set_profile(profile);
+        res
+    }
+
     #[test]
     fn parse_and_encode_list_query() {
         let xml = include_bytes!("../../test-data/ca/rfc8181/list.xml");
@@ -1648,6 +2940,92 @@ mod tests {
         assert_eq!(msg, re_decoded);
     }
 
+    #[test]
+    fn list_reply_streaming() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list-reply.xml");
+        let list = match Message::decode(xml.as_ref()).unwrap() {
+            Message::Reply(Reply::List(list)) => list,
+            _ => panic!("not a list reply")
+        };
+        let streamed = ListReplyReader::new(
+            xml.as_ref()
+        ).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(list.elements(), &streamed);
+
+        let mut written = Vec::new();
+        ListReply::write_xml_streaming(
+            streamed.into_iter().map(Ok::<_, io::Error>), &mut written
+        ).unwrap();
+        assert_eq!(
+            Message::decode(written.as_slice()).unwrap(),
+            Message::list_reply(list)
+        );
+
+        let xml = include_bytes!(
+            "../../test-data/ca/rfc8181/list-reply-empty-short.xml"
+        );
+        assert!(
+            ListReplyReader::new(xml.as_ref()).unwrap().next().is_none()
+        );
+
+        let xml = include_bytes!(
+            "../../test-data/ca/rfc8181/success-reply.xml"
+        );
+        assert!(matches!(
+            ListReplyReader::new(xml.as_ref()).unwrap().next(),
+            Some(Err(Error::NotListReply))
+        ));
+    }
+
+    #[test]
+    fn decode_cancellable() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list-reply.xml");
+        let cancel = CancelToken::new();
+        let mut options = DecodeOptions::new();
+        options.set_cancel_token(cancel.clone());
+        assert_eq!(
+            Message::decode_with(xml.as_ref(), &options).unwrap().message,
+            Message::decode(xml.as_ref()).unwrap()
+        );
+
+        let mut reader = ListReplyReader::new(xml.as_ref()).unwrap();
+        reader.set_cancel_token(cancel.clone());
+        assert!(reader.next().unwrap().is_ok());
+        cancel.cancel();
+        assert!(matches!(reader.next(), Some(Err(Error::Cancelled))));
+        assert!(reader.next().is_none());
+
+        assert!(matches!(
+            Message::decode_with(xml.as_ref(), &options),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn withdraw_all() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list-reply.xml");
+        let list = match Message::decode(xml.as_ref()).unwrap() {
+            Message::Reply(Reply::List(list)) => list,
+            _ => panic!("not a list reply")
+        };
+        let delta = PublishDelta::withdraw_all(
+            ListReplyReader::new(xml.as_ref()).unwrap().map(Result::unwrap)
+        );
+        assert_eq!(delta.len(), list.elements().len());
+        for (withdraw, element) in delta.clone().into_elements().iter().zip(
+            list.elements()
+        ) {
+            match withdraw {
+                PublishDeltaElement::Withdraw(withdraw) => {
+                    assert_eq!(withdraw.uri(), element.uri());
+                    assert_eq!(withdraw.hash(), element.hash());
+                }
+                _ => panic!("not a withdraw")
+            }
+        }
+        assert_eq!(Message::withdraw_all(list), Message::delta(delta));
+    }
+
     #[test]
     fn parse_and_success_reply() {
         let xml = include_bytes!("../../test-data/ca/rfc8181/success-reply.xml");
@@ -1669,6 +3047,524 @@ mod tests {
 
         assert_eq!(msg, re_decoded);
     }
+
+    #[test]
+    fn decode_lenient_query() {
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="4" type="query">
+              <withdraw
+                uri="rsync://wombat.example/Alice/blCrcCp9ltyPDNzYKPfxc.cer"
+                hash="caaabbbcccdddeeefff000111222333444555666777888999aaabbbccc000fff"/>
+              <future foo="bar"><nested><deeper/></nested>text</future>
+              <withdraw
+                uri="rsync://wombat.example/Alice/blCrcCp9ltyPDNzYKPfxd.cer"
+                hash="caaabbbcccdddeeefff000111222333444555666777888999aaabbbccc000fff"/>
+              <other xmlns="urn:example:other"/>
+            </msg>"#;
+
+        assert!(Message::decode(xml.as_bytes()).is_err());
+
+        let Decoded { message: msg, unknown, .. } = Message::decode_with(
+            xml.as_bytes(), &lenient()
+        ).unwrap();
+        match msg {
+            Message::Query(Query::Delta(delta)) => {
+                assert_eq!(delta.len(), 2)
+            }
+            _ => panic!("expected delta query")
+        }
+        assert_eq!(unknown.len(), 2);
+        assert_eq!(unknown[0].local(), "future");
+        assert_eq!(
+            unknown[0].namespace(),
+            Some("http://www.hactrn.net/uris/rpki/publication-spec/")
+        );
+        assert_eq!(unknown[1].local(), "other");
+        assert_eq!(unknown[1].to_string(), "{urn:example:other}other");
+    }
+
+    #[test]
+    fn decode_lenient_error_reply() {
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="4" type="reply">
+              <report_error error_code="other_error" tag="x">
+                <error_text>Oops</error_text>
+                <error_details><line>12</line></error_details>
+              </report_error>
+            </msg>"#;
+
+        assert!(Message::decode(xml.as_bytes()).is_err());
+
+        let Decoded { message: msg, unknown, .. } = Message::decode_with(
+            xml.as_bytes(), &lenient()
+        ).unwrap();
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].local(), "error_details");
+        let known = Message::decode(msg.to_xml_string().as_bytes()).unwrap();
+        assert_eq!(msg, known);
+    }
+
+    #[test]
+    fn decode_error_source() {
+        use std::error::Error as _;
+
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="4" type="query">
+              <publish uri="rsync://wombat.example/Alice/a.cer">!!</publish>
+            </msg>"#;
+
+        let err = Message::decode(xml.as_bytes()).unwrap_err();
+        let xml_err = err.source().unwrap();
+        assert!(xml_err.downcast_ref::<XmlError>().is_some());
+        let base64_err = xml_err.source().unwrap();
+        assert!(
+            base64_err.downcast_ref::<base64::XmlDecodeError>().is_some()
+        );
+    }
+
+    #[test]
+    fn decode_content_whitespace() {
+        let xml = "<msg \
+            xmlns=\"http://www.hactrn.net/uris/rpki/publication-spec/\" \
+            version=\"4\" type=\"query\">\r\n\
+            <publish uri=\"rsync://wombat.example/Alice/a.cer\">\r\n\
+            \tZm9v\r\n  IGJh\rcg\n\no=\t\r\n\
+            </publish></msg>";
+        let msg = Message::decode(xml.as_bytes()).unwrap();
+        let delta = match msg.as_query().unwrap() {
+            Query::Delta(delta) => delta,
+            _ => panic!("expected delta query"),
+        };
+        let (_, uri, content) = match delta.into_elements().pop() {
+            Some(PublishDeltaElement::Publish(publish)) => publish.unpack(),
+            _ => panic!("expected publish element"),
+        };
+        assert_eq!(uri.as_str(), "rsync://wombat.example/Alice/a.cer");
+        assert_eq!(content.to_bytes().as_ref(), b"foo bar\n");
+
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="4" type="query">
+              <publish uri="rsync://wombat.example/Alice/a.cer">
+                Zm9vIGJh
+                cgo=
+              </publish>
+              <publish uri="rsync://wombat.example/Alice/b.cer">
+                Zm9vIGJh
+                c\o=
+              </publish>
+            </msg>"#;
+        match Message::decode(xml.as_bytes()) {
+            Err(Error::InvalidContent(uri, _)) => {
+                assert_eq!(uri.as_str(), "rsync://wombat.example/Alice/b.cer")
+            }
+            _ => panic!("expected invalid content error")
+        }
+    }
+
+    #[test]
+    fn decode_profile_strict_conforming() {
+        for xml in [
+            include_str!("../../test-data/ca/rfc8181/list.xml"),
+            include_str!("../../test-data/ca/rfc8181/publish-multi.xml"),
+            include_str!("../../test-data/ca/rfc8181/list-reply.xml"),
+            include_str!("../../test-data/ca/rfc8181/success-reply.xml"),
+            include_str!("../../test-data/ca/rfc8181/error-reply.xml"),
+        ] {
+            let decoded = Message::decode_with(
+                xml.as_bytes(), &profile(DecodeProfile::Strict)
+            ).unwrap();
+            let (msg, deviations) = (decoded.message, decoded.deviations);
+            assert!(deviations.is_empty());
+            assert_eq!(msg, Message::decode(xml.as_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn decode_profile_deviations() {
+        let xml = r#"<msg type="query">
+              <list hash="e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"/>
+            </msg>"#;
+        assert!(matches!(
+            Message::decode_with(
+                xml.as_bytes(), &profile(DecodeProfile::Strict)
+            ),
+            Err(Error::Deviation(Deviation::Namespace(None)))
+        ));
+        let Decoded { message: msg, deviations, .. } = Message::decode_with(
+            xml.as_bytes(), &profile(DecodeProfile::Compatible)
+        ).unwrap();
+        assert_eq!(msg, Message::list_query());
+        assert_eq!(
+            deviations,
+            [
+                Deviation::Namespace(None),
+                Deviation::MissingVersion,
+                Deviation::UnexpectedAttribute {
+                    element: "list", attribute: "hash"
+                },
+            ]
+        );
+        assert!(Message::decode(xml.as_bytes()).is_ok());
+
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="4" type="reply">
+              <report_error error_code="other_error">
+                <failed_pdu>
+                  <withdraw uri="rsync://wombat.example/Alice/a.cer" hash="e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"/>
+                </failed_pdu>
+                <error_text>Oops</error_text>
+              </report_error>
+            </msg>"#;
+        assert!(matches!(
+            Message::decode_with(
+                xml.as_bytes(), &profile(DecodeProfile::Strict)
+            ),
+            Err(Error::Deviation(Deviation::ElementOrder { .. }))
+        ));
+        let deviations = Message::decode_with(
+            xml.as_bytes(), &profile(DecodeProfile::Compatible)
+        ).unwrap().deviations;
+        assert_eq!(
+            deviations,
+            [
+                Deviation::ElementOrder {
+                    element: "report_error", child: "error_text"
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_untrusted() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list.xml");
+        let mut options = DecodeOptions::new();
+        options.set_limit(Some(xml.len()));
+        assert_eq!(
+            Message::decode_with(xml.as_ref(), &options).unwrap().message,
+            Message::decode(xml.as_ref()).unwrap()
+        );
+        options.set_limit(Some(xml.len() - 1));
+        assert!(matches!(
+            Message::decode_with(xml.as_ref(), &options),
+            Err(Error::XmlError(XmlError::TooLarge(_)))
+        ));
+    }
+
+    #[test]
+    fn deserialize_invalid_base64() {
+        assert!(
+            serde_json::from_str::<Base64>("\"Zm9vIGJhcgo=\"").is_ok()
+        );
+        assert!(
+            serde_json::from_str::<Base64>("\"Zm9v*GJhcgo=\"").is_err()
+        );
+    }
+
+    #[test]
+    fn decode_lenient_known_content() {
+        let xml = include_bytes!("../../test-data/ca/rfc8181/list-reply.xml");
+        let Decoded { message: msg, unknown, .. } = Message::decode_with(
+            xml.as_ref(), &lenient()
+        ).unwrap();
+        assert!(unknown.is_empty());
+        assert_eq!(msg, Message::decode(xml.as_ref()).unwrap());
+    }
+
+    /// Returns pseudo-random data for creating arbitrary values.
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_data(seed: u64, len: usize) -> Vec<u8> {
+        // A simple xorshift generator is good enough for this.
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_messages_round_trip() {
+        use arbitrary::Unstructured;
+
+        for seed in 1..200 {
+            let data = arbitrary_data(seed, 2048);
+            let mut u = Unstructured::new(&data);
+            let msg: Message = u.arbitrary().unwrap();
+            let xml = msg.to_xml_string();
+            let decoded = Message::decode(xml.as_bytes()).unwrap();
+            assert_eq!(xml, decoded.to_xml_string());
+        }
+    }
+
+    #[test]
+    fn split_delta() {
+        let uri = |s: &str| uri::Rsync::from_str(s).unwrap();
+        let content = Base64::from_content(&[0u8; 300]);
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(Withdraw::new(
+            None, uri("rsync://localhost/ca/old.roa"), content.to_hash()
+        ));
+        delta.add_update(Update::new(
+            None, uri("rsync://localhost/ca/ca.mft"), content.clone(),
+            content.to_hash()
+        ));
+        delta.add_update(Update::new(
+            None, uri("rsync://localhost/ca/ca.crl"), content.clone(),
+            content.to_hash()
+        ));
+        delta.add_publish(Publish::new(
+            None, uri("rsync://localhost/ca/a.roa"), content.clone()
+        ));
+        delta.add_publish(Publish::new(
+            None, uri("rsync://localhost/ca/b.roa"), content.clone()
+        ));
+
+        let paths = |deltas: &[PublishDelta]| -> Vec<Vec<String>> {
+            deltas.iter().map(|delta| {
+                delta.iter().map(|element| {
+                    element.uri().path().to_string()
+                }).collect()
+            }).collect()
+        };
+
+        assert!(PublishDelta::empty().split(&Default::default()).is_empty());
+        assert_eq!(
+            paths(&delta.clone().split(&SplitLimits {
+                max_elements: Some(2), .. Default::default()
+            })),
+            [
+                vec!["a.roa", "b.roa"],
+                vec!["ca.crl", "ca.mft"],
+                vec!["old.roa"],
+            ]
+        );
+        let size = delta.iter().map(|el| el.size_approx()).max().unwrap();
+        let split = delta.clone().split(&SplitLimits {
+            max_size: Some(size * 2), .. Default::default()
+        });
+        assert!(split.iter().all(|delta| {
+            delta.iter().map(|el| el.size_approx()).sum::<usize>()
+                <= size * 2
+        }));
+        assert_eq!(split.iter().map(PublishDelta::len).sum::<usize>(), 5);
+
+        // An element larger than the limit gets its own delta.
+        let split = delta.clone().split(&SplitLimits {
+            max_size: Some(1), .. Default::default()
+        });
+        assert_eq!(split.len(), 5);
+
+        // With a URI appearing twice, the order is kept.
+        delta.add_withdraw(Withdraw::new(
+            None, uri("rsync://localhost/ca/a.roa"), content.to_hash()
+        ));
+        assert_eq!(
+            paths(&delta.split(&SplitLimits {
+                max_elements: Some(3), .. Default::default()
+            })),
+            [
+                vec!["old.roa", "ca.mft", "ca.crl"],
+                vec!["a.roa", "b.roa", "a.roa"],
+            ]
+        );
+    }
+
+    #[test]
+    fn canonicalize_list_reply() {
+        let element = |uri: &str, content: &[u8]| {
+            ListElement::new(
+                uri::Rsync::from_str(uri).unwrap(),
+                Base64::from_content(content).to_hash()
+            )
+        };
+        let a = element("rsync://localhost/ca/a.cer", b"a");
+        let b = element("rsync://localhost/ca/b.cer", b"b");
+        let mut left = ListReply::new(vec![b.clone(), a.clone(), b.clone()]);
+        let mut right = ListReply::new(vec![
+            a, element("rsync://LOCALHOST/ca/b.cer", b"b")
+        ]);
+        assert_ne!(left, right);
+        left.canonicalize();
+        right.canonicalize();
+        assert_eq!(left, right);
+        assert_eq!(left.len(), 2);
+        assert_eq!(left.iter().next().unwrap().uri().path(), "a.cer");
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            version="5" type="query">
+              <list/>
+            </msg>"#;
+        assert!(matches!(
+            Message::decode(xml.as_bytes()),
+            Err(Error::UnsupportedVersion(ref version)) if version == "5"
+        ));
+        assert!(matches!(
+            ListReplyReader::new(xml.as_bytes()),
+            Err(Error::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            Message::decode_with(
+                xml.as_bytes(), &profile(DecodeProfile::Compatible)
+            ),
+            Err(Error::UnsupportedVersion(ref version)) if version == "5"
+        ));
+        let xml = r#"<msg
+            xmlns="http://www.hactrn.net/uris/rpki/publication-spec/"
+            type="query">
+              <list/>
+            </msg>"#;
+        assert_eq!(
+            Message::decode_with(
+                xml.as_bytes(), &profile(DecodeProfile::Compatible)
+            ).unwrap().deviations,
+            [Deviation::MissingVersion]
+        );
+
+        let error = ReportError::unsupported_version("5");
+        assert_eq!(error.error_code(), &ReportErrorCode::XmlError);
+        assert!(error.error_text().unwrap().contains("'5'"));
+    }
+
+    #[test]
+    fn decode_quirks() {
+        let xml = r#"<msg type="query" version="4">
+              <list/>
+              <future/>
+            </msg>"#;
+        let decode = |quirks: &Quirks| {
+            Message::decode_with(
+                xml.as_bytes(), &DecodeOptions::from_quirks(quirks)
+            )
+        };
+        assert!(matches!(
+            decode(&Quirks::strict()),
+            Err(Error::Deviation(Deviation::Namespace(None)))
+        ));
+        assert!(decode(&Quirks::default()).is_err());
+        assert_eq!(
+            decode(
+                &Quirks { unknown_elements: true, .. Default::default() }
+            ).unwrap().message,
+            Message::list_query()
+        );
+    }
+
+    #[test]
+    fn apply_quirks() {
+        let uri = |s: &str| uri::Rsync::from_str(s).unwrap();
+        let a = Base64::from_content(b"a");
+        let c = Base64::from_content(b"c").to_hash();
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(Publish::new(
+            None, uri("rsync://localhost/ca/a.cer"), a.clone()
+        ));
+        delta.add_withdraw(Withdraw::new(
+            Some("keep".into()), uri("rsync://localhost/ca/c.cer"), c
+        ));
+        let mut msg = Message::Query(Query::Delta(delta.clone()));
+        msg.apply_quirks(&Quirks::default());
+        assert_eq!(msg, Message::Query(Query::Delta(delta)));
+
+        msg.apply_quirks(&Quirks { hash_tags: true, .. Default::default() });
+        let delta = match msg {
+            Message::Query(Query::Delta(delta)) => delta,
+            _ => panic!("expected delta query")
+        };
+        let tags: Vec<_> = delta.iter().map(|element| {
+            element.tag().cloned()
+        }).collect();
+        assert_eq!(
+            tags, [Some(a.to_hash().to_string()), Some("keep".into())]
+        );
+    }
+
+    #[test]
+    fn retry_delta() {
+        let uri = |s: &str| uri::Rsync::from_str(s).unwrap();
+        let a = Base64::from_content(b"a");
+        let b = Base64::from_content(b"b");
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(Publish::new(
+            None, uri("rsync://localhost/ca/a.cer"), a.clone()
+        ));
+        delta.add_update(Update::new(
+            None, uri("rsync://localhost/ca/b.cer"), b.clone(),
+            Base64::from_content(b"old").to_hash()
+        ));
+        delta.add_withdraw(Withdraw::new(
+            None, uri("rsync://localhost/ca/c.cer"),
+            Base64::from_content(b"c").to_hash()
+        ));
+        delta.set_unique_tags("7");
+        let elements = delta.clone().into_elements();
+        assert_eq!(elements[2].tag().map(String::as_str), Some("7-2"));
+
+        assert_eq!(
+            delta.classify_retry(&Reply::Success), RetryOutcome::Applied
+        );
+
+        let mut errors = ErrorReply::empty();
+        errors.add_error(ReportError::for_element(
+            ReportErrorCode::ObjectAlreadyPresent, elements[0].clone()
+        ));
+        errors.add_error(ReportError::for_element(
+            ReportErrorCode::NoObjectMatchingHash, elements[1].clone()
+        ));
+        errors.add_error(ReportError::for_element(
+            ReportErrorCode::NoObjectPresent, elements[2].clone()
+        ));
+        assert_eq!(
+            delta.classify_retry(&Reply::ErrorReply(errors.clone())),
+            RetryOutcome::AlreadyApplied
+        );
+
+        errors.add_error(ReportError::for_element(
+            ReportErrorCode::PermissionFailure, elements[0].clone()
+        ));
+        assert_eq!(
+            delta.classify_retry(&Reply::ErrorReply(errors)),
+            RetryOutcome::Failed
+        );
+        let mut errors = ErrorReply::empty();
+        errors.add_error(ReportError::for_element(
+            ReportErrorCode::NoObjectPresent, elements[0].clone()
+        ));
+        assert_eq!(
+            delta.classify_retry(&Reply::ErrorReply(errors)),
+            RetryOutcome::Failed
+        );
+
+        let mut list = ListReply::empty();
+        list.add_element(ListElement::new(
+            uri("rsync://localhost/ca/c.cer"),
+            Base64::from_content(b"c").to_hash()
+        ));
+        assert_eq!(delta.status_in(&list), DeltaStatus::NotApplied);
+        list = ListReply::empty();
+        list.add_element(ListElement::new(
+            uri("rsync://localhost/ca/a.cer"), a.to_hash()
+        ));
+        list.add_element(ListElement::new(
+            uri("rsync://localhost/ca/b.cer"), b.to_hash()
+        ));
+        assert_eq!(delta.status_in(&list), DeltaStatus::Applied);
+        list = ListReply::empty();
+        list.add_element(ListElement::new(
+            uri("rsync://localhost/ca/a.cer"), a.to_hash()
+        ));
+        assert_eq!(delta.status_in(&list), DeltaStatus::Partial);
+    }
 }
 
 
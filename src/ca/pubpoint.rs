@@ -0,0 +1,573 @@
+//! Building the complete content of a CA’s publication point.
+//!
+//! A CA publishes its products – certificates issued to its children,
+//! ROAs, ASPAs, and so on – in its publication point together with a CRL
+//! and a manifest. The CRL and manifest need to be consistent with each
+//! other and with the published objects: the manifest has to list the CRL
+//! and every object with its current hash, the manifest’s EE certificate
+//! has to refer to the CRL, and the validity windows and numbers of CRL and
+//! manifest should match.
+//!
+//! The [`PublicationPointBuilder`] takes care of all of this. It collects
+//! the objects and revocations, and then creates the CRL and the manifest
+//! in the right order. The resulting [`PublicationPoint`] can be turned
+//! into a [`PublishDelta`] for the publication protocol.
+
+use std::{error, fmt};
+use std::collections::{BTreeMap, HashMap};
+use bytes::Bytes;
+use chrono::Timelike;
+use crate::uri;
+use crate::crypto::{DigestAlgorithm, RpkiSignatureAlgorithm, Signer};
+use crate::crypto::SigningError;
+use crate::repository::crl::{Crl, CrlEntry, TbsCertList};
+use crate::repository::names;
+use crate::repository::manifest::{FileAndHash, Manifest, ManifestContent};
+use crate::repository::sigobj::SignedObjectBuilder;
+use crate::repository::x509::{Serial, Time, Validity};
+use crate::rrdp;
+use super::publication::{
+    Base64, ListElement, Publish, PublishDelta, Update, Withdraw
+};
+
+
+//------------ PublicationPointBuilder ---------------------------------------
+
+/// A builder for the complete content of a publication point.
+///
+/// The builder is created with the information that determines the
+/// CRL and manifest: the rsync URI of the publication point’s directory,
+/// the URI of the CA certificate, the number to use as both the manifest
+/// and CRL number, and the validity window for both. Objects are then
+/// added with [`add_object`][Self::add_object] and revocations with
+/// [`add_revocation`][Self::add_revocation]. Finally,
+/// [`build`][Self::build] signs CRL and manifest with the CA’s key.
+///
+/// The CRL and manifest are named after the key identifier of the CA’s
+/// key unless different names are set explicitly.
+#[derive(Clone, Debug)]
+pub struct PublicationPointBuilder {
+    /// The rsync URI of the publication point’s directory.
+    base_uri: uri::Rsync,
+
+    /// The rsync URI of the CA certificate.
+    ca_cert_uri: uri::Rsync,
+
+    /// The manifest and CRL number.
+    number: Serial,
+
+    /// The serial number of the manifest’s EE certificate.
+    ///
+    /// If this is `None`, `number` is used.
+    ee_serial: Option<Serial>,
+
+    /// The this update time of manifest and CRL.
+    this_update: Time,
+
+    /// The next update time of manifest and CRL.
+    next_update: Time,
+
+    /// The validity of the manifest’s EE certificate if set explicitly.
+    ee_validity: Option<Validity>,
+
+    /// The file name of the CRL if it has been set explicitly.
+    crl_name: Option<String>,
+
+    /// The file name of the manifest if it has been set explicitly.
+    manifest_name: Option<String>,
+
+    /// The revoked certificates.
+    revocations: Vec<CrlEntry>,
+
+    /// The objects to publish keyed by their file name.
+    objects: BTreeMap<String, Bytes>,
+}
+
+impl PublicationPointBuilder {
+    /// Creates a new builder.
+    ///
+    /// The `base_uri` is the URI of the publication point’s directory as
+    /// given in the CA certificate. It should end in a slash. Since times
+    /// are encoded with a precision of seconds, any fractions of a second
+    /// are dropped from `this_update` and `next_update`.
+    pub fn new(
+        base_uri: uri::Rsync,
+        ca_cert_uri: uri::Rsync,
+        number: Serial,
+        this_update: Time,
+        next_update: Time,
+    ) -> Self {
+        PublicationPointBuilder {
+            base_uri,
+            ca_cert_uri,
+            number,
+            ee_serial: None,
+            this_update: whole_seconds(this_update),
+            next_update: whole_seconds(next_update),
+            ee_validity: None,
+            crl_name: None,
+            manifest_name: None,
+            revocations: Vec::new(),
+            objects: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the serial number of the manifest’s EE certificate.
+    ///
+    /// Since the serial number needs to be unique for all certificates
+    /// issued by the CA, it may be necessary to choose a different number
+    /// from the manifest number which is used by default.
+    pub fn set_ee_serial(&mut self, serial: Option<Serial>) {
+        self.ee_serial = serial
+    }
+
+    /// Sets the validity of the manifest’s EE certificate.
+    ///
+    /// If this is `None`, the EE certificate is valid from the this update
+    /// to the next update time as RFC 9286 requires. A different validity
+    /// is mostly useful for testing how relying parties deal with stale
+    /// manifests.
+    pub fn set_ee_validity(&mut self, validity: Option<Validity>) {
+        self.ee_validity = validity
+    }
+
+    /// Sets the file name of the CRL.
+    pub fn set_crl_name(&mut self, name: Option<String>) -> Result<(), Error> {
+        if let Some(ref name) = name {
+            check_name(name, "crl")?;
+        }
+        self.crl_name = name;
+        Ok(())
+    }
+
+    /// Sets the file name of the manifest.
+    pub fn set_manifest_name(
+        &mut self, name: Option<String>
+    ) -> Result<(), Error> {
+        if let Some(ref name) = name {
+            check_name(name, "mft")?;
+        }
+        self.manifest_name = name;
+        Ok(())
+    }
+
+    /// Adds an object to the publication point.
+    ///
+    /// The name is the file name of the object within the publication
+    /// point. It must follow the rules for file names on manifests, i.e.,
+    /// consist of letters, digits, hyphens and underscores followed by a
+    /// full stop and a three letter lowercase extension. CRLs and
+    /// manifests cannot be added – they are created by the builder.
+    pub fn add_object(
+        &mut self, name: impl Into<String>, content: Bytes
+    ) -> Result<(), Error> {
+        let name = name.into();
+        check_name(&name, "")?;
+        if name.ends_with(".crl") || name.ends_with(".mft") {
+            return Err(Error::InvalidName(name))
+        }
+        if self.objects.contains_key(&name) {
+            return Err(Error::DuplicateName(name))
+        }
+        self.objects.insert(name, content);
+        Ok(())
+    }
+
+    /// Adds a revoked certificate to the CRL.
+    pub fn add_revocation(&mut self, entry: CrlEntry) {
+        self.revocations.push(entry)
+    }
+
+    /// Builds the publication point content.
+    ///
+    /// The CRL and manifest are signed with the CA key `key`. The manifest
+    /// lists the CRL and all added objects.
+    pub fn build<S: Signer>(
+        self, signer: &S, key: &S::KeyId
+    ) -> Result<PublicationPoint, SigningError<S::Error>> {
+        let key_info = signer.get_key_info(key)?;
+        let key_id = key_info.key_identifier();
+        let crl_name = self.crl_name.unwrap_or_else(|| {
+            names::crl_name(&key_id)
+        });
+        let manifest_name = self.manifest_name.unwrap_or_else(|| {
+            names::manifest_name(&key_id)
+        });
+        let crl_uri = join(&self.base_uri, &crl_name);
+        let manifest_uri = join(&self.base_uri, &manifest_name);
+
+        // The CRL comes first since the manifest lists it.
+        let crl = TbsCertList::new(
+            RpkiSignatureAlgorithm::default(),
+            key_info.to_subject_name(),
+            self.this_update,
+            self.next_update,
+            self.revocations,
+            key_id,
+            self.number,
+        ).into_crl(signer, key)?;
+        let crl_bytes = Bytes::copy_from_slice(crl.as_bytes());
+
+        let alg = DigestAlgorithm::default();
+        let mut files = vec![FileAndHash::new(
+            Bytes::from(crl_name), hash_bytes(alg, &crl_bytes)
+        )];
+        for (name, content) in &self.objects {
+            files.push(FileAndHash::new(
+                Bytes::copy_from_slice(name.as_bytes()),
+                hash_bytes(alg, content)
+            ));
+        }
+        files.sort_by(|left, right| left.file().cmp(right.file()));
+        let content = ManifestContent::new(
+            self.number, self.this_update, self.next_update, alg,
+            files.iter()
+        );
+        let manifest = content.into_manifest(
+            SignedObjectBuilder::new(
+                self.ee_serial.unwrap_or(self.number),
+                self.ee_validity.unwrap_or_else(|| {
+                    Validity::new(self.this_update, self.next_update)
+                }),
+                crl_uri.clone(),
+                self.ca_cert_uri,
+                manifest_uri.clone(),
+            ),
+            signer, key
+        )?;
+        let manifest_bytes = Bytes::copy_from_slice(manifest.as_bytes());
+
+        let objects = self.objects.into_iter().map(|(name, content)| {
+            (join(&self.base_uri, &name), content)
+        }).collect();
+
+        Ok(PublicationPoint {
+            base_uri: self.base_uri,
+            crl_uri,
+            crl,
+            crl_bytes,
+            manifest_uri,
+            manifest,
+            manifest_bytes,
+            objects,
+        })
+    }
+}
+
+
+//------------ PublicationPoint ----------------------------------------------
+
+/// The complete content of a publication point.
+///
+/// Values of this type are created by [`PublicationPointBuilder`].
+#[derive(Clone, Debug)]
+pub struct PublicationPoint {
+    /// The rsync URI of the publication point’s directory.
+    base_uri: uri::Rsync,
+
+    /// The rsync URI of the CRL.
+    crl_uri: uri::Rsync,
+
+    /// The CRL.
+    crl: Crl,
+
+    /// The encoded CRL.
+    crl_bytes: Bytes,
+
+    /// The rsync URI of the manifest.
+    manifest_uri: uri::Rsync,
+
+    /// The manifest.
+    manifest: Manifest,
+
+    /// The encoded manifest.
+    manifest_bytes: Bytes,
+
+    /// All other objects.
+    objects: Vec<(uri::Rsync, Bytes)>,
+}
+
+impl PublicationPoint {
+    /// Returns the rsync URI of the publication point’s directory.
+    pub fn base_uri(&self) -> &uri::Rsync {
+        &self.base_uri
+    }
+
+    /// Returns the rsync URI of the CRL.
+    pub fn crl_uri(&self) -> &uri::Rsync {
+        &self.crl_uri
+    }
+
+    /// Returns the CRL.
+    pub fn crl(&self) -> &Crl {
+        &self.crl
+    }
+
+    /// Returns the rsync URI of the manifest.
+    pub fn manifest_uri(&self) -> &uri::Rsync {
+        &self.manifest_uri
+    }
+
+    /// Returns the manifest.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Returns an iterator over all objects of the publication point.
+    ///
+    /// The objects are returned in the order they should be published:
+    /// first the objects listed on the manifest, then the CRL, and finally
+    /// the manifest itself.
+    pub fn iter(&self) -> impl Iterator<Item = (&uri::Rsync, &Bytes)> + '_ {
+        self.objects.iter().map(|(uri, content)| (uri, content)).chain([
+            (&self.crl_uri, &self.crl_bytes),
+            (&self.manifest_uri, &self.manifest_bytes),
+        ])
+    }
+
+    /// Returns the publish delta to get from the current to this content.
+    ///
+    /// The current content of the publication server is given via
+    /// `current`, typically taken from a list reply. Only elements below
+    /// the publication point’s directory are considered. Objects not
+    /// present yet are published, objects with different content are
+    /// updated, and objects no longer present are withdrawn. The delta
+    /// lists the elements in the same order as [`iter`][Self::iter] with
+    /// all withdrawals at the end.
+    pub fn to_publish_delta<'a>(
+        &self, current: impl IntoIterator<Item = &'a ListElement>
+    ) -> PublishDelta {
+        let mut current: HashMap<_, _> = current.into_iter().filter(|item| {
+            self.base_uri.is_parent_of(item.uri())
+        }).map(|item| (item.uri(), item.hash())).collect();
+
+        let mut res = PublishDelta::empty();
+        for (uri, content) in self.iter() {
+            let hash = rrdp::Hash::from_data(content);
+            match current.remove(uri) {
+                Some(old) if *old == hash => { }
+                Some(old) => {
+                    res.add_update(Update::new(
+                        None, uri.clone(), Base64::from_content(content),
+                        *old
+                    ))
+                }
+                None => {
+                    res.add_publish(Publish::new(
+                        None, uri.clone(), Base64::from_content(content)
+                    ))
+                }
+            }
+        }
+        let mut withdraw: Vec<_> = current.into_iter().collect();
+        withdraw.sort_by(|left, right| left.0.as_str().cmp(right.0.as_str()));
+        for (uri, hash) in withdraw {
+            res.add_withdraw(Withdraw::new(None, uri.clone(), *hash))
+        }
+        res
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Checks that a file name is acceptable for a manifest.
+///
+/// If `ext` isn’t empty, the name must have this extension.
+fn check_name(name: &str, ext: &str) -> Result<(), Error> {
+    let err = || Error::InvalidName(name.into());
+    let (stem, name_ext) = name.rsplit_once('.').ok_or_else(err)?;
+    if stem.is_empty() || !stem.bytes().all(|ch| {
+        ch.is_ascii_alphanumeric() || ch == b'-' || ch == b'_'
+    }) {
+        return Err(err())
+    }
+    if name_ext.len() != 3 || !name_ext.bytes().all(|ch| {
+        ch.is_ascii_lowercase()
+    }) {
+        return Err(err())
+    }
+    if !ext.is_empty() && name_ext != ext {
+        return Err(err())
+    }
+    Ok(())
+}
+
+/// Joins a checked file name to the base URI.
+fn join(base: &uri::Rsync, name: &str) -> uri::Rsync {
+    base.join(name.as_bytes()).expect("checked file name")
+}
+
+/// Drops the fractional seconds from a time.
+fn whole_seconds(time: Time) -> Time {
+    Time::new(time.with_nanosecond(0).unwrap_or(*time))
+}
+
+/// Returns the hash of some data as bytes.
+fn hash_bytes(alg: DigestAlgorithm, data: &[u8]) -> Bytes {
+    Bytes::copy_from_slice(alg.digest(data).as_ref())
+}
+
+
+//============ Errors ========================================================
+
+//------------ Error ---------------------------------------------------------
+
+/// An object could not be added to a publication point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The file name is not acceptable.
+    InvalidName(String),
+
+    /// An object with this file name has already been added.
+    DuplicateName(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidName(ref name) => {
+                write!(f, "invalid file name '{}'", name)
+            }
+            Error::DuplicateName(ref name) => {
+                write!(f, "duplicate file name '{}'", name)
+            }
+        }
+    }
+}
+
+impl error::Error for Error { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_names() {
+        assert!(check_name("foo.cer", "").is_ok());
+        assert!(check_name("Foo-bar_1.roa", "").is_ok());
+        assert!(check_name("foo.crl", "crl").is_ok());
+        assert!(check_name("foo.crl", "mft").is_err());
+        assert!(check_name("foo", "").is_err());
+        assert!(check_name(".cer", "").is_err());
+        assert!(check_name("foo.CER", "").is_err());
+        assert!(check_name("foo.cerx", "").is_err());
+        assert!(check_name("foo bar.cer", "").is_err());
+        assert!(check_name("foo/bar.cer", "").is_err());
+        assert!(check_name("foo.bar.cer", "").is_err());
+    }
+}
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::crypto::PublicKeyFormat;
+    use crate::crypto::softsigner::OpenSslSigner;
+    use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::tal::TalInfo;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn build_publication_point() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let pubkey = signer.get_key_info(&key).unwrap();
+        let base = uri("rsync://example.com/repo/ca/");
+        let ca_uri = uri("rsync://example.com/repo/ta.cer");
+
+        let mut cert = TbsCert::new(
+            12u64.into(), pubkey.to_subject_name(),
+            Validity::from_secs(86400), None, pubkey.clone(), KeyUsage::Ca,
+            Overclaim::Trim
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(base.clone()));
+        cert.set_rpki_manifest(Some(
+            base.join(format!("{}.mft", pubkey.key_identifier()).as_bytes())
+                .unwrap()
+        ));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&signer, &key).unwrap();
+        let ca = cert.validate_ta(
+            TalInfo::from_name("foo".into()).into_arc(), true
+        ).unwrap();
+
+        let now = Time::now();
+        let mut builder = PublicationPointBuilder::new(
+            base.clone(), ca_uri, 5u64.into(), now, Time::next_week()
+        );
+        builder.add_object("b.roa", Bytes::from_static(b"roa")).unwrap();
+        builder.add_object("a.cer", Bytes::from_static(b"cer")).unwrap();
+        assert_eq!(
+            builder.add_object("a.cer", Bytes::new()),
+            Err(Error::DuplicateName("a.cer".into()))
+        );
+        assert!(builder.add_object("x.mft", Bytes::new()).is_err());
+        builder.add_revocation(CrlEntry::new(3u64.into(), now));
+        let point = builder.build(&signer, &key).unwrap();
+
+        // CRL and manifest are consistent.
+        assert!(point.crl().contains(3u64.into()));
+        assert_eq!(point.crl().crl_number(), 5u64.into());
+        let (ee, content) = Manifest::decode(
+            point.manifest().as_bytes(), true
+        ).unwrap().validate(&ca, true).unwrap();
+        assert_eq!(ee.crl_uri(), Some(point.crl_uri()));
+        assert_eq!(content.manifest_number(), 5u64.into());
+        assert_eq!(content.this_update(), point.crl().this_update());
+        let listed: Vec<_> = content.iter_uris(&base).collect();
+        assert_eq!(listed.len(), 3);
+        for (uri, hash) in listed {
+            let (_, data) = point.iter().find(|item| *item.0 == uri).unwrap();
+            hash.verify(data).unwrap();
+        }
+
+        // Order of the objects.
+        let uris: Vec<_> = point.iter().map(|item| item.0.clone()).collect();
+        assert_eq!(uris[0], uri("rsync://example.com/repo/ca/a.cer"));
+        assert_eq!(uris[1], uri("rsync://example.com/repo/ca/b.roa"));
+        assert_eq!(&uris[2], point.crl_uri());
+        assert_eq!(&uris[3], point.manifest_uri());
+
+        // Deltas.
+        let delta = point.to_publish_delta(&[]);
+        assert_eq!(delta.len(), 4);
+        let current = [
+            ListElement::new(
+                uri("rsync://example.com/repo/ca/a.cer"),
+                rrdp::Hash::from_data(b"cer")
+            ),
+            ListElement::new(
+                uri("rsync://example.com/repo/ca/b.roa"),
+                rrdp::Hash::from_data(b"old")
+            ),
+            ListElement::new(
+                uri("rsync://example.com/repo/ca/gone.roa"),
+                rrdp::Hash::from_data(b"gone")
+            ),
+            ListElement::new(
+                uri("rsync://example.com/repo/other/keep.roa"),
+                rrdp::Hash::from_data(b"keep")
+            ),
+        ];
+        let delta = point.to_publish_delta(&current).into_elements();
+        assert_eq!(delta.len(), 4);
+        assert!(matches!(
+            delta[0], crate::ca::publication::PublishDeltaElement::Update(_)
+        ));
+        assert!(matches!(
+            delta[3], crate::ca::publication::PublishDeltaElement::Withdraw(_)
+        ));
+    }
+}
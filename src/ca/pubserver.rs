@@ -0,0 +1,2166 @@
+//! Server-side processing of publication protocol queries.
+//!
+//! A publication server receives queries from its publishers via the
+//! publication protocol defined in RFC 8181. These queries either ask for
+//! the list of currently published objects or contain a delta of objects
+//! to publish, update, or withdraw. This module contains the
+//! [`PublicationHandler`] which processes these queries for a single
+//! publisher, keeping the published objects in an [`ObjectStore`].
+//!
+//! RFC 8181 requires that all elements of a publish query are applied as
+//! a unit: if any of them fails, none of them must take effect. The
+//! handler therefore first checks the complete delta against the current
+//! content of the store and only then applies it. Should the store fail
+//! while applying the changes, the changes made so far are rolled back.
+//!
+//! In order to protect the repository from runaway publishers, the handler
+//! can enforce [`PublisherLimits`] on the number and size of the objects
+//! of a publisher as well as on how often it can send publish queries.
+//!
+//! A repository usually serves many publishers from the same store. This
+//! is what [`Repository`] is for. It manages publishers and their
+//! handlers and keeps track of changes for producing RRDP snapshots and
+//! deltas.
+
+use std::{error, fmt, io};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::time::Duration;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use uuid::Uuid;
+use crate::uri;
+use crate::clock::{Clock, SharedClock};
+use crate::crypto::Signer;
+use crate::rrdp::{
+    Delta, DeltaElement, DeltaInfo, Hash, NotificationFile, PublishElement,
+    Snapshot, UpdateElement, UriAndHash, WithdrawElement,
+};
+use crate::store::{MemoryStore, ObjectStore, StoreError};
+use super::receipt::{ReceiptError, StateReceipt};
+use super::publication::{
+    ErrorReply, ListElement, ListReply, PublishDelta, PublishDeltaElement,
+    Query, Reply, ReportError, ReportErrorCode, Withdraw,
+};
+
+
+//------------ PublicationHandler --------------------------------------------
+
+/// Processes publication queries for a single publisher.
+///
+/// The handler is created with the rsync URI of the directory the
+/// publisher is allowed to publish in, its _base URI,_ and the object
+/// store to keep the published objects in. All objects below the base
+/// URI in the store are considered to have been published by the
+/// publisher.
+///
+/// By default, there are no limits for the publisher. They can be set via
+/// [`set_limits`][Self::set_limits].
+///
+/// If a store is to be shared by multiple publishers, use [`Repository`]
+/// instead.
+#[derive(Clone, Debug)]
+pub struct PublicationHandler<S> {
+    /// The publisher.
+    publisher: Publisher,
+
+    /// The store for the published objects.
+    store: S,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+}
+
+impl<S> PublicationHandler<S> {
+    /// Creates a new handler for the given base URI and store.
+    pub fn new(base_uri: uri::Rsync, store: S) -> Self {
+        PublicationHandler {
+            publisher: Publisher::new(base_uri),
+            store,
+            clock: Default::default(),
+        }
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock is used by the methods without an explicit time. By
+    /// default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Returns the base URI of the publisher.
+    pub fn base_uri(&self) -> &uri::Rsync {
+        &self.publisher.base_uri
+    }
+
+    /// Returns the limits for the publisher.
+    pub fn limits(&self) -> &PublisherLimits {
+        &self.publisher.limits
+    }
+
+    /// Sets the limits for the publisher.
+    pub fn set_limits(&mut self, limits: PublisherLimits) {
+        self.publisher.limits = limits
+    }
+
+    /// Returns a reference to the object store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Converts the handler into the object store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl<S: ObjectStore> PublicationHandler<S> {
+    /// Processes a query and returns the reply.
+    ///
+    /// A list query results in a list reply while a publish query results
+    /// in either a success reply or an error reply with all the elements
+    /// of the delta that couldn’t be applied.
+    ///
+    /// Returns an error if accessing the store fails. In this case, the
+    /// content of the store is the same as it was before the query,
+    /// unless rolling back the changes failed, too.
+    pub fn handle(&mut self, query: Query) -> Result<Reply, StoreError> {
+        self.handle_at(query, self.clock.now())
+    }
+
+    /// Processes a query received at the given time.
+    ///
+    /// This is the same as [`handle`][Self::handle] but uses `now` as the
+    /// current time for enforcing the rate limit.
+    pub fn handle_at(
+        &mut self, query: Query, now: DateTime<Utc>
+    ) -> Result<Reply, StoreError> {
+        match query {
+            Query::List => self.list().map(Reply::List),
+            Query::Delta(delta) => {
+                ApplyError::into_reply(self.apply_at(delta, now))
+            }
+        }
+    }
+
+    /// Returns the list of objects currently published.
+    pub fn list(&self) -> Result<ListReply, StoreError> {
+        self.publisher.list(&self.store)
+    }
+
+    /// Returns an iterator over the objects currently published.
+    ///
+    /// Unlike [`list`][Self::list], this doesn’t collect all elements
+    /// into a list reply first. The content of each object is only loaded
+    /// from the store when the iterator gets to it.
+    pub fn list_elements(
+        &self
+    ) -> Result<
+        impl Iterator<Item = Result<ListElement, StoreError>> + '_,
+        StoreError
+    > {
+        self.publisher.list_elements(&self.store)
+    }
+
+    /// Returns a delta withdrawing all objects currently published.
+    ///
+    /// Applying the delta resets the publisher to having no objects.
+    pub fn withdraw_all_delta(&self) -> Result<PublishDelta, StoreError> {
+        self.publisher.withdraw_all_delta(&self.store)
+    }
+
+    /// Writes the XML of the list reply message to a target.
+    ///
+    /// The elements are produced from the store one by one while writing
+    /// so that large replies don’t have to be kept in memory twice. Store
+    /// errors are returned as I/O errors.
+    pub fn write_list_reply(
+        &self, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        self.publisher.write_list_reply(&self.store, target)
+    }
+
+    /// Creates a signed receipt for the objects currently published.
+    ///
+    /// This is typically done after applying a delta and the receipt
+    /// given to the publisher. See the [receipt][super::receipt] module
+    /// for details.
+    pub fn receipt<K: Signer>(
+        &self, key: &K::KeyId, signer: &K
+    ) -> Result<StateReceipt, ReceiptError<K::Error>> {
+        StateReceipt::create(self.list()?, key, signer).map_err(Into::into)
+    }
+
+    /// Applies a publish delta as a unit.
+    ///
+    /// All elements are checked first. If any of them can’t be applied,
+    /// the store remains unchanged and an error reply listing the failed
+    /// elements is returned. Only if all elements are fine, the changes are
+    /// made to the store.
+    ///
+    /// If applying the delta would exceed the publisher’s limits, the
+    /// store remains unchanged, too, and the exceeded limit is returned.
+    pub fn apply(&mut self, delta: PublishDelta) -> Result<(), ApplyError> {
+        self.apply_at(delta, self.clock.now())
+    }
+
+    /// Applies a publish delta received at the given time.
+    ///
+    /// This is the same as [`apply`][Self::apply] but uses `now` as the
+    /// current time for enforcing the rate limit.
+    pub fn apply_at(
+        &mut self, delta: PublishDelta, now: DateTime<Utc>
+    ) -> Result<(), ApplyError> {
+        self.publisher.apply_at(&mut self.store, delta, now).map(|_| ())
+    }
+}
+
+
+//------------ Repository ----------------------------------------------------
+
+/// The content of a repository shared by many publishers.
+///
+/// A repository keeps the objects of all its publishers in a single object
+/// store. Each publisher is identified by a handle and is confined to its
+/// own base URI. The base URIs of different publishers must not overlap.
+/// Publishers can be added via [`add_publisher`][Self::add_publisher] and
+/// removed via [`remove_publisher`][Self::remove_publisher] which also
+/// withdraws all their objects.
+///
+/// In addition, the repository keeps track of all changes to the published
+/// objects for generating RRDP files. Changes are collected until
+/// [`take_delta`][Self::take_delta] is called. It moves the repository to
+/// the next RRDP serial number and returns all changes as an RRDP delta.
+/// The RRDP snapshot of the current content is available via
+/// [`snapshot`][Self::snapshot].
+///
+/// The repository remembers the size of the deltas it produced since the
+/// last compaction, the _delta chain._ Once the deltas become too large
+/// compared to the snapshot according to the repository’s
+/// [`CompactionPolicy`], it is cheaper for relying parties to fetch the
+/// snapshot. The host application should therefore check
+/// [`needs_compaction`][Self::needs_compaction] whenever convenient,
+/// typically after taking a delta, and then call
+/// [`compact`][Self::compact] which starts a new delta chain. By leaving
+/// the timing to the application, it can for instance choose to compact
+/// during quiet times only.
+#[derive(Clone, Debug)]
+pub struct Repository<S> {
+    /// The store for the published objects.
+    store: S,
+
+    /// The publishers by their handle.
+    publishers: BTreeMap<String, Publisher>,
+
+    /// The RRDP session ID.
+    session_id: Uuid,
+
+    /// The current RRDP serial number.
+    serial: u64,
+
+    /// The changes since the last RRDP delta.
+    pending: Changes,
+
+    /// The serial numbers and sizes of the deltas since the last compaction.
+    delta_chain: Vec<(u64, u64)>,
+
+    /// The policy for when to compact the delta chain.
+    compaction: CompactionPolicy,
+
+    /// The most recent notification file.
+    notification: Option<PublishedNotification>,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+}
+
+impl<S> Repository<S> {
+    /// Creates a new repository.
+    ///
+    /// The store should not contain any objects outside of the base URIs
+    /// of the publishers that will be added to the repository. The new
+    /// repository will start an RRDP session with the given session ID at
+    /// the given serial number.
+    pub fn new(store: S, session_id: Uuid, serial: u64) -> Self {
+        Repository {
+            store,
+            publishers: BTreeMap::new(),
+            session_id,
+            serial,
+            pending: Changes::default(),
+            delta_chain: Vec::new(),
+            compaction: CompactionPolicy::default(),
+            notification: None,
+            clock: Default::default(),
+        }
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock is used by the methods without an explicit time. By
+    /// default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Returns a reference to the object store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the RRDP session ID.
+    pub fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    /// Returns the current RRDP serial number.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// Returns whether there are changes not yet included in a delta.
+    pub fn has_pending_changes(&self) -> bool {
+        self.pending.objects.values().any(Change::is_change)
+    }
+
+    /// Returns the compaction policy of the repository.
+    pub fn compaction_policy(&self) -> CompactionPolicy {
+        self.compaction
+    }
+
+    /// Sets the compaction policy of the repository.
+    pub fn set_compaction_policy(&mut self, policy: CompactionPolicy) {
+        self.compaction = policy
+    }
+
+    /// Returns an iterator over the deltas since the last compaction.
+    ///
+    /// The iterator returns the serial number and the size of each delta
+    /// in the order the deltas were taken. These are the deltas that
+    /// should be listed in the RRDP notification file.
+    pub fn delta_chain(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.delta_chain.iter().copied()
+    }
+
+    /// Returns the most recent notification file.
+    ///
+    /// Returns `None` if [`update_notification`][Self::update_notification]
+    /// hasn’t been called yet.
+    pub fn notification(&self) -> Option<&PublishedNotification> {
+        self.notification.as_ref()
+    }
+
+    /// Updates the notification file.
+    ///
+    /// Creates a notification file for the current session and serial
+    /// number referencing the given snapshot and deltas. If the file is
+    /// identical to the current one, it is kept as is, including its
+    /// modification time, and `false` is returned. Otherwise, the new file
+    /// becomes the current notification file and `true` is returned.
+    pub fn update_notification(
+        &mut self, snapshot: UriAndHash, deltas: Vec<DeltaInfo>
+    ) -> bool {
+        self.update_notification_at(snapshot, deltas, self.clock.now())
+    }
+
+    /// Updates the notification file at the given time.
+    pub fn update_notification_at(
+        &mut self,
+        snapshot: UriAndHash,
+        deltas: Vec<DeltaInfo>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let file = NotificationFile::new(
+            self.session_id, self.serial, snapshot, deltas
+        );
+        if let Some(ref current) = self.notification {
+            if current.file == file {
+                return false
+            }
+        }
+        self.notification = Some(PublishedNotification::new(file, now));
+        true
+    }
+
+    /// Returns the total size of the deltas since the last compaction.
+    ///
+    /// The size of a delta is the total size of the objects it publishes
+    /// or updates.
+    pub fn delta_chain_size(&self) -> u64 {
+        self.delta_chain.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Returns an iterator over the handles and base URIs of publishers.
+    pub fn publishers(
+        &self
+    ) -> impl Iterator<Item = (&str, &uri::Rsync)> + '_ {
+        self.publishers.iter().map(|(handle, publisher)| {
+            (handle.as_str(), &publisher.base_uri)
+        })
+    }
+
+    /// Returns the base URI of a publisher.
+    pub fn base_uri(&self, publisher: &str) -> Option<&uri::Rsync> {
+        self.publishers.get(publisher).map(|p| &p.base_uri)
+    }
+
+    /// Sets the limits for a publisher.
+    pub fn set_limits(
+        &mut self, publisher: &str, limits: PublisherLimits
+    ) -> Result<(), RepositoryError> {
+        self.publisher_mut(publisher)?.limits = limits;
+        Ok(())
+    }
+
+    /// Returns the publisher with the given handle.
+    fn publisher(
+        &self, publisher: &str
+    ) -> Result<&Publisher, RepositoryError> {
+        self.publishers.get(publisher).ok_or_else(|| {
+            RepositoryError::UnknownPublisher(publisher.into())
+        })
+    }
+
+    /// Returns the publisher with the given handle for modification.
+    fn publisher_mut(
+        &mut self, publisher: &str
+    ) -> Result<&mut Publisher, RepositoryError> {
+        self.publishers.get_mut(publisher).ok_or_else(|| {
+            RepositoryError::UnknownPublisher(publisher.into())
+        })
+    }
+}
+
+impl<S: ObjectStore> Repository<S> {
+    /// Adds a new publisher.
+    ///
+    /// Fails if there already is a publisher with this handle or if the
+    /// base URI overlaps with that of another publisher.
+    pub fn add_publisher(
+        &mut self, publisher: String, base_uri: uri::Rsync
+    ) -> Result<(), RepositoryError> {
+        if self.publishers.contains_key(&publisher) {
+            return Err(RepositoryError::DuplicatePublisher(publisher))
+        }
+        for other in self.publishers.values() {
+            if other.base_uri == base_uri
+                || other.base_uri.is_parent_of(&base_uri)
+                || base_uri.is_parent_of(&other.base_uri)
+            {
+                return Err(RepositoryError::OverlappingBaseUri(base_uri))
+            }
+        }
+        debug!("publication: adding publisher {} at {}", publisher, base_uri);
+        self.publishers.insert(publisher, Publisher::new(base_uri));
+        Ok(())
+    }
+
+    /// Removes a publisher and withdraws all its objects.
+    ///
+    /// The withdrawals are recorded for the next RRDP delta.
+    pub fn remove_publisher(
+        &mut self, publisher: &str
+    ) -> Result<(), RepositoryError> {
+        let base_uri = match self.publishers.get(publisher) {
+            Some(publisher) => publisher.base_uri.clone(),
+            None => {
+                return Err(
+                    RepositoryError::UnknownPublisher(publisher.into())
+                )
+            }
+        };
+        let mut changes = Changes::default();
+        for uri in self.store.list(&base_uri).map_err(StoreError::new)? {
+            if changes.current(&uri, &self.store)?.is_some() {
+                changes.set(&uri, None);
+            }
+        }
+        let changes = commit(&mut self.store, &base_uri, changes)?;
+        self.pending.merge(changes);
+        self.publishers.remove(publisher);
+        debug!("publication: removed publisher {}", publisher);
+        Ok(())
+    }
+
+    /// Processes a query of a publisher and returns the reply.
+    ///
+    /// This is the same as [`PublicationHandler::handle`] for the given
+    /// publisher.
+    pub fn handle(
+        &mut self, publisher: &str, query: Query
+    ) -> Result<Reply, RepositoryError> {
+        self.handle_at(publisher, query, self.clock.now())
+    }
+
+    /// Processes a query of a publisher received at the given time.
+    pub fn handle_at(
+        &mut self, publisher: &str, query: Query, now: DateTime<Utc>
+    ) -> Result<Reply, RepositoryError> {
+        match query {
+            Query::List => self.list(publisher).map(Reply::List),
+            Query::Delta(delta) => {
+                match self.apply_at(publisher, delta, now) {
+                    Err(RepositoryError::Apply(err)) => {
+                        ApplyError::into_reply(Err(err)).map_err(Into::into)
+                    }
+                    res => res.map(|_| Reply::Success)
+                }
+            }
+        }
+    }
+
+    /// Returns the list of objects currently published by a publisher.
+    pub fn list(&self, publisher: &str) -> Result<ListReply, RepositoryError> {
+        self.publisher(publisher)?.list(&self.store).map_err(Into::into)
+    }
+
+    /// Returns an iterator over the objects published by a publisher.
+    ///
+    /// This is the same as [`PublicationHandler::list_elements`] for the
+    /// given publisher.
+    pub fn list_elements(
+        &self, publisher: &str
+    ) -> Result<
+        impl Iterator<Item = Result<ListElement, StoreError>> + '_,
+        RepositoryError
+    > {
+        self.publisher(publisher)?.list_elements(
+            &self.store
+        ).map_err(Into::into)
+    }
+
+    /// Returns a delta withdrawing all objects of a publisher.
+    ///
+    /// This is the same as [`PublicationHandler::withdraw_all_delta`] for
+    /// the given publisher.
+    pub fn withdraw_all_delta(
+        &self, publisher: &str
+    ) -> Result<PublishDelta, RepositoryError> {
+        self.publisher(publisher)?.withdraw_all_delta(
+            &self.store
+        ).map_err(Into::into)
+    }
+
+    /// Writes the XML of the list reply message for a publisher.
+    ///
+    /// This is the same as [`PublicationHandler::write_list_reply`] for
+    /// the given publisher.
+    pub fn write_list_reply(
+        &self, publisher: &str, target: &mut impl io::Write
+    ) -> Result<(), RepositoryError> {
+        self.publisher(publisher)?.write_list_reply(
+            &self.store, target
+        ).map_err(RepositoryError::Io)
+    }
+
+    /// Applies a publish delta of a publisher as a unit.
+    ///
+    /// This is the same as [`PublicationHandler::apply`] for the given
+    /// publisher. The changes are recorded for the next RRDP delta.
+    pub fn apply(
+        &mut self, publisher: &str, delta: PublishDelta
+    ) -> Result<(), RepositoryError> {
+        self.apply_at(publisher, delta, self.clock.now())
+    }
+
+    /// Applies a publish delta of a publisher received at the given time.
+    pub fn apply_at(
+        &mut self, publisher: &str, delta: PublishDelta, now: DateTime<Utc>
+    ) -> Result<(), RepositoryError> {
+        let item = match self.publishers.get_mut(publisher) {
+            Some(item) => item,
+            None => {
+                return Err(
+                    RepositoryError::UnknownPublisher(publisher.into())
+                )
+            }
+        };
+        let changes = item.apply_at(&mut self.store, delta, now)?;
+        self.pending.merge(changes);
+        Ok(())
+    }
+
+    /// Returns the RRDP snapshot for the current content.
+    ///
+    /// The snapshot contains the objects of all publishers and uses the
+    /// current session ID and serial number. If there are pending changes,
+    /// it already includes them. In this case,
+    /// [`take_delta`][Self::take_delta] should be called first in order to
+    /// get a consistent serial number.
+    pub fn snapshot(&self) -> Result<Snapshot, StoreError> {
+        let mut elements = Vec::new();
+        for publisher in self.publishers.values() {
+            let uris = self.store.list(&publisher.base_uri);
+            for uri in uris.map_err(StoreError::new)? {
+                let data = self.store.get(&uri).map_err(StoreError::new)?;
+                if let Some(data) = data {
+                    elements.push(PublishElement::new(uri, data));
+                }
+            }
+        }
+        Ok(Snapshot::new(self.session_id, self.serial, elements))
+    }
+
+    /// Returns the RRDP delta for the pending changes.
+    ///
+    /// If there are any pending changes, increases the serial number and
+    /// returns a delta for the new serial number containing all the
+    /// changes. Otherwise, returns `None`.
+    pub fn take_delta(&mut self) -> Option<Delta> {
+        let pending = std::mem::take(&mut self.pending);
+        let elements: Vec<_> = pending.into_delta_elements().collect();
+        if elements.is_empty() {
+            return None
+        }
+        self.serial += 1;
+        let size = elements.iter().map(|element| {
+            match *element {
+                DeltaElement::Publish(ref publish) => {
+                    publish.data().len() as u64
+                }
+                DeltaElement::Update(ref update) => {
+                    update.data().len() as u64
+                }
+                DeltaElement::Withdraw(_) => 0,
+            }
+        }).sum();
+        self.delta_chain.push((self.serial, size));
+        Some(Delta::new(self.session_id, self.serial, elements))
+    }
+
+    /// Returns the total size of all current objects.
+    ///
+    /// This is the size of a snapshot as used by the compaction policy.
+    pub fn snapshot_size(&self) -> Result<u64, StoreError> {
+        let mut res = 0;
+        for publisher in self.publishers.values() {
+            let uris = self.store.list(&publisher.base_uri);
+            for uri in uris.map_err(StoreError::new)? {
+                let data = self.store.get(&uri).map_err(StoreError::new)?;
+                if let Some(data) = data {
+                    res += data.len() as u64;
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns whether the delta chain should be compacted.
+    ///
+    /// This applies the repository’s compaction policy to the current
+    /// delta chain.
+    pub fn needs_compaction(&self) -> Result<bool, StoreError> {
+        if let Some(max) = self.compaction.max_deltas {
+            if self.delta_chain.len() > max {
+                return Ok(true)
+            }
+        }
+        if let Some(percent) = self.compaction.max_delta_percent {
+            if self.delta_chain.is_empty() {
+                return Ok(false)
+            }
+            let limit = self.snapshot_size()?.saturating_mul(
+                u64::from(percent)
+            ) / 100;
+            if self.delta_chain_size() > limit {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    /// Compacts the delta chain.
+    ///
+    /// If there are pending changes, they are first turned into a final
+    /// delta of the current chain, increasing the serial number. The
+    /// method then starts a new, empty delta chain and returns the final
+    /// delta, if any, and the snapshot for the current serial number.
+    ///
+    /// The notification file published after the compaction should only
+    /// reference the new snapshot and none of the earlier deltas.
+    pub fn compact(&mut self) -> Result<Compaction, StoreError> {
+        let delta = self.take_delta();
+        let snapshot = self.snapshot()?;
+        debug!(
+            "publication: compacted {} deltas at serial {}",
+            self.delta_chain.len(), self.serial
+        );
+        self.delta_chain.clear();
+        Ok(Compaction { delta, snapshot })
+    }
+
+    /// Resets the RRDP session.
+    ///
+    /// Starts a new session with the given session ID at serial number 1
+    /// and returns the snapshot for it containing the current content.
+    /// Pending changes are included in the snapshot and no delta is
+    /// produced for them. The delta chain is cleared.
+    ///
+    /// This should be used if the RRDP state was lost or may be
+    /// inconsistent, e.g., after data corruption or a migration. Relying
+    /// parties will notice the new session ID in the notification file and
+    /// will fetch the new snapshot as required by RFC 8182.
+    pub fn reset_session(
+        &mut self, session_id: Uuid
+    ) -> Result<Snapshot, StoreError> {
+        debug!(
+            "publication: resetting RRDP session {} at serial {} to {}",
+            self.session_id, self.serial, session_id
+        );
+        self.session_id = session_id;
+        self.serial = 1;
+        self.pending = Changes::default();
+        self.delta_chain.clear();
+        self.snapshot()
+    }
+}
+
+
+//------------ PublishedNotification -----------------------------------------
+
+/// A notification file ready to be served.
+///
+/// In addition to the file itself, this type keeps its XML encoding, the
+/// SHA-256 hash over it, and the time it was last changed. This allows
+/// serving the file with the correct HTTP caching headers: the hash can
+/// be used as the entity tag and the time as the last modified date.
+#[derive(Clone, Debug)]
+pub struct PublishedNotification {
+    /// The notification file.
+    file: NotificationFile,
+
+    /// The XML encoding of the file.
+    xml: Bytes,
+
+    /// The SHA-256 hash over the XML encoding.
+    hash: Hash,
+
+    /// The time the file was last changed.
+    last_modified: DateTime<Utc>,
+}
+
+impl PublishedNotification {
+    /// Creates a new value for a file changed at the given time.
+    fn new(file: NotificationFile, last_modified: DateTime<Utc>) -> Self {
+        let mut xml = Vec::new();
+        file.write_xml(&mut xml).expect("writing to vec failed");
+        let hash = Hash::from_data(&xml);
+        PublishedNotification {
+            file,
+            xml: xml.into(),
+            hash,
+            last_modified,
+        }
+    }
+
+    /// Returns the notification file.
+    pub fn file(&self) -> &NotificationFile {
+        &self.file
+    }
+
+    /// Returns the XML encoding of the notification file.
+    pub fn xml(&self) -> &Bytes {
+        &self.xml
+    }
+
+    /// Returns the SHA-256 hash over the XML encoding.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Returns the entity tag for the notification file.
+    ///
+    /// This is the hex-encoded hash in double quotes and can be used as
+    /// the value of the HTTP `ETag` header.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.hash)
+    }
+
+    /// Returns the time the notification file was last changed.
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.last_modified
+    }
+}
+
+
+//------------ Compaction ----------------------------------------------------
+
+/// The result of compacting the delta chain of a repository.
+#[derive(Clone, Debug)]
+pub struct Compaction {
+    /// The delta with the changes pending before compaction, if any.
+    pub delta: Option<Delta>,
+
+    /// The snapshot for the current serial number.
+    pub snapshot: Snapshot,
+}
+
+
+//------------ CompactionPolicy ----------------------------------------------
+
+/// The policy for when to compact the delta chain of a repository.
+///
+/// Both criteria are optional. The chain should be compacted if any of
+/// them is met. By default, the chain is compacted when the deltas are
+/// larger than the snapshot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompactionPolicy {
+    /// The maximum size of the delta chain in percent of the snapshot.
+    pub max_delta_percent: Option<u32>,
+
+    /// The maximum number of deltas in the chain.
+    pub max_deltas: Option<usize>,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy {
+            max_delta_percent: Some(100),
+            max_deltas: None,
+        }
+    }
+}
+
+
+//------------ Publisher -----------------------------------------------------
+
+/// The state of a single publisher.
+#[derive(Clone, Debug)]
+struct Publisher {
+    /// The base URI of the publisher.
+    base_uri: uri::Rsync,
+
+    /// The limits for the publisher.
+    limits: PublisherLimits,
+
+    /// The times of recent publish queries.
+    ///
+    /// This is only kept if there is a limit for the rate of queries and
+    /// only contains the queries within the limit’s time window.
+    recent: VecDeque<DateTime<Utc>>,
+
+    /// The number and total size of the published objects.
+    ///
+    /// This is only determined from the store when a size limit is first
+    /// checked and then kept up to date with every applied delta.
+    usage: Option<Usage>,
+}
+
+impl Publisher {
+    /// Creates a new publisher without limits.
+    fn new(base_uri: uri::Rsync) -> Self {
+        Publisher {
+            base_uri,
+            limits: Default::default(),
+            recent: VecDeque::new(),
+            usage: None,
+        }
+    }
+
+    /// Returns the list of objects currently published.
+    fn list<S: ObjectStore>(
+        &self, store: &S
+    ) -> Result<ListReply, StoreError> {
+        self.list_elements(store)?.collect::<Result<_, _>>().map(
+            ListReply::new
+        )
+    }
+
+    /// Returns an iterator over the objects currently published.
+    ///
+    /// The content of the objects is only loaded from the store when the
+    /// iterator gets to them.
+    fn list_elements<'a, S: ObjectStore>(
+        &self, store: &'a S
+    ) -> Result<
+        impl Iterator<Item = Result<ListElement, StoreError>> + 'a,
+        StoreError
+    > {
+        let uris = store.list(&self.base_uri).map_err(StoreError::new)?;
+        Ok(uris.into_iter().filter_map(move |uri| {
+            match store.get(&uri) {
+                Ok(Some(data)) => {
+                    let hash = Hash::from_data(data.as_ref());
+                    Some(Ok(ListElement::new(uri, hash)))
+                }
+                Ok(None) => None,
+                Err(err) => Some(Err(StoreError::new(err)))
+            }
+        }))
+    }
+
+    /// Returns a delta withdrawing all objects of the publisher.
+    fn withdraw_all_delta<S: ObjectStore>(
+        &self, store: &S
+    ) -> Result<PublishDelta, StoreError> {
+        let mut res = PublishDelta::empty();
+        for item in self.list_elements(store)? {
+            let (uri, hash) = item?.unpack();
+            res.add_withdraw(Withdraw::with_hash_tag(uri, hash));
+        }
+        Ok(res)
+    }
+
+    /// Writes the list reply message for the publisher.
+    fn write_list_reply<S: ObjectStore>(
+        &self, store: &S, target: &mut impl io::Write
+    ) -> Result<(), io::Error> {
+        ListReply::write_xml_streaming(
+            self.list_elements(store).map_err(store_to_io)?.map(|item| {
+                item.map_err(store_to_io)
+            }),
+            target
+        )
+    }
+
+    /// Applies a publish delta received at the given time.
+    ///
+    /// Returns the changes made to the store.
+    fn apply_at<S: ObjectStore>(
+        &mut self, store: &mut S, delta: PublishDelta, now: DateTime<Utc>
+    ) -> Result<Changes, ApplyError> {
+        self.check_rate(now)?;
+        if let Some(max) = self.limits.max_delta_elements {
+            if delta.len() > max {
+                return Err(LimitExceeded::DeltaElements(max).into())
+            }
+        }
+        let changes = self.stage(store, delta)?;
+        let usage = self.check_size(store, &changes)?;
+        let changes = commit(
+            store, &self.base_uri, changes
+        ).map_err(ApplyError::Store)?;
+        if usage.is_some() {
+            self.usage = usage;
+        }
+        Ok(changes)
+    }
+
+    /// Checks and records the rate of publish queries.
+    fn check_rate(&mut self, now: DateTime<Utc>) -> Result<(), ApplyError> {
+        let (max, window) = match self.limits.max_deltas {
+            Some(limit) => limit,
+            None => return Ok(())
+        };
+        while let Some(first) = self.recent.front() {
+            let elapsed = now.signed_duration_since(
+                *first
+            ).to_std().unwrap_or_default();
+            if elapsed < window {
+                break
+            }
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= max {
+            return Err(LimitExceeded::Rate(max, window).into())
+        }
+        self.recent.push_back(now);
+        Ok(())
+    }
+
+    /// Checks that the changes keep the publisher within its size limits.
+    ///
+    /// Returns the usage after the changes have been applied if it is
+    /// being tracked.
+    fn check_size<S: ObjectStore>(
+        &mut self, store: &S, changes: &Changes
+    ) -> Result<Option<Usage>, ApplyError> {
+        let limits = self.limits;
+        let mut usage = match self.usage {
+            Some(usage) => usage,
+            None => {
+                if limits.max_objects.is_none() && limits.max_size.is_none()
+                {
+                    return Ok(None)
+                }
+                let usage = Usage::from_store(store, &self.base_uri)?;
+                self.usage = Some(usage);
+                usage
+            }
+        };
+        for change in changes.objects.values() {
+            usage.apply(change);
+        }
+        if let Some(max) = limits.max_objects {
+            if usage.objects > max {
+                return Err(LimitExceeded::Objects(max).into())
+            }
+        }
+        if let Some(max) = limits.max_size {
+            if usage.size > max {
+                return Err(LimitExceeded::Size(max).into())
+            }
+        }
+        Ok(Some(usage))
+    }
+
+    /// Checks all elements of a delta and collects the resulting changes.
+    ///
+    /// Elements are checked in order against the state of the store with
+    /// the changes of the preceding elements applied.
+    fn stage<S: ObjectStore>(
+        &self, store: &S, delta: PublishDelta
+    ) -> Result<Changes, ApplyError> {
+        let mut changes = Changes::default();
+        let mut errors = ErrorReply::empty();
+        for element in delta.into_elements() {
+            match self.check(store, &element, &mut changes) {
+                Ok(Some(code)) => {
+                    debug!(
+                        "publication: rejecting element for {}: {}",
+                        element.uri(), code
+                    );
+                    errors.add_error(
+                        ReportError::for_element(code, element)
+                    );
+                }
+                Ok(None) => changes.add(element),
+                Err(err) => return Err(ApplyError::Store(err)),
+            }
+        }
+        if errors.errors().is_empty() {
+            Ok(changes)
+        }
+        else {
+            Err(ApplyError::Rejected(errors))
+        }
+    }
+
+    /// Checks a single delta element.
+    ///
+    /// Returns the error code if the element can’t be applied. Makes sure
+    /// the current content of the element’s URI is part of `changes` so
+    /// the changes can be rolled back later.
+    fn check<S: ObjectStore>(
+        &self,
+        store: &S,
+        element: &PublishDeltaElement,
+        changes: &mut Changes
+    ) -> Result<Option<ReportErrorCode>, StoreError> {
+        let uri = element.uri();
+        if !self.base_uri.is_parent_of(uri) {
+            return Ok(Some(ReportErrorCode::PermissionFailure))
+        }
+        let current = changes.current(uri, store)?;
+        Ok(match (element, current) {
+            (PublishDeltaElement::Publish(_), None) => None,
+            (PublishDeltaElement::Publish(_), Some(_)) => {
+                Some(ReportErrorCode::ObjectAlreadyPresent)
+            }
+            (PublishDeltaElement::Update(_), None)
+            | (PublishDeltaElement::Withdraw(_), None) => {
+                Some(ReportErrorCode::NoObjectPresent)
+            }
+            (PublishDeltaElement::Update(update), Some(current)) => {
+                if update.hash().matches(current.as_ref()) {
+                    None
+                }
+                else {
+                    Some(ReportErrorCode::NoObjectMatchingHash)
+                }
+            }
+            (PublishDeltaElement::Withdraw(withdraw), Some(current)) => {
+                if withdraw.hash().matches(current.as_ref()) {
+                    None
+                }
+                else {
+                    Some(ReportErrorCode::NoObjectMatchingHash)
+                }
+            }
+        })
+    }
+}
+
+
+//------------ Usage ---------------------------------------------------------
+
+/// The number and total size of the objects of a publisher.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Usage {
+    /// The number of objects.
+    objects: usize,
+
+    /// The total size of the objects in octets.
+    size: u64,
+}
+
+impl Usage {
+    /// Determines the usage of the objects below `base_uri` in a store.
+    fn from_store<S: ObjectStore>(
+        store: &S, base_uri: &uri::Rsync
+    ) -> Result<Self, StoreError> {
+        let mut res = Usage::default();
+        for uri in store.list(base_uri).map_err(StoreError::new)? {
+            if let Some(data) = store.get(&uri).map_err(StoreError::new)? {
+                res.objects += 1;
+                res.size += data.len() as u64;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Updates the usage for a change to an object.
+    fn apply(&mut self, change: &Change) {
+        if let Some(ref old) = change.old {
+            self.objects = self.objects.saturating_sub(1);
+            self.size = self.size.saturating_sub(old.len() as u64);
+        }
+        if let Some(ref new) = change.new {
+            self.objects += 1;
+            self.size += new.len() as u64;
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Applies the staged changes to the store.
+///
+/// If the store fails, rolls back all changes made so far. Otherwise
+/// returns the changes for further processing. The base URI is only used
+/// for logging.
+fn commit<S: ObjectStore>(
+    store: &mut S, base_uri: &uri::Rsync, changes: Changes
+) -> Result<Changes, StoreError> {
+    let order: Vec<_> = changes.order.iter().map(|key| {
+        &changes.objects[key]
+    }).filter(|change| change.is_change()).collect();
+    for (idx, change) in order.iter().enumerate() {
+        if let Err(err) = set(store, &change.uri, change.new.clone()) {
+            error!(
+                "publication: failed to apply changes for {}: {}. \
+                 Rolling back.",
+                base_uri, err
+            );
+            for change in order[..idx].iter().rev() {
+                let uri = &change.uri;
+                if let Err(err) = set(store, uri, change.old.clone()) {
+                    error!(
+                        "publication: failed to roll back {}: {}",
+                        uri, err
+                    );
+                }
+            }
+            return Err(err)
+        }
+    }
+    Ok(changes)
+}
+
+/// Converts a store error into an I/O error.
+fn store_to_io(err: StoreError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+/// Sets the content of an object in the store.
+fn set<S: ObjectStore>(
+    store: &mut S, uri: &uri::Rsync, data: Option<Bytes>
+) -> Result<(), StoreError> {
+    match data {
+        Some(data) => store.insert(uri.clone(), data),
+        None => store.remove(uri).map(|_| ()),
+    }.map_err(StoreError::new)
+}
+
+
+//------------ PublisherLimits -----------------------------------------------
+
+/// The limits for a publisher.
+///
+/// All limits are optional. The default value doesn’t impose any limits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PublisherLimits {
+    /// The maximum number of objects a publisher can have published.
+    pub max_objects: Option<usize>,
+
+    /// The maximum total size in octets of all published objects.
+    pub max_size: Option<u64>,
+
+    /// The maximum number of elements in a single publish query.
+    pub max_delta_elements: Option<usize>,
+
+    /// The maximum number of publish queries within a time window.
+    ///
+    /// All publish queries count towards this limit, whether they were
+    /// successful or not.
+    pub max_deltas: Option<(usize, Duration)>,
+}
+
+
+//------------ Changes -------------------------------------------------------
+
+/// The staged changes of a publish delta.
+///
+/// Changes are keyed by the same canonical form of the object URI that
+/// [`MemoryStore`] uses so that equivalent URIs refer to the same change.
+#[derive(Clone, Debug, Default)]
+struct Changes {
+    /// The changes by the key of the object URI.
+    objects: HashMap<String, Change>,
+
+    /// The keys of the changed objects in the order they were first seen.
+    order: Vec<String>,
+}
+
+/// The change to a single object.
+#[derive(Clone, Debug)]
+struct Change {
+    /// The URI of the object as it was first seen.
+    uri: uri::Rsync,
+
+    /// The content of the object before the delta.
+    old: Option<Bytes>,
+
+    /// The content of the object after the delta.
+    new: Option<Bytes>,
+}
+
+impl Changes {
+    /// Returns the current content of an object.
+    ///
+    /// If the object hasn’t been seen yet, it is loaded from the store.
+    fn current<S: ObjectStore>(
+        &mut self, uri: &uri::Rsync, store: &S
+    ) -> Result<Option<Bytes>, StoreError> {
+        match self.objects.entry(MemoryStore::key(uri)) {
+            Entry::Occupied(entry) => Ok(entry.get().new.clone()),
+            Entry::Vacant(entry) => {
+                let old = store.get(uri).map_err(StoreError::new)?;
+                self.order.push(entry.key().clone());
+                entry.insert(Change {
+                    uri: uri.clone(), old: old.clone(), new: old.clone()
+                });
+                Ok(old)
+            }
+        }
+    }
+
+    /// Adds a checked element.
+    ///
+    /// The element’s URI must have been seen via `current` before.
+    fn add(&mut self, element: PublishDeltaElement) {
+        let (uri, new) = match element {
+            PublishDeltaElement::Publish(publish) => {
+                let (_, uri, content) = publish.unpack();
+                (uri, Some(content.to_bytes()))
+            }
+            PublishDeltaElement::Update(update) => {
+                let (_, uri, content, _) = update.unpack();
+                (uri, Some(content.to_bytes()))
+            }
+            PublishDeltaElement::Withdraw(withdraw) => {
+                let (_, uri, _) = withdraw.unpack();
+                (uri, None)
+            }
+        };
+        self.set(&uri, new)
+    }
+
+    /// Sets the new content of an object.
+    ///
+    /// The object’s URI must have been seen via `current` before.
+    fn set(&mut self, uri: &uri::Rsync, new: Option<Bytes>) {
+        if let Some(change) = self.objects.get_mut(&MemoryStore::key(uri)) {
+            change.new = new;
+        }
+    }
+
+    /// Merges later changes into these changes.
+    fn merge(&mut self, other: Changes) {
+        let Changes { mut objects, order } = other;
+        for key in order {
+            let change = match objects.remove(&key) {
+                Some(change) => change,
+                None => continue
+            };
+            match self.objects.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().new = change.new;
+                }
+                Entry::Vacant(entry) => {
+                    self.order.push(entry.key().clone());
+                    entry.insert(change);
+                }
+            }
+        }
+    }
+
+    /// Converts the changes into RRDP delta elements.
+    fn into_delta_elements(self) -> impl Iterator<Item = DeltaElement> {
+        let Changes { mut objects, order } = self;
+        order.into_iter().filter_map(move |key| {
+            let Change { uri, old, new } = objects.remove(&key)?;
+            match (old, new) {
+                (None, Some(new)) => {
+                    Some(PublishElement::new(uri, new).into())
+                }
+                (Some(old), Some(new)) if old != new => {
+                    Some(UpdateElement::new(
+                        uri, Hash::from_data(old.as_ref()), new
+                    ).into())
+                }
+                (Some(old), None) => {
+                    Some(WithdrawElement::new(
+                        uri, Hash::from_data(old.as_ref())
+                    ).into())
+                }
+                _ => None
+            }
+        })
+    }
+}
+
+impl Change {
+    /// Returns whether the content of the object actually changed.
+    fn is_change(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ ApplyError ----------------------------------------------------
+
+/// Applying a publish delta failed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ApplyError {
+    /// Some elements of the delta could not be applied.
+    ///
+    /// The error reply contains a report for each failed element.
+    Rejected(ErrorReply),
+
+    /// Applying the delta would exceed one of the publisher’s limits.
+    LimitExceeded(LimitExceeded),
+
+    /// Accessing the object store failed.
+    Store(StoreError),
+}
+
+impl ApplyError {
+    /// Converts the result of applying a delta into a reply.
+    fn into_reply(res: Result<(), Self>) -> Result<Reply, StoreError> {
+        match res {
+            Ok(()) => Ok(Reply::Success),
+            Err(ApplyError::Rejected(reply)) => Ok(Reply::ErrorReply(reply)),
+            Err(ApplyError::LimitExceeded(err)) => {
+                let mut report = ReportError::with_code(
+                    ReportErrorCode::OtherError
+                );
+                report.set_error_text(Some(err.to_string()));
+                Ok(Reply::ErrorReply(ErrorReply::for_error(report)))
+            }
+            Err(ApplyError::Store(err)) => Err(err),
+        }
+    }
+}
+
+impl From<LimitExceeded> for ApplyError {
+    fn from(err: LimitExceeded) -> Self {
+        ApplyError::LimitExceeded(err)
+    }
+}
+
+impl From<StoreError> for ApplyError {
+    fn from(err: StoreError) -> Self {
+        ApplyError::Store(err)
+    }
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApplyError::Rejected(ref reply) => reply.fmt(f),
+            ApplyError::LimitExceeded(ref err) => err.fmt(f),
+            ApplyError::Store(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ApplyError::Rejected(_) => None,
+            ApplyError::LimitExceeded(ref err) => Some(err),
+            ApplyError::Store(ref err) => Some(err),
+        }
+    }
+}
+
+
+//------------ RepositoryError -----------------------------------------------
+
+/// An error happened while processing a request for a repository.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RepositoryError {
+    /// There is no publisher with the given handle.
+    UnknownPublisher(String),
+
+    /// There already is a publisher with the given handle.
+    DuplicatePublisher(String),
+
+    /// The base URI overlaps with that of another publisher.
+    OverlappingBaseUri(uri::Rsync),
+
+    /// Applying a delta failed.
+    Apply(ApplyError),
+
+    /// Writing a reply failed.
+    Io(io::Error),
+}
+
+impl From<ApplyError> for RepositoryError {
+    fn from(err: ApplyError) -> Self {
+        RepositoryError::Apply(err)
+    }
+}
+
+impl From<StoreError> for RepositoryError {
+    fn from(err: StoreError) -> Self {
+        RepositoryError::Apply(ApplyError::Store(err))
+    }
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RepositoryError::UnknownPublisher(ref handle) => {
+                write!(f, "unknown publisher '{}'", handle)
+            }
+            RepositoryError::DuplicatePublisher(ref handle) => {
+                write!(f, "duplicate publisher '{}'", handle)
+            }
+            RepositoryError::OverlappingBaseUri(ref uri) => {
+                write!(f, "base URI {} overlaps with other publisher", uri)
+            }
+            RepositoryError::Apply(ref err) => err.fmt(f),
+            RepositoryError::Io(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RepositoryError::Apply(ref err) => Some(err),
+            RepositoryError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+
+//------------ LimitExceeded -------------------------------------------------
+
+/// A publisher has exceeded one of its limits.
+///
+/// The variants contain the value of the exceeded limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LimitExceeded {
+    /// The publisher would have too many objects.
+    Objects(usize),
+
+    /// The published objects would be too large.
+    Size(u64),
+
+    /// The publish query contained too many elements.
+    DeltaElements(usize),
+
+    /// The publisher sent too many publish queries.
+    Rate(usize, Duration),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitExceeded::Objects(max) => {
+                write!(f, "publisher exceeds limit of {} objects", max)
+            }
+            LimitExceeded::Size(max) => {
+                write!(f, "publisher exceeds size limit of {} octets", max)
+            }
+            LimitExceeded::DeltaElements(max) => {
+                write!(
+                    f, "publish query exceeds limit of {} elements", max
+                )
+            }
+            LimitExceeded::Rate(max, window) => {
+                write!(
+                    f, "publisher exceeds limit of {} queries in {}s",
+                    max, window.as_secs()
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for LimitExceeded { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use crate::store::MemoryStore;
+    use super::*;
+    use super::super::publication::{Base64, Message, Publish, Update};
+
+    /// A store that fails one specific change.
+    #[derive(Debug)]
+    struct FailingStore {
+        store: MemoryStore,
+        fail_at: usize,
+        changes: usize,
+    }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("test error")
+        }
+    }
+
+    impl error::Error for TestError { }
+
+    impl FailingStore {
+        fn consume(&mut self) -> Result<(), TestError> {
+            self.changes += 1;
+            if self.changes == self.fail_at {
+                Err(TestError)
+            }
+            else {
+                Ok(())
+            }
+        }
+    }
+
+    impl ObjectStore for FailingStore {
+        type Error = TestError;
+
+        fn get(
+            &self, uri: &uri::Rsync
+        ) -> Result<Option<Bytes>, Self::Error> {
+            Ok(self.store.get(uri).unwrap())
+        }
+
+        fn insert(
+            &mut self, uri: uri::Rsync, data: Bytes
+        ) -> Result<(), Self::Error> {
+            self.consume()?;
+            self.store.insert(uri, data).unwrap();
+            Ok(())
+        }
+
+        fn remove(
+            &mut self, uri: &uri::Rsync
+        ) -> Result<bool, Self::Error> {
+            self.consume()?;
+            Ok(self.store.remove(uri).unwrap())
+        }
+
+        fn list(
+            &self, base: &uri::Rsync
+        ) -> Result<Vec<uri::Rsync>, Self::Error> {
+            Ok(self.store.list(base).unwrap())
+        }
+    }
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn handler() -> PublicationHandler<MemoryStore> {
+        let mut store = MemoryStore::new();
+        store.insert(
+            uri("rsync://example.com/repo/alice/a.cer"), Bytes::from("a")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/repo/alice/b.cer"), Bytes::from("b")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/repo/bob/c.cer"), Bytes::from("c")
+        ).unwrap();
+        PublicationHandler::new(uri("rsync://example.com/repo/alice/"), store)
+    }
+
+    fn publish(name: &str, data: &str) -> Publish {
+        Publish::with_hash_tag(
+            uri(&format!("rsync://example.com/repo/alice/{}", name)),
+            Base64::from_content(data.as_bytes())
+        )
+    }
+
+    fn update(name: &str, old: &str, data: &str) -> Update {
+        Update::with_hash_tag(
+            uri(&format!("rsync://example.com/repo/alice/{}", name)),
+            Base64::from_content(data.as_bytes()),
+            Hash::from_data(old.as_bytes())
+        )
+    }
+
+    fn withdraw(name: &str, old: &str) -> Withdraw {
+        Withdraw::with_hash_tag(
+            uri(&format!("rsync://example.com/repo/alice/{}", name)),
+            Hash::from_data(old.as_bytes())
+        )
+    }
+
+    fn content(
+        handler: &PublicationHandler<impl ObjectStore>
+    ) -> Vec<(String, Hash)> {
+        handler.list().unwrap().into_elements().into_iter().map(|el| {
+            let (uri, hash) = el.unpack();
+            (uri.to_string(), hash)
+        }).collect()
+    }
+
+    #[test]
+    fn list() {
+        let handler = handler();
+        assert_eq!(
+            content(&handler),
+            [
+                (
+                    "rsync://example.com/repo/alice/a.cer".to_string(),
+                    Hash::from_data(b"a")
+                ),
+                (
+                    "rsync://example.com/repo/alice/b.cer".to_string(),
+                    Hash::from_data(b"b")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_list_reply() {
+        let handler = handler();
+        let mut xml = Vec::new();
+        handler.write_list_reply(&mut xml).unwrap();
+        assert_eq!(
+            Message::decode(xml.as_slice()).unwrap(),
+            Message::list_reply(handler.list().unwrap())
+        );
+    }
+
+    #[test]
+    fn withdraw_all() {
+        let mut handler = handler();
+        let delta = handler.withdraw_all_delta().unwrap();
+        assert_eq!(delta.len(), 2);
+        handler.apply(delta).unwrap();
+        assert!(handler.list().unwrap().elements().is_empty());
+        assert_eq!(handler.store().len(), 1);
+    }
+
+    #[test]
+    fn apply_delta() {
+        let mut handler = handler();
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_update(update("a.cer", "a", "a2"));
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        delta.add_update(update("d.cer", "d", "d2"));
+        assert_eq!(
+            handler.handle(Query::Delta(delta)).unwrap(), Reply::Success
+        );
+        let store = handler.store();
+        assert_eq!(
+            store.get(&uri("rsync://example.com/repo/alice/a.cer")).unwrap(),
+            Some(Bytes::from("a2"))
+        );
+        assert_eq!(
+            store.get(&uri("rsync://example.com/repo/alice/b.cer")).unwrap(),
+            None
+        );
+        assert_eq!(
+            store.get(&uri("rsync://example.com/repo/alice/d.cer")).unwrap(),
+            Some(Bytes::from("d2"))
+        );
+    }
+
+    #[test]
+    fn reject_delta() {
+        let mut handler = handler();
+        let before = content(&handler);
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_publish(publish("a.cer", "a"));
+        delta.add_update(update("b.cer", "x", "b2"));
+        delta.add_withdraw(withdraw("e.cer", "e"));
+        delta.add_publish(Publish::with_hash_tag(
+            uri("rsync://example.com/repo/bob/d.cer"),
+            Base64::from_content(b"d")
+        ));
+        let reply = match handler.handle(Query::Delta(delta)).unwrap() {
+            Reply::ErrorReply(reply) => reply,
+            _ => panic!("expected error reply")
+        };
+        assert_eq!(
+            reply.errors().iter().map(|err| {
+                err.error_code().clone()
+            }).collect::<Vec<_>>(),
+            [
+                ReportErrorCode::ObjectAlreadyPresent,
+                ReportErrorCode::NoObjectMatchingHash,
+                ReportErrorCode::NoObjectPresent,
+                ReportErrorCode::PermissionFailure,
+            ]
+        );
+        assert!(reply.errors()[0].failed_pdu().is_some());
+        assert_eq!(content(&handler), before);
+    }
+
+    #[test]
+    fn size_limits() {
+        let mut handler = handler();
+        handler.set_limits(PublisherLimits {
+            max_objects: Some(3),
+            max_size: Some(5),
+            .. Default::default()
+        });
+        let before = content(&handler);
+
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_publish(publish("e.cer", "e"));
+        assert!(matches!(
+            handler.apply(delta),
+            Err(ApplyError::LimitExceeded(LimitExceeded::Objects(3)))
+        ));
+        assert_eq!(content(&handler), before);
+
+        let mut delta = PublishDelta::empty();
+        delta.add_update(update("a.cer", "a", "aaaaa"));
+        assert!(matches!(
+            handler.apply(delta),
+            Err(ApplyError::LimitExceeded(LimitExceeded::Size(5)))
+        ));
+        assert_eq!(content(&handler), before);
+
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        delta.add_publish(publish("d.cer", "dd"));
+        delta.add_publish(publish("e.cer", "e"));
+        handler.apply(delta).unwrap();
+        assert_eq!(content(&handler).len(), 3);
+
+        // The usage is now tracked rather than recalculated.
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("f.cer", "f"));
+        assert!(matches!(
+            handler.apply(delta),
+            Err(ApplyError::LimitExceeded(LimitExceeded::Objects(3)))
+        ));
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("d.cer", "dd"));
+        delta.add_publish(publish("f.cer", "ff"));
+        handler.apply(delta).unwrap();
+        let mut delta = PublishDelta::empty();
+        delta.add_update(update("f.cer", "ff", "ffff"));
+        assert!(matches!(
+            handler.apply(delta),
+            Err(ApplyError::LimitExceeded(LimitExceeded::Size(5)))
+        ));
+        assert_eq!(content(&handler).len(), 3);
+    }
+
+    #[test]
+    fn equivalent_uris() {
+        let mut handler = handler();
+        handler.set_limits(PublisherLimits {
+            max_objects: Some(3),
+            .. Default::default()
+        });
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_update(Update::with_hash_tag(
+            uri("rsync://EXAMPLE.com/repo/alice/d.cer"),
+            Base64::from_content(b"d2"),
+            Hash::from_data(b"d")
+        ));
+        handler.apply(delta).unwrap();
+        assert_eq!(content(&handler).len(), 3);
+        assert_eq!(
+            handler.store().get(
+                &uri("rsync://example.com/repo/alice/d.cer")
+            ).unwrap(),
+            Some(Bytes::from("d2"))
+        );
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut handler = handler();
+        handler.set_limits(PublisherLimits {
+            max_deltas: Some((2, Duration::from_secs(60))),
+            .. Default::default()
+        });
+        let time = |secs| {
+            DateTime::<Utc>::from_str("2023-05-01T12:00:00Z").unwrap()
+                + chrono::Duration::seconds(secs)
+        };
+        for secs in [0, 10] {
+            assert_eq!(
+                handler.handle_at(
+                    Query::Delta(PublishDelta::empty()), time(secs)
+                ).unwrap(),
+                Reply::Success
+            );
+        }
+        match handler.handle_at(
+            Query::Delta(PublishDelta::empty()), time(20)
+        ).unwrap() {
+            Reply::ErrorReply(reply) => {
+                assert_eq!(
+                    reply.errors()[0].error_code(),
+                    &ReportErrorCode::OtherError
+                );
+            }
+            _ => panic!("expected error reply")
+        }
+        // The list query doesn’t count.
+        assert!(matches!(
+            handler.handle_at(Query::List, time(30)).unwrap(),
+            Reply::List(_)
+        ));
+        assert_eq!(
+            handler.handle_at(
+                Query::Delta(PublishDelta::empty()), time(65)
+            ).unwrap(),
+            Reply::Success
+        );
+    }
+
+    #[test]
+    fn repository() {
+        let mut repo = Repository::new(
+            handler().into_store(), Uuid::nil(), 1
+        );
+        repo.add_publisher(
+            "alice".into(), uri("rsync://example.com/repo/alice/")
+        ).unwrap();
+        repo.add_publisher(
+            "bob".into(), uri("rsync://example.com/repo/bob/")
+        ).unwrap();
+        assert!(matches!(
+            repo.add_publisher(
+                "carol".into(), uri("rsync://example.com/repo/")
+            ),
+            Err(RepositoryError::OverlappingBaseUri(_))
+        ));
+        assert!(matches!(
+            repo.add_publisher(
+                "bob".into(), uri("rsync://example.com/repo/carol/")
+            ),
+            Err(RepositoryError::DuplicatePublisher(_))
+        ));
+        assert!(repo.take_delta().is_none());
+        assert_eq!(repo.snapshot().unwrap().elements().len(), 3);
+
+        // Alice can’t publish into Bob’s directory.
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(Withdraw::with_hash_tag(
+            uri("rsync://example.com/repo/bob/c.cer"), Hash::from_data(b"c")
+        ));
+        assert!(matches!(
+            repo.handle("alice", Query::Delta(delta)).unwrap(),
+            Reply::ErrorReply(_)
+        ));
+        assert!(!repo.has_pending_changes());
+
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_update(update("a.cer", "a", "a2"));
+        repo.apply("alice", delta).unwrap();
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("d.cer", "d"));
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        repo.apply("alice", delta).unwrap();
+        repo.remove_publisher("bob").unwrap();
+        assert!(matches!(
+            repo.list("bob"), Err(RepositoryError::UnknownPublisher(_))
+        ));
+
+        let delta = repo.take_delta().unwrap();
+        assert_eq!(delta.serial(), 2);
+        assert_eq!(repo.serial(), 2);
+        let elements = delta.into_elements();
+        assert_eq!(elements.len(), 3);
+        assert!(matches!(elements[0], DeltaElement::Update(_)));
+        assert!(matches!(elements[1], DeltaElement::Withdraw(_)));
+        assert!(matches!(elements[2], DeltaElement::Withdraw(_)));
+        assert!(repo.take_delta().is_none());
+
+        let snapshot = repo.snapshot().unwrap();
+        assert_eq!(snapshot.serial(), 2);
+        assert_eq!(snapshot.elements().len(), 1);
+        assert_eq!(snapshot.elements()[0].data().as_ref(), b"a2");
+    }
+
+    #[test]
+    fn reset_session() {
+        /// A relying party following RFC 8182, section 3.4.1.
+        struct Client {
+            session_id: Uuid,
+            serial: u64,
+            store: MemoryStore,
+        }
+
+        impl Client {
+            fn update(
+                &mut self, session_id: Uuid, snapshot: &Snapshot,
+                deltas: &[Delta],
+            ) {
+                let first = deltas.first().map(|d| d.serial());
+                if session_id != self.session_id
+                    || self.serial > snapshot.serial()
+                    || first.map(|first| first > self.serial + 1)
+                        .unwrap_or(self.serial != snapshot.serial())
+                {
+                    self.store = MemoryStore::new();
+                    for item in snapshot.elements() {
+                        self.store.insert(
+                            item.uri().clone(), item.data().clone()
+                        ).unwrap();
+                    }
+                    self.session_id = session_id;
+                    self.serial = snapshot.serial();
+                    return
+                }
+                for delta in deltas {
+                    if delta.serial() <= self.serial {
+                        continue
+                    }
+                    for element in delta.elements() {
+                        match *element {
+                            DeltaElement::Publish(ref item) => {
+                                self.store.insert(
+                                    item.uri().clone(), item.data().clone()
+                                ).unwrap();
+                            }
+                            DeltaElement::Update(ref item) => {
+                                self.store.insert(
+                                    item.uri().clone(), item.data().clone()
+                                ).unwrap();
+                            }
+                            DeltaElement::Withdraw(ref item) => {
+                                self.store.remove(item.uri()).unwrap();
+                            }
+                        }
+                    }
+                    self.serial = delta.serial();
+                }
+            }
+        }
+
+        let mut repo = Repository::new(
+            handler().into_store(), Uuid::nil(), 1
+        );
+        repo.add_publisher(
+            "alice".into(), uri("rsync://example.com/repo/alice/")
+        ).unwrap();
+        let mut client = Client {
+            session_id: Uuid::nil(), serial: 0, store: MemoryStore::new()
+        };
+        client.update(Uuid::nil(), &repo.snapshot().unwrap(), &[]);
+        assert_eq!(client.store.len(), 2);
+
+        let mut delta = PublishDelta::empty();
+        delta.add_update(update("a.cer", "a", "a2"));
+        repo.apply("alice", delta).unwrap();
+        let mut deltas = vec![repo.take_delta().unwrap()];
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        repo.apply("alice", delta).unwrap();
+        deltas.push(repo.take_delta().unwrap());
+        client.update(Uuid::nil(), &repo.snapshot().unwrap(), &deltas);
+        assert_eq!(client.serial, 3);
+        assert_eq!(client.store.len(), 1);
+
+        // Changes pending at reset go straight into the new snapshot.
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        repo.apply("alice", delta).unwrap();
+        let session_id = Uuid::from_u128(1);
+        let snapshot = repo.reset_session(session_id).unwrap();
+        assert_eq!(repo.session_id(), session_id);
+        assert_eq!(repo.serial(), 1);
+        assert_eq!(snapshot.session_id(), session_id);
+        assert_eq!(snapshot.serial(), 1);
+        assert!(!repo.has_pending_changes());
+        assert!(repo.take_delta().is_none());
+        assert_eq!(repo.delta_chain().count(), 0);
+
+        // The client has a higher serial but must still resync.
+        client.update(repo.session_id(), &snapshot, &[]);
+        assert_eq!(client.session_id, session_id);
+        assert_eq!(client.serial, 1);
+        assert_eq!(
+            client.store.iter().map(|(uri, data)| {
+                (uri.clone(), data.clone())
+            }).collect::<Vec<_>>(),
+            [
+                (uri("rsync://example.com/repo/alice/a.cer"), "a2".into()),
+                (uri("rsync://example.com/repo/alice/d.cer"), "d".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn notification() {
+        let mut repo = Repository::new(
+            handler().into_store(), Uuid::nil(), 1
+        );
+        repo.add_publisher(
+            "alice".into(), uri("rsync://example.com/repo/alice/")
+        ).unwrap();
+        let snapshot = UriAndHash::new(
+            uri::Https::from_str("https://example.com/1/snapshot.xml")
+                .unwrap(),
+            Hash::from_data(b"snapshot")
+        );
+        assert!(repo.notification().is_none());
+
+        let first = Utc::now();
+        assert!(
+            repo.update_notification_at(snapshot.clone(), Vec::new(), first)
+        );
+        let notification = repo.notification().unwrap().clone();
+        assert_eq!(notification.last_modified(), first);
+        assert_eq!(
+            notification.hash(), Hash::from_data(notification.xml())
+        );
+        assert_eq!(
+            notification.etag(), format!("\"{}\"", notification.hash())
+        );
+        assert_eq!(
+            NotificationFile::parse(notification.xml().as_ref()).unwrap(),
+            *notification.file()
+        );
+
+        // Same content: nothing changes.
+        let later = first + chrono::Duration::seconds(60);
+        assert!(
+            !repo.update_notification_at(snapshot.clone(), Vec::new(), later)
+        );
+        assert_eq!(repo.notification().unwrap().last_modified(), first);
+
+        // New serial: new file.
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        repo.apply("alice", delta).unwrap();
+        repo.take_delta().unwrap();
+        assert!(repo.update_notification_at(snapshot, Vec::new(), later));
+        let updated = repo.notification().unwrap();
+        assert_eq!(updated.file().serial(), 2);
+        assert_eq!(updated.last_modified(), later);
+        assert_ne!(updated.hash(), notification.hash());
+    }
+
+    #[test]
+    fn clock() {
+        use chrono::TimeZone;
+        use crate::clock::ManualClock;
+
+        let mut repo = Repository::new(
+            handler().into_store(), Uuid::nil(), 1
+        );
+        let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let clock = ManualClock::new(start);
+        repo.set_clock(SharedClock::new(clock.clone()));
+        let snapshot = UriAndHash::new(
+            uri::Https::from_str("https://example.com/1/snapshot.xml")
+                .unwrap(),
+            Hash::from_data(b"snapshot")
+        );
+        assert!(repo.update_notification(snapshot, Vec::new()));
+        assert_eq!(repo.notification().unwrap().last_modified(), start);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut repo = Repository::new(
+            handler().into_store(), Uuid::nil(), 1
+        );
+        repo.add_publisher(
+            "alice".into(), uri("rsync://example.com/repo/alice/")
+        ).unwrap();
+        assert_eq!(repo.snapshot_size().unwrap(), 2);
+        assert!(!repo.needs_compaction().unwrap());
+
+        let mut delta = PublishDelta::empty();
+        delta.add_update(update("a.cer", "a", "a2"));
+        repo.apply("alice", delta).unwrap();
+        repo.take_delta().unwrap();
+        assert_eq!(repo.delta_chain().collect::<Vec<_>>(), [(2, 2)]);
+        assert!(!repo.needs_compaction().unwrap());
+
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        repo.apply("alice", delta).unwrap();
+        repo.take_delta().unwrap();
+        assert_eq!(repo.delta_chain_size(), 2);
+        assert_eq!(repo.snapshot_size().unwrap(), 2);
+        assert!(!repo.needs_compaction().unwrap());
+        repo.set_compaction_policy(CompactionPolicy {
+            max_delta_percent: Some(50),
+            max_deltas: None,
+        });
+        assert!(repo.needs_compaction().unwrap());
+
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        repo.apply("alice", delta).unwrap();
+        let compaction = repo.compact().unwrap();
+        assert_eq!(compaction.delta.unwrap().serial(), 4);
+        assert_eq!(compaction.snapshot.serial(), 4);
+        assert_eq!(compaction.snapshot.elements().len(), 2);
+        assert_eq!(repo.serial(), 4);
+        assert_eq!(repo.delta_chain().count(), 0);
+        assert!(!repo.needs_compaction().unwrap());
+
+        repo.set_compaction_policy(CompactionPolicy {
+            max_delta_percent: None,
+            max_deltas: Some(0),
+        });
+        let compaction = repo.compact().unwrap();
+        assert!(compaction.delta.is_none());
+        assert_eq!(compaction.snapshot.serial(), 4);
+        let mut delta = PublishDelta::empty();
+        delta.add_withdraw(withdraw("d.cer", "d"));
+        repo.apply("alice", delta).unwrap();
+        repo.take_delta().unwrap();
+        assert!(repo.needs_compaction().unwrap());
+    }
+
+    #[test]
+    fn roll_back() {
+        let handler = handler();
+        let before = content(&handler);
+        let mut handler = PublicationHandler::new(
+            handler.base_uri().clone(),
+            FailingStore {
+                store: handler.into_store(),
+                fail_at: 3,
+                changes: 0,
+            }
+        );
+        let mut delta = PublishDelta::empty();
+        delta.add_publish(publish("d.cer", "d"));
+        delta.add_update(update("a.cer", "a", "a2"));
+        delta.add_withdraw(withdraw("b.cer", "b"));
+        assert!(handler.handle(Query::Delta(delta)).is_err());
+        assert_eq!(handler.store().changes, 5);
+        assert_eq!(content(&handler), before);
+    }
+}
@@ -0,0 +1,139 @@
+//! Working around the peculiarities of other implementations.
+//!
+//! Not every implementation of the publication protocol follows RFC 8181
+//! to the letter. Some put their messages into the wrong namespace or add
+//! elements of their own, others choke on messages that are perfectly
+//! valid, such as publish elements with an empty tag. Rather than relaxing
+//! the decoders and encoders for everyone, the [`Quirks`] of a particular
+//! peer can be registered with a [`QuirkRegistry`] under the service URI
+//! or handle of the peer. The quirks are then consulted when decoding
+//! messages from and encoding messages for that peer via
+//! [`DecodeOptions::from_quirks`] and [`Message::apply_quirks`].
+//!
+//! [`DecodeOptions::from_quirks`]: super::publication::DecodeOptions::from_quirks
+//! [`Message::apply_quirks`]: super::publication::Message::apply_quirks
+
+use std::collections::HashMap;
+
+
+//------------ Quirks --------------------------------------------------------
+
+/// The peculiarities of a peer.
+///
+/// The default value matches the behaviour of the plain decoders and
+/// encoders.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Quirks {
+    /// Whether known deviations from the RFC are accepted in messages.
+    ///
+    /// This covers, for instance, messages in the wrong namespace. See
+    /// [`Deviation`][super::publication::Deviation] for the full list.
+    pub deviations: bool,
+
+    /// Whether unknown elements in messages are skipped.
+    ///
+    /// If this is `false`, unknown elements lead to an error.
+    pub unknown_elements: bool,
+
+    /// Whether elements without a tag get one before sending.
+    ///
+    /// If this is `true`, publish, update, and withdraw elements without a
+    /// tag are given the hash of the published or withdrawn object as
+    /// their tag instead of an empty one.
+    pub hash_tags: bool,
+}
+
+impl Quirks {
+    /// Returns the quirks of a peer strictly following the RFCs.
+    ///
+    /// Deviations and unknown elements are rejected.
+    pub fn strict() -> Self {
+        Quirks {
+            deviations: false,
+            unknown_elements: false,
+            hash_tags: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            deviations: true,
+            unknown_elements: false,
+            hash_tags: false,
+        }
+    }
+}
+
+
+//------------ QuirkRegistry -------------------------------------------------
+
+/// The quirks of known peers.
+///
+/// Peers are identified by a string, typically their service URI when
+/// acting as a client or their handle when acting as a server. Peers
+/// without an entry get the default quirks.
+#[derive(Clone, Debug, Default)]
+pub struct QuirkRegistry {
+    /// The quirks of peers without an entry.
+    default: Quirks,
+
+    /// The quirks of the registered peers.
+    peers: HashMap<String, Quirks>,
+}
+
+impl QuirkRegistry {
+    /// Creates a new registry using the quirks for unknown peers.
+    pub fn new(default: Quirks) -> Self {
+        QuirkRegistry { default, peers: HashMap::new() }
+    }
+
+    /// Returns the quirks of peers without an entry.
+    pub fn default_quirks(&self) -> &Quirks {
+        &self.default
+    }
+
+    /// Registers the quirks of a peer.
+    ///
+    /// Returns the quirks previously registered for the peer.
+    pub fn register(
+        &mut self, peer: impl Into<String>, quirks: Quirks
+    ) -> Option<Quirks> {
+        self.peers.insert(peer.into(), quirks)
+    }
+
+    /// Removes the quirks of a peer.
+    pub fn remove(&mut self, peer: &str) -> Option<Quirks> {
+        self.peers.remove(peer)
+    }
+
+    /// Returns the quirks to use for a peer.
+    pub fn get(&self, peer: &str) -> &Quirks {
+        self.peers.get(peer).unwrap_or(&self.default)
+    }
+
+    /// Returns an iterator over all registered peers and their quirks.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Quirks)> + '_ {
+        self.peers.iter().map(|(key, value)| (key.as_str(), value))
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let mut registry = QuirkRegistry::new(Quirks::strict());
+        let quirks = Quirks { hash_tags: true, .. Default::default() };
+        assert_eq!(registry.register("alice", quirks), None);
+        assert_eq!(registry.get("alice"), &quirks);
+        assert_eq!(registry.get("bob"), &Quirks::strict());
+        assert_eq!(registry.remove("alice"), Some(quirks));
+        assert_eq!(registry.get("alice"), &Quirks::strict());
+    }
+}
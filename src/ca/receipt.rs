@@ -0,0 +1,209 @@
+//! Signed receipts for the state of a publisher.
+//!
+//! After a publication server has applied a delta of a publisher, neither
+//! side can later prove to a third party what the server published at the
+//! time. A [`StateReceipt`] provides this: it is a CMS signed message as
+//! used by the publication protocol whose content is the canonical list of
+//! all objects of the publisher at the time of signing. The server creates
+//! it, for instance via [`PublicationHandler::receipt`], and hands it to
+//! the publisher, which can verify it against its own idea of what it
+//! published and then archive it.
+//!
+//! The content of a receipt is the XML of an RFC 8181 list reply message
+//! whose elements are in canonical order as produced by
+//! [`ListReply::canonicalize`]. The time of the receipt is the signing time
+//! of the CMS.
+//!
+//! [`PublicationHandler::receipt`]: super::pubserver::PublicationHandler::receipt
+
+use std::{error, fmt};
+use bytes::Bytes;
+use crate::crypto::{PublicKey, Signer, SigningError};
+use crate::repository::x509::{Time, Validity};
+use crate::store::StoreError;
+use crate::xml::decode::Error as XmlError;
+use super::publication::{Error, ListReply, ListReplyReader};
+use super::sigmsg::SignedMessage;
+
+
+//------------ StateReceipt --------------------------------------------------
+
+/// A signed statement of the objects published by a publisher.
+#[derive(Clone, Debug)]
+pub struct StateReceipt {
+    /// The signed message.
+    signed_msg: SignedMessage,
+
+    /// The canonical list of objects.
+    list: ListReply,
+}
+
+impl StateReceipt {
+    /// Creates a receipt for a list of objects.
+    ///
+    /// The list is brought into canonical form first.
+    pub fn create<S: Signer>(
+        mut list: ListReply,
+        issuing_key_id: &S::KeyId,
+        signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        list.canonicalize();
+        let mut data = Vec::new();
+        ListReply::write_xml_streaming::<_, std::io::Error>(
+            list.iter().cloned().map(Ok), &mut data
+        ).expect("writing to vec failed");
+        let signed_msg = SignedMessage::create(
+            data.into(),
+            Validity::new(
+                Time::five_minutes_ago(), Time::five_minutes_from_now()
+            ),
+            issuing_key_id,
+            signer
+        )?;
+        Ok(StateReceipt { signed_msg, list })
+    }
+
+    /// Decodes a receipt.
+    ///
+    /// Fails if the content isn’t a list reply in canonical form.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let signed_msg = SignedMessage::decode(bytes, false)
+            .map_err(|e| Error::CmsDecode(e.to_string()))?;
+        let content = signed_msg.content().to_bytes();
+        let list = ListReply::new(
+            ListReplyReader::new(
+                content.as_ref()
+            )?.collect::<Result<_, _>>()?
+        );
+        if list.elements().windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(XmlError::Malformed.into())
+        }
+        Ok(StateReceipt { signed_msg, list })
+    }
+
+    /// Validates the receipt with the server’s key.
+    pub fn validate(&self, issuer_key: &PublicKey) -> Result<(), Error> {
+        self.signed_msg.validate(issuer_key).map_err(Into::into)
+    }
+
+    /// Validates the receipt with the server’s key at the given time.
+    pub fn validate_at(
+        &self, issuer_key: &PublicKey, when: Time
+    ) -> Result<(), Error> {
+        self.signed_msg.validate_at(issuer_key, when).map_err(Into::into)
+    }
+
+    /// Returns the canonical list of objects.
+    pub fn list(&self) -> &ListReply {
+        &self.list
+    }
+
+    /// Returns the time the receipt was signed.
+    pub fn signing_time(&self) -> Option<Time> {
+        self.signed_msg.signing_time()
+    }
+
+    /// Returns whether the receipt covers exactly the given objects.
+    ///
+    /// The objects can be given in any order.
+    pub fn matches(&self, list: &ListReply) -> bool {
+        let mut list = list.clone();
+        list.canonicalize();
+        list == self.list
+    }
+
+    /// Returns the encoded receipt.
+    pub fn to_bytes(&self) -> Bytes {
+        self.signed_msg.to_captured().into_bytes()
+    }
+}
+
+
+//------------ ReceiptError --------------------------------------------------
+
+/// Creating a receipt failed.
+#[derive(Debug)]
+pub enum ReceiptError<S> {
+    /// Listing the objects failed.
+    Store(StoreError),
+
+    /// Signing the receipt failed.
+    Signing(SigningError<S>),
+}
+
+impl<S> From<StoreError> for ReceiptError<S> {
+    fn from(err: StoreError) -> Self {
+        ReceiptError::Store(err)
+    }
+}
+
+impl<S> From<SigningError<S>> for ReceiptError<S> {
+    fn from(err: SigningError<S>) -> Self {
+        ReceiptError::Signing(err)
+    }
+}
+
+impl<S: fmt::Display> fmt::Display for ReceiptError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReceiptError::Store(err) => err.fmt(f),
+            ReceiptError::Signing(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<S: error::Error + 'static> error::Error for ReceiptError<S> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReceiptError::Store(err) => Some(err),
+            ReceiptError::Signing(err) => Some(err),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::uri;
+    use crate::crypto::PublicKeyFormat;
+    use crate::crypto::softsigner::OpenSslSigner;
+    use super::*;
+    use super::super::publication::{Base64, ListElement};
+
+    #[test]
+    fn create_and_verify() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let public = signer.get_key_info(&key).unwrap();
+
+        let element = |uri: &str| {
+            ListElement::new(
+                uri::Rsync::from_str(uri).unwrap(),
+                Base64::from_content(uri.as_bytes()).to_hash()
+            )
+        };
+        let list = ListReply::new(vec![
+            element("rsync://localhost/ca/b.cer"),
+            element("rsync://localhost/ca/a.cer"),
+        ]);
+
+        let receipt = StateReceipt::create(list.clone(), &key, &signer)
+            .unwrap();
+        let receipt = StateReceipt::decode(&receipt.to_bytes()).unwrap();
+        receipt.validate(&public).unwrap();
+        assert!(receipt.signing_time().is_some());
+        assert!(receipt.matches(&list));
+        assert_eq!(
+            receipt.list().elements()[0].uri().path(), "a.cer"
+        );
+        assert!(!receipt.matches(&ListReply::empty()));
+        assert!(receipt.validate(
+            &signer.get_key_info(
+                &signer.create_key(PublicKeyFormat::Rsa).unwrap()
+            ).unwrap()
+        ).is_err());
+    }
+}
@@ -0,0 +1,749 @@
+//! Planning the re-issuance of child certificates.
+//!
+//! When the resources of a CA change or the CA rolls its key, some or all
+//! of the certificates it has issued to its children need to be replaced.
+//! A certificate that claims resources the CA no longer holds makes the
+//! child’s products invalid, a certificate that is missing resources the
+//! child is now entitled to withholds them, and after a key roll all
+//! certificates need to be issued under the new key before the old one
+//! can be retired.
+//!
+//! The [`ReissuePlanner`] takes the CA’s current key and resources as well
+//! as the certificates currently issued to its children and determines
+//! what needs to be done. The resulting [`ReissuePlan`] is an ordered list
+//! of [`ReissueAction`]s: all new certificates come first, the revocations
+//! of the certificates they replace after. This way, a child always has a
+//! valid certificate when the actions are performed in order and published
+//! together.
+//!
+//! For the common case of the CA’s resources shrinking, the planner can
+//! also create the replacement certificates for all over-claiming children
+//! directly. These are returned as [`Replacements`] which provide the CRL
+//! entries and the publish delta needed to put them in place.
+
+use std::{cmp, fmt};
+use crate::uri;
+use crate::clock::{Clock, SharedClock};
+use crate::crypto::{KeyIdentifier, Signer, SigningError};
+use crate::repository::cert::Cert;
+use crate::repository::crl::CrlEntry;
+use crate::repository::names::KeyNameStyle;
+use crate::repository::resources::ResourceSet;
+use crate::repository::x509::{Serial, Time, Validity};
+use crate::rrdp;
+use super::idexchange::ChildHandle;
+use super::provisioning::RequestResourceLimit;
+use super::publication::{Base64, PublishDelta, Update, Withdraw};
+
+
+//------------ ReissuePlanner ------------------------------------------------
+
+/// Determines which child certificates need to be re-issued.
+///
+/// The planner is created from the CA’s current key identifier and
+/// resources. Each child certificate is then added via
+/// [`add_child`][Self::add_child] together with the resources the child
+/// is entitled to and the limit it requested. Finally,
+/// [`plan`][Self::plan] produces the plan.
+///
+/// The resources a child should have are its entitlements restricted to
+/// the resources of the CA and then to the child’s request limit. If these
+/// differ from the resources on the current certificate, or if the
+/// current certificate was issued under a different key, a new
+/// certificate is planned and the current one revoked. If nothing is left
+/// for the child, the current certificate is only revoked.
+#[derive(Clone, Debug)]
+pub struct ReissuePlanner {
+    /// The identifier of the key the CA currently issues under.
+    key: KeyIdentifier,
+
+    /// The resources currently held by the CA.
+    resources: ResourceSet,
+
+    /// The children added so far.
+    children: Vec<ChildCert>,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+}
+
+/// A certificate currently issued to a child.
+#[derive(Clone, Debug)]
+struct ChildCert {
+    handle: ChildHandle,
+    entitlements: ResourceSet,
+    limit: RequestResourceLimit,
+    cert: Cert,
+}
+
+impl ReissuePlanner {
+    /// Creates a new planner for a CA’s current key and resources.
+    pub fn new(key: KeyIdentifier, resources: ResourceSet) -> Self {
+        ReissuePlanner {
+            key, resources,
+            children: Vec::new(),
+            clock: Default::default(),
+        }
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock is used by the methods without an explicit time. By
+    /// default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Adds a certificate currently issued to a child.
+    ///
+    /// The `entitlements` are the resources the child is entitled to
+    /// receive from this CA, `limit` is the limit the child included in
+    /// its last issuance request. A child with several current
+    /// certificates, e.g., during its own key roll, is added once for
+    /// each.
+    pub fn add_child(
+        &mut self,
+        handle: ChildHandle,
+        entitlements: ResourceSet,
+        limit: RequestResourceLimit,
+        cert: Cert,
+    ) {
+        self.children.push(ChildCert { handle, entitlements, limit, cert })
+    }
+
+    /// Returns the resources a child should have on its certificate.
+    fn target_resources(
+        &self, entitlements: &ResourceSet, limit: &RequestResourceLimit
+    ) -> ResourceSet {
+        let available = self.resources.intersection(entitlements);
+        if limit.is_empty() {
+            return available
+        }
+        let limit = ResourceSet::new(
+            limit.asn().cloned().unwrap_or_else(|| {
+                available.asn().clone()
+            }),
+            limit.ipv4().cloned().unwrap_or_else(|| {
+                available.ipv4().clone()
+            }),
+            limit.ipv6().cloned().unwrap_or_else(|| {
+                available.ipv6().clone()
+            }),
+        );
+        available.intersection(&limit)
+    }
+
+    /// Produces the plan.
+    pub fn plan(&self) -> ReissuePlan {
+        let mut issue = Vec::new();
+        let mut revoke = Vec::new();
+        for child in &self.children {
+            let target = self.target_resources(
+                &child.entitlements, &child.limit
+            );
+            let issuer = child.cert.authority_key_identifier();
+            let reason = if issuer != Some(self.key) {
+                ReissueReason::KeyRoll
+            }
+            else {
+                // A certificate with inherited resources cannot be
+                // compared and is always replaced.
+                match ResourceSet::try_from(&child.cert) {
+                    Ok(current) => {
+                        match ReissueReason::from_change(&current, &target) {
+                            Some(reason) => reason,
+                            None => continue,
+                        }
+                    }
+                    Err(_) => ReissueReason::Changed,
+                }
+            };
+            let reason = if target.is_empty() {
+                ReissueReason::NoResources
+            }
+            else {
+                issue.push(ReissueAction::Issue {
+                    child: child.handle.clone(),
+                    key: child.cert.subject_key_identifier(),
+                    resources: target,
+                    reason,
+                });
+                reason
+            };
+            revoke.push(ReissueAction::Revoke {
+                child: child.handle.clone(),
+                issuer: issuer.unwrap_or(self.key),
+                serial: child.cert.serial_number(),
+                reason,
+            });
+        }
+        issue.append(&mut revoke);
+        ReissuePlan { actions: issue }
+    }
+
+    /// Creates replacement certificates for over-claiming children.
+    ///
+    /// This is the same as
+    /// [`replace_overclaiming_at`][Self::replace_overclaiming_at] using
+    /// the current time of the planner’s clock.
+    pub fn replace_overclaiming<S: Signer>(
+        &self, not_after: Time, signer: &S, key: &S::KeyId,
+    ) -> Result<Replacements, SigningError<S::Error>> {
+        self.replace_overclaiming_at(
+            not_after, signer, key, Time::new(self.clock.now())
+        )
+    }
+
+    /// Creates replacement certificates for over-claiming children.
+    ///
+    /// Following RFC 6492, a CA whose resources shrink should promptly
+    /// issue new certificates to its children that no longer claim the
+    /// lost resources and revoke the old ones. This method does so for
+    /// all certificates issued under the CA’s current key that claim
+    /// resources the child should no longer have.
+    ///
+    /// The replacement certificates are copies of the old certificates
+    /// with the new resources, a new random serial number, and a validity
+    /// starting at `now`. The validity ends at the end of the old
+    /// certificate’s validity or at `not_after`, whichever is earlier.
+    /// Typically, `not_after` is the end of the validity of the CA’s own
+    /// resources as given by its parent. If no resources are left for a
+    /// child, its certificate is only revoked.
+    ///
+    /// The certificates are signed with `key` which must be the key the
+    /// planner was created for.
+    pub fn replace_overclaiming_at<S: Signer>(
+        &self, not_after: Time, signer: &S, key: &S::KeyId, now: Time,
+    ) -> Result<Replacements, SigningError<S::Error>> {
+        let mut res = Vec::new();
+        for child in &self.children {
+            if child.cert.authority_key_identifier() != Some(self.key) {
+                continue
+            }
+            let target = self.target_resources(
+                &child.entitlements, &child.limit
+            );
+            let over_claiming = match ResourceSet::try_from(&child.cert) {
+                Ok(current) => !target.contains(&current),
+                Err(_) => true,
+            };
+            if !over_claiming {
+                continue
+            }
+            if target.is_empty() {
+                res.push(Replacement {
+                    child: child.handle.clone(),
+                    old: child.cert.clone(),
+                    new: None,
+                });
+                continue
+            }
+            let mut tbs = (*child.cert).clone();
+            tbs.set_serial_number(Serial::random(signer)?);
+            tbs.set_validity(Validity::new(
+                now, cmp::min(child.cert.validity().not_after(), not_after)
+            ));
+            tbs.set_as_resources(target.to_as_resources());
+            tbs.set_v4_resources(target.to_ip_resources_v4());
+            tbs.set_v6_resources(target.to_ip_resources_v6());
+            res.push(Replacement {
+                child: child.handle.clone(),
+                old: child.cert.clone(),
+                new: Some(tbs.into_cert(signer, key)?),
+            });
+        }
+        Ok(Replacements { replacements: res, revocation_date: now })
+    }
+}
+
+
+//------------ ReissuePlan ---------------------------------------------------
+
+/// An ordered list of actions for re-issuing child certificates.
+///
+/// The actions should be performed in order. All issuances come before
+/// all revocations.
+#[derive(Clone, Debug, Default)]
+pub struct ReissuePlan {
+    actions: Vec<ReissueAction>,
+}
+
+impl ReissuePlan {
+    /// Returns whether there is nothing to do.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Returns the actions in the order they should be performed.
+    pub fn actions(&self) -> &[ReissueAction] {
+        &self.actions
+    }
+
+    /// Returns an iterator over the planned issuances.
+    pub fn issuances(&self) -> impl Iterator<Item = &ReissueAction> + '_ {
+        self.actions.iter().filter(|action| action.is_issue())
+    }
+
+    /// Returns an iterator over the planned revocations.
+    pub fn revocations(
+        &self
+    ) -> impl Iterator<Item = &ReissueAction> + '_ {
+        self.actions.iter().filter(|action| !action.is_issue())
+    }
+
+    /// Returns the CRL entries for revocations by the given issuer key.
+    ///
+    /// The entries can be added to the CRL of that key, e.g., via the
+    /// publication point builder.
+    pub fn crl_entries(
+        &self, issuer_key: KeyIdentifier, revocation_date: Time
+    ) -> Vec<CrlEntry> {
+        self.actions.iter().filter_map(|action| match *action {
+            ReissueAction::Revoke { issuer, serial, .. }
+                if issuer == issuer_key
+            => {
+                Some(CrlEntry::new(serial, revocation_date))
+            }
+            _ => None,
+        }).collect()
+    }
+}
+
+impl IntoIterator for ReissuePlan {
+    type Item = ReissueAction;
+    type IntoIter = std::vec::IntoIter<ReissueAction>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.actions.into_iter()
+    }
+}
+
+
+//------------ ReissueAction -------------------------------------------------
+
+/// A single action of a re-issuance plan.
+#[derive(Clone, Debug)]
+pub enum ReissueAction {
+    /// Issue a new certificate to a child under the CA’s current key.
+    Issue {
+        /// The child to issue the certificate to.
+        child: ChildHandle,
+
+        /// The subject key identifier of the child’s key.
+        key: KeyIdentifier,
+
+        /// The resources to include in the certificate.
+        resources: ResourceSet,
+
+        /// Why the certificate is issued.
+        reason: ReissueReason,
+    },
+
+    /// Revoke a certificate previously issued to a child.
+    Revoke {
+        /// The child the certificate was issued to.
+        child: ChildHandle,
+
+        /// The identifier of the key the certificate was issued under.
+        ///
+        /// The revocation needs to go onto the CRL for this key.
+        issuer: KeyIdentifier,
+
+        /// The serial number of the certificate.
+        serial: Serial,
+
+        /// Why the certificate is revoked.
+        reason: ReissueReason,
+    },
+}
+
+impl ReissueAction {
+    /// Returns whether this is an issuance.
+    pub fn is_issue(&self) -> bool {
+        matches!(*self, ReissueAction::Issue { .. })
+    }
+
+    /// Returns the child the action is for.
+    pub fn child(&self) -> &ChildHandle {
+        match *self {
+            ReissueAction::Issue { ref child, .. } => child,
+            ReissueAction::Revoke { ref child, .. } => child,
+        }
+    }
+
+    /// Returns the reason for the action.
+    pub fn reason(&self) -> ReissueReason {
+        match *self {
+            ReissueAction::Issue { reason, .. } => reason,
+            ReissueAction::Revoke { reason, .. } => reason,
+        }
+    }
+}
+
+impl fmt::Display for ReissueAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReissueAction::Issue {
+                ref child, key, ref resources, reason
+            } => {
+                write!(f,
+                    "issue to {} for key {} ({}): {}",
+                    child, key, reason, resources
+                )
+            }
+            ReissueAction::Revoke { ref child, issuer, serial, reason } => {
+                write!(f,
+                    "revoke {} of {} under key {} ({})",
+                    serial, child, issuer, reason
+                )
+            }
+        }
+    }
+}
+
+
+//------------ ReissueReason -------------------------------------------------
+
+/// The reason for re-issuing or revoking a child certificate.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ReissueReason {
+    /// The certificate was issued under a key other than the current one.
+    KeyRoll,
+
+    /// The certificate claims resources the child should no longer have.
+    Shrunk,
+
+    /// The certificate lacks resources the child should now have.
+    Grown,
+
+    /// The certificate both lacks and over-claims resources.
+    Changed,
+
+    /// The child should not have any resources anymore.
+    NoResources,
+}
+
+impl ReissueReason {
+    /// Returns the reason for changing from current to target resources.
+    fn from_change(
+        current: &ResourceSet, target: &ResourceSet
+    ) -> Option<Self> {
+        match (current.contains(target), target.contains(current)) {
+            (true, true) => None,
+            (true, false) => Some(ReissueReason::Shrunk),
+            (false, true) => Some(ReissueReason::Grown),
+            (false, false) => Some(ReissueReason::Changed),
+        }
+    }
+}
+
+impl fmt::Display for ReissueReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ReissueReason::KeyRoll => "key roll",
+            ReissueReason::Shrunk => "resources shrunk",
+            ReissueReason::Grown => "resources grown",
+            ReissueReason::Changed => "resources changed",
+            ReissueReason::NoResources => "no resources",
+        })
+    }
+}
+
+
+//------------ Replacements --------------------------------------------------
+
+/// Replacement certificates for over-claiming children.
+///
+/// A value of this type is created by
+/// [`ReissuePlanner::replace_overclaiming`]. To put the replacements in
+/// place, the [CRL entries][Self::crl_entries] need to be added to the
+/// CA’s CRL and the [publish delta][Self::to_publish_delta] needs to be
+/// sent to the publication server together with the updated CRL and
+/// manifest.
+#[derive(Clone, Debug)]
+pub struct Replacements {
+    /// The replacements.
+    replacements: Vec<Replacement>,
+
+    /// The revocation date for the old certificates.
+    revocation_date: Time,
+}
+
+impl Replacements {
+    /// Returns whether there aren’t any replacements.
+    pub fn is_empty(&self) -> bool {
+        self.replacements.is_empty()
+    }
+
+    /// Returns an iterator over the replacements.
+    pub fn iter(&self) -> impl Iterator<Item = &Replacement> + '_ {
+        self.replacements.iter()
+    }
+
+    /// Returns the CRL entries revoking the old certificates.
+    pub fn crl_entries(&self) -> Vec<CrlEntry> {
+        self.replacements.iter().map(|item| {
+            CrlEntry::new(item.old.serial_number(), self.revocation_date)
+        }).collect()
+    }
+
+    /// Returns the publish delta for the replacements.
+    ///
+    /// The certificates are assumed to be published in `base_uri` under
+    /// a name derived from the child’s subject key identifier via `style`.
+    /// Each old certificate is updated with its replacement or withdrawn
+    /// if there is none.
+    pub fn to_publish_delta(
+        &self, base_uri: &uri::Rsync, style: KeyNameStyle
+    ) -> PublishDelta {
+        let mut res = PublishDelta::empty();
+        for item in &self.replacements {
+            let uri = match base_uri.join(
+                style.file_name(
+                    &item.old.subject_key_identifier(), "cer"
+                ).as_bytes()
+            ) {
+                Ok(uri) => uri,
+                Err(_) => continue,
+            };
+            let hash = rrdp::Hash::from_data(
+                item.old.to_captured().as_slice()
+            );
+            match item.new {
+                Some(ref new) => {
+                    res.add_update(Update::new(
+                        None, uri,
+                        Base64::from_content(new.to_captured().as_slice()),
+                        hash
+                    ))
+                }
+                None => res.add_withdraw(Withdraw::new(None, uri, hash)),
+            }
+        }
+        res
+    }
+}
+
+impl IntoIterator for Replacements {
+    type Item = Replacement;
+    type IntoIter = std::vec::IntoIter<Replacement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.replacements.into_iter()
+    }
+}
+
+
+//------------ Replacement ---------------------------------------------------
+
+/// The replacement of a single over-claiming child certificate.
+#[derive(Clone, Debug)]
+pub struct Replacement {
+    /// The child the certificates are issued to.
+    child: ChildHandle,
+
+    /// The certificate to be replaced.
+    old: Cert,
+
+    /// The replacement certificate if the child has resources left.
+    new: Option<Cert>,
+}
+
+impl Replacement {
+    /// Returns the child the certificates are issued to.
+    pub fn child(&self) -> &ChildHandle {
+        &self.child
+    }
+
+    /// Returns the certificate to be replaced.
+    pub fn old(&self) -> &Cert {
+        &self.old
+    }
+
+    /// Returns the replacement certificate.
+    ///
+    /// Returns `None` if the child has no resources left and the old
+    /// certificate is only revoked.
+    pub fn new_cert(&self) -> Option<&Cert> {
+        self.new.as_ref()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn child_cert() -> Cert {
+        Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap()
+    }
+
+    fn planner(resources: ResourceSet) -> ReissuePlanner {
+        let cert = child_cert();
+        let mut res = ReissuePlanner::new(
+            cert.authority_key_identifier().unwrap(), resources
+        );
+        res.add_child(
+            "child".parse().unwrap(), ResourceSet::all(),
+            RequestResourceLimit::new(), cert
+        );
+        res
+    }
+
+    #[test]
+    fn unchanged() {
+        assert!(planner(ResourceSet::all()).plan().is_empty());
+    }
+
+    #[test]
+    fn shrunk() {
+        let resources = ResourceSet::from_strs(
+            "AS65000", "10.0.0.0/8", ""
+        ).unwrap();
+        let plan = planner(resources.clone()).plan();
+        assert_eq!(plan.actions().len(), 2);
+        match plan.actions()[0] {
+            ReissueAction::Issue { resources: ref issued, reason, .. } => {
+                assert_eq!(issued, &resources);
+                assert_eq!(reason, ReissueReason::Shrunk);
+            }
+            _ => panic!("expected issuance first"),
+        }
+        assert!(!plan.actions()[1].is_issue());
+
+        let cert = child_cert();
+        let entries = plan.crl_entries(
+            cert.authority_key_identifier().unwrap(), Time::now()
+        );
+        assert_eq!(entries.len(), 1);
+        match plan.actions()[1] {
+            ReissueAction::Revoke { serial, .. } => {
+                assert_eq!(serial, cert.serial_number());
+            }
+            _ => panic!("expected revocation second"),
+        }
+    }
+
+    #[test]
+    fn limited() {
+        let cert = child_cert();
+        let mut planner = ReissuePlanner::new(
+            cert.authority_key_identifier().unwrap(), ResourceSet::all()
+        );
+        let mut limit = RequestResourceLimit::new();
+        limit.with_ipv4("10.0.0.0/8".parse().unwrap());
+        planner.add_child(
+            "child".parse().unwrap(), ResourceSet::all(), limit, cert
+        );
+        let plan = planner.plan();
+        assert_eq!(plan.issuances().count(), 1);
+        assert_eq!(plan.revocations().count(), 1);
+        assert_eq!(plan.actions()[0].reason(), ReissueReason::Shrunk);
+    }
+
+    #[test]
+    fn key_roll() {
+        let cert = child_cert();
+        let mut planner = ReissuePlanner::new(
+            cert.subject_key_identifier(), ResourceSet::all()
+        );
+        planner.add_child(
+            "child".parse().unwrap(), ResourceSet::all(),
+            RequestResourceLimit::new(), cert.clone()
+        );
+        let plan = planner.plan();
+        assert_eq!(plan.actions().len(), 2);
+        assert!(plan.actions()[0].is_issue());
+        assert_eq!(plan.actions()[0].reason(), ReissueReason::KeyRoll);
+        assert!(plan.crl_entries(
+            cert.subject_key_identifier(), Time::now()
+        ).is_empty());
+        assert_eq!(
+            plan.crl_entries(
+                cert.authority_key_identifier().unwrap(), Time::now()
+            ).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn no_resources() {
+        let plan = planner(ResourceSet::empty()).plan();
+        assert_eq!(plan.actions().len(), 1);
+        assert!(!plan.actions()[0].is_issue());
+        assert_eq!(plan.actions()[0].reason(), ReissueReason::NoResources);
+    }
+}
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::crypto::PublicKeyFormat;
+    use crate::crypto::softsigner::OpenSslSigner;
+    use super::*;
+
+    #[test]
+    fn replace_overclaiming() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let cert = Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap();
+        let resources = ResourceSet::from_strs(
+            "AS65000", "10.0.0.0/8", ""
+        ).unwrap();
+        let mut planner = ReissuePlanner::new(
+            cert.authority_key_identifier().unwrap(), resources.clone()
+        );
+        planner.add_child(
+            "child".parse().unwrap(), ResourceSet::all(),
+            RequestResourceLimit::new(), cert.clone()
+        );
+        planner.add_child(
+            "gone".parse().unwrap(), ResourceSet::from_strs(
+                "AS65001", "", ""
+            ).unwrap(),
+            RequestResourceLimit::new(), cert.clone()
+        );
+
+        let now = Time::now();
+        let not_after = cert.validity().not_after();
+        let replacements = planner.replace_overclaiming_at(
+            not_after, &signer, &key, now
+        ).unwrap();
+        let items: Vec<_> = replacements.iter().collect();
+        assert_eq!(items.len(), 2);
+        let new = items[0].new_cert().unwrap();
+        assert_eq!(ResourceSet::try_from(new).unwrap(), resources);
+        assert_eq!(
+            new.subject_key_identifier(), cert.subject_key_identifier()
+        );
+        assert_eq!(new.validity().not_after(), not_after);
+        assert_ne!(new.serial_number(), cert.serial_number());
+        assert!(items[1].new_cert().is_none());
+        assert_eq!(replacements.crl_entries().len(), 2);
+
+        let base = uri::Rsync::from_str("rsync://example.com/ca/").unwrap();
+        assert_eq!(
+            replacements.to_publish_delta(&base, KeyNameStyle::Hex).len(),
+            2
+        );
+
+        // Nothing to do if the resources didn’t shrink.
+        let mut planner = ReissuePlanner::new(
+            cert.authority_key_identifier().unwrap(), ResourceSet::all()
+        );
+        planner.add_child(
+            "child".parse().unwrap(), ResourceSet::all(),
+            RequestResourceLimit::new(), cert
+        );
+        assert!(planner.replace_overclaiming_at(
+            not_after, &signer, &key, now
+        ).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,868 @@
+//! Serving the publication and provisioning protocols over HTTP.
+//!
+//! Both the publication protocol of RFC 8181 and the provisioning
+//! protocol of RFC 6492 exchange CMS signed messages via HTTP POST
+//! requests. The server needs to check the content type of the request,
+//! decode and validate the CMS against the identity certificate of the
+//! client, process the enclosed query, and sign and return the reply.
+//!
+//! This module takes care of all of this except for the processing of the
+//! query itself, which is left to implementations of the
+//! [`PublicationServer`] and [`ProvisioningServer`] traits. Just as with
+//! [fetching][crate::fetch::https], the crate does not tie itself to a
+//! particular HTTP implementation. Instead, the [`PublicationEndpoint`]
+//! and [`ProvisioningEndpoint`] take the content type and body of a
+//! request and produce a [`Response`] that is straightforward to turn
+//! into a response of whatever HTTP server is used.
+
+#![cfg(feature = "server")]
+
+use bytes::Bytes;
+use log::debug;
+use crate::clock::SharedClock;
+use crate::crypto::{PublicKey, Signer};
+use crate::repository::options::SigningTimeWindow;
+use crate::repository::x509::Time;
+use crate::util::media::{self, MediaType};
+use super::idexchange::{RecipientHandle, SenderHandle};
+use super::provisioning::{self, PayloadType, ProvisioningCms};
+use super::publication::{
+    self, DecodeOptions, ErrorReply, PublicationCms, Query, Reply,
+    ReportError
+};
+use super::quirks::QuirkRegistry;
+use super::sigmsg::SignedMessage;
+#[cfg(feature = "trace")]
+use log::warn;
+#[cfg(feature = "trace")]
+use super::trace::{Protocol, TraceRecorder};
+
+
+//------------ Content Types -------------------------------------------------
+
+/// The content type of publication protocol messages.
+pub const PUBLICATION_CONTENT_TYPE: &str = media::RPKI_PUBLICATION;
+
+/// The content type of provisioning protocol messages.
+pub const PROVISIONING_CONTENT_TYPE: &str = media::RPKI_UPDOWN;
+
+
+//------------ PublicationServer ---------------------------------------------
+
+/// A type processing publication protocol queries.
+pub trait PublicationServer {
+    /// Returns the key of a publisher’s identity certificate.
+    ///
+    /// Returns `None` if the publisher is unknown.
+    fn publisher_key(&self, publisher: &str) -> Option<PublicKey>;
+
+    /// Processes a validated query of a publisher.
+    ///
+    /// Failures should be reported via an error reply.
+    fn handle_query(&mut self, publisher: &str, query: Query) -> Reply;
+}
+
+
+//------------ ProvisioningServer --------------------------------------------
+
+/// A type processing provisioning protocol queries.
+pub trait ProvisioningServer {
+    /// Returns the key of a child’s identity certificate.
+    ///
+    /// The `sender` is the child and the `recipient` the parent as given
+    /// in the message. Returns `None` if the combination is unknown.
+    fn child_key(
+        &self, sender: &SenderHandle, recipient: &RecipientHandle
+    ) -> Option<PublicKey>;
+
+    /// Processes a validated query message of a child.
+    ///
+    /// Returns the response message. Failures should be reported via a
+    /// not performed response.
+    fn handle_message(
+        &mut self, message: provisioning::Message
+    ) -> provisioning::Message;
+}
+
+
+//------------ PublicationEndpoint -------------------------------------------
+
+/// An HTTP endpoint for the publication protocol.
+///
+/// The endpoint wraps a [`PublicationServer`] and the key used for signing
+/// replies. Since the publication protocol messages don’t identify the
+/// publisher, it has to be determined from the request, typically from
+/// the last path segment of the request URI.
+#[derive(Clone, Debug)]
+pub struct PublicationEndpoint<H, S: Signer> {
+    /// The server processing the queries.
+    server: H,
+
+    /// The signer for replies.
+    signer: S,
+
+    /// The key used for signing replies.
+    key: S::KeyId,
+
+    /// The acceptable signing times of queries.
+    signing_time: SigningTimeWindow,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+
+    /// The quirks of the publishers, keyed by their handle.
+    quirks: QuirkRegistry,
+
+    /// The recorder for exchanges if tracing is enabled.
+    #[cfg(feature = "trace")]
+    trace: Option<TraceRecorder>,
+}
+
+impl<H: PublicationServer, S: Signer> PublicationEndpoint<H, S> {
+    /// Creates a new endpoint.
+    pub fn new(server: H, signer: S, key: S::KeyId) -> Self {
+        PublicationEndpoint {
+            server, signer, key,
+            signing_time: Default::default(),
+            clock: Default::default(),
+            quirks: Default::default(),
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Sets the acceptable signing times of queries.
+    pub fn set_signing_time(&mut self, window: SigningTimeWindow) {
+        self.signing_time = window
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// By default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Sets the quirks of publishers.
+    ///
+    /// Queries are decoded using the quirks registered for the handle of
+    /// the publisher.
+    pub fn set_quirks(&mut self, quirks: QuirkRegistry) {
+        self.quirks = quirks
+    }
+
+    /// Sets the recorder for exchanges.
+    ///
+    /// If a recorder is set, the bodies of all requests and responses are
+    /// recorded. Failing to record is logged but otherwise ignored.
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, trace: Option<TraceRecorder>) {
+        self.trace = trace
+    }
+
+    /// Returns a reference to the server.
+    pub fn server(&self) -> &H {
+        &self.server
+    }
+
+    /// Returns a mutable reference to the server.
+    pub fn server_mut(&mut self) -> &mut H {
+        &mut self.server
+    }
+
+    /// Processes a request of a publisher.
+    ///
+    /// This is the same as [`handle_at`][Self::handle_at] using the
+    /// current time according to the endpoint’s clock.
+    pub fn handle(
+        &mut self, publisher: &str, content_type: Option<&str>, body: &[u8]
+    ) -> Response {
+        self.handle_at(
+            publisher, content_type, body, Time::from_clock(&self.clock)
+        )
+    }
+
+    /// Processes a request of a publisher received at the given time.
+    pub fn handle_at(
+        &mut self,
+        publisher: &str,
+        content_type: Option<&str>,
+        body: &[u8],
+        now: Time,
+    ) -> Response {
+        let response = self.process(publisher, content_type, body, now);
+        #[cfg(feature = "trace")]
+        record(&self.trace, Protocol::Publication, body, &response);
+        response
+    }
+
+    /// Processes a request without recording it.
+    fn process(
+        &mut self,
+        publisher: &str,
+        content_type: Option<&str>,
+        body: &[u8],
+        now: Time,
+    ) -> Response {
+        if let Err(err) = check_content_type(
+            content_type, MediaType::Publication
+        ) {
+            return err
+        }
+        let key = match self.server.publisher_key(publisher) {
+            Some(key) => key,
+            None => {
+                return Response::forbidden(
+                    format_args!("unknown publisher '{}'", publisher)
+                )
+            }
+        };
+        let cms = match PublicationCms::decode_with(
+            body, &DecodeOptions::from_quirks(self.quirks.get(publisher))
+        ) {
+            Ok(cms) => cms,
+            Err(publication::Error::UnsupportedVersion(version)) => {
+                return self.unsupported_version(&key, body, &version, now)
+            }
+            Err(err) => return Response::bad_request(err),
+        };
+        if let Err(err) = cms.validate_with_signing_time_at(
+            &key, &self.signing_time, now
+        ) {
+            return Response::forbidden(err)
+        }
+        let query = match cms.into_message().as_query() {
+            Ok(query) => query,
+            Err(err) => return Response::bad_request(err),
+        };
+        let reply = self.server.handle_query(publisher, query);
+        let reply = match reply {
+            Reply::List(list) => publication::Message::list_reply(list),
+            Reply::Success => publication::Message::success(),
+            Reply::ErrorReply(err) => publication::Message::error(err),
+        };
+        self.reply(reply)
+    }
+
+    /// Responds to a query using an unsupported protocol version.
+    ///
+    /// If the query is correctly signed by the publisher, the response is
+    /// an error reply as described by
+    /// [`ReportError::unsupported_version`].
+    fn unsupported_version(
+        &self, key: &PublicKey, body: &[u8], version: &str, now: Time,
+    ) -> Response {
+        let signed = match SignedMessage::decode(body, false) {
+            Ok(signed) => signed,
+            Err(err) => return Response::bad_request(err),
+        };
+        if let Err(err) = signed.validate_with_signing_time_at(
+            key, &self.signing_time, now
+        ) {
+            return Response::forbidden(err)
+        }
+        debug!("RFC 8181: query with unsupported version '{}'", version);
+        self.reply(publication::Message::error(ErrorReply::for_error(
+            ReportError::unsupported_version(version)
+        )))
+    }
+
+    /// Signs a reply and creates the response.
+    fn reply(&self, reply: publication::Message) -> Response {
+        match PublicationCms::create(reply, &self.key, &self.signer) {
+            Ok(cms) => Response::ok(PUBLICATION_CONTENT_TYPE, cms.to_bytes()),
+            Err(err) => Response::internal_error(err),
+        }
+    }
+}
+
+
+//------------ ProvisioningEndpoint ------------------------------------------
+
+/// An HTTP endpoint for the provisioning protocol.
+///
+/// The endpoint wraps a [`ProvisioningServer`] and the key used for
+/// signing responses. The child is identified by the sender of the
+/// message.
+#[derive(Clone, Debug)]
+pub struct ProvisioningEndpoint<H, S: Signer> {
+    /// The server processing the queries.
+    server: H,
+
+    /// The signer for responses.
+    signer: S,
+
+    /// The key used for signing responses.
+    key: S::KeyId,
+
+    /// The acceptable signing times of queries.
+    signing_time: SigningTimeWindow,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+
+    /// The recorder for exchanges if tracing is enabled.
+    #[cfg(feature = "trace")]
+    trace: Option<TraceRecorder>,
+}
+
+impl<H: ProvisioningServer, S: Signer> ProvisioningEndpoint<H, S> {
+    /// Creates a new endpoint.
+    pub fn new(server: H, signer: S, key: S::KeyId) -> Self {
+        ProvisioningEndpoint {
+            server, signer, key,
+            signing_time: Default::default(),
+            clock: Default::default(),
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Sets the acceptable signing times of queries.
+    pub fn set_signing_time(&mut self, window: SigningTimeWindow) {
+        self.signing_time = window
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// By default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Sets the recorder for exchanges.
+    ///
+    /// If a recorder is set, the bodies of all requests and responses are
+    /// recorded. Failing to record is logged but otherwise ignored.
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, trace: Option<TraceRecorder>) {
+        self.trace = trace
+    }
+
+    /// Returns a reference to the server.
+    pub fn server(&self) -> &H {
+        &self.server
+    }
+
+    /// Returns a mutable reference to the server.
+    pub fn server_mut(&mut self) -> &mut H {
+        &mut self.server
+    }
+
+    /// Processes a request.
+    ///
+    /// This is the same as [`handle_at`][Self::handle_at] using the
+    /// current time according to the endpoint’s clock.
+    pub fn handle(
+        &mut self, content_type: Option<&str>, body: &[u8]
+    ) -> Response {
+        self.handle_at(content_type, body, Time::from_clock(&self.clock))
+    }
+
+    /// Processes a request received at the given time.
+    pub fn handle_at(
+        &mut self, content_type: Option<&str>, body: &[u8], now: Time,
+    ) -> Response {
+        let response = self.process(content_type, body, now);
+        #[cfg(feature = "trace")]
+        record(&self.trace, Protocol::Provisioning, body, &response);
+        response
+    }
+
+    /// Processes a request without recording it.
+    fn process(
+        &mut self, content_type: Option<&str>, body: &[u8], now: Time,
+    ) -> Response {
+        if let Err(err) = check_content_type(
+            content_type, MediaType::Provisioning
+        ) {
+            return err
+        }
+        let cms = match ProvisioningCms::decode(body) {
+            Ok(cms) => cms,
+            Err(provisioning::Error::UnsupportedVersion(_)) => {
+                return self.unsupported_version(body, now)
+            }
+            Err(err) => return Response::bad_request(err),
+        };
+        let message = cms.message();
+        let key = match self.server.child_key(
+            message.sender(), message.recipient()
+        ) {
+            Some(key) => key,
+            None => {
+                return Response::forbidden(format_args!(
+                    "unknown child '{}' of '{}'",
+                    message.sender(), message.recipient()
+                ))
+            }
+        };
+        if let Err(err) = cms.validate_with_signing_time_at(
+            &key, &self.signing_time, now
+        ) {
+            return Response::forbidden(err)
+        }
+        let message = cms.into_message();
+        if !matches!(
+            message.payload().payload_type(),
+            PayloadType::List | PayloadType::Issue | PayloadType::Revoke
+        ) {
+            return Response::bad_request("not a query")
+        }
+        let response = self.server.handle_message(message);
+        self.reply(response)
+    }
+
+    /// Responds to a query using an unsupported protocol version.
+    ///
+    /// If the query is correctly signed by the child, the response is the
+    /// not performed response for version errors.
+    fn unsupported_version(&self, body: &[u8], now: Time) -> Response {
+        let signed = match SignedMessage::decode(body, false) {
+            Ok(signed) => signed,
+            Err(err) => return Response::bad_request(err),
+        };
+        let header = match provisioning::Message::decode_header(
+            signed.content().to_bytes().as_ref()
+        ) {
+            Ok(header) => header,
+            Err(err) => return Response::bad_request(err),
+        };
+        let key = match self.server.child_key(
+            header.sender(), header.recipient()
+        ) {
+            Some(key) => key,
+            None => {
+                return Response::forbidden(format_args!(
+                    "unknown child '{}' of '{}'",
+                    header.sender(), header.recipient()
+                ))
+            }
+        };
+        if let Err(err) = signed.validate_with_signing_time_at(
+            &key, &self.signing_time, now
+        ) {
+            return Response::forbidden(err)
+        }
+        debug!(
+            "RFC 6492: query with unsupported version '{}'",
+            header.version().unwrap_or("")
+        );
+        self.reply(header.version_error())
+    }
+
+    /// Signs a response message and creates the response.
+    fn reply(&self, response: provisioning::Message) -> Response {
+        match ProvisioningCms::create(response, &self.key, &self.signer) {
+            Ok(cms) => {
+                Response::ok(PROVISIONING_CONTENT_TYPE, cms.to_bytes())
+            }
+            Err(err) => Response::internal_error(err),
+        }
+    }
+}
+
+
+//------------ Response ------------------------------------------------------
+
+/// The HTTP response to a protocol request.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Response {
+    /// The HTTP status code.
+    status: u16,
+
+    /// The content type of the body.
+    content_type: &'static str,
+
+    /// The body.
+    body: Bytes,
+}
+
+impl Response {
+    /// Creates a successful response with a signed message.
+    fn ok(content_type: &'static str, body: Bytes) -> Self {
+        Response { status: 200, content_type, body }
+    }
+
+    /// Creates a plain text error response.
+    fn error(status: u16, msg: impl std::fmt::Display) -> Self {
+        let msg = msg.to_string();
+        debug!("protocol server: responding {}: {}", status, msg);
+        Response {
+            status,
+            content_type: "text/plain",
+            body: msg.into(),
+        }
+    }
+
+    fn bad_request(msg: impl std::fmt::Display) -> Self {
+        Self::error(400, msg)
+    }
+
+    fn forbidden(msg: impl std::fmt::Display) -> Self {
+        Self::error(403, msg)
+    }
+
+    fn internal_error(msg: impl std::fmt::Display) -> Self {
+        Self::error(500, msg)
+    }
+
+    /// Returns the HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns whether the request was processed.
+    ///
+    /// Note that the reply may still report an error at the protocol
+    /// level.
+    pub fn is_success(&self) -> bool {
+        self.status == 200
+    }
+
+    /// Returns the value of the Content-Type header.
+    pub fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+
+    /// Returns the body.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Converts the response into the body.
+    pub fn into_body(self) -> Bytes {
+        self.body
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Checks that the content type of a request is the expected one.
+///
+/// Parameters of the content type are ignored.
+fn check_content_type(
+    content_type: Option<&str>, expected: MediaType
+) -> Result<(), Response> {
+    media::check_content_type(content_type, expected).map_err(|err| {
+        Response::error(415, err)
+    })
+}
+
+/// Records an exchange if there is a recorder.
+#[cfg(feature = "trace")]
+fn record(
+    trace: &Option<TraceRecorder>,
+    protocol: Protocol,
+    request: &[u8],
+    response: &Response,
+) {
+    if let Some(trace) = trace {
+        if let Err(err) = trace.record(protocol, request, response.body()) {
+            warn!(
+                "failed to record {} exchange in {}: {}",
+                protocol, trace.dir().display(), err
+            );
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use crate::crypto::PublicKeyFormat;
+    use crate::crypto::softsigner::{KeyId, OpenSslSigner};
+    use super::*;
+    use super::provisioning::ResourceClassListResponse;
+    use super::publication::{ListReply, Message};
+
+    struct TestPublication(PublicKey);
+
+    impl PublicationServer for TestPublication {
+        fn publisher_key(&self, publisher: &str) -> Option<PublicKey> {
+            if publisher == "alice" {
+                Some(self.0.clone())
+            }
+            else {
+                None
+            }
+        }
+
+        fn handle_query(&mut self, _publisher: &str, query: Query) -> Reply {
+            match query {
+                Query::List => Reply::List(ListReply::default()),
+                Query::Delta(_) => Reply::Success,
+            }
+        }
+    }
+
+    fn key(signer: &OpenSslSigner) -> (KeyId, PublicKey) {
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let public = signer.get_key_info(&key).unwrap();
+        (key, public)
+    }
+
+    #[test]
+    fn publication() {
+        let signer = OpenSslSigner::new();
+        let server_signer = OpenSslSigner::new();
+        let (client_key, client_public) = key(&signer);
+        let (server_key, server_public) = key(&server_signer);
+        let mut endpoint = PublicationEndpoint::new(
+            TestPublication(client_public), server_signer, server_key
+        );
+
+        let query = PublicationCms::create(
+            Message::list_query(), &client_key, &signer
+        ).unwrap().to_bytes();
+
+        let response = endpoint.handle(
+            "alice", Some("application/rpki-publication"), &query
+        );
+        assert!(response.is_success());
+        assert_eq!(response.content_type(), PUBLICATION_CONTENT_TYPE);
+        let reply = PublicationCms::decode(response.body()).unwrap();
+        reply.validate(&server_public).unwrap();
+        assert_eq!(
+            reply.into_message(), Message::list_reply(ListReply::default())
+        );
+
+        assert_eq!(
+            endpoint.handle("bob", Some(PUBLICATION_CONTENT_TYPE), &query)
+                .status(),
+            403
+        );
+        assert_eq!(
+            endpoint.handle("alice", Some("text/plain"), &query).status(),
+            415
+        );
+        assert_eq!(
+            endpoint.handle(
+                "alice", Some(PUBLICATION_CONTENT_TYPE), b"foo"
+            ).status(),
+            400
+        );
+
+        // Signed by the wrong key.
+        let query = PublicationCms::create(
+            Message::list_query(), &key(&signer).0, &signer
+        ).unwrap().to_bytes();
+        assert_eq!(
+            endpoint.handle("alice", Some(PUBLICATION_CONTENT_TYPE), &query)
+                .status(),
+            403
+        );
+    }
+
+    #[test]
+    fn publication_unsupported_version() {
+        use bytes::Bytes;
+        use crate::repository::x509::Validity;
+        use super::publication::ReportErrorCode;
+
+        let signer = OpenSslSigner::new();
+        let server_signer = OpenSslSigner::new();
+        let (client_key, client_public) = key(&signer);
+        let (server_key, _) = key(&server_signer);
+        let mut endpoint = PublicationEndpoint::new(
+            TestPublication(client_public), server_signer, server_key
+        );
+
+        let query = SignedMessage::create(
+            Bytes::from_static(
+                b"<msg \
+                    xmlns=\"http://www.hactrn.net/uris/rpki/publication-spec/\" \
+                    version=\"5\" type=\"query\"><list/></msg>"
+            ),
+            Validity::new(
+                Time::five_minutes_ago(), Time::five_minutes_from_now()
+            ),
+            &client_key, &signer
+        ).unwrap().to_captured().into_bytes();
+        let response = endpoint.handle(
+            "alice", Some(PUBLICATION_CONTENT_TYPE), &query
+        );
+        assert!(response.is_success());
+        let reply = PublicationCms::decode(response.body()).unwrap();
+        match reply.into_message() {
+            Message::Reply(Reply::ErrorReply(reply)) => {
+                assert_eq!(
+                    reply.errors()[0].error_code(),
+                    &ReportErrorCode::XmlError
+                );
+            }
+            _ => panic!("expected error reply")
+        }
+
+        // Signed by the wrong key.
+        let query = SignedMessage::create(
+            Bytes::from_static(b"<msg version=\"5\" type=\"query\"/>"),
+            Validity::new(
+                Time::five_minutes_ago(), Time::five_minutes_from_now()
+            ),
+            &key(&signer).0, &signer
+        ).unwrap().to_captured().into_bytes();
+        assert_eq!(
+            endpoint.handle("alice", Some(PUBLICATION_CONTENT_TYPE), &query)
+                .status(),
+            403
+        );
+    }
+
+    struct TestProvisioning(PublicKey);
+
+    impl ProvisioningServer for TestProvisioning {
+        fn child_key(
+            &self, sender: &SenderHandle, _recipient: &RecipientHandle
+        ) -> Option<PublicKey> {
+            if sender.as_str() == "child" {
+                Some(self.0.clone())
+            }
+            else {
+                None
+            }
+        }
+
+        fn handle_message(
+            &mut self, message: provisioning::Message
+        ) -> provisioning::Message {
+            let (sender, recipient, _) = message.unpack();
+            provisioning::Message::list_response(
+                recipient.convert(), sender.convert(),
+                ResourceClassListResponse::new(Vec::new())
+            )
+        }
+    }
+
+    #[test]
+    fn provisioning() {
+        let signer = OpenSslSigner::new();
+        let server_signer = OpenSslSigner::new();
+        let (client_key, client_public) = key(&signer);
+        let (server_key, server_public) = key(&server_signer);
+        let mut endpoint = ProvisioningEndpoint::new(
+            TestProvisioning(client_public), server_signer, server_key
+        );
+
+        let query = ProvisioningCms::create(
+            provisioning::Message::list(
+                "child".parse().unwrap(), "parent".parse().unwrap()
+            ),
+            &client_key, &signer
+        ).unwrap().to_bytes();
+        let response = endpoint.handle(
+            Some(PROVISIONING_CONTENT_TYPE), &query
+        );
+        assert!(response.is_success());
+        let reply = ProvisioningCms::decode(response.body()).unwrap();
+        reply.validate(&server_public).unwrap();
+        assert!(reply.message().is_list_response());
+        assert_eq!(reply.message().recipient().as_str(), "child");
+
+        let query = ProvisioningCms::create(
+            provisioning::Message::list(
+                "other".parse().unwrap(), "parent".parse().unwrap()
+            ),
+            &client_key, &signer
+        ).unwrap().to_bytes();
+        assert_eq!(
+            endpoint.handle(Some(PROVISIONING_CONTENT_TYPE), &query).status(),
+            403
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn trace() {
+        use crate::ca::trace::{Decoded, Trace};
+
+        let signer = OpenSslSigner::new();
+        let server_signer = OpenSslSigner::new();
+        let (client_key, client_public) = key(&signer);
+        let (server_key, _) = key(&server_signer);
+        let mut endpoint = PublicationEndpoint::new(
+            TestPublication(client_public), server_signer, server_key
+        );
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-server-trace-test-{}", std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        endpoint.set_trace(Some(TraceRecorder::new(&dir).unwrap()));
+
+        let query = PublicationCms::create(
+            Message::list_query(), &client_key, &signer
+        ).unwrap().to_bytes();
+        assert!(endpoint.handle(
+            "alice", Some(PUBLICATION_CONTENT_TYPE), &query
+        ).is_success());
+
+        let trace = Trace::open(&dir).unwrap();
+        let replay: Vec<_> = trace.replay().map(|item| {
+            item.1.unwrap()
+        }).collect();
+        assert_eq!(
+            replay,
+            [
+                Decoded::Publication(Message::list_query()),
+                Decoded::Publication(
+                    Message::list_reply(ListReply::default())
+                ),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn provisioning_unsupported_version() {
+        use bytes::Bytes;
+        use crate::repository::x509::Validity;
+
+        let signer = OpenSslSigner::new();
+        let server_signer = OpenSslSigner::new();
+        let (client_key, client_public) = key(&signer);
+        let (server_key, _) = key(&server_signer);
+        let mut endpoint = ProvisioningEndpoint::new(
+            TestProvisioning(client_public), server_signer, server_key
+        );
+
+        let query = SignedMessage::create(
+            Bytes::from_static(
+                b"<message \
+                    xmlns=\"http://www.apnic.net/specs/rescerts/up-down/\" \
+                    version=\"2\" sender=\"child\" recipient=\"parent\" \
+                    type=\"list\"/>"
+            ),
+            Validity::new(
+                Time::five_minutes_ago(), Time::five_minutes_from_now()
+            ),
+            &client_key, &signer
+        ).unwrap().to_captured().into_bytes();
+        let response = endpoint.handle(
+            Some(PROVISIONING_CONTENT_TYPE), &query
+        );
+        assert!(response.is_success());
+        let reply = ProvisioningCms::decode(response.body()).unwrap();
+        assert_eq!(reply.message().sender().as_str(), "parent");
+        assert_eq!(reply.message().recipient().as_str(), "child");
+        match reply.message().payload() {
+            provisioning::Payload::ErrorResponse(err) => {
+                assert_eq!(err.status(), 1102)
+            }
+            _ => panic!("expected error response")
+        }
+    }
+
+    #[test]
+    fn content_type() {
+        assert!(check_content_type(
+            Some("application/rpki-updown; charset=utf-8"),
+            MediaType::Provisioning
+        ).is_ok());
+        assert!(
+            check_content_type(None, MediaType::Provisioning).is_err()
+        );
+    }
+}
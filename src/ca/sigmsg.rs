@@ -6,14 +6,17 @@ use bcder::{Mode, Oid, OctetString, Tag};
 use bcder::decode::{DecodeError, IntoSource, Source};
 use bcder::encode::PrimitiveContent;
 use bytes::Bytes;
+use log::debug;
 use crate::oid;
 use crate::crypto::{
-    DigestAlgorithm, KeyIdentifier, RpkiSignature, RpkiSignatureAlgorithm,
-    SignatureAlgorithm, Signer, SigningError, PublicKey
+    AlgorithmSuite, DigestAlgorithm, KeyIdentifier, RpkiSignature,
+    RpkiSignatureAlgorithm, SignatureAlgorithm, Signer, SigningError,
+    PublicKey
 };
 use crate::repository::error::{
     InspectionError, ValidationError, VerificationError
 };
+use crate::repository::options::{SigningTimeError, SigningTimeWindow};
 use crate::repository::sigobj::{MessageDigest, SignedAttrs};
 use crate::repository::x509::{
     Name, Serial, SignedData, Time, Validity, encode_extension,
@@ -60,6 +63,8 @@ pub struct  SignedMessage {
     //--- SignedAttributes
     //
     message_digest: MessageDigest,
+    signing_time: Option<Time>,
+    binary_signing_time: Option<u64>,
 }
 
 /// # Data Access
@@ -74,6 +79,34 @@ impl SignedMessage {
     pub fn content(&self) -> &OctetString {
         &self.content
     }
+
+    /// Returns a reference to the EE certificate of the message.
+    ///
+    /// A message by itself doesn’t reveal whether its EE certificate was
+    /// minted just for it or is reused for many messages. Receivers that
+    /// need to know can compare the certificate’s subject key identifier
+    /// with that of previous messages or with known long-lived EE
+    /// certificates via [`uses_ee_cert`][Self::uses_ee_cert].
+    pub fn ee_cert(&self) -> &IdCert {
+        &self.ee_cert
+    }
+
+    /// Returns whether the message was signed with the given EE certificate.
+    pub fn uses_ee_cert(&self, cert: &IdCert) -> bool {
+        self.sid == cert.subject_key_identifier()
+            && self.ee_cert.subject_public_key_info()
+                == cert.subject_public_key_info()
+    }
+
+    /// Returns the signing time if available.
+    pub fn signing_time(&self) -> Option<Time> {
+        self.signing_time
+    }
+
+    /// Returns the binary signing time if available.
+    pub fn binary_signing_time(&self) -> Option<u64> {
+        self.binary_signing_time
+    }
 }
 
 
@@ -172,6 +205,8 @@ impl SignedMessage {
                 signature,
 
                 message_digest: attrs.1,
+                signing_time: attrs.3,
+                binary_signing_time: attrs.4,
             })
         })
     }
@@ -226,6 +261,39 @@ impl SignedMessage {
     /// Validates a signed message for a given point in time.
     pub fn validate_at(
         &self, issuer_key: &PublicKey, when: Time
+    ) -> Result<(), ValidationError> {
+        self.validate_checks(issuer_key, when).map_err(|err| {
+            debug!("signed message validation failed: {}", err);
+            err
+        })
+    }
+
+    /// Validates a signed message including its signing time.
+    ///
+    /// In addition to [`validate_at`][Self::validate_at], this checks that
+    /// the signing time of the message is within `window` around `when`.
+    /// Use [`ValidationError::signing_time_error`] to find out whether
+    /// validation failed because of the signing time.
+    pub fn validate_with_signing_time_at(
+        &self, issuer_key: &PublicKey, window: &SigningTimeWindow, when: Time
+    ) -> Result<(), ValidationError> {
+        self.check_signing_time(window, when).map_err(|err| {
+            debug!("signed message validation failed: {}", err);
+            err
+        })?;
+        self.validate_at(issuer_key, when)
+    }
+
+    /// Checks that the signing time of the message is within `window`.
+    pub fn check_signing_time(
+        &self, window: &SigningTimeWindow, when: Time
+    ) -> Result<(), SigningTimeError> {
+        window.check(self.signing_time, self.binary_signing_time, when)
+    }
+
+    /// Performs the actual checks for validation.
+    fn validate_checks(
+        &self, issuer_key: &PublicKey, when: Time
     ) -> Result<(), ValidationError> {
         self.inspect()?;
         self.verify()?;
@@ -280,20 +348,45 @@ impl SignedMessage {
 /// 
 impl SignedMessage {
     /// Create a new signed message under the given TA IdCert.
+    ///
+    /// This mints a one-off EE certificate for the message. Use
+    /// [`create_with_policy`][Self::create_with_policy] to reuse an
+    /// existing EE certificate instead.
     pub fn create<S: Signer>(
         data: Bytes,
         validity: Validity,
         issuing_key_id: &S::KeyId,
         signer: &S,
+    ) -> Result<Self, SigningError<S::Error>> {
+        Self::create_with_policy(
+            data, validity, issuing_key_id, &EeCertPolicy::OneOff,
+            AlgorithmSuite::default(), signer
+        )
+    }
+
+    /// Create a new signed message using the given EE certificate policy.
+    ///
+    /// The `validity` is used for the CRL and, if the policy is
+    /// [`EeCertPolicy::OneOff`], for the EE certificate. The CRL is always
+    /// issued anew under the key identified by `issuing_key_id`. The
+    /// message and the CRL are signed and digested using the algorithms of
+    /// `suite`.
+    pub fn create_with_policy<S: Signer>(
+        data: Bytes,
+        validity: Validity,
+        issuing_key_id: &S::KeyId,
+        policy: &EeCertPolicy<S::KeyId>,
+        suite: AlgorithmSuite,
+        signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
         // Steps:
         // - create content to sign
-        // - sign content with one off key
-        // - create and sign EE cert with one off key as subject
+        // - sign content with one off key or the reused EE key
+        // - create and sign EE cert with one off key as subject, if needed
         // - create and sign new CRL
         // - include EE cert
 
-        let digest_algorithm = DigestAlgorithm::default();
+        let digest_algorithm = suite.digest_algorithm();
         let content_type = Oid(oid::PROTOCOL_CONTENT_TYPE.0.into());
         
         // Produce signed attributes
@@ -307,25 +400,39 @@ impl SignedMessage {
             signing_time,
             binary_signing_time
         );
-        
-        let (signature, ee_key) = signer.sign_one_off(
-            RpkiSignatureAlgorithm::default(), &signed_attrs.encode_verify()
-        )?;
-        let sid = ee_key.key_identifier();
+
+        let (signature, ee_cert) = match *policy {
+            EeCertPolicy::OneOff => {
+                let (signature, ee_key) = signer.sign_one_off(
+                    suite.signature_algorithm(),
+                    &signed_attrs.encode_verify()
+                )?;
+                let ee_cert = IdCert::new_ee(
+                    &ee_key,
+                    validity,
+                    issuing_key_id,
+                    signer
+                )?;
+                (signature, ee_cert)
+            }
+            EeCertPolicy::Reuse { ref key, ref cert } => {
+                let signature = signer.sign(
+                    key,
+                    suite.signature_algorithm(),
+                    &signed_attrs.encode_verify()
+                )?;
+                (signature, cert.clone())
+            }
+        };
+        let sid = ee_cert.subject_key_identifier();
         
         let crl = SignedMessageCrl::create(
             &validity,
             issuing_key_id,
+            suite.signature_algorithm(),
             signer
         )?;
 
-        let ee_cert = IdCert::new_ee(
-            &ee_key,
-            validity,
-            issuing_key_id,
-            signer
-        )?;
-        
         let content = OctetString::new(data);
 
         Ok(SignedMessage {
@@ -338,6 +445,8 @@ impl SignedMessage {
             signed_attrs,
             signature,
             message_digest,
+            signing_time,
+            binary_signing_time,
         })
     }
 
@@ -387,6 +496,45 @@ impl SignedMessage {
 
 }
 
+//------------ EeCertPolicy --------------------------------------------------
+
+/// The choice of EE certificate when creating a signed message.
+///
+/// RFC 6492 and RFC 8181 don’t prescribe how the EE certificate of a
+/// message is to be managed, and both approaches are in use: some CAs
+/// create a new key and certificate for each message while others keep a
+/// long-lived EE certificate issued under their identity certificate.
+/// Receivers accept either.
+#[derive(Clone, Debug, Default)]
+#[allow(clippy::large_enum_variant)]
+pub enum EeCertPolicy<K> {
+    /// A one-off key and EE certificate is created for each message.
+    ///
+    /// This is the default.
+    #[default]
+    OneOff,
+
+    /// The given EE certificate is reused.
+    ///
+    /// The message is signed with the key identified by `key`, which needs
+    /// to be the subject key of `cert`.
+    Reuse {
+        /// The signer’s identifier for the EE certificate’s private key.
+        key: K,
+
+        /// The EE certificate to include in messages.
+        cert: IdCert,
+    },
+}
+
+impl<K> EeCertPolicy<K> {
+    /// Returns whether the policy creates one-off EE certificates.
+    pub fn is_one_off(&self) -> bool {
+        matches!(*self, EeCertPolicy::OneOff)
+    }
+}
+
+
 //------------ SignedMessageCrl ----------------------------------------------
 
 /// A CRL used in RFC6492 and RFC8181 CMS.
@@ -476,11 +624,11 @@ impl SignedMessageCrl {
     fn create<S: Signer>(
         validity: &Validity,
         issuing_key_id: &S::KeyId,
+        signature: RpkiSignatureAlgorithm,
         signer: &S,
     ) -> Result<Self, SigningError<S::Error>> {
         let issuing_pub_key = signer.get_key_info(issuing_key_id)?;
         
-        let signature = RpkiSignatureAlgorithm::default();
         let issuer = Name::from_pub_key(&issuing_pub_key);
         
         let this_update = validity.not_before();
@@ -869,6 +1017,42 @@ mod tests {
         ).unwrap();
     }
 
+    #[test]
+    fn validate_signing_time() {
+        use std::time::Duration;
+
+        let der = include_bytes!("../../test-data/ca/sigmsg/pdu_200.der");
+        let msg = SignedMessage::decode(Bytes::from_static(der), false).unwrap();
+        let b = include_bytes!("../../test-data/ca/sigmsg/cms_ta.cer");
+        let id_cert = IdCert::decode(Bytes::from_static(b)).unwrap();
+        let now = Time::utc(2012, 1, 1, 0, 0, 0);
+        let signing_time = Time::utc(2011, 7, 1, 4, 9, 5);
+        assert_eq!(msg.signing_time(), Some(signing_time));
+
+        msg.validate_with_signing_time_at(
+            id_cert.public_key(), &SigningTimeWindow::default(), now
+        ).unwrap();
+        msg.validate_with_signing_time_at(
+            id_cert.public_key(),
+            &SigningTimeWindow::new(
+                Duration::from_secs(365 * 86400), Duration::from_secs(60)
+            ),
+            now
+        ).unwrap();
+
+        let err = msg.validate_with_signing_time_at(
+            id_cert.public_key(),
+            &SigningTimeWindow::new(
+                Duration::from_secs(86400), Duration::from_secs(60)
+            ),
+            now
+        ).unwrap_err();
+        assert_eq!(
+            err.signing_time_error(),
+            Some(SigningTimeError::TooOld(signing_time))
+        );
+    }
+
 }
 
 #[cfg(all(test, feature="softkeys"))]
@@ -912,4 +1096,55 @@ mod signer_test {
         // Validate it
         decoded.validate(ta_cert.public_key()).unwrap();
     }
+
+    #[test]
+    fn sign_with_reused_ee_cert() {
+        let signer = OpenSslSigner::new();
+
+        let ta_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let ta_cert = IdCert::new_ta(
+            Validity::from_secs(60),
+            &ta_key,
+            &signer
+        ).unwrap();
+
+        let ee_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let ee_cert = IdCert::new_ee(
+            &signer.get_key_info(&ee_key).unwrap(),
+            Validity::from_secs(3600),
+            &ta_key,
+            &signer
+        ).unwrap();
+        let policy = EeCertPolicy::Reuse { key: ee_key, cert: ee_cert };
+
+        let mut sids = Vec::new();
+        for _ in 0..2 {
+            let msg = SignedMessage::create_with_policy(
+                Bytes::from_static(b"euj"),
+                Validity::from_secs(60),
+                &ta_key,
+                &policy,
+                AlgorithmSuite::default(),
+                &signer
+            ).unwrap();
+            let decoded = SignedMessage::decode(
+                msg.to_captured().into_bytes(), false
+            ).unwrap();
+            decoded.validate(ta_cert.public_key()).unwrap();
+            if let EeCertPolicy::Reuse { ref cert, .. } = policy {
+                assert!(decoded.uses_ee_cert(cert));
+            }
+            sids.push(decoded.ee_cert().subject_key_identifier());
+        }
+        assert_eq!(sids[0], sids[1]);
+
+        let one_off = SignedMessage::create(
+            Bytes::from_static(b"euj"),
+            Validity::from_secs(60),
+            &ta_key,
+            &signer
+        ).unwrap();
+        one_off.validate(ta_cert.public_key()).unwrap();
+        assert_ne!(one_off.ee_cert().subject_key_identifier(), sids[0]);
+    }
 }
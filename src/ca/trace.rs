@@ -0,0 +1,457 @@
+//! Capturing and replaying protocol exchanges.
+//!
+//! Interoperability problems between implementations of the publication
+//! and provisioning protocols are often hard to reproduce: they depend on
+//! the exact bytes one side sent. This module allows recording the raw
+//! bodies of requests and responses to a directory via a
+//! [`TraceRecorder`] and later reading them back and running them through
+//! the decoders of this crate via a [`Trace`]. A trace directory can be
+//! attached to a bug report.
+//!
+//! Each body is stored in a file of its own named after the sequence
+//! number of the exchange, the protocol, and the direction, e.g.,
+//! `000001-publication-request.der`. The file contains the body exactly
+//! as it was sent, which normally is a CMS signed message. Bodies that
+//! contain plain XML are accepted during replay, too, so that messages
+//! can be added to a trace by hand.
+//!
+//! Note that the CMS messages are only decoded during replay, they are not
+//! validated since the identity certificates of the parties normally
+//! won’t be part of the trace.
+
+#![cfg(feature = "trace")]
+
+use std::{error, fmt, fs, io};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use bytes::Bytes;
+use super::provisioning::{self, ProvisioningCms};
+use super::publication::{self, PublicationCms};
+
+
+//------------ Protocol ------------------------------------------------------
+
+/// The protocol of an exchange.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Protocol {
+    /// The publication protocol of RFC 8181.
+    Publication,
+
+    /// The provisioning protocol of RFC 6492.
+    Provisioning,
+}
+
+impl Protocol {
+    /// Returns the name of the protocol as used in file names.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Publication => "publication",
+            Protocol::Provisioning => "provisioning",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = UnknownName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "publication" => Ok(Protocol::Publication),
+            "provisioning" => Ok(Protocol::Provisioning),
+            _ => Err(UnknownName),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ Direction -----------------------------------------------------
+
+/// The direction of a message within an exchange.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Direction {
+    /// The request sent by the client.
+    Request,
+
+    /// The response sent by the server.
+    Response,
+}
+
+impl Direction {
+    /// Returns the name of the direction as used in file names.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Response => "response",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = UnknownName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "request" => Ok(Direction::Request),
+            "response" => Ok(Direction::Response),
+            _ => Err(UnknownName),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ TraceRecorder -------------------------------------------------
+
+/// Records protocol exchanges to a directory.
+///
+/// Clones of a value share the same directory and sequence numbers, so a
+/// recorder can be given to several endpoints.
+#[derive(Clone, Debug)]
+pub struct TraceRecorder(Arc<RecorderInner>);
+
+#[derive(Debug)]
+struct RecorderInner {
+    /// The directory to record to.
+    dir: PathBuf,
+
+    /// The sequence number of the next exchange.
+    next: AtomicU64,
+}
+
+impl TraceRecorder {
+    /// Creates a new recorder for the given directory.
+    ///
+    /// The directory is created if necessary. If it already contains a
+    /// trace, new exchanges are appended to it.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let next = Trace::open(&dir)?.entries.last().map(|entry| {
+            entry.seq + 1
+        }).unwrap_or(1);
+        Ok(TraceRecorder(Arc::new(RecorderInner {
+            dir,
+            next: AtomicU64::new(next),
+        })))
+    }
+
+    /// Returns the directory the recorder records to.
+    pub fn dir(&self) -> &Path {
+        &self.0.dir
+    }
+
+    /// Records an exchange.
+    ///
+    /// Returns the sequence number of the exchange.
+    pub fn record(
+        &self, protocol: Protocol, request: &[u8], response: &[u8]
+    ) -> Result<u64, io::Error> {
+        let seq = self.0.next.fetch_add(1, Ordering::Relaxed);
+        for (direction, body) in [
+            (Direction::Request, request), (Direction::Response, response)
+        ] {
+            fs::write(
+                self.0.dir.join(file_name(seq, protocol, direction)), body
+            )?;
+        }
+        Ok(seq)
+    }
+}
+
+
+//------------ Trace ---------------------------------------------------------
+
+/// A recorded trace.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// The entries of the trace, ordered by sequence number.
+    entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    /// Opens the trace in the given directory.
+    ///
+    /// Files in the directory that don’t look like trace entries are
+    /// ignored.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let mut entries = Vec::new();
+        for item in fs::read_dir(dir)? {
+            let item = item?;
+            if let Some(entry) = TraceEntry::from_path(item.path()) {
+                entries.push(entry)
+            }
+        }
+        entries.sort_by_key(|entry| (entry.seq, entry.direction));
+        Ok(Trace { entries })
+    }
+
+    /// Returns the entries of the trace.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    /// Returns whether the trace is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of entries in the trace.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns an iterator replaying all entries of the trace.
+    pub fn replay(
+        &self
+    ) -> impl Iterator<Item = (&TraceEntry, Result<Decoded, ReplayError>)> {
+        self.entries.iter().map(|entry| (entry, entry.decode()))
+    }
+}
+
+
+//------------ TraceEntry ----------------------------------------------------
+
+/// A single recorded message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceEntry {
+    /// The sequence number of the exchange.
+    seq: u64,
+
+    /// The protocol of the exchange.
+    protocol: Protocol,
+
+    /// The direction of the message.
+    direction: Direction,
+
+    /// The path of the file containing the message.
+    path: PathBuf,
+}
+
+impl TraceEntry {
+    /// Creates an entry from a path if it is a trace file.
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let stem = path.file_name()?.to_str()?.strip_suffix(".der")?;
+        let mut parts = stem.splitn(3, '-');
+        let seq = parts.next()?.parse().ok()?;
+        let protocol = parts.next()?.parse().ok()?;
+        let direction = parts.next()?.parse().ok()?;
+        Some(TraceEntry { seq, protocol, direction, path })
+    }
+
+    /// Returns the sequence number of the exchange.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the protocol of the exchange.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns the direction of the message.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the path of the file containing the message.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the raw body of the message.
+    pub fn read(&self) -> Result<Bytes, io::Error> {
+        fs::read(&self.path).map(Into::into)
+    }
+
+    /// Reads and decodes the message.
+    pub fn decode(&self) -> Result<Decoded, ReplayError> {
+        decode(self.protocol, &self.read()?)
+    }
+}
+
+
+//------------ Decoded -------------------------------------------------------
+
+/// A decoded message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Decoded {
+    /// A publication protocol message.
+    Publication(publication::Message),
+
+    /// A provisioning protocol message.
+    Provisioning(provisioning::Message),
+}
+
+
+//------------ decode --------------------------------------------------------
+
+/// Decodes the body of a message of the given protocol.
+///
+/// The body can either be a CMS signed message or the plain XML message.
+/// The CMS is not validated.
+pub fn decode(
+    protocol: Protocol, body: &[u8]
+) -> Result<Decoded, ReplayError> {
+    let xml = body.iter().find(|ch| !ch.is_ascii_whitespace()) == Some(&b'<');
+    match protocol {
+        Protocol::Publication => {
+            let msg = if xml {
+                publication::Message::decode(body)
+            }
+            else {
+                PublicationCms::decode(body).map(|cms| cms.into_message())
+            };
+            msg.map(Decoded::Publication).map_err(ReplayError::Publication)
+        }
+        Protocol::Provisioning => {
+            let msg = if xml {
+                provisioning::Message::decode(body)
+            }
+            else {
+                ProvisioningCms::decode(body).map(|cms| cms.into_message())
+            };
+            msg.map(Decoded::Provisioning).map_err(ReplayError::Provisioning)
+        }
+    }
+}
+
+/// Returns the file name for a message.
+fn file_name(seq: u64, protocol: Protocol, direction: Direction) -> String {
+    format!("{:06}-{}-{}.der", seq, protocol, direction)
+}
+
+
+//------------ UnknownName ---------------------------------------------------
+
+/// A protocol or direction name was not recognised.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnknownName;
+
+impl fmt::Display for UnknownName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("unknown name")
+    }
+}
+
+impl error::Error for UnknownName { }
+
+
+//------------ ReplayError ---------------------------------------------------
+
+/// An error happened while replaying a message.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// Reading the message failed.
+    Io(io::Error),
+
+    /// Decoding a publication message failed.
+    Publication(publication::Error),
+
+    /// Decoding a provisioning message failed.
+    Provisioning(provisioning::Error),
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => err.fmt(f),
+            ReplayError::Publication(err) => err.fmt(f),
+            ReplayError::Provisioning(err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReplayError::Io(err) => Some(err),
+            ReplayError::Publication(err) => Some(err),
+            ReplayError::Provisioning(err) => Some(err),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+    use super::*;
+    use super::provisioning::PayloadType;
+
+    /// Returns a new, empty, temporary directory.
+    fn temp_dir() -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let res = std::env::temp_dir().join(format!(
+            "rpki-trace-test-{}-{}",
+            std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&res);
+        res
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let dir = temp_dir();
+        let recorder = TraceRecorder::new(&dir).unwrap();
+        assert_eq!(
+            recorder.record(
+                Protocol::Provisioning,
+                include_bytes!("../../test-data/ca/rfc6492/list.der"),
+                include_bytes!(
+                    "../../test-data/ca/rfc6492/not-performed-response.xml"
+                ),
+            ).unwrap(),
+            1
+        );
+        fs::write(dir.join("README"), b"ignored").unwrap();
+
+        // A new recorder continues the trace.
+        let recorder = TraceRecorder::new(&dir).unwrap();
+        assert_eq!(
+            recorder.record(
+                Protocol::Publication,
+                include_bytes!("../../test-data/ca/rfc8181/list.xml"),
+                b"garbage",
+            ).unwrap(),
+            2
+        );
+
+        let trace = Trace::open(&dir).unwrap();
+        assert_eq!(trace.len(), 4);
+        let replay: Vec<_> = trace.replay().collect();
+        assert_eq!(replay[0].0.seq(), 1);
+        assert_eq!(replay[0].0.direction(), Direction::Request);
+        assert!(matches!(
+            replay[0].1, Ok(Decoded::Provisioning(ref msg))
+                if matches!(msg.payload().payload_type(), PayloadType::List)
+        ));
+        assert_eq!(replay[1].0.direction(), Direction::Response);
+        assert!(matches!(replay[1].1, Ok(Decoded::Provisioning(_))));
+        assert_eq!(replay[2].0.protocol(), Protocol::Publication);
+        assert!(matches!(replay[2].1, Ok(Decoded::Publication(_))));
+        assert!(matches!(replay[3].1, Err(ReplayError::Publication(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,873 @@
+//! A C API for parsing and validating repository objects.
+//!
+//! This module provides `extern "C"` functions that allow programs written
+//! in other languages to decode resource certificates, ROAs, and manifests
+//! from byte buffers, validate them, and access their most important
+//! fields.
+//!
+//! All objects are handed out as opaque pointers which must be released
+//! via the matching `_free` function. Functions returning a pointer return
+//! a null pointer if the operation failed. Times are given and returned as
+//! seconds since the Unix epoch.
+//!
+//! Since the functions are exported under their unmangled names, a
+//! library usable from C can be built by a crate depending on this crate
+//! and specifying `crate-type = ["staticlib"]` or `["cdylib"]`. The
+//! declarations for C are provided in `include/rpki.h`.
+//!
+//! None of the functions let a panic unwind into the caller. If one
+//! happens anyway, the function returns its error value: a null pointer,
+//! `false`, `-1` for validation results, or zero otherwise.
+//!
+//! The module is only available if the `"capi"` feature is enabled.
+
+#![cfg(feature = "capi")]
+
+use std::{panic, ptr, slice};
+use std::panic::AssertUnwindSafe;
+use std::net::IpAddr;
+use std::os::raw::c_int;
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use crate::repository::cert::{Cert, ResourceCert};
+use crate::repository::crl::Crl;
+use crate::repository::error::{ValidationError, VerificationError};
+use crate::repository::manifest::{FileAndHash, Manifest};
+use crate::repository::roa::Roa;
+use crate::repository::tal::TalInfo;
+use crate::repository::x509::Time;
+
+
+//------------ Certificates --------------------------------------------------
+
+/// A decoded resource certificate.
+pub struct RpkiCert(Cert);
+
+/// A validated resource certificate.
+pub struct RpkiResourceCert(ResourceCert);
+
+/// Decodes a resource certificate from a buffer.
+///
+/// Returns a null pointer if the buffer does not contain a valid
+/// certificate. The returned pointer must be released via
+/// [`rpki_cert_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable octets.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_decode(
+    data: *const u8, len: usize
+) -> *mut RpkiCert {
+    guard(ptr::null_mut(), || {
+        let data = match buffer(data, len) {
+            Some(data) => data,
+            None => return ptr::null_mut(),
+        };
+        match Cert::decode(data) {
+            Ok(cert) => Box::into_raw(Box::new(RpkiCert(cert))),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Releases a certificate returned by [`rpki_cert_decode`].
+///
+/// # Safety
+///
+/// `cert` must be null or a pointer returned by [`rpki_cert_decode`] that
+/// has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_free(cert: *mut RpkiCert) {
+    guard((), || {
+        if !cert.is_null() {
+            drop(Box::from_raw(cert))
+        }
+    })
+}
+
+/// Returns whether the certificate is a CA certificate.
+///
+/// # Safety
+///
+/// `cert` must be null or a valid pointer returned by [`rpki_cert_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_is_ca(cert: *const RpkiCert) -> bool {
+    guard(false, || {
+        cert.as_ref().map(|cert| cert.0.is_ca()).unwrap_or(false)
+    })
+}
+
+/// Returns the start of the validity period of the certificate.
+///
+/// # Safety
+///
+/// `cert` must be a valid pointer returned by [`rpki_cert_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_not_before(cert: *const RpkiCert) -> i64 {
+    guard(0, || {
+        cert.as_ref().map(|cert| {
+            cert.0.validity().not_before().timestamp()
+        }).unwrap_or(0)
+    })
+}
+
+/// Returns the end of the validity period of the certificate.
+///
+/// # Safety
+///
+/// `cert` must be a valid pointer returned by [`rpki_cert_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_not_after(cert: *const RpkiCert) -> i64 {
+    guard(0, || {
+        cert.as_ref().map(|cert| {
+            cert.0.validity().not_after().timestamp()
+        }).unwrap_or(0)
+    })
+}
+
+/// Validates the certificate as a trust anchor certificate at `now`.
+///
+/// The certificate itself is left untouched. Returns a null pointer if
+/// validation fails. The returned pointer must be released via
+/// [`rpki_resource_cert_free`].
+///
+/// # Safety
+///
+/// `cert` must be null or a valid pointer returned by [`rpki_cert_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_validate_ta(
+    cert: *const RpkiCert, strict: bool, now: i64
+) -> *mut RpkiResourceCert {
+    guard(ptr::null_mut(), || {
+        let (cert, now) = match (cert.as_ref(), time(now)) {
+            (Some(cert), Some(now)) => (cert, now),
+            _ => return ptr::null_mut()
+        };
+        resource_cert(cert.0.clone().validate_ta_at(
+            TalInfo::from_name("capi".into()).into_arc(), strict, now
+        ).ok())
+    })
+}
+
+/// Validates the certificate as a CA certificate issued by `issuer`.
+///
+/// The certificate itself is left untouched. Returns a null pointer if
+/// validation fails. The returned pointer must be released via
+/// [`rpki_resource_cert_free`].
+///
+/// # Safety
+///
+/// `cert` must be null or a valid pointer returned by [`rpki_cert_decode`]
+/// and `issuer` must be null or a valid pointer returned by one of the
+/// validation functions.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_cert_validate_ca(
+    cert: *const RpkiCert, issuer: *const RpkiResourceCert,
+    strict: bool, now: i64
+) -> *mut RpkiResourceCert {
+    guard(ptr::null_mut(), || {
+        let (cert, issuer, now) = match (
+            cert.as_ref(), issuer.as_ref(), time(now)
+        ) {
+            (Some(cert), Some(issuer), Some(now)) => (cert, issuer, now),
+            _ => return ptr::null_mut()
+        };
+        resource_cert(
+            cert.0.clone().validate_ca_at(&issuer.0, strict, now).ok()
+        )
+    })
+}
+
+/// Releases a certificate returned by one of the validation functions.
+///
+/// # Safety
+///
+/// `cert` must be null or a pointer returned by [`rpki_cert_validate_ta`]
+/// or [`rpki_cert_validate_ca`] that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_resource_cert_free(
+    cert: *mut RpkiResourceCert
+) {
+    guard((), || {
+        if !cert.is_null() {
+            drop(Box::from_raw(cert))
+        }
+    })
+}
+
+
+//------------ ROAs ----------------------------------------------------------
+
+/// A decoded ROA.
+pub struct RpkiRoa {
+    /// The ROA.
+    roa: Roa,
+
+    /// The prefixes of the ROA.
+    ///
+    /// These are converted once when decoding so that retrieving them by
+    /// index doesn’t have to walk the encoded prefix list every time.
+    prefixes: Vec<RpkiRoaPrefix>,
+}
+
+/// A prefix of a ROA.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct RpkiRoaPrefix {
+    /// The address family, 4 for IPv4 or 6 for IPv6.
+    pub family: u8,
+
+    /// The address in network byte order.
+    ///
+    /// For IPv4 prefixes, only the first four octets are used.
+    pub addr: [u8; 16],
+
+    /// The length of the prefix.
+    pub prefix_len: u8,
+
+    /// The maximum prefix length.
+    pub max_len: u8,
+}
+
+/// Decodes a ROA from a buffer.
+///
+/// Returns a null pointer if the buffer does not contain a valid ROA. The
+/// returned pointer must be released via [`rpki_roa_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable octets.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_decode(
+    data: *const u8, len: usize, strict: bool
+) -> *mut RpkiRoa {
+    guard(ptr::null_mut(), || {
+        let data = match buffer(data, len) {
+            Some(data) => data,
+            None => return ptr::null_mut(),
+        };
+        match Roa::decode(data, strict) {
+            Ok(roa) => {
+                let prefixes = roa.content().iter().map(|item| {
+                    let mut res = RpkiRoaPrefix {
+                        prefix_len: item.address_length(),
+                        max_len: item.max_length(),
+                        .. Default::default()
+                    };
+                    match item.address() {
+                        IpAddr::V4(addr) => {
+                            res.family = 4;
+                            res.addr[..4].copy_from_slice(&addr.octets());
+                        }
+                        IpAddr::V6(addr) => {
+                            res.family = 6;
+                            res.addr.copy_from_slice(&addr.octets());
+                        }
+                    }
+                    res
+                }).collect();
+                Box::into_raw(Box::new(RpkiRoa { roa, prefixes }))
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Releases a ROA returned by [`rpki_roa_decode`].
+///
+/// # Safety
+///
+/// `roa` must be null or a pointer returned by [`rpki_roa_decode`] that
+/// has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_free(roa: *mut RpkiRoa) {
+    guard((), || {
+        if !roa.is_null() {
+            drop(Box::from_raw(roa))
+        }
+    })
+}
+
+/// Returns the AS number of the ROA.
+///
+/// # Safety
+///
+/// `roa` must be a valid pointer returned by [`rpki_roa_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_as_id(roa: *const RpkiRoa) -> u32 {
+    guard(0, || {
+        roa.as_ref().map(|roa| {
+            roa.roa.content().as_id().into_u32()
+        }).unwrap_or(0)
+    })
+}
+
+/// Returns the number of prefixes of the ROA.
+///
+/// # Safety
+///
+/// `roa` must be a valid pointer returned by [`rpki_roa_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_prefix_count(roa: *const RpkiRoa) -> usize {
+    guard(0, || {
+        roa.as_ref().map(|roa| roa.prefixes.len()).unwrap_or(0)
+    })
+}
+
+/// Retrieves the prefix with the given index.
+///
+/// Returns whether there is such a prefix. If so, it is written to
+/// `prefix`.
+///
+/// # Safety
+///
+/// `roa` must be a valid pointer returned by [`rpki_roa_decode`] and
+/// `prefix` must be null or point to writable memory for a
+/// [`RpkiRoaPrefix`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_prefix(
+    roa: *const RpkiRoa, index: usize, prefix: *mut RpkiRoaPrefix
+) -> bool {
+    guard(false, || {
+        let (roa, prefix) = match (roa.as_ref(), prefix.as_mut()) {
+            (Some(roa), Some(prefix)) => (roa, prefix),
+            _ => return false
+        };
+        match roa.prefixes.get(index) {
+            Some(item) => {
+                *prefix = *item;
+                true
+            }
+            None => false
+        }
+    })
+}
+
+/// Validates the ROA issued by `issuer` at the given time.
+///
+/// If `crl` is not null, it must contain the DER encoded CRL of `issuer`.
+/// The ROA is then also invalid if the CRL wasn’t signed by `issuer`, is
+/// stale at `now`, or revokes the ROA’s EE certificate. If `crl` is null,
+/// revocation is not checked at all and a revoked ROA is considered valid.
+///
+/// The ROA itself is left untouched. Returns `1` if the ROA is valid, `0`
+/// if it is invalid, and `-1` if any of the arguments is invalid,
+/// including a CRL that cannot be decoded.
+///
+/// # Safety
+///
+/// `roa` must be null or a valid pointer returned by [`rpki_roa_decode`],
+/// `issuer` must be null or a valid pointer returned by one of the
+/// certificate validation functions, and `crl` must be null or point to
+/// `crl_len` readable octets.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_roa_validate(
+    roa: *const RpkiRoa, issuer: *const RpkiResourceCert,
+    crl: *const u8, crl_len: usize, strict: bool, now: i64
+) -> c_int {
+    guard(-1, || {
+        let (roa, issuer, now) = match (
+            roa.as_ref(), issuer.as_ref(), time(now)
+        ) {
+            (Some(roa), Some(issuer), Some(now)) => (roa, issuer, now),
+            _ => return -1
+        };
+        let crl = match buffer(crl, crl_len) {
+            Some(data) => match Crl::decode(data) {
+                Ok(crl) => Some(crl),
+                Err(_) => return -1
+            }
+            None => None
+        };
+        roa.roa.clone().process_at(&issuer.0, strict, now, |ee| {
+            match crl {
+                Some(crl) => check_crl(&crl, ee, &issuer.0, now),
+                None => Ok(())
+            }
+        }).is_ok() as c_int
+    })
+}
+
+
+//------------ Manifests -----------------------------------------------------
+
+/// A decoded manifest.
+pub struct RpkiManifest {
+    /// The manifest.
+    manifest: Manifest,
+
+    /// The file list of the manifest.
+    ///
+    /// This is kept so pointers to the names and hashes remain valid for
+    /// as long as the manifest exists.
+    files: Vec<FileAndHash<Bytes, Bytes>>,
+}
+
+/// An entry in the file list of a manifest.
+///
+/// Both the name and the hash point into the manifest and remain valid
+/// until it is released.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RpkiManifestFile {
+    /// The file name.
+    ///
+    /// The name is not terminated by a null octet.
+    pub name: *const u8,
+
+    /// The length of the file name in octets.
+    pub name_len: usize,
+
+    /// The SHA-256 hash of the file.
+    pub hash: *const u8,
+
+    /// The length of the hash in octets.
+    pub hash_len: usize,
+}
+
+/// Decodes a manifest from a buffer.
+///
+/// Returns a null pointer if the buffer does not contain a valid manifest.
+/// The returned pointer must be released via [`rpki_manifest_free`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable octets.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_decode(
+    data: *const u8, len: usize, strict: bool
+) -> *mut RpkiManifest {
+    guard(ptr::null_mut(), || {
+        let data = match buffer(data, len) {
+            Some(data) => data,
+            None => return ptr::null_mut(),
+        };
+        match Manifest::decode(data, strict) {
+            Ok(manifest) => {
+                let files = manifest.content().iter().collect();
+                Box::into_raw(Box::new(RpkiManifest { manifest, files }))
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+/// Releases a manifest returned by [`rpki_manifest_decode`].
+///
+/// # Safety
+///
+/// `manifest` must be null or a pointer returned by
+/// [`rpki_manifest_decode`] that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_free(manifest: *mut RpkiManifest) {
+    guard((), || {
+        if !manifest.is_null() {
+            drop(Box::from_raw(manifest))
+        }
+    })
+}
+
+/// Returns the time the manifest was issued.
+///
+/// # Safety
+///
+/// `manifest` must be a valid pointer returned by [`rpki_manifest_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_this_update(
+    manifest: *const RpkiManifest
+) -> i64 {
+    guard(0, || {
+        manifest.as_ref().map(|manifest| {
+            manifest.manifest.content().this_update().timestamp()
+        }).unwrap_or(0)
+    })
+}
+
+/// Returns the time the next manifest is expected.
+///
+/// # Safety
+///
+/// `manifest` must be a valid pointer returned by [`rpki_manifest_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_next_update(
+    manifest: *const RpkiManifest
+) -> i64 {
+    guard(0, || {
+        manifest.as_ref().map(|manifest| {
+            manifest.manifest.content().next_update().timestamp()
+        }).unwrap_or(0)
+    })
+}
+
+/// Returns the number of entries in the file list of the manifest.
+///
+/// # Safety
+///
+/// `manifest` must be a valid pointer returned by [`rpki_manifest_decode`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_file_count(
+    manifest: *const RpkiManifest
+) -> usize {
+    guard(0, || {
+        manifest.as_ref().map(|manifest| manifest.files.len()).unwrap_or(0)
+    })
+}
+
+/// Retrieves the file list entry with the given index.
+///
+/// Returns whether there is such an entry. If so, it is written to `file`.
+///
+/// # Safety
+///
+/// `manifest` must be a valid pointer returned by [`rpki_manifest_decode`]
+/// and `file` must be null or point to writable memory for a
+/// [`RpkiManifestFile`].
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_file(
+    manifest: *const RpkiManifest, index: usize, file: *mut RpkiManifestFile
+) -> bool {
+    guard(false, || {
+        let (manifest, file) = match (manifest.as_ref(), file.as_mut()) {
+            (Some(manifest), Some(file)) => (manifest, file),
+            _ => return false
+        };
+        let item = match manifest.files.get(index) {
+            Some(item) => item,
+            None => return false
+        };
+        *file = RpkiManifestFile {
+            name: item.file().as_ptr(),
+            name_len: item.file().len(),
+            hash: item.hash().as_ptr(),
+            hash_len: item.hash().len(),
+        };
+        true
+    })
+}
+
+/// Validates the manifest issued by `issuer` at the given time.
+///
+/// The manifest itself is left untouched. Returns `1` if the manifest is
+/// valid, `0` if it is invalid, and `-1` if any of the arguments is
+/// invalid.
+///
+/// # Safety
+///
+/// `manifest` must be null or a valid pointer returned by
+/// [`rpki_manifest_decode`] and `issuer` must be null or a valid pointer
+/// returned by one of the certificate validation functions.
+#[no_mangle]
+pub unsafe extern "C" fn rpki_manifest_validate(
+    manifest: *const RpkiManifest, issuer: *const RpkiResourceCert,
+    strict: bool, now: i64
+) -> c_int {
+    guard(-1, || {
+        let (manifest, issuer, now) = match (
+            manifest.as_ref(), issuer.as_ref(), time(now)
+        ) {
+            (Some(manifest), Some(issuer), Some(now)) => {
+                (manifest, issuer, now)
+            }
+            _ => return -1
+        };
+        manifest.manifest.clone().validate_at(
+            &issuer.0, strict, now
+        ).is_ok() as c_int
+    })
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Runs `op`, returning `error` instead if it panics.
+///
+/// A panic must not unwind across an `extern "C"` boundary, so all
+/// functions of the API run their body through this function.
+fn guard<T>(error: T, op: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(op)).unwrap_or(error)
+}
+
+/// Converts a pointer and length into a buffer.
+///
+/// Returns `None` if `data` is null.
+unsafe fn buffer(data: *const u8, len: usize) -> Option<Bytes> {
+    if data.is_null() {
+        return None
+    }
+    Some(Bytes::copy_from_slice(slice::from_raw_parts(data, len)))
+}
+
+/// Checks that `cert` issued by `issuer` hasn’t been revoked by `crl`.
+fn check_crl(
+    crl: &Crl, cert: &Cert, issuer: &Cert, now: Time
+) -> Result<(), ValidationError> {
+    if *crl.authority_key_identifier() != issuer.subject_key_identifier() {
+        return Err(VerificationError::new("CRL issuer mismatch").into())
+    }
+    crl.verify_signature(issuer.subject_public_key_info())?;
+    if crl.is_stale_at(now) {
+        return Err(VerificationError::new("stale CRL").into())
+    }
+    if crl.contains(cert.serial_number()) {
+        return Err(VerificationError::new("certificate revoked").into())
+    }
+    Ok(())
+}
+
+/// Converts seconds since the Unix epoch into a time.
+fn time(secs: i64) -> Option<Time> {
+    Utc.timestamp_opt(secs, 0).single().map(Time::new)
+}
+
+/// Converts the result of a validation into a pointer.
+fn resource_cert(cert: Option<ResourceCert>) -> *mut RpkiResourceCert {
+    match cert {
+        Some(cert) => Box::into_raw(Box::new(RpkiResourceCert(cert))),
+        None => ptr::null_mut()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::mem;
+    use std::process::Command;
+    use super::*;
+
+    fn at() -> i64 {
+        Utc.with_ymd_and_hms(2019, 5, 1, 0, 0, 0).unwrap().timestamp()
+    }
+
+    #[test]
+    fn cert_and_manifest() {
+        let ta = include_bytes!("../test-data/repository/ta.cer");
+        let mft = include_bytes!("../test-data/repository/ta.mft");
+        unsafe {
+            assert!(rpki_cert_decode(b"foo".as_ptr(), 3).is_null());
+            let cert = rpki_cert_decode(ta.as_ptr(), ta.len());
+            assert!(!cert.is_null());
+            assert!(rpki_cert_is_ca(cert));
+            assert!(rpki_cert_not_before(cert) < at());
+            assert!(rpki_cert_not_after(cert) > at());
+            let ta_cert = rpki_cert_validate_ta(cert, false, at());
+            assert!(!ta_cert.is_null());
+
+            let manifest = rpki_manifest_decode(
+                mft.as_ptr(), mft.len(), false
+            );
+            assert!(!manifest.is_null());
+            assert!(
+                rpki_manifest_this_update(manifest)
+                    < rpki_manifest_next_update(manifest)
+            );
+            let count = rpki_manifest_file_count(manifest);
+            assert!(count > 0);
+            let mut file = RpkiManifestFile {
+                name: ptr::null(), name_len: 0, hash: ptr::null(), hash_len: 0
+            };
+            assert!(rpki_manifest_file(manifest, 0, &mut file));
+            assert!(file.name_len > 0);
+            assert_eq!(file.hash_len, 32);
+            assert!(!rpki_manifest_file(manifest, count, &mut file));
+            assert_eq!(
+                rpki_manifest_validate(manifest, ta_cert, false, at()), 1
+            );
+            assert_eq!(
+                rpki_manifest_validate(manifest, ptr::null(), true, at()), -1
+            );
+
+            rpki_manifest_free(manifest);
+            rpki_resource_cert_free(ta_cert);
+            rpki_cert_free(cert);
+        }
+    }
+
+    #[test]
+    fn guard_panic() {
+        assert_eq!(guard(-1, || -> c_int { panic!("oops") }), -1);
+        assert_eq!(guard(-1, || 1), 1);
+    }
+
+    #[test]
+    fn header_declares_all_functions() {
+        let header = include_str!("../include/rpki.h");
+        let source = include_str!("capi.rs");
+        let marker = concat!("extern \"C\" fn ", "rpki_");
+        let mut count = 0;
+        for item in source.split(marker).skip(1) {
+            let name = item.split('(').next().unwrap();
+            assert!(
+                header.contains(&format!(" *rpki_{}(", name))
+                    || header.contains(&format!(" rpki_{}(", name)),
+                "rpki_{} missing from rpki.h", name
+            );
+            count += 1;
+        }
+        assert_eq!(count, 21);
+    }
+
+    #[test]
+    fn header_compiles() {
+        fn offset<T, F>(base: &T, field: &F) -> usize {
+            field as *const F as usize - base as *const T as usize
+        }
+
+        let prefix = RpkiRoaPrefix::default();
+        let file = RpkiManifestFile {
+            name: ptr::null(), name_len: 0, hash: ptr::null(), hash_len: 0
+        };
+        let defines = [
+            ("ROA_PREFIX_SIZE", mem::size_of::<RpkiRoaPrefix>()),
+            ("ROA_PREFIX_ADDR", offset(&prefix, &prefix.addr)),
+            ("ROA_PREFIX_PREFIX_LEN", offset(&prefix, &prefix.prefix_len)),
+            ("ROA_PREFIX_MAX_LEN", offset(&prefix, &prefix.max_len)),
+            ("MANIFEST_FILE_SIZE", mem::size_of::<RpkiManifestFile>()),
+            ("MANIFEST_FILE_HASH", offset(&file, &file.hash)),
+        ];
+        let root = env!("CARGO_MANIFEST_DIR");
+        let mut cmd = Command::new(
+            std::env::var("CC").unwrap_or_else(|_| "cc".into())
+        );
+        cmd.args(["-std=c11", "-fsyntax-only", "-Wall", "-Wextra"]);
+        cmd.args(["-pedantic", "-Werror", "-I"]);
+        cmd.arg(format!("{}/include", root));
+        for (name, value) in defines {
+            cmd.arg(format!("-D{}={}", name, value));
+        }
+        cmd.arg(format!("{}/test-data/capi/header.c", root));
+        match cmd.status() {
+            Ok(status) => assert!(status.success(), "rpki.h doesn’t compile"),
+            Err(err) => eprintln!("skipping, no C compiler: {}", err),
+        }
+    }
+
+    #[test]
+    fn roa() {
+        let data = include_bytes!("../test-data/repository/example-ripe.roa");
+        unsafe {
+            let roa = rpki_roa_decode(data.as_ptr(), data.len(), false);
+            assert!(!roa.is_null());
+            let rust = Roa::decode(data.as_ref(), false).unwrap();
+            assert_eq!(
+                rpki_roa_as_id(roa), rust.content().as_id().into_u32()
+            );
+            let count = rpki_roa_prefix_count(roa);
+            assert_eq!(count, rust.content().iter().count());
+            let mut prefix = RpkiRoaPrefix::default();
+            for (index, item) in rust.content().iter().enumerate() {
+                assert!(rpki_roa_prefix(roa, index, &mut prefix));
+                assert_eq!(prefix.family, if item.is_v4() { 4 } else { 6 });
+                assert_eq!(prefix.prefix_len, item.address_length());
+                assert_eq!(prefix.max_len, item.max_length());
+            }
+            assert!(!rpki_roa_prefix(roa, count, &mut prefix));
+            assert_eq!(
+                rpki_roa_validate(
+                    roa, ptr::null(), ptr::null(), 0, true, 0
+                ),
+                -1
+            );
+            rpki_roa_free(roa);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+    use crate::uri;
+    use crate::crypto::{PublicKeyFormat, RpkiSignatureAlgorithm, Signer};
+    use crate::crypto::softsigner::OpenSslSigner;
+    use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+    use crate::repository::crl::{CrlEntry, TbsCertList};
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::roa::RoaBuilder;
+    use crate::repository::sigobj::SignedObjectBuilder;
+    use crate::repository::x509::Validity;
+    use super::*;
+
+    #[test]
+    fn roa_validate_crl() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let pubkey = signer.get_key_info(&key).unwrap();
+        let uri = uri::Rsync::from_str("rsync://example.com/m/p").unwrap();
+
+        let mut cert = TbsCert::new(
+            12u64.into(), pubkey.to_subject_name(),
+            Validity::from_secs(86400), None, pubkey.clone(), KeyUsage::Ca,
+            Overclaim::Trim
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(uri.clone()));
+        cert.set_rpki_manifest(Some(uri.clone()));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&signer, &key).unwrap();
+
+        let mut roa = RoaBuilder::new(64496.into());
+        roa.push_v4_addr(Ipv4Addr::new(192, 0, 2, 0), 24, None);
+        let roa = roa.finalize(
+            SignedObjectBuilder::new(
+                13u64.into(), Validity::from_secs(86400), uri.clone(),
+                uri.clone(), uri
+            ),
+            &signer, &key
+        ).unwrap();
+
+        let now = Time::now();
+        let make_crl = |revoked: Vec<CrlEntry>, next_update: Time| {
+            let crl = TbsCertList::new(
+                RpkiSignatureAlgorithm::default(), pubkey.to_subject_name(),
+                now, next_update, revoked, pubkey.key_identifier(),
+                1u64.into()
+            ).into_crl(&signer, &key).unwrap();
+            crl.as_bytes().to_vec()
+        };
+        let empty = make_crl(Vec::new(), Time::tomorrow());
+        let revoked = make_crl(
+            vec![CrlEntry::new(13u64.into(), now)], Time::tomorrow()
+        );
+        let stale = make_crl(Vec::new(), now);
+        let at = Time::tomorrow().timestamp() - 3600;
+
+        unsafe {
+            let ta = rpki_cert_decode(
+                cert.as_bytes().as_ptr(), cert.as_bytes().len()
+            );
+            let ta_cert = rpki_cert_validate_ta(ta, true, at);
+            assert!(!ta_cert.is_null());
+            let data = roa.as_bytes();
+            let roa = rpki_roa_decode(data.as_ptr(), data.len(), true);
+            assert!(!roa.is_null());
+            assert_eq!(rpki_roa_prefix_count(roa), 1);
+            let mut prefix = RpkiRoaPrefix::default();
+            assert!(rpki_roa_prefix(roa, 0, &mut prefix));
+            assert_eq!(prefix.family, 4);
+            assert_eq!(prefix.addr[..4], [192, 0, 2, 0]);
+            assert_eq!((prefix.prefix_len, prefix.max_len), (24, 24));
+
+            let validate = |crl: Option<&[u8]>| {
+                let (ptr, len) = match crl {
+                    Some(crl) => (crl.as_ptr(), crl.len()),
+                    None => (ptr::null(), 0)
+                };
+                rpki_roa_validate(roa, ta_cert, ptr, len, true, at)
+            };
+            assert_eq!(validate(None), 1);
+            assert_eq!(validate(Some(&empty)), 1);
+            assert_eq!(validate(Some(&revoked)), 0);
+            assert_eq!(validate(Some(&stale)), 0);
+            assert_eq!(validate(Some(b"foo")), -1);
+
+            rpki_roa_free(roa);
+            rpki_resource_cert_free(ta_cert);
+            rpki_cert_free(ta);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Sources of the current time.
+//!
+//! Much of the RPKI depends on time: certificates have a validity period,
+//! manifests and CRLs have a next update time, signed messages carry a
+//! signing time, and repositories go stale. The validation methods
+//! throughout the crate come in two flavours for this: one that uses the
+//! current system time and an `_at` variant taking the time to use as an
+//! argument.
+//!
+//! Components that need to determine the current time themselves instead
+//! ask a [`Clock`]. By default, this is the [`SystemClock`]. For tests,
+//! a [`ManualClock`] can be given to them via their `set_clock` method.
+//! Its time only changes when told to, which allows testing expiry and
+//! rollover behaviour without waiting for it. Components keep their clock
+//! as a [`SharedClock`].
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+
+
+//------------ Clock ---------------------------------------------------------
+
+/// A type providing the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.as_ref().now()
+    }
+}
+
+
+//------------ SystemClock ---------------------------------------------------
+
+/// A clock using the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+
+//------------ ManualClock ---------------------------------------------------
+
+/// A clock that only changes when told to.
+///
+/// Clones of a value share the same time, so a clone can be given to a
+/// component while the original is used to change the time.
+#[derive(Clone, Debug)]
+pub struct ManualClock(Arc<Mutex<DateTime<Utc>>>);
+
+impl ManualClock {
+    /// Creates a new clock starting at the given time.
+    pub fn new(start: DateTime<Utc>) -> Self {
+        ManualClock(Arc::new(Mutex::new(start)))
+    }
+
+    /// Sets the clock to the given time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now
+    }
+
+    /// Moves the clock forward by the given duration.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if the resulting time is out of range.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += chrono::Duration::from_std(duration).expect(
+            "duration out of range"
+        );
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+
+//------------ SharedClock ---------------------------------------------------
+
+/// A shared reference to a clock.
+///
+/// This is the type components keep their clock in. The default value
+/// uses the [`SystemClock`].
+#[derive(Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    /// Creates a new value from a clock.
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        SharedClock(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        SharedClock::new(SystemClock)
+    }
+}
+
+impl<C: Clock + 'static> From<Arc<C>> for SharedClock {
+    fn from(clock: Arc<C>) -> Self {
+        SharedClock(clock)
+    }
+}
+
+impl Deref for SharedClock {
+    type Target = dyn Clock;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.now()
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedClock")
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let start = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let clock = ManualClock::new(start);
+        let shared = SharedClock::new(clock.clone());
+        assert_eq!(shared.now(), start);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(
+            shared.now(), Utc.timestamp_opt(1_600_000_060, 0).unwrap()
+        );
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+}
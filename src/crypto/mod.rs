@@ -9,6 +9,7 @@ pub use self::keys::{
     KeyIdentifier, PublicKey, PublicKeyFormat, SignatureVerificationError,
 };
 pub use self::signer::{Signer, SigningError};
+pub use self::suite::AlgorithmSuite;
 pub use self::signature::{
     BgpsecSignatureAlgorithm, Signature, SignatureAlgorithm, RpkiSignature,
     RpkiSignatureAlgorithm,
@@ -18,5 +19,6 @@ pub mod digest;
 pub mod keys;
 pub mod signer;
 pub mod signature;
+pub mod suite;
 #[cfg(feature = "softkeys")] pub mod softsigner;
 
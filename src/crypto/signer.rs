@@ -1,6 +1,6 @@
 //! A generic interface to a signer.
 
-use std::fmt;
+use std::{error, fmt};
 use super::keys::{PublicKey, PublicKeyFormat};
 use super::signature::{SignatureAlgorithm, Signature};
 
@@ -88,6 +88,7 @@ impl SigningAlgorithm {
 //------------ KeyError ------------------------------------------------------
 
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum KeyError<S> {
     /// A key with the given key ID doesn’t exist.
     KeyNotFound,
@@ -113,10 +114,20 @@ impl<S: fmt::Display> fmt::Display for KeyError<S> {
     }
 }
 
+impl<S: error::Error + 'static> error::Error for KeyError<S> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            KeyError::KeyNotFound => None,
+            KeyError::Signer(ref s) => Some(s),
+        }
+    }
+}
+
 
 //------------ SigningError --------------------------------------------------
 
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum SigningError<S> {
     /// A key with the given key ID doesn’t exist.
     KeyNotFound,
@@ -155,3 +166,12 @@ impl<S: fmt::Display> fmt::Display for SigningError<S> {
     }
 }
 
+impl<S: error::Error + 'static> error::Error for SigningError<S> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SigningError::Signer(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
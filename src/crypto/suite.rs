@@ -0,0 +1,76 @@
+//! Algorithm suites.
+//!
+//! An algorithm suite combines the signature and digest algorithms used
+//! together when creating objects. [RFC 7935] currently defines exactly
+//! one suite for the RPKI, RSA PKCS #1 v1.5 with SHA-256. The builders for
+//! certificates, CRLs, signed objects, and signed messages accept a suite
+//! so that supporting a future profile only requires adding a suite
+//! definition here.
+//!
+//! [RFC 7935]: https://tools.ietf.org/html/rfc7935
+
+use super::digest::DigestAlgorithm;
+use super::signature::RpkiSignatureAlgorithm;
+
+
+//------------ AlgorithmSuite ------------------------------------------------
+
+/// The signature and digest algorithms to use when creating objects.
+///
+/// The default value is the suite defined in [RFC 7935].
+///
+/// [RFC 7935]: https://tools.ietf.org/html/rfc7935
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AlgorithmSuite {
+    /// The signature algorithm.
+    signature: RpkiSignatureAlgorithm,
+
+    /// The digest algorithm.
+    digest: DigestAlgorithm,
+}
+
+impl AlgorithmSuite {
+    /// Creates a suite from its signature and digest algorithms.
+    pub fn new(
+        signature: RpkiSignatureAlgorithm, digest: DigestAlgorithm
+    ) -> Self {
+        AlgorithmSuite { signature, digest }
+    }
+
+    /// Returns the suite defined in RFC 7935.
+    ///
+    /// This suite uses RSA PKCS #1 v1.5 signatures with SHA-256 digests.
+    pub fn rfc7935() -> Self {
+        Self::new(
+            RpkiSignatureAlgorithm::default(), DigestAlgorithm::sha256()
+        )
+    }
+
+    /// Returns the signature algorithm of the suite.
+    pub fn signature_algorithm(self) -> RpkiSignatureAlgorithm {
+        self.signature
+    }
+
+    /// Returns the digest algorithm of the suite.
+    pub fn digest_algorithm(self) -> DigestAlgorithm {
+        self.digest
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_is_rfc7935() {
+        let suite = AlgorithmSuite::default();
+        assert_eq!(suite, AlgorithmSuite::rfc7935());
+        assert!(suite.digest_algorithm().is_sha256());
+        assert_eq!(
+            suite.signature_algorithm(), RpkiSignatureAlgorithm::default()
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Notification of data changes.
+//!
+//! Applications built on the relying party components of this crate often
+//! want to react to certain changes right away – alert an operator when a
+//! trust anchor becomes unreachable or a manifest goes stale, or push
+//! changed VRPs somewhere. Instead of polling and diffing the results of
+//! each run, they can subscribe to an [`EventBus`] and receive typed
+//! [`Event`]s as they happen.
+//!
+//! Components emitting events keep a clone of the bus given to them via
+//! their `set_events` method. By default, components have a bus of their
+//! own without any subscribers, which makes emitting events essentially
+//! free. Subscribers are either implementations of the [`Subscriber`]
+//! trait – which includes closures taking an event reference – or a
+//! channel created via [`EventBus::channel`].
+//!
+//! Repositories and objects are identified by the string version of their
+//! URI, just like for [progress reporting][crate::progress].
+
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+#[cfg(feature = "rtr")] use crate::rtr::payload::RouteOrigin;
+#[cfg(feature = "rtr")] use crate::rtr::vrp::VrpDiff;
+
+
+//------------ Event ---------------------------------------------------------
+
+/// Something that happened that applications may want to know about.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A route origin has been added to the VRPs.
+    #[cfg(feature = "rtr")]
+    VrpAdded(RouteOrigin),
+
+    /// A route origin has been removed from the VRPs.
+    #[cfg(feature = "rtr")]
+    VrpRemoved(RouteOrigin),
+
+    /// The certificate of a trust anchor could not be retrieved.
+    TaUnreachable {
+        /// The name of the trust anchor.
+        tal: String,
+
+        /// The URI the certificate was expected at.
+        uri: String,
+    },
+
+    /// A manifest is past its next update time.
+    ManifestStale {
+        /// The URI of the manifest.
+        uri: String,
+
+        /// The next update time given in the manifest.
+        next_update: DateTime<Utc>,
+    },
+
+    /// An RRDP server has started a new session.
+    ///
+    /// When this happens, the complete content of the repository has to
+    /// be reloaded from the snapshot.
+    RrdpSessionReset {
+        /// The URI of the notification file.
+        notify: String,
+
+        /// The session ID of the previous successful update.
+        old: Uuid,
+
+        /// The new session ID.
+        new: Uuid,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "rtr")]
+            Event::VrpAdded(ref origin) => {
+                write!(
+                    f, "VRP added: {} => {}", origin.prefix, origin.asn
+                )
+            }
+            #[cfg(feature = "rtr")]
+            Event::VrpRemoved(ref origin) => {
+                write!(
+                    f, "VRP removed: {} => {}", origin.prefix, origin.asn
+                )
+            }
+            Event::TaUnreachable { ref tal, ref uri } => {
+                write!(f, "trust anchor {} unreachable at {}", tal, uri)
+            }
+            Event::ManifestStale { ref uri, next_update } => {
+                write!(f, "manifest {} stale since {}", uri, next_update)
+            }
+            Event::RrdpSessionReset { ref notify, old, new } => {
+                write!(
+                    f, "RRDP {}: session reset from {} to {}",
+                    notify, old, new
+                )
+            }
+        }
+    }
+}
+
+
+//------------ Subscriber ----------------------------------------------------
+
+/// A type that receives events.
+///
+/// Events are delivered synchronously from within the operation that
+/// emits them, possibly from many threads at once. Implementations should
+/// therefore be cheap and must not block for long. Anything more elaborate
+/// is better done by subscribing via [`EventBus::channel`] and processing
+/// the events on a thread of its own.
+///
+/// The trait is implemented for closures taking an event reference.
+pub trait Subscriber: Send + Sync {
+    /// Receives an event.
+    fn event(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> Subscriber for F {
+    fn event(&self, event: &Event) {
+        (self)(event)
+    }
+}
+
+
+//------------ SubscriptionId ------------------------------------------------
+
+/// Identifies a subscription to an event bus.
+///
+/// The identifier is returned when subscribing and can later be used to
+/// unsubscribe again.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SubscriptionId(u64);
+
+
+//------------ EventBus ------------------------------------------------------
+
+/// Distributes events to all its subscribers.
+///
+/// The bus is a shared value: clones refer to the same set of
+/// subscribers. This way, an application can keep a clone for managing
+/// subscriptions while handing others to the components emitting events.
+#[derive(Clone, Default)]
+pub struct EventBus(Arc<BusInner>);
+
+/// The shared part of an event bus.
+#[derive(Default)]
+struct BusInner {
+    /// The identifier for the next subscription.
+    next_id: AtomicU64,
+
+    /// The current subscribers.
+    subscribers: RwLock<Vec<(SubscriptionId, Arc<dyn Subscriber>)>>,
+}
+
+impl EventBus {
+    /// Creates a new bus without any subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a subscriber.
+    ///
+    /// The subscriber will receive all events emitted from now on until it
+    /// is removed again via [`unsubscribe`][Self::unsubscribe] with the
+    /// returned identifier.
+    pub fn subscribe(
+        &self, subscriber: impl Subscriber + 'static
+    ) -> SubscriptionId {
+        let id = SubscriptionId(
+            self.0.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        self.0.subscribers.write().expect("poisoned lock").push(
+            (id, Arc::new(subscriber))
+        );
+        id
+    }
+
+    /// Adds a subscriber receiving events through a channel.
+    ///
+    /// Returns the identifier of the subscription and the receiving end of
+    /// the channel. Events emitted after the receiver has been dropped are
+    /// silently discarded until the subscription is removed.
+    pub fn channel(&self) -> (SubscriptionId, mpsc::Receiver<Event>) {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let id = self.subscribe(move |event: &Event| {
+            let _ = tx.lock().expect("poisoned lock").send(event.clone());
+        });
+        (id, rx)
+    }
+
+    /// Removes a subscriber.
+    ///
+    /// Returns whether there was a subscription with the given identifier.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscribers = self.0.subscribers.write().expect(
+            "poisoned lock"
+        );
+        let len = subscribers.len();
+        subscribers.retain(|item| item.0 != id);
+        subscribers.len() != len
+    }
+
+    /// Returns the number of current subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.0.subscribers.read().expect("poisoned lock").len()
+    }
+
+    /// Returns whether there are any subscribers.
+    ///
+    /// Components can use this to avoid the cost of creating events
+    /// nobody is interested in.
+    pub fn has_subscribers(&self) -> bool {
+        self.subscriber_count() > 0
+    }
+
+    /// Delivers an event to all subscribers.
+    pub fn emit(&self, event: Event) {
+        let subscribers = self.0.subscribers.read().expect("poisoned lock");
+        for (_, subscriber) in subscribers.iter() {
+            subscriber.event(&event)
+        }
+    }
+
+    /// Emits the events for the changes of a VRP diff.
+    ///
+    /// Removals are emitted before additions.
+    #[cfg(feature = "rtr")]
+    pub fn emit_vrp_diff(&self, diff: &VrpDiff) {
+        if !self.has_subscribers() {
+            return
+        }
+        for origin in &diff.withdrawn {
+            self.emit(Event::VrpRemoved(*origin))
+        }
+        for origin in &diff.announced {
+            self.emit(Event::VrpAdded(*origin))
+        }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stale() -> Event {
+        Event::ManifestStale {
+            uri: "rsync://a.example/m/m.mft".into(),
+            next_update: DateTime::<Utc>::from(std::time::UNIX_EPOCH),
+        }
+    }
+
+    #[test]
+    fn subscribe() {
+        let bus = EventBus::new();
+        assert!(!bus.has_subscribers());
+        bus.emit(stale());
+
+        let seen = Arc::new(AtomicU64::new(0));
+        let counter = seen.clone();
+        let id = bus.clone().subscribe(move |_: &Event| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let (chan_id, rx) = bus.channel();
+        assert_eq!(bus.subscriber_count(), 2);
+        assert_ne!(id, chan_id);
+
+        bus.emit(stale());
+        assert_eq!(seen.load(Ordering::Relaxed), 1);
+        assert_eq!(rx.try_recv().unwrap(), stale());
+
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.emit(stale());
+        assert_eq!(seen.load(Ordering::Relaxed), 1);
+        assert_eq!(rx.try_recv().unwrap(), stale());
+        assert!(rx.try_recv().is_err());
+
+        drop(rx);
+        bus.emit(stale());
+    }
+
+    #[test]
+    #[cfg(feature = "rtr")]
+    fn vrp_diff() {
+        use std::str::FromStr;
+        use crate::resources::addr::{MaxLenPrefix, Prefix};
+        use crate::resources::asn::Asn;
+        use crate::rtr::vrp::VrpSet;
+
+        let origin = |asn: u32| {
+            RouteOrigin::new(
+                MaxLenPrefix::new(
+                    Prefix::from_str("10.0.0.0/8").unwrap(), None
+                ).unwrap(),
+                Asn::from_u32(asn)
+            )
+        };
+        let old: VrpSet = [origin(1), origin(2)].into_iter().collect();
+        let new: VrpSet = [origin(2), origin(3)].into_iter().collect();
+
+        let bus = EventBus::new();
+        let (_, rx) = bus.channel();
+        bus.emit_vrp_diff(&old.diff(&new));
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [Event::VrpRemoved(origin(1)), Event::VrpAdded(origin(3))]
+        );
+    }
+}
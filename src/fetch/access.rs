@@ -0,0 +1,457 @@
+//! Choosing between RRDP and rsync for accessing repositories.
+//!
+//! CAs publish the rsync URI of their publication point and, optionally,
+//! the URI of an RRDP notification file. Relying parties should prefer
+//! RRDP when it is available but may fall back to rsync if the RRDP server
+//! is unavailable. This module contains [`RepositoryAccess`] which keeps
+//! track of the health of the RRDP repositories it has seen and decides,
+//! based on an [`AccessPolicy`], which transport to use for a publication
+//! point.
+//!
+//! The component itself doesn’t fetch anything. A typical fetch looks like
+//! this:
+//!
+//! 1. Ask [`RepositoryAccess::select`] which transport to use.
+//! 2. If it answers [`Transport::Rrdp`], update the RRDP repository and
+//!    report the outcome via [`RepositoryAccess::rrdp_succeeded`] or
+//!    [`RepositoryAccess::rrdp_failed`]. The latter returns whether to
+//!    fall back to rsync right away.
+//! 3. If it answers [`Transport::Rsync`] or step 2 asks for a fallback,
+//!    fetch the publication point via rsync.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use uuid::Uuid;
+use crate::uri;
+use crate::events::{Event, EventBus};
+use crate::metrics::{SharedMetrics, names};
+
+
+//------------ Transport -----------------------------------------------------
+
+/// The transport to use for accessing a publication point.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Transport {
+    /// Use RRDP.
+    Rrdp,
+
+    /// Use rsync.
+    Rsync,
+}
+
+
+//------------ AccessPolicy --------------------------------------------------
+
+/// The policy for falling back from RRDP to rsync.
+///
+/// The default policy falls back to rsync for RRDP repositories that have
+/// never been fetched successfully and for those that have been failing
+/// for more than an hour. RRDP is retried after an hour. Stale
+/// repositories are not detected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AccessPolicy {
+    /// Whether to fall back to rsync at all.
+    pub fallback: bool,
+
+    /// Whether to fall back immediately for new repositories.
+    ///
+    /// A repository is new if it has never been updated successfully.
+    pub fallback_new: bool,
+
+    /// How long a repository needs to be failing before falling back.
+    pub fallback_after: Duration,
+
+    /// How long to wait before trying RRDP again after falling back.
+    pub retry_after: Duration,
+
+    /// How long a serial number must be unchanged to consider it stale.
+    ///
+    /// An RRDP server that keeps serving the same serial number may have
+    /// stopped updating its content. If this is not `None`, such a
+    /// repository is treated like a failing one after the given time.
+    pub stale_after: Option<Duration>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        AccessPolicy {
+            fallback: true,
+            fallback_new: true,
+            fallback_after: Duration::from_secs(3600),
+            retry_after: Duration::from_secs(3600),
+            stale_after: None,
+        }
+    }
+}
+
+
+//------------ RrdpStatus ----------------------------------------------------
+
+/// The health of an RRDP repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RrdpStatus {
+    /// The time of the last successful update.
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The time of the first failure since the last success.
+    ///
+    /// This is `None` if the last attempt succeeded.
+    pub failing_since: Option<DateTime<Utc>>,
+
+    /// The time of the last attempt, successful or not.
+    pub last_attempt: Option<DateTime<Utc>>,
+
+    /// The session and serial number of the last successful update.
+    pub state: Option<(Uuid, u64)>,
+
+    /// When the session or serial number last changed.
+    pub state_changed: Option<DateTime<Utc>>,
+}
+
+impl RrdpStatus {
+    /// Returns whether the repository is currently failing.
+    pub fn is_failing(&self) -> bool {
+        self.failing_since.is_some()
+    }
+
+    /// Returns whether the repository is stale at the given time.
+    pub fn is_stale(
+        &self, stale_after: Option<Duration>, now: DateTime<Utc>
+    ) -> bool {
+        match (stale_after, self.state_changed) {
+            (Some(stale_after), Some(changed)) => {
+                elapsed(changed, now) >= stale_after
+            }
+            _ => false
+        }
+    }
+}
+
+
+//------------ RepositoryAccess ----------------------------------------------
+
+/// Keeps track of RRDP repositories and decides on the transport to use.
+///
+/// See the [module documentation][self] for how to use it.
+#[derive(Clone, Debug, Default)]
+pub struct RepositoryAccess {
+    /// The policy to apply.
+    policy: AccessPolicy,
+
+    /// The status of the RRDP repositories, keyed by notification URI.
+    rrdp: HashMap<uri::Https, RrdpStatus>,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The bus to emit events to.
+    events: EventBus,
+}
+
+impl RepositoryAccess {
+    /// Creates a new value using the given policy.
+    pub fn new(policy: AccessPolicy) -> Self {
+        RepositoryAccess {
+            policy,
+            rrdp: HashMap::new(),
+            metrics: Default::default(),
+            events: Default::default(),
+        }
+    }
+
+    /// Sets the metrics to report to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the bus to emit events to.
+    ///
+    /// The component emits [`Event::RrdpSessionReset`] whenever a
+    /// repository reports a session ID different from that of its last
+    /// successful update.
+    pub fn set_events(&mut self, events: EventBus) {
+        self.events = events
+    }
+
+    /// Returns the policy.
+    pub fn policy(&self) -> &AccessPolicy {
+        &self.policy
+    }
+
+    /// Returns the status of an RRDP repository if it is known.
+    pub fn status(&self, notify: &uri::Https) -> Option<&RrdpStatus> {
+        self.rrdp.get(notify)
+    }
+
+    /// Returns an iterator over the status of all known RRDP repositories.
+    pub fn iter(
+        &self
+    ) -> impl Iterator<Item = (&uri::Https, &RrdpStatus)> + '_ {
+        self.rrdp.iter()
+    }
+
+    /// Sets the status of an RRDP repository.
+    ///
+    /// This can be used to restore the status kept from an earlier run.
+    pub fn set_status(&mut self, notify: uri::Https, status: RrdpStatus) {
+        self.rrdp.insert(notify, status);
+    }
+
+    /// Selects the transport for a publication point.
+    ///
+    /// The publication point’s RRDP notification URI is given via
+    /// `notify`. If there isn’t one, rsync is the only option.
+    pub fn select(
+        &self, notify: Option<&uri::Https>, now: DateTime<Utc>
+    ) -> Transport {
+        let notify = match notify {
+            Some(notify) => notify,
+            None => return Transport::Rsync
+        };
+        let status = match self.rrdp.get(notify) {
+            Some(status) => status,
+            None => return Transport::Rrdp
+        };
+        if !self.should_fall_back(status, now) {
+            return Transport::Rrdp
+        }
+        match status.last_attempt {
+            Some(attempt) if elapsed(attempt, now) < self.policy.retry_after => {
+                Transport::Rsync
+            }
+            _ => Transport::Rrdp
+        }
+    }
+
+    /// Records a successful update of an RRDP repository.
+    ///
+    /// The session ID and serial number of the notification file used for
+    /// the update are provided via `session` and `serial`.
+    pub fn rrdp_succeeded(
+        &mut self,
+        notify: &uri::Https,
+        session: Uuid,
+        serial: u64,
+        now: DateTime<Utc>,
+    ) {
+        let status = self.rrdp.entry(notify.clone()).or_default();
+        if status.failing_since.is_some() {
+            info!("RRDP {}: recovered", notify);
+        }
+        status.last_success = Some(now);
+        status.failing_since = None;
+        status.last_attempt = Some(now);
+        if status.state != Some((session, serial)) {
+            if let Some((old, _)) = status.state {
+                if old != session {
+                    info!("RRDP {}: session reset", notify);
+                    self.events.emit(Event::RrdpSessionReset {
+                        notify: notify.to_string(),
+                        old,
+                        new: session,
+                    });
+                }
+            }
+            status.state = Some((session, serial));
+            status.state_changed = Some(now);
+        }
+        self.metrics.counter(names::RRDP_UPDATES, &[("result", "ok")], 1);
+        self.report_failing();
+    }
+
+    /// Records a failed update of an RRDP repository.
+    ///
+    /// Returns whether the publication point should be fetched via rsync
+    /// instead.
+    pub fn rrdp_failed(
+        &mut self, notify: &uri::Https, now: DateTime<Utc>
+    ) -> bool {
+        let status = self.rrdp.entry(notify.clone()).or_default();
+        if status.failing_since.is_none() {
+            status.failing_since = Some(now);
+        }
+        status.last_attempt = Some(now);
+        let status = status.clone();
+        let res = self.should_fall_back(&status, now);
+        if res {
+            debug!("RRDP {}: falling back to rsync", notify);
+            self.metrics.counter(names::RRDP_FALLBACKS, &[], 1);
+        }
+        self.metrics.counter(names::RRDP_UPDATES, &[("result", "error")], 1);
+        self.report_failing();
+        res
+    }
+
+    /// Forgets about repositories not attempted since the given time.
+    pub fn purge(&mut self, before: DateTime<Utc>) {
+        self.rrdp.retain(|_, status| {
+            matches!(status.last_attempt, Some(attempt) if attempt >= before)
+        });
+        self.report_failing();
+    }
+
+    /// Reports the number of currently failing repositories.
+    fn report_failing(&self) {
+        self.metrics.gauge(
+            names::RRDP_FAILING, &[],
+            self.rrdp.values().filter(|status| status.is_failing()).count()
+                as f64
+        );
+    }
+
+    /// Returns whether the policy asks for falling back to rsync.
+    fn should_fall_back(
+        &self, status: &RrdpStatus, now: DateTime<Utc>
+    ) -> bool {
+        if !self.policy.fallback {
+            return false
+        }
+        if status.is_stale(self.policy.stale_after, now) {
+            return true
+        }
+        if status.failing_since.is_none() {
+            return false
+        }
+        match status.last_success {
+            Some(success) => {
+                elapsed(success, now) >= self.policy.fallback_after
+            }
+            None => self.policy.fallback_new,
+        }
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the time elapsed between two points in time.
+///
+/// If `later` is actually earlier, returns a zero duration.
+fn elapsed(earlier: DateTime<Utc>, later: DateTime<Utc>) -> Duration {
+    later.signed_duration_since(earlier).to_std().unwrap_or_default()
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use chrono::TimeZone;
+    use super::*;
+
+    fn notify() -> uri::Https {
+        uri::Https::from_str("https://rrdp.example.com/notify.xml").unwrap()
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn no_rrdp() {
+        let access = RepositoryAccess::default();
+        assert_eq!(access.select(None, time(0)), Transport::Rsync);
+        assert_eq!(access.select(Some(&notify()), time(0)), Transport::Rrdp);
+    }
+
+    #[test]
+    fn new_repository() {
+        let mut access = RepositoryAccess::default();
+        assert!(access.rrdp_failed(&notify(), time(0)));
+        assert_eq!(access.select(Some(&notify()), time(10)), Transport::Rsync);
+        assert_eq!(
+            access.select(Some(&notify()), time(3600)), Transport::Rrdp
+        );
+
+        let mut access = RepositoryAccess::new(AccessPolicy {
+            fallback_new: false, .. Default::default()
+        });
+        assert!(!access.rrdp_failed(&notify(), time(0)));
+        assert_eq!(access.select(Some(&notify()), time(10)), Transport::Rrdp);
+    }
+
+    #[test]
+    fn fallback_after() {
+        let mut access = RepositoryAccess::default();
+        let session = Uuid::nil();
+        access.rrdp_succeeded(&notify(), session, 1, time(0));
+        assert!(!access.rrdp_failed(&notify(), time(600)));
+        assert!(access.status(&notify()).unwrap().is_failing());
+        assert_eq!(
+            access.select(Some(&notify()), time(1200)), Transport::Rrdp
+        );
+        assert!(access.rrdp_failed(&notify(), time(3600)));
+        assert_eq!(
+            access.select(Some(&notify()), time(4000)), Transport::Rsync
+        );
+        assert_eq!(
+            access.select(Some(&notify()), time(7200)), Transport::Rrdp
+        );
+        access.rrdp_succeeded(&notify(), session, 2, time(7200));
+        assert!(!access.status(&notify()).unwrap().is_failing());
+        assert_eq!(
+            access.select(Some(&notify()), time(7300)), Transport::Rrdp
+        );
+
+        let mut access = RepositoryAccess::new(AccessPolicy {
+            fallback: false, .. Default::default()
+        });
+        assert!(!access.rrdp_failed(&notify(), time(0)));
+        assert_eq!(
+            access.select(Some(&notify()), time(10)), Transport::Rrdp
+        );
+    }
+
+    #[test]
+    fn stale() {
+        let mut access = RepositoryAccess::new(AccessPolicy {
+            stale_after: Some(Duration::from_secs(86400)),
+            .. Default::default()
+        });
+        let session = Uuid::nil();
+        access.rrdp_succeeded(&notify(), session, 1, time(0));
+        access.rrdp_succeeded(&notify(), session, 1, time(80000));
+        assert_eq!(
+            access.select(Some(&notify()), time(80000)), Transport::Rrdp
+        );
+        access.rrdp_succeeded(&notify(), session, 1, time(86400));
+        assert_eq!(
+            access.select(Some(&notify()), time(86401)), Transport::Rsync
+        );
+        access.rrdp_succeeded(&notify(), session, 2, time(90000));
+        assert_eq!(
+            access.select(Some(&notify()), time(90001)), Transport::Rrdp
+        );
+    }
+
+    #[test]
+    fn purge() {
+        let mut access = RepositoryAccess::default();
+        access.rrdp_succeeded(&notify(), Uuid::nil(), 1, time(0));
+        access.purge(time(0));
+        assert!(access.status(&notify()).is_some());
+        access.purge(time(1));
+        assert!(access.status(&notify()).is_none());
+    }
+
+    #[test]
+    fn session_reset() {
+        let events = EventBus::new();
+        let (_, rx) = events.channel();
+        let mut access = RepositoryAccess::default();
+        access.set_events(events);
+        let old = Uuid::nil();
+        let new = Uuid::from_u128(1);
+        access.rrdp_succeeded(&notify(), old, 1, time(0));
+        access.rrdp_succeeded(&notify(), old, 2, time(10));
+        assert!(rx.try_recv().is_err());
+        access.rrdp_succeeded(&notify(), new, 1, time(20));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Event::RrdpSessionReset { notify: notify().to_string(), old, new }
+        );
+    }
+}
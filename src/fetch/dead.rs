@@ -0,0 +1,322 @@
+//! Skipping repositories that keep failing.
+//!
+//! A handful of repositories that don’t respond at all can make a
+//! validation run take a lot longer than necessary: every run waits for
+//! their connections to time out, often several times if retries are
+//! enabled. This module contains [`DeadRepositories`] which keeps track
+//! of consecutive failures per repository and, based on a
+//! [`DeadPolicy`], declares repositories that keep failing dead for a
+//! while. Fetching dead repositories is skipped until their time to live
+//! expires. The objects previously fetched from them remain in the object
+//! store and should be used for validation as usual.
+//!
+//! Repositories are identified by their URI as a string, i.e., the rsync
+//! module or RRDP notification URI. A typical fetch looks like this:
+//!
+//! 1. Ask [`DeadRepositories::should_skip`] whether to fetch at all. If
+//!    not, use the cached objects and record `FetchOutcome::Skipped` in
+//!    the validation report.
+//! 2. Otherwise fetch and report the outcome via
+//!    [`DeadRepositories::succeeded`] or [`DeadRepositories::failed`].
+
+use std::cmp;
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use crate::clock::{Clock, SharedClock};
+use crate::metrics::{SharedMetrics, names};
+
+
+//------------ DeadPolicy ----------------------------------------------------
+
+/// The policy for declaring repositories dead.
+///
+/// The default policy declares a repository dead after three consecutive
+/// failures for one hour. Every further failure after that doubles the
+/// time up to a maximum of one day.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeadPolicy {
+    /// The number of consecutive failures before a repository is dead.
+    ///
+    /// A value of zero disables tracking of dead repositories.
+    pub failures: u32,
+
+    /// How long to skip a repository once it is dead.
+    pub ttl: Duration,
+
+    /// The maximum time to skip a repository.
+    pub max_ttl: Duration,
+}
+
+impl DeadPolicy {
+    /// Returns how long to skip a repository after the given failures.
+    ///
+    /// Returns `None` if the repository isn’t dead yet.
+    pub fn ttl_after(&self, failures: u32) -> Option<Duration> {
+        if self.failures == 0 || failures < self.failures {
+            return None
+        }
+        let factor = 1u32.checked_shl(
+            failures - self.failures
+        ).unwrap_or(u32::MAX);
+        Some(cmp::min(self.ttl.saturating_mul(factor), self.max_ttl))
+    }
+}
+
+impl Default for DeadPolicy {
+    fn default() -> Self {
+        DeadPolicy {
+            failures: 3,
+            ttl: Duration::from_secs(3600),
+            max_ttl: Duration::from_secs(86400),
+        }
+    }
+}
+
+
+//------------ RepositoryHealth ----------------------------------------------
+
+/// The health of a repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepositoryHealth {
+    /// The number of consecutive failures.
+    pub failures: u32,
+
+    /// The time of the last successful fetch.
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The time of the last attempt, successful or not.
+    pub last_attempt: Option<DateTime<Utc>>,
+
+    /// The time until which the repository is considered dead.
+    pub dead_until: Option<DateTime<Utc>>,
+}
+
+impl RepositoryHealth {
+    /// Returns whether the repository is dead at the given time.
+    pub fn is_dead(&self, now: DateTime<Utc>) -> bool {
+        matches!(self.dead_until, Some(until) if now < until)
+    }
+}
+
+
+//------------ DeadRepositories ----------------------------------------------
+
+/// Keeps track of failing repositories and decides which ones to skip.
+///
+/// See the [module documentation][self] for how to use it.
+#[derive(Clone, Debug, Default)]
+pub struct DeadRepositories {
+    /// The policy to apply.
+    policy: DeadPolicy,
+
+    /// The health of the repositories, keyed by their URI.
+    repositories: HashMap<String, RepositoryHealth>,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The clock providing the current time for reporting metrics.
+    clock: SharedClock,
+}
+
+impl DeadRepositories {
+    /// Creates a new value using the given policy.
+    pub fn new(policy: DeadPolicy) -> Self {
+        DeadRepositories {
+            policy,
+            repositories: HashMap::new(),
+            metrics: Default::default(),
+            clock: Default::default(),
+        }
+    }
+
+    /// Sets the metrics to report to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock is only used for reporting the number of dead
+    /// repositories when no time is given. By default, the system time
+    /// is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Returns the policy.
+    pub fn policy(&self) -> &DeadPolicy {
+        &self.policy
+    }
+
+    /// Returns the health of a repository if it is known.
+    pub fn status(&self, repository: &str) -> Option<&RepositoryHealth> {
+        self.repositories.get(repository)
+    }
+
+    /// Returns an iterator over the health of all known repositories.
+    pub fn iter(
+        &self
+    ) -> impl Iterator<Item = (&str, &RepositoryHealth)> + '_ {
+        self.repositories.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Returns an iterator over the repositories dead at the given time.
+    pub fn dead(
+        &self, now: DateTime<Utc>
+    ) -> impl Iterator<Item = &str> + '_ {
+        self.iter().filter_map(move |(key, health)| {
+            health.is_dead(now).then_some(key)
+        })
+    }
+
+    /// Sets the health of a repository.
+    ///
+    /// This can be used to restore the status kept from an earlier run.
+    pub fn set_status(
+        &mut self, repository: impl Into<String>, health: RepositoryHealth
+    ) {
+        self.repositories.insert(repository.into(), health);
+        self.report_dead(self.clock.now());
+    }
+
+    /// Returns whether fetching a repository should be skipped.
+    pub fn should_skip(&self, repository: &str, now: DateTime<Utc>) -> bool {
+        let res = self.repositories.get(repository).map(|health| {
+            health.is_dead(now)
+        }).unwrap_or(false);
+        if res {
+            debug!("{}: dead, skipping", repository);
+            self.metrics.counter(names::DEAD_SKIPPED, &[], 1);
+        }
+        res
+    }
+
+    /// Records a successful fetch of a repository.
+    pub fn succeeded(&mut self, repository: &str, now: DateTime<Utc>) {
+        let health = self.entry(repository);
+        if health.dead_until.is_some() {
+            info!("{}: alive again", repository);
+        }
+        health.failures = 0;
+        health.last_success = Some(now);
+        health.last_attempt = Some(now);
+        health.dead_until = None;
+        self.report_dead(now);
+    }
+
+    /// Records a failed fetch of a repository.
+    ///
+    /// Returns whether the repository is now considered dead.
+    pub fn failed(&mut self, repository: &str, now: DateTime<Utc>) -> bool {
+        let policy = self.policy;
+        let health = self.entry(repository);
+        health.failures = health.failures.saturating_add(1);
+        health.last_attempt = Some(now);
+        let ttl = policy.ttl_after(health.failures).and_then(|ttl| {
+            chrono::Duration::from_std(ttl).ok()
+        });
+        let res = match ttl {
+            Some(ttl) => {
+                health.dead_until = now.checked_add_signed(ttl);
+                info!(
+                    "{}: dead after {} failures, skipping for {}s",
+                    repository, health.failures, ttl.num_seconds()
+                );
+                true
+            }
+            None => false
+        };
+        self.report_dead(now);
+        res
+    }
+
+    /// Forgets about repositories not attempted since the given time.
+    pub fn purge(&mut self, before: DateTime<Utc>) {
+        self.repositories.retain(|_, health| {
+            matches!(health.last_attempt, Some(attempt) if attempt >= before)
+        });
+        self.report_dead(self.clock.now());
+    }
+
+    /// Returns the health entry for a repository, creating it if needed.
+    fn entry(&mut self, repository: &str) -> &mut RepositoryHealth {
+        // Avoid allocating the key for repositories we already know.
+        if !self.repositories.contains_key(repository) {
+            self.repositories.insert(
+                repository.into(), Default::default()
+            );
+        }
+        self.repositories.get_mut(repository).unwrap()
+    }
+
+    /// Reports the number of currently dead repositories.
+    fn report_dead(&self, now: DateTime<Utc>) {
+        self.metrics.gauge(
+            names::DEAD_REPOSITORIES, &[], self.dead(now).count() as f64
+        );
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use super::*;
+
+    const REPO: &str = "https://rrdp.example.com/notify.xml";
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn ttl_after() {
+        let policy = DeadPolicy::default();
+        assert_eq!(policy.ttl_after(2), None);
+        assert_eq!(policy.ttl_after(3), Some(Duration::from_secs(3600)));
+        assert_eq!(policy.ttl_after(4), Some(Duration::from_secs(7200)));
+        assert_eq!(policy.ttl_after(100), Some(Duration::from_secs(86400)));
+        assert_eq!(
+            DeadPolicy { failures: 0, .. Default::default() }.ttl_after(10),
+            None
+        );
+    }
+
+    #[test]
+    fn dead_and_alive() {
+        let mut dead = DeadRepositories::default();
+        assert!(!dead.should_skip(REPO, time(0)));
+        assert!(!dead.failed(REPO, time(0)));
+        assert!(!dead.failed(REPO, time(10)));
+        assert!(!dead.should_skip(REPO, time(20)));
+        assert!(dead.failed(REPO, time(20)));
+        assert!(dead.should_skip(REPO, time(30)));
+        assert_eq!(dead.dead(time(30)).collect::<Vec<_>>(), [REPO]);
+        assert!(!dead.should_skip(REPO, time(3620)));
+
+        // Failing again after the TTL doubles it.
+        assert!(dead.failed(REPO, time(3620)));
+        assert!(dead.should_skip(REPO, time(3620 + 7199)));
+        assert!(!dead.should_skip(REPO, time(3620 + 7200)));
+
+        dead.succeeded(REPO, time(11000));
+        assert!(!dead.should_skip(REPO, time(11000)));
+        assert_eq!(dead.status(REPO).unwrap().failures, 0);
+        assert!(!dead.failed(REPO, time(11010)));
+    }
+
+    #[test]
+    fn purge() {
+        let mut dead = DeadRepositories::default();
+        dead.failed(REPO, time(0));
+        dead.purge(time(0));
+        assert!(dead.status(REPO).is_some());
+        dead.purge(time(1));
+        assert!(dead.status(REPO).is_none());
+    }
+}
@@ -0,0 +1,1242 @@
+//! Retrieving repository content via HTTPS.
+//!
+//! RRDP uses HTTPS to transfer the notification, snapshot, and delta files
+//! of a repository. The [`HttpsFetcher`] provides the logic a relying party
+//! needs for these transfers: it keeps track of the entity tags and
+//! modification times of previously retrieved files in order to make
+//! conditional requests, decompresses gzip-encoded responses, and limits
+//! the size of downloads.
+//!
+//! The crate does not contain an HTTP client. Instead, the actual requests
+//! are performed by a [`Transport`] provided by the user. Since the fetcher
+//! keeps its transport for its whole lifetime, a transport that pools its
+//! connections will have them reused for all requests. The transport is
+//! also expected to follow redirects and honour the timeouts given in the
+//! [`Request`].
+//!
+//! If the `"async"` feature is enabled, the fetcher can also be used with
+//! an `AsyncTransport`, providing asynchronous versions of its methods.
+//! Only the transfers themselves are asynchronous. The downloaded RRDP
+//! files are still parsed and applied synchronously via the `rrdp` module.
+
+use std::{error, fmt, io};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+#[cfg(feature = "async")] use std::future::Future;
+#[cfg(feature = "async")] use std::pin::Pin;
+use bytes::Bytes;
+use log::debug;
+use crate::uri;
+use crate::metrics::{SharedMetrics, names};
+use crate::progress::SharedProgress;
+use crate::util::gzip;
+use crate::util::http::{HttpClientConfig, host};
+use crate::util::net::AddressFamily;
+use super::limit::FetchLimits;
+use super::policy::{FetchTarget, PolicyRejection, SharedPolicy};
+use super::retry::{RetryPolicy, Retryable};
+#[cfg(feature = "rrdp")] use crate::rrdp::NotificationFile;
+#[cfg(feature = "rrdp")] use crate::xml::decode::Error as XmlError;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The size of the chunks the body is read in.
+const READ_CHUNK: usize = 16 * 1024;
+
+
+//------------ Transport -----------------------------------------------------
+
+/// A type that can perform HTTP requests.
+pub trait Transport {
+    /// The type of the body of a response.
+    type Body: io::Read;
+
+    /// The error returned when a request fails.
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Performs a GET request.
+    ///
+    /// The transport must add all the headers given in `request` and
+    /// should follow redirects. It must not decode the content of the
+    /// response.
+    fn get(
+        &self, request: &Request
+    ) -> Result<Response<Self::Body>, Self::Error>;
+}
+
+
+//------------ AsyncTransport ------------------------------------------------
+
+/// A type that can perform HTTP requests asynchronously.
+///
+/// This is the asynchronous version of [`Transport`].
+#[cfg(feature = "async")]
+pub trait AsyncTransport {
+    /// The type of the body of a response.
+    type Body: tokio::io::AsyncRead + Unpin + Send;
+
+    /// The error returned when a request fails.
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Performs a GET request.
+    ///
+    /// The same requirements as for [`Transport::get`] apply.
+    fn get<'a>(
+        &'a self, request: &'a Request
+    ) -> ResponseFuture<'a, Self::Body, Self::Error>;
+}
+
+/// The future returned by [`AsyncTransport::get`].
+#[cfg(feature = "async")]
+pub type ResponseFuture<'a, B, E> = Pin<Box<
+    dyn Future<Output = Result<Response<B>, E>> + Send + 'a
+>>;
+
+
+//------------ Request -------------------------------------------------------
+
+/// A GET request to be performed by a transport.
+#[derive(Clone, Debug)]
+pub struct Request {
+    /// The URI to request.
+    uri: uri::Https,
+
+    /// The headers to include in the request.
+    headers: Vec<(&'static str, String)>,
+
+    /// The timeout for establishing a connection.
+    connect_timeout: Option<Duration>,
+
+    /// The timeout for the complete request.
+    timeout: Option<Duration>,
+
+    /// The server name to use instead of the URI’s host.
+    server_name: Option<String>,
+
+    /// The address families to connect via.
+    address_family: AddressFamily,
+}
+
+impl Request {
+    /// Returns the URI to request.
+    pub fn uri(&self) -> &uri::Https {
+        &self.uri
+    }
+
+    /// Returns an iterator over the headers to include.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.headers.iter().map(|(name, value)| (*name, value.as_str()))
+    }
+
+    /// Returns the value of the given header if it is present.
+    ///
+    /// The header name is compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers().find_map(|(key, value)| {
+            if key.eq_ignore_ascii_case(name) {
+                Some(value)
+            }
+            else {
+                None
+            }
+        })
+    }
+
+    /// Returns the timeout for establishing a connection.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    /// Returns the timeout for the complete request.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns the server name to use instead of the URI’s host.
+    ///
+    /// If this returns a value, the transport must use it for the TLS
+    /// server name indication and for verifying the server certificate.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Returns the address families the transport should connect via.
+    ///
+    /// Transports should resolve both A and AAAA records and race
+    /// connections to the addresses of the allowed families, for instance
+    /// via [`HappyEyeballs`][crate::util::net::HappyEyeballs].
+    pub fn address_family(&self) -> AddressFamily {
+        self.address_family
+    }
+}
+
+
+//------------ Response ------------------------------------------------------
+
+/// The response to a request as returned by a transport.
+#[derive(Clone, Debug)]
+pub struct Response<B> {
+    /// The HTTP status code.
+    status: u16,
+
+    /// The response headers.
+    headers: Vec<(String, String)>,
+
+    /// The response body.
+    body: B,
+}
+
+impl<B> Response<B> {
+    /// Creates a new response from its components.
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: B) -> Self {
+        Response { status, headers, body }
+    }
+
+    /// Returns the HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the value of the given header if it is present.
+    ///
+    /// The header name is compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find_map(|(key, value)| {
+            if key.eq_ignore_ascii_case(name) {
+                Some(value.as_str())
+            }
+            else {
+                None
+            }
+        })
+    }
+
+    /// Converts the response into its body.
+    pub fn into_body(self) -> B {
+        self.body
+    }
+}
+
+
+//------------ Validators ----------------------------------------------------
+
+/// The information necessary for conditional requests.
+///
+/// These are the values of the `ETag` and `Last-Modified` headers of a
+/// previous response. They are kept as the strings received from the
+/// server since they only ever are sent back to it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Validators {
+    /// The entity tag of the last response.
+    pub etag: Option<String>,
+
+    /// The last modification time of the last response.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Takes the validators from the headers of a response.
+    fn from_response<B>(response: &Response<B>) -> Option<Self> {
+        let res = Validators {
+            etag: response.header("ETag").map(Into::into),
+            last_modified: response.header("Last-Modified").map(Into::into),
+        };
+        if res.is_empty() {
+            None
+        }
+        else {
+            Some(res)
+        }
+    }
+
+    /// Returns whether there are no validators at all.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+
+//------------ HttpsFetcher --------------------------------------------------
+
+/// A fetcher for repository content via HTTPS.
+///
+/// The fetcher is created via [`new`][Self::new] from the transport to use
+/// and can then be configured through a number of setter methods. By
+/// default, gzip compression is requested, there is no size limit, and no
+/// timeouts are set.
+///
+/// The method [`fetch`][Self::fetch] unconditionally downloads a file.
+/// This is appropriate for snapshot and delta files which never change.
+/// The method [`fetch_if_modified`][Self::fetch_if_modified] makes a
+/// conditional request if the file has been downloaded before and is meant
+/// for notification files. The validators for the conditional requests are
+/// kept by the fetcher but can be accessed via
+/// [`validators`][Self::validators] and
+/// [`set_validators`][Self::set_validators] to retain them between runs.
+#[derive(Debug)]
+pub struct HttpsFetcher<T> {
+    /// The transport to make the actual requests.
+    transport: T,
+
+    /// The value of the User-Agent header.
+    user_agent: Option<String>,
+
+    /// Whether to request gzip compression.
+    gzip: bool,
+
+    /// The maximum size of a downloaded file.
+    max_size: Option<u64>,
+
+    /// The timeout for establishing a connection.
+    connect_timeout: Option<Duration>,
+
+    /// The timeout for a complete request.
+    timeout: Option<Duration>,
+
+    /// The validators of previously fetched files.
+    validators: Mutex<HashMap<uri::Https, Validators>>,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The progress reporter.
+    progress: SharedProgress,
+
+    /// The TLS configuration.
+    client_config: HttpClientConfig,
+
+    /// The policy for retrying failed requests.
+    retry: RetryPolicy,
+
+    /// The limits on concurrency and bandwidth.
+    limits: FetchLimits,
+
+    /// The policy deciding which URIs may be fetched.
+    policy: SharedPolicy,
+}
+
+impl<T> HttpsFetcher<T> {
+    /// Creates a new fetcher using the given transport.
+    pub fn new(transport: T) -> Self {
+        HttpsFetcher {
+            transport,
+            user_agent: None,
+            gzip: true,
+            max_size: None,
+            connect_timeout: None,
+            timeout: None,
+            validators: Default::default(),
+            metrics: Default::default(),
+            progress: Default::default(),
+            client_config: Default::default(),
+            retry: Default::default(),
+            limits: Default::default(),
+            policy: Default::default(),
+        }
+    }
+
+    /// Returns a reference to the transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Sets the value of the User-Agent header to include.
+    pub fn set_user_agent(&mut self, user_agent: Option<String>) {
+        self.user_agent = user_agent
+    }
+
+    /// Sets whether gzip compression should be requested.
+    pub fn set_gzip(&mut self, gzip: bool) {
+        self.gzip = gzip
+    }
+
+    /// Sets the maximum size of a downloaded file.
+    ///
+    /// The limit applies to the decompressed size of the file. If a
+    /// download exceeds it, it is aborted and the fetch fails.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size
+    }
+
+    /// Sets the timeout for establishing a connection.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.connect_timeout = timeout
+    }
+
+    /// Sets the timeout for a complete request.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+
+    /// Sets the metrics the fetcher reports to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the progress reporter of the fetcher.
+    ///
+    /// Each request is reported as a repository identified by the
+    /// requested URI. The bytes of the response body are reported as they
+    /// are received, before decompression.
+    pub fn set_progress(&mut self, progress: SharedProgress) {
+        self.progress = progress
+    }
+
+    /// Returns the TLS configuration.
+    pub fn client_config(&self) -> &HttpClientConfig {
+        &self.client_config
+    }
+
+    /// Sets the TLS configuration.
+    ///
+    /// The fetcher only uses the server name overrides of the
+    /// configuration and provides them with each request. Trust anchors
+    /// and client identity need to be used when creating the transport.
+    pub fn set_client_config(&mut self, config: HttpClientConfig) {
+        self.client_config = config
+    }
+
+    /// Sets the policy for retrying failed requests.
+    ///
+    /// By default, failed requests are not retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy
+    }
+
+    /// Sets the limits on concurrency and bandwidth.
+    ///
+    /// A request waits until the limits allow it to start. The bandwidth
+    /// limit is applied while reading the response body. By default,
+    /// there are no limits.
+    pub fn set_limits(&mut self, limits: FetchLimits) {
+        self.limits = limits
+    }
+
+    /// Sets the policy deciding which URIs may be fetched.
+    ///
+    /// The policy is checked before a request is made. If it rejects the
+    /// URI, the request fails with [`ErrorKind::Rejected`]. By default,
+    /// all URIs are allowed.
+    pub fn set_policy(&mut self, policy: SharedPolicy) {
+        self.policy = policy
+    }
+
+    /// Checks the URI against the policy.
+    fn check_policy(&self, uri: &uri::Https) -> Result<(), Error> {
+        self.policy.check(FetchTarget::Https(uri)).map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Rejected(err))
+        })
+    }
+
+    /// Returns the validators for a previously fetched URI.
+    pub fn validators(&self, uri: &uri::Https) -> Option<Validators> {
+        self.validators.lock().unwrap().get(uri).cloned()
+    }
+
+    /// Returns the validators for all previously fetched URIs.
+    ///
+    /// This can be used to retain the validators between runs and restore
+    /// them via [`set_validators`][Self::set_validators].
+    pub fn all_validators(&self) -> Vec<(uri::Https, Validators)> {
+        self.validators.lock().unwrap().iter().map(|(uri, validators)| {
+            (uri.clone(), validators.clone())
+        }).collect()
+    }
+
+    /// Sets or removes the validators for a URI.
+    pub fn set_validators(
+        &self, uri: uri::Https, validators: Option<Validators>
+    ) {
+        let mut map = self.validators.lock().unwrap();
+        match validators {
+            Some(validators) if !validators.is_empty() => {
+                map.insert(uri, validators);
+            }
+            _ => {
+                map.remove(&uri);
+            }
+        }
+    }
+
+    /// Creates a request for the URI.
+    fn request(
+        &self, uri: &uri::Https, validators: Option<&Validators>
+    ) -> Request {
+        let mut headers = Vec::new();
+        if let Some(ref agent) = self.user_agent {
+            headers.push(("User-Agent", agent.clone()));
+        }
+        if self.gzip {
+            headers.push(("Accept-Encoding", "gzip".into()));
+        }
+        if let Some(validators) = validators {
+            if let Some(ref etag) = validators.etag {
+                headers.push(("If-None-Match", etag.clone()));
+            }
+            if let Some(ref modified) = validators.last_modified {
+                headers.push(("If-Modified-Since", modified.clone()));
+            }
+        }
+        Request {
+            uri: uri.clone(),
+            headers,
+            connect_timeout: self.connect_timeout,
+            timeout: self.timeout,
+            server_name: self.client_config.server_name_override(
+                host(uri.authority())
+            ).map(Into::into),
+            address_family: self.client_config.address_family(),
+        }
+    }
+}
+
+impl<T: Transport> HttpsFetcher<T> {
+    /// Downloads the file at the given URI.
+    pub fn fetch(&self, uri: &uri::Https) -> Result<Bytes, Error> {
+        self.fetch_request(uri, None).map(|res| {
+            res.expect("got not modified on unconditional request")
+        })
+    }
+
+    /// Downloads the file at the given URI if it has changed.
+    ///
+    /// If the file has been fetched before and the server confirms that
+    /// it hasn’t changed since, returns `Ok(None)`.
+    pub fn fetch_if_modified(
+        &self, uri: &uri::Https
+    ) -> Result<Option<Bytes>, Error> {
+        let validators = self.validators(uri);
+        self.fetch_request(uri, validators.as_ref())
+    }
+
+    /// Downloads and parses the RRDP notification file at the given URI.
+    ///
+    /// Makes a conditional request and returns `Ok(None)` if the file
+    /// hasn’t changed since the last time it was fetched.
+    #[cfg(feature = "rrdp")]
+    pub fn notification(
+        &self, uri: &uri::Https
+    ) -> Result<Option<NotificationFile>, Error> {
+        match self.fetch_if_modified(uri)? {
+            Some(data) => self.parse_notification(uri, data).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Performs a request with optional validators.
+    fn fetch_request(
+        &self, uri: &uri::Https, validators: Option<&Validators>
+    ) -> Result<Option<Bytes>, Error> {
+        let start = Instant::now();
+        self.progress.repository_started(uri.as_str());
+        let res = self.check_policy(uri).and_then(|_| {
+            self.retry.retry(|| {
+                self.fetch_request_inner(uri, validators, start)
+            })
+        });
+        self.record(uri, &res, start);
+        res
+    }
+
+    /// Performs the actual request for
+    /// [`fetch_request`][Self::fetch_request].
+    fn fetch_request_inner(
+        &self, uri: &uri::Https, validators: Option<&Validators>,
+        start: Instant,
+    ) -> Result<Option<Bytes>, Error> {
+        let request = self.request(uri, validators);
+        let _permit = self.limits.acquire(host(uri.authority()));
+        debug!("https: requesting {}", uri);
+        let response = self.transport.get(&request).map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Transport(Box::new(err)))
+        })?;
+        let gzipped = match self.check_response(
+            uri, validators.is_some(), &response
+        )? {
+            Some(gzipped) => gzipped,
+            None => return Ok(None)
+        };
+        let new_validators = Validators::from_response(&response);
+        let mut data = Vec::new();
+        let mut body = response.into_body().take(
+            self.max_size.map_or(u64::MAX, |max| max.saturating_add(1))
+        );
+        let mut buf = [0u8; READ_CHUNK];
+        let res = loop {
+            match body.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(len) => {
+                    data.extend_from_slice(&buf[..len]);
+                    self.progress.bytes_downloaded(uri.as_str(), len as u64);
+                    self.limits.throttle(len);
+                }
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::Interrupted => { }
+                Err(err) => break Err(err),
+            }
+        };
+        res.map_err(|err| Error::new(uri.clone(), ErrorKind::Io(err)))?;
+        self.finish(uri, data, gzipped, new_validators, start).map(Some)
+    }
+}
+
+/// # Asynchronous Fetching
+///
+/// These methods are available if the fetcher’s transport implements
+/// [`AsyncTransport`] and the `"async"` feature is enabled.
+#[cfg(feature = "async")]
+impl<T: AsyncTransport> HttpsFetcher<T> {
+    /// Asynchronously downloads the file at the given URI.
+    ///
+    /// This is the asynchronous version of [`fetch`][Self::fetch].
+    pub async fn fetch_async(&self, uri: &uri::Https) -> Result<Bytes, Error> {
+        self.fetch_request_async(uri, None).await.map(|res| {
+            res.expect("got not modified on unconditional request")
+        })
+    }
+
+    /// Asynchronously downloads the file at the given URI if it changed.
+    ///
+    /// This is the asynchronous version of
+    /// [`fetch_if_modified`][Self::fetch_if_modified].
+    pub async fn fetch_if_modified_async(
+        &self, uri: &uri::Https
+    ) -> Result<Option<Bytes>, Error> {
+        let validators = self.validators(uri);
+        self.fetch_request_async(uri, validators.as_ref()).await
+    }
+
+    /// Asynchronously downloads and parses an RRDP notification file.
+    ///
+    /// This is the asynchronous version of
+    /// [`notification`][Self::notification].
+    #[cfg(feature = "rrdp")]
+    pub async fn notification_async(
+        &self, uri: &uri::Https
+    ) -> Result<Option<NotificationFile>, Error> {
+        match self.fetch_if_modified_async(uri).await? {
+            Some(data) => self.parse_notification(uri, data).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Asynchronously performs a request with optional validators.
+    async fn fetch_request_async(
+        &self, uri: &uri::Https, validators: Option<&Validators>
+    ) -> Result<Option<Bytes>, Error> {
+        let start = Instant::now();
+        self.progress.repository_started(uri.as_str());
+        let res = match self.check_policy(uri) {
+            Ok(()) => {
+                self.retry.retry_async(|| {
+                    self.fetch_request_async_inner(uri, validators, start)
+                }).await
+            }
+            Err(err) => Err(err)
+        };
+        self.record(uri, &res, start);
+        res
+    }
+
+    /// Performs the actual request for
+    /// [`fetch_request_async`][Self::fetch_request_async].
+    async fn fetch_request_async_inner(
+        &self, uri: &uri::Https, validators: Option<&Validators>,
+        start: Instant,
+    ) -> Result<Option<Bytes>, Error> {
+        use tokio::io::AsyncReadExt;
+
+        let request = self.request(uri, validators);
+        let _permit = self.limits.acquire_async(
+            host(uri.authority())
+        ).await;
+        debug!("https: requesting {}", uri);
+        let response = self.transport.get(&request).await.map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Transport(Box::new(err)))
+        })?;
+        let gzipped = match self.check_response(
+            uri, validators.is_some(), &response
+        )? {
+            Some(gzipped) => gzipped,
+            None => return Ok(None)
+        };
+        let new_validators = Validators::from_response(&response);
+        let mut data = Vec::new();
+        let mut body = response.into_body().take(
+            self.max_size.map_or(u64::MAX, |max| max.saturating_add(1))
+        );
+        let mut buf = [0u8; READ_CHUNK];
+        let res = loop {
+            match body.read(&mut buf).await {
+                Ok(0) => break Ok(()),
+                Ok(len) => {
+                    data.extend_from_slice(&buf[..len]);
+                    self.progress.bytes_downloaded(uri.as_str(), len as u64);
+                    self.limits.throttle_async(len).await;
+                }
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::Interrupted => { }
+                Err(err) => break Err(err),
+            }
+        };
+        res.map_err(|err| Error::new(uri.clone(), ErrorKind::Io(err)))?;
+        self.finish(uri, data, gzipped, new_validators, start).map(Some)
+    }
+}
+
+/// # Processing Responses
+///
+/// These methods are shared between the synchronous and asynchronous
+/// versions.
+impl<T> HttpsFetcher<T> {
+    /// Checks the status and headers of a response.
+    ///
+    /// Returns `Ok(None)` if the response indicates that the file hasn’t
+    /// been modified. Otherwise returns whether the body is gzip-encoded.
+    fn check_response<B>(
+        &self, uri: &uri::Https, conditional: bool, response: &Response<B>
+    ) -> Result<Option<bool>, Error> {
+        let err = |kind| Error::new(uri.clone(), kind);
+        if conditional && response.status() == 304 {
+            debug!("https: {} not modified", uri);
+            return Ok(None)
+        }
+        if response.status() != 200 {
+            return Err(err(ErrorKind::Status(response.status())))
+        }
+        match response.header("Content-Encoding") {
+            None => Ok(Some(false)),
+            Some(value) => {
+                let value = value.trim();
+                if value.eq_ignore_ascii_case("identity") {
+                    Ok(Some(false))
+                }
+                else if
+                    value.eq_ignore_ascii_case("gzip")
+                    || value.eq_ignore_ascii_case("x-gzip")
+                {
+                    Ok(Some(true))
+                }
+                else {
+                    Err(err(ErrorKind::Encoding(value.into())))
+                }
+            }
+        }
+    }
+
+    /// Finishes processing of a response body.
+    ///
+    /// The body must have been read with a limit of one more than the
+    /// maximum size so we can detect if it is too large.
+    fn finish(
+        &self,
+        uri: &uri::Https,
+        mut data: Vec<u8>,
+        gzipped: bool,
+        validators: Option<Validators>,
+        start: Instant,
+    ) -> Result<Bytes, Error> {
+        let err = |kind| Error::new(uri.clone(), kind);
+        if let Some(max) = self.max_size {
+            if data.len() as u64 > max {
+                return Err(err(ErrorKind::TooLarge(max)))
+            }
+        }
+        if gzipped {
+            let limit = self.max_size.map(|max| {
+                usize::try_from(max).unwrap_or(usize::MAX)
+            });
+            data = gzip::decode(&data, limit).map_err(|e| match e {
+                gzip::Error::TooLarge => {
+                    err(ErrorKind::TooLarge(self.max_size.unwrap_or_default()))
+                }
+                e => err(ErrorKind::Gzip(e))
+            })?;
+        }
+        self.set_validators(uri.clone(), validators);
+        debug!(
+            "https: fetched {} ({} bytes{}) in {:.3}s",
+            uri, data.len(), if gzipped { ", gzipped" } else { "" },
+            start.elapsed().as_secs_f64()
+        );
+        Ok(data.into())
+    }
+
+    /// Reports the result of a request to the metrics and progress.
+    fn record(
+        &self, uri: &uri::Https, res: &Result<Option<Bytes>, Error>,
+        start: Instant
+    ) {
+        self.progress.repository_finished(uri.as_str(), res.is_ok());
+        let result = match *res {
+            Ok(Some(ref data)) => {
+                self.metrics.counter(
+                    names::HTTPS_BYTES, &[], data.len() as u64
+                );
+                self.metrics.histogram(
+                    names::HTTPS_DURATION, &[],
+                    start.elapsed().as_secs_f64()
+                );
+                "ok"
+            }
+            Ok(None) => "not_modified",
+            Err(_) => "error",
+        };
+        self.metrics.counter(names::HTTPS_REQUESTS, &[("result", result)], 1);
+    }
+
+    /// Parses a notification file.
+    #[cfg(feature = "rrdp")]
+    fn parse_notification(
+        &self, uri: &uri::Https, data: Bytes
+    ) -> Result<NotificationFile, Error> {
+        NotificationFile::parse(data.as_ref()).map_err(|err| {
+            debug!("https: bad notification file {}: {}", uri, err);
+            self.metrics.counter(names::RRDP_NOTIFICATION_ERRORS, &[], 1);
+            // Don’t keep validators for a broken file or we’d never
+            // try again.
+            self.set_validators(uri.clone(), None);
+            Error::new(uri.clone(), ErrorKind::Xml(err))
+        })
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ Error ---------------------------------------------------------
+
+/// An error happened while fetching via HTTPS.
+#[derive(Debug)]
+pub struct Error {
+    /// The URI that was being fetched.
+    uri: uri::Https,
+
+    /// What went wrong.
+    kind: ErrorKind,
+}
+
+impl Error {
+    /// Creates a new error for the given URI.
+    fn new(uri: uri::Https, kind: ErrorKind) -> Self {
+        Error { uri, kind }
+    }
+
+    /// Returns the URI that was being fetched.
+    pub fn uri(&self) -> &uri::Https {
+        &self.uri
+    }
+
+    /// Returns what went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Converts the error into what went wrong.
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.uri, self.kind)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Transport(ref err) => Some(err.as_ref()),
+            ErrorKind::Io(ref err) => Some(err),
+            ErrorKind::Gzip(ref err) => Some(err),
+            #[cfg(feature = "rrdp")]
+            ErrorKind::Xml(ref err) => Some(err),
+            ErrorKind::Rejected(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind.is_retryable()
+    }
+}
+
+
+//------------ ErrorKind -----------------------------------------------------
+
+/// The kinds of errors that can happen while fetching via HTTPS.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The transport failed to perform the request.
+    Transport(Box<dyn error::Error + Send + Sync>),
+
+    /// The server responded with an unexpected status code.
+    Status(u16),
+
+    /// The server used an unsupported content encoding.
+    Encoding(String),
+
+    /// Reading the response body failed.
+    Io(io::Error),
+
+    /// The response body was not correctly gzip-encoded.
+    Gzip(gzip::Error),
+
+    /// The file exceeded the maximum size.
+    TooLarge(u64),
+
+    /// The notification file was malformed.
+    #[cfg(feature = "rrdp")]
+    Xml(XmlError),
+
+    /// The fetch policy rejected the URI.
+    Rejected(PolicyRejection),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Transport(ref err) => {
+                write!(f, "request failed: {}", err)
+            }
+            ErrorKind::Status(status) => {
+                write!(f, "unexpected status code {}", status)
+            }
+            ErrorKind::Encoding(ref encoding) => {
+                write!(f, "unsupported content encoding '{}'", encoding)
+            }
+            ErrorKind::Io(ref err) => {
+                write!(f, "failed to read response: {}", err)
+            }
+            ErrorKind::Gzip(ref err) => err.fmt(f),
+            ErrorKind::TooLarge(max) => {
+                write!(f, "file exceeds maximum size of {} bytes", max)
+            }
+            #[cfg(feature = "rrdp")]
+            ErrorKind::Xml(ref err) => {
+                write!(f, "malformed notification file: {}", err)
+            }
+            ErrorKind::Rejected(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl Retryable for ErrorKind {
+    /// Returns whether the request should be retried.
+    ///
+    /// Failures of the transport and while reading the body as well as
+    /// server errors, “Request Timeout”, and “Too Many Requests” are
+    /// considered temporary.
+    fn is_retryable(&self) -> bool {
+        match *self {
+            ErrorKind::Transport(_) | ErrorKind::Io(_) => true,
+            ErrorKind::Status(status) => {
+                status >= 500 || status == 408 || status == 429
+            }
+            _ => false,
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use crate::metrics::Metrics;
+    use super::super::policy::HostPolicy;
+    use super::*;
+
+    /// A transport serving a single file with an entity tag.
+    #[derive(Default)]
+    struct TestTransport {
+        data: &'static [u8],
+        gzip: Option<&'static [u8]>,
+        etag: &'static str,
+        requests: Mutex<Vec<Request>>,
+    }
+
+    #[derive(Debug)]
+    struct TestError;
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("test error")
+        }
+    }
+
+    impl error::Error for TestError { }
+
+    impl Transport for TestTransport {
+        type Body = &'static [u8];
+        type Error = TestError;
+
+        fn get(
+            &self, request: &Request
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            self.requests.lock().unwrap().push(request.clone());
+            if request.uri().path() == "/broken" {
+                return Err(TestError)
+            }
+            if request.uri().path() == "/missing" {
+                return Ok(Response::new(404, Vec::new(), b""))
+            }
+            let mut headers = vec![("ETag".into(), self.etag.into())];
+            if request.header("if-none-match") == Some(self.etag) {
+                return Ok(Response::new(304, headers, b""))
+            }
+            match self.gzip {
+                Some(gzip) if request.header("Accept-Encoding").is_some() => {
+                    headers.push(("Content-Encoding".into(), "gzip".into()));
+                    Ok(Response::new(200, headers, gzip))
+                }
+                _ => Ok(Response::new(200, headers, self.data))
+            }
+        }
+    }
+
+    fn uri(s: &str) -> uri::Https {
+        uri::Https::from_str(s).unwrap()
+    }
+
+    const NOTIFICATION: &[u8] = include_bytes!(
+        "../../test-data/rrdp/ripe-notification.xml"
+    );
+    const NOTIFICATION_GZ: &[u8] = include_bytes!(
+        "../../test-data/rrdp/ripe-notification.xml.gz"
+    );
+
+    #[test]
+    fn conditional_requests() {
+        let fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", etag: "\"1\"", .. Default::default()
+        });
+        let uri = uri("https://example.com/notification.xml");
+        assert_eq!(
+            fetcher.fetch_if_modified(&uri).unwrap(),
+            Some(Bytes::from("data"))
+        );
+        assert_eq!(
+            fetcher.validators(&uri),
+            Some(Validators { etag: Some("\"1\"".into()), last_modified: None })
+        );
+        assert_eq!(fetcher.fetch_if_modified(&uri).unwrap(), None);
+        assert_eq!(fetcher.fetch(&uri).unwrap(), Bytes::from("data"));
+
+        fetcher.set_validators(uri.clone(), None);
+        assert!(fetcher.fetch_if_modified(&uri).unwrap().is_some());
+
+        let requests = fetcher.transport().requests.lock().unwrap();
+        assert_eq!(requests[0].header("If-None-Match"), None);
+        assert_eq!(requests[1].header("If-None-Match"), Some("\"1\""));
+        assert_eq!(requests[2].header("If-None-Match"), None);
+        assert_eq!(requests[3].header("If-None-Match"), None);
+    }
+
+    #[test]
+    fn server_names() {
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", etag: "\"1\"", .. Default::default()
+        });
+        let mut config = HttpClientConfig::new();
+        config.set_server_name("example.com", "front.example.net");
+        fetcher.set_client_config(config);
+        fetcher.fetch(&uri("https://example.com:8443/a.xml")).unwrap();
+        fetcher.fetch(&uri("https://example.org/a.xml")).unwrap();
+
+        let requests = fetcher.transport().requests.lock().unwrap();
+        assert_eq!(requests[0].server_name(), Some("front.example.net"));
+        assert_eq!(requests[1].server_name(), None);
+    }
+
+    #[test]
+    fn retry() {
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", etag: "\"1\"", .. Default::default()
+        });
+        let mut policy = RetryPolicy::new(3);
+        policy.set_initial_delay(Duration::ZERO);
+        fetcher.set_retry_policy(policy);
+        assert!(fetcher.fetch(&uri("https://example.com/broken")).is_err());
+        assert!(fetcher.fetch(&uri("https://example.com/missing")).is_err());
+
+        // Transport errors are retried, a 404 isn’t.
+        let requests = fetcher.transport().requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+    }
+
+    #[test]
+    fn policy() {
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", etag: "\"1\"", .. Default::default()
+        });
+        let mut policy = HostPolicy::new();
+        policy.deny("example.com");
+        fetcher.set_policy(SharedPolicy::new(policy));
+        assert!(matches!(
+            fetcher.fetch(&uri("https://example.com/a.xml")).unwrap_err()
+                .kind(),
+            ErrorKind::Rejected(_)
+        ));
+        fetcher.fetch(&uri("https://example.org/a.xml")).unwrap();
+
+        // The rejected URI was never requested.
+        let requests = fetcher.transport().requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn fetch_limits() {
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: NOTIFICATION, .. Default::default()
+        });
+        let limits = FetchLimits::new(Some(1), None, Some(u32::MAX.into()));
+        fetcher.set_limits(limits.clone());
+        let uri = uri("https://example.com/notification.xml");
+        assert_eq!(fetcher.fetch(&uri).unwrap().as_ref(), NOTIFICATION);
+        assert_eq!(limits.running(), 0);
+    }
+
+    #[test]
+    fn gzip_and_limits() {
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: NOTIFICATION, gzip: Some(NOTIFICATION_GZ),
+            .. Default::default()
+        });
+        let uri = uri("https://example.com/notification.xml");
+        assert_eq!(fetcher.fetch(&uri).unwrap().as_ref(), NOTIFICATION);
+
+        fetcher.set_max_size(Some(NOTIFICATION.len() as u64 - 1));
+        assert!(matches!(
+            fetcher.fetch(&uri).unwrap_err().kind(), ErrorKind::TooLarge(_)
+        ));
+        fetcher.set_gzip(false);
+        assert!(matches!(
+            fetcher.fetch(&uri).unwrap_err().kind(), ErrorKind::TooLarge(_)
+        ));
+        fetcher.set_max_size(Some(NOTIFICATION.len() as u64));
+        assert_eq!(fetcher.fetch(&uri).unwrap().as_ref(), NOTIFICATION);
+    }
+
+    #[test]
+    fn metrics() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Metrics for Recorder {
+            fn counter(
+                &self, name: &str, labels: &[(&str, &str)], value: u64
+            ) {
+                self.0.lock().unwrap().push(
+                    format!("{} {:?} {}", name, labels, value)
+                );
+            }
+        }
+
+        let recorder = std::sync::Arc::new(Recorder::default());
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", etag: "\"1\"", .. Default::default()
+        });
+        fetcher.set_metrics(recorder.clone().into());
+        let uri = uri("https://example.com/notification.xml");
+        fetcher.fetch_if_modified(&uri).unwrap();
+        fetcher.fetch_if_modified(&uri).unwrap();
+        fetcher.fetch(
+            &self::uri("https://example.com/missing")
+        ).unwrap_err();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "rpki_https_received_bytes_total [] 4",
+                "rpki_https_requests_total [(\"result\", \"ok\")] 1",
+                "rpki_https_requests_total \
+                    [(\"result\", \"not_modified\")] 1",
+                "rpki_https_requests_total [(\"result\", \"error\")] 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn progress() {
+        use crate::progress::ProgressCounter;
+
+        let counter = std::sync::Arc::new(ProgressCounter::new());
+        let mut fetcher = HttpsFetcher::new(TestTransport {
+            data: b"data", .. Default::default()
+        });
+        fetcher.set_progress(counter.clone().into());
+        fetcher.fetch(&uri("https://example.com/notification.xml")).unwrap();
+        fetcher.fetch(&uri("https://example.com/missing")).unwrap_err();
+        assert_eq!(counter.repositories(), 1);
+        assert_eq!(counter.failed_repositories(), 1);
+        assert_eq!(counter.bytes(), 4);
+        assert!(counter.current().is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let fetcher = HttpsFetcher::new(TestTransport::default());
+        assert!(matches!(
+            fetcher.fetch(&uri("https://example.com/missing")).unwrap_err()
+                .kind(),
+            ErrorKind::Status(404)
+        ));
+        assert!(matches!(
+            fetcher.fetch(&uri("https://example.com/broken")).unwrap_err()
+                .kind(),
+            ErrorKind::Transport(_)
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    impl AsyncTransport for TestTransport {
+        type Body = &'static [u8];
+        type Error = TestError;
+
+        fn get<'a>(
+            &'a self, request: &'a Request
+        ) -> ResponseFuture<'a, Self::Body, Self::Error> {
+            Box::pin(async move { Transport::get(self, request) })
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "async", feature = "rrdp"))]
+    async fn notification_async() {
+        let fetcher = HttpsFetcher::new(TestTransport {
+            data: NOTIFICATION, gzip: Some(NOTIFICATION_GZ), etag: "\"x\"",
+            .. Default::default()
+        });
+        let uri = uri("https://example.com/notification.xml");
+        let notify = fetcher.notification_async(&uri).await.unwrap().unwrap();
+        assert_eq!(
+            notify, NotificationFile::parse(NOTIFICATION).unwrap()
+        );
+        assert!(fetcher.notification_async(&uri).await.unwrap().is_none());
+        assert_eq!(
+            fetcher.fetch_async(&uri).await.unwrap().as_ref(), NOTIFICATION
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rrdp")]
+    fn notification() {
+        let fetcher = HttpsFetcher::new(TestTransport {
+            data: NOTIFICATION, gzip: Some(NOTIFICATION_GZ), etag: "\"x\"",
+            .. Default::default()
+        });
+        let uri = uri("https://example.com/notification.xml");
+        let notify = fetcher.notification(&uri).unwrap().unwrap();
+        assert_eq!(
+            notify, NotificationFile::parse(NOTIFICATION).unwrap()
+        );
+        assert!(fetcher.notification(&uri).unwrap().is_none());
+    }
+}
@@ -0,0 +1,365 @@
+//! Limiting concurrency and bandwidth of fetching.
+//!
+//! A relying party fetches from hundreds of repositories. Doing so with
+//! unlimited concurrency can overload both the local network and the
+//! repository servers, which in turn may rate-limit or block the relying
+//! party. [`FetchLimits`] restricts the number of transfers running at
+//! the same time, both in total and per host, and can throttle the
+//! combined bandwidth of all transfers.
+//!
+//! The limits are shared: cloning a value of the type produces a handle
+//! to the same limits. Giving clones to both the
+//! [HTTPS][super::https::HttpsFetcher] and
+//! [rsync][super::rsync::RsyncFetcher] fetchers makes the total limit
+//! apply to all transfers.
+
+use std::{cmp, fmt};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+
+//------------ FetchLimits ---------------------------------------------------
+
+/// Shared limits on concurrency and bandwidth of fetching.
+///
+/// By default, there are no limits.
+#[derive(Clone, Default)]
+pub struct FetchLimits {
+    inner: Arc<Inner>,
+}
+
+/// The shared part of the limits.
+#[derive(Default)]
+struct Inner {
+    /// The maximum number of concurrent transfers.
+    max_total: Option<usize>,
+
+    /// The maximum number of concurrent transfers per host.
+    max_per_host: Option<usize>,
+
+    /// The maximum combined bandwidth in bytes per second.
+    bandwidth: Option<u64>,
+
+    /// The currently running transfers.
+    running: Mutex<Running>,
+
+    /// Signalled whenever a transfer finishes.
+    finished: Condvar,
+
+    /// Notified whenever a transfer finishes for async waiters.
+    #[cfg(feature = "async")]
+    finished_async: tokio::sync::Notify,
+
+    /// The token bucket for bandwidth throttling.
+    bucket: Mutex<Option<Bucket>>,
+}
+
+/// The currently running transfers.
+#[derive(Default)]
+struct Running {
+    /// The total number of running transfers.
+    total: usize,
+
+    /// The number of running transfers per host.
+    hosts: HashMap<String, usize>,
+}
+
+impl FetchLimits {
+    /// Creates new limits.
+    ///
+    /// The `max_total` and `max_per_host` limit the number of transfers
+    /// in total and per host. The `bandwidth` limits the combined
+    /// bandwidth of all HTTPS transfers in bytes per second. For rsync,
+    /// it is passed to each rsync process. A value of `None` means no
+    /// limit.
+    pub fn new(
+        max_total: Option<usize>,
+        max_per_host: Option<usize>,
+        bandwidth: Option<u64>,
+    ) -> Self {
+        FetchLimits {
+            inner: Arc::new(Inner {
+                max_total: max_total.map(|max| cmp::max(max, 1)),
+                max_per_host: max_per_host.map(|max| cmp::max(max, 1)),
+                bandwidth: bandwidth.map(|max| cmp::max(max, 1)),
+                .. Default::default()
+            })
+        }
+    }
+
+    /// Creates limits that don’t limit anything.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Returns the maximum number of concurrent transfers.
+    pub fn max_total(&self) -> Option<usize> {
+        self.inner.max_total
+    }
+
+    /// Returns the maximum number of concurrent transfers per host.
+    pub fn max_per_host(&self) -> Option<usize> {
+        self.inner.max_per_host
+    }
+
+    /// Returns the maximum bandwidth in bytes per second.
+    pub fn bandwidth(&self) -> Option<u64> {
+        self.inner.bandwidth
+    }
+
+    /// Returns the number of transfers currently running.
+    pub fn running(&self) -> usize {
+        self.inner.running.lock().unwrap().total
+    }
+
+    /// Tries to start a transfer from the given host.
+    ///
+    /// Returns a permit if the limits allow another transfer. The transfer
+    /// counts as running until the permit is dropped.
+    pub fn try_acquire(&self, host: &str) -> Option<FetchPermit> {
+        let host = host.to_ascii_lowercase();
+        let mut running = self.inner.running.lock().unwrap();
+        if !self.has_capacity(&running, &host) {
+            return None
+        }
+        running.total += 1;
+        *running.hosts.entry(host.clone()).or_default() += 1;
+        Some(FetchPermit { limits: self.clone(), host })
+    }
+
+    /// Starts a transfer from the given host.
+    ///
+    /// Blocks the current thread until the limits allow another transfer.
+    pub fn acquire(&self, host: &str) -> FetchPermit {
+        let host = host.to_ascii_lowercase();
+        let mut running = self.inner.running.lock().unwrap();
+        while !self.has_capacity(&running, &host) {
+            running = self.inner.finished.wait(running).unwrap();
+        }
+        running.total += 1;
+        *running.hosts.entry(host.clone()).or_default() += 1;
+        FetchPermit { limits: self.clone(), host }
+    }
+
+    /// Asynchronously starts a transfer from the given host.
+    ///
+    /// This is the asynchronous version of [`acquire`][Self::acquire].
+    #[cfg(feature = "async")]
+    pub async fn acquire_async(&self, host: &str) -> FetchPermit {
+        loop {
+            // The notified future receives notifications as soon as it
+            // is created, so we can’t miss a finishing transfer between
+            // checking and waiting.
+            let finished = self.inner.finished_async.notified();
+            if let Some(permit) = self.try_acquire(host) {
+                return permit
+            }
+            finished.await;
+        }
+    }
+
+    /// Returns whether another transfer from `host` can be started.
+    fn has_capacity(&self, running: &Running, host: &str) -> bool {
+        if let Some(max) = self.inner.max_total {
+            if running.total >= max {
+                return false
+            }
+        }
+        if let Some(max) = self.inner.max_per_host {
+            if running.hosts.get(host).copied().unwrap_or(0) >= max {
+                return false
+            }
+        }
+        true
+    }
+
+    /// Releases a transfer.
+    fn release(&self, host: &str) {
+        let mut running = self.inner.running.lock().unwrap();
+        running.total = running.total.saturating_sub(1);
+        if let Some(count) = running.hosts.get_mut(host) {
+            *count -= 1;
+            if *count == 0 {
+                running.hosts.remove(host);
+            }
+        }
+        drop(running);
+        self.inner.finished.notify_all();
+        #[cfg(feature = "async")]
+        self.inner.finished_async.notify_waiters();
+    }
+
+    /// Accounts for transferred data and returns how long to pause.
+    ///
+    /// Returns `None` if there is no need to pause.
+    pub fn reserve(&self, bytes: usize) -> Option<Duration> {
+        let rate = self.inner.bandwidth?;
+        let mut bucket = self.inner.bucket.lock().unwrap();
+        let bucket = bucket.get_or_insert_with(|| Bucket::new(rate));
+        bucket.reserve(bytes as u64, rate, Instant::now())
+    }
+
+    /// Accounts for transferred data, pausing if necessary.
+    ///
+    /// Blocks the current thread if the bandwidth limit is exceeded.
+    pub fn throttle(&self, bytes: usize) {
+        if let Some(pause) = self.reserve(bytes) {
+            std::thread::sleep(pause)
+        }
+    }
+
+    /// Asynchronously accounts for transferred data, pausing if necessary.
+    ///
+    /// This is the asynchronous version of [`throttle`][Self::throttle].
+    #[cfg(feature = "async")]
+    pub async fn throttle_async(&self, bytes: usize) {
+        if let Some(pause) = self.reserve(bytes) {
+            tokio::time::sleep(pause).await
+        }
+    }
+}
+
+impl fmt::Debug for FetchLimits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FetchLimits")
+            .field("max_total", &self.inner.max_total)
+            .field("max_per_host", &self.inner.max_per_host)
+            .field("bandwidth", &self.inner.bandwidth)
+            .field("running", &self.running())
+            .finish()
+    }
+}
+
+
+//------------ FetchPermit ---------------------------------------------------
+
+/// Permission to run a transfer.
+///
+/// The transfer counts against the limits until the permit is dropped.
+#[derive(Debug)]
+pub struct FetchPermit {
+    /// The limits the permit was acquired from.
+    limits: FetchLimits,
+
+    /// The host the transfer is for.
+    host: String,
+}
+
+impl FetchPermit {
+    /// Returns the host the transfer is for.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+}
+
+impl Drop for FetchPermit {
+    fn drop(&mut self) {
+        self.limits.release(&self.host)
+    }
+}
+
+
+//------------ Bucket --------------------------------------------------------
+
+/// A token bucket for throttling bandwidth.
+///
+/// The bucket holds at most one second worth of bytes. Transfers take
+/// bytes out of the bucket and may drive it into debt which then needs
+/// to be waited out.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    /// The number of bytes available, may be negative.
+    available: f64,
+
+    /// When the bucket was last refilled.
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Self {
+        Bucket { available: rate as f64, refilled: Instant::now() }
+    }
+
+    fn reserve(
+        &mut self, bytes: u64, rate: u64, now: Instant
+    ) -> Option<Duration> {
+        let rate = rate as f64;
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.available = (
+            self.available + elapsed.as_secs_f64() * rate
+        ).min(rate);
+        self.refilled = now;
+        self.available -= bytes as f64;
+        if self.available < 0. {
+            Some(Duration::from_secs_f64(-self.available / rate))
+        }
+        else {
+            None
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn concurrency() {
+        let limits = FetchLimits::new(Some(3), Some(2), None);
+        let a1 = limits.try_acquire("a.example").unwrap();
+        let _a2 = limits.try_acquire("A.example").unwrap();
+        assert!(limits.try_acquire("a.example").is_none());
+        let _b1 = limits.try_acquire("b.example").unwrap();
+        assert!(limits.try_acquire("c.example").is_none());
+        assert_eq!(limits.running(), 3);
+        drop(a1);
+        assert_eq!(limits.running(), 2);
+        let _c1 = limits.try_acquire("c.example").unwrap();
+
+        let unlimited = FetchLimits::unlimited();
+        let permits: Vec<_> = (0..100).map(|_| {
+            unlimited.try_acquire("a.example").unwrap()
+        }).collect();
+        assert_eq!(unlimited.running(), permits.len());
+    }
+
+    #[test]
+    fn acquire_blocks() {
+        let limits = FetchLimits::new(Some(1), None, None);
+        let permit = limits.acquire("a.example");
+        let other = limits.clone();
+        let thread = std::thread::spawn(move || {
+            other.acquire("b.example").host().to_string()
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(limits.running(), 1);
+        drop(permit);
+        assert_eq!(thread.join().unwrap(), "b.example");
+        assert_eq!(limits.running(), 0);
+    }
+
+    #[test]
+    fn bucket() {
+        let start = Instant::now();
+        let mut bucket = Bucket { available: 1000., refilled: start };
+        assert_eq!(bucket.reserve(600, 1000, start), None);
+        assert_eq!(
+            bucket.reserve(600, 1000, start),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            bucket.reserve(100, 1000, start + Duration::from_millis(300)),
+            None
+        );
+        // The bucket doesn’t fill beyond one second of data.
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.reserve(1000, 1000, later), None);
+        assert!(bucket.reserve(1, 1000, later).is_some());
+
+        assert_eq!(FetchLimits::unlimited().reserve(1_000_000), None);
+    }
+}
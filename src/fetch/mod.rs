@@ -0,0 +1,30 @@
+//! Fetching repository content.
+//!
+//! This module contains the components a relying party needs to retrieve
+//! the content of RPKI repositories from the publication points of the
+//! various CAs. Content is retrieved into an [object store][crate::store]
+//! which is then used as the source for validation.
+//!
+//! The [`access`] module helps deciding between RRDP and rsync for a
+//! publication point. The [`retry`] module provides a policy for retrying
+//! failed transfers and the [`limit`] module limits their concurrency and
+//! bandwidth. The [`policy`] module decides which hosts and URIs may be
+//! contacted at all and the [`dead`] module skips repositories that keep
+//! failing.
+//!
+//! Currently, the following transports are supported:
+//!
+//! * [`https`]: retrieval of RRDP files via HTTPS using a transport
+//!   provided by the user, and
+//! * [`rsync`]: retrieval via the rsync protocol by running an external
+//!   rsync binary.
+
+#![cfg(feature = "fetch")]
+
+pub mod access;
+pub mod dead;
+pub mod https;
+pub mod limit;
+pub mod policy;
+pub mod retry;
+pub mod rsync;
@@ -0,0 +1,368 @@
+//! Deciding which repositories may be contacted.
+//!
+//! The URIs of repositories are taken from certificates and thus are
+//! chosen by the CAs. An operator may not want a relying party to contact
+//! every host mentioned there: addresses in private networks should
+//! usually not be reachable from the outside, and some hosts may be known
+//! to misbehave. A [`FetchPolicy`] is consulted by the fetchers before any
+//! connection is made and can veto contacting a URI. A vetoed fetch fails
+//! with a [`PolicyRejection`].
+//!
+//! The [`HostPolicy`] provides the common case of allow and deny lists of
+//! host names and the rejection of literal addresses from private and
+//! special purpose ranges. Any closure taking a [`FetchTarget`] can be
+//! used as a policy, too.
+
+use std::{error, fmt};
+use std::net::IpAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use crate::uri;
+
+
+//------------ FetchPolicy ---------------------------------------------------
+
+/// A type deciding whether a URI may be fetched.
+pub trait FetchPolicy: Send + Sync {
+    /// Checks whether the target may be fetched.
+    fn check(&self, target: FetchTarget) -> Result<(), PolicyRejection>;
+}
+
+impl<F> FetchPolicy for F
+where F: Fn(FetchTarget) -> Result<(), PolicyRejection> + Send + Sync {
+    fn check(&self, target: FetchTarget) -> Result<(), PolicyRejection> {
+        (self)(target)
+    }
+}
+
+
+//------------ AllowAll ------------------------------------------------------
+
+/// A policy allowing everything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+impl FetchPolicy for AllowAll {
+    fn check(&self, _target: FetchTarget) -> Result<(), PolicyRejection> {
+        Ok(())
+    }
+}
+
+
+//------------ SharedPolicy --------------------------------------------------
+
+/// A shared reference to a policy implementation.
+///
+/// This is the type the fetchers keep their policy in. The default value
+/// uses [`AllowAll`].
+#[derive(Clone)]
+pub struct SharedPolicy(Arc<dyn FetchPolicy>);
+
+impl SharedPolicy {
+    /// Creates a new value from a policy implementation.
+    pub fn new(policy: impl FetchPolicy + 'static) -> Self {
+        SharedPolicy(Arc::new(policy))
+    }
+}
+
+impl Default for SharedPolicy {
+    fn default() -> Self {
+        SharedPolicy::new(AllowAll)
+    }
+}
+
+impl<P: FetchPolicy + 'static> From<Arc<P>> for SharedPolicy {
+    fn from(policy: Arc<P>) -> Self {
+        SharedPolicy(policy)
+    }
+}
+
+impl Deref for SharedPolicy {
+    type Target = dyn FetchPolicy;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for SharedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedPolicy")
+    }
+}
+
+
+//------------ FetchTarget ---------------------------------------------------
+
+/// A URI about to be fetched.
+#[derive(Clone, Copy, Debug)]
+pub enum FetchTarget<'a> {
+    /// A directory to be fetched via rsync.
+    Rsync(&'a uri::Rsync),
+
+    /// A file to be fetched via HTTPS.
+    Https(&'a uri::Https),
+}
+
+impl<'a> FetchTarget<'a> {
+    /// Returns the host part of the URI.
+    ///
+    /// For IPv6 addresses, the host includes the square brackets.
+    pub fn host(self) -> &'a str {
+        match self {
+            FetchTarget::Rsync(uri) => {
+                crate::util::http::host(uri.authority())
+            }
+            FetchTarget::Https(uri) => {
+                crate::util::http::host(uri.authority())
+            }
+        }
+    }
+
+    /// Returns the host as an IP address if it is a literal address.
+    pub fn ip_addr(self) -> Option<IpAddr> {
+        let host = self.host();
+        let host = host.strip_prefix('[').and_then(|host| {
+            host.strip_suffix(']')
+        }).unwrap_or(host);
+        host.parse().ok()
+    }
+
+    /// Returns the URI as a string.
+    pub fn as_str(self) -> &'a str {
+        match self {
+            FetchTarget::Rsync(uri) => uri.as_str(),
+            FetchTarget::Https(uri) => uri.as_str(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for FetchTarget<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ HostPolicy ----------------------------------------------------
+
+/// A policy based on host names and addresses.
+///
+/// The policy has a deny list and an optional allow list of host names.
+/// Entries match the host exactly or, if they start with a dot, any host
+/// ending in them. A host is rejected if it matches the deny list or if
+/// there is an allow list and it doesn’t match it.
+///
+/// In addition, the policy can reject literal addresses from private,
+/// loopback, link-local, and other special purpose ranges. Since host
+/// names are resolved by the transport only after the policy has been
+/// consulted, names resolving to such addresses are not caught by this.
+#[derive(Clone, Debug, Default)]
+pub struct HostPolicy {
+    /// The hosts that are allowed if this isn’t `None`.
+    allow: Option<Vec<String>>,
+
+    /// The hosts that are denied.
+    deny: Vec<String>,
+
+    /// Whether to reject literal addresses from special ranges.
+    deny_special: bool,
+}
+
+impl HostPolicy {
+    /// Creates a new policy that allows everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a host to the allow list.
+    ///
+    /// Once the first host has been added, only hosts on the list are
+    /// allowed.
+    pub fn allow(&mut self, host: &str) {
+        self.allow.get_or_insert_with(Vec::new).push(
+            host.to_ascii_lowercase()
+        )
+    }
+
+    /// Adds a host to the deny list.
+    pub fn deny(&mut self, host: &str) {
+        self.deny.push(host.to_ascii_lowercase())
+    }
+
+    /// Sets whether literal addresses from special ranges are rejected.
+    pub fn set_deny_special(&mut self, deny: bool) {
+        self.deny_special = deny
+    }
+
+    /// Returns whether a host matches a list.
+    fn matches(list: &[String], host: &str) -> bool {
+        list.iter().any(|item| {
+            if item.starts_with('.') {
+                host.ends_with(item.as_str())
+            }
+            else {
+                host == item
+            }
+        })
+    }
+}
+
+impl FetchPolicy for HostPolicy {
+    fn check(&self, target: FetchTarget) -> Result<(), PolicyRejection> {
+        let host = target.host().to_ascii_lowercase();
+        if Self::matches(&self.deny, &host) {
+            return Err(PolicyRejection::new(
+                format!("host {} is denied", host)
+            ))
+        }
+        if let Some(ref allow) = self.allow {
+            if !Self::matches(allow, &host) {
+                return Err(PolicyRejection::new(
+                    format!("host {} is not allowed", host)
+                ))
+            }
+        }
+        if self.deny_special {
+            if let Some(addr) = target.ip_addr() {
+                if is_special(addr) {
+                    return Err(PolicyRejection::new(
+                        format!("address {} is in a special range", addr)
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns whether an address is not globally reachable.
+fn is_special(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => {
+            let octets = addr.octets();
+            addr.is_private() || addr.is_loopback() || addr.is_link_local()
+            || addr.is_unspecified() || addr.is_broadcast()
+            || addr.is_documentation() || addr.is_multicast()
+            // 100.64.0.0/10, shared address space (RFC 6598)
+            || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+            // 0.0.0.0/8, “this network”
+            || octets[0] == 0
+        }
+        IpAddr::V6(addr) => {
+            let first = addr.segments()[0];
+            if let Some(addr) = addr.to_ipv4_mapped() {
+                return is_special(IpAddr::V4(addr))
+            }
+            addr.is_loopback() || addr.is_unspecified()
+            || addr.is_multicast()
+            // fc00::/7, unique local addresses
+            || first & 0xfe00 == 0xfc00
+            // fe80::/10, link-local addresses
+            || first & 0xffc0 == 0xfe80
+            // 2001:db8::/32, documentation
+            || (first == 0x2001 && addr.segments()[1] == 0x0db8)
+        }
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ PolicyRejection -----------------------------------------------
+
+/// A fetch was rejected by the policy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PolicyRejection {
+    /// Why the fetch was rejected.
+    reason: String,
+}
+
+impl PolicyRejection {
+    /// Creates a new rejection for the given reason.
+    pub fn new(reason: impl Into<String>) -> Self {
+        PolicyRejection { reason: reason.into() }
+    }
+
+    /// Returns the reason for the rejection.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for PolicyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rejected by policy: {}", self.reason)
+    }
+}
+
+impl error::Error for PolicyRejection { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn check(policy: &dyn FetchPolicy, uri: &str) -> bool {
+        if let Ok(uri) = uri::Https::from_str(uri) {
+            policy.check(FetchTarget::Https(&uri)).is_ok()
+        }
+        else {
+            let uri = uri::Rsync::from_str(uri).unwrap();
+            policy.check(FetchTarget::Rsync(&uri)).is_ok()
+        }
+    }
+
+    #[test]
+    fn host_policy() {
+        let mut policy = HostPolicy::new();
+        assert!(check(&policy, "https://10.0.0.1/notify.xml"));
+        policy.set_deny_special(true);
+        policy.deny(".bad.example");
+        assert!(!check(&policy, "https://10.0.0.1/notify.xml"));
+        assert!(!check(&policy, "rsync://192.168.1.1/module/"));
+        assert!(check(&policy, "rsync://193.0.0.1/module/"));
+        assert!(!check(&policy, "rsync://rpki.BAD.example/module/"));
+        assert!(check(&policy, "rsync://bad.example/module/"));
+
+        policy.allow("rpki.example.net");
+        assert!(check(&policy, "https://rpki.example.net/notify.xml"));
+        assert!(!check(&policy, "https://rpki.example.com/notify.xml"));
+    }
+
+    #[test]
+    fn special_addrs() {
+        fn special(addr: &str) -> bool {
+            is_special(IpAddr::from_str(addr).unwrap())
+        }
+
+        assert!(special("100.64.0.1"));
+        assert!(special("169.254.0.1"));
+        assert!(!special("100.128.0.1"));
+        assert!(special("fd00::1"));
+        assert!(special("fe80::1"));
+        assert!(special("::ffff:127.0.0.1"));
+        assert!(special("2001:db8::1"));
+        assert!(!special("2001:67c::1"));
+    }
+
+    #[test]
+    fn closure_policy() {
+        let policy = SharedPolicy::new(|target: FetchTarget| {
+            if target.as_str().ends_with(".xml") {
+                Ok(())
+            }
+            else {
+                Err(PolicyRejection::new("not XML"))
+            }
+        });
+        assert!(check(&*policy, "https://example.net/notify.xml"));
+        assert!(!check(&*policy, "https://example.net/notify.json"));
+    }
+}
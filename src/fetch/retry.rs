@@ -0,0 +1,314 @@
+//! Retrying failed network operations.
+//!
+//! Fetching from remote repositories fails every now and then for reasons
+//! that go away by themselves: a connection is reset, a server is briefly
+//! overloaded, an rsync daemon hits its connection limit. A
+//! [`RetryPolicy`] describes how often and after which delay such
+//! operations should be retried. Whether an error is worth retrying is
+//! decided by the error itself via the [`Retryable`] trait.
+//!
+//! The [HTTPS][super::https::HttpsFetcher] and
+//! [rsync][super::rsync::RsyncFetcher] fetchers accept a policy. Other
+//! clients, such as those for the publication and provisioning protocols,
+//! can use [`RetryPolicy::retry`] directly.
+
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+#[cfg(feature = "async")] use std::future::Future;
+use log::debug;
+
+
+//------------ RetryPolicy ---------------------------------------------------
+
+/// A policy for retrying failed operations.
+///
+/// The policy allows for a maximum number of attempts. Before each retry,
+/// it waits for a delay that starts at the initial delay and is doubled
+/// for every retry up to the maximum delay. If jitter is enabled, which it
+/// is by default, the actual delay is a random duration between half the
+/// delay and the full delay so that many clients failing at the same time
+/// don’t all retry at the same time.
+///
+/// The default policy makes only a single attempt, i.e., it never retries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts including the first one.
+    max_attempts: u32,
+
+    /// The delay before the first retry.
+    initial_delay: Duration,
+
+    /// The maximum delay before a retry.
+    max_delay: Duration,
+
+    /// Whether to randomize the delay.
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a new policy with the given maximum number of attempts.
+    ///
+    /// The policy starts with an initial delay of one second and a
+    /// maximum delay of one minute. A value of zero for `max_attempts`
+    /// is treated as one.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: cmp::max(max_attempts, 1),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: true,
+        }
+    }
+
+    /// Creates a policy that never retries.
+    pub fn never() -> Self {
+        Self::new(1)
+    }
+
+    /// Returns the maximum number of attempts.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Sets the maximum number of attempts.
+    pub fn set_max_attempts(&mut self, max_attempts: u32) {
+        self.max_attempts = cmp::max(max_attempts, 1)
+    }
+
+    /// Returns the delay before the first retry.
+    pub fn initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn set_initial_delay(&mut self, delay: Duration) {
+        self.initial_delay = delay
+    }
+
+    /// Returns the maximum delay before a retry.
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    /// Sets the maximum delay before a retry.
+    pub fn set_max_delay(&mut self, delay: Duration) {
+        self.max_delay = delay
+    }
+
+    /// Returns whether the delay is randomized.
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+
+    /// Sets whether the delay is randomized.
+    pub fn set_jitter(&mut self, jitter: bool) {
+        self.jitter = jitter
+    }
+
+    /// Returns the delay before the given retry without jitter.
+    ///
+    /// The first retry is retry 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(
+            retry.saturating_sub(1)
+        ).unwrap_or(u32::MAX);
+        cmp::min(
+            self.initial_delay.saturating_mul(factor), self.max_delay
+        )
+    }
+
+    /// Returns the actual delay before the given retry.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self.backoff(retry);
+        if !self.jitter || delay.is_zero() {
+            return delay
+        }
+        // We don’t need good randomness here, just enough to spread out
+        // retries, so we use the random keys of the standard library’s
+        // hash maps rather than pulling in a dependency.
+        let random = RandomState::new().build_hasher().finish();
+        let half = delay / 2;
+        half + Duration::from_nanos(
+            random % cmp::max(half.as_nanos() as u64, 1)
+        )
+    }
+
+    /// Returns whether another attempt should be made after an error.
+    fn should_retry<E: Retryable>(&self, attempt: u32, err: &E) -> bool {
+        attempt < self.max_attempts && err.is_retryable()
+    }
+
+    /// Runs an operation, retrying it according to the policy.
+    ///
+    /// The operation is run until it succeeds, it fails with an error that
+    /// isn’t retryable, or the maximum number of attempts is reached. In
+    /// the latter two cases, the last error is returned. The current
+    /// thread sleeps between attempts.
+    pub fn retry<T, E, F>(&self, mut op: F) -> Result<T, E>
+    where
+        E: Retryable,
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(err) if self.should_retry(attempt, &err) => {
+                    let delay = self.delay(attempt);
+                    debug!(
+                        "retry: attempt {} failed, retrying in {:.3}s",
+                        attempt, delay.as_secs_f64()
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                res => return res
+            }
+        }
+    }
+
+    /// Asynchronously runs an operation, retrying it per the policy.
+    ///
+    /// This is the asynchronous version of [`retry`][Self::retry]. It
+    /// uses Tokio’s timer for waiting between attempts.
+    #[cfg(feature = "async")]
+    pub async fn retry_async<T, E, F, Fut>(&self, mut op: F) -> Result<T, E>
+    where
+        E: Retryable,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(err) if self.should_retry(attempt, &err) => {
+                    let delay = self.delay(attempt);
+                    debug!(
+                        "retry: attempt {} failed, retrying in {:.3}s",
+                        attempt, delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res
+            }
+        }
+    }
+}
+
+
+//------------ Retryable -----------------------------------------------------
+
+/// An error that may go away when trying again.
+pub trait Retryable {
+    /// Returns whether the failed operation should be retried.
+    fn is_retryable(&self) -> bool;
+}
+
+impl<T: Retryable + ?Sized> Retryable for &T {
+    fn is_retryable(&self) -> bool {
+        (*self).is_retryable()
+    }
+}
+
+impl Retryable for std::io::Error {
+    fn is_retryable(&self) -> bool {
+        use std::io::ErrorKind;
+
+        matches!(
+            self.kind(),
+            ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe | ErrorKind::TimedOut
+            | ErrorKind::Interrupted | ErrorKind::UnexpectedEof
+        )
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Eq, PartialEq)]
+    struct TestError(bool);
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            self.0
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        let mut res = RetryPolicy::new(max_attempts);
+        res.set_initial_delay(Duration::ZERO);
+        res
+    }
+
+    #[test]
+    fn backoff() {
+        let mut policy = RetryPolicy::new(10);
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(4), Duration::from_secs(8));
+        assert_eq!(policy.backoff(8), Duration::from_secs(60));
+        assert_eq!(policy.backoff(100), Duration::from_secs(60));
+        for retry in 1..10 {
+            let delay = policy.delay(retry);
+            assert!(delay <= policy.backoff(retry));
+            assert!(delay >= policy.backoff(retry) / 2);
+        }
+        policy.set_jitter(false);
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn retry() {
+        let mut attempts = 0;
+        assert_eq!(
+            policy(3).retry(|| {
+                attempts += 1;
+                if attempts < 3 { Err(TestError(true)) } else { Ok(attempts) }
+            }),
+            Ok(3)
+        );
+
+        let mut attempts = 0;
+        assert_eq!(
+            policy(3).retry(|| -> Result<(), _> {
+                attempts += 1;
+                Err(TestError(true))
+            }),
+            Err(TestError(true))
+        );
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        assert_eq!(
+            policy(3).retry(|| -> Result<(), _> {
+                attempts += 1;
+                Err(TestError(false))
+            }),
+            Err(TestError(false))
+        );
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        assert!(
+            RetryPolicy::default().retry(|| -> Result<(), _> {
+                attempts += 1;
+                Err(TestError(true))
+            }).is_err()
+        );
+        assert_eq!(attempts, 1);
+    }
+}
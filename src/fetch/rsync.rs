@@ -0,0 +1,997 @@
+//! Retrieving repository content via rsync.
+//!
+//! The rsync protocol is the original transport for RPKI repositories and
+//! still serves as the fallback when RRDP isn’t available. Since there is
+//! no usable native implementation of the protocol, the [`RsyncFetcher`]
+//! runs an external rsync binary to update a local mirror of a remote
+//! directory and then imports the content of that mirror into an
+//! [`ObjectStore`].
+//!
+//! The local mirror is kept between runs so that rsync only needs to
+//! transfer changes. It lives in a cache directory given when creating the
+//! fetcher. Each remote directory is mirrored into a path constructed from
+//! its URI, see [`RsyncFetcher::local_path`] for details.
+
+use std::{cmp, error, fmt, fs, io, thread};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use log::{debug, warn};
+use crate::uri;
+use crate::metrics::{SharedMetrics, names};
+use crate::progress::SharedProgress;
+use crate::store::{ObjectStore, StoreError};
+use crate::store::fs::FsStore;
+use super::limit::FetchLimits;
+use super::policy::{FetchTarget, PolicyRejection, SharedPolicy};
+use super::retry::{RetryPolicy, Retryable};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The file extensions of objects used in RPKI.
+///
+/// If filtering is enabled, only files with these extensions are
+/// transferred by default.
+pub const RPKI_EXTENSIONS: &[&str] = &[
+    "asa", "cer", "crl", "gbr", "mft", "roa", "sig", "tak",
+];
+
+/// The default arguments passed to the rsync command.
+const DEFAULT_ARGS: &[&str] = &["-rltz", "--delete"];
+
+/// How often we check whether a running rsync process has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+
+//------------ RsyncFetcher --------------------------------------------------
+
+/// A fetcher for repository content via rsync.
+///
+/// The fetcher is created via [`new`][Self::new] from the path of the
+/// local cache directory and can then be configured through a number of
+/// setter methods.
+///
+/// By default, the command `rsync` is run with the arguments `-rltz
+/// --delete`, only files ending in one of the extensions in
+/// [`RPKI_EXTENSIONS`] are transferred, and there is no timeout. A timeout
+/// can be set both as a default for all modules and specifically for
+/// individual modules.
+///
+/// The method [`fetch_into`][Self::fetch_into] updates the local mirror of
+/// a remote directory and then updates an object store with its content.
+/// The two steps are also available separately via [`fetch`][Self::fetch]
+/// and [`import`][Self::import].
+#[derive(Clone, Debug)]
+pub struct RsyncFetcher {
+    /// The directory where the local mirrors are kept.
+    cache_dir: PathBuf,
+
+    /// The rsync command to run.
+    command: OsString,
+
+    /// The arguments to pass to the command.
+    ///
+    /// If this is `None`, the default arguments are used.
+    args: Option<Vec<OsString>>,
+
+    /// The extensions of the files to be transferred.
+    ///
+    /// If this is `None`, all files are transferred.
+    extensions: Option<Vec<String>>,
+
+    /// The default timeout for running an rsync process.
+    timeout: Option<Duration>,
+
+    /// Timeouts for specific modules.
+    ///
+    /// The key is the canonical module URI.
+    module_timeouts: HashMap<String, Option<Duration>>,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The progress reporter.
+    progress: SharedProgress,
+
+    /// The policy for retrying failed rsync runs.
+    retry: RetryPolicy,
+
+    /// The limits on concurrency and bandwidth.
+    limits: FetchLimits,
+
+    /// The policy deciding which URIs may be fetched.
+    policy: SharedPolicy,
+}
+
+impl RsyncFetcher {
+    /// Creates a new fetcher using the given cache directory.
+    pub fn new(cache_dir: impl Into<PathBuf>) -> Self {
+        RsyncFetcher {
+            cache_dir: cache_dir.into(),
+            command: "rsync".into(),
+            args: None,
+            extensions: Some(
+                RPKI_EXTENSIONS.iter().map(|ext| ext.to_string()).collect()
+            ),
+            timeout: None,
+            module_timeouts: HashMap::new(),
+            metrics: Default::default(),
+            progress: Default::default(),
+            retry: Default::default(),
+            limits: Default::default(),
+            policy: Default::default(),
+        }
+    }
+
+    /// Returns the cache directory of the fetcher.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Sets the rsync command to run.
+    pub fn set_command(&mut self, command: impl Into<OsString>) {
+        self.command = command.into()
+    }
+
+    /// Sets the arguments to pass to the rsync command.
+    ///
+    /// The arguments replace the default arguments. They are followed by
+    /// the filter arguments, if there are any, the source URI and the
+    /// target directory. If `args` is `None`, the default arguments are
+    /// used.
+    pub fn set_args<I, T>(&mut self, args: Option<I>)
+    where I: IntoIterator<Item = T>, T: Into<OsString> {
+        self.args = args.map(|args| {
+            args.into_iter().map(Into::into).collect()
+        })
+    }
+
+    /// Sets the file extensions to transfer.
+    ///
+    /// If `extensions` is `None`, filtering is disabled and all files are
+    /// transferred. The extensions should be given without the leading
+    /// full stop.
+    pub fn set_extensions<I, T>(&mut self, extensions: Option<I>)
+    where I: IntoIterator<Item = T>, T: Into<String> {
+        self.extensions = extensions.map(|ext| {
+            ext.into_iter().map(Into::into).collect()
+        })
+    }
+
+    /// Sets the default timeout for an rsync run.
+    ///
+    /// If an rsync process takes longer than the timeout, it is killed and
+    /// the fetch fails. If `timeout` is `None`, rsync can run for as long
+    /// as it wants.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+
+    /// Sets the timeout for a specific module.
+    ///
+    /// The module is taken from the given URI. The timeout overides the
+    /// default timeout for all fetches from this module.
+    pub fn set_module_timeout(
+        &mut self, module: &uri::Rsync, timeout: Option<Duration>
+    ) {
+        self.module_timeouts.insert(
+            module.canonical_module().into_owned(), timeout
+        );
+    }
+
+    /// Sets the metrics the fetcher reports to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the progress reporter of the fetcher.
+    ///
+    /// Each rsync run is reported as a repository identified by the
+    /// fetched URI and each file imported into a store as a processed
+    /// object. Since the transfer is done by an external process, the
+    /// number of bytes downloaded is not reported.
+    pub fn set_progress(&mut self, progress: SharedProgress) {
+        self.progress = progress
+    }
+
+    /// Sets the policy for retrying failed rsync runs.
+    ///
+    /// By default, failed runs are not retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy
+    }
+
+    /// Sets the limits on concurrency and bandwidth.
+    ///
+    /// An rsync run waits until the limits allow it to start. The
+    /// bandwidth limit is passed to each rsync process via its
+    /// `--bwlimit` option and thus applies to each run separately. By
+    /// default, there are no limits.
+    pub fn set_limits(&mut self, limits: FetchLimits) {
+        self.limits = limits
+    }
+
+    /// Sets the policy deciding which URIs may be fetched.
+    ///
+    /// The policy is checked before rsync is run. If it rejects the URI,
+    /// fetching fails with [`ErrorKind::Rejected`]. By default, all URIs
+    /// are allowed.
+    pub fn set_policy(&mut self, policy: SharedPolicy) {
+        self.policy = policy
+    }
+
+    /// Returns the timeout used when fetching the given URI.
+    pub fn timeout(&self, uri: &uri::Rsync) -> Option<Duration> {
+        match self.module_timeouts.get(uri.canonical_module().as_ref()) {
+            Some(timeout) => *timeout,
+            None => self.timeout
+        }
+    }
+
+    /// Returns the path of the local mirror of the given URI.
+    ///
+    /// The path is constructed by appending the lowercase authority, the
+    /// module name, and the path segments of the URI to the cache
+    /// directory as described for [`FsStore::local_path`]. The method
+    /// returns an error if the path would escape the cache directory.
+    pub fn local_path(&self, uri: &uri::Rsync) -> Result<PathBuf, Error> {
+        FsStore::new(&self.cache_dir).local_path(uri).map_err(|_| {
+            Error::new(uri.clone(), ErrorKind::BadUri)
+        })
+    }
+
+    /// Fetches the given directory and updates the object store.
+    ///
+    /// This first updates the local mirror of the directory via rsync and
+    /// then makes the content of the store below the URI match that of the
+    /// local mirror. It returns a summary of the changes to the store.
+    pub fn fetch_into<S: ObjectStore>(
+        &self, uri: &uri::Rsync, store: &mut S
+    ) -> Result<Changes, Error> {
+        self.fetch(uri)?;
+        self.import(uri, store)
+    }
+
+    /// Updates the local mirror of the given directory.
+    ///
+    /// Returns the path of the local mirror upon success.
+    pub fn fetch(&self, uri: &uri::Rsync) -> Result<PathBuf, Error> {
+        self.policy.check(FetchTarget::Rsync(uri)).map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Rejected(err))
+        })?;
+        let path = self.local_path(uri)?;
+        fs::create_dir_all(&path).map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Io(err))
+        })?;
+        let start = Instant::now();
+        self.progress.repository_started(uri.as_str());
+        let res = self.retry.retry(|| {
+            let _permit = self.limits.acquire(uri.authority());
+            let command = self.command_for(uri, &path);
+            debug!("rsync: running {:?}", command);
+            run(command, self.timeout(uri))
+        });
+        let elapsed = start.elapsed().as_secs_f64();
+        self.progress.repository_finished(uri.as_str(), res.is_ok());
+        self.metrics.histogram(names::RSYNC_DURATION, &[], elapsed);
+        self.metrics.counter(
+            names::RSYNC_RUNS,
+            &[("result", if res.is_ok() { "ok" } else { "error" })],
+            1
+        );
+        res.map_err(|kind| {
+            let err = Error::new(uri.clone(), kind);
+            debug!("rsync: {} failed after {:.3}s", err, elapsed);
+            err
+        })?;
+        debug!("rsync: updated {} in {:.3}s", uri, elapsed);
+        Ok(path)
+    }
+
+    /// Updates an object store from the local mirror of a directory.
+    ///
+    /// After successful return, the objects in the store with URIs below
+    /// `uri` will be exactly the files in the local mirror. Files that
+    /// don’t have one of the configured extensions or whose names cannot
+    /// be used in rsync URIs are skipped.
+    pub fn import<S: ObjectStore>(
+        &self, uri: &uri::Rsync, store: &mut S
+    ) -> Result<Changes, Error> {
+        let base = self.local_path(uri)?;
+        let mut changes = Changes::default();
+        let mut seen = HashSet::new();
+        debug!("rsync: importing {} from {}", uri, base.display());
+        self.import_dir(
+            uri.as_str(), uri, &base, store, &mut seen, &mut changes
+        )?;
+        for existing in store.list(uri).map_err(|err| {
+            Error::new(uri.clone(), ErrorKind::Store(StoreError::new(err)))
+        })? {
+            if !seen.contains(&existing) {
+                store.remove(&existing).map_err(|err| {
+                    Error::new(
+                        uri.clone(), ErrorKind::Store(StoreError::new(err))
+                    )
+                })?;
+                changes.removed += 1;
+            }
+        }
+        debug!(
+            "rsync: imported {}: {} added, {} updated, {} removed",
+            uri, changes.added, changes.updated, changes.removed
+        );
+        for (change, value) in [
+            ("added", changes.added),
+            ("updated", changes.updated),
+            ("removed", changes.removed),
+        ] {
+            if value > 0 {
+                self.metrics.counter(
+                    names::RSYNC_IMPORTED, &[("change", change)],
+                    value as u64
+                );
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Imports the content of a local directory into the store.
+    ///
+    /// Processed files are reported to the progress reporter under
+    /// `repository`.
+    fn import_dir<S: ObjectStore>(
+        &self,
+        repository: &str,
+        uri: &uri::Rsync,
+        path: &Path,
+        store: &mut S,
+        seen: &mut HashSet<uri::Rsync>,
+        changes: &mut Changes,
+    ) -> Result<(), Error> {
+        let io_err = |err| Error::new(uri.clone(), ErrorKind::Io(err));
+        let store_err = |err| {
+            Error::new(uri.clone(), ErrorKind::Store(StoreError::new(err)))
+        };
+
+        let dir = match fs::read_dir(path) {
+            Ok(dir) => dir,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(err) => return Err(io_err(err))
+        };
+        for entry in dir {
+            let entry = entry.map_err(io_err)?;
+            let file_type = entry.file_type().map_err(io_err)?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(name) => {
+                    warn!(
+                        "rsync: skipping file with non-Unicode name {:?} \
+                         in {}", name, uri
+                    );
+                    continue
+                }
+            };
+            if file_type.is_dir() {
+                let dir_uri = match uri.join(format!("{}/", name).as_bytes()) {
+                    Ok(dir_uri) => dir_uri,
+                    Err(_) => {
+                        warn!(
+                            "rsync: skipping directory with invalid name \
+                             '{}' in {}", name, uri
+                        );
+                        continue
+                    }
+                };
+                self.import_dir(
+                    repository, &dir_uri, &entry.path(), store, seen, changes
+                )?;
+            }
+            else if file_type.is_file() {
+                if !self.is_wanted(&name) {
+                    continue
+                }
+                let file_uri = match uri.join(name.as_bytes()) {
+                    Ok(file_uri) => file_uri,
+                    Err(_) => {
+                        warn!(
+                            "rsync: skipping file with invalid name \
+                             '{}' in {}", name, uri
+                        );
+                        continue
+                    }
+                };
+                let data = Bytes::from(
+                    fs::read(entry.path()).map_err(io_err)?
+                );
+                match store.get(&file_uri).map_err(store_err)? {
+                    Some(ref old) if *old == data => { }
+                    Some(_) => {
+                        store.insert(
+                            file_uri.clone(), data
+                        ).map_err(store_err)?;
+                        changes.updated += 1;
+                    }
+                    None => {
+                        store.insert(
+                            file_uri.clone(), data
+                        ).map_err(store_err)?;
+                        changes.added += 1;
+                    }
+                }
+                seen.insert(file_uri);
+                self.progress.objects_processed(repository, 1);
+            }
+            // We silently skip symlinks and other special files.
+        }
+        Ok(())
+    }
+
+    /// Returns whether a file with the given name should be used.
+    fn is_wanted(&self, name: &str) -> bool {
+        match self.extensions {
+            Some(ref extensions) => {
+                match name.rsplit_once('.') {
+                    Some((_, ext)) => extensions.iter().any(|item| {
+                        item.eq_ignore_ascii_case(ext)
+                    }),
+                    None => false
+                }
+            }
+            None => true
+        }
+    }
+
+    /// Creates the command for fetching the given URI to the given path.
+    fn command_for(&self, uri: &uri::Rsync, path: &Path) -> Command {
+        let mut command = Command::new(&self.command);
+        match self.args {
+            Some(ref args) => {
+                command.args(args);
+            }
+            None => {
+                command.args(DEFAULT_ARGS);
+            }
+        }
+        if let Some(ref extensions) = self.extensions {
+            command.arg("--include=*/");
+            for ext in extensions {
+                command.arg(format!("--include=*.{}", ext));
+            }
+            command.arg("--exclude=*");
+        }
+        if let Some(bandwidth) = self.limits.bandwidth() {
+            // rsync wants the limit in units of 1024 bytes per second.
+            command.arg(
+                format!("--bwlimit={}", cmp::max(bandwidth / 1024, 1))
+            );
+        }
+
+        // Both source and destination need a trailing slash so rsync
+        // synchronizes the content of the directories.
+        if uri.path_is_dir() {
+            command.arg(uri.as_str());
+        }
+        else {
+            command.arg(format!("{}/", uri));
+        }
+        let mut dest = path.as_os_str().to_owned();
+        dest.push(std::path::MAIN_SEPARATOR.to_string());
+        command.arg(dest);
+        command
+    }
+}
+
+
+/// # Asynchronous Fetching
+///
+/// These methods are available if the `"async"` feature is enabled. They
+/// require a Tokio runtime.
+#[cfg(feature = "async")]
+impl RsyncFetcher {
+    /// Asynchronously updates the local mirror of the given directory.
+    ///
+    /// This is the asynchronous version of [`fetch`][Self::fetch]. The
+    /// rsync process is run on a blocking thread of the runtime.
+    pub async fn fetch_async(
+        &self, uri: &uri::Rsync
+    ) -> Result<PathBuf, Error> {
+        let fetcher = self.clone();
+        let fetch_uri = uri.clone();
+        match tokio::task::spawn_blocking(move || {
+            fetcher.fetch(&fetch_uri)
+        }).await {
+            Ok(res) => res,
+            Err(err) if err.is_panic() => {
+                std::panic::resume_unwind(err.into_panic())
+            }
+            Err(err) => {
+                Err(Error::new(
+                    uri.clone(),
+                    ErrorKind::Io(io::Error::new(io::ErrorKind::Other, err))
+                ))
+            }
+        }
+    }
+}
+
+//------------ Changes -------------------------------------------------------
+
+/// A summary of the changes made to an object store by an import.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Changes {
+    /// The number of objects newly added to the store.
+    pub added: usize,
+
+    /// The number of objects whose content has changed.
+    pub updated: usize,
+
+    /// The number of objects removed from the store.
+    pub removed: usize,
+}
+
+impl Changes {
+    /// Returns whether there were no changes at all.
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.removed == 0
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Runs a command to completion or until the timeout expires.
+///
+/// The command’s standard error is collected and included in the error if
+/// the command fails.
+fn run(
+    mut command: Command, timeout: Option<Duration>
+) -> Result<(), ErrorKind> {
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::null());
+    command.stderr(Stdio::piped());
+    let mut child = command.spawn().map_err(ErrorKind::Command)?;
+
+    // We need to read stderr in a separate thread or the process may block
+    // if the pipe’s buffer fills up.
+    let stderr = child.stderr.take();
+    let stderr = thread::spawn(move || {
+        let mut res = Vec::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_end(&mut res);
+        }
+        res
+    });
+
+    let status = match timeout {
+        Some(timeout) => wait_timeout(&mut child, timeout)?,
+        None => Some(child.wait().map_err(ErrorKind::Io)?),
+    };
+    let status = match status {
+        Some(status) => status,
+        None => {
+            // Timeout. Kill the process and leave the stderr thread behind
+            // since the pipe may be held open by other processes.
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(ErrorKind::Timeout(timeout.unwrap_or_default()))
+        }
+    };
+    if status.success() {
+        Ok(())
+    }
+    else {
+        let stderr = stderr.join().unwrap_or_default();
+        Err(ErrorKind::Exit(
+            status, String::from_utf8_lossy(&stderr).trim().into()
+        ))
+    }
+}
+
+/// Waits for a child process to exit for at most the given time.
+///
+/// Returns `Ok(None)` if the process has not exited when the timeout
+/// expires.
+fn wait_timeout(
+    child: &mut Child, timeout: Duration
+) -> Result<Option<ExitStatus>, ErrorKind> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(ErrorKind::Io)? {
+            return Ok(Some(status))
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None)
+        }
+        thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ Error ---------------------------------------------------------
+
+/// An error happened while fetching via rsync.
+#[derive(Debug)]
+pub struct Error {
+    /// The URI that was being fetched.
+    uri: uri::Rsync,
+
+    /// What went wrong.
+    kind: ErrorKind,
+}
+
+impl Error {
+    /// Creates a new error for the given URI.
+    fn new(uri: uri::Rsync, kind: ErrorKind) -> Self {
+        Error { uri, kind }
+    }
+
+    /// Returns the URI that was being fetched.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+
+    /// Returns what went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Converts the error into what went wrong.
+    pub fn into_kind(self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.uri, self.kind)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Command(ref err) => Some(err),
+            ErrorKind::Io(ref err) => Some(err),
+            ErrorKind::Store(ref err) => Some(err),
+            ErrorKind::Rejected(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl Retryable for Error {
+    fn is_retryable(&self) -> bool {
+        self.kind.is_retryable()
+    }
+}
+
+
+//------------ ErrorKind -----------------------------------------------------
+
+/// The kinds of errors that can happen while fetching via rsync.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The URI cannot be mapped to a local path.
+    BadUri,
+
+    /// The rsync command could not be started.
+    Command(io::Error),
+
+    /// The rsync command did not finish within the given time.
+    Timeout(Duration),
+
+    /// The rsync command failed.
+    ///
+    /// Contains the exit status and the standard error output of the
+    /// command.
+    Exit(ExitStatus, String),
+
+    /// Accessing the local mirror failed.
+    Io(io::Error),
+
+    /// Accessing the object store failed.
+    Store(StoreError),
+
+    /// The fetch policy rejected the URI.
+    Rejected(PolicyRejection),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::BadUri => {
+                f.write_str("URI cannot be mapped to a local path")
+            }
+            ErrorKind::Command(ref err) => {
+                write!(f, "failed to run rsync: {}", err)
+            }
+            ErrorKind::Timeout(timeout) => {
+                write!(f, "rsync timed out after {}s", timeout.as_secs())
+            }
+            ErrorKind::Exit(status, ref stderr) => {
+                if stderr.is_empty() {
+                    write!(f, "rsync failed with {}", status)
+                }
+                else {
+                    write!(f, "rsync failed with {}: {}", status, stderr)
+                }
+            }
+            ErrorKind::Io(ref err) => {
+                write!(f, "failed to access local mirror: {}", err)
+            }
+            ErrorKind::Store(ref err) => err.fmt(f),
+            ErrorKind::Rejected(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl Retryable for ErrorKind {
+    /// Returns whether the rsync run should be retried.
+    ///
+    /// Timeouts are considered temporary, as are the rsync exit codes
+    /// for errors starting the protocol (5), socket I/O (10), the data
+    /// stream (12), timeouts (30), and daemon connection timeouts (35).
+    fn is_retryable(&self) -> bool {
+        match *self {
+            ErrorKind::Timeout(_) => true,
+            ErrorKind::Exit(status, _) => {
+                matches!(status.code(), Some(5 | 10 | 12 | 30 | 35))
+            }
+            _ => false,
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::store::MemoryStore;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    /// Returns a new, empty, temporary directory.
+    fn temp_dir() -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let res = std::env::temp_dir().join(format!(
+            "rpki-rsync-test-{}-{}",
+            std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&res);
+        fs::create_dir_all(&res).unwrap();
+        res
+    }
+
+    #[test]
+    fn local_path() {
+        let fetcher = RsyncFetcher::new("/var/cache");
+        assert_eq!(
+            fetcher.local_path(
+                &uri("rsync://Example.COM/mod/some/path/")
+            ).unwrap(),
+            Path::new("/var/cache/example.com/mod/some/path")
+        );
+        assert!(
+            fetcher.local_path(&uri("rsync://../mod/some/path/")).is_err()
+        );
+        assert!(
+            fetcher.local_path(&uri("rsync://host/../some/path/")).is_err()
+        );
+    }
+
+    #[test]
+    fn command_args() {
+        let mut fetcher = RsyncFetcher::new("/var/cache");
+        fetcher.set_extensions(Some(["cer", "roa"]));
+        let uri = uri("rsync://example.com/mod/path");
+        let path = fetcher.local_path(&uri).unwrap();
+        let command = fetcher.command_for(&uri, &path);
+        let args: Vec<_> = command.get_args().map(|arg| {
+            arg.to_str().unwrap().to_string()
+        }).collect();
+        let mut dest = path.into_os_string().into_string().unwrap();
+        dest.push(std::path::MAIN_SEPARATOR);
+        assert_eq!(
+            args,
+            [
+                "-rltz", "--delete",
+                "--include=*/", "--include=*.cer", "--include=*.roa",
+                "--exclude=*",
+                "rsync://example.com/mod/path/", dest.as_str(),
+            ]
+        );
+
+        fetcher.set_extensions(None::<Vec<String>>);
+        fetcher.set_args(Some(["-rt"]));
+        let command = fetcher.command_for(&uri, Path::new("/tmp"));
+        assert_eq!(command.get_args().count(), 3);
+
+        fetcher.set_limits(FetchLimits::new(None, None, Some(512 * 1024)));
+        let command = fetcher.command_for(&uri, Path::new("/tmp"));
+        assert!(command.get_args().any(|arg| arg == "--bwlimit=512"));
+    }
+
+    #[test]
+    fn timeouts() {
+        let mut fetcher = RsyncFetcher::new("/var/cache");
+        fetcher.set_timeout(Some(Duration::from_secs(10)));
+        fetcher.set_module_timeout(
+            &uri("rsync://slow.example.com/mod/"),
+            Some(Duration::from_secs(100))
+        );
+        assert_eq!(
+            fetcher.timeout(&uri("rsync://example.com/mod/foo/")),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            fetcher.timeout(&uri("rsync://SLOW.example.com/mod/foo/")),
+            Some(Duration::from_secs(100))
+        );
+        assert_eq!(
+            fetcher.timeout(&uri("rsync://slow.example.com/other/")),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn import() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        let progress = std::sync::Arc::new(
+            crate::progress::ProgressCounter::new()
+        );
+        fetcher.set_progress(progress.clone().into());
+        let base = uri("rsync://example.com/mod/ca/");
+        let path = fetcher.local_path(&base).unwrap();
+        fs::create_dir_all(path.join("sub")).unwrap();
+        fs::write(path.join("one.cer"), b"one").unwrap();
+        fs::write(path.join("sub/two.roa"), b"two").unwrap();
+        fs::write(path.join("ignored.txt"), b"ignored").unwrap();
+
+        let mut store = MemoryStore::new();
+        store.insert(
+            uri("rsync://example.com/mod/ca/gone.mft"), Bytes::from("gone")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/mod/other.cer"), Bytes::from("other")
+        ).unwrap();
+
+        let changes = fetcher.import(&base, &mut store).unwrap();
+        assert_eq!(
+            changes, Changes { added: 2, updated: 0, removed: 1 }
+        );
+        assert_eq!(progress.objects(), 2);
+        assert_eq!(
+            store.get(&uri("rsync://example.com/mod/ca/sub/two.roa")).unwrap(),
+            Some(Bytes::from("two"))
+        );
+        assert!(
+            store.contains(&uri("rsync://example.com/mod/other.cer")).unwrap()
+        );
+        assert_eq!(store.len(), 3);
+
+        fs::write(path.join("one.cer"), b"new one").unwrap();
+        let changes = fetcher.import(&base, &mut store).unwrap();
+        assert_eq!(
+            changes, Changes { added: 0, updated: 1, removed: 0 }
+        );
+        assert!(fetcher.import(&base, &mut store).unwrap().is_empty());
+
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn policy() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("/nonexistent/rsync");
+        let mut policy = super::super::policy::HostPolicy::new();
+        policy.set_deny_special(true);
+        fetcher.set_policy(SharedPolicy::new(policy));
+        let err = fetcher.fetch(&uri("rsync://10.1.2.3/mod/")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Rejected(_)));
+        assert!(!cache.join("10.1.2.3").exists());
+        let err = fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Command(_)));
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn missing_command() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("/nonexistent/rsync");
+        let err = fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Command(_)));
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn timeout() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("sh");
+        fetcher.set_args(Some(["-c", "exec sleep 10", "sh"]));
+        fetcher.set_timeout(Some(Duration::from_millis(200)));
+        let start = Instant::now();
+        let err = fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Timeout(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg(all(unix, feature = "async"))]
+    async fn fetch_async() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("sh");
+        fetcher.set_args(Some(["-c", "exit 0", "sh"]));
+        let uri = uri("rsync://example.com/mod/dir/");
+        assert_eq!(
+            fetcher.fetch_async(&uri).await.unwrap(),
+            fetcher.local_path(&uri).unwrap()
+        );
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn exit_status() {
+        let cache = temp_dir();
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("sh");
+        fetcher.set_args(Some(["-c", "echo broken >&2; exit 5", "sh"]));
+        let err = fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap_err();
+        match err.kind() {
+            ErrorKind::Exit(status, stderr) => {
+                assert_eq!(status.code(), Some(5));
+                assert_eq!(stderr, "broken");
+            }
+            _ => panic!("unexpected error {}", err)
+        }
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn retry() {
+        let cache = temp_dir();
+        let count = cache.join("count");
+        let script = format!(
+            "echo x >> {0}; [ $(wc -l < {0}) -ge 2 ] || exit 10",
+            count.display()
+        );
+        let mut fetcher = RsyncFetcher::new(&cache);
+        fetcher.set_command("sh");
+        fetcher.set_args(Some(["-c", script.as_str(), "sh"]));
+        let mut policy = RetryPolicy::new(3);
+        policy.set_initial_delay(Duration::ZERO);
+        fetcher.set_retry_policy(policy);
+        fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap();
+        assert_eq!(fs::read_to_string(&count).unwrap(), "x\nx\n");
+
+        // Other exit codes aren’t retried.
+        fetcher.set_args(Some(["-c", "exit 1", "sh"]));
+        let err = fetcher.fetch(&uri("rsync://example.com/mod/")).unwrap_err();
+        assert!(!err.is_retryable());
+        fs::remove_dir_all(&cache).unwrap();
+    }
+}
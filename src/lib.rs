@@ -12,8 +12,25 @@
 //! The crate uses the features to enable functionality that isn’t necessary
 //! for all use cases. Currently, the following features are defined:
 //!
+//! * `"async"`: asynchronous versions of the HTTPS and rsync fetchers
+//!   based on Tokio – enabling this feature also enables the `"fetch"`
+//!   feature; note that processing RRDP files stays synchronous and that
+//!   the RTR components are always asynchronous;
+//! * `"bench"`: measuring the throughput of parsing and validating
+//!   repository objects – enabling this feature also enables the
+//!   `"repository"` feature;
+//! * `"capi"`: a C API for parsing and validating certificates, ROAs, and
+//!   manifests – enabling this feature also enables the `"repository"`
+//!   feature;
+//! * `"fetch"`: support for retrieving the content of RPKI repositories
+//!   from their publication points;
+//! * `"mmap"`: memory-mapped reading of large files from local
+//!   repository copies on Unix systems;
 //! * `"repository"`: support for creating, validating, and processing of
 //!   repository objects, such as certificates, manifests, or ROAs;
+//! * `"ribdump"`: reading routes from BGP table dumps for validating them
+//!   against RPKI – enabling this feature also enables the `"rtr"`
+//!   feature;
 //! * `"rrdp"`: support for the RRDP protocol for synchronising RPKI
 //!   repositories;
 //! * `"rtr"`: support for the RPKI-to-router protocol (RTR);
@@ -23,22 +40,48 @@
 //! * `"softkeys"`: enables an OpenSSL-based signer for creating repository
 //!   objects – enabling this feature also enables the `"repository"`
 //!   feature;
+//! * `"state"`: persisting the state of a relying party between runs –
+//!   enabling this feature also enables the `"fetch"`, `"repository"`,
+//!   `"rtr"`, and `"serde-support"` features – also provides a status
+//!   summary for health endpoints;
+//! * `"testdata"`: provides example messages and objects for use in tests;
 //! * `"extra-debug"`: enables printing stack traces when parsing of a
 //!   repository object fails – this feature should only be used during
 //!   debugging and must not be enabled in release builds.
+//!
+//! # WebAssembly
+//!
+//! The parsing and validation of repository objects and RRDP files, i.e.,
+//! the `"repository"`, `"rrdp"`, and `"serde-support"` features, can be
+//! used on the `wasm32-unknown-unknown` target. Cryptography on that target
+//! is provided by *ring* which needs a C compiler for WebAssembly, such as
+//! clang, to build. There is currently no alternative cryptography backend
+//! implemented in pure Rust. The `"softkeys"` feature requires OpenSSL and
+//! is not available there.
 
 #![allow(renamed_and_removed_lints)]
 #![allow(clippy::unknown_clippy_lints)]
 
 
 pub mod ca;
+pub mod capi;
+pub mod clock;
 pub mod crypto;
+pub mod events;
+pub mod fetch;
+pub mod metrics;
 pub mod oid;
+pub mod progress;
 pub mod repository;
 pub mod resources;
+pub mod ribdump;
 pub mod rrdp;
 pub mod rtr;
 pub mod slurm;
+pub mod state;
+pub mod status;
+pub mod store;
+pub mod testdata;
 pub mod uri;
 pub mod util;
 pub mod xml;
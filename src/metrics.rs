@@ -0,0 +1,478 @@
+//! Operational metrics.
+//!
+//! Long-running components of this crate, such as the fetchers, the RRDP
+//! repository access tracking, the validation engine, and the RTR server,
+//! report what they are doing through the [`Metrics`] trait. This allows
+//! integrators to collect consistent operational metrics using whatever
+//! metrics system they prefer.
+//!
+//! The trait knows three kinds of metrics: counters that only ever
+//! increase, gauges that are set to a current value, and histograms that
+//! record observations such as durations. Each metric is identified by its
+//! name and a list of label pairs. The names of all metrics reported by the
+//! crate are available as constants in the [`names`] module.
+//!
+//! Components keep the metrics implementation to use as a
+//! [`SharedMetrics`]. By default, this is [`NoMetrics`] which simply drops
+//! everything. [`PrometheusMetrics`] collects all metrics and renders them
+//! in the Prometheus text exposition format.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+
+//------------ Metrics -------------------------------------------------------
+
+/// A type that receives operational metrics.
+///
+/// All methods have a default implementation that does nothing, so an
+/// implementation only needs to provide the methods for the kinds of
+/// metrics it is interested in.
+///
+/// The methods are called synchronously from within the operation that is
+/// being measured. Implementations should therefore be cheap and must not
+/// block for long.
+pub trait Metrics: Send + Sync {
+    /// Increases the counter with the given name and labels by `value`.
+    fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let _ = (name, labels, value);
+    }
+
+    /// Sets the gauge with the given name and labels to `value`.
+    fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = (name, labels, value);
+    }
+
+    /// Records an observation for the histogram with the given name.
+    fn histogram(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = (name, labels, value);
+    }
+}
+
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        self.as_ref().counter(name, labels, value)
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.as_ref().gauge(name, labels, value)
+    }
+
+    fn histogram(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.as_ref().histogram(name, labels, value)
+    }
+}
+
+
+//------------ NoMetrics -----------------------------------------------------
+
+/// A metrics implementation that drops all metrics.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics { }
+
+
+//------------ SharedMetrics -------------------------------------------------
+
+/// A shared reference to a metrics implementation.
+///
+/// This is the type components keep their metrics implementation in. It
+/// can be created from any `Arc<M>` for a type `M` implementing
+/// [`Metrics`], which allows keeping a copy of the arc around for
+/// accessing the collected metrics. The default value uses
+/// [`NoMetrics`].
+#[derive(Clone)]
+pub struct SharedMetrics(Arc<dyn Metrics>);
+
+impl SharedMetrics {
+    /// Creates a new value from a metrics implementation.
+    pub fn new(metrics: impl Metrics + 'static) -> Self {
+        SharedMetrics(Arc::new(metrics))
+    }
+}
+
+impl Default for SharedMetrics {
+    fn default() -> Self {
+        SharedMetrics::new(NoMetrics)
+    }
+}
+
+impl<M: Metrics + 'static> From<Arc<M>> for SharedMetrics {
+    fn from(metrics: Arc<M>) -> Self {
+        SharedMetrics(metrics)
+    }
+}
+
+impl Deref for SharedMetrics {
+    type Target = dyn Metrics;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for SharedMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedMetrics")
+    }
+}
+
+
+//------------ names ---------------------------------------------------------
+
+/// The names of the metrics reported by the crate.
+///
+/// The documentation of each constant describes the kind of the metric and
+/// the labels it uses.
+pub mod names {
+    /// Counter of HTTPS requests.
+    ///
+    /// The label `result` is one of `ok`, `not_modified`, or `error`.
+    pub const HTTPS_REQUESTS: &str = "rpki_https_requests_total";
+
+    /// Histogram of the duration of successful HTTPS requests in seconds.
+    pub const HTTPS_DURATION: &str = "rpki_https_request_duration_seconds";
+
+    /// Counter of bytes received via HTTPS after decompression.
+    pub const HTTPS_BYTES: &str = "rpki_https_received_bytes_total";
+
+    /// Counter of RRDP notification files that could not be parsed.
+    pub const RRDP_NOTIFICATION_ERRORS: &str =
+        "rpki_rrdp_notification_errors_total";
+
+    /// Counter of runs of the rsync binary.
+    ///
+    /// The label `result` is either `ok` or `error`.
+    pub const RSYNC_RUNS: &str = "rpki_rsync_runs_total";
+
+    /// Histogram of the duration of rsync runs in seconds.
+    pub const RSYNC_DURATION: &str = "rpki_rsync_duration_seconds";
+
+    /// Counter of changes to an object store when importing rsync data.
+    ///
+    /// The label `change` is one of `added`, `updated`, or `removed`.
+    pub const RSYNC_IMPORTED: &str = "rpki_rsync_imported_objects_total";
+
+    /// Counter of RRDP updates.
+    ///
+    /// The label `result` is either `ok` or `error`.
+    pub const RRDP_UPDATES: &str = "rpki_rrdp_updates_total";
+
+    /// Counter of decisions to fall back from RRDP to rsync.
+    pub const RRDP_FALLBACKS: &str = "rpki_rrdp_fallbacks_total";
+
+    /// Gauge of the number of RRDP repositories currently failing.
+    pub const RRDP_FAILING: &str = "rpki_rrdp_failing_repositories";
+
+    /// Gauge of the number of repositories currently considered dead.
+    pub const DEAD_REPOSITORIES: &str = "rpki_dead_repositories";
+
+    /// Counter of fetches skipped because the repository is dead.
+    pub const DEAD_SKIPPED: &str = "rpki_dead_repository_skips_total";
+
+    /// Counter of lookups in a validation cache.
+    ///
+    /// The label `result` is either `hit` or `miss`.
+    pub const VALIDATION_CACHE_LOOKUPS: &str =
+        "rpki_validation_cache_lookups_total";
+
+    /// Counter of entries dropped from a full validation cache.
+    pub const VALIDATION_CACHE_EVICTIONS: &str =
+        "rpki_validation_cache_evictions_total";
+
+    /// Counter of objects validated by the validation engine.
+    ///
+    /// The label `type` is the file extension of the object, such as
+    /// `cer` or `roa`, or `other`. The label `result` is either `accepted`
+    /// or `rejected`.
+    pub const VALIDATION_OBJECTS: &str = "rpki_validation_objects_total";
+
+    /// Counter of publication points processed by the validation engine.
+    ///
+    /// The label `outcome` is one of `current`, `cached`, or `failed`.
+    pub const VALIDATION_POINTS: &str =
+        "rpki_validation_publication_points_total";
+
+    /// Histogram of the duration of validation runs in seconds.
+    pub const VALIDATION_DURATION: &str =
+        "rpki_validation_run_duration_seconds";
+
+    /// Counter of ROA prefixes flagged for an excessive maxLength.
+    ///
+    /// The label `action` is either `warn` or `reject`.
+    pub const ROA_EXCESSIVE_MAX_LENGTH: &str =
+        "rpki_roa_excessive_max_length_total";
+
+    /// Counter of connections accepted by the RTR server.
+    pub const RTR_CONNECTIONS: &str = "rpki_rtr_connections_total";
+
+    /// Counter of queries received by the RTR server.
+    ///
+    /// The label `query` is either `serial` or `reset`.
+    pub const RTR_QUERIES: &str = "rpki_rtr_queries_total";
+
+    /// Counter of responses sent by the RTR server.
+    ///
+    /// The label `response` is one of `diff`, `full`, `cache_reset`, or
+    /// `not_ready`.
+    pub const RTR_RESPONSES: &str = "rpki_rtr_responses_total";
+
+    /// Counter of payload PDUs sent by the RTR server.
+    pub const RTR_PAYLOAD: &str = "rpki_rtr_payload_sent_total";
+}
+
+
+//------------ PrometheusMetrics ---------------------------------------------
+
+pub use self::prometheus::PrometheusMetrics;
+
+mod prometheus {
+    use std::fmt::Write;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+    use super::Metrics;
+
+    /// The default histogram buckets.
+    ///
+    /// These are suitable for durations in seconds of network operations.
+    const DEFAULT_BUCKETS: &[f64] = &[
+        0.01, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60., 300.
+    ];
+
+    /// A metrics implementation for Prometheus.
+    ///
+    /// The type collects all metrics it receives in memory. The method
+    /// [`render`][Self::render] produces their current values in the
+    /// Prometheus text exposition format, suitable for serving from a
+    /// metrics endpoint.
+    ///
+    /// All histograms share the same buckets. They can be set when creating
+    /// the value via [`with_buckets`][Self::with_buckets].
+    #[derive(Debug)]
+    pub struct PrometheusMetrics {
+        /// The upper bounds of the histogram buckets in increasing order.
+        buckets: Vec<f64>,
+
+        /// The collected metrics.
+        data: Mutex<Data>,
+    }
+
+    /// The collected metrics.
+    #[derive(Debug, Default)]
+    struct Data {
+        /// The counters by name and rendered labels.
+        counters: BTreeMap<String, BTreeMap<String, u64>>,
+
+        /// The gauges by name and rendered labels.
+        gauges: BTreeMap<String, BTreeMap<String, f64>>,
+
+        /// The histograms by name and rendered labels.
+        histograms: BTreeMap<String, BTreeMap<String, Histogram>>,
+    }
+
+    /// The data of a histogram.
+    #[derive(Debug)]
+    struct Histogram {
+        /// The number of observations per bucket, not cumulative.
+        ///
+        /// This has one more element than there are buckets for the
+        /// observations larger than all bucket bounds.
+        buckets: Vec<u64>,
+
+        /// The sum of all observations.
+        sum: f64,
+
+        /// The number of observations.
+        count: u64,
+    }
+
+    impl PrometheusMetrics {
+        /// Creates a new value with the default histogram buckets.
+        pub fn new() -> Self {
+            Self::with_buckets(DEFAULT_BUCKETS.into())
+        }
+
+        /// Creates a new value with the given histogram buckets.
+        ///
+        /// The buckets are given via their upper bounds. They will be
+        /// sorted.
+        pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+            buckets.sort_by(|left, right| left.total_cmp(right));
+            buckets.dedup();
+            PrometheusMetrics {
+                buckets,
+                data: Default::default()
+            }
+        }
+
+        /// Renders all metrics in the Prometheus text exposition format.
+        pub fn render(&self) -> String {
+            let data = self.data.lock().expect("poisoned lock");
+            let mut res = String::new();
+            for (name, values) in &data.counters {
+                writeln!(res, "# TYPE {} counter", name).unwrap();
+                for (labels, value) in values {
+                    writeln!(res, "{}{} {}", name, labels, value).unwrap();
+                }
+            }
+            for (name, values) in &data.gauges {
+                writeln!(res, "# TYPE {} gauge", name).unwrap();
+                for (labels, value) in values {
+                    writeln!(res, "{}{} {}", name, labels, value).unwrap();
+                }
+            }
+            for (name, values) in &data.histograms {
+                writeln!(res, "# TYPE {} histogram", name).unwrap();
+                for (labels, hist) in values {
+                    let mut cumulative = 0;
+                    for (bound, count) in self.buckets.iter().zip(
+                        hist.buckets.iter()
+                    ) {
+                        cumulative += count;
+                        writeln!(
+                            res, "{}_bucket{} {}",
+                            name, with_le(labels, &bound.to_string()),
+                            cumulative
+                        ).unwrap();
+                    }
+                    writeln!(
+                        res, "{}_bucket{} {}",
+                        name, with_le(labels, "+Inf"), hist.count
+                    ).unwrap();
+                    writeln!(res, "{}_sum{} {}", name, labels, hist.sum)
+                        .unwrap();
+                    writeln!(res, "{}_count{} {}", name, labels, hist.count)
+                        .unwrap();
+                }
+            }
+            res
+        }
+    }
+
+    impl Default for PrometheusMetrics {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Metrics for PrometheusMetrics {
+        fn counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+            let mut data = self.data.lock().expect("poisoned lock");
+            *data.counters.entry(name.into()).or_default().entry(
+                render_labels(labels)
+            ).or_default() += value;
+        }
+
+        fn gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+            let mut data = self.data.lock().expect("poisoned lock");
+            data.gauges.entry(name.into()).or_default().insert(
+                render_labels(labels), value
+            );
+        }
+
+        fn histogram(
+            &self, name: &str, labels: &[(&str, &str)], value: f64
+        ) {
+            let mut data = self.data.lock().expect("poisoned lock");
+            let hist = data.histograms.entry(name.into()).or_default()
+                .entry(render_labels(labels)).or_insert_with(|| {
+                    Histogram {
+                        buckets: vec![0; self.buckets.len() + 1],
+                        sum: 0.,
+                        count: 0,
+                    }
+                });
+            let idx = self.buckets.iter().position(|bound| value <= *bound)
+                .unwrap_or(self.buckets.len());
+            hist.buckets[idx] += 1;
+            hist.sum += value;
+            hist.count += 1;
+        }
+    }
+
+    /// Renders a label set including the braces.
+    ///
+    /// Labels are sorted by name so the same set always renders the same.
+    /// An empty set renders as an empty string.
+    fn render_labels(labels: &[(&str, &str)]) -> String {
+        if labels.is_empty() {
+            return String::new()
+        }
+        let mut labels = labels.to_vec();
+        labels.sort_unstable();
+        let mut res = String::from("{");
+        for (i, (name, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                res.push(',');
+            }
+            res.push_str(name);
+            res.push_str("=\"");
+            for ch in value.chars() {
+                match ch {
+                    '\\' => res.push_str("\\\\"),
+                    '"' => res.push_str("\\\""),
+                    '\n' => res.push_str("\\n"),
+                    ch => res.push(ch)
+                }
+            }
+            res.push('"');
+        }
+        res.push('}');
+        res
+    }
+
+    /// Adds the `le` label to a rendered label set.
+    fn with_le(labels: &str, le: &str) -> String {
+        match labels.strip_suffix('}') {
+            Some(labels) => format!("{},le=\"{}\"}}", labels, le),
+            None => format!("{{le=\"{}\"}}", le),
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_default() {
+        let metrics = SharedMetrics::default();
+        metrics.counter(names::RSYNC_RUNS, &[("result", "ok")], 1);
+        metrics.histogram(names::RSYNC_DURATION, &[], 1.);
+    }
+
+    #[test]
+    fn prometheus_render() {
+        let metrics = Arc::new(PrometheusMetrics::with_buckets(
+            vec![1., 0.5]
+        ));
+        let shared = SharedMetrics::from(metrics.clone());
+        shared.counter("requests", &[("result", "ok")], 2);
+        shared.counter("requests", &[("result", "ok")], 1);
+        shared.counter("requests", &[("result", "a\"b")], 1);
+        shared.gauge("failing", &[], 4.);
+        shared.histogram("duration", &[("kind", "x")], 0.25);
+        shared.histogram("duration", &[("kind", "x")], 0.75);
+        shared.histogram("duration", &[("kind", "x")], 3.);
+
+        assert_eq!(
+            metrics.render(),
+            "# TYPE requests counter\n\
+             requests{result=\"a\\\"b\"} 1\n\
+             requests{result=\"ok\"} 3\n\
+             # TYPE failing gauge\n\
+             failing 4\n\
+             # TYPE duration histogram\n\
+             duration_bucket{kind=\"x\",le=\"0.5\"} 1\n\
+             duration_bucket{kind=\"x\",le=\"1\"} 2\n\
+             duration_bucket{kind=\"x\",le=\"+Inf\"} 3\n\
+             duration_sum{kind=\"x\"} 4\n\
+             duration_count{kind=\"x\"} 3\n"
+        );
+    }
+}
@@ -81,10 +81,14 @@ pub const CP_IPADDR_ASNUMBER_V2: Oid<&[u8]> = Oid(&[43, 6, 1, 5, 5, 7, 14, 3]);
 
 pub const CT_RPKI_MANIFEST: ConstOid
     = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 26]);
+pub const CT_RPKI_GHOSTBUSTERS: ConstOid
+    = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 35]);
 pub const CT_RESOURCE_TAGGED_ATTESTATION: ConstOid
     = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 36]);
 pub const CT_ASPA: ConstOid
     = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 49]);
+pub const CT_SIGNED_TAL: ConstOid
+    = Oid(&[42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 50]);
 
 pub const KP_BGPSEC_ROUTER: ConstOid
     = Oid(&[43, 6, 1, 5, 5, 7, 3, 30]);
@@ -0,0 +1,275 @@
+//! Progress reporting.
+//!
+//! Synchronizing and validating the complete RPKI takes several minutes.
+//! User interfaces and logs want to show what is going on during that
+//! time. Components of this crate that can take a while – the fetchers,
+//! RRDP processing, and the validation engine – report their progress
+//! through the [`Progress`] trait.
+//!
+//! Progress is reported in terms of repositories being worked on, bytes
+//! downloaded, objects processed, and CAs validated. Repositories are
+//! identified by the string version of their URI, i.e., the URI of the
+//! RRDP notification file or the rsync URI of the directory being
+//! fetched. CAs are identified by the rsync URI of their certificate and
+//! reported via [`ca_validated`][Progress::ca_validated] by the
+//! validation engine as well as by validation code of its own using this
+//! crate.
+//!
+//! Components keep their progress reporter as a [`SharedProgress`]. By
+//! default, this is [`NoProgress`] which ignores everything. A
+//! [`ProgressCounter`] sums up everything reported and can be polled
+//! periodically to display the current state.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+
+//------------ Progress ------------------------------------------------------
+
+/// A type that receives progress reports.
+///
+/// All methods have a default implementation that does nothing, so an
+/// implementation only needs to provide the methods it is interested in.
+///
+/// The methods are called synchronously from within the operation that
+/// reports progress, possibly from many threads at once. Implementations
+/// should therefore be cheap and must not block for long.
+pub trait Progress: Send + Sync {
+    /// Work on a repository has started.
+    fn repository_started(&self, repository: &str) {
+        let _ = repository;
+    }
+
+    /// Work on a repository has finished.
+    ///
+    /// The argument `success` indicates whether the work succeeded.
+    fn repository_finished(&self, repository: &str, success: bool) {
+        let _ = (repository, success);
+    }
+
+    /// Another `bytes` bytes have been downloaded from a repository.
+    fn bytes_downloaded(&self, repository: &str, bytes: u64) {
+        let _ = (repository, bytes);
+    }
+
+    /// Another `count` objects of a repository have been processed.
+    fn objects_processed(&self, repository: &str, count: u64) {
+        let _ = (repository, count);
+    }
+
+    /// A CA has been validated.
+    ///
+    /// The CA is identified by the rsync URI of its certificate.
+    fn ca_validated(&self, ca: &str) {
+        let _ = ca;
+    }
+}
+
+impl<P: Progress + ?Sized> Progress for Arc<P> {
+    fn repository_started(&self, repository: &str) {
+        self.as_ref().repository_started(repository)
+    }
+
+    fn repository_finished(&self, repository: &str, success: bool) {
+        self.as_ref().repository_finished(repository, success)
+    }
+
+    fn bytes_downloaded(&self, repository: &str, bytes: u64) {
+        self.as_ref().bytes_downloaded(repository, bytes)
+    }
+
+    fn objects_processed(&self, repository: &str, count: u64) {
+        self.as_ref().objects_processed(repository, count)
+    }
+
+    fn ca_validated(&self, ca: &str) {
+        self.as_ref().ca_validated(ca)
+    }
+}
+
+
+//------------ NoProgress ----------------------------------------------------
+
+/// A progress reporter that ignores all reports.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress { }
+
+
+//------------ SharedProgress ------------------------------------------------
+
+/// A shared reference to a progress reporter.
+///
+/// This is the type components keep their progress reporter in. It can be
+/// created from any `Arc<P>` for a type `P` implementing [`Progress`],
+/// which allows keeping a copy of the arc around for polling the reported
+/// progress. The default value uses [`NoProgress`].
+#[derive(Clone)]
+pub struct SharedProgress(Arc<dyn Progress>);
+
+impl SharedProgress {
+    /// Creates a new value from a progress reporter.
+    pub fn new(progress: impl Progress + 'static) -> Self {
+        SharedProgress(Arc::new(progress))
+    }
+}
+
+impl Default for SharedProgress {
+    fn default() -> Self {
+        SharedProgress::new(NoProgress)
+    }
+}
+
+impl<P: Progress + 'static> From<Arc<P>> for SharedProgress {
+    fn from(progress: Arc<P>) -> Self {
+        SharedProgress(progress)
+    }
+}
+
+impl Deref for SharedProgress {
+    type Target = dyn Progress;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for SharedProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedProgress")
+    }
+}
+
+
+//------------ ProgressCounter -----------------------------------------------
+
+/// A progress reporter summing up all reports.
+///
+/// The counter keeps the totals of everything reported as well as the
+/// repositories currently being worked on. Its methods can be called at
+/// any time, typically from a timer, to learn the current state.
+#[derive(Debug, Default)]
+pub struct ProgressCounter {
+    /// The number of repositories finished successfully.
+    repositories: AtomicU64,
+
+    /// The number of repositories that failed.
+    failed: AtomicU64,
+
+    /// The number of bytes downloaded.
+    bytes: AtomicU64,
+
+    /// The number of objects processed.
+    objects: AtomicU64,
+
+    /// The number of CAs validated.
+    cas: AtomicU64,
+
+    /// The repositories currently being worked on.
+    current: Mutex<Vec<String>>,
+}
+
+impl ProgressCounter {
+    /// Creates a new counter with everything at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of repositories finished successfully.
+    pub fn repositories(&self) -> u64 {
+        self.repositories.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of repositories that failed.
+    pub fn failed_repositories(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes downloaded.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of objects processed.
+    pub fn objects(&self) -> u64 {
+        self.objects.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of CAs validated.
+    pub fn cas(&self) -> u64 {
+        self.cas.load(Ordering::Relaxed)
+    }
+
+    /// Returns the repositories currently being worked on.
+    ///
+    /// The repositories are returned in the order work on them started.
+    pub fn current(&self) -> Vec<String> {
+        self.current.lock().expect("poisoned lock").clone()
+    }
+}
+
+impl Progress for ProgressCounter {
+    fn repository_started(&self, repository: &str) {
+        self.current.lock().expect("poisoned lock").push(repository.into())
+    }
+
+    fn repository_finished(&self, repository: &str, success: bool) {
+        let mut current = self.current.lock().expect("poisoned lock");
+        if let Some(pos) = current.iter().position(|item| item == repository) {
+            current.remove(pos);
+        }
+        if success {
+            self.repositories.fetch_add(1, Ordering::Relaxed);
+        }
+        else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn bytes_downloaded(&self, _repository: &str, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn objects_processed(&self, _repository: &str, count: u64) {
+        self.objects.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn ca_validated(&self, _ca: &str) {
+        self.cas.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counter() {
+        let counter = Arc::new(ProgressCounter::new());
+        let progress = SharedProgress::from(counter.clone());
+        progress.repository_started("https://a.example/n.xml");
+        progress.repository_started("rsync://b.example/m/");
+        progress.bytes_downloaded("https://a.example/n.xml", 100);
+        progress.bytes_downloaded("https://a.example/n.xml", 20);
+        progress.objects_processed("https://a.example/n.xml", 3);
+        assert_eq!(
+            counter.current(),
+            ["https://a.example/n.xml", "rsync://b.example/m/"]
+        );
+        progress.repository_finished("https://a.example/n.xml", true);
+        progress.repository_finished("rsync://b.example/m/", false);
+        progress.ca_validated("rsync://b.example/m/ca.cer");
+        assert!(counter.current().is_empty());
+        assert_eq!(counter.repositories(), 1);
+        assert_eq!(counter.failed_repositories(), 1);
+        assert_eq!(counter.bytes(), 120);
+        assert_eq!(counter.objects(), 3);
+        assert_eq!(counter.cas(), 1);
+    }
+}
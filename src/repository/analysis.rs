@@ -0,0 +1,562 @@
+//! Aggregate statistics over the content of a repository.
+//!
+//! Researchers and monitoring systems often want an overview of a
+//! repository rather than the validated payload: how many objects of each
+//! type there are, how many children each CA has, how much address space
+//! is covered by ROAs under each trust anchor, and when objects are going
+//! to expire.
+//!
+//! The [`RepositoryAnalyzer`] ingests objects either one by one via
+//! [`add_object`][RepositoryAnalyzer::add_object], from an
+//! [`ObjectStore`] such as a directory tree, or – if the `"rrdp"` feature
+//! is enabled – from an RRDP snapshot file. Objects are only decoded, not
+//! validated, so the statistics describe what is published rather than
+//! what a relying party would accept. Issuers are found by matching the
+//! authority key identifier of an object with the subject key identifier
+//! of a CA certificate.
+//!
+//! The outcome is an [`AnalysisReport`] which can be serialized if the
+//! `"serde-support"` feature is enabled.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use bytes::Bytes;
+use crate::uri;
+use crate::crypto::KeyIdentifier;
+use crate::store::ObjectStore;
+use super::aspa::Aspa;
+use super::cert::Cert;
+use super::crl::Crl;
+use super::manifest::Manifest;
+use super::roa::Roa;
+use super::x509::Time;
+
+
+//------------ RepositoryAnalyzer --------------------------------------------
+
+/// Collects statistics over the objects of a repository.
+#[derive(Clone, Debug, Default)]
+pub struct RepositoryAnalyzer {
+    /// Whether to decode objects in strict mode.
+    strict: bool,
+
+    /// The number of objects by type.
+    counts: ObjectCounts,
+
+    /// The CA certificates keyed by their subject key identifier.
+    cas: HashMap<KeyIdentifier, CaInfo>,
+
+    /// The issuer and expiry time of each decoded object.
+    objects: Vec<ObjectInfo>,
+
+    /// The prefixes of all ROAs together with the ROA’s issuer.
+    prefixes: Vec<(KeyIdentifier, IpAddr, u8)>,
+}
+
+/// Information about a CA certificate.
+#[derive(Clone, Debug)]
+struct CaInfo {
+    /// The URI of the certificate.
+    uri: uri::Rsync,
+
+    /// The key identifier of the issuer unless self-signed.
+    issuer: Option<KeyIdentifier>,
+}
+
+/// Information about a decoded object.
+#[derive(Clone, Copy, Debug)]
+struct ObjectInfo {
+    /// The key identifier of the issuer unless self-signed.
+    issuer: Option<KeyIdentifier>,
+
+    /// The time the object expires or becomes stale.
+    expires: Time,
+}
+
+impl RepositoryAnalyzer {
+    /// Creates a new, empty analyzer.
+    ///
+    /// If `strict` is `true`, objects are decoded in strict mode and
+    /// objects not strictly following the standards are counted as
+    /// invalid.
+    pub fn new(strict: bool) -> Self {
+        RepositoryAnalyzer { strict, .. Default::default() }
+    }
+
+    /// Adds all objects below `base` in the given store.
+    pub fn add_store<S: ObjectStore>(
+        &mut self, store: &S, base: &uri::Rsync
+    ) -> Result<(), S::Error> {
+        for uri in store.list(base)? {
+            if let Some(data) = store.get(&uri)? {
+                self.add_object(&uri, data)
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a single object.
+    ///
+    /// The type of the object is determined from the file extension of its
+    /// URI. Objects that fail to decode are counted as invalid.
+    pub fn add_object(&mut self, uri: &uri::Rsync, data: Bytes) {
+        let path = uri.path();
+        let ext = path.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+        let ok = match ext {
+            "cer" => self.add_cert(uri, data),
+            "crl" => self.add_crl(data),
+            "mft" => self.add_manifest(data),
+            "roa" => self.add_roa(data),
+            "asa" => self.add_aspa(data),
+            _ => {
+                self.counts.other += 1;
+                true
+            }
+        };
+        if !ok {
+            self.counts.invalid += 1;
+        }
+    }
+
+    fn add_cert(&mut self, uri: &uri::Rsync, data: Bytes) -> bool {
+        let cert = match Cert::decode(data) {
+            Ok(cert) => cert,
+            Err(_) => return false
+        };
+        let issuer = Self::issuer(&cert);
+        if cert.is_ca() {
+            self.cas.insert(
+                cert.subject_key_identifier(),
+                CaInfo { uri: uri.clone(), issuer }
+            );
+        }
+        self.add_info(issuer, cert.validity().not_after());
+        self.counts.certificates += 1;
+        true
+    }
+
+    fn add_crl(&mut self, data: Bytes) -> bool {
+        let crl = match Crl::decode(data) {
+            Ok(crl) => crl,
+            Err(_) => return false
+        };
+        self.add_info(
+            Some(*crl.authority_key_identifier()), crl.next_update()
+        );
+        self.counts.crls += 1;
+        true
+    }
+
+    fn add_manifest(&mut self, data: Bytes) -> bool {
+        let mft = match Manifest::decode(data, self.strict) {
+            Ok(mft) => mft,
+            Err(_) => return false
+        };
+        self.add_info(
+            Self::issuer(mft.cert()),
+            cmp::min(
+                mft.cert().validity().not_after(),
+                mft.content().next_update()
+            )
+        );
+        self.counts.manifests += 1;
+        true
+    }
+
+    fn add_roa(&mut self, data: Bytes) -> bool {
+        let roa = match Roa::decode(data, self.strict) {
+            Ok(roa) => roa,
+            Err(_) => return false
+        };
+        let issuer = Self::issuer(roa.cert());
+        self.add_info(issuer, roa.cert().validity().not_after());
+        if let Some(issuer) = issuer {
+            for addr in roa.content().iter() {
+                self.prefixes.push(
+                    (issuer, addr.address(), addr.address_length())
+                );
+            }
+        }
+        self.counts.roas += 1;
+        true
+    }
+
+    fn add_aspa(&mut self, data: Bytes) -> bool {
+        let aspa = match Aspa::decode(data, self.strict) {
+            Ok(aspa) => aspa,
+            Err(_) => return false
+        };
+        self.add_info(
+            Self::issuer(aspa.cert()), aspa.cert().validity().not_after()
+        );
+        self.counts.aspas += 1;
+        true
+    }
+
+    fn add_info(&mut self, issuer: Option<KeyIdentifier>, expires: Time) {
+        self.objects.push(ObjectInfo { issuer, expires })
+    }
+
+    /// Returns the key identifier of the issuer of a certificate.
+    fn issuer(cert: &Cert) -> Option<KeyIdentifier> {
+        if cert.is_self_signed() {
+            None
+        }
+        else {
+            cert.authority_key_identifier()
+        }
+    }
+
+    /// Returns the trust anchor certificate for the given issuer.
+    fn trust_anchor(&self, mut key: KeyIdentifier) -> Option<KeyIdentifier> {
+        // Limit the walk to the number of CAs to guard against loops.
+        for _ in 0..=self.cas.len() {
+            match self.cas.get(&key)?.issuer {
+                Some(issuer) => key = issuer,
+                None => return Some(key)
+            }
+        }
+        None
+    }
+
+    /// Produces the report for the current time.
+    pub fn report(&self) -> AnalysisReport {
+        self.report_at(Time::now())
+    }
+
+    /// Produces the report using `now` as the reference for expiry.
+    pub fn report_at(&self, now: Time) -> AnalysisReport {
+        let mut children = HashMap::<_, usize>::new();
+        let mut expiry = ExpiryHistogram::default();
+        for info in &self.objects {
+            if let Some(issuer) = info.issuer {
+                *children.entry(issuer).or_default() += 1;
+            }
+            expiry.add(info.expires, now);
+        }
+        let mut cas: Vec<_> = self.cas.iter().map(|(key, ca)| {
+            CaStats {
+                uri: ca.uri.clone(),
+                children: children.get(key).copied().unwrap_or(0),
+            }
+        }).collect();
+        cas.sort_by(|l, r| l.uri.as_str().cmp(r.uri.as_str()));
+
+        let mut ranges = HashMap::<_, (Vec<_>, Vec<_>)>::new();
+        for (issuer, addr, len) in &self.prefixes {
+            let ta = match self.trust_anchor(*issuer) {
+                Some(ta) => ta,
+                None => continue
+            };
+            let entry = ranges.entry(ta).or_default();
+            match *addr {
+                IpAddr::V4(addr) => {
+                    entry.0.push(range(u32::from(addr).into(), *len, 32))
+                }
+                IpAddr::V6(addr) => {
+                    entry.1.push(range(u128::from(addr), *len, 128))
+                }
+            }
+        }
+        let mut trust_anchors: Vec<_> = ranges.into_iter().filter_map(
+            |(key, (v4, v6))| {
+                Some(TaCoverage {
+                    uri: self.cas.get(&key)?.uri.clone(),
+                    ipv4_addresses: covered(v4) as u64,
+                    ipv6_addresses: covered(v6),
+                })
+            }
+        ).collect();
+        trust_anchors.sort_by(|l, r| l.uri.as_str().cmp(r.uri.as_str()));
+
+        AnalysisReport {
+            objects: self.counts, cas, trust_anchors, expiry
+        }
+    }
+}
+
+
+//--- ProcessSnapshot
+
+#[cfg(feature = "rrdp")]
+impl crate::rrdp::ProcessSnapshot for RepositoryAnalyzer {
+    type Err = crate::rrdp::ProcessError;
+
+    fn meta(
+        &mut self,
+        _session_id: uuid::Uuid,
+        _serial: u64,
+    ) -> Result<(), Self::Err> {
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        data: &mut crate::rrdp::ObjectReader,
+    ) -> Result<(), Self::Err> {
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(data, &mut content)?;
+        self.add_object(&uri, content.into());
+        Ok(())
+    }
+}
+
+
+//------------ AnalysisReport ------------------------------------------------
+
+/// Aggregate statistics over the content of a repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalysisReport {
+    /// The number of objects by type.
+    pub objects: ObjectCounts,
+
+    /// The number of children of each CA, ordered by URI.
+    pub cas: Vec<CaStats>,
+
+    /// The address space covered by ROAs under each trust anchor.
+    ///
+    /// The list is ordered by the URI of the trust anchor certificate.
+    pub trust_anchors: Vec<TaCoverage>,
+
+    /// The distribution of the expiry times of all objects.
+    pub expiry: ExpiryHistogram,
+}
+
+
+//------------ ObjectCounts --------------------------------------------------
+
+/// The number of objects by type.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectCounts {
+    pub certificates: usize,
+    pub crls: usize,
+    pub manifests: usize,
+    pub roas: usize,
+    pub aspas: usize,
+
+    /// Objects with an unknown file extension.
+    pub other: usize,
+
+    /// Objects with a known file extension that failed to decode.
+    pub invalid: usize,
+}
+
+impl ObjectCounts {
+    /// Returns the total number of objects.
+    pub fn total(&self) -> usize {
+        self.certificates + self.crls + self.manifests + self.roas
+            + self.aspas + self.other + self.invalid
+    }
+}
+
+
+//------------ CaStats -------------------------------------------------------
+
+/// Statistics for a single CA.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaStats {
+    /// The URI of the CA certificate.
+    pub uri: uri::Rsync,
+
+    /// The number of objects issued by the CA.
+    pub children: usize,
+}
+
+
+//------------ TaCoverage ----------------------------------------------------
+
+/// The address space covered by ROAs under a trust anchor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaCoverage {
+    /// The URI of the trust anchor certificate.
+    pub uri: uri::Rsync,
+
+    /// The number of IPv4 addresses covered by at least one ROA.
+    pub ipv4_addresses: u64,
+
+    /// The number of IPv6 addresses covered by at least one ROA.
+    pub ipv6_addresses: u128,
+}
+
+
+//------------ ExpiryHistogram -----------------------------------------------
+
+/// The distribution of the expiry times of objects.
+///
+/// Each object is counted in the first bucket its expiry time falls into.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExpiryHistogram {
+    /// Objects that have already expired.
+    pub expired: usize,
+
+    /// Objects expiring within a day.
+    pub day: usize,
+
+    /// Objects expiring within a week.
+    pub week: usize,
+
+    /// Objects expiring within 30 days.
+    pub month: usize,
+
+    /// Objects expiring within 365 days.
+    pub year: usize,
+
+    /// Objects expiring later.
+    pub later: usize,
+}
+
+impl ExpiryHistogram {
+    fn add(&mut self, expires: Time, now: Time) {
+        const DAY: i64 = 24 * 3600;
+
+        let left = expires.timestamp() - now.timestamp();
+        let bucket = if left < 0 {
+            &mut self.expired
+        }
+        else if left < DAY {
+            &mut self.day
+        }
+        else if left < 7 * DAY {
+            &mut self.week
+        }
+        else if left < 30 * DAY {
+            &mut self.month
+        }
+        else if left < 365 * DAY {
+            &mut self.year
+        }
+        else {
+            &mut self.later
+        };
+        *bucket += 1;
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Returns the first and last address of a prefix.
+fn range(addr: u128, len: u8, bits: u8) -> (u128, u128) {
+    let host = u32::from(bits.saturating_sub(len));
+    let mask = 1u128.checked_shl(host).map(|x| x - 1).unwrap_or(u128::MAX);
+    (addr & !mask, addr | mask)
+}
+
+/// Returns the number of addresses covered by a list of ranges.
+///
+/// Saturates if all IPv6 addresses are covered.
+fn covered(mut ranges: Vec<(u128, u128)>) -> u128 {
+    ranges.sort_unstable();
+    let mut res = 0u128;
+    let mut current: Option<(u128, u128)> = None;
+    for (start, end) in ranges {
+        current = match current {
+            Some((cur_start, cur_end)) if start <= cur_end.saturating_add(1)
+                => Some((cur_start, cmp::max(cur_end, end))),
+            Some((cur_start, cur_end)) => {
+                res = res.saturating_add(
+                    (cur_end - cur_start).saturating_add(1)
+                );
+                Some((start, end))
+            }
+            None => Some((start, end))
+        };
+    }
+    if let Some((start, end)) = current {
+        res = res.saturating_add((end - start).saturating_add(1));
+    }
+    res
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn covered_ranges() {
+        assert_eq!(covered(vec![]), 0);
+        assert_eq!(
+            covered(vec![
+                range(0x0a00_0000, 8, 32),
+                range(0x0a01_0000, 16, 32),
+                range(0x0b00_0000, 24, 32),
+            ]),
+            (1 << 24) + 256
+        );
+        assert_eq!(covered(vec![range(0, 0, 128)]), u128::MAX);
+    }
+
+    #[test]
+    fn analyze() {
+        let mut analyzer = RepositoryAnalyzer::new(false);
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/ta.cer"),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.cer")
+            )
+        );
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/ta.crl"),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.crl")
+            )
+        );
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/ta.mft"),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.mft")
+            )
+        );
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/ca1.cer"),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ca1.cer")
+            )
+        );
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/broken.roa"),
+            Bytes::from_static(b"broken")
+        );
+        analyzer.add_object(
+            &uri("rsync://example.com/ta/README"),
+            Bytes::from_static(b"hello")
+        );
+
+        let report = analyzer.report_at(Time::utc(2019, 5, 1, 0, 0, 0));
+        assert_eq!(
+            report.objects,
+            ObjectCounts {
+                certificates: 2, crls: 1, manifests: 1, other: 1,
+                invalid: 1, .. Default::default()
+            }
+        );
+        assert_eq!(report.objects.total(), 6);
+        assert_eq!(report.cas.len(), 2);
+        let ta = report.cas.iter().find(|ca| {
+            ca.uri == uri("rsync://example.com/ta/ta.cer")
+        }).unwrap();
+        assert_eq!(ta.children, 3);
+        assert!(report.trust_anchors.is_empty());
+        assert_eq!(
+            report.expiry.expired + report.expiry.day + report.expiry.week
+                + report.expiry.month + report.expiry.year
+                + report.expiry.later,
+            4
+        );
+    }
+}
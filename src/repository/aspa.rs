@@ -9,6 +9,7 @@
 
 use std::fmt;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use bcder::{decode, encode};
 use bcder::{Captured, Mode, Oid, Tag};
 use bcder::decode::{DecodeError, IntoSource, SliceSource, Source};
@@ -19,8 +20,10 @@ use crate::resources::asn::SmallAsnSet;
 use crate::util::base64;
 use super::cert::{Cert, ResourceCert};
 use super::error::{ValidationError, VerificationError};
+use super::options::{GraceWarning, ValidationOptions};
 use super::resources::{AsBlock, AsBlocks, AsBlocksBuilder, Asn, AsResources};
 use super::sigobj::{SignedObject, SignedObjectBuilder};
+use super::x509::Time;
 
 
 //------------ Aspa ----------------------------------------------------------
@@ -58,6 +61,70 @@ impl Aspa {
         Ok((cert, self.content))
     }
 
+    /// Processes the ASPA at the given time.
+    ///
+    /// This is identical to [`process`][Self::process] except that the
+    /// validity of the ASPA’s EE certificate is checked against `now`
+    /// instead of the current time.
+    pub fn process_at<F>(
+        mut self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, AsProviderAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let cert = self.signed.validate_at(issuer, strict, now)?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert)?;
+        Ok((cert, self.content))
+    }
+
+    /// Processes the ASPA using the given options.
+    ///
+    /// Unlike [`process`][Self::process], this checks the signing time
+    /// against the window given in the options and applies the grace
+    /// period for an EE certificate that is not yet valid. If the grace
+    /// period was needed, a warning is returned alongside the result.
+    pub fn process_with_options<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, AsProviderAttestation, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_with_options_at(
+            issuer, options, Time::now(), check_crl
+        )
+    }
+
+    /// Processes the ASPA using the given options at the given time.
+    ///
+    /// This is identical to
+    /// [`process_with_options`][Self::process_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn process_with_options_at<F>(
+        mut self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, AsProviderAttestation, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let (cert, warning) = self.signed.validate_with_options_at(
+            issuer, options, now
+        )?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert)?;
+        Ok((cert, self.content, warning))
+    }
+
     /// Returns a value encoder for a reference to an ASPA.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         self.signed.encode_ref()
@@ -68,6 +135,14 @@ impl Aspa {
         self.encode_ref().to_captured(Mode::Der)
     }
 
+    /// Returns the encoded octets of the ASPA.
+    ///
+    /// If the ASPA was decoded, these are exactly the octets it was
+    /// decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
     /// Returns a reference to the EE certificate of this ROA.
     pub fn cert(&self) -> &Cert {
         self.signed.cert()
@@ -214,7 +289,7 @@ impl ProviderAsSet {
         }
     }
 
-    pub fn iter(&self) -> ProviderAsIter {
+    pub fn iter(&self) -> ProviderAsIter<'_> {
         ProviderAsIter(self.0.as_slice().into_source())
     }
 
@@ -365,6 +440,255 @@ impl AspaBuilder {
 }
 
 
+//------------ AspaSet -------------------------------------------------------
+
+/// A collection of validated ASPA objects.
+///
+/// The set keeps the provider ASNs for each customer ASN. If there is more
+/// than one ASPA object for the same customer ASN, the union of their
+/// provider sets is used.
+///
+/// The set implements the AS_PATH verification procedures of
+/// [draft-ietf-sidrops-aspa-verification] via the
+/// [`verify_path`][Self::verify_path] method.
+///
+/// [draft-ietf-sidrops-aspa-verification]: https://datatracker.ietf.org/doc/draft-ietf-sidrops-aspa-verification/
+#[derive(Clone, Debug, Default)]
+pub struct AspaSet {
+    /// The provider ASNs for each customer ASN.
+    providers: BTreeMap<Asn, SmallAsnSet>,
+}
+
+impl AspaSet {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of customer ASNs in the set.
+    pub fn len(&self) -> usize {
+        self.providers.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Adds the content of a validated ASPA object.
+    pub fn insert(&mut self, aspa: &AsProviderAttestation) {
+        self.insert_providers(
+            aspa.customer_as(), aspa.provider_as_set().iter()
+        )
+    }
+
+    /// Adds providers for a customer ASN.
+    pub fn insert_providers(
+        &mut self, customer: Asn, providers: impl IntoIterator<Item = Asn>
+    ) {
+        let mut providers: Vec<_> = providers.into_iter().collect();
+        providers.sort();
+        providers.dedup();
+        // Safety: We just sorted and de-duplicated.
+        let providers = unsafe { SmallAsnSet::from_vec_unchecked(providers) };
+        let providers = match self.providers.get(&customer) {
+            Some(existing) => existing.union(&providers).collect(),
+            None => providers
+        };
+        self.providers.insert(customer, providers);
+    }
+
+    /// Returns the provider ASNs of a customer ASN.
+    ///
+    /// Returns `None` if there is no ASPA for the customer ASN.
+    pub fn providers(&self, customer: Asn) -> Option<&SmallAsnSet> {
+        self.providers.get(&customer)
+    }
+
+    /// Returns an iterator over customer ASNs and their providers.
+    pub fn iter(&self) -> impl Iterator<Item = (Asn, &SmallAsnSet)> + '_ {
+        self.providers.iter().map(|(asn, providers)| (*asn, providers))
+    }
+
+    /// Returns whether `provider` is an authorized provider of `customer`.
+    ///
+    /// This is the hop check function of the verification procedure.
+    pub fn authorized(
+        &self, customer: Asn, provider: Asn
+    ) -> ProviderAuthorization {
+        match self.providers.get(&customer) {
+            Some(providers) if providers.contains(provider) => {
+                ProviderAuthorization::Provider
+            }
+            Some(_) => ProviderAuthorization::NotProvider,
+            None => ProviderAuthorization::NoAttestation,
+        }
+    }
+
+    /// Verifies an AS_PATH.
+    ///
+    /// The `path` is given in the order it appears in the AS_PATH
+    /// attribute of a route, i.e., starting with the neighbor AS and
+    /// ending with the origin AS. Prepended ASNs are collapsed before
+    /// verification. The path must not contain AS_SET segments – such a
+    /// path is always invalid and should not be passed to this method.
+    ///
+    /// The `direction` describes from which kind of neighbor the route was
+    /// received.
+    ///
+    /// Checking that the neighbor AS is indeed the first AS in the path is
+    /// left to the caller.
+    pub fn verify_path(
+        &self, path: &[Asn], direction: AspaDirection,
+    ) -> AspaVerification {
+        // Collapse prepends and turn into origin first order.
+        let mut path: Vec<_> = path.iter().rev().copied().collect();
+        path.dedup();
+        let len = path.len();
+
+        // authorized(AS(i), AS(i+1)) for the up-ramp and
+        // authorized(AS(j), AS(j-1)) for the down-ramp, both in the order
+        // we need to walk them.
+        let up = path.windows(2).map(|hop| self.authorized(hop[0], hop[1]));
+        let down = path.windows(2).rev().map(|hop| {
+            self.authorized(hop[1], hop[0])
+        });
+
+        match direction {
+            AspaDirection::Upstream => {
+                if len <= 1 {
+                    return AspaVerification::Valid
+                }
+                let (max_up, min_up) = Self::ramps(up, len);
+                if max_up < len {
+                    AspaVerification::Invalid
+                }
+                else if min_up < len {
+                    AspaVerification::Unknown
+                }
+                else {
+                    AspaVerification::Valid
+                }
+            }
+            AspaDirection::Downstream => {
+                if len <= 2 {
+                    return AspaVerification::Valid
+                }
+                let (max_up, min_up) = Self::ramps(up, len);
+                let (max_down, min_down) = Self::ramps(down, len);
+                if max_up + max_down < len {
+                    AspaVerification::Invalid
+                }
+                else if min_up + min_down < len {
+                    AspaVerification::Unknown
+                }
+                else {
+                    AspaVerification::Valid
+                }
+            }
+        }
+    }
+
+    /// Returns the maximum and minimum length of a ramp.
+    ///
+    /// The maximum ramp ends before the first hop that is not a provider
+    /// while the minimum ramp also ends before the first hop without an
+    /// attestation. If there is no such hop, the ramp covers the entire
+    /// path of length `len`.
+    fn ramps(
+        hops: impl Iterator<Item = ProviderAuthorization>, len: usize
+    ) -> (usize, usize) {
+        let mut max = None;
+        let mut min = None;
+        for (idx, hop) in hops.enumerate() {
+            match hop {
+                ProviderAuthorization::Provider => { }
+                ProviderAuthorization::NoAttestation => {
+                    if min.is_none() {
+                        min = Some(idx + 1)
+                    }
+                }
+                ProviderAuthorization::NotProvider => {
+                    if min.is_none() {
+                        min = Some(idx + 1)
+                    }
+                    max = Some(idx + 1);
+                    break
+                }
+            }
+        }
+        (max.unwrap_or(len), min.unwrap_or(len))
+    }
+}
+
+#[cfg(feature = "rtr")]
+impl AspaSet {
+    /// Returns an iterator over the RTR ASPA payload of the set.
+    ///
+    /// Customers with more providers than fit into a single RTR PDU are
+    /// skipped.
+    pub fn iter_payload(
+        &self
+    ) -> impl Iterator<Item = crate::rtr::payload::Aspa> + '_ {
+        use crate::rtr::payload::Aspa as AspaPayload;
+        use crate::rtr::pdu::ProviderAsns;
+
+        self.providers.iter().filter_map(|(customer, providers)| {
+            ProviderAsns::try_from_iter(providers.iter()).ok().map(|p| {
+                AspaPayload::new(*customer, p)
+            })
+        })
+    }
+}
+
+
+//------------ AspaDirection -------------------------------------------------
+
+/// The direction a route was received from for ASPA verification.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AspaDirection {
+    /// The route was received from a customer or lateral peer.
+    ///
+    /// This includes routes received by a route server from its clients.
+    Upstream,
+
+    /// The route was received from a provider.
+    Downstream,
+}
+
+
+//------------ ProviderAuthorization -----------------------------------------
+
+/// The outcome of checking a single hop of an AS_PATH.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProviderAuthorization {
+    /// The customer AS has no ASPA.
+    NoAttestation,
+
+    /// The AS is an authorized provider of the customer AS.
+    Provider,
+
+    /// The customer AS has an ASPA but it doesn’t include the AS.
+    NotProvider,
+}
+
+
+//------------ AspaVerification ----------------------------------------------
+
+/// The outcome of ASPA-based AS_PATH verification.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AspaVerification {
+    /// The path is valid.
+    Valid,
+
+    /// The path is invalid.
+    Invalid,
+
+    /// There is not enough information to determine the validity.
+    Unknown,
+}
+
+
 //------------ DuplicateProviderAs -------------------------------------------
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -402,6 +726,88 @@ mod test {
         );
     }
 
+    fn asns(asns: &[u32]) -> Vec<Asn> {
+        asns.iter().map(|&asn| asn.into()).collect()
+    }
+
+    #[test]
+    fn aspa_set() {
+        let mut set = AspaSet::new();
+        set.insert_providers(10.into(), asns(&[20, 30]));
+        set.insert_providers(10.into(), asns(&[30, 40]));
+        set.insert_providers(20.into(), asns(&[100]));
+        set.insert_providers(30.into(), asns(&[0]));
+        assert_eq!(set.len(), 3);
+        assert_eq!(
+            set.providers(10.into()).unwrap().iter().collect::<Vec<_>>(),
+            asns(&[20, 30, 40])
+        );
+        assert_eq!(
+            set.authorized(10.into(), 40.into()),
+            ProviderAuthorization::Provider
+        );
+        assert_eq!(
+            set.authorized(30.into(), 40.into()),
+            ProviderAuthorization::NotProvider
+        );
+        assert_eq!(
+            set.authorized(40.into(), 10.into()),
+            ProviderAuthorization::NoAttestation
+        );
+
+        let content = Mode::Der.decode(
+            include_bytes!(
+                "../../test-data/repository/aspa-content.der"
+            ).as_ref(),
+            AsProviderAttestation::take_from
+        ).unwrap();
+        set.insert(&content);
+        assert_eq!(set.providers(15562.into()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn verify_path() {
+        use self::AspaDirection::{Downstream, Upstream};
+        use self::AspaVerification::{Invalid, Unknown, Valid};
+
+        // 10 has provider 20, 20 has provider 100, 30 has no providers.
+        let mut set = AspaSet::new();
+        set.insert_providers(10.into(), asns(&[20]));
+        set.insert_providers(20.into(), asns(&[100]));
+        set.insert_providers(30.into(), asns(&[0]));
+        set.insert_providers(200.into(), asns(&[100]));
+
+        // Paths are in AS_PATH order, neighbor first.
+        assert_eq!(set.verify_path(&asns(&[10]), Upstream), Valid);
+        assert_eq!(set.verify_path(&asns(&[20, 10]), Upstream), Valid);
+        assert_eq!(set.verify_path(&asns(&[20, 20, 10]), Upstream), Valid);
+        assert_eq!(
+            set.verify_path(&asns(&[100, 20, 10]), Upstream), Valid
+        );
+        assert_eq!(set.verify_path(&asns(&[40, 10]), Upstream), Invalid);
+        assert_eq!(set.verify_path(&asns(&[10, 40]), Upstream), Unknown);
+
+        // Up from 10 to 100, then down to 200.
+        assert_eq!(
+            set.verify_path(&asns(&[200, 100, 20, 10]), Downstream), Valid
+        );
+        // Down from 100 to 30 is fine, but 30 has no providers.
+        assert_eq!(
+            set.verify_path(&asns(&[30, 100, 20, 10]), Downstream), Valid
+        );
+        // 20 to 30 can only be a lateral hop but 200 doesn’t have 30 as
+        // its provider.
+        assert_eq!(
+            set.verify_path(&asns(&[200, 30, 20, 10]), Downstream), Invalid
+        );
+        assert_eq!(
+            set.verify_path(&asns(&[100, 30, 20, 10]), Downstream), Unknown
+        );
+        assert_eq!(
+            set.verify_path(&asns(&[50, 100, 20, 40]), Downstream), Unknown
+        );
+    }
+
     #[test]
     fn fail_decode_draft_13() {
         assert!(
@@ -500,6 +906,7 @@ mod signer_test {
         let decoded = Aspa::decode(encoded.as_slice(), true).unwrap();
         
         assert_eq!(encoded.as_slice(), decoded.to_captured().as_slice());
+        assert_eq!(encoded.as_slice(), decoded.as_bytes());
         
         let (_, attestation) = decoded.process(
             &issuer_cert, true, |_| Ok(())
@@ -0,0 +1,582 @@
+//! Measuring the throughput of object processing.
+//!
+//! Parsing and validating repository objects is where a relying party
+//! spends most of its time. This module allows measuring how fast this
+//! happens for a given set of objects in a given environment, so that
+//! users can track performance across releases of the crate and changes
+//! to their setup.
+//!
+//! A [`Corpus`] collects the objects to measure, typically by loading a
+//! local copy of a repository via [`Corpus::load_dir`]. A [`Benchmark`]
+//! then processes all objects in the corpus a number of times and
+//! produces a [`Report`] with the [`Throughput`] for each kind of object.
+//! Reports can be compared against an earlier report serving as a
+//! baseline via [`Report::regressions`].
+//!
+//! The benchmark can measure two phases. When only parsing, every object
+//! is decoded. When validating, the signature of each object is verified
+//! in addition. For certificates and CRLs, this requires the issuing
+//! certificate to be part of the corpus, unless the certificate is self
+//! signed. Objects whose issuer is missing are counted as skipped. For
+//! signed objects, the signature is verified against the EE certificate
+//! contained in the object; the EE certificate itself is treated like
+//! any other certificate. Since there is no complete chain to a trust
+//! anchor, resource checks are not performed.
+#![cfg(feature = "bench")]
+
+use std::{fmt, fs, io};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use crate::crypto::KeyIdentifier;
+use super::cert::Cert;
+use super::crl::Crl;
+use super::manifest::Manifest;
+use super::roa::Roa;
+
+
+//------------ ObjectKind ----------------------------------------------------
+
+/// The kind of an object in a corpus.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ObjectKind {
+    /// A resource or router certificate.
+    Cert,
+
+    /// A certificate revocation list.
+    Crl,
+
+    /// A manifest.
+    Manifest,
+
+    /// A route origin authorization.
+    Roa,
+}
+
+impl ObjectKind {
+    /// All object kinds in the order they are reported in.
+    pub const ALL: [ObjectKind; 4] = [
+        ObjectKind::Cert, ObjectKind::Crl, ObjectKind::Manifest,
+        ObjectKind::Roa,
+    ];
+
+    /// Determines the kind of an object from its file name.
+    ///
+    /// Returns `None` if the extension isn’t one of a supported kind.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        if ext.eq_ignore_ascii_case("cer") {
+            Some(ObjectKind::Cert)
+        }
+        else if ext.eq_ignore_ascii_case("crl") {
+            Some(ObjectKind::Crl)
+        }
+        else if ext.eq_ignore_ascii_case("mft") {
+            Some(ObjectKind::Manifest)
+        }
+        else if ext.eq_ignore_ascii_case("roa") {
+            Some(ObjectKind::Roa)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns a short name of the kind.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectKind::Cert => "cert",
+            ObjectKind::Crl => "crl",
+            ObjectKind::Manifest => "manifest",
+            ObjectKind::Roa => "roa",
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+
+//------------ Corpus --------------------------------------------------------
+
+/// A set of objects to measure.
+#[derive(Clone, Debug, Default)]
+pub struct Corpus {
+    /// The objects and their kinds.
+    objects: Vec<(ObjectKind, Bytes)>,
+}
+
+impl Corpus {
+    /// Creates a new, empty corpus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a corpus from all objects in a directory tree.
+    ///
+    /// The kind of an object is determined from its file name. Files of
+    /// unknown kind are ignored.
+    pub fn load_dir(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let mut res = Self::new();
+        res.add_dir(path.as_ref())?;
+        Ok(res)
+    }
+
+    /// Adds all objects in a directory tree.
+    pub fn add_dir(&mut self, path: &Path) -> Result<(), io::Error> {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                self.add_dir(&path)?;
+                continue;
+            }
+            let kind = match entry.file_name().to_str().and_then(
+                ObjectKind::from_file_name
+            ) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            self.add(kind, fs::read(path)?.into());
+        }
+        Ok(())
+    }
+
+    /// Adds an object.
+    pub fn add(&mut self, kind: ObjectKind, data: Bytes) {
+        self.objects.push((kind, data))
+    }
+
+    /// Returns the number of objects in the corpus.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the corpus is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns the number of objects of the given kind.
+    pub fn count(&self, kind: ObjectKind) -> usize {
+        self.objects.iter().filter(|(item, _)| *item == kind).count()
+    }
+
+    /// Returns the certificates of the corpus by subject key identifier.
+    fn issuers(&self) -> HashMap<KeyIdentifier, Cert> {
+        self.objects.iter().filter(|(kind, _)| {
+            *kind == ObjectKind::Cert
+        }).filter_map(|(_, data)| {
+            Cert::decode(data.as_ref()).ok()
+        }).map(|cert| (cert.subject_key_identifier(), cert)).collect()
+    }
+}
+
+
+//------------ Benchmark -----------------------------------------------------
+
+/// The configuration of a benchmark run.
+///
+/// By default, objects are only parsed, every object is processed once,
+/// and strict mode is off.
+#[derive(Clone, Copy, Debug)]
+pub struct Benchmark {
+    /// The number of times every object is processed.
+    iterations: usize,
+
+    /// Whether signatures are verified, too.
+    validate: bool,
+
+    /// Whether to use strict mode.
+    strict: bool,
+}
+
+impl Benchmark {
+    /// Creates a new benchmark with the default configuration.
+    pub fn new() -> Self {
+        Benchmark { iterations: 1, validate: false, strict: false }
+    }
+
+    /// Sets the number of times every object is processed.
+    ///
+    /// A value of zero is treated as one.
+    pub fn set_iterations(&mut self, iterations: usize) {
+        self.iterations = iterations
+    }
+
+    /// Sets whether signatures are verified in addition to parsing.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate
+    }
+
+    /// Sets whether to use strict mode.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict
+    }
+
+    /// Runs the benchmark over a corpus.
+    pub fn run(&self, corpus: &Corpus) -> Report {
+        let issuers = if self.validate {
+            corpus.issuers()
+        }
+        else {
+            HashMap::new()
+        };
+        let mut report = Report::default();
+        for _ in 0..self.iterations.max(1) {
+            for (kind, data) in &corpus.objects {
+                let start = Instant::now();
+                let outcome = self.process(*kind, data, &issuers);
+                let elapsed = start.elapsed();
+                let item = report.get_mut(*kind);
+                item.elapsed += elapsed;
+                item.bytes += data.len() as u64;
+                match outcome {
+                    Outcome::Success => item.objects += 1,
+                    Outcome::Skipped => item.skipped += 1,
+                    Outcome::Failed => item.failures += 1,
+                }
+            }
+        }
+        report
+    }
+
+    /// Processes a single object.
+    fn process(
+        &self,
+        kind: ObjectKind,
+        data: &Bytes,
+        issuers: &HashMap<KeyIdentifier, Cert>,
+    ) -> Outcome {
+        match kind {
+            ObjectKind::Cert => {
+                let cert = match Cert::decode(data.as_ref()) {
+                    Ok(cert) => cert,
+                    Err(_) => return Outcome::Failed,
+                };
+                if self.validate {
+                    self.verify_cert(&cert, issuers)
+                }
+                else {
+                    Outcome::Success
+                }
+            }
+            ObjectKind::Crl => {
+                let crl = match Crl::decode(data.as_ref()) {
+                    Ok(crl) => crl,
+                    Err(_) => return Outcome::Failed,
+                };
+                if !self.validate {
+                    return Outcome::Success
+                }
+                match issuers.get(crl.authority_key_identifier()) {
+                    Some(issuer) => {
+                        Outcome::from_result(crl.verify_signature(
+                            issuer.subject_public_key_info()
+                        ))
+                    }
+                    None => Outcome::Skipped,
+                }
+            }
+            ObjectKind::Manifest => {
+                let mft = match Manifest::decode(data.as_ref(), self.strict) {
+                    Ok(mft) => mft,
+                    Err(_) => return Outcome::Failed,
+                };
+                if !self.validate {
+                    return Outcome::Success
+                }
+                if mft.signed_object().verify_self(self.strict).is_err() {
+                    return Outcome::Failed
+                }
+                self.verify_cert(mft.cert(), issuers)
+            }
+            ObjectKind::Roa => {
+                let roa = match Roa::decode(data.as_ref(), self.strict) {
+                    Ok(roa) => roa,
+                    Err(_) => return Outcome::Failed,
+                };
+                if !self.validate {
+                    return Outcome::Success
+                }
+                if roa.signed_object().verify_self(self.strict).is_err() {
+                    return Outcome::Failed
+                }
+                self.verify_cert(roa.cert(), issuers)
+            }
+        }
+    }
+
+    /// Verifies the signature of a certificate.
+    fn verify_cert(
+        &self, cert: &Cert, issuers: &HashMap<KeyIdentifier, Cert>
+    ) -> Outcome {
+        let issuer = match cert.authority_key_identifier() {
+            Some(aki) if aki != cert.subject_key_identifier() => {
+                match issuers.get(&aki) {
+                    Some(issuer) => issuer,
+                    None => return Outcome::Skipped,
+                }
+            }
+            _ => cert,
+        };
+        Outcome::from_result(cert.verify_signature(issuer, self.strict))
+    }
+}
+
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+//------------ Outcome -------------------------------------------------------
+
+/// The outcome of processing a single object.
+enum Outcome {
+    Success,
+    Skipped,
+    Failed,
+}
+
+impl Outcome {
+    fn from_result<T, E>(res: Result<T, E>) -> Self {
+        match res {
+            Ok(_) => Outcome::Success,
+            Err(_) => Outcome::Failed,
+        }
+    }
+}
+
+
+//------------ Report --------------------------------------------------------
+
+/// The result of a benchmark run.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The throughput of each kind of object.
+    kinds: HashMap<ObjectKind, Throughput>,
+}
+
+impl Report {
+    /// Returns the throughput for a kind of object.
+    ///
+    /// Returns `None` if the corpus didn’t contain objects of this kind.
+    pub fn get(&self, kind: ObjectKind) -> Option<&Throughput> {
+        self.kinds.get(&kind)
+    }
+
+    /// Returns the throughput for all kinds of objects.
+    pub fn iter(&self) -> impl Iterator<Item = &Throughput> + '_ {
+        ObjectKind::ALL.into_iter().filter_map(|kind| self.get(kind))
+    }
+
+    /// Returns the combined throughput of all kinds of objects.
+    ///
+    /// The kind of the returned value is meaningless.
+    pub fn total(&self) -> Throughput {
+        let mut res = Throughput::new(ObjectKind::Cert);
+        for item in self.kinds.values() {
+            res.objects += item.objects;
+            res.skipped += item.skipped;
+            res.failures += item.failures;
+            res.bytes += item.bytes;
+            res.elapsed += item.elapsed;
+        }
+        res
+    }
+
+    /// Returns the kinds of objects that got slower than in a baseline.
+    ///
+    /// A kind is considered to have regressed if its objects per second
+    /// dropped by more than `tolerance`, given as a fraction of the
+    /// baseline value. Kinds missing from either report are ignored.
+    /// Returns the kinds together with the ratio of the current to the
+    /// baseline throughput.
+    pub fn regressions(
+        &self, baseline: &Report, tolerance: f64
+    ) -> Vec<(ObjectKind, f64)> {
+        self.iter().filter_map(|item| {
+            let base = baseline.get(item.kind)?.objects_per_sec();
+            if base <= 0. {
+                return None
+            }
+            let ratio = item.objects_per_sec() / base;
+            (ratio < 1. - tolerance).then_some((item.kind, ratio))
+        }).collect()
+    }
+
+    /// Returns the mutable throughput for a kind, adding it if necessary.
+    fn get_mut(&mut self, kind: ObjectKind) -> &mut Throughput {
+        self.kinds.entry(kind).or_insert_with(|| Throughput::new(kind))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in self.iter() {
+            writeln!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Throughput ----------------------------------------------------
+
+/// The measured throughput for a kind of object.
+#[derive(Clone, Copy, Debug)]
+pub struct Throughput {
+    /// The kind of object.
+    pub kind: ObjectKind,
+
+    /// The number of objects processed successfully.
+    pub objects: u64,
+
+    /// The number of objects that couldn’t be validated for lack of an
+    /// issuer.
+    pub skipped: u64,
+
+    /// The number of objects that failed to parse or validate.
+    pub failures: u64,
+
+    /// The total size of all processed objects.
+    pub bytes: u64,
+
+    /// The total time spent processing.
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Creates an empty value for the given kind.
+    fn new(kind: ObjectKind) -> Self {
+        Throughput {
+            kind,
+            objects: 0,
+            skipped: 0,
+            failures: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns the number of objects processed in total.
+    pub fn total(&self) -> u64 {
+        self.objects + self.skipped + self.failures
+    }
+
+    /// Returns the number of objects processed per second.
+    pub fn objects_per_sec(&self) -> f64 {
+        per_sec(self.total(), self.elapsed)
+    }
+
+    /// Returns the number of bytes processed per second.
+    pub fn bytes_per_sec(&self) -> f64 {
+        per_sec(self.bytes, self.elapsed)
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{:<8} {:>10} objects {:>12.1} obj/s {:>12.1} KiB/s",
+            self.kind, self.total(), self.objects_per_sec(),
+            self.bytes_per_sec() / 1024.
+        )?;
+        if self.skipped > 0 {
+            write!(f, " ({} skipped)", self.skipped)?;
+        }
+        if self.failures > 0 {
+            write!(f, " ({} failed)", self.failures)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the rate of `count` over `elapsed`.
+fn per_sec(count: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0. {
+        count as f64 / secs
+    }
+    else {
+        0.
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn corpus() -> Corpus {
+        let mut corpus = Corpus::new();
+        for name in [
+            "ta.cer", "ta.crl", "ta.mft", "ca1.cer", "ca1.crl", "ca1.mft",
+            "example-ripe.roa",
+        ] {
+            corpus.add(
+                ObjectKind::from_file_name(name).unwrap(),
+                fs::read(
+                    Path::new("test-data/repository").join(name)
+                ).unwrap().into()
+            );
+        }
+        corpus
+    }
+
+    #[test]
+    fn parse() {
+        let corpus = corpus();
+        assert_eq!(corpus.count(ObjectKind::Cert), 2);
+        let mut bench = Benchmark::new();
+        bench.set_iterations(2);
+        let report = bench.run(&corpus);
+        let certs = report.get(ObjectKind::Cert).unwrap();
+        assert_eq!(certs.objects, 4);
+        assert_eq!(certs.failures, 0);
+        assert_eq!(report.total().total(), 14);
+        assert_eq!(report.iter().count(), 4);
+        assert!(report.to_string().starts_with("cert"));
+    }
+
+    #[test]
+    fn validate() {
+        let mut bench = Benchmark::new();
+        bench.set_validate(true);
+        let report = bench.run(&corpus());
+        // The ROA is from a different repository and its issuer isn’t
+        // part of the corpus.
+        let roas = report.get(ObjectKind::Roa).unwrap();
+        assert_eq!(roas.total(), 1);
+        assert_eq!(roas.failures, 0);
+        let mfts = report.get(ObjectKind::Manifest).unwrap();
+        assert_eq!(mfts.objects + mfts.skipped, 2);
+        assert_eq!(report.total().failures, 0);
+    }
+
+    #[test]
+    fn regressions() {
+        let mut fast = Report::default();
+        let item = fast.get_mut(ObjectKind::Roa);
+        item.objects = 100;
+        item.elapsed = Duration::from_secs(1);
+        let mut slow = Report::default();
+        let item = slow.get_mut(ObjectKind::Roa);
+        item.objects = 50;
+        item.elapsed = Duration::from_secs(1);
+        assert!(fast.regressions(&slow, 0.1).is_empty());
+        let res = slow.regressions(&fast, 0.1);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, ObjectKind::Roa);
+        assert!(slow.regressions(&fast, 0.6).is_empty());
+    }
+}
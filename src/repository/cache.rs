@@ -0,0 +1,593 @@
+//! Caching of validation results.
+//!
+//! Between two validation runs, the vast majority of objects in the RPKI
+//! stay the same. Validating them again – checking signatures in
+//! particular – is a waste of time if the result can’t have changed. The
+//! result of validating an object only depends on the object itself, the
+//! key and resources of the CA that issued it, and the options used for
+//! validation. Together, these form a [`CacheKey`]. Since the resources
+//! are part of the key, a CA certificate re-issued with fewer resources
+//! doesn’t match the entries of objects validated under the old one.
+//!
+//! A [`ValidationCache`] maps these keys to whatever the validation code
+//! wants to remember about an object, e.g., the resources of a CA
+//! certificate or the payload of a ROA. It can be shared between any
+//! number of threads. Entries are kept for a limited time only, since the
+//! validity of an object also depends on time, and the number of entries
+//! can be limited. Once the limit is reached, the oldest entries are
+//! dropped.
+//!
+//! In order to keep the cache across runs, its entries can be taken out
+//! via [`ValidationCache::entries`] and added back via
+//! [`ValidationCache::extend`]. A [`CacheEntry`] can be serialized if the
+//! `"serde-support"` feature is enabled.
+
+use std::{cmp, mem};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use crate::clock::{Clock, SharedClock};
+use crate::crypto::digest::DigestAlgorithm;
+use crate::crypto::keys::KeyIdentifier;
+use crate::metrics::{SharedMetrics, names};
+use super::cert::ResourceCert;
+use super::options::ValidationOptions;
+use super::x509::Time;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The number of shards the cache is split into.
+///
+/// Each shard has its own lock, so with more shards there is less
+/// contention between threads.
+const SHARDS: usize = 16;
+
+
+//------------ CacheKey ------------------------------------------------------
+
+/// The key identifying a cached validation result.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheKey {
+    /// The SHA-256 digest of the encoded object.
+    object: [u8; 32],
+
+    /// The key identifier of the issuing CA’s key.
+    issuer: KeyIdentifier,
+
+    /// The SHA-256 digest of the issuing CA’s resources.
+    resources: [u8; 32],
+
+    /// The fingerprint of the validation options.
+    options: u64,
+}
+
+impl CacheKey {
+    /// Creates a key from the raw parts.
+    pub fn from_parts(
+        object: [u8; 32], issuer: KeyIdentifier, resources: [u8; 32],
+        options: u64
+    ) -> Self {
+        CacheKey { object, issuer, resources, options }
+    }
+
+    /// Creates the key for an object.
+    ///
+    /// The object is given in its encoded form. The issuer is the validated
+    /// CA certificate the object was issued by. Its subject key identifier
+    /// and its resources, with inherited resources already resolved, become
+    /// part of the key.
+    pub fn new(
+        object: &[u8], issuer: &ResourceCert, options: &ValidationOptions
+    ) -> Self {
+        Self::from_parts(
+            sha256(object), issuer.subject_key_identifier(),
+            Self::resources_digest(issuer), options.fingerprint()
+        )
+    }
+
+    /// Returns the digest of the resources of a CA certificate.
+    pub fn resources_digest(cert: &ResourceCert) -> [u8; 32] {
+        sha256(format!(
+            "{}|{}|{}",
+            cert.v4_resources().as_v4(), cert.v6_resources().as_v6(),
+            cert.as_resources()
+        ).as_bytes())
+    }
+
+    /// Returns the SHA-256 digest of the object.
+    pub fn object(&self) -> &[u8; 32] {
+        &self.object
+    }
+
+    /// Returns the key identifier of the issuer.
+    pub fn issuer(&self) -> KeyIdentifier {
+        self.issuer
+    }
+
+    /// Returns the digest of the issuer’s resources.
+    pub fn resources(&self) -> &[u8; 32] {
+        &self.resources
+    }
+
+    /// Returns the fingerprint of the validation options.
+    pub fn options(&self) -> u64 {
+        self.options
+    }
+
+    /// Returns the shard for the key.
+    fn shard(&self) -> usize {
+        usize::from(self.object[0]) % SHARDS
+    }
+}
+
+
+/// Returns the SHA-256 digest of some data.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    res.copy_from_slice(DigestAlgorithm::sha256().digest(data).as_ref());
+    res
+}
+
+
+//------------ CacheEntry ----------------------------------------------------
+
+/// An entry of a validation cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheEntry<T> {
+    /// The key of the entry.
+    pub key: CacheKey,
+
+    /// The cached value.
+    pub value: T,
+
+    /// The time the entry expires.
+    pub expires: Time,
+}
+
+
+//------------ ValidationCache -----------------------------------------------
+
+/// A thread-safe cache of validation results.
+///
+/// By default, entries are kept for one hour and there is no limit on the
+/// number of entries.
+#[derive(Debug)]
+pub struct ValidationCache<T> {
+    /// The shards of the cache.
+    shards: Vec<Mutex<Shard<T>>>,
+
+    /// How long entries are kept.
+    ttl: Duration,
+
+    /// The maximum number of entries per shard.
+    shard_limit: Option<usize>,
+
+    /// The statistics of the cache.
+    stats: Counters,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+}
+
+/// A shard of the cache.
+#[derive(Debug)]
+struct Shard<T> {
+    /// The entries and the sequence number of their insertion.
+    entries: HashMap<CacheKey, (T, Time, u64)>,
+
+    /// The keys in insertion order with their sequence number.
+    ///
+    /// This may contain keys that have since been removed or re-inserted.
+    /// These are recognized by their sequence number.
+    order: VecDeque<(CacheKey, u64)>,
+
+    /// The sequence number for the next insertion.
+    next_seq: u64,
+}
+
+impl<T> Default for Shard<T> {
+    fn default() -> Self {
+        Shard {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+/// The statistics of the cache.
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<T> Default for ValidationCache<T> {
+    fn default() -> Self {
+        ValidationCache {
+            shards: (0..SHARDS).map(|_| Default::default()).collect(),
+            ttl: Duration::from_secs(3600),
+            shard_limit: None,
+            stats: Default::default(),
+            metrics: Default::default(),
+            clock: Default::default(),
+        }
+    }
+}
+
+impl<T> ValidationCache<T> {
+    /// Creates a new, empty cache with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long entries are kept.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl
+    }
+
+    /// Sets the maximum number of entries.
+    ///
+    /// The limit is enforced separately for a number of internal parts of
+    /// the cache, so it is approximate. If `limit` is `None`, the number
+    /// of entries is not limited.
+    pub fn set_max_entries(&mut self, limit: Option<usize>) {
+        self.shard_limit = limit.map(|limit| {
+            cmp::max(1, (limit + SHARDS - 1) / SHARDS)
+        })
+    }
+
+    /// Sets the metrics the cache reports to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock is used by the methods without an explicit time. By
+    /// default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Returns the current time according to the cache’s clock.
+    pub fn now(&self) -> Time {
+        Time::new(self.clock.now())
+    }
+
+    /// Returns the number of entries in the cache.
+    ///
+    /// This includes expired entries that haven’t been removed yet.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| {
+            shard.lock().expect("poisoned lock").entries.len()
+        }).sum()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the current statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            evictions: self.stats.evictions.load(Ordering::Relaxed),
+            entries: self.len(),
+        }
+    }
+
+    /// Adds a value valid for the configured time from now.
+    pub fn insert(&self, key: CacheKey, value: T) {
+        self.insert_at(key, value, self.now())
+    }
+
+    /// Adds a value valid for the configured time from the given time.
+    pub fn insert_at(&self, key: CacheKey, value: T, now: Time) {
+        let expires = chrono::Duration::from_std(self.ttl).ok().and_then(
+            |ttl| now.checked_add_signed(ttl)
+        ).map(Time::new).unwrap_or_else(|| {
+            Time::utc(9999, 12, 31, 23, 59, 59)
+        });
+        self.insert_until(key, value, expires)
+    }
+
+    /// Adds a value valid until the given time.
+    ///
+    /// This can be used if the validation result itself expires earlier
+    /// than the configured time, e.g., because the object does.
+    pub fn insert_until(&self, key: CacheKey, value: T, expires: Time) {
+        let mut shard = self.shards[key.shard()].lock().expect(
+            "poisoned lock"
+        );
+        let seq = shard.next_seq;
+        shard.next_seq += 1;
+        shard.entries.insert(key, (value, expires, seq));
+        shard.order.push_back((key, seq));
+        if let Some(limit) = self.shard_limit {
+            let mut evicted = 0;
+            while shard.entries.len() > limit {
+                let (key, seq) = match shard.order.pop_front() {
+                    Some(item) => item,
+                    None => break
+                };
+                if shard.entries.get(&key).map(|item| item.2) == Some(seq) {
+                    shard.entries.remove(&key);
+                    evicted += 1;
+                }
+            }
+            if evicted > 0 {
+                self.stats.evictions.fetch_add(evicted, Ordering::Relaxed);
+                self.metrics.counter(
+                    names::VALIDATION_CACHE_EVICTIONS, &[], evicted
+                );
+            }
+        }
+        // Don’t let the order queue grow without bounds if the same keys
+        // are inserted over and over again.
+        if shard.order.len() > 2 * shard.entries.len() + 16 {
+            let Shard { ref entries, ref mut order, .. } = *shard;
+            order.retain(|(key, seq)| {
+                entries.get(key).map(|item| item.2) == Some(*seq)
+            });
+        }
+    }
+
+    /// Removes the value for the given key.
+    pub fn remove(&self, key: &CacheKey) -> Option<T> {
+        self.shards[key.shard()].lock().expect(
+            "poisoned lock"
+        ).entries.remove(key).map(|item| item.0)
+    }
+
+    /// Removes all entries that have expired at the given time.
+    pub fn purge(&self, now: Time) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().expect("poisoned lock");
+            shard.entries.retain(|_, item| item.1 > now);
+            let Shard { ref entries, ref mut order, .. } = *shard;
+            order.retain(|(key, seq)| {
+                entries.get(key).map(|item| item.2) == Some(*seq)
+            });
+        }
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            *shard.lock().expect("poisoned lock") = Default::default();
+        }
+    }
+
+    /// Records the outcome of a lookup.
+    fn record(&self, hit: bool) {
+        let (counter, result) = if hit {
+            (&self.stats.hits, "hit")
+        }
+        else {
+            (&self.stats.misses, "miss")
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.metrics.counter(
+            names::VALIDATION_CACHE_LOOKUPS, &[("result", result)], 1
+        );
+    }
+}
+
+impl<T: Clone> ValidationCache<T> {
+    /// Returns the value for the given key if it hasn’t expired yet.
+    pub fn get(&self, key: &CacheKey) -> Option<T> {
+        self.get_at(key, self.now())
+    }
+
+    /// Returns the value for the given key if it is valid at `now`.
+    ///
+    /// An expired entry is removed and counted as a miss.
+    pub fn get_at(&self, key: &CacheKey, now: Time) -> Option<T> {
+        let res = {
+            let mut shard = self.shards[key.shard()].lock().expect(
+                "poisoned lock"
+            );
+            match shard.entries.get(key) {
+                Some((value, expires, _)) if *expires > now => {
+                    Some(value.clone())
+                }
+                Some(_) => {
+                    shard.entries.remove(key);
+                    None
+                }
+                None => None
+            }
+        };
+        self.record(res.is_some());
+        res
+    }
+
+    /// Returns all entries that are still valid at the given time.
+    ///
+    /// The entries are returned in no particular order.
+    pub fn entries(&self, now: Time) -> Vec<CacheEntry<T>> {
+        let mut res = Vec::new();
+        for shard in &self.shards {
+            let shard = shard.lock().expect("poisoned lock");
+            res.extend(shard.order.iter().filter_map(|(key, seq)| {
+                let (value, expires, item_seq) = shard.entries.get(key)?;
+                (item_seq == seq && *expires > now).then(|| CacheEntry {
+                    key: *key, value: value.clone(), expires: *expires
+                })
+            }));
+        }
+        res
+    }
+}
+
+impl<T> ValidationCache<T> {
+    /// Adds entries, e.g., those taken from the cache of an earlier run.
+    ///
+    /// The entries keep their expiry time.
+    pub fn extend(&self, entries: impl IntoIterator<Item = CacheEntry<T>>) {
+        for entry in entries {
+            self.insert_until(entry.key, entry.value, entry.expires)
+        }
+    }
+
+    /// Takes all entries out of the cache, leaving it empty.
+    pub fn take_entries(&self) -> Vec<CacheEntry<T>> {
+        let mut res = Vec::new();
+        for shard in &self.shards {
+            let mut shard = mem::take(
+                &mut *shard.lock().expect("poisoned lock")
+            );
+            for (key, seq) in shard.order.drain(..) {
+                if shard.entries.get(&key).map(|item| item.2) == Some(seq) {
+                    if let Some((value, expires, _)) = shard.entries.remove(
+                        &key
+                    ) {
+                        res.push(CacheEntry { key, value, expires })
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+
+//------------ CacheStats ----------------------------------------------------
+
+/// Statistics of a validation cache.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of successful lookups.
+    pub hits: u64,
+
+    /// The number of lookups that didn’t find a valid entry.
+    pub misses: u64,
+
+    /// The number of entries dropped because the cache was full.
+    pub evictions: u64,
+
+    /// The current number of entries.
+    pub entries: usize,
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use chrono::{TimeZone, Utc};
+    use super::*;
+
+    fn key_with(
+        object: &[u8], issuer: u8, resources: u8,
+        options: &ValidationOptions
+    ) -> CacheKey {
+        CacheKey::from_parts(
+            sha256(object), KeyIdentifier::from([issuer; 20]),
+            [resources; 32], options.fingerprint()
+        )
+    }
+
+    fn key(object: &[u8]) -> CacheKey {
+        key_with(object, 1, 1, &ValidationOptions::default())
+    }
+
+    #[test]
+    fn keys() {
+        let strict = ValidationOptions {
+            strict: true, .. Default::default()
+        };
+        let default = ValidationOptions::default();
+        assert_eq!(key(b"one"), key(b"one"));
+        assert_ne!(key(b"one"), key(b"two"));
+        assert_ne!(key(b"one"), key_with(b"one", 1, 1, &strict));
+        assert_ne!(key(b"one"), key_with(b"one", 2, 1, &default));
+        assert_ne!(key(b"one"), key_with(b"one", 1, 2, &default));
+    }
+
+    #[test]
+    fn get_and_expire() {
+        let now = Time::utc(2024, 1, 1, 0, 0, 0);
+        let mut cache = ValidationCache::new();
+        cache.set_ttl(Duration::from_secs(60));
+        cache.insert_at(key(b"one"), 1, now);
+        assert_eq!(cache.get_at(&key(b"one"), now), Some(1));
+        assert_eq!(cache.get_at(&key(b"two"), now), None);
+        assert_eq!(
+            cache.get_at(&key(b"one"), now + chrono::Duration::seconds(61)),
+            None
+        );
+        assert!(cache.is_empty());
+        assert_eq!(
+            cache.stats(),
+            CacheStats { hits: 1, misses: 2, evictions: 0, entries: 0 }
+        );
+    }
+
+    #[test]
+    fn clock() {
+        use crate::clock::ManualClock;
+
+        let clock = ManualClock::new(Utc.with_ymd_and_hms(
+            2024, 1, 1, 0, 0, 0
+        ).unwrap());
+        let mut cache = ValidationCache::new();
+        cache.set_ttl(Duration::from_secs(60));
+        cache.set_clock(SharedClock::new(clock.clone()));
+        cache.insert(key(b"one"), 1);
+        assert_eq!(cache.get(&key(b"one")), Some(1));
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(cache.get(&key(b"one")), None);
+    }
+
+    #[test]
+    fn limit() {
+        let mut cache = ValidationCache::new();
+        cache.set_max_entries(Some(SHARDS));
+        for i in 0..1000u32 {
+            cache.insert(key(&i.to_be_bytes()), i);
+        }
+        assert!(cache.len() <= SHARDS);
+        assert_eq!(cache.stats().evictions, 1000 - cache.len() as u64);
+
+        // The newest entries are kept.
+        assert_eq!(cache.get(&key(&999u32.to_be_bytes())), Some(999));
+    }
+
+    #[test]
+    fn shared_and_persisted() {
+        let cache = Arc::new(ValidationCache::new());
+        let threads: Vec<_> = (0..4u32).map(|t| {
+            let cache = cache.clone();
+            std::thread::spawn(move || {
+                for i in 0..100u32 {
+                    cache.insert(key(&(t * 1000 + i).to_be_bytes()), i);
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(cache.len(), 400);
+
+        let entries = cache.entries(Time::now());
+        assert_eq!(entries.len(), 400);
+        let restored = ValidationCache::new();
+        restored.extend(cache.take_entries());
+        assert!(cache.is_empty());
+        assert_eq!(restored.len(), 400);
+        assert_eq!(restored.get(&key(&2005u32.to_be_bytes())), Some(5));
+    }
+}
@@ -29,8 +29,8 @@ use bcder::encode::{PrimitiveContent, Values};
 use bytes::Bytes;
 use crate::{oid, uri};
 use crate::crypto::{
-    KeyIdentifier, PublicKey, RpkiSignatureAlgorithm, SignatureAlgorithm,
-    SignatureVerificationError, Signer, SigningError,
+    AlgorithmSuite, KeyIdentifier, PublicKey, RpkiSignatureAlgorithm,
+    SignatureAlgorithm, SignatureVerificationError, Signer, SigningError,
 };
 use crate::util::base64;
 use super::error::{InspectionError, ValidationError, VerificationError};
@@ -40,7 +40,8 @@ use super::resources::{
 };
 use super::tal::TalInfo;
 use super::x509::{
-    Name, SignedData, Serial, Time, Validity, encode_extension, update_first,
+    Encoded, Name, SignedData, Serial, Time, Validity, encode_extension,
+    update_first,
 };
 
 
@@ -88,6 +89,9 @@ pub struct Cert {
 
     /// The actual data of the certificate.
     tbs: TbsCert,
+
+    /// The complete encoding of the certificate.
+    encoded: Encoded,
 }
 
 
@@ -108,35 +112,180 @@ impl Cert {
     pub fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
-        cons.take_sequence(Self::from_constructed)
+        let ((signed_data, tbs), encoded) = Encoded::take_from(cons, |cons| {
+            cons.take_sequence(Self::parse_content)
+        })?;
+        Ok(Self { signed_data, tbs, encoded })
     }
 
     /// Takes an optional certificate from the beginning of a value.
     pub fn take_opt_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Option<Self>, DecodeError<S::Error>> {
-        cons.take_opt_sequence(Self::from_constructed)
+        let (res, encoded) = Encoded::take_from(cons, |cons| {
+            cons.take_opt_sequence(Self::parse_content)
+        })?;
+        Ok(res.map(|(signed_data, tbs)| Self { signed_data, tbs, encoded }))
     }
 
     /// Parses the content of a Certificate sequence.
+    ///
+    /// Since the sequence’s tag and length are not available, the
+    /// encoding of the certificate kept for [`as_bytes`][Self::as_bytes]
+    /// uses a DER-encoded tag and length with the original content.
+    #[allow(clippy::redundant_closure)]
     pub fn from_constructed<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
+        let ((signed_data, tbs), content) = Encoded::take_from(
+            cons, |cons| Self::parse_content(cons)
+        )?;
+        Ok(Self {
+            signed_data, tbs,
+            encoded: Encoded::from_values(encode::sequence(content)),
+        })
+    }
+
+    /// Parses the content of a Certificate sequence into its parts.
+    fn parse_content<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<(SignedData, TbsCert), DecodeError<S::Error>> {
         let signed_data = SignedData::from_constructed(cons)?;
         let tbs = signed_data.data().clone().decode(
             TbsCert::from_constructed
         ).map_err(DecodeError::convert)?;
-        Ok(Self { signed_data, tbs })
+        Ok((signed_data, tbs))
     }
 
     /// Returns a value encoder for a reference to the certificate.
+    ///
+    /// The encoder produces exactly the octets the certificate was decoded
+    /// from.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
-        self.signed_data.encode_ref()
+        &self.encoded
+    }
+
+    /// Returns the encoded octets of the certificate.
+    ///
+    /// If the certificate was decoded, these are exactly the octets it
+    /// was decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.encoded.as_slice()
     }
 
     /// Returns a captured encoding of the certificate.
     pub fn to_captured(&self) -> Captured {
-        Captured::from_values(Mode::Der, self.encode_ref())
+        self.encoded.to_captured()
+    }
+}
+
+
+/// # Extensions
+///
+impl Cert {
+    /// Returns all extensions of the certificate.
+    ///
+    /// This includes the extensions that are also available in decoded form
+    /// through the methods of [`TbsCert`] as well as any extension not
+    /// defined for resource certificates. The extensions are returned in
+    /// the order they appear in the certificate.
+    pub fn extensions(&self) -> Vec<Extension> {
+        // The data has been decoded successfully before, so this can only
+        // fail if the certificate was created from a broken `TbsCert`.
+        self.signed_data.data().clone().decode(
+            Extension::take_all_from_tbs
+        ).unwrap_or_default()
+    }
+
+    /// Returns an iterator over all extensions not known to this crate.
+    ///
+    /// Critical unknown extensions are rejected when decoding a
+    /// certificate, so all these extensions are non-critical.
+    pub fn unknown_extensions(&self) -> impl Iterator<Item = Extension> {
+        self.extensions().into_iter().filter(|ext| !ext.is_known())
+    }
+
+    /// Inspects the certificate for unknown extensions.
+    ///
+    /// RFC 5280 and RFC 6487 allow ignoring unknown non-critical
+    /// extensions. With [`UnknownExtensions::Reject`], this method fails
+    /// if there are any, which can be useful to spot non-conforming
+    /// certificates.
+    pub fn inspect_unknown_extensions(
+        &self, policy: UnknownExtensions,
+    ) -> Result<(), InspectionError> {
+        if policy == UnknownExtensions::Tolerate {
+            return Ok(())
+        }
+        match self.unknown_extensions().next() {
+            Some(ext) => {
+                Err(InspectionError::new(InvalidExtension::new(ext.oid)))
+            }
+            None => Ok(())
+        }
+    }
+}
+
+
+/// # BGPsec Router Keys
+///
+#[cfg(feature = "rtr")]
+impl Cert {
+    /// Returns an iterator over the router keys of a router certificate.
+    ///
+    /// The iterator produces one router key for each AS number in the AS
+    /// resources of the certificate, each with the certificate’s subject
+    /// key identifier and public key. This is what RFC 8210 expects to be
+    /// sent to routers for a BGPsec router certificate.
+    ///
+    /// The method does not check that the certificate is a router
+    /// certificate or that it is valid. Use
+    /// [`validate_router_keys`][Self::validate_router_keys] for that.
+    /// If the AS resources are missing or inherited, the iterator is empty.
+    pub fn iter_router_keys(
+        &self
+    ) -> impl Iterator<Item = crate::rtr::payload::RouterKey> + '_ {
+        use crate::resources::asn::Asn;
+        use crate::rtr::payload::RouterKey;
+        use crate::rtr::pdu::RouterKeyInfo;
+
+        let key_id = self.subject_key_identifier();
+        let key_info = RouterKeyInfo::new(
+            self.subject_public_key_info().to_info_bytes()
+        ).ok();
+        let blocks: Vec<_> = match self.as_resources().to_blocks() {
+            Ok(blocks) if key_info.is_some() => blocks.iter().collect(),
+            _ => Vec::new()
+        };
+        blocks.into_iter().flat_map(|block| {
+            (block.min().into_u32()..=block.max().into_u32()).map(Asn::from)
+        }).filter_map(move |asn| {
+            key_info.clone().map(|info| RouterKey::new(key_id, asn, info))
+        })
+    }
+
+    /// Validates a router certificate and returns its router keys.
+    pub fn validate_router_keys(
+        &self,
+        issuer: &ResourceCert,
+        strict: bool,
+    ) -> Result<Vec<crate::rtr::payload::RouterKey>, ValidationError> {
+        self.validate_router_keys_at(issuer, strict, Time::now())
+    }
+
+    /// Validates a router certificate at a given time and returns its keys.
+    ///
+    /// This is identical to
+    /// [`validate_router_keys`][Self::validate_router_keys] with an
+    /// explicitly given value for the current time.
+    pub fn validate_router_keys_at(
+        &self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+    ) -> Result<Vec<crate::rtr::payload::RouterKey>, ValidationError> {
+        self.validate_router_at(issuer, strict, now)?;
+        Ok(self.iter_router_keys().collect())
     }
 }
 
@@ -1238,8 +1387,10 @@ impl TbsCert {
     ) -> Result<Cert, SigningError<S::Error>> {
         let data = Captured::from_values(Mode::Der, self.encode_ref());
         let signature = signer.sign(key, self.signature, &data)?;
+        let signed_data = SignedData::new(data, signature);
         Ok(Cert {
-            signed_data: SignedData::new(data, signature),
+            encoded: Encoded::from_values(signed_data.encode_ref()),
+            signed_data,
             tbs: self
         })
     }
@@ -1259,6 +1410,23 @@ impl TbsCert {
         self.serial_number = serial.into()
     }
 
+    /// Returns the signature algorithm used for signing the certificate.
+    pub fn signature(&self) -> RpkiSignatureAlgorithm {
+        self.signature
+    }
+
+    /// Sets the signature algorithm for signing the certificate.
+    ///
+    /// By default, the signature algorithm of the RFC 7935 suite is used.
+    pub fn set_signature(&mut self, signature: RpkiSignatureAlgorithm) {
+        self.signature = signature
+    }
+
+    /// Sets the algorithms for signing the certificate from a suite.
+    pub fn set_algorithm_suite(&mut self, suite: AlgorithmSuite) {
+        self.signature = suite.signature_algorithm()
+    }
+
     /// Returns a reference to the issuer.
     pub fn issuer(&self) -> &Name {
         &self.issuer
@@ -1657,10 +1825,8 @@ impl TbsCert {
                     "wrong AS resources extension for certificate policy"
                 ))
             }
-            let (v4_resources, v6_resources) = match ip_resources {
-                Some(res) => res,
-                None => (None, None)
-            };
+            let (v4_resources, v6_resources) =
+                ip_resources.unwrap_or_default();
             let (ca_repository, rpki_manifest, signed_object, rpki_notify) = {
                 match sia {
                     Some(sia) => (
@@ -2535,6 +2701,113 @@ impl ExtendedKeyUsage {
 }
 
 
+//------------ Extension -----------------------------------------------------
+
+/// A raw certificate extension.
+///
+/// This type is used to provide access to all extensions of a certificate,
+/// including those not defined for resource certificates. See
+/// [`Cert::extensions`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Extension {
+    /// The object identifier of the extension.
+    oid: Oid<Bytes>,
+
+    /// Whether the extension is marked as critical.
+    critical: bool,
+
+    /// The DER-encoded value of the extension.
+    value: Bytes,
+}
+
+impl Extension {
+    /// Returns the object identifier of the extension.
+    pub fn oid(&self) -> &Oid<Bytes> {
+        &self.oid
+    }
+
+    /// Returns whether the extension is marked as critical.
+    pub fn is_critical(&self) -> bool {
+        self.critical
+    }
+
+    /// Returns the DER-encoded value of the extension.
+    ///
+    /// This is the content of the `extnValue` octet string.
+    pub fn value(&self) -> &Bytes {
+        &self.value
+    }
+
+    /// Returns whether the extension is defined for resource certificates.
+    pub fn is_known(&self) -> bool {
+        let id = &self.oid;
+        *id == oid::CE_BASIC_CONSTRAINTS
+            || *id == oid::CE_SUBJECT_KEY_IDENTIFIER
+            || *id == oid::CE_AUTHORITY_KEY_IDENTIFIER
+            || *id == oid::CE_KEY_USAGE
+            || *id == oid::CE_EXTENDED_KEY_USAGE
+            || *id == oid::CE_CRL_DISTRIBUTION_POINTS
+            || *id == oid::PE_AUTHORITY_INFO_ACCESS
+            || *id == oid::PE_SUBJECT_INFO_ACCESS
+            || *id == oid::CE_CERTIFICATE_POLICIES
+            || Overclaim::from_ip_res(id).is_some()
+            || Overclaim::from_as_res(id).is_some()
+    }
+
+    /// Takes all extensions from the content of a TBSCertificate sequence.
+    fn take_all_from_tbs<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Vec<Self>, DecodeError<S::Error>> {
+        cons.take_sequence(|cons| {
+            cons.take_opt_constructed_if(Tag::CTX_0, |c| c.skip_all())?;
+
+            // serialNumber, signature, issuer, validity, subject,
+            // subjectPublicKeyInfo.
+            for _ in 0..6 {
+                cons.skip_one()?;
+            }
+
+            // issuerUniqueID and subjectUniqueID.
+            cons.take_opt_constructed_if(Tag::CTX_1, |c| c.skip_all())?;
+            cons.take_opt_constructed_if(Tag::CTX_2, |c| c.skip_all())?;
+
+            let mut res = Vec::new();
+            cons.take_opt_constructed_if(Tag::CTX_3, |c| {
+                c.take_sequence(|cons| {
+                    while let Some(()) = cons.take_opt_sequence(|cons| {
+                        let oid = Oid::take_from(cons)?;
+                        let critical = cons.take_opt_bool()?.unwrap_or(false);
+                        let value = OctetString::take_from(cons)?;
+                        res.push(Extension {
+                            oid, critical, value: value.into_bytes()
+                        });
+                        Ok(())
+                    })? { }
+                    Ok(())
+                })
+            })?;
+            cons.skip_all()?;
+            Ok(res)
+        })
+    }
+}
+
+
+//------------ UnknownExtensions ---------------------------------------------
+
+/// How to treat unknown non-critical extensions during inspection.
+///
+/// See [`Cert::inspect_unknown_extensions`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum UnknownExtensions {
+    /// Ignore unknown non-critical extensions as RFC 5280 allows.
+    #[default]
+    Tolerate,
+
+    /// Reject certificates with unknown non-critical extensions.
+    Reject,
+}
+
 //------------ Overclaim -----------------------------------------------------
 
 /// The overclaim mode for resource validation.
@@ -2634,7 +2907,6 @@ pub(crate) struct InvalidExtension {
 }
 
 impl InvalidExtension {
-    #[cfg(feature = "ca")]
     pub(crate) fn new(oid: Oid<Bytes>) -> Self {
         InvalidExtension { oid }
     }
@@ -2737,6 +3009,74 @@ mod test {
         ).unwrap().inspect_router(true).unwrap();
     }
 
+    #[test]
+    fn round_trip() {
+        for der in [
+            include_bytes!("../../test-data/repository/ta.cer").as_ref(),
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref(),
+            include_bytes!("../../test-data/repository/router.cer").as_ref(),
+        ] {
+            let cert = Cert::decode(der).unwrap();
+            assert_eq!(cert.as_bytes(), der);
+            assert_eq!(cert.to_captured().as_slice(), der);
+
+            let cert = Mode::Der.decode(der, |cons| {
+                cons.take_sequence(Cert::from_constructed)
+            }).unwrap();
+            assert_eq!(cert.as_bytes(), der);
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        let cert = Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap();
+        let extensions = cert.extensions();
+        assert!(!extensions.is_empty());
+        assert!(extensions.iter().all(Extension::is_known));
+        assert!(extensions.iter().any(|ext| {
+            *ext.oid() == oid::CE_BASIC_CONSTRAINTS && ext.is_critical()
+        }));
+        assert!(extensions.iter().any(|ext| {
+            *ext.oid() == oid::CE_SUBJECT_KEY_IDENTIFIER
+                && ext.value().ends_with(
+                    cert.subject_key_identifier().as_slice()
+                )
+        }));
+        assert!(cert.unknown_extensions().next().is_none());
+        cert.inspect_unknown_extensions(
+            UnknownExtensions::Reject
+        ).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "rtr")]
+    fn router_keys() {
+        use crate::resources::asn::Asn;
+
+        let cert = Cert::decode(
+            include_bytes!("../../test-data/repository/router.cer").as_ref()
+        ).unwrap();
+        let keys: Vec<_> = cert.iter_router_keys().collect();
+
+        // The certificate has AS3000-AS9001 and AS199664.
+        assert_eq!(keys.len(), 6003);
+        assert_eq!(keys[0].asn, Asn::from(3000));
+        assert_eq!(keys[6001].asn, Asn::from(9001));
+        assert_eq!(keys[6002].asn, Asn::from(199664));
+        assert!(keys.iter().all(|key| {
+            key.key_identifier == cert.subject_key_identifier()
+            && key.key_info.as_slice()
+                == cert.subject_public_key_info().to_info_bytes().as_ref()
+        }));
+
+        let pdu = keys[0].to_pdu(1, 1);
+        assert_eq!(pdu.asn(), Asn::from(3000));
+        assert_eq!(pdu.key_identifier(), *keys[0].key_identifier.as_ref());
+        assert_eq!(pdu.key_info(), &keys[0].key_info);
+    }
+
     /// Tests that inconsistent algorithm encoding fails validation.
     ///
     /// Specifically, tests that a certificate with different encoding of
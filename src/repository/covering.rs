@@ -0,0 +1,676 @@
+//! Finding the objects that cover a resource.
+//!
+//! When a route turns out to be RPKI invalid, the first question usually
+//! is which objects are responsible. The [`CoverageIndex`] collects the
+//! validated CA certificates, ROAs, and ASPAs encountered during a
+//! validation run together with their URIs and answers this question for
+//! a prefix or an ASN via [`CoverageIndex::find_covering`]. The result, a
+//! [`Covering`], contains the chains of certificates holding the resource
+//! as well as the ROAs and ASPAs relevant for it.
+//!
+//! The validation engine collects the index into its report where it is
+//! available via [`ValidationReport::find_covering`][report].
+//!
+//! The module is only available if both the `"repository"` and `"rtr"`
+//! features are enabled since it is part of the validation report.
+//!
+//! [report]: super::report::ValidationReport::find_covering
+
+#![cfg(feature = "rtr")]
+
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use crate::uri;
+use crate::crypto::KeyIdentifier;
+use crate::resources::addr::{MaxLenPrefix, Prefix};
+use super::aspa::AsProviderAttestation;
+use super::cert::ResourceCert;
+use super::resources::{AsBlocks, Asn, IpBlocks};
+use super::roa::RouteOriginAttestation;
+
+
+//------------ Resource ------------------------------------------------------
+
+/// A resource to look for in a [`CoverageIndex`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Resource {
+    /// An IPv4 or IPv6 prefix.
+    Prefix(Prefix),
+
+    /// An AS number.
+    Asn(Asn),
+}
+
+impl From<Prefix> for Resource {
+    fn from(prefix: Prefix) -> Self {
+        Resource::Prefix(prefix)
+    }
+}
+
+impl From<Asn> for Resource {
+    fn from(asn: Asn) -> Self {
+        Resource::Asn(asn)
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Resource::Prefix(prefix) => prefix.fmt(f),
+            Resource::Asn(asn) => asn.fmt(f),
+        }
+    }
+}
+
+
+//------------ CoverageIndex -------------------------------------------------
+
+/// The validated certificates and objects of a validation run.
+///
+/// CA certificates are added via [`add_ca`][Self::add_ca] once they have
+/// been validated, ROAs and ASPAs via [`add_roa`][Self::add_roa] and
+/// [`add_aspa`][Self::add_aspa] for the CA that issued them. As with the
+/// [`ContactIndex`][super::gbr::ContactIndex], CAs are identified by
+/// their key and their parents by the authority key identifier of their
+/// certificate. Likewise, ROAs and ASPAs only remember the key of their
+/// issuing CA, so certificates and objects can be added in any order.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageIndex {
+    /// The CA certificates, keyed by their subject key identifier.
+    cas: HashMap<KeyIdentifier, CoveringCert>,
+
+    /// The ROAs.
+    roas: Vec<CoveringRoa>,
+
+    /// The ASPAs.
+    aspas: Vec<CoveringAspa>,
+}
+
+impl CoverageIndex {
+    /// The maximum length of a delegation chain to follow.
+    const MAX_DEPTH: usize = 64;
+
+    /// Creates a new, empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of CA certificates in the index.
+    pub fn ca_count(&self) -> usize {
+        self.cas.len()
+    }
+
+    /// Returns the number of ROAs in the index.
+    pub fn roa_count(&self) -> usize {
+        self.roas.len()
+    }
+
+    /// Returns the number of ASPAs in the index.
+    pub fn aspa_count(&self) -> usize {
+        self.aspas.len()
+    }
+
+    /// Adds a validated CA certificate published at `uri`.
+    pub fn add_ca(&mut self, uri: uri::Rsync, cert: &ResourceCert) {
+        let key = cert.subject_key_identifier();
+        self.cas.insert(key, CoveringCert {
+            uri,
+            key,
+            parent: cert.authority_key_identifier().filter(|parent| {
+                *parent != key
+            }),
+            v4: cert.v4_resources().clone(),
+            v6: cert.v6_resources().clone(),
+            asns: cert.as_resources().clone(),
+            ca_repository: cert.ca_repository().cloned(),
+            rpki_manifest: cert.rpki_manifest().cloned(),
+            rpki_notify: cert.rpki_notify().cloned(),
+        });
+    }
+
+    /// Adds a validated ROA published at `uri` and issued by `ca`.
+    pub fn add_roa(
+        &mut self,
+        uri: uri::Rsync,
+        ca: &ResourceCert,
+        roa: &RouteOriginAttestation,
+    ) {
+        self.roas.push(CoveringRoa {
+            uri,
+            issuer: ca.subject_key_identifier(),
+            asn: roa.as_id(),
+            prefixes: roa.iter_origins().map(|origin| {
+                origin.prefix
+            }).collect(),
+        })
+    }
+
+    /// Adds a validated ASPA published at `uri` and issued by `ca`.
+    pub fn add_aspa(
+        &mut self,
+        uri: uri::Rsync,
+        ca: &ResourceCert,
+        aspa: &AsProviderAttestation,
+    ) {
+        self.aspas.push(CoveringAspa {
+            uri,
+            issuer: ca.subject_key_identifier(),
+            customer: aspa.customer_as(),
+            providers: aspa.provider_as_set().iter().collect(),
+        })
+    }
+
+    /// Returns an iterator over the CA certificates in the index.
+    pub fn certs(&self) -> impl Iterator<Item = &CoveringCert> + '_ {
+        self.cas.values()
+    }
+
+    /// Returns the CA certificate with the given key identifier.
+    ///
+    /// This can be used to find the issuing CA of a ROA or ASPA from
+    /// their [`issuer`][CoveringRoa::issuer]. Returns `None` if the CA
+    /// hasn’t been added to the index.
+    pub fn cert(&self, key: &KeyIdentifier) -> Option<&CoveringCert> {
+        self.cas.get(key)
+    }
+
+    /// Returns an iterator over the ROAs in the index.
+    pub fn roas(&self) -> impl Iterator<Item = &CoveringRoa> + '_ {
+        self.roas.iter()
+    }
+
+    /// Returns an iterator over the ASPAs in the index.
+    pub fn aspas(&self) -> impl Iterator<Item = &CoveringAspa> + '_ {
+        self.aspas.iter()
+    }
+
+    /// Returns the objects covering a resource.
+    ///
+    /// For a prefix, the result contains the chains of certificates that
+    /// hold the entire prefix and all ROAs with a prefix covering it. For
+    /// an ASN, it contains the chains of certificates holding the ASN,
+    /// all ROAs with the ASN as origin, and the ASPAs with the ASN as
+    /// their customer.
+    pub fn find_covering(
+        &self, resource: impl Into<Resource>
+    ) -> Covering<'_> {
+        let resource = resource.into();
+        let mut res = Covering {
+            resource,
+            chains: self.chains(resource),
+            roas: Vec::new(),
+            aspas: Vec::new(),
+        };
+        match resource {
+            Resource::Prefix(prefix) => {
+                res.roas.extend(self.roas.iter().filter(|roa| {
+                    roa.prefixes.iter().any(|item| {
+                        item.prefix().covers(prefix)
+                    })
+                }));
+            }
+            Resource::Asn(asn) => {
+                res.roas.extend(self.roas.iter().filter(|roa| {
+                    roa.asn == asn
+                }));
+                res.aspas.extend(self.aspas.iter().filter(|aspa| {
+                    aspa.customer == asn
+                }));
+            }
+        }
+        res.roas.sort_by(|left, right| left.uri.cmp(&right.uri));
+        res.aspas.sort_by(|left, right| left.uri.cmp(&right.uri));
+        res
+    }
+
+    /// Returns the certificate chains holding a resource.
+    ///
+    /// There is one chain for each certificate holding the resource that
+    /// doesn’t have a child also holding it.
+    fn chains(&self, resource: Resource) -> Vec<Vec<&CoveringCert>> {
+        let holders: Vec<_> = self.cas.iter().filter(|(_, cert)| {
+            cert.holds(resource)
+        }).collect();
+        let parents: HashSet<_> = holders.iter().filter_map(|(_, cert)| {
+            cert.parent
+        }).collect();
+        let mut res: Vec<_> = holders.iter().filter(|(key, _)| {
+            !parents.contains(*key)
+        }).map(|(_, cert)| {
+            let mut chain = vec![*cert];
+            let mut cert = *cert;
+            while let Some(parent) = cert.parent.as_ref().and_then(|key| {
+                self.cas.get(key)
+            }) {
+                if chain.len() >= Self::MAX_DEPTH {
+                    break
+                }
+                chain.push(parent);
+                cert = parent;
+            }
+            chain.reverse();
+            chain
+        }).collect();
+        res.sort_by(|left, right| {
+            left.last().map(|cert| &cert.uri).cmp(
+                &right.last().map(|cert| &cert.uri)
+            )
+        });
+        res
+    }
+}
+
+
+//------------ Covering ------------------------------------------------------
+
+/// The objects covering a resource.
+///
+/// This is returned by [`CoverageIndex::find_covering`].
+#[derive(Clone, Debug)]
+pub struct Covering<'a> {
+    /// The resource in question.
+    resource: Resource,
+
+    /// The certificate chains holding the resource.
+    chains: Vec<Vec<&'a CoveringCert>>,
+
+    /// The ROAs relevant for the resource.
+    roas: Vec<&'a CoveringRoa>,
+
+    /// The ASPAs relevant for the resource.
+    aspas: Vec<&'a CoveringAspa>,
+}
+
+impl<'a> Covering<'a> {
+    /// Returns the resource in question.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the certificate chains holding the resource.
+    ///
+    /// Each chain starts with the trust anchor certificate and ends with
+    /// the most specific certificate holding the resource. There can be
+    /// more than one chain if the resource has been delegated to several
+    /// CAs or is held by several trust anchors.
+    pub fn chains(&self) -> &[Vec<&'a CoveringCert>] {
+        &self.chains
+    }
+
+    /// Returns the ROAs relevant for the resource.
+    pub fn roas(&self) -> &[&'a CoveringRoa] {
+        &self.roas
+    }
+
+    /// Returns the ASPAs relevant for the resource.
+    pub fn aspas(&self) -> &[&'a CoveringAspa] {
+        &self.aspas
+    }
+
+    /// Returns whether nothing covers the resource.
+    pub fn is_empty(&self) -> bool {
+        self.chains.is_empty() && self.roas.is_empty()
+            && self.aspas.is_empty()
+    }
+}
+
+
+//------------ CoveringCert --------------------------------------------------
+
+/// A CA certificate in a [`CoverageIndex`].
+#[derive(Clone, Debug)]
+pub struct CoveringCert {
+    /// The URI of the certificate.
+    uri: uri::Rsync,
+
+    /// The key identifier of the certificate’s subject.
+    key: KeyIdentifier,
+
+    /// The key identifier of the issuing CA.
+    parent: Option<KeyIdentifier>,
+
+    /// The IPv4 resources of the certificate.
+    v4: IpBlocks,
+
+    /// The IPv6 resources of the certificate.
+    v6: IpBlocks,
+
+    /// The AS resources of the certificate.
+    asns: AsBlocks,
+
+    /// The URI of the CA’s publication point.
+    ca_repository: Option<uri::Rsync>,
+
+    /// The URI of the CA’s manifest.
+    rpki_manifest: Option<uri::Rsync>,
+
+    /// The URI of the RRDP notification file of the CA’s repository.
+    rpki_notify: Option<uri::Https>,
+}
+
+impl CoveringCert {
+    /// Returns the URI of the certificate.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+
+    /// Returns the key identifier of the certificate’s subject.
+    pub fn key(&self) -> &KeyIdentifier {
+        &self.key
+    }
+
+    /// Returns the key identifier of the issuing CA.
+    ///
+    /// Returns `None` for trust anchor certificates.
+    pub fn parent(&self) -> Option<&KeyIdentifier> {
+        self.parent.as_ref()
+    }
+
+    /// Returns the URI of the CA’s publication point.
+    pub fn ca_repository(&self) -> Option<&uri::Rsync> {
+        self.ca_repository.as_ref()
+    }
+
+    /// Returns the URI of the CA’s manifest.
+    pub fn rpki_manifest(&self) -> Option<&uri::Rsync> {
+        self.rpki_manifest.as_ref()
+    }
+
+    /// Returns the URI of the RRDP notification file of the repository.
+    pub fn rpki_notify(&self) -> Option<&uri::Https> {
+        self.rpki_notify.as_ref()
+    }
+
+    /// Returns the IPv4 resources of the certificate.
+    pub fn v4_resources(&self) -> &IpBlocks {
+        &self.v4
+    }
+
+    /// Returns the IPv6 resources of the certificate.
+    pub fn v6_resources(&self) -> &IpBlocks {
+        &self.v6
+    }
+
+    /// Returns the AS resources of the certificate.
+    pub fn as_resources(&self) -> &AsBlocks {
+        &self.asns
+    }
+
+    /// Returns whether the certificate holds the entire resource.
+    pub fn holds(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Prefix(prefix) if prefix.is_v4() => {
+                self.v4.contains_block(prefix)
+            }
+            Resource::Prefix(prefix) => self.v6.contains_block(prefix),
+            Resource::Asn(asn) => self.asns.contains_asn(asn),
+        }
+    }
+}
+
+
+//------------ CoveringRoa ---------------------------------------------------
+
+/// A ROA in a [`CoverageIndex`].
+#[derive(Clone, Debug)]
+pub struct CoveringRoa {
+    /// The URI of the ROA.
+    uri: uri::Rsync,
+
+    /// The key identifier of the issuing CA.
+    issuer: KeyIdentifier,
+
+    /// The origin AS.
+    asn: Asn,
+
+    /// The prefixes of the ROA.
+    prefixes: Vec<MaxLenPrefix>,
+}
+
+impl CoveringRoa {
+    /// Returns the URI of the ROA.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+
+    /// Returns the key identifier of the issuing CA.
+    ///
+    /// The CA’s certificate can be found via [`CoverageIndex::cert`].
+    pub fn issuer(&self) -> &KeyIdentifier {
+        &self.issuer
+    }
+
+    /// Returns the origin AS of the ROA.
+    pub fn asn(&self) -> Asn {
+        self.asn
+    }
+
+    /// Returns the prefixes of the ROA.
+    pub fn prefixes(&self) -> &[MaxLenPrefix] {
+        &self.prefixes
+    }
+}
+
+
+//------------ CoveringAspa --------------------------------------------------
+
+/// An ASPA in a [`CoverageIndex`].
+#[derive(Clone, Debug)]
+pub struct CoveringAspa {
+    /// The URI of the ASPA.
+    uri: uri::Rsync,
+
+    /// The key identifier of the issuing CA.
+    issuer: KeyIdentifier,
+
+    /// The customer AS.
+    customer: Asn,
+
+    /// The provider ASs.
+    providers: Vec<Asn>,
+}
+
+impl CoveringAspa {
+    /// Returns the URI of the ASPA.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+
+    /// Returns the key identifier of the issuing CA.
+    ///
+    /// The CA’s certificate can be found via [`CoverageIndex::cert`].
+    pub fn issuer(&self) -> &KeyIdentifier {
+        &self.issuer
+    }
+
+    /// Returns the customer AS of the ASPA.
+    pub fn customer(&self) -> Asn {
+        self.customer
+    }
+
+    /// Returns the provider ASs of the ASPA.
+    pub fn providers(&self) -> &[Asn] {
+        &self.providers
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn key(id: u8) -> KeyIdentifier {
+        KeyIdentifier::from([id; 20])
+    }
+
+    fn prefix(s: &str) -> Prefix {
+        Prefix::from_str(s).unwrap()
+    }
+
+    fn cert(
+        name: &str, id: u8, parent: Option<u8>,
+        v4: &str, v6: &str, asns: &str,
+    ) -> CoveringCert {
+        CoveringCert {
+            uri: uri(&format!("rsync://example.com/repo/{}.cer", name)),
+            key: key(id),
+            parent: parent.map(key),
+            v4: IpBlocks::from_str(v4).unwrap(),
+            v6: IpBlocks::from_str(v6).unwrap(),
+            asns: AsBlocks::from_str(asns).unwrap(),
+            ca_repository: None,
+            rpki_manifest: None,
+            rpki_notify: None,
+        }
+    }
+
+    fn roa(
+        name: &str, issuer: u8, asn: u32, prefixes: &[&str]
+    ) -> CoveringRoa {
+        CoveringRoa {
+            uri: uri(&format!("rsync://example.com/repo/{}.roa", name)),
+            issuer: key(issuer),
+            asn: Asn::from_u32(asn),
+            prefixes: prefixes.iter().map(|s| {
+                MaxLenPrefix::from_str(s).unwrap()
+            }).collect(),
+        }
+    }
+
+    /// Creates an index with a TA holding everything and two CAs.
+    fn index() -> CoverageIndex {
+        let mut res = CoverageIndex::new();
+        for cert in [
+            cert("ta", 1, None, "0.0.0.0/0", "::/0", "AS0-AS4294967295"),
+            cert("a", 2, Some(1), "10.0.0.0/8", "2001:db8::/32", "AS64496"),
+            cert("b", 3, Some(2), "10.1.0.0/16", "", "AS64497"),
+        ] {
+            res.cas.insert(cert.key, cert);
+        }
+        res.roas.push(roa("a", 2, 64496, &["10.0.0.0/8-24"]));
+        res.roas.push(roa("b", 3, 64497, &["10.1.0.0/16"]));
+        res.roas.push(roa("b-v6", 3, 64497, &["2001:db8::/48"]));
+        res.aspas.push(CoveringAspa {
+            uri: uri("rsync://example.com/repo/a.asa"),
+            issuer: key(2),
+            customer: Asn::from_u32(64496),
+            providers: vec![Asn::from_u32(64500)],
+        });
+        res
+    }
+
+    fn chain_names(covering: &Covering) -> Vec<Vec<String>> {
+        covering.chains().iter().map(|chain| {
+            chain.iter().map(|cert| cert.uri().to_string()).collect()
+        }).collect()
+    }
+
+    fn roa_names(covering: &Covering) -> Vec<String> {
+        covering.roas().iter().map(|roa| roa.uri().to_string()).collect()
+    }
+
+    #[test]
+    fn find_covering_prefix() {
+        let index = index();
+
+        // A prefix held by the most specific CA.
+        let covering = index.find_covering(prefix("10.1.2.0/24"));
+        assert_eq!(
+            chain_names(&covering),
+            [[
+                "rsync://example.com/repo/ta.cer",
+                "rsync://example.com/repo/a.cer",
+                "rsync://example.com/repo/b.cer",
+            ]]
+        );
+        assert_eq!(
+            roa_names(&covering),
+            [
+                "rsync://example.com/repo/a.roa",
+                "rsync://example.com/repo/b.roa",
+            ]
+        );
+        assert!(covering.aspas().is_empty());
+
+        // A less specific prefix only covered by the middle CA.
+        let covering = index.find_covering(prefix("10.0.0.0/8"));
+        assert_eq!(chain_names(&covering)[0].len(), 2);
+        assert_eq!(roa_names(&covering), ["rsync://example.com/repo/a.roa"]);
+
+        // An unrelated prefix is only held by the TA.
+        let covering = index.find_covering(prefix("192.0.2.0/24"));
+        assert_eq!(
+            chain_names(&covering), [["rsync://example.com/repo/ta.cer"]]
+        );
+        assert!(covering.roas().is_empty());
+        assert!(!covering.is_empty());
+
+        // Without the TA, nothing covers it.
+        let mut index = index;
+        index.cas.remove(&key(1));
+        assert!(index.find_covering(prefix("192.0.2.0/24")).is_empty());
+    }
+
+    #[test]
+    fn find_covering_families() {
+        let index = index();
+
+        // The v6 prefix is held by CA a and covered by b’s v6 ROA only.
+        let covering = index.find_covering(prefix("2001:db8::/56"));
+        assert_eq!(chain_names(&covering)[0].len(), 2);
+        assert_eq!(
+            roa_names(&covering), ["rsync://example.com/repo/b-v6.roa"]
+        );
+
+        // A v6 prefix with the same bits as 10.0.0.0/8 isn’t covered by
+        // the v4 resources or ROAs.
+        let covering = index.find_covering(prefix("a00::/8"));
+        assert_eq!(
+            chain_names(&covering), [["rsync://example.com/repo/ta.cer"]]
+        );
+        assert!(covering.roas().is_empty());
+        assert!(!index.cas[&key(2)].holds(prefix("a00::/8").into()));
+        assert!(index.cas[&key(2)].holds(prefix("10.0.0.0/8").into()));
+
+        // And vice versa.
+        assert!(!index.cas[&key(2)].holds(prefix("32.1.0.0/16").into()));
+    }
+
+    #[test]
+    fn find_covering_asn() {
+        let index = index();
+        let covering = index.find_covering(Asn::from_u32(64496));
+        assert_eq!(chain_names(&covering)[0].len(), 2);
+        assert_eq!(roa_names(&covering), ["rsync://example.com/repo/a.roa"]);
+        assert_eq!(covering.aspas().len(), 1);
+        assert!(index.find_covering(Asn::from_u32(64497)).aspas().is_empty());
+    }
+
+    #[test]
+    fn issuer() {
+        let mut index = index();
+        let roa = index.roas().find(|roa| {
+            roa.uri().as_str().ends_with("/a.roa")
+        }).unwrap();
+        assert_eq!(
+            index.cert(roa.issuer()).map(CoveringCert::uri),
+            Some(&uri("rsync://example.com/repo/a.cer"))
+        );
+
+        // An issuer that wasn’t added can’t be found but its key is still
+        // known, and adding it later makes it available.
+        let missing = index.cas.remove(&key(2)).unwrap();
+        let roa = index.roas[0].clone();
+        assert_eq!(roa.issuer(), &key(2));
+        assert!(index.cert(roa.issuer()).is_none());
+        index.cas.insert(missing.key, missing);
+        assert!(index.cert(roa.issuer()).is_some());
+    }
+}
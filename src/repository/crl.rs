@@ -6,17 +6,20 @@
 //!
 //! This module implements the CRLs themselves via the type [`Crl`] as well
 //! as a [`CrlStore`] that can keep several CRLs which may be helpful during
-//! validation.
+//! validation. The type [`CrlCheck`] checks certificates against the CRL
+//! of their issuer taken from an object store.
 //!
 //! The RPKI CRL profile is defined in RFC 6487 based on the Internet RPIX
 //! profile defined in RFC 5280.
 //!
 //! [`Crl`]: struct.Crl.html
 //! [`CrlStore`]: struct.CrlStore.html
+//! [`CrlCheck`]: struct.CrlCheck.html
 
-use std::{fmt, ops};
+use std::{error, fmt, mem, ops};
 use std::collections::HashSet;
 use std::str::FromStr;
+use std::time::Duration;
 use bcder::{decode, encode};
 use bcder::{Captured, Mode, OctetString, Oid, Tag};
 use bcder::decode::{ContentError, DecodeError, IntoSource, Source};
@@ -24,13 +27,17 @@ use bcder::encode::PrimitiveContent;
 use bytes::Bytes;
 use crate::{oid, uri};
 use crate::crypto::{
-    KeyIdentifier, PublicKey, RpkiSignatureAlgorithm, SignatureAlgorithm,
-    Signer, SigningError,
+    AlgorithmSuite, KeyIdentifier, PublicKey, RpkiSignatureAlgorithm,
+    SignatureAlgorithm, Signer, SigningError,
 };
+use crate::store::{ObjectStore, StoreError};
 use crate::util::base64;
-use super::error::VerificationError;
+use super::cert::Cert;
+use super::error::{ValidationError, VerificationError};
+use super::options::{GraceWarning, ValidationOptions};
 use super::x509::{
-    Name, RepresentationError, Serial, SignedData, Time, encode_extension,
+    Encoded, Name, RepresentationError, Serial, SignedData, Time,
+    encode_extension,
 };
 
 
@@ -51,6 +58,9 @@ pub struct Crl {
 
     /// An optional cache of the serial numbers in the CRL.
     serials: Option<HashSet<Serial>>,
+
+    /// The complete encoding of the CRL.
+    encoded: Encoded,
 }
 
 /// # Data Access
@@ -103,20 +113,49 @@ impl Crl {
     pub fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
-        cons.take_sequence(Self::from_constructed)
+        let ((signed_data, tbs), encoded) = Encoded::take_from(cons, |cons| {
+            cons.take_sequence(Self::parse_content)
+        })?;
+        Ok(Self { signed_data, tbs, serials: None, encoded })
     }
 
     /// Takes an encoded CRL from the beginning of a constructed value.
     pub fn take_opt_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Option<Self>, DecodeError<S::Error>> {
-        cons.take_opt_sequence(Self::from_constructed)
+        let (res, encoded) = Encoded::take_from(cons, |cons| {
+            cons.take_opt_sequence(Self::parse_content)
+        })?;
+        Ok(res.map(|(signed_data, tbs)| {
+            Self { signed_data, tbs, serials: None, encoded }
+        }))
     }
 
     /// Parses the content of a certificate revocation list.
+    ///
+    /// Since the sequence’s tag and length are not available, the
+    /// encoding of the CRL kept for [`as_bytes`][Self::as_bytes] uses a
+    /// DER-encoded tag and length with the original content.
+    #[allow(clippy::redundant_closure)]
     pub fn from_constructed<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
+        let ((signed_data, tbs), content) = Encoded::take_from(
+            cons, |cons| Self::parse_content(cons)
+        )?;
+        Ok(Self {
+            signed_data, tbs, serials: None,
+            encoded: Encoded::from_values(encode::sequence(content)),
+        })
+    }
+
+    /// Parses the content of a certificate revocation list into its parts.
+    fn parse_content<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<
+        (SignedData, TbsCertList<RevokedCertificates>),
+        DecodeError<S::Error>
+    > {
         let signed_data = SignedData::from_constructed(cons)?;
         let tbs = signed_data.data().clone().decode(
             TbsCertList::take_from
@@ -126,7 +165,7 @@ impl Crl {
                 "CRL signature algorithm mismatch"
             ))
         }
-        Ok(Self { signed_data, tbs, serials: None })
+        Ok((signed_data, tbs))
     }
 
     /// Verifies the certificate revocation list’s signature.
@@ -141,13 +180,24 @@ impl Crl {
         ).map_err(VerificationError::new)
     }
 
+    /// Returns a value encoder for a reference to the CRL.
+    ///
+    /// The encoder produces exactly the octets the CRL was decoded from.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
-        self.signed_data.encode_ref()
+        &self.encoded
+    }
+
+    /// Returns the encoded octets of the CRL.
+    ///
+    /// If the CRL was decoded, these are exactly the octets it was decoded
+    /// from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.encoded.as_slice()
     }
 
     /// Returns a captured encoding of the CRL.
     pub fn to_captured(&self) -> Captured {
-        Captured::from_values(Mode::Der, self.encode_ref())
+        self.encoded.to_captured()
     }
 }
 
@@ -265,8 +315,10 @@ impl<C> TbsCertList<C> {
         let tbs: TbsCertList<RevokedCertificates> = self.into();
         let data = Captured::from_values(Mode::Der, tbs.encode_ref());
         let signature = signer.sign(key, tbs.signature, &data)?;
+        let signed_data = SignedData::new(data, signature);
         Ok(Crl {
-            signed_data: SignedData::new(data, signature),
+            encoded: Encoded::from_values(signed_data.encode_ref()),
+            signed_data,
             tbs,
             serials: None,
         })
@@ -286,6 +338,11 @@ impl<C> TbsCertList<C> {
         self.signature = signature
     }
 
+    /// Sets the algorithms for signing the CRL from a suite.
+    pub fn set_algorithm_suite(&mut self, suite: AlgorithmSuite) {
+        self.signature = suite.signature_algorithm()
+    }
+
     /// Returns a reference to the issuer name of the CRL.
     pub fn issuer(&self) -> &Name {
         &self.issuer
@@ -313,7 +370,12 @@ impl<C> TbsCertList<C> {
 
     /// Returns whether the CRL’s nextUpdate time has passed.
     pub fn is_stale(&self) -> bool {
-        self.next_update < Time::now()
+        self.is_stale_at(Time::now())
+    }
+
+    /// Returns whether the CRL’s nextUpdate time has passed at `now`.
+    pub fn is_stale_at(&self, now: Time) -> bool {
+        self.next_update < now
     }
 
     /// Sets the time of next update.
@@ -618,6 +680,16 @@ impl CrlEntry {
         CrlEntry { user_certificate, revocation_date }
     }
 
+    /// Returns the serial number of the revoked certificate.
+    pub fn user_certificate(&self) -> Serial {
+        self.user_certificate
+    }
+
+    /// Returns the time of revocation.
+    pub fn revocation_date(&self) -> Time {
+        self.revocation_date
+    }
+
     /// Takes a single CRL entry from the beginning of a constructed value.
     pub fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
@@ -741,6 +813,153 @@ impl Default for CrlStore {
 }
 
 
+//------------ CrlCheck ------------------------------------------------------
+
+/// Checks certificates against the CRL of their issuer.
+///
+/// The check locates the CRL via the CRL distribution point of the
+/// certificate and takes it from an object store. It then verifies that
+/// the CRL has indeed been issued by the certificate’s issuer, that it is
+/// not stale, and that the certificate’s serial number is not on it.
+///
+/// Since all objects issued by a CA share the same CRL, the last CRL used
+/// is kept, so a value should be reused when checking all the objects of a
+/// CA.
+///
+/// The [`check`][Self::check] method can be used from within the CRL
+/// closure of the various `process` methods of signed objects:
+///
+/// ```ignore
+/// let mut crls = CrlCheck::new(&store);
+/// let (cert, roa) = roa.process(&issuer, strict, |cert| {
+///     crls.check(cert, &issuer).map_err(Into::into)
+/// })?;
+/// ```
+///
+/// Alternatively, [`SignedObject::validate_with_crl`] performs the check,
+/// too.
+///
+/// A grace period for stale CRLs can be set via
+/// [`set_stale_grace`][Self::set_stale_grace], typically from
+/// [`ValidationOptions::stale_crl_grace`]. CRLs accepted only because of
+/// it are recorded as warnings available via
+/// [`take_warnings`][Self::take_warnings].
+///
+/// [`SignedObject::validate_with_crl`]: super::sigobj::SignedObject::validate_with_crl
+/// [`ValidationOptions::stale_crl_grace`]: super::options::ValidationOptions::stale_crl_grace
+#[derive(Clone, Debug)]
+pub struct CrlCheck<'a, S: ?Sized> {
+    /// The object store to take the CRLs from.
+    store: &'a S,
+
+    /// The last CRL used.
+    ///
+    /// This is the URI of the CRL, the key identifier of the issuer it was
+    /// successfully verified against, and the CRL itself.
+    cached: Option<(uri::Rsync, KeyIdentifier, Crl)>,
+
+    /// How long to accept a CRL after its next update time.
+    stale_grace: Duration,
+
+    /// The warnings for CRLs accepted within the grace period.
+    warnings: Vec<GraceWarning>,
+}
+
+impl<'a, S: ObjectStore + ?Sized> CrlCheck<'a, S> {
+    /// Creates a new check using the given object store.
+    pub fn new(store: &'a S) -> Self {
+        CrlCheck {
+            store,
+            cached: None,
+            stale_grace: Duration::ZERO,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Sets the grace period for stale CRLs.
+    ///
+    /// By default, there is no grace period and stale CRLs are rejected.
+    pub fn set_stale_grace(&mut self, grace: Duration) {
+        self.stale_grace = grace
+    }
+
+    /// Takes the warnings collected so far.
+    pub fn take_warnings(&mut self) -> Vec<GraceWarning> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// Checks that `cert` issued by `issuer` has not been revoked.
+    pub fn check(
+        &mut self, cert: &Cert, issuer: &Cert,
+    ) -> Result<(), CrlError> {
+        self.check_at(cert, issuer, Time::now())
+    }
+
+    /// Checks that `cert` has not been revoked at the given time.
+    ///
+    /// This is identical to [`check`][Self::check] with an explicitly
+    /// given value for the current time.
+    pub fn check_at(
+        &mut self, cert: &Cert, issuer: &Cert, now: Time,
+    ) -> Result<(), CrlError> {
+        let uri = match cert.crl_uri() {
+            Some(uri) => uri,
+            None => return Err(CrlError::MissingCrlUri)
+        };
+        let options = ValidationOptions {
+            stale_crl_grace: self.stale_grace,
+            .. Default::default()
+        };
+        let crl = self.get_crl(uri, issuer)?;
+        let warning = options.check_crl_next_update(
+            crl.next_update(), now
+        ).map_err(|_| CrlError::Stale(uri.clone()))?;
+        if crl.contains(cert.serial_number()) {
+            return Err(CrlError::Revoked(cert.serial_number()))
+        }
+        self.warnings.extend(warning);
+        Ok(())
+    }
+
+    /// Returns the CRL at `uri` after verifying it against `issuer`.
+    fn get_crl(
+        &mut self, uri: &uri::Rsync, issuer: &Cert,
+    ) -> Result<&Crl, CrlError> {
+        let issuer_id = issuer.subject_key_identifier();
+        let cached = match self.cached {
+            Some((ref cached_uri, cached_id, _)) => {
+                cached_uri == uri && cached_id == issuer_id
+            }
+            None => false
+        };
+        if !cached {
+            let data = match self.store.get(uri) {
+                Ok(Some(data)) => data,
+                Ok(None) => return Err(CrlError::NotFound(uri.clone())),
+                Err(err) => return Err(CrlError::Store(StoreError::new(err)))
+            };
+            let mut crl = Crl::decode(data).map_err(|_| {
+                CrlError::Malformed(uri.clone())
+            })?;
+            if *crl.authority_key_identifier() != issuer_id {
+                return Err(CrlError::IssuerMismatch(uri.clone()))
+            }
+            if crl.verify_signature(
+                issuer.subject_public_key_info()
+            ).is_err() {
+                return Err(CrlError::InvalidSignature(uri.clone()))
+            }
+            crl.cache_serials();
+            self.cached = Some((uri.clone(), issuer_id, crl));
+        }
+        match self.cached {
+            Some((_, _, ref crl)) => Ok(crl),
+            None => unreachable!()
+        }
+    }
+}
+
+
 //============ Error Types ===================================================
 
 //------------ InvalidExtension ----------------------------------------------
@@ -770,6 +989,87 @@ impl fmt::Display for InvalidExtension {
 }
 
 
+//------------ CrlError ------------------------------------------------------
+
+/// A certificate failed the check against its issuer’s CRL.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CrlError {
+    /// The certificate has no CRL distribution point.
+    MissingCrlUri,
+
+    /// The CRL is not present in the object store.
+    NotFound(uri::Rsync),
+
+    /// Accessing the object store failed.
+    Store(StoreError),
+
+    /// The CRL could not be decoded.
+    Malformed(uri::Rsync),
+
+    /// The CRL was not issued by the certificate’s issuer.
+    IssuerMismatch(uri::Rsync),
+
+    /// The signature of the CRL is invalid.
+    InvalidSignature(uri::Rsync),
+
+    /// The CRL’s next update time has passed.
+    Stale(uri::Rsync),
+
+    /// The certificate with the given serial number has been revoked.
+    Revoked(Serial),
+}
+
+impl From<CrlError> for VerificationError {
+    fn from(err: CrlError) -> Self {
+        VerificationError::new(ContentError::from_boxed(Box::new(err)))
+    }
+}
+
+impl From<CrlError> for ValidationError {
+    fn from(err: CrlError) -> Self {
+        VerificationError::from(err).into()
+    }
+}
+
+impl fmt::Display for CrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CrlError::MissingCrlUri => {
+                f.write_str("certificate without CRL distribution point")
+            }
+            CrlError::NotFound(ref uri) => {
+                write!(f, "CRL {} not found", uri)
+            }
+            CrlError::Store(ref err) => err.fmt(f),
+            CrlError::Malformed(ref uri) => {
+                write!(f, "failed to decode CRL {}", uri)
+            }
+            CrlError::IssuerMismatch(ref uri) => {
+                write!(f, "CRL {} not issued by certificate issuer", uri)
+            }
+            CrlError::InvalidSignature(ref uri) => {
+                write!(f, "invalid signature on CRL {}", uri)
+            }
+            CrlError::Stale(ref uri) => {
+                write!(f, "CRL {} is stale", uri)
+            }
+            CrlError::Revoked(serial) => {
+                write!(f, "certificate {} has been revoked", serial)
+            }
+        }
+    }
+}
+
+impl error::Error for CrlError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            CrlError::Store(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
 //============ Tests =========================================================
 
 #[cfg(test)]
@@ -786,6 +1086,78 @@ mod test {
         ).unwrap();
     }
 
+    #[test]
+    fn round_trip() {
+        for der in [
+            include_bytes!("../../test-data/repository/ta.crl").as_ref(),
+            include_bytes!("../../test-data/repository/ca1.crl").as_ref(),
+        ] {
+            let crl = Crl::decode(der).unwrap();
+            assert_eq!(crl.as_bytes(), der);
+            assert_eq!(crl.to_captured().as_slice(), der);
+        }
+    }
+
+    #[test]
+    fn crl_check() {
+        use std::str::FromStr;
+        use crate::store::MemoryStore;
+        use crate::repository::manifest::Manifest;
+
+        let ta = Cert::decode(
+            include_bytes!("../../test-data/repository/ta.cer").as_ref()
+        ).unwrap();
+        let mft = Manifest::decode(
+            include_bytes!("../../test-data/repository/ta.mft").as_ref(),
+            false
+        ).unwrap();
+        let ee = mft.cert();
+        let crl_uri = ee.crl_uri().unwrap().clone();
+        let at = Time::utc(2019, 5, 1, 0, 0, 0);
+
+        let mut store = MemoryStore::new();
+        assert!(matches!(
+            CrlCheck::new(&store).check_at(ee, &ta, at),
+            Err(CrlError::NotFound(_))
+        ));
+
+        // The wrong CRL.
+        store.insert(crl_uri.clone(), Bytes::from_static(
+            include_bytes!("../../test-data/repository/ca1.crl")
+        )).unwrap();
+        assert!(matches!(
+            CrlCheck::new(&store).check_at(ee, &ta, at),
+            Err(CrlError::IssuerMismatch(_))
+        ));
+
+        store.insert(crl_uri.clone(), Bytes::from_static(
+            include_bytes!("../../test-data/repository/ta.crl")
+        )).unwrap();
+        let mut check = CrlCheck::new(&store);
+        check.check_at(ee, &ta, at).unwrap();
+        assert!(matches!(
+            check.check_at(ee, &ta, Time::utc(2019, 6, 1, 0, 0, 0)),
+            Err(CrlError::Stale(_))
+        ));
+        check.set_stale_grace(Duration::from_secs(86400 * 7));
+        check.check_at(ee, &ta, Time::utc(2019, 5, 27, 0, 0, 0)).unwrap();
+        assert_eq!(check.take_warnings().len(), 1);
+        assert!(check.take_warnings().is_empty());
+
+        // A CRL elsewhere isn’t found.
+        let mut store = MemoryStore::new();
+        store.insert(
+            uri::Rsync::from_str("rsync://example.com/ta/ta.crl").unwrap(),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.crl")
+            )
+        ).unwrap();
+        assert!(matches!(
+            CrlCheck::new(&store).check_at(ee, &ta, at),
+            Err(CrlError::NotFound(_))
+        ));
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn serde_crl() {
@@ -0,0 +1,418 @@
+//! Human readable dumps of objects.
+//!
+//! When debugging interoperability issues, it helps to see what exactly
+//! is in an object. The [`Describe`] trait provides a detailed,
+//! multi-line description similar to the text output of OpenSSL for
+//! certificates, CRLs, manifests, and ROAs as well as for publication
+//! protocol messages if the `"ca"` feature is enabled.
+//!
+//! The description is meant for humans. Its exact format may change
+//! between releases and should not be parsed.
+
+use std::fmt;
+use super::cert::{Cert, KeyUsage, Overclaim, TbsCert};
+use super::crl::Crl;
+use super::manifest::Manifest;
+use super::resources::IpResources;
+use super::roa::Roa;
+use super::x509::{Name, Time};
+
+
+//------------ Describe ------------------------------------------------------
+
+/// A type that can be described in detail.
+pub trait Describe {
+    /// Writes a detailed, multi-line description to a formatter.
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Returns a value displaying the detailed description.
+    fn describe(&self) -> Description<'_, Self> {
+        Description(self)
+    }
+}
+
+
+//------------ Description ---------------------------------------------------
+
+/// A value displaying the detailed description of an object.
+///
+/// Values of this type are returned by [`Describe::describe`].
+pub struct Description<'a, T: ?Sized>(&'a T);
+
+impl<'a, T: Describe + ?Sized> fmt::Display for Description<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_detailed(f)
+    }
+}
+
+
+//------------ Certificates --------------------------------------------------
+
+impl Describe for Cert {
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Certificate:")?;
+        fmt_tbs_cert(self, 4, f)
+    }
+}
+
+impl Describe for TbsCert {
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Certificate (unsigned):")?;
+        fmt_tbs_cert(self, 4, f)
+    }
+}
+
+/// Writes the content of a certificate with the given indentation.
+fn fmt_tbs_cert(
+    cert: &TbsCert, indent: usize, f: &mut fmt::Formatter
+) -> fmt::Result {
+    let sub = indent + 4;
+    line(f, indent, "Serial Number", cert.serial_number())?;
+    line(f, indent, "Issuer", DisplayName(cert.issuer()))?;
+    writeln!(f, "{:indent$}Validity:", "", indent = indent)?;
+    line(f, sub, "Not Before", DisplayTime(cert.validity().not_before()))?;
+    line(f, sub, "Not After", DisplayTime(cert.validity().not_after()))?;
+    line(f, indent, "Subject", DisplayName(cert.subject()))?;
+    line(
+        f, indent, "Subject Key Identifier",
+        cert.subject_key_identifier()
+    )?;
+    if let Some(aki) = cert.authority_key_identifier() {
+        line(f, indent, "Authority Key Identifier", aki)?;
+    }
+    line(
+        f, indent, "Key Usage",
+        match cert.key_usage() {
+            KeyUsage::Ca => "CA (keyCertSign, cRLSign)",
+            KeyUsage::Ee => "EE (digitalSignature)",
+        }
+    )?;
+    if let Some(crl) = cert.crl_uri() {
+        line(f, indent, "CRL Distribution Point", crl)?;
+    }
+    if let Some(issuer) = cert.ca_issuer() {
+        line(f, indent, "CA Issuer", issuer)?;
+    }
+    if cert.ca_repository().is_some() || cert.rpki_manifest().is_some()
+        || cert.rpki_notify().is_some() || cert.signed_object().is_some()
+    {
+        writeln!(
+            f, "{:indent$}Subject Information Access:", "", indent = indent
+        )?;
+        opt_line(f, sub, "CA Repository", cert.ca_repository())?;
+        opt_line(f, sub, "RPKI Manifest", cert.rpki_manifest())?;
+        opt_line(f, sub, "RPKI Notify", cert.rpki_notify())?;
+        opt_line(f, sub, "Signed Object", cert.signed_object())?;
+    }
+    line(
+        f, indent, "Policy",
+        match cert.overclaim() {
+            Overclaim::Refuse => "RFC 6484 (refuse overclaim)",
+            Overclaim::Trim => "RFC 8360 (trim overclaim)",
+        }
+    )?;
+    writeln!(f, "{:indent$}Resources:", "", indent = indent)?;
+    line(f, sub, "AS", EmptyAsNone(cert.as_resources()))?;
+    line(f, sub, "IPv4", DisplayIp(cert.v4_resources(), true))?;
+    line(f, sub, "IPv6", DisplayIp(cert.v6_resources(), false))
+}
+
+
+//------------ CRLs ----------------------------------------------------------
+
+impl Describe for Crl {
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Certificate Revocation List:")?;
+        line(f, 4, "Issuer", DisplayName(self.issuer()))?;
+        line(f, 4, "CRL Number", self.crl_number())?;
+        line(f, 4, "This Update", DisplayTime(self.this_update()))?;
+        line(f, 4, "Next Update", DisplayTime(self.next_update()))?;
+        line(
+            f, 4, "Authority Key Identifier",
+            self.authority_key_identifier()
+        )?;
+        let mut revoked = self.revoked_certs().iter().peekable();
+        if revoked.peek().is_none() {
+            return writeln!(f, "    No Revoked Certificates")
+        }
+        writeln!(f, "    Revoked Certificates:")?;
+        for entry in revoked {
+            writeln!(
+                f, "        {} revoked {}",
+                entry.user_certificate(),
+                DisplayTime(entry.revocation_date())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------ Signed Objects ------------------------------------------------
+
+impl Describe for Manifest {
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Manifest:")?;
+        line(f, 4, "Manifest Number", self.manifest_number())?;
+        line(f, 4, "This Update", DisplayTime(self.this_update()))?;
+        line(f, 4, "Next Update", DisplayTime(self.next_update()))?;
+        writeln!(f, "    Files:")?;
+        for item in self.iter() {
+            writeln!(
+                f, "        {} {}",
+                String::from_utf8_lossy(item.file()),
+                DisplayHex(item.hash())
+            )?;
+        }
+        fmt_ee_cert(self.cert(), f)
+    }
+}
+
+impl Describe for Roa {
+    fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Route Origin Authorization:")?;
+        line(f, 4, "Origin AS", self.content().as_id())?;
+        writeln!(f, "    Prefixes:")?;
+        for addr in self.content().iter() {
+            writeln!(f, "        {}", addr)?;
+        }
+        fmt_ee_cert(self.cert(), f)
+    }
+}
+
+/// Writes the EE certificate of a signed object.
+fn fmt_ee_cert(cert: &Cert, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "    EE Certificate:")?;
+    fmt_tbs_cert(cert, 8, f)
+}
+
+
+//------------ Publication Messages ------------------------------------------
+
+#[cfg(feature = "ca")]
+mod publication {
+    use std::fmt;
+    use crate::ca::publication::{
+        Message, PublishDeltaElement, Query, Reply,
+    };
+    use super::Describe;
+
+    impl Describe for Message {
+        fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Message::Query(ref query) => query.fmt_detailed(f),
+                Message::Reply(ref reply) => reply.fmt_detailed(f),
+            }
+        }
+    }
+
+    impl Describe for Query {
+        fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Query::List => writeln!(f, "List Query"),
+                Query::Delta(ref delta) => {
+                    writeln!(f, "Delta Query:")?;
+                    for element in delta.iter() {
+                        match *element {
+                            PublishDeltaElement::Publish(ref publish) => {
+                                writeln!(
+                                    f, "    publish {} ({} bytes)",
+                                    publish.uri(),
+                                    publish.content().to_bytes().len()
+                                )?;
+                            }
+                            PublishDeltaElement::Update(ref update) => {
+                                writeln!(
+                                    f, "    update {} ({} bytes)",
+                                    update.uri(),
+                                    update.content().to_bytes().len()
+                                )?;
+                                writeln!(
+                                    f, "        replaces {}", update.hash()
+                                )?;
+                            }
+                            PublishDeltaElement::Withdraw(ref withdraw) => {
+                                writeln!(
+                                    f, "    withdraw {}", withdraw.uri()
+                                )?;
+                                writeln!(
+                                    f, "        hash {}", withdraw.hash()
+                                )?;
+                            }
+                        }
+                        if let Some(tag) = element.tag() {
+                            writeln!(f, "        tag {}", tag)?;
+                        }
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl Describe for Reply {
+        fn fmt_detailed(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                Reply::List(ref list) => {
+                    writeln!(f, "List Reply:")?;
+                    for element in list.elements() {
+                        writeln!(
+                            f, "    {} {}", element.uri(), element.hash()
+                        )?;
+                    }
+                    Ok(())
+                }
+                Reply::Success => writeln!(f, "Success Reply"),
+                Reply::ErrorReply(ref reply) => {
+                    writeln!(f, "Error Reply:")?;
+                    for err in reply.errors() {
+                        writeln!(f, "    {}", err.error_code())?;
+                        if let Some(tag) = err.tag() {
+                            writeln!(f, "        tag {}", tag)?;
+                        }
+                        if let Some(text) = err.error_text() {
+                            writeln!(f, "        text {}", text)?;
+                        }
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Writes a line with a label and a value.
+fn line(
+    f: &mut fmt::Formatter, indent: usize, label: &str,
+    value: impl fmt::Display,
+) -> fmt::Result {
+    writeln!(f, "{:indent$}{}: {}", "", label, value, indent = indent)
+}
+
+/// Writes a line with a label and a value if there is a value.
+fn opt_line(
+    f: &mut fmt::Formatter, indent: usize, label: &str,
+    value: Option<impl fmt::Display>,
+) -> fmt::Result {
+    match value {
+        Some(value) => line(f, indent, label, value),
+        None => Ok(())
+    }
+}
+
+/// Displays a name.
+///
+/// Names following the RPKI profile are shown via their common name.
+struct DisplayName<'a>(&'a Name);
+
+impl<'a> fmt::Display for DisplayName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.common_name() {
+            Some(cn) => write!(f, "CN={}", cn),
+            None => write!(f, "(non-RPKI name)"),
+        }
+    }
+}
+
+/// Displays a time in RFC 3339 format.
+struct DisplayTime(Time);
+
+impl fmt::Display for DisplayTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_rfc3339())
+    }
+}
+
+/// Displays bytes as hex.
+struct DisplayHex<'a>(&'a [u8]);
+
+impl<'a> fmt::Display for DisplayHex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for ch in self.0 {
+            write!(f, "{:02x}", ch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays IP resources of one address family.
+struct DisplayIp<'a>(&'a IpResources, bool);
+
+impl<'a> fmt::Display for DisplayIp<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.to_blocks() {
+            Ok(blocks) => {
+                if blocks.is_empty() {
+                    f.write_str("none")
+                }
+                else if self.1 {
+                    blocks.as_v4().fmt(f)
+                }
+                else {
+                    blocks.as_v6().fmt(f)
+                }
+            }
+            Err(_) => f.write_str("inherit"),
+        }
+    }
+}
+
+/// Displays a value, showing an empty value as “none”.
+struct EmptyAsNone<T>(T);
+
+impl<T: fmt::Display> fmt::Display for EmptyAsNone<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.0.to_string();
+        if s.is_empty() {
+            f.write_str("none")
+        }
+        else {
+            f.write_str(&s)
+        }
+    }
+}
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describe_cert() {
+        let cert = Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap();
+        let text = cert.describe().to_string();
+        assert!(text.starts_with("Certificate:\n"));
+        assert!(text.contains(
+            "    Subject Key Identifier: \
+             2A7DD1D787D793E4C8AF56E197D4EED92AF6BA13\n"
+        ));
+        assert!(text.contains("        AS: AS0-AS4294967295\n"));
+        assert!(text.contains("        IPv4: 0.0.0.0/0\n"));
+    }
+
+    #[test]
+    fn describe_crl_and_roa() {
+        let crl = Crl::decode(
+            include_bytes!("../../test-data/repository/ca1.crl").as_ref()
+        ).unwrap();
+        assert!(
+            crl.describe().to_string().starts_with(
+                "Certificate Revocation List:\n"
+            )
+        );
+        let roa = Roa::decode(
+            include_bytes!("../../test-data/repository/example-ripe.roa")
+                .as_ref(),
+            false
+        ).unwrap();
+        let text = roa.describe().to_string();
+        assert!(text.contains("    Origin AS: AS"));
+        assert!(text.contains("    EE Certificate:\n        Serial Number"));
+    }
+}
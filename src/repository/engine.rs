@@ -0,0 +1,2454 @@
+//! Validating a complete repository tree.
+//!
+//! The [`Engine`] performs top-down validation of the RPKI as described in
+//! RFC 6487 and RFC 9286: starting from the trust anchor certificates of
+//! all enabled trust anchors of a [`TalSet`], it follows the manifest of
+//! each CA, checks the manifest and the CRL, and then validates all
+//! objects listed on the manifest. Certificates of child CAs are validated
+//! recursively; ROAs, ASPAs, and router certificates produce payload.
+//! TAK objects published by a trust anchor are recorded in the report so
+//! that announced successor keys can be passed on to a
+//! [`TaKeyMonitor`][super::tal::TaKeyMonitor]. All validated CAs and their
+//! Ghostbusters records are collected into the report’s
+//! [`ContactIndex`][super::gbr::ContactIndex]. Similarly, validated
+//! certificates, ROAs, and ASPAs end up in its
+//! [`CoverageIndex`][super::covering::CoverageIndex]. Unless turned off,
+//! the [`Provenance`] of each payload item is recorded, too.
+//!
+//! Publication points are treated as a whole as required by RFC 9286: if
+//! the manifest or CRL are invalid or any of the files listed on the
+//! manifest are missing or don’t match their hash, none of the objects of
+//! the publication point are used. Instead, if a fallback store with
+//! previously validated objects has been given via
+//! [`Engine::set_fallback`] and its [`LastKnownGood`] permits it, the
+//! publication point is validated from the objects in that store. The
+//! decision is recorded in the report as a [`PointOutcome`] and the
+//! subtree below the publication point is flagged. If the publication
+//! point fails, all objects listed on its manifest are rejected. If the
+//! cached objects are used instead, the reason the current objects were
+//! rejected is kept as a warning.
+//!
+//! Local policy can be added via [`ObjectHook`]s. They are called for
+//! each object listed on a manifest before and after it is validated and
+//! can reject an object or add warnings to the report.
+//!
+//! The [`ValidationLimits`][super::options::ValidationLimits] of the
+//! validation options are enforced for the trust anchor certificates, the
+//! manifests, and all objects listed on them. Objects exceeding a limit
+//! are rejected. If a CA’s manifest exceeds one, the entire publication
+//! point is skipped.
+//!
+//! ROAs, ASPAs, Ghostbusters records, and TAK objects are validated with
+//! the validation options, i.e., their signing time is checked and the
+//! grace period for EE certificates not yet valid applies. These objects
+//! as well as manifests and CRLs accepted only thanks to a grace period
+//! get the [`GraceWarning`] as a warning. For a CRL, it is reported once
+//! even if many objects were checked against it.
+//!
+//! The engine doesn’t fetch anything. All objects, including the trust
+//! anchor certificates, are taken from an [`ObjectStore`] under their
+//! rsync URI. For trust anchor certificates, this is the first rsync URI
+//! given in the TAL. The engine keeps working on the data returned by the
+//! store without copying it. With an [`FsStore`][crate::store::fs::FsStore]
+//! that has a threshold set via
+//! [`set_mmap_threshold`][crate::store::fs::FsStore::set_mmap_threshold],
+//! large objects are thus validated straight from memory-mapped files.
+//! The store only maps files that can’t be modified in place, such as those
+//! it wrote itself.
+//!
+//! Validation normally happens as of the current time. With
+//! [`Engine::validate_at`], all time-dependent checks – the validity of
+//! certificates, whether manifests and CRLs are stale – are performed as
+//! of a different instant instead. Together with the snapshot of an
+//! [`ArchiveStore`][crate::store::archive::ArchiveStore] for that same
+//! instant, this allows recomputing the payload of the past.
+//!
+//! Subscribers to the engine’s [`EventBus`] learn about trust anchors
+//! whose certificate is missing from the store and about stale manifests
+//! while validation is running.
+//!
+//! Validating ROAs and ASPAs can be sped up with a [`ValidationCache`]
+//! given via [`Engine::set_cache`]. For an object found in the cache, the
+//! signatures and resources aren’t checked again. Its EE certificate is
+//! still checked for expiry and revocation.
+//!
+//! If the `"fetch"` feature is enabled, the engine can be told about
+//! repositories that were skipped because they are dead via
+//! [`Engine::set_dead_repositories`]. Their objects are validated from the
+//! store as usual, but their CAs’ manifests get a warning and the
+//! repositories are recorded as
+//! [`FetchOutcome::Skipped`][super::report::FetchOutcome::Skipped].
+//!
+//! At the end of each run, the number of accepted and rejected objects by
+//! type, the outcomes of publication points, and the duration of the run
+//! are reported to the [`Metrics`][crate::metrics::Metrics] given via
+//! [`Engine::set_metrics`].
+//!
+//! Each CA whose publication point is validated is reported to the
+//! progress reporter given via [`Engine::set_progress`].
+//!
+//! A run can be aborted via the [`CancelToken`] given to
+//! [`Engine::set_cancel_token`]. The engine checks the token before each
+//! CA and each object and stops once it has been cancelled. The report of
+//! such a run is marked via [`ValidationReport::is_cancelled`].
+//!
+//! The outcome of validation is a [`ValidationReport`]. The module is only
+//! available if both the `"repository"` and `"rtr"` features are enabled.
+
+#![cfg(feature = "rtr")]
+
+use std::{error, fmt};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use bytes::Bytes;
+use crate::uri;
+use crate::clock::{Clock, SharedClock};
+use crate::events::{Event, EventBus};
+#[cfg(feature = "fetch")]
+use crate::fetch::dead::DeadRepositories;
+use crate::metrics::{SharedMetrics, names};
+use crate::progress::SharedProgress;
+use crate::rtr::payload::{Aspa as AspaPayload, Payload};
+use crate::rtr::pdu::ProviderAsns;
+use crate::store::ObjectStore;
+use crate::store::fallback::{Fallback, LastKnownGood};
+use crate::util::cancel::CancelToken;
+use super::aspa::Aspa;
+use super::cache::{CacheKey, ValidationCache};
+use super::cert::{Cert, ResourceCert};
+use super::crl::CrlCheck;
+use super::gbr::Ghostbusters;
+use super::hook::{HookObject, Hooks, ObjectHook};
+use super::manifest::{Manifest, ManifestHash};
+use super::options::{GraceWarning, ValidationOptions};
+use super::provenance::Provenance;
+use super::report::{PointOutcome, SubtreeFlag, ValidationReport};
+use super::roa::Roa;
+use super::tak::Tak;
+use super::tal::{TalSet, TalUri, TrustAnchor};
+use super::x509::{Time, Validity};
+
+
+//------------ Engine --------------------------------------------------------
+
+/// The validation engine.
+///
+/// The engine is created for an object store and a set of trust anchors.
+/// Validation options can be changed via
+/// [`set_options`][Self::set_options]. Each call to
+/// [`validate`][Self::validate] or [`validate_at`][Self::validate_at]
+/// performs a complete validation run.
+#[derive(Debug)]
+pub struct Engine<'a, S> {
+    /// The store to take all objects from.
+    store: &'a S,
+
+    /// The trust anchors to start from.
+    anchors: &'a TalSet,
+
+    /// The options for validating objects.
+    options: ValidationOptions,
+
+    /// The bus to emit events to.
+    events: EventBus,
+
+    /// Whether to record the provenance of payload.
+    provenance: bool,
+
+    /// The store with cached objects to fall back to.
+    fallback: Option<FallbackStore<'a>>,
+
+    /// The hooks for local policy.
+    hooks: Hooks,
+
+    /// The clock providing the current time.
+    clock: SharedClock,
+
+    /// The token for cancelling a run.
+    cancel: CancelToken,
+
+    /// The progress reporter.
+    progress: SharedProgress,
+
+    /// The cache of validated objects.
+    cache: Option<&'a ValidationCache<()>>,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
+    /// The repositories considered dead.
+    #[cfg(feature = "fetch")]
+    dead: Option<&'a DeadRepositories>,
+}
+
+impl<'a, S: ObjectStore> Engine<'a, S> {
+    /// Creates a new engine with default options.
+    pub fn new(store: &'a S, anchors: &'a TalSet) -> Self {
+        Engine {
+            store,
+            anchors,
+            options: ValidationOptions::default(),
+            events: EventBus::default(),
+            provenance: true,
+            fallback: None,
+            hooks: Hooks::new(),
+            clock: Default::default(),
+            cancel: CancelToken::new(),
+            progress: SharedProgress::default(),
+            cache: None,
+            metrics: SharedMetrics::default(),
+            #[cfg(feature = "fetch")]
+            dead: None,
+        }
+    }
+
+    /// Returns the validation options.
+    pub fn options(&self) -> &ValidationOptions {
+        &self.options
+    }
+
+    /// Sets the validation options.
+    pub fn set_options(&mut self, options: ValidationOptions) {
+        self.options = options
+    }
+
+    /// Sets the bus to emit events to.
+    ///
+    /// The engine emits [`Event::TaUnreachable`] if the certificate of a
+    /// trust anchor is not in the store and [`Event::ManifestStale`] for
+    /// each manifest past its next update time, whether or not it is
+    /// still accepted thanks to a grace period.
+    pub fn set_events(&mut self, events: EventBus) {
+        self.events = events
+    }
+
+    /// Sets whether to record the provenance of payload.
+    ///
+    /// Provenance is recorded by default. Turning it off saves some memory
+    /// for applications that only need the payload itself.
+    pub fn set_provenance(&mut self, enabled: bool) {
+        self.provenance = enabled
+    }
+
+    /// Sets the store with cached objects to fall back to.
+    ///
+    /// If the current objects of a publication point in the store don’t
+    /// agree with its manifest, the engine asks `good` whether cached
+    /// objects may be used for the publication point. If so, it validates
+    /// the publication point using the objects in `store` instead –
+    /// typically a copy of the objects that were successfully validated
+    /// last time. The store can be of a different type than the engine’s
+    /// store. Without a fallback store, such a publication point is not
+    /// used at all.
+    ///
+    /// The engine doesn’t update `good`. After a run, the publication
+    /// points with a [`PointOutcome::Current`] can be recorded as
+    /// refreshed.
+    pub fn set_fallback<F: ObjectStore>(
+        &mut self, store: &'a F, good: &'a LastKnownGood
+    ) {
+        self.fallback = Some(FallbackStore { store, good })
+    }
+
+    /// Adds a hook for local policy.
+    ///
+    /// Hooks are called in the order they were added.
+    pub fn add_hook(&mut self, hook: impl ObjectHook + 'static) {
+        self.hooks.push(hook)
+    }
+
+    /// Replaces all hooks for local policy.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks
+    }
+
+    /// Sets the clock providing the current time.
+    ///
+    /// The clock determines the time used by [`validate`][Self::validate].
+    /// By default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Sets the token for cancelling a run.
+    ///
+    /// Once the token has been cancelled, a run stops before the next CA
+    /// or object and returns a report marked as cancelled. Since a token
+    /// stays cancelled, all later runs stop right away.
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel
+    }
+
+    /// Sets the progress reporter of the engine.
+    ///
+    /// Each CA is reported via [`Progress::ca_validated`] once its
+    /// publication point is about to be validated, identified by the URI
+    /// of its certificate.
+    ///
+    /// [`Progress::ca_validated`]: crate::progress::Progress::ca_validated
+    pub fn set_progress(&mut self, progress: SharedProgress) {
+        self.progress = progress
+    }
+
+    /// Sets the cache of validated objects.
+    ///
+    /// ROAs and ASPAs that validate successfully are added to the cache
+    /// under their [`CacheKey`] with the time of the run. If an object is
+    /// found in the cache in a later run, its signatures and resources are
+    /// not checked again. The key includes the resources of the issuing CA
+    /// and the fingerprint of the validation options, so existing entries
+    /// don’t match once the CA is re-issued with different resources or
+    /// the options change. The cache can be shared between engines.
+    pub fn set_cache(&mut self, cache: &'a ValidationCache<()>) {
+        self.cache = Some(cache)
+    }
+
+    /// Sets the metrics the engine reports to.
+    ///
+    /// The metrics of a run are reported once it has finished. See
+    /// [`names::VALIDATION_OBJECTS`], [`names::VALIDATION_POINTS`], and
+    /// [`names::VALIDATION_DURATION`].
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Sets the repositories considered dead.
+    ///
+    /// A CA’s repository is dead if its RRDP notification URI or its rsync
+    /// module is dead at the validation time. Its objects were not
+    /// refreshed, so the manifest of the CA gets a warning and the
+    /// repository is recorded as
+    /// [`FetchOutcome::Skipped`][super::report::FetchOutcome::Skipped]
+    /// unless the report already has an outcome for it.
+    #[cfg(feature = "fetch")]
+    pub fn set_dead_repositories(&mut self, dead: &'a DeadRepositories) {
+        self.dead = Some(dead)
+    }
+
+    /// Validates all enabled trust anchors as of now.
+    ///
+    /// The current time is taken from the engine’s clock.
+    pub fn validate(&self) -> ValidationReport {
+        self.validate_at(Time::new(self.clock.now()))
+    }
+
+    /// Validates all enabled trust anchors as of the given time.
+    ///
+    /// Certificates, manifests, and CRLs are considered valid if they were
+    /// valid at `now`. Note that the objects are still taken from the store
+    /// as they are. In order to recreate the result of a validation run in
+    /// the past, the store needs to contain the objects that were
+    /// published at that time.
+    pub fn validate_at(&self, now: Time) -> ValidationReport {
+        let start = self.clock.now();
+        let mut run = Run {
+            engine: self,
+            now,
+            report: ValidationReport::new(),
+            visited: HashSet::new(),
+        };
+        for anchor in self.anchors.enabled() {
+            if run.cancelled() {
+                break
+            }
+            run.process_anchor(anchor);
+        }
+        let duration = (
+            self.clock.now() - start
+        ).to_std().unwrap_or_default();
+        self.report_metrics(&run.report, duration);
+        run.report
+    }
+
+    /// Reports the metrics of a finished run.
+    fn report_metrics(
+        &self, report: &ValidationReport, duration: Duration
+    ) {
+        const TYPES: &[&str] = &[
+            "cer", "mft", "crl", "roa", "asa", "gbr", "tak"
+        ];
+
+        let mut objects = HashMap::<_, u64>::new();
+        for (uri, outcome) in report.outcomes() {
+            let kind = uri.as_str().rsplit('.').next().filter(|kind| {
+                TYPES.contains(kind)
+            }).unwrap_or("other");
+            let result = if outcome.is_accepted() {
+                "accepted"
+            }
+            else {
+                "rejected"
+            };
+            *objects.entry((kind, result)).or_default() += 1;
+        }
+        for ((kind, result), value) in objects {
+            self.metrics.counter(
+                names::VALIDATION_OBJECTS,
+                &[("type", kind), ("result", result)], value
+            );
+        }
+
+        let mut points = HashMap::<_, u64>::new();
+        for (_, outcome) in report.point_outcomes() {
+            let outcome = match outcome {
+                PointOutcome::Current => "current",
+                PointOutcome::Cached(_) => "cached",
+                PointOutcome::Failed(_) => "failed",
+            };
+            *points.entry(outcome).or_default() += 1;
+        }
+        for (outcome, value) in points {
+            self.metrics.counter(
+                names::VALIDATION_POINTS, &[("outcome", outcome)], value
+            );
+        }
+
+        self.metrics.histogram(
+            names::VALIDATION_DURATION, &[], duration.as_secs_f64()
+        );
+    }
+}
+
+
+//------------ FallbackStore -------------------------------------------------
+
+/// The store with cached objects and the policy for using them.
+#[derive(Clone, Copy)]
+struct FallbackStore<'a> {
+    /// The store with the cached objects.
+    store: &'a dyn AnyStore,
+
+    /// The decision whether cached objects may be used.
+    good: &'a LastKnownGood,
+}
+
+impl<'a> fmt::Debug for FallbackStore<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FallbackStore")
+            .field("good", &self.good)
+            .finish_non_exhaustive()
+    }
+}
+
+
+//------------ PublicationPoint ----------------------------------------------
+
+/// The checked content of a publication point.
+struct PublicationPoint<'a> {
+    /// The CRL check for the publication point.
+    crl: CrlCheck<'a, dyn AnyStore + 'a>,
+
+    /// The URI of the CRL of the publication point.
+    crl_uri: uri::Rsync,
+
+    /// The objects listed on the manifest other than the CRL.
+    ///
+    /// Each item contains the URI, content, and manifest hash.
+    objects: Vec<(uri::Rsync, Bytes, ManifestHash)>,
+}
+
+
+//------------ PointFailure --------------------------------------------------
+
+/// The reason why a publication point couldn’t be used.
+struct PointFailure {
+    /// The URI of the object that caused the failure.
+    uri: uri::Rsync,
+
+    /// Why the object caused the failure.
+    reason: String,
+
+    /// The URIs of all files listed on the manifest.
+    ///
+    /// This is empty if the manifest couldn’t be decoded.
+    listed: Vec<uri::Rsync>,
+}
+
+impl PointFailure {
+    fn new(uri: &uri::Rsync, reason: impl Into<String>) -> Self {
+        PointFailure {
+            uri: uri.clone(), reason: reason.into(), listed: Vec::new()
+        }
+    }
+}
+
+impl fmt::Display for PointFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.uri, self.reason)
+    }
+}
+
+
+//------------ Run -----------------------------------------------------------
+
+/// The state of a single validation run.
+struct Run<'e, 'a, S> {
+    /// The engine performing the run.
+    engine: &'e Engine<'a, S>,
+
+    /// The time to validate as of.
+    now: Time,
+
+    /// The report to add outcomes to.
+    report: ValidationReport,
+
+    /// The manifest URIs of all CAs processed so far.
+    ///
+    /// This protects against loops in the repository tree.
+    visited: HashSet<String>,
+}
+
+impl<'e, 'a, S: ObjectStore> Run<'e, 'a, S> {
+    fn strict(&self) -> bool {
+        self.engine.options.strict
+    }
+
+    /// Returns whether the run has been cancelled.
+    ///
+    /// If so, marks the report as cancelled.
+    fn cancelled(&mut self) -> bool {
+        if self.engine.cancel.is_cancelled() {
+            self.report.set_cancelled();
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Returns the content of the object at `uri`.
+    ///
+    /// If the object is missing, records `reason` as the rejection reason
+    /// for `uri`.
+    fn load(&mut self, uri: &uri::Rsync, reason: &str) -> Option<Bytes> {
+        match self.load_from(self.engine.store, uri, reason) {
+            Ok(data) => Some(data),
+            Err(reason) => {
+                self.report.reject(uri.clone(), reason);
+                None
+            }
+        }
+    }
+
+    /// Returns the content of the object at `uri` in the given store.
+    ///
+    /// If the object is missing, returns `reason` as the error. If there is
+    /// a maximum object size, the size of the object is checked via
+    /// [`ObjectStore::size`] before loading it. Objects exceeding it are
+    /// refused, too.
+    fn load_from(
+        &self, store: &dyn AnyStore, uri: &uri::Rsync, reason: &str
+    ) -> Result<Bytes, String> {
+        let limits = self.engine.options.limits;
+        if limits.max_object_size.is_some() {
+            match store.size(uri) {
+                Ok(Some(size)) => {
+                    limits.check_object_size(
+                        usize::try_from(size).unwrap_or(usize::MAX)
+                    ).map_err(|err| err.to_string())?;
+                }
+                Ok(None) => return Err(reason.into()),
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+        let data = match store.get(uri) {
+            Ok(Some(data)) => data,
+            Ok(None) => return Err(reason.into()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        // The object may have changed since we checked its size.
+        limits.check_object_size(data.len()).map_err(|err| err.to_string())?;
+        Ok(data)
+    }
+
+    /// Validates a trust anchor and everything below it.
+    fn process_anchor(&mut self, anchor: &TrustAnchor) {
+        let tal_uri = anchor.uris().into_iter().find(|uri| uri.is_rsync());
+        let uri = match tal_uri {
+            Some(TalUri::Rsync(uri)) => uri,
+            _ => return
+        };
+        let data = match self.load(
+            uri, "trust anchor certificate not found"
+        ) {
+            Some(data) => data,
+            None => {
+                self.engine.events.emit(Event::TaUnreachable {
+                    tal: anchor.name().into(),
+                    uri: uri.to_string(),
+                });
+                return
+            }
+        };
+        let cert = match Cert::decode(data) {
+            Ok(cert) => cert,
+            Err(err) => {
+                self.report.reject(uri.clone(), err.to_string());
+                return
+            }
+        };
+        let tal_uri = TalUri::Rsync(uri.clone());
+        if let Some(event) = anchor.tal().check_ta_key_at(
+            &tal_uri, &cert, self.strict(), self.now
+        ) {
+            self.report.reject(uri.clone(), event.to_string());
+            return
+        }
+        match cert.validate_ta_at(
+            anchor.info().clone(), self.strict(), self.now
+        ) {
+            Ok(cert) => {
+                self.report.accept(uri.clone());
+                self.report.contacts_mut().add_ca(uri.clone(), &cert);
+                self.report.coverage_mut().add_ca(uri.clone(), &cert);
+                self.process_ca(anchor, &cert, uri, 0);
+            }
+            Err(err) => {
+                self.report.reject(uri.clone(), err.to_string());
+            }
+        }
+    }
+
+    /// Validates the publication point of a CA.
+    fn process_ca(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        ca_uri: &uri::Rsync,
+        depth: usize,
+    ) {
+        if self.cancelled() {
+            return
+        }
+        let (mft_uri, repository) = match (
+            ca.rpki_manifest(), ca.ca_repository()
+        ) {
+            (Some(mft), Some(repository)) => {
+                (mft.clone(), repository.clone())
+            }
+            _ => {
+                self.report.reject(
+                    ca_uri.clone(), "missing manifest or repository URI"
+                );
+                return
+            }
+        };
+        if let Err(err) = self.engine.options.limits.check_depth(depth) {
+            self.report.reject(mft_uri, err.to_string());
+            return
+        }
+        if !self.visited.insert(mft_uri.to_string()) {
+            self.report.reject(mft_uri, "loop in CA tree");
+            return
+        }
+        self.engine.progress.ca_validated(ca_uri.as_str());
+        #[cfg(feature = "fetch")]
+        self.check_dead(ca, &mft_uri, &repository);
+
+        let mut stale = false;
+        let point = match self.load_point(
+            ca, &mft_uri, &repository, self.engine.store, &mut stale
+        ) {
+            Ok(point) => {
+                self.report.set_point_outcome(
+                    repository, PointOutcome::Current
+                );
+                point
+            }
+            Err(failure) => {
+                match self.fall_back(
+                    ca, &mft_uri, repository, failure, &mut stale
+                ) {
+                    Some(point) => point,
+                    None => return
+                }
+            }
+        };
+
+        let PublicationPoint { mut crl, crl_uri, objects } = point;
+        let mut children = 0;
+        for (uri, data, hash) in objects {
+            if self.cancelled() {
+                return
+            }
+            if uri.ends_with(".cer") {
+                children += 1;
+                if let Err(err) = self.engine.options.limits.check_children(
+                    children
+                ) {
+                    self.report.reject(uri, err.to_string());
+                    continue
+                }
+            }
+            if !self.check_before(anchor, ca, &uri, &data) {
+                continue
+            }
+            if uri.ends_with(".cer") {
+                self.process_cer(
+                    anchor, ca, &mut crl, uri, data, &hash, depth
+                )
+            }
+            else if uri.ends_with(".roa") {
+                self.process_roa(anchor, ca, &mut crl, uri, data, &hash)
+            }
+            else if uri.ends_with(".asa") {
+                self.process_aspa(anchor, ca, &mut crl, uri, data, &hash)
+            }
+            else if uri.ends_with(".gbr") {
+                self.process_gbr(anchor, ca, &mut crl, uri, data)
+            }
+            else if uri.ends_with(".tak") {
+                self.process_tak(anchor, ca, &mut crl, uri, data, depth)
+            }
+        }
+        for warning in crl.take_warnings() {
+            self.warn_once(&crl_uri, warning.to_string())
+        }
+    }
+
+    /// Records if the repositories of a CA are dead.
+    #[cfg(feature = "fetch")]
+    fn check_dead(
+        &mut self,
+        ca: &ResourceCert,
+        mft_uri: &uri::Rsync,
+        repository: &uri::Rsync,
+    ) {
+        let dead = match self.engine.dead {
+            Some(dead) => dead,
+            None => return
+        };
+        let notify = ca.rpki_notify().map(uri::Https::as_str);
+        for key in notify.into_iter().chain(Some(repository.module())) {
+            let is_dead = dead.status(key).map(|health| {
+                health.is_dead(*self.now)
+            }).unwrap_or(false);
+            if !is_dead {
+                continue
+            }
+            self.report.warn(
+                mft_uri.clone(),
+                format!("repository {} is dead, not refreshed", key)
+            );
+            if self.report.fetch_outcome(key).is_none() {
+                self.report.set_fetch_outcome(
+                    key, super::report::FetchOutcome::Skipped
+                );
+            }
+        }
+    }
+
+    /// Falls back to cached objects after a publication point failed.
+    ///
+    /// Records the decision in the report. Returns the cached content of
+    /// the publication point if it is to be used.
+    fn fall_back(
+        &mut self,
+        ca: &ResourceCert,
+        mft_uri: &uri::Rsync,
+        repository: uri::Rsync,
+        failure: PointFailure,
+        stale: &mut bool,
+    ) -> Option<PublicationPoint<'a>> {
+        let fallback = match self.engine.fallback {
+            Some(fallback) => fallback,
+            None => return self.fail_point(repository, failure, None),
+        };
+        let decision = fallback.good.fallback(&repository, *self.now);
+        if !decision.is_cached() {
+            return self.fail_point(repository, failure, Some(decision))
+        }
+        let point = self.load_point(
+            ca, mft_uri, &repository, fallback.store, stale
+        );
+        match point {
+            Ok(point) => {
+                self.report.warn(
+                    failure.uri.clone(),
+                    format!(
+                        "rejected, using cached objects: {}", failure.reason
+                    )
+                );
+                self.report.flag_subtree(repository.clone(), decision.into());
+                self.report.set_point_outcome(
+                    repository, PointOutcome::Cached(failure.to_string())
+                );
+                Some(point)
+            }
+            Err(cached) => {
+                self.report.warn(
+                    cached.uri.clone(),
+                    format!("cached object rejected: {}", cached.reason)
+                );
+                self.fail_point(
+                    repository, failure, Some(Fallback::Unavailable)
+                )
+            }
+        }
+    }
+
+    /// Records that a publication point failed.
+    ///
+    /// The object that caused the failure is rejected for its reason, all
+    /// other objects listed on the manifest because of the failure. The
+    /// subtree is flagged with `fallback` if there is a fallback store.
+    fn fail_point(
+        &mut self,
+        repository: uri::Rsync,
+        failure: PointFailure,
+        fallback: Option<Fallback>,
+    ) -> Option<PublicationPoint<'a>> {
+        let reason = failure.to_string();
+        for uri in failure.listed {
+            if uri != failure.uri {
+                self.report.reject(
+                    uri, format!("publication point failed: {}", reason)
+                );
+            }
+        }
+        self.report.reject(failure.uri, failure.reason);
+        self.report.flag_subtree(
+            repository.clone(),
+            fallback.map(Into::into).unwrap_or(SubtreeFlag::Unavailable)
+        );
+        self.report.set_point_outcome(
+            repository, PointOutcome::Failed(reason)
+        );
+        None
+    }
+
+    /// Loads the content of a publication point from a store.
+    ///
+    /// Following RFC 9286, the manifest and CRL have to be valid and all
+    /// files listed on the manifest have to be present with matching
+    /// hashes. Otherwise the publication point as a whole has failed and
+    /// the first problem found is returned.
+    ///
+    /// If the manifest is stale and `stale` is still `false`, emits an
+    /// [`Event::ManifestStale`] and sets `stale` so the event is only
+    /// emitted once even if the point is loaded from several stores.
+    fn load_point(
+        &mut self,
+        ca: &ResourceCert,
+        mft_uri: &uri::Rsync,
+        repository: &uri::Rsync,
+        store: &'a dyn AnyStore,
+        stale: &mut bool,
+    ) -> Result<PublicationPoint<'a>, PointFailure> {
+        let data = self.load_from(
+            store, mft_uri, "manifest not found"
+        ).map_err(|err| PointFailure::new(mft_uri, err))?;
+        let manifest = Manifest::decode(data, self.strict()).map_err(|err| {
+            PointFailure::new(mft_uri, err.to_string())
+        })?;
+        self.engine.options.limits.check_manifest_entries(
+            manifest.content().len()
+        ).map_err(|err| PointFailure::new(mft_uri, err.to_string()))?;
+        let listed: Vec<_> = manifest.content().iter().filter_map(|item| {
+            repository.join(item.file().as_ref()).ok()
+        }).filter(|uri| !uri.ends_with(".mft")).collect();
+        let fail = |uri: &uri::Rsync, reason: &str| PointFailure {
+            uri: uri.clone(), reason: reason.into(), listed: listed.clone()
+        };
+        if !*stale && manifest.content().is_stale_at(self.now) {
+            *stale = true;
+            self.engine.events.emit(Event::ManifestStale {
+                uri: mft_uri.to_string(),
+                next_update: *manifest.content().next_update(),
+            });
+        }
+        let (ee, content, warnings) = manifest.validate_with_options_at(
+            ca, &self.engine.options, self.now
+        ).map_err(|err| fail(mft_uri, &err.to_string()))?;
+        let mut crl = CrlCheck::new(store);
+        crl.set_stale_grace(self.engine.options.stale_crl_grace);
+        crl.check_at(&ee, ca, self.now).map_err(|err| {
+            fail(mft_uri, &err.to_string())
+        })?;
+
+        let alg = content.file_hash_alg();
+        let mut crl_listed = false;
+        let mut objects = Vec::new();
+        for item in content.iter() {
+            let (file, hash) = item.into_pair();
+            let uri = match repository.join(file.as_ref()) {
+                Ok(uri) => uri,
+                Err(_) => return Err(fail(mft_uri, "invalid file name"))
+            };
+            if uri.ends_with(".mft") {
+                continue
+            }
+            let hash = ManifestHash::new(hash, alg);
+            let data = self.load_from(
+                store, &uri, "listed on manifest but missing"
+            ).map_err(|err| fail(&uri, &err))?;
+            if hash.verify(&data).is_err() {
+                return Err(fail(&uri, "hash does not match manifest"))
+            }
+            if uri.ends_with(".crl") {
+                crl_listed |= ee.crl_uri() == Some(&uri);
+                continue
+            }
+            objects.push((uri, data, hash));
+        }
+        let crl_uri = match ee.crl_uri() {
+            Some(uri) if crl_listed => uri.clone(),
+            _ => return Err(fail(mft_uri, "CRL not listed on manifest"))
+        };
+        self.report.accept(crl_uri.clone());
+        self.report.accept(mft_uri.clone());
+        for warning in warnings {
+            self.warn_once(mft_uri, warning.to_string())
+        }
+        for warning in crl.take_warnings() {
+            self.warn_once(&crl_uri, warning.to_string())
+        }
+        Ok(PublicationPoint { crl, crl_uri, objects })
+    }
+
+    /// Records a warning for an object unless it has been recorded before.
+    ///
+    /// The CRL check produces a grace warning for every object checked
+    /// against a stale CRL. It only needs to be reported once.
+    fn warn_once(&mut self, uri: &uri::Rsync, warning: String) {
+        if !self.report.warnings(uri).contains(&warning) {
+            self.report.warn(uri.clone(), warning)
+        }
+    }
+
+    /// Runs the pre-validation hooks for an object.
+    ///
+    /// Records the warnings of the hooks or rejects the object. Returns
+    /// whether the object may be validated.
+    fn check_before(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        uri: &uri::Rsync,
+        data: &[u8],
+    ) -> bool {
+        if self.engine.hooks.is_empty() {
+            return true
+        }
+        let res = self.engine.hooks.before(
+            &HookObject::new(anchor.name(), ca, uri, data)
+        );
+        self.apply_hooks(uri, res)
+    }
+
+    /// Runs the post-validation hooks for an object.
+    ///
+    /// Records the warnings of the hooks or rejects the object. Returns
+    /// whether the object may be accepted.
+    fn check_after(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        uri: &uri::Rsync,
+        data: &[u8],
+        payload: &[Payload],
+    ) -> bool {
+        if self.engine.hooks.is_empty() {
+            return true
+        }
+        let res = self.engine.hooks.after(
+            &HookObject::new(anchor.name(), ca, uri, data), payload
+        );
+        self.apply_hooks(uri, res)
+    }
+
+    /// Applies the combined verdict of the hooks to an object.
+    fn apply_hooks(
+        &mut self, uri: &uri::Rsync, res: Result<Vec<String>, String>
+    ) -> bool {
+        match res {
+            Ok(warnings) => {
+                for warning in warnings {
+                    self.report.warn(uri.clone(), warning)
+                }
+                true
+            }
+            Err(reason) => {
+                self.report.reject(uri.clone(), reason);
+                false
+            }
+        }
+    }
+
+    /// Looks up a signed object in the validation cache.
+    ///
+    /// Returns whether the object has been validated before and, if there
+    /// is a cache, the key to add the object under once it has been
+    /// validated.
+    fn lookup(
+        &self, ca: &ResourceCert, data: &[u8]
+    ) -> (bool, Option<CacheKey>) {
+        let cache = match self.engine.cache {
+            Some(cache) => cache,
+            None => return (false, None)
+        };
+        let key = CacheKey::new(data, ca, &self.engine.options);
+        (cache.get_at(&key, self.now).is_some(), Some(key))
+    }
+
+    /// Adds a validated signed object to the validation cache.
+    fn add_to_cache(&self, key: Option<CacheKey>) {
+        if let (Some(cache), Some(key)) = (self.engine.cache, key) {
+            cache.insert_at(key, (), self.now)
+        }
+    }
+
+    /// Checks the EE certificate of a signed object found in the cache.
+    ///
+    /// Only the checks that can change over time are performed: whether
+    /// the certificate is valid now, possibly thanks to the grace period
+    /// for certificates not yet valid, and whether it has been revoked.
+    /// Returns the warning if the grace period was needed.
+    fn check_cached(
+        &self,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        ee: &Cert,
+    ) -> Result<Option<GraceWarning>, String> {
+        let (now, warning) = self.engine.options.validation_time(
+            ee.validity().not_before(), self.now
+        );
+        ee.validity().verify_at(now).map_err(|err| err.to_string())?;
+        crl.check_at(ee, ca, self.now).map_err(|err| err.to_string())?;
+        Ok(warning)
+    }
+
+    /// Records the grace warning for a signed object if there is one.
+    fn grace_warning(
+        &mut self, uri: &uri::Rsync, warning: Option<GraceWarning>
+    ) {
+        if let Some(warning) = warning {
+            self.report.warn(uri.clone(), warning.to_string())
+        }
+    }
+
+    /// Returns the provenance for an object if it is to be recorded.
+    fn provenance(
+        &self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        uri: &uri::Rsync,
+        hash: &ManifestHash,
+        validity: Validity,
+    ) -> Option<Arc<Provenance>> {
+        if !self.engine.provenance {
+            return None
+        }
+        let ca_uri = self.report.coverage().cert(
+            &ca.subject_key_identifier()
+        )?.uri().clone();
+        Some(Arc::new(Provenance::new(
+            anchor.info().clone(), ca_uri, uri.clone(), hash.clone(),
+            validity
+        )))
+    }
+
+    /// Validates a certificate published by a CA.
+    #[allow(clippy::too_many_arguments)]
+    fn process_cer(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        uri: uri::Rsync,
+        data: Bytes,
+        hash: &ManifestHash,
+        depth: usize,
+    ) {
+        let cert = match Cert::decode(data.clone()) {
+            Ok(cert) => cert,
+            Err(err) => {
+                self.report.reject(uri, err.to_string());
+                return
+            }
+        };
+        if let Err(err) = crl.check_at(&cert, ca, self.now) {
+            self.report.reject(uri, err.to_string());
+            return
+        }
+        if cert.is_ca() {
+            match cert.validate_ca_at(ca, self.strict(), self.now) {
+                Ok(cert) => {
+                    if !self.check_after(anchor, ca, &uri, &data, &[]) {
+                        return
+                    }
+                    self.report.accept(uri.clone());
+                    self.report.contacts_mut().add_ca(uri.clone(), &cert);
+                    self.report.coverage_mut().add_ca(uri.clone(), &cert);
+                    self.process_ca(anchor, &cert, &uri, depth + 1);
+                }
+                Err(err) => self.report.reject(uri, err.to_string()),
+            }
+        }
+        else {
+            match cert.validate_router_keys_at(ca, self.strict(), self.now) {
+                Ok(keys) => {
+                    let payload: Vec<_> = keys.into_iter().map(
+                        Payload::from
+                    ).collect();
+                    if !self.check_after(anchor, ca, &uri, &data, &payload) {
+                        return
+                    }
+                    let source = self.provenance(
+                        anchor, ca, &uri, hash, cert.validity()
+                    );
+                    self.report.accept(uri);
+                    for key in payload {
+                        if let Some(source) = source.as_ref() {
+                            self.report.add_provenance(
+                                key.clone(), source.clone()
+                            );
+                        }
+                        self.report.add_payload(key);
+                    }
+                }
+                Err(err) => self.report.reject(uri, err.to_string()),
+            }
+        }
+    }
+
+    /// Validates a ROA published by a CA.
+    fn process_roa(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        uri: uri::Rsync,
+        data: Bytes,
+        hash: &ManifestHash,
+    ) {
+        let roa = match Roa::decode(data.clone(), self.strict()) {
+            Ok(roa) => roa,
+            Err(err) => {
+                self.report.reject(uri, err.to_string());
+                return
+            }
+        };
+        let now = self.now;
+        let options = self.engine.options;
+        let res = match self.lookup(ca, &data) {
+            (true, _) => {
+                self.check_cached(ca, crl, roa.cert()).map(|warning| {
+                    (roa.cert().validity(), roa.content().clone(), warning)
+                })
+            }
+            (false, key) => {
+                roa.process_with_options_at(ca, &options, now, |ee| {
+                    crl.check_at(ee, ca, now).map_err(Into::into)
+                }).map(|(cert, roa, warning)| {
+                    self.add_to_cache(key);
+                    (cert.validity(), roa, warning)
+                }).map_err(|err| err.to_string())
+            }
+        };
+        match res {
+            Ok((validity, roa, warning)) => {
+                let origins: Vec<_> = anchor.route_origins(&roa).collect();
+                let payload: Vec<_> = origins.iter().map(|origin| {
+                    Payload::from(origin.origin)
+                }).collect();
+                if !self.check_after(anchor, ca, &uri, &data, &payload) {
+                    return
+                }
+                self.report.coverage_mut().add_roa(uri.clone(), ca, &roa);
+                let source = self.provenance(
+                    anchor, ca, &uri, hash, validity
+                );
+                self.grace_warning(&uri, warning);
+                self.report.accept(uri);
+                for origin in origins {
+                    if let Some(source) = source.as_ref() {
+                        self.report.add_provenance(
+                            origin.origin, source.clone()
+                        );
+                    }
+                    self.report.add_origin(origin);
+                }
+            }
+            Err(err) => self.report.reject(uri, err),
+        }
+    }
+
+    /// Validates a Ghostbusters record published by a CA.
+    fn process_gbr(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        uri: uri::Rsync,
+        data: Bytes,
+    ) {
+        let gbr = match Ghostbusters::decode(data.clone(), self.strict()) {
+            Ok(gbr) => gbr,
+            Err(err) => {
+                self.report.reject(uri, err.to_string());
+                return
+            }
+        };
+        let now = self.now;
+        let options = self.engine.options;
+        match gbr.process_with_options_at(ca, &options, now, |ee| {
+            crl.check_at(ee, ca, now).map_err(Into::into)
+        }) {
+            Ok((_, record, warning)) => {
+                if !self.check_after(anchor, ca, &uri, &data, &[]) {
+                    return
+                }
+                self.grace_warning(&uri, warning);
+                self.report.accept(uri);
+                self.report.contacts_mut().add_record(ca, record);
+            }
+            Err(err) => self.report.reject(uri, err.to_string()),
+        }
+    }
+
+    /// Validates a TAK object published by a CA.
+    ///
+    /// Only the trust anchor itself may publish TAK objects.
+    fn process_tak(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        uri: uri::Rsync,
+        data: Bytes,
+        depth: usize,
+    ) {
+        if depth > 0 {
+            self.report.reject(uri, "TAK object not issued by trust anchor");
+            return
+        }
+        let tak = match Tak::decode(data.clone(), self.strict()) {
+            Ok(tak) => tak,
+            Err(err) => {
+                self.report.reject(uri, err.to_string());
+                return
+            }
+        };
+        let now = self.now;
+        let options = self.engine.options;
+        match tak.process_with_options_at(ca, &options, now, |ee| {
+            crl.check_at(ee, ca, now).map_err(Into::into)
+        }) {
+            Ok((_, keys, warning)) => {
+                if !self.check_after(anchor, ca, &uri, &data, &[]) {
+                    return
+                }
+                self.grace_warning(&uri, warning);
+                self.report.accept(uri);
+                self.report.set_trust_anchor_keys(anchor.name(), keys);
+            }
+            Err(err) => self.report.reject(uri, err.to_string()),
+        }
+    }
+
+    /// Validates an ASPA published by a CA.
+    fn process_aspa(
+        &mut self,
+        anchor: &TrustAnchor,
+        ca: &ResourceCert,
+        crl: &mut CrlCheck<'a, dyn AnyStore + 'a>,
+        uri: uri::Rsync,
+        data: Bytes,
+        hash: &ManifestHash,
+    ) {
+        let aspa = match Aspa::decode(data.clone(), self.strict()) {
+            Ok(aspa) => aspa,
+            Err(err) => {
+                self.report.reject(uri, err.to_string());
+                return
+            }
+        };
+        let now = self.now;
+        let options = self.engine.options;
+        let res = match self.lookup(ca, &data) {
+            (true, _) => {
+                self.check_cached(ca, crl, aspa.cert()).map(|warning| {
+                    (aspa.cert().validity(), aspa.content().clone(), warning)
+                })
+            }
+            (false, key) => {
+                aspa.process_with_options_at(ca, &options, now, |ee| {
+                    crl.check_at(ee, ca, now).map_err(Into::into)
+                }).map(|(cert, aspa, warning)| {
+                    self.add_to_cache(key);
+                    (cert.validity(), aspa, warning)
+                }).map_err(|err| err.to_string())
+            }
+        };
+        match res {
+            Ok((validity, aspa, warning)) => {
+                match ProviderAsns::try_from_iter(
+                    aspa.provider_as_set().iter()
+                ) {
+                    Ok(providers) => {
+                        let payload = Payload::from(
+                            AspaPayload::new(aspa.customer_as(), providers)
+                        );
+                        if !self.check_after(
+                            anchor, ca, &uri, &data,
+                            std::slice::from_ref(&payload)
+                        ) {
+                            return
+                        }
+                        self.report.coverage_mut().add_aspa(
+                            uri.clone(), ca, &aspa
+                        );
+                        let source = self.provenance(
+                            anchor, ca, &uri, hash, validity
+                        );
+                        self.grace_warning(&uri, warning);
+                        self.report.accept(uri);
+                        if let Some(source) = source {
+                            self.report.add_provenance(
+                                payload.clone(), source
+                            );
+                        }
+                        self.report.add_payload(payload);
+                    }
+                    Err(err) => self.report.reject(uri, err.to_string()),
+                }
+            }
+            Err(err) => self.report.reject(uri, err),
+        }
+    }
+}
+
+
+//------------ AnyStore ------------------------------------------------------
+
+/// An object store of any type.
+///
+/// This allows taking objects from the engine’s store and the fallback
+/// store alike even if they are of different types. It is implemented
+/// for all object stores.
+trait AnyStore {
+    fn any_get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError>;
+
+    fn any_insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), StoreError>;
+
+    fn any_remove(&mut self, uri: &uri::Rsync) -> Result<bool, StoreError>;
+
+    fn any_list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError>;
+
+    fn any_size(&self, uri: &uri::Rsync) -> Result<Option<u64>, StoreError>;
+}
+
+impl<S: ObjectStore> AnyStore for S {
+    fn any_get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, StoreError> {
+        self.get(uri).map_err(StoreError::new)
+    }
+
+    fn any_insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), StoreError> {
+        self.insert(uri, data).map_err(StoreError::new)
+    }
+
+    fn any_remove(&mut self, uri: &uri::Rsync) -> Result<bool, StoreError> {
+        self.remove(uri).map_err(StoreError::new)
+    }
+
+    fn any_list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, StoreError> {
+        self.list(base).map_err(StoreError::new)
+    }
+
+    fn any_size(&self, uri: &uri::Rsync) -> Result<Option<u64>, StoreError> {
+        self.size(uri).map_err(StoreError::new)
+    }
+}
+
+impl<'a> ObjectStore for dyn AnyStore + 'a {
+    type Error = StoreError;
+
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error> {
+        self.any_get(uri)
+    }
+
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error> {
+        self.any_insert(uri, data)
+    }
+
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        self.any_remove(uri)
+    }
+
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error> {
+        self.any_list(base)
+    }
+
+    fn size(&self, uri: &uri::Rsync) -> Result<Option<u64>, Self::Error> {
+        self.any_size(uri)
+    }
+}
+
+
+//------------ StoreError ----------------------------------------------------
+
+/// The error of an object store of any type.
+#[derive(Debug)]
+struct StoreError(Box<dyn error::Error + Send + Sync>);
+
+impl StoreError {
+    fn new(err: impl error::Error + Send + Sync + 'static) -> Self {
+        StoreError(Box::new(err))
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for StoreError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(all(test, feature = "softkeys", feature = "ca"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::ca::pubpoint::PublicationPointBuilder;
+    use crate::crypto::{PublicKeyFormat, Signer};
+    use crate::crypto::softsigner::{KeyId, OpenSslSigner};
+    use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+    use crate::repository::gbr::{GhostbustersBuilder, GhostbustersRecord};
+    use crate::repository::names;
+    use crate::repository::report::ObjectOutcome;
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::roa::RoaBuilder;
+    use crate::repository::sigobj::SignedObjectBuilder;
+    use crate::repository::tal::Tal;
+    use crate::repository::x509::Validity;
+    use crate::resources::addr::MaxLenPrefix;
+    use crate::rtr::payload::RouteOrigin;
+    use crate::store::MemoryStore;
+    use crate::store::archive::ArchiveStore;
+    use crate::util::base64;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn make_roa(
+        signer: &OpenSslSigner, key: &KeyId, serial: u64,
+        addr: [u8; 4], len: u8, max_len: Option<u8>, name: &str,
+    ) -> Bytes {
+        let key_id = signer.get_key_info(key).unwrap().key_identifier();
+        let mut roa = RoaBuilder::new(64496.into());
+        roa.push_v4_addr(addr.into(), len, max_len);
+        let roa = roa.finalize(
+            SignedObjectBuilder::new(
+                serial.into(), Validity::from_secs(86400),
+                uri("rsync://example.com/ca/").join(
+                    names::crl_name(&key_id).as_bytes()
+                ).unwrap(),
+                uri("rsync://example.com/ta/ca.cer"),
+                uri("rsync://example.com/ca/").join(name.as_bytes()).unwrap(),
+            ),
+            signer, key
+        ).unwrap();
+        Bytes::copy_from_slice(roa.as_bytes())
+    }
+
+    fn make_gbr(signer: &OpenSslSigner, key: &KeyId) -> Bytes {
+        let key_id = signer.get_key_info(key).unwrap().key_identifier();
+        let mut record = GhostbustersRecord::new("Example NOC");
+        record.emails.push("noc@example.com".into());
+        let gbr = GhostbustersBuilder::new(record).finalize(
+            SignedObjectBuilder::new(
+                13u64.into(), Validity::from_secs(86400),
+                uri("rsync://example.com/ca/").join(
+                    names::crl_name(&key_id).as_bytes()
+                ).unwrap(),
+                uri("rsync://example.com/ta/ca.cer"),
+                uri("rsync://example.com/ca/noc.gbr"),
+            ),
+            signer, key
+        ).unwrap();
+        Bytes::copy_from_slice(gbr.as_bytes())
+    }
+
+    /// Creates a repository with a TA and one CA below it.
+    ///
+    /// The CA publishes one good and one over-claiming ROA as well as a
+    /// Ghostbusters record.
+    fn make_repository() -> (MemoryStore, TalSet) {
+        let (mut stores, anchors) = make_repositories(&[
+            Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 8)
+        ]);
+        (stores.remove(0), anchors)
+    }
+
+    /// Creates repositories that only differ in the CA’s IPv4 resources.
+    ///
+    /// One store is returned for each element of `ca_v4`. All of them use
+    /// the same keys and trust anchor.
+    fn make_repositories(ca_v4: &[Prefix]) -> (Vec<MemoryStore>, TalSet) {
+        make_repositories_with(ca_v4, |_, _| {
+            PublicationPointBuilder::new(
+                uri("rsync://example.com/ca/"),
+                uri("rsync://example.com/ta/ca.cer"), 1u64.into(),
+                Time::now(), Time::tomorrow()
+            )
+        })
+    }
+
+    /// Creates repositories with a custom publication point for the CA.
+    ///
+    /// The builder for the CA’s publication point is created by `ca_point`
+    /// which receives the signer and the CA’s key. The usual objects are
+    /// added to it afterwards.
+    fn make_repositories_with(
+        ca_v4: &[Prefix],
+        ca_point: impl FnOnce(
+            &OpenSslSigner, &KeyId
+        ) -> PublicationPointBuilder,
+    ) -> (Vec<MemoryStore>, TalSet) {
+        let signer = OpenSslSigner::new();
+        let ta_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let ta_pub = signer.get_key_info(&ta_key).unwrap();
+        let ca_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let ca_pub = signer.get_key_info(&ca_key).unwrap();
+        let ta_uri = uri("rsync://example.com/tal/ta.cer");
+        let ta_base = uri("rsync://example.com/ta/");
+        let ca_base = uri("rsync://example.com/ca/");
+        let mut store = MemoryStore::new();
+
+        let mut cert = TbsCert::new(
+            1u64.into(), ta_pub.to_subject_name(),
+            Validity::from_secs(86400), None, ta_pub.clone(), KeyUsage::Ca,
+            Overclaim::Trim
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(ta_base.clone()));
+        cert.set_rpki_manifest(Some(ta_base.join(
+            names::manifest_name(&ta_pub.key_identifier()).as_bytes()
+        ).unwrap()));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&signer, &ta_key).unwrap();
+        store.insert(
+            ta_uri.clone(), Bytes::copy_from_slice(cert.as_bytes())
+        ).unwrap();
+
+        let now = Time::now();
+        let mut point = ca_point(&signer, &ca_key);
+        point.set_ee_serial(Some(10u64.into()));
+        point.add_object(
+            "good.roa",
+            make_roa(
+                &signer, &ca_key, 11, [10, 0, 0, 0], 16, Some(24),
+                "good.roa"
+            )
+        ).unwrap();
+        point.add_object(
+            "bad.roa",
+            make_roa(
+                &signer, &ca_key, 12, [192, 0, 2, 0], 24, None, "bad.roa"
+            )
+        ).unwrap();
+        point.add_object("noc.gbr", make_gbr(&signer, &ca_key)).unwrap();
+        let point = point.build(&signer, &ca_key).unwrap();
+        for (uri, data) in point.iter() {
+            store.insert(uri.clone(), data.clone()).unwrap();
+        }
+
+        let mut stores = Vec::new();
+        for (index, prefix) in ca_v4.iter().enumerate() {
+            let mut ca_store = store.clone();
+            let mut cert = TbsCert::new(
+                (2 + index as u64).into(), ta_pub.to_subject_name(),
+                Validity::from_secs(86400), Some(ca_pub.to_subject_name()),
+                ca_pub.clone(), KeyUsage::Ca, Overclaim::Refuse
+            );
+            cert.set_basic_ca(Some(true));
+            cert.set_authority_key_identifier(
+                Some(ta_pub.key_identifier())
+            );
+            cert.set_crl_uri(Some(ta_base.join(
+                names::crl_name(&ta_pub.key_identifier()).as_bytes()
+            ).unwrap()));
+            cert.set_ca_issuer(Some(ta_uri.clone()));
+            cert.set_ca_repository(Some(ca_base.clone()));
+            cert.set_rpki_manifest(Some(ca_base.join(
+                names::manifest_name(&ca_pub.key_identifier()).as_bytes()
+            ).unwrap()));
+            cert.build_v4_resource_blocks(|b| b.push(*prefix));
+            cert.build_as_resource_blocks(|b| {
+                b.push(Asn::from_u32(64496))
+            });
+            let cert = cert.into_cert(&signer, &ta_key).unwrap();
+
+            let mut point = PublicationPointBuilder::new(
+                ta_base.clone(), ta_uri.clone(), 1u64.into(), now,
+                Time::tomorrow()
+            );
+            point.set_ee_serial(Some(10u64.into()));
+            point.add_object(
+                "ca.cer", Bytes::copy_from_slice(cert.as_bytes())
+            ).unwrap();
+            let point = point.build(&signer, &ta_key).unwrap();
+            for (uri, data) in point.iter() {
+                ca_store.insert(uri.clone(), data.clone()).unwrap();
+            }
+            stores.push(ca_store);
+        }
+
+        let tal = format!(
+            "{}\n\n{}\n",
+            ta_uri, base64::Xml.encode(&ta_pub.to_info_bytes())
+        );
+        let tal = Tal::read_named(
+            "test".into(), &mut tal.as_bytes()
+        ).unwrap();
+        let mut anchors = TalSet::new();
+        anchors.insert(tal);
+        (stores, anchors)
+    }
+
+    #[test]
+    fn validate() {
+        let (store, anchors) = make_repository();
+        let engine = Engine::new(&store, &anchors);
+        let report = engine.validate();
+        let payload: Vec<_> = report.payload().cloned().collect();
+        assert_eq!(
+            payload,
+            [Payload::from(RouteOrigin::new(
+                MaxLenPrefix::from_str("10.0.0.0/16-24").unwrap(),
+                Asn::from_u32(64496)
+            ))]
+        );
+        assert!(report.outcome(
+            &uri("rsync://example.com/ta/ca.cer")
+        ).unwrap().is_accepted());
+        assert!(report.outcome(
+            &uri("rsync://example.com/ca/good.roa")
+        ).unwrap().is_accepted());
+        assert!(!report.outcome(
+            &uri("rsync://example.com/ca/bad.roa")
+        ).unwrap().is_accepted());
+
+        // A week from now, everything has expired.
+        let report = engine.validate_at(Time::next_week());
+        assert_eq!(report.payload().count(), 0);
+        assert!(!report.outcome(
+            &uri("rsync://example.com/tal/ta.cer")
+        ).unwrap().is_accepted());
+
+        // Validating an archived state of the repository.
+        let start = Time::now();
+        let mut archive = ArchiveStore::new();
+        for (uri, data) in store.iter() {
+            archive.insert_at(uri.clone(), data.clone(), start.into());
+        }
+        let removed = start + chrono::Duration::hours(1);
+        archive.remove_at(
+            &uri("rsync://example.com/ca/good.roa"), removed.into()
+        );
+        let report = Engine::new(
+            &archive.snapshot_at(start.into()), &anchors
+        ).validate_at(start);
+        assert_eq!(report.payload().count(), 1);
+        let later = removed + chrono::Duration::minutes(1);
+        let report = Engine::new(
+            &archive.snapshot_at(later.into()), &anchors
+        ).validate_at(later);
+        assert_eq!(report.payload().count(), 0);
+
+        // A disabled trust anchor isn’t validated at all.
+        let mut anchors = anchors;
+        anchors.set_enabled("test", false);
+        let report = Engine::new(&store, &anchors).validate();
+        assert_eq!(report.object_count(), 0);
+    }
+
+    #[test]
+    fn events() {
+        let (store, anchors) = make_repository();
+        let events = EventBus::new();
+        let (_, rx) = events.channel();
+
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_events(events.clone());
+        engine.validate();
+        assert!(rx.try_recv().is_err());
+
+        let empty = MemoryStore::new();
+        let mut engine = Engine::new(&empty, &anchors);
+        engine.set_events(events);
+        engine.validate();
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Event::TaUnreachable {
+                tal: "test".into(),
+                uri: "rsync://example.com/tal/ta.cer".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn contacts() {
+        let (store, anchors) = make_repository();
+        let report = Engine::new(&store, &anchors).validate();
+        assert!(report.outcome(
+            &uri("rsync://example.com/ca/noc.gbr")
+        ).unwrap().is_accepted());
+        let contacts = report.contacts();
+        assert_eq!(contacts.len(), 2);
+
+        let found = contacts.for_prefix(
+            crate::resources::addr::Prefix::from_str("10.1.0.0/16").unwrap()
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].uri(), &uri("rsync://example.com/ta/ca.cer"));
+        assert_eq!(found[0].records()[0].full_name, "Example NOC");
+        assert_eq!(contacts.for_asn(Asn::from_u32(64496)).len(), 1);
+
+        // The TA covers everything but has no records.
+        assert!(contacts.for_asn(Asn::from_u32(64497)).is_empty());
+    }
+
+    #[test]
+    fn covering() {
+        let (store, anchors) = make_repository();
+        let report = Engine::new(&store, &anchors).validate();
+
+        let covering = report.find_covering(
+            crate::resources::addr::Prefix::from_str("10.0.0.0/24").unwrap()
+        );
+        assert_eq!(covering.chains().len(), 1);
+        assert_eq!(
+            covering.chains()[0].iter().map(|cert| {
+                cert.uri().clone()
+            }).collect::<Vec<_>>(),
+            [
+                uri("rsync://example.com/tal/ta.cer"),
+                uri("rsync://example.com/ta/ca.cer"),
+            ]
+        );
+        assert_eq!(covering.roas().len(), 1);
+        assert_eq!(
+            covering.roas()[0].uri(), &uri("rsync://example.com/ca/good.roa")
+        );
+        assert_eq!(
+            report.coverage().cert(covering.roas()[0].issuer()).map(|ca| {
+                ca.uri()
+            }),
+            Some(&uri("rsync://example.com/ta/ca.cer"))
+        );
+
+        // The rejected ROA doesn’t show up and the TA is the only holder.
+        let covering = report.find_covering(
+            crate::resources::addr::Prefix::from_str("192.0.2.0/24").unwrap()
+        );
+        assert_eq!(covering.chains().len(), 1);
+        assert_eq!(covering.chains()[0].len(), 1);
+        assert!(covering.roas().is_empty());
+
+        let covering = report.find_covering(Asn::from_u32(64496));
+        assert_eq!(covering.chains()[0].len(), 2);
+        assert_eq!(covering.roas().len(), 1);
+        assert!(covering.aspas().is_empty());
+    }
+
+    #[test]
+    fn delegation_tree() {
+        let (store, anchors) = make_repository();
+        let tree = Engine::new(&store, &anchors).validate().delegation_tree();
+        assert_eq!(tree.len(), 2);
+        let ta = &tree.roots[0];
+        assert_eq!(ta.uri, uri("rsync://example.com/tal/ta.cer"));
+        assert_eq!(ta.roas, 0);
+        let ca = &ta.children[0];
+        assert_eq!(ca.uri, uri("rsync://example.com/ta/ca.cer"));
+        assert_eq!(ca.ca_repository, Some(uri("rsync://example.com/ca/")));
+        assert_eq!(ca.ipv4, "10.0.0.0/8");
+        assert_eq!(ca.roas, 1);
+        assert!(ca.children.is_empty());
+    }
+
+    #[test]
+    fn provenance() {
+        let (store, anchors) = make_repository();
+        let origin = Payload::from(RouteOrigin::new(
+            MaxLenPrefix::from_str("10.0.0.0/16-24").unwrap(),
+            Asn::from_u32(64496)
+        ));
+
+        let mut engine = Engine::new(&store, &anchors);
+        let mut report = engine.validate();
+        let sources = report.provenance(&origin);
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].tal().name(), "test");
+        assert_eq!(
+            sources[0].ca_uri(), &uri("rsync://example.com/ta/ca.cer")
+        );
+        assert_eq!(
+            sources[0].object_uri(), &uri("rsync://example.com/ca/good.roa")
+        );
+        assert!(sources[0].hash().verify(
+            store.get(sources[0].object_uri()).unwrap().unwrap()
+        ).is_ok());
+        report.strip_provenance();
+        assert!(report.provenance(&origin).is_empty());
+
+        engine.set_provenance(false);
+        assert!(engine.validate().provenance(&origin).is_empty());
+    }
+
+    #[test]
+    fn failed_fetch() {
+        let (store, anchors) = make_repository();
+        let ca_base = uri("rsync://example.com/ca/");
+        let good = uri("rsync://example.com/ca/good.roa");
+
+        let report = Engine::new(&store, &anchors).validate();
+        assert!(report.point_outcome(&ca_base).unwrap().is_current());
+
+        // A missing file fails the whole publication point.
+        let mut broken = MemoryStore::new();
+        for (uri, data) in store.iter() {
+            if *uri != good {
+                broken.insert(uri.clone(), data.clone()).unwrap();
+            }
+        }
+        let report = Engine::new(&broken, &anchors).validate();
+        assert!(report.point_outcome(&ca_base).unwrap().is_failed());
+        assert_eq!(report.payload().count(), 0);
+        assert!(!report.outcome(&good).unwrap().is_accepted());
+        match report.outcome(&uri("rsync://example.com/ca/noc.gbr")) {
+            Some(ObjectOutcome::Rejected(reason)) => {
+                assert!(
+                    reason.starts_with("publication point failed"), "{}",
+                    reason
+                );
+            }
+            other => panic!("unexpected outcome {:?}", other)
+        }
+
+        // So does a hash mismatch.
+        broken.insert(good.clone(), Bytes::from_static(b"foo")).unwrap();
+        let report = Engine::new(&broken, &anchors).validate();
+        assert!(report.point_outcome(&ca_base).unwrap().is_failed());
+        assert_eq!(report.payload().count(), 0);
+
+        // Without a last known good refresh, there is no fallback.
+        let mut good_refresh = LastKnownGood::default();
+        let mut engine = Engine::new(&broken, &anchors);
+        engine.set_fallback(&store, &good_refresh);
+        let report = engine.validate();
+        assert!(report.point_outcome(&ca_base).unwrap().is_failed());
+        assert_eq!(
+            report.subtree_flag(&ca_base), Some(&SubtreeFlag::Unavailable)
+        );
+
+        // After a recent refresh, the cached objects are used instead.
+        good_refresh.refreshed(ca_base.clone(), chrono::Utc::now());
+        let mut engine = Engine::new(&broken, &anchors);
+        engine.set_fallback(&store, &good_refresh);
+        let report = engine.validate();
+        assert!(matches!(
+            report.point_outcome(&ca_base), Some(PointOutcome::Cached(_))
+        ));
+        assert!(matches!(
+            report.subtree_flag(&ca_base), Some(SubtreeFlag::Cached(_))
+        ));
+        assert!(report.outcome(&good).unwrap().is_accepted());
+        assert!(!report.warnings(&good).is_empty());
+        assert_eq!(report.payload().count(), 1);
+
+        // But not if the refresh is too long ago.
+        good_refresh.refreshed(
+            ca_base.clone(), chrono::Utc::now() - chrono::Duration::days(2)
+        );
+        let mut engine = Engine::new(&broken, &anchors);
+        engine.set_fallback(&store, &good_refresh);
+        let report = engine.validate();
+        assert!(report.point_outcome(&ca_base).unwrap().is_failed());
+        assert!(matches!(
+            report.subtree_flag(&ca_base), Some(SubtreeFlag::Expired(_))
+        ));
+        assert_eq!(report.payload().count(), 0);
+    }
+
+    #[test]
+    fn limits() {
+        use crate::repository::options::ValidationLimits;
+
+        let (store, anchors) = make_repository();
+        let ca_mft = store.iter().map(|(uri, _)| uri.clone()).find(|uri| {
+            uri.as_str().starts_with("rsync://example.com/ca/")
+                && uri.ends_with(".mft")
+        }).unwrap();
+        let validate = |limits: ValidationLimits| {
+            let mut engine = Engine::new(&store, &anchors);
+            engine.set_options(ValidationOptions {
+                limits, .. Default::default()
+            });
+            engine.validate()
+        };
+
+        let report = validate(ValidationLimits {
+            max_object_size: Some(100), .. Default::default()
+        });
+        assert!(!report.outcome(
+            &uri("rsync://example.com/tal/ta.cer")
+        ).unwrap().is_accepted());
+
+        let report = validate(ValidationLimits {
+            max_children: Some(0), .. Default::default()
+        });
+        assert!(!report.outcome(
+            &uri("rsync://example.com/ta/ca.cer")
+        ).unwrap().is_accepted());
+
+        let report = validate(ValidationLimits {
+            max_depth: Some(0), .. Default::default()
+        });
+        assert!(report.outcome(
+            &uri("rsync://example.com/ta/ca.cer")
+        ).unwrap().is_accepted());
+        assert!(!report.outcome(&ca_mft).unwrap().is_accepted());
+        assert_eq!(report.payload().count(), 0);
+
+        let report = validate(ValidationLimits {
+            max_manifest_entries: Some(2), .. Default::default()
+        });
+        assert!(!report.outcome(&ca_mft).unwrap().is_accepted());
+        assert_eq!(report.payload().count(), 0);
+    }
+
+    #[test]
+    fn size_before_load() {
+        use std::cell::RefCell;
+        use std::convert::Infallible;
+        use crate::repository::options::ValidationLimits;
+        use crate::repository::report::ObjectOutcome;
+
+        /// A store recording which objects were loaded.
+        struct Recording<'a> {
+            store: &'a MemoryStore,
+            loaded: RefCell<Vec<uri::Rsync>>,
+        }
+
+        impl<'a> ObjectStore for Recording<'a> {
+            type Error = Infallible;
+
+            fn get(
+                &self, uri: &uri::Rsync
+            ) -> Result<Option<Bytes>, Self::Error> {
+                self.loaded.borrow_mut().push(uri.clone());
+                self.store.get(uri)
+            }
+
+            fn insert(
+                &mut self, _: uri::Rsync, _: Bytes
+            ) -> Result<(), Self::Error> {
+                unreachable!()
+            }
+
+            fn remove(
+                &mut self, _: &uri::Rsync
+            ) -> Result<bool, Self::Error> {
+                unreachable!()
+            }
+
+            fn list(
+                &self, base: &uri::Rsync
+            ) -> Result<Vec<uri::Rsync>, Self::Error> {
+                self.store.list(base)
+            }
+
+            fn size(
+                &self, uri: &uri::Rsync
+            ) -> Result<Option<u64>, Self::Error> {
+                Ok(self.store.get(uri)?.map(|data| data.len() as u64))
+            }
+        }
+
+        let (store, anchors) = make_repository();
+        let ta_uri = uri("rsync://example.com/tal/ta.cer");
+        let ta_size = store.get(&ta_uri).unwrap().unwrap().len();
+        let recording = Recording {
+            store: &store, loaded: RefCell::new(Vec::new())
+        };
+        let mut engine = Engine::new(&recording, &anchors);
+        engine.set_options(ValidationOptions {
+            limits: ValidationLimits {
+                max_object_size: Some(100), .. Default::default()
+            },
+            .. Default::default()
+        });
+        let report = engine.validate();
+        assert_eq!(
+            report.outcome(&ta_uri),
+            Some(&ObjectOutcome::Rejected(format!(
+                "object size of {} octets exceeds limit of 100", ta_size
+            )))
+        );
+        assert!(recording.loaded.borrow().is_empty());
+    }
+
+    #[test]
+    fn hooks() {
+        use crate::repository::hook::HookVerdict;
+
+        struct Policy;
+
+        impl ObjectHook for Policy {
+            fn before(&self, object: &HookObject) -> HookVerdict {
+                if object.uri().ends_with(".gbr") {
+                    HookVerdict::Warn("contact published".into())
+                }
+                else {
+                    HookVerdict::Accept
+                }
+            }
+
+            fn after(
+                &self, _: &HookObject, payload: &[Payload]
+            ) -> HookVerdict {
+                let flagged = payload.iter().any(|item| {
+                    matches!(
+                        item, Payload::Origin(origin)
+                            if origin.asn == Asn::from_u32(64496)
+                    )
+                });
+                if flagged {
+                    HookVerdict::Reject("AS64496 not permitted".into())
+                }
+                else {
+                    HookVerdict::Accept
+                }
+            }
+        }
+
+        let (store, anchors) = make_repository();
+        let mut engine = Engine::new(&store, &anchors);
+        engine.add_hook(Policy);
+        let report = engine.validate();
+        let good = uri("rsync://example.com/ca/good.roa");
+        let gbr = uri("rsync://example.com/ca/noc.gbr");
+        assert_eq!(
+            report.outcome(&good),
+            Some(&ObjectOutcome::Rejected("AS64496 not permitted".into()))
+        );
+        assert_eq!(report.payload().count(), 0);
+        assert!(report.outcome(&gbr).unwrap().is_accepted());
+        assert_eq!(report.warnings(&gbr), ["contact published"]);
+        assert_eq!(report.all_warnings().count(), 1);
+    }
+
+    #[test]
+    fn cache() {
+        let (store, anchors) = make_repository();
+        let cache = ValidationCache::new();
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_cache(&cache);
+        let report = engine.validate();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (0, 2, 1));
+        assert_eq!(report.payload().count(), 1);
+
+        // The second run finds the good ROA in the cache. The bad one
+        // isn’t cached.
+        let cached = engine.validate();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 1));
+        assert!(cached.payload().eq(report.payload()));
+        let item = report.payload().next().unwrap();
+        assert_eq!(
+            cached.provenance(item)[0].validity(),
+            report.provenance(item)[0].validity()
+        );
+
+        // Different options don’t use the entry.
+        engine.set_options(ValidationOptions {
+            strict: true, .. Default::default()
+        });
+        engine.validate();
+        assert_eq!(cache.stats().misses, 5);
+    }
+
+    #[test]
+    fn cache_reissued_issuer() {
+        let (stores, anchors) = make_repositories(&[
+            Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 8),
+            Prefix::new(std::net::Ipv4Addr::new(10, 1, 0, 0), 16),
+        ]);
+        let good = uri("rsync://example.com/ca/good.roa");
+        let cache = ValidationCache::new();
+        let mut engine = Engine::new(&stores[0], &anchors);
+        engine.set_cache(&cache);
+        let report = engine.validate();
+        assert!(report.outcome(&good).unwrap().is_accepted());
+        assert_eq!(cache.stats().entries, 1);
+
+        // The CA is re-issued with the same key but without the ROA’s
+        // prefix. The cached result must not be used.
+        let mut engine = Engine::new(&stores[1], &anchors);
+        engine.set_cache(&cache);
+        let report = engine.validate();
+        assert!(!report.outcome(&good).unwrap().is_accepted());
+        assert_eq!(report.payload().count(), 0);
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn grace_warnings() {
+        use std::time::Duration;
+
+        // Manifest and CRL of the CA went stale an hour ago but the
+        // manifest’s EE certificate is still valid.
+        let (stores, anchors) = make_repositories_with(
+            &[Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 8)],
+            |_, _| {
+                let now = Time::now();
+                let mut point = PublicationPointBuilder::new(
+                    uri("rsync://example.com/ca/"),
+                    uri("rsync://example.com/ta/ca.cer"), 1u64.into(),
+                    now - chrono::Duration::days(1),
+                    now - chrono::Duration::hours(1)
+                );
+                point.set_ee_validity(Some(Validity::from_secs(86400)));
+                point
+            }
+        );
+        let good = uri("rsync://example.com/ca/good.roa");
+        let mut engine = Engine::new(&stores[0], &anchors);
+        let report = engine.validate();
+        assert!(!report.outcome(&good).unwrap().is_accepted());
+        assert_eq!(report.all_warnings().count(), 0);
+
+        // Within the grace periods, the objects are accepted and both
+        // manifest and CRL get exactly one warning.
+        engine.set_options(ValidationOptions {
+            stale_manifest_grace: Duration::from_secs(86400),
+            stale_crl_grace: Duration::from_secs(86400),
+            .. Default::default()
+        });
+        let report = engine.validate();
+        assert!(report.outcome(&good).unwrap().is_accepted());
+        let mut warnings: Vec<_> = report.all_warnings().filter(|item| {
+            item.0.as_str().starts_with("rsync://example.com/ca/")
+        }).collect();
+        warnings.sort_by_key(|item| item.0.ends_with(".mft"));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].0.ends_with(".crl"));
+        assert_eq!(warnings[0].1.len(), 1);
+        assert!(warnings[0].1[0].starts_with("CRL stale since"));
+        assert!(warnings[1].0.ends_with(".mft"));
+        assert_eq!(warnings[1].1.len(), 1);
+        assert!(warnings[1].1[0].starts_with("manifest stale since"));
+    }
+
+    #[test]
+    fn not_yet_valid_grace() {
+        use std::time::Duration;
+
+        // The CA also publishes a ROA that only becomes valid in ten
+        // minutes.
+        let (stores, anchors) = make_repositories_with(
+            &[Prefix::new(std::net::Ipv4Addr::new(10, 0, 0, 0), 8)],
+            |signer, key| {
+                let now = Time::now();
+                let key_id = signer.get_key_info(key).unwrap()
+                    .key_identifier();
+                let mut roa = RoaBuilder::new(64496.into());
+                roa.push_v4_addr([10, 1, 0, 0].into(), 16, None);
+                let roa = roa.finalize(
+                    SignedObjectBuilder::new(
+                        14u64.into(),
+                        Validity::new(
+                            now + chrono::Duration::minutes(10),
+                            now + chrono::Duration::days(1)
+                        ),
+                        uri("rsync://example.com/ca/").join(
+                            names::crl_name(&key_id).as_bytes()
+                        ).unwrap(),
+                        uri("rsync://example.com/ta/ca.cer"),
+                        uri("rsync://example.com/ca/early.roa"),
+                    ),
+                    signer, key
+                ).unwrap();
+                let mut point = PublicationPointBuilder::new(
+                    uri("rsync://example.com/ca/"),
+                    uri("rsync://example.com/ta/ca.cer"), 1u64.into(),
+                    now, Time::tomorrow()
+                );
+                point.add_object(
+                    "early.roa", Bytes::copy_from_slice(roa.as_bytes())
+                ).unwrap();
+                point
+            }
+        );
+        let early = uri("rsync://example.com/ca/early.roa");
+        let mut engine = Engine::new(&stores[0], &anchors);
+        let report = engine.validate();
+        assert!(!report.outcome(&early).unwrap().is_accepted());
+        assert_eq!(report.payload().count(), 1);
+
+        // Within the grace period, the ROA is accepted with a warning.
+        engine.set_options(ValidationOptions {
+            not_yet_valid_grace: Duration::from_secs(3600),
+            .. Default::default()
+        });
+        let report = engine.validate();
+        assert!(report.outcome(&early).unwrap().is_accepted());
+        assert_eq!(report.payload().count(), 2);
+        assert_eq!(report.warnings(&early).len(), 1);
+        assert!(
+            report.warnings(&early)[0].starts_with(
+                "certificate not valid before"
+            )
+        );
+
+        // The same goes for the ROA taken from the cache.
+        let cache = ValidationCache::new();
+        engine.set_cache(&cache);
+        engine.validate();
+        let report = engine.validate();
+        assert_eq!(cache.stats().hits, 2);
+        assert!(report.outcome(&early).unwrap().is_accepted());
+        assert_eq!(report.warnings(&early).len(), 1);
+    }
+
+    #[test]
+    fn metrics() {
+        use std::sync::Mutex;
+        use crate::metrics::Metrics;
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Metrics for Recorder {
+            fn counter(
+                &self, name: &str, labels: &[(&str, &str)], value: u64
+            ) {
+                self.0.lock().unwrap().push(
+                    format!("{} {:?} {}", name, labels, value)
+                );
+            }
+
+            fn histogram(
+                &self, name: &str, _: &[(&str, &str)], _: f64
+            ) {
+                self.0.lock().unwrap().push(name.into());
+            }
+        }
+
+        let (store, anchors) = make_repository();
+        let recorder = Arc::new(Recorder::default());
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_metrics(recorder.clone().into());
+        engine.validate();
+        let mut recorded = recorder.0.lock().unwrap().clone();
+        recorded.sort();
+        let objects = |kind: &str, result: &str, value: u64| {
+            format!(
+                "rpki_validation_objects_total \
+                    [(\"type\", \"{}\"), (\"result\", \"{}\")] {}",
+                kind, result, value
+            )
+        };
+        assert_eq!(
+            recorded,
+            [
+                objects("cer", "accepted", 2),
+                objects("crl", "accepted", 2),
+                objects("gbr", "accepted", 1),
+                objects("mft", "accepted", 2),
+                objects("roa", "accepted", 1),
+                objects("roa", "rejected", 1),
+                "rpki_validation_publication_points_total \
+                    [(\"outcome\", \"current\")] 2".into(),
+                "rpki_validation_run_duration_seconds".into(),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "fetch")]
+    fn dead_repositories() {
+        use crate::fetch::dead::DeadRepositories;
+        use crate::repository::report::FetchOutcome;
+
+        let (store, anchors) = make_repository();
+        let module = "rsync://example.com/ca/";
+        let now = chrono::Utc::now();
+        let mut dead = DeadRepositories::default();
+        dead.failed(module, now);
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_dead_repositories(&dead);
+        let report = engine.validate();
+        assert!(report.fetch_outcome(module).is_none());
+        assert_eq!(report.all_warnings().count(), 0);
+
+        // Once the repository is dead, it is still used but flagged.
+        dead.failed(module, now);
+        dead.failed(module, now);
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_dead_repositories(&dead);
+        let report = engine.validate();
+        assert_eq!(
+            report.fetch_outcome(module), Some(&FetchOutcome::Skipped)
+        );
+        let mft = report.all_warnings().map(|(uri, _)| uri).next().unwrap();
+        assert!(mft.as_str().starts_with(module));
+        assert!(mft.ends_with(".mft"));
+        assert_eq!(report.payload().count(), 1);
+    }
+
+    #[test]
+    fn mmap_store() {
+        use crate::store::fs::FsStore;
+
+        let (store, anchors) = make_repository();
+        let dir = std::env::temp_dir().join(format!(
+            "rpki-engine-mmap-test-{}", std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut fs_store = FsStore::new(&dir);
+        fs_store.set_mmap_threshold(Some(0));
+        for (uri, data) in store.iter() {
+            fs_store.insert(uri.clone(), data.clone()).unwrap();
+        }
+        let report = Engine::new(&store, &anchors).validate();
+        let mapped = Engine::new(&fs_store, &anchors).validate();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(mapped.payload().count(), 1);
+        assert!(mapped.payload().eq(report.payload()));
+        assert_eq!(mapped.object_count(), report.object_count());
+    }
+
+    #[test]
+    fn progress() {
+        use crate::progress::ProgressCounter;
+
+        let (store, anchors) = make_repository();
+        let counter = Arc::new(ProgressCounter::new());
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_progress(counter.clone().into());
+        engine.validate();
+        assert_eq!(counter.cas(), 2);
+    }
+
+    #[test]
+    fn max_length() {
+        use std::sync::Mutex;
+        use crate::metrics::{self, Metrics};
+        use crate::repository::maxlen::{MaxLengthAction, MaxLengthPolicy};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Metrics for Recorder {
+            fn counter(
+                &self, name: &str, labels: &[(&str, &str)], value: u64
+            ) {
+                if name == metrics::names::ROA_EXCESSIVE_MAX_LENGTH {
+                    self.0.lock().unwrap().push(
+                        format!("{:?} {}", labels, value)
+                    );
+                }
+            }
+        }
+
+        let (store, anchors) = make_repository();
+        let good = uri("rsync://example.com/ca/good.roa");
+        let recorder = Arc::new(Recorder::default());
+        let mut policy = MaxLengthPolicy::new();
+        policy.set_v4_span(4);
+        policy.set_metrics(recorder.clone().into());
+
+        // The default action only warns.
+        let mut engine = Engine::new(&store, &anchors);
+        engine.add_hook(policy.clone());
+        let report = engine.validate();
+        assert!(report.outcome(&good).unwrap().is_accepted());
+        assert_eq!(
+            report.warnings(&good),
+            ["excessive maxLength: 10.0.0.0/16-24"]
+        );
+        assert_eq!(report.payload().count(), 1);
+
+        // Rejecting drops the payload.
+        policy.set_action(MaxLengthAction::Reject);
+        let mut engine = Engine::new(&store, &anchors);
+        engine.add_hook(policy);
+        let report = engine.validate();
+        assert_eq!(
+            report.outcome(&good),
+            Some(&ObjectOutcome::Rejected(
+                "excessive maxLength: 10.0.0.0/16-24".into()
+            ))
+        );
+        assert_eq!(report.payload().count(), 0);
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "[(\"action\", \"warn\")] 1",
+                "[(\"action\", \"reject\")] 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel() {
+        use crate::repository::hook::HookVerdict;
+
+        struct Cancel(CancelToken);
+
+        impl ObjectHook for Cancel {
+            fn before(&self, _: &HookObject) -> HookVerdict {
+                self.0.cancel();
+                HookVerdict::Accept
+            }
+        }
+
+        let (store, anchors) = make_repository();
+        let ca = uri("rsync://example.com/ta/ca.cer");
+        let good = uri("rsync://example.com/ca/good.roa");
+        let report = Engine::new(&store, &anchors).validate();
+        assert!(!report.is_cancelled());
+
+        // The run stops after the first object.
+        let token = CancelToken::new();
+        let mut engine = Engine::new(&store, &anchors);
+        engine.set_cancel_token(token.clone());
+        engine.add_hook(Cancel(token.clone()));
+        let report = engine.validate();
+        assert!(report.is_cancelled());
+        assert!(report.outcome(&ca).unwrap().is_accepted());
+        assert!(report.outcome(&good).is_none());
+        assert_eq!(report.payload().count(), 0);
+
+        // A cancelled token stops the next run right away.
+        let report = engine.validate();
+        assert!(report.is_cancelled());
+        assert_eq!(report.object_count(), 0);
+    }
+}
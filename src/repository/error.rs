@@ -1,10 +1,11 @@
 //! Error handling for the `repository` module.
 //!
 
-use std::fmt;
+use std::{error, fmt};
 use std::convert::Infallible;
 use bcder::decode::{DecodeError, ContentError};
 use crate::crypto::keys::SignatureVerificationError;
+use super::options::SigningTimeError;
 
 
 //------------ InspectionError -----------------------------------------------
@@ -38,6 +39,8 @@ impl fmt::Display for InspectionError {
     }
 }
 
+impl error::Error for InspectionError { }
+
 
 //------------ VerificationError ---------------------------------------------
 
@@ -70,6 +73,8 @@ impl fmt::Display for VerificationError {
     }
 }
 
+impl error::Error for VerificationError { }
+
 
 //------------ ValidationError -----------------------------------------------
 
@@ -83,6 +88,17 @@ enum ValidationErrorKind {
     Decoding(DecodeError<Infallible>),
     Inspection(InspectionError),
     Verification(VerificationError),
+    SigningTime(SigningTimeError),
+}
+
+impl ValidationError {
+    /// Returns the signing time error if this is one.
+    pub fn signing_time_error(&self) -> Option<SigningTimeError> {
+        match self.inner {
+            ValidationErrorKind::SigningTime(err) => Some(err),
+            _ => None
+        }
+    }
 }
 
 impl From<DecodeError<Infallible>> for ValidationError {
@@ -109,12 +125,32 @@ impl From<VerificationError> for ValidationError {
     }
 }
 
+impl From<SigningTimeError> for ValidationError {
+    fn from(err: SigningTimeError) -> ValidationError {
+        ValidationError {
+            inner: ValidationErrorKind::SigningTime(err)
+        }
+    }
+}
+
 impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.inner {
             ValidationErrorKind::Decoding(ref inner) => inner.fmt(f),
             ValidationErrorKind::Inspection(ref inner) => inner.fmt(f),
             ValidationErrorKind::Verification(ref inner) => inner.fmt(f),
+            ValidationErrorKind::SigningTime(ref inner) => inner.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.inner {
+            ValidationErrorKind::Decoding(ref inner) => Some(inner),
+            ValidationErrorKind::Inspection(ref inner) => Some(inner),
+            ValidationErrorKind::Verification(ref inner) => Some(inner),
+            ValidationErrorKind::SigningTime(ref inner) => Some(inner),
         }
     }
 }
@@ -0,0 +1,605 @@
+//! Ghostbusters records.
+//!
+//! A Ghostbusters record is a signed object published by a CA that
+//! contains contact information for the people responsible for the CA in
+//! the form of a vCard. The object is defined in [RFC 6493]. Its purpose is
+//! to give operators seeing problems with the RPKI data for some resources
+//! someone to talk to.
+//!
+//! Finding the right contact for a resource requires knowing the
+//! validated tree of CAs: the relevant records are those of the CA
+//! holding the resource or, if that CA doesn’t publish any, of the CA that
+//! delegated the resources to it, and so on. The [`ContactIndex`] collects
+//! the CAs and records encountered during validation and answers these
+//! questions via [`ContactIndex::for_prefix`] and
+//! [`ContactIndex::for_asn`].
+//!
+//! [RFC 6493]: https://tools.ietf.org/html/rfc6493
+
+use std::collections::HashMap;
+use bcder::Oid;
+use bcder::decode::{DecodeError, IntoSource, Source};
+use bytes::Bytes;
+use crate::{oid, uri};
+use crate::crypto::{KeyIdentifier, Signer, SigningError};
+use crate::resources::addr::Prefix;
+use super::cert::{Cert, ResourceCert};
+use super::error::ValidationError;
+use super::options::{GraceWarning, ValidationOptions};
+use super::resources::{AsBlocks, Asn, IpBlocks};
+use super::sigobj::{SignedObject, SignedObjectBuilder};
+use super::x509::Time;
+
+
+//------------ Ghostbusters --------------------------------------------------
+
+/// A Ghostbusters record signed object.
+#[derive(Clone, Debug)]
+pub struct Ghostbusters {
+    signed: SignedObject,
+    content: GhostbustersRecord,
+}
+
+impl Ghostbusters {
+    /// Decodes a Ghostbusters record from a source.
+    ///
+    /// If `strict` is `true`, the vCard must not contain any properties
+    /// other than those permitted by RFC 6493.
+    pub fn decode<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<Self, DecodeError<<S::Source as Source>::Error>> {
+        let signed = SignedObject::decode_if_type(
+            source, &oid::CT_RPKI_GHOSTBUSTERS, strict
+        )?;
+        let content = GhostbustersRecord::from_vcard(
+            &signed.content().to_bytes(), strict
+        ).map_err(|err| DecodeError::content(err, Default::default()))?;
+        Ok(Ghostbusters { signed, content })
+    }
+
+    /// Validates the Ghostbusters record.
+    pub fn process<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        check_crl: F
+    ) -> Result<(ResourceCert, GhostbustersRecord), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at(issuer, strict, Time::now(), check_crl)
+    }
+
+    /// Validates the Ghostbusters record at the given time.
+    ///
+    /// This is identical to [`process`][Self::process] except that the
+    /// validity of the EE certificate is checked against `now` instead of
+    /// the current time.
+    pub fn process_at<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, GhostbustersRecord), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let cert = self.signed.validate_at(issuer, strict, now)?;
+        check_crl(cert.as_ref())?;
+        Ok((cert, self.content))
+    }
+
+    /// Validates the Ghostbusters record using the given options.
+    ///
+    /// Unlike [`process`][Self::process], this checks the signing time
+    /// against the window given in the options and applies the grace
+    /// period for an EE certificate that is not yet valid. If the grace
+    /// period was needed, a warning is returned alongside the result.
+    pub fn process_with_options<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, GhostbustersRecord, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_with_options_at(
+            issuer, options, Time::now(), check_crl
+        )
+    }
+
+    /// Validates the Ghostbusters record with options at the given time.
+    ///
+    /// This is identical to
+    /// [`process_with_options`][Self::process_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn process_with_options_at<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, GhostbustersRecord, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let (cert, warning) = self.signed.validate_with_options_at(
+            issuer, options, now
+        )?;
+        check_crl(cert.as_ref())?;
+        Ok((cert, self.content, warning))
+    }
+
+    /// Returns the encoded octets of the record.
+    ///
+    /// If the record was decoded, these are exactly the octets it was
+    /// decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
+    /// Returns a reference to the EE certificate of the record.
+    pub fn cert(&self) -> &Cert {
+        self.signed.cert()
+    }
+
+    /// Returns a reference to the content of the record.
+    pub fn content(&self) -> &GhostbustersRecord {
+        &self.content
+    }
+}
+
+
+//------------ GhostbustersRecord --------------------------------------------
+
+/// The contact information of a Ghostbusters record.
+///
+/// RFC 6493 restricts the vCard to the properties `FN`, `ORG`, `ADR`,
+/// `TEL`, and `EMAIL`. The full name is mandatory and there must be at
+/// least one way to reach someone, i.e., an address, phone number, or
+/// email address. Values are kept as given in the vCard with escaping
+/// removed. Structured values, such as addresses, are kept with their
+/// components separated by semicolons.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GhostbustersRecord {
+    /// The full name of the contact.
+    pub full_name: String,
+
+    /// The organization of the contact.
+    pub organization: Option<String>,
+
+    /// The postal addresses.
+    pub addresses: Vec<String>,
+
+    /// The phone numbers.
+    pub phones: Vec<String>,
+
+    /// The email addresses.
+    pub emails: Vec<String>,
+}
+
+impl GhostbustersRecord {
+    /// Creates a new record with only the full name.
+    pub fn new(full_name: impl Into<String>) -> Self {
+        GhostbustersRecord {
+            full_name: full_name.into(),
+            .. Default::default()
+        }
+    }
+
+    /// Parses a record from its vCard representation.
+    ///
+    /// If `strict` is `true`, properties not permitted by RFC 6493 are
+    /// rejected. Otherwise they are ignored.
+    pub fn from_vcard(
+        data: &[u8], strict: bool
+    ) -> Result<Self, &'static str> {
+        let data = std::str::from_utf8(data).map_err(|_| {
+            "vCard is not valid UTF-8"
+        })?;
+
+        // Unfold lines: a line starting with white space continues the
+        // previous one.
+        let mut lines: Vec<String> = Vec::new();
+        for line in data.split('\n') {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if let Some(cont) = line.strip_prefix([' ', '\t']) {
+                match lines.last_mut() {
+                    Some(last) => last.push_str(cont),
+                    None => return Err("vCard starts with continuation")
+                }
+            }
+            else if !line.is_empty() {
+                lines.push(line.into())
+            }
+        }
+
+        let mut lines = lines.iter();
+        if !lines.next().map_or(false, |line| {
+            line.eq_ignore_ascii_case("BEGIN:VCARD")
+        }) {
+            return Err("vCard does not start with BEGIN:VCARD")
+        }
+        let mut res = GhostbustersRecord::default();
+        let mut version = false;
+        let mut full_name = None;
+        let mut end = false;
+        for line in lines.by_ref() {
+            let (name, value) = match line.split_once(':') {
+                Some(parts) => parts,
+                None => return Err("invalid vCard line")
+            };
+            // Parameters are separated from the name by a semicolon.
+            let name = name.split(';').next().unwrap_or(name);
+            let name = name.to_ascii_uppercase();
+            match name.as_str() {
+                "VERSION" => {
+                    if value != "4.0" {
+                        return Err("unsupported vCard version")
+                    }
+                    version = true;
+                }
+                "FN" => {
+                    if full_name.is_some() {
+                        return Err("multiple FN properties in vCard")
+                    }
+                    full_name = Some(unescape(value))
+                }
+                "ORG" => res.organization = Some(unescape(value)),
+                "ADR" => res.addresses.push(unescape(value)),
+                "TEL" => res.phones.push(unescape(value)),
+                "EMAIL" => res.emails.push(unescape(value)),
+                "END" => {
+                    if !value.eq_ignore_ascii_case("VCARD") {
+                        return Err("invalid vCard END")
+                    }
+                    end = true;
+                    break
+                }
+                _ => {
+                    if strict {
+                        return Err("property not permitted in vCard")
+                    }
+                }
+            }
+        }
+        if !end || lines.next().is_some() {
+            return Err("vCard does not end with END:VCARD")
+        }
+        if !version {
+            return Err("missing VERSION in vCard")
+        }
+        res.full_name = full_name.ok_or("missing FN in vCard")?;
+        if res.addresses.is_empty()
+            && res.phones.is_empty()
+            && res.emails.is_empty()
+        {
+            return Err("vCard contains no contact information")
+        }
+        Ok(res)
+    }
+
+    /// Returns the vCard representation of the record.
+    pub fn to_vcard(&self) -> String {
+        let mut res = String::from("BEGIN:VCARD\r\nVERSION:4.0\r\n");
+        let mut push = |name: &str, value: &str, structured: bool| {
+            res.push_str(name);
+            res.push(':');
+            res.push_str(&escape(value, structured));
+            res.push_str("\r\n");
+        };
+        push("FN", &self.full_name, false);
+        if let Some(org) = self.organization.as_ref() {
+            push("ORG", org, true);
+        }
+        for value in &self.addresses {
+            push("ADR", value, true);
+        }
+        for value in &self.phones {
+            push("TEL", value, false);
+        }
+        for value in &self.emails {
+            push("EMAIL", value, false);
+        }
+        res.push_str("END:VCARD\r\n");
+        res
+    }
+}
+
+/// Removes vCard escaping from a value.
+fn unescape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            res.push(ch);
+            continue
+        }
+        match chars.next() {
+            Some('n') | Some('N') => res.push('\n'),
+            Some(ch) => res.push(ch),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+/// Adds vCard escaping to a value.
+///
+/// Semicolons separate components of structured values and are only
+/// escaped for other values.
+fn escape(value: &str, structured: bool) -> String {
+    let mut res = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => res.push_str("\\\\"),
+            ',' => res.push_str("\\,"),
+            ';' if !structured => res.push_str("\\;"),
+            '\n' => res.push_str("\\n"),
+            _ => res.push(ch),
+        }
+    }
+    res
+}
+
+
+//------------ GhostbustersBuilder -------------------------------------------
+
+/// A builder for Ghostbusters records.
+#[derive(Clone, Debug)]
+pub struct GhostbustersBuilder {
+    content: GhostbustersRecord,
+}
+
+impl GhostbustersBuilder {
+    /// Creates a builder for the given contact information.
+    pub fn new(content: GhostbustersRecord) -> Self {
+        GhostbustersBuilder { content }
+    }
+
+    /// Finalizes the builder into a Ghostbusters record.
+    ///
+    /// The resources of the EE certificate are set to inherit.
+    pub fn finalize<S: Signer>(
+        self,
+        mut sigobj: SignedObjectBuilder,
+        signer: &S,
+        issuer_key: &S::KeyId,
+    ) -> Result<Ghostbusters, SigningError<S::Error>> {
+        sigobj.set_v4_resources_inherit();
+        sigobj.set_v6_resources_inherit();
+        sigobj.set_as_resources_inherit();
+        let signed = sigobj.finalize(
+            Oid(oid::CT_RPKI_GHOSTBUSTERS.0.into()),
+            Bytes::from(self.content.to_vcard()),
+            signer,
+            issuer_key,
+        )?;
+        Ok(Ghostbusters { signed, content: self.content })
+    }
+}
+
+
+//------------ ContactIndex --------------------------------------------------
+
+/// The CAs of a validated tree and their Ghostbusters records.
+///
+/// CAs are added via [`add_ca`][Self::add_ca] when their certificate has
+/// been validated and records via [`add_record`][Self::add_record] for the
+/// CA that issued them. CAs are identified by their key, their parents by
+/// the authority key identifier of their certificate.
+#[derive(Clone, Debug, Default)]
+pub struct ContactIndex {
+    /// The CAs, keyed by their subject key identifier.
+    cas: HashMap<KeyIdentifier, CaContacts>,
+}
+
+impl ContactIndex {
+    /// The maximum length of a delegation chain to follow.
+    const MAX_DEPTH: usize = 64;
+
+    /// Creates a new, empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of CAs in the index.
+    pub fn len(&self) -> usize {
+        self.cas.len()
+    }
+
+    /// Returns whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cas.is_empty()
+    }
+
+    /// Adds a validated CA certificate published at `uri`.
+    pub fn add_ca(&mut self, uri: uri::Rsync, cert: &ResourceCert) {
+        let key = cert.subject_key_identifier();
+        let parent = cert.authority_key_identifier().filter(|parent| {
+            *parent != key
+        });
+        let entry = self.cas.entry(key).or_insert_with(|| CaContacts {
+            uri: uri.clone(),
+            parent,
+            v4: IpBlocks::empty(),
+            v6: IpBlocks::empty(),
+            asns: AsBlocks::empty(),
+            records: Vec::new(),
+        });
+        entry.uri = uri;
+        entry.parent = parent;
+        entry.v4 = cert.v4_resources().clone();
+        entry.v6 = cert.v6_resources().clone();
+        entry.asns = cert.as_resources().clone();
+    }
+
+    /// Adds a validated Ghostbusters record issued by `ca`.
+    ///
+    /// The CA should have been added before. If it hasn’t, the record is
+    /// ignored.
+    pub fn add_record(
+        &mut self, ca: &ResourceCert, record: GhostbustersRecord
+    ) {
+        if let Some(entry) = self.cas.get_mut(&ca.subject_key_identifier()) {
+            entry.records.push(record)
+        }
+    }
+
+    /// Returns the CAs with contacts responsible for a prefix.
+    ///
+    /// Starting with the most specific CA holding the entire prefix, the
+    /// method walks up the delegation chain and returns all CAs along the
+    /// way that have Ghostbusters records. The nearest CA comes first.
+    pub fn for_prefix(&self, prefix: Prefix) -> Vec<&CaContacts> {
+        self.chain(|ca| {
+            if prefix.is_v4() {
+                ca.v4.contains_block(prefix)
+            }
+            else {
+                ca.v6.contains_block(prefix)
+            }
+        })
+    }
+
+    /// Returns the CAs with contacts responsible for an ASN.
+    ///
+    /// This works like [`for_prefix`][Self::for_prefix].
+    pub fn for_asn(&self, asn: Asn) -> Vec<&CaContacts> {
+        self.chain(|ca| ca.asns.contains_asn(asn))
+    }
+
+    /// Returns the CAs with records up the chain from the deepest match.
+    fn chain(
+        &self, op: impl Fn(&CaContacts) -> bool
+    ) -> Vec<&CaContacts> {
+        let mut best: Option<(usize, &CaContacts)> = None;
+        for ca in self.cas.values().filter(|ca| op(ca)) {
+            let depth = self.depth(ca);
+            if best.map_or(true, |(best_depth, best_ca)| {
+                depth > best_depth
+                    || (depth == best_depth && ca.uri < best_ca.uri)
+            }) {
+                best = Some((depth, ca))
+            }
+        }
+        let mut res = Vec::new();
+        let mut ca = best.map(|(_, ca)| ca);
+        for _ in 0..Self::MAX_DEPTH {
+            let current = match ca {
+                Some(current) => current,
+                None => break
+            };
+            if !current.records.is_empty() {
+                res.push(current)
+            }
+            ca = current.parent.as_ref().and_then(|key| self.cas.get(key));
+        }
+        res
+    }
+
+    /// Returns the number of ancestors of a CA.
+    fn depth<'a>(&'a self, mut ca: &'a CaContacts) -> usize {
+        let mut depth = 0;
+        while let Some(parent) = ca.parent.as_ref().and_then(|key| {
+            self.cas.get(key)
+        }) {
+            depth += 1;
+            if depth >= Self::MAX_DEPTH {
+                break
+            }
+            ca = parent;
+        }
+        depth
+    }
+}
+
+
+//------------ CaContacts ----------------------------------------------------
+
+/// A CA and its Ghostbusters records.
+#[derive(Clone, Debug)]
+pub struct CaContacts {
+    /// The URI of the CA certificate.
+    uri: uri::Rsync,
+
+    /// The key identifier of the parent CA.
+    parent: Option<KeyIdentifier>,
+
+    /// The IPv4 resources of the CA.
+    v4: IpBlocks,
+
+    /// The IPv6 resources of the CA.
+    v6: IpBlocks,
+
+    /// The AS resources of the CA.
+    asns: AsBlocks,
+
+    /// The Ghostbusters records published by the CA.
+    records: Vec<GhostbustersRecord>,
+}
+
+impl CaContacts {
+    /// Returns the URI of the CA certificate.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+
+    /// Returns the Ghostbusters records published by the CA.
+    pub fn records(&self) -> &[GhostbustersRecord] {
+        &self.records
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vcard() {
+        let vcard = b"BEGIN:VCARD\r\n\
+            VERSION:4.0\r\n\
+            FN:Human\r\n\
+            ORG:Organizational Entity\r\n\
+            ADR;TYPE=WORK:;;42 Twisty Passage;Deep Cavern;WA;98666;U.S.A.\r\n\
+            TEL;TYPE=VOICE,TEXT,WORK;VALUE=uri:tel:+1-666-555-1212\r\n\
+            EMAIL:human@example.com\r\n\
+            END:VCARD\r\n";
+        let record = GhostbustersRecord::from_vcard(vcard, true).unwrap();
+        assert_eq!(record.full_name, "Human");
+        assert_eq!(
+            record.organization.as_deref(), Some("Organizational Entity")
+        );
+        assert_eq!(
+            record.addresses,
+            [";;42 Twisty Passage;Deep Cavern;WA;98666;U.S.A."]
+        );
+        assert_eq!(record.phones, ["tel:+1-666-555-1212"]);
+        assert_eq!(record.emails, ["human@example.com"]);
+        assert_eq!(
+            GhostbustersRecord::from_vcard(
+                record.to_vcard().as_bytes(), true
+            ).unwrap(),
+            record
+        );
+
+        // Folded lines and extra properties.
+        let vcard = b"BEGIN:VCARD\nVERSION:4.0\nFN:Hu\n man\n\
+            NOTE:ignore me\nEMAIL:a\\,b@example.com\nEND:VCARD\n";
+        assert!(GhostbustersRecord::from_vcard(vcard, true).is_err());
+        let record = GhostbustersRecord::from_vcard(vcard, false).unwrap();
+        assert_eq!(record.full_name, "Human");
+        assert_eq!(record.emails, ["a,b@example.com"]);
+
+        assert!(GhostbustersRecord::from_vcard(
+            b"BEGIN:VCARD\nVERSION:4.0\nFN:Human\nEND:VCARD\n", false
+        ).is_err());
+        assert!(GhostbustersRecord::from_vcard(
+            b"BEGIN:VCARD\nFN:Human\nEMAIL:a@example.com\nEND:VCARD\n", false
+        ).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Hooks for local policy during validation.
+//!
+//! Organizations often want to apply policy of their own on top of what
+//! the RPKI standards demand: reject ROAs with an overly generous
+//! maxLength, flag objects concerning certain ASNs, refuse objects from a
+//! misbehaving CA. Instead of changing the validation engine, they can
+//! implement the [`ObjectHook`] trait and add it to the engine.
+//!
+//! Each hook is called twice for every object listed on a manifest: once
+//! before the object is validated with only its raw content and once after
+//! it has been validated successfully with the payload derived from it.
+//! Either time, the hook returns a [`HookVerdict`] that lets the object
+//! pass, passes it with a warning recorded in the validation report, or
+//! rejects it. A rejected CA certificate takes the entire subtree below it
+//! with it.
+//!
+//! The hooks of an engine are kept in a [`Hooks`] value which calls all
+//! of them in the order they were added.
+//!
+//! The module is only available if both the `"repository"` and `"rtr"`
+//! features are enabled since it is used by the validation engine.
+
+#![cfg(feature = "rtr")]
+
+use std::fmt;
+use std::sync::Arc;
+use crate::uri;
+use crate::rtr::payload::Payload;
+use super::cert::ResourceCert;
+
+
+//------------ ObjectHook ----------------------------------------------------
+
+/// A type that applies local policy to objects during validation.
+///
+/// Both methods have a default implementation that accepts everything,
+/// so an implementation only needs to provide the method it needs.
+///
+/// Hooks are called from within the validation run. Implementations
+/// should therefore be reasonably cheap.
+pub trait ObjectHook: Send + Sync {
+    /// Inspects an object before it is validated.
+    fn before(&self, object: &HookObject) -> HookVerdict {
+        let _ = object;
+        HookVerdict::Accept
+    }
+
+    /// Inspects an object after it has been validated successfully.
+    ///
+    /// The `payload` contains the payload derived from the object. It is
+    /// empty for objects that don’t produce payload, such as CA
+    /// certificates.
+    fn after(
+        &self, object: &HookObject, payload: &[Payload]
+    ) -> HookVerdict {
+        let _ = (object, payload);
+        HookVerdict::Accept
+    }
+}
+
+
+//------------ HookObject ----------------------------------------------------
+
+/// An object handed to a hook.
+#[derive(Clone, Copy, Debug)]
+pub struct HookObject<'a> {
+    /// The name of the trust anchor the object is validated under.
+    tal: &'a str,
+
+    /// The certificate of the CA that published the object.
+    ca: &'a ResourceCert,
+
+    /// The URI of the object.
+    uri: &'a uri::Rsync,
+
+    /// The raw content of the object.
+    data: &'a [u8],
+}
+
+impl<'a> HookObject<'a> {
+    /// Creates a new object from its components.
+    pub fn new(
+        tal: &'a str,
+        ca: &'a ResourceCert,
+        uri: &'a uri::Rsync,
+        data: &'a [u8],
+    ) -> Self {
+        HookObject { tal, ca, uri, data }
+    }
+
+    /// Returns the name of the trust anchor.
+    pub fn tal(&self) -> &'a str {
+        self.tal
+    }
+
+    /// Returns the certificate of the CA that published the object.
+    pub fn ca(&self) -> &'a ResourceCert {
+        self.ca
+    }
+
+    /// Returns the URI of the object.
+    ///
+    /// The file extension of the URI indicates the type of the object.
+    pub fn uri(&self) -> &'a uri::Rsync {
+        self.uri
+    }
+
+    /// Returns the raw content of the object.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+
+//------------ HookVerdict ---------------------------------------------------
+
+/// The decision of a hook about an object.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum HookVerdict {
+    /// The object may pass.
+    #[default]
+    Accept,
+
+    /// The object may pass but a warning is recorded.
+    Warn(String),
+
+    /// The object is rejected for the given reason.
+    Reject(String),
+}
+
+
+//------------ Hooks ---------------------------------------------------------
+
+/// A list of hooks.
+///
+/// Clones of the list share the hooks themselves.
+#[derive(Clone, Default)]
+pub struct Hooks(Vec<Arc<dyn ObjectHook>>);
+
+impl Hooks {
+    /// Creates a new, empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook to the end of the list.
+    pub fn push(&mut self, hook: impl ObjectHook + 'static) {
+        self.0.push(Arc::new(hook))
+    }
+
+    /// Adds a shared hook to the end of the list.
+    pub fn push_arc(&mut self, hook: Arc<dyn ObjectHook>) {
+        self.0.push(hook)
+    }
+
+    /// Returns the number of hooks in the list.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Runs the pre-validation check of all hooks.
+    ///
+    /// Returns the warnings of all hooks if the object may pass or the
+    /// reason of the first hook rejecting it.
+    pub fn before(&self, object: &HookObject) -> Result<Vec<String>, String> {
+        Self::combine(self.0.iter().map(|hook| hook.before(object)))
+    }
+
+    /// Runs the post-validation check of all hooks.
+    ///
+    /// Returns the same as [`before`][Self::before].
+    pub fn after(
+        &self, object: &HookObject, payload: &[Payload]
+    ) -> Result<Vec<String>, String> {
+        Self::combine(self.0.iter().map(|hook| hook.after(object, payload)))
+    }
+
+    fn combine(
+        verdicts: impl Iterator<Item = HookVerdict>
+    ) -> Result<Vec<String>, String> {
+        let mut warnings = Vec::new();
+        for verdict in verdicts {
+            match verdict {
+                HookVerdict::Accept => { }
+                HookVerdict::Warn(warning) => warnings.push(warning),
+                HookVerdict::Reject(reason) => return Err(reason),
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks").field("len", &self.0.len()).finish()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn combine() {
+        assert_eq!(Hooks::combine(std::iter::empty()), Ok(Vec::new()));
+        assert_eq!(
+            Hooks::combine([
+                HookVerdict::Warn("one".into()),
+                HookVerdict::Accept,
+                HookVerdict::Warn("two".into()),
+            ].into_iter()),
+            Ok(vec!["one".into(), "two".into()])
+        );
+        assert_eq!(
+            Hooks::combine([
+                HookVerdict::Warn("one".into()),
+                HookVerdict::Reject("bad".into()),
+                HookVerdict::Reject("worse".into()),
+            ].into_iter()),
+            Err("bad".into())
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! Best-practice checks for a CA’s publication point.
+//!
+//! Objects that are perfectly valid today can still be a problem waiting
+//! to happen: a CRL that goes stale before the manifest is due to be
+//! re-issued, an EE certificate that expires before its manifest’s next
+//! update time, or an object left behind in the publication point after it
+//! was removed from the manifest. The [`Linter`] runs a battery of such
+//! checks over the objects of a single publication point and returns a
+//! list of [`Finding`]s tagged with a [`Severity`] for CA operators to act
+//! upon.
+//!
+//! Objects are only decoded, not validated. Validation problems are
+//! reported by the validation itself.
+
+use std::fmt;
+use std::collections::BTreeMap;
+use bytes::Bytes;
+use crate::uri;
+use crate::store::ObjectStore;
+use super::cert::Cert;
+use super::crl::Crl;
+use super::manifest::{Manifest, ManifestHash};
+use super::names::check_file_name;
+use super::sigobj::SignedObject;
+use super::x509::{Time, Validity};
+
+
+//------------ Linter --------------------------------------------------------
+
+/// Runs best-practice checks over the objects of a publication point.
+#[derive(Clone, Debug, Default)]
+pub struct Linter {
+    /// The files of the publication point keyed by their name.
+    files: BTreeMap<String, Bytes>,
+}
+
+impl Linter {
+    /// Creates a new, empty linter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the objects directly under `base` in the given store.
+    ///
+    /// Objects in sub-directories of `base` are ignored as they belong to a
+    /// different publication point.
+    pub fn add_store<S: ObjectStore>(
+        &mut self, store: &S, base: &uri::Rsync
+    ) -> Result<(), S::Error> {
+        let base_path = base.path();
+        for uri in store.list(base)? {
+            let name = match uri.path().strip_prefix(base_path) {
+                Some(name) if !name.contains('/') => name.to_string(),
+                _ => continue,
+            };
+            if let Some(data) = store.get(&uri)? {
+                self.add_file(name, data)
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a file of the publication point.
+    pub fn add_file(&mut self, name: impl Into<String>, data: Bytes) {
+        self.files.insert(name.into(), data);
+    }
+
+    /// Runs the checks for the current time.
+    pub fn lint(&self) -> Vec<Finding> {
+        self.lint_at(Time::now())
+    }
+
+    /// Runs the checks for the given time.
+    ///
+    /// The findings are ordered by file name with findings concerning the
+    /// publication point as a whole first.
+    pub fn lint_at(&self, now: Time) -> Vec<Finding> {
+        let mut res = Vec::new();
+        for name in self.files.keys() {
+            if let Err(err) = check_file_name(name.as_bytes(), None) {
+                res.push(Finding::new(
+                    Severity::Warning, Check::FileName, Some(name),
+                    err.to_string()
+                ));
+            }
+        }
+        match self.find_manifest(&mut res) {
+            Some((name, manifest)) => {
+                self.check_manifest(name, &manifest, now, &mut res);
+            }
+            None => {
+                // Without a manifest, we can only look at the objects.
+                for (name, data) in &self.files {
+                    self.check_object(name, data, now, &mut res);
+                }
+            }
+        }
+        res.sort_by(|left, right| left.file.cmp(&right.file));
+        res
+    }
+
+    /// Finds and decodes the manifest of the publication point.
+    fn find_manifest(
+        &self, res: &mut Vec<Finding>
+    ) -> Option<(&str, Manifest)> {
+        let mut manifests = self.files.iter().filter(|(name, _)| {
+            name.ends_with(".mft")
+        });
+        let (name, data) = match manifests.next() {
+            Some(item) => item,
+            None => {
+                res.push(Finding::new(
+                    Severity::Error, Check::Manifest, None,
+                    "no manifest in publication point"
+                ));
+                return None
+            }
+        };
+        if manifests.next().is_some() {
+            res.push(Finding::new(
+                Severity::Error, Check::Manifest, None,
+                "more than one manifest in publication point"
+            ));
+            return None
+        }
+        match Manifest::decode(data.clone(), false) {
+            Ok(manifest) => Some((name, manifest)),
+            Err(err) => {
+                res.push(Finding::new(
+                    Severity::Error, Check::Decode, Some(name),
+                    format!("cannot decode manifest: {}", err)
+                ));
+                None
+            }
+        }
+    }
+
+    /// Runs all checks that start from the manifest.
+    fn check_manifest(
+        &self,
+        mft_name: &str,
+        manifest: &Manifest,
+        now: Time,
+        res: &mut Vec<Finding>,
+    ) {
+        let content = manifest.content();
+        if content.next_update() < now {
+            res.push(Finding::new(
+                Severity::Error, Check::Manifest, Some(mft_name),
+                format!("manifest stale since {}", *content.next_update())
+            ));
+        }
+        let ee = manifest.cert().validity();
+        if ee.not_after() < content.next_update() {
+            res.push(Finding::new(
+                Severity::Error, Check::Validity, Some(mft_name),
+                "EE certificate expires before manifest next update"
+            ));
+        }
+        if ee.not_before() > content.this_update() {
+            res.push(Finding::new(
+                Severity::Warning, Check::Validity, Some(mft_name),
+                "EE certificate not valid at manifest this update"
+            ));
+        }
+
+        // All listed files must be present and match.
+        let mut listed = Vec::new();
+        for (file, hash) in content.iter().map(|item| item.into_pair()) {
+            let name = String::from_utf8_lossy(&file).into_owned();
+            match self.files.get(&name) {
+                Some(data) => {
+                    let hash = ManifestHash::new(
+                        hash, content.file_hash_alg()
+                    );
+                    if hash.verify(data).is_err() {
+                        res.push(Finding::new(
+                            Severity::Error, Check::Manifest, Some(&name),
+                            "hash differs from manifest"
+                        ));
+                    }
+                }
+                None => {
+                    res.push(Finding::new(
+                        Severity::Error, Check::Manifest, Some(&name),
+                        "listed on manifest but missing"
+                    ));
+                }
+            }
+            listed.push(name);
+        }
+
+        // All present files must be listed.
+        for (name, data) in &self.files {
+            if name == mft_name {
+                continue
+            }
+            if !listed.contains(name) {
+                res.push(Finding::new(
+                    Severity::Warning, Check::Orphaned, Some(name),
+                    "not listed on manifest"
+                ));
+            }
+            self.check_object(name, data, now, res);
+        }
+
+        // There must be exactly one CRL and it must match the manifest.
+        let crl_name = manifest.cert().crl_uri().and_then(|uri| {
+            uri.path().rsplit('/').next()
+        });
+        let crl_data = crl_name.and_then(|crl_name| {
+            self.files.get(crl_name).map(|data| (crl_name, data))
+        });
+        match crl_data {
+            Some((crl_name, data)) => {
+                if let Ok(crl) = Crl::decode(data.clone()) {
+                    Self::check_crl(crl_name, &crl, manifest, res)
+                }
+            }
+            None => {
+                res.push(Finding::new(
+                    Severity::Error, Check::Crl, crl_name,
+                    "CRL of the manifest is missing"
+                ));
+            }
+        }
+        if self.files.keys().filter(|name| name.ends_with(".crl")).count() > 1
+        {
+            res.push(Finding::new(
+                Severity::Warning, Check::Crl, None,
+                "more than one CRL in publication point"
+            ));
+        }
+    }
+
+    /// Checks that the CRL’s update times are aligned with the manifest.
+    fn check_crl(
+        name: &str, crl: &Crl, manifest: &Manifest, res: &mut Vec<Finding>
+    ) {
+        let content = manifest.content();
+        if crl.next_update() < content.next_update() {
+            res.push(Finding::new(
+                Severity::Error, Check::UpdateTimes, Some(name),
+                "CRL next update is before manifest next update"
+            ));
+        }
+        else if crl.next_update() != content.next_update() {
+            res.push(Finding::new(
+                Severity::Info, Check::UpdateTimes, Some(name),
+                "CRL and manifest next update times differ"
+            ));
+        }
+        if crl.this_update() != content.this_update() {
+            res.push(Finding::new(
+                Severity::Info, Check::UpdateTimes, Some(name),
+                "CRL and manifest this update times differ"
+            ));
+        }
+    }
+
+    /// Checks an individual object other than the manifest.
+    fn check_object(
+        &self, name: &str, data: &Bytes, now: Time, res: &mut Vec<Finding>
+    ) {
+        let ext = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+        let validity = match ext {
+            "cer" => {
+                Cert::decode(data.clone()).map(|cert| cert.validity())
+                    .map_err(|err| err.to_string())
+            }
+            "crl" => {
+                Crl::decode(data.clone()).map(|crl| {
+                    Validity::new(crl.this_update(), crl.next_update())
+                }).map_err(|err| err.to_string())
+            }
+            "roa" | "asa" | "gbr" | "sig" | "tak" => {
+                SignedObject::decode(data.clone(), false).map(|obj| {
+                    obj.cert().validity()
+                }).map_err(|err| err.to_string())
+            }
+            _ => return
+        };
+        let validity = match validity {
+            Ok(validity) => validity,
+            Err(err) => {
+                res.push(Finding::new(
+                    Severity::Error, Check::Decode, Some(name),
+                    format!("cannot decode object: {}", err)
+                ));
+                return
+            }
+        };
+        if validity.not_after() < now {
+            res.push(Finding::new(
+                Severity::Error, Check::Validity, Some(name),
+                format!("expired since {}", *validity.not_after())
+            ));
+        }
+        else if validity.not_before() > now {
+            res.push(Finding::new(
+                Severity::Warning, Check::Validity, Some(name),
+                format!("not valid before {}", *validity.not_before())
+            ));
+        }
+    }
+}
+
+
+//------------ Finding -------------------------------------------------------
+
+/// A problem found by the linter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Finding {
+    /// How serious the problem is.
+    pub severity: Severity,
+
+    /// The check that found the problem.
+    pub check: Check,
+
+    /// The name of the file concerned.
+    ///
+    /// This is `None` if the problem concerns the publication point as a
+    /// whole.
+    pub file: Option<String>,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl Finding {
+    /// Creates a new finding.
+    fn new(
+        severity: Severity, check: Check, file: Option<&str>,
+        message: impl Into<String>,
+    ) -> Self {
+        Finding {
+            severity, check,
+            file: file.map(Into::into),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => {
+                write!(f, "{}: {}: {}", self.severity, file, self.message)
+            }
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+
+//------------ Severity ------------------------------------------------------
+
+/// How serious a finding is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    /// Deviates from common practice but is harmless.
+    Info,
+
+    /// May cause problems for some relying parties or in the future.
+    Warning,
+
+    /// Causes relying parties to reject objects now or soon.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+
+//------------ Check ---------------------------------------------------------
+
+/// The check that produced a finding.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[non_exhaustive]
+pub enum Check {
+    /// An object could not be decoded.
+    Decode,
+
+    /// A file name does not conform to RFC 9286.
+    FileName,
+
+    /// The manifest is missing, stale, or doesn’t match the files.
+    Manifest,
+
+    /// The CRL is missing or there is more than one.
+    Crl,
+
+    /// The update times of manifest and CRL are not aligned.
+    UpdateTimes,
+
+    /// An object or its EE certificate is not valid or doesn’t fit.
+    Validity,
+
+    /// A file is not listed on the manifest.
+    Orphaned,
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ta_linter() -> Linter {
+        let mut linter = Linter::new();
+        linter.add_file(
+            "ripe-ncc-ta.mft",
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.mft")
+            )
+        );
+        linter.add_file(
+            "ripe-ncc-ta.crl",
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.crl")
+            )
+        );
+        linter
+    }
+
+    #[test]
+    fn lint() {
+        let mut linter = ta_linter();
+        linter.add_file("extra.roa", Bytes::from_static(b"foo"));
+        linter.add_file("bad name.roa", Bytes::from_static(b"foo"));
+        let findings = linter.lint_at(Time::utc(2019, 5, 1, 0, 0, 0));
+
+        let find = |file: &str, check| {
+            findings.iter().find(|item| {
+                item.file.as_deref() == Some(file) && item.check == check
+            }).map(|item| item.severity)
+        };
+        assert_eq!(
+            find("2a7dd1d787d793e4c8af56e197d4eed92af6ba13.cer",
+                 Check::Manifest),
+            Some(Severity::Error)
+        );
+        assert_eq!(
+            find("extra.roa", Check::Orphaned), Some(Severity::Warning)
+        );
+        assert_eq!(find("extra.roa", Check::Decode), Some(Severity::Error));
+        assert_eq!(
+            find("bad name.roa", Check::FileName), Some(Severity::Warning)
+        );
+        assert_eq!(find("ripe-ncc-ta.crl", Check::UpdateTimes), None);
+        assert_eq!(find("ripe-ncc-ta.mft", Check::Validity), None);
+        assert!(!findings.iter().any(|item| item.file.is_none()));
+    }
+
+    #[test]
+    fn lint_stale() {
+        let findings = ta_linter().lint_at(Time::utc(2019, 6, 1, 0, 0, 0));
+        assert!(findings.iter().any(|item| {
+            item.file.as_deref() == Some("ripe-ncc-ta.mft")
+                && item.check == Check::Manifest
+        }));
+        assert!(findings.iter().any(|item| {
+            item.file.as_deref() == Some("ripe-ncc-ta.crl")
+                && item.check == Check::Validity
+        }));
+    }
+
+    #[test]
+    fn no_manifest() {
+        let mut linter = Linter::new();
+        linter.add_file(
+            "ripe-ncc-ta.crl",
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.crl")
+            )
+        );
+        let findings = linter.lint_at(Time::utc(2019, 5, 1, 0, 0, 0));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].check, Check::Manifest);
+        assert_eq!(findings[0].file, None);
+    }
+}
@@ -11,6 +11,7 @@
 //! [`ManifestContent`]: struct.ManifestContent.html
 
 use std::{borrow, fmt, ops};
+use std::convert::Infallible;
 use bcder::{decode, encode};
 use bcder::{
     BitString, Captured, Ia5String, Mode, OctetString, Oid, Tag,
@@ -23,7 +24,11 @@ use crate::crypto::{DigestAlgorithm, Signer, SigningError};
 use crate::util::base64;
 use super::cert::{Cert, ResourceCert};
 use super::error::{ValidationError, VerificationError};
-use super::sigobj::{SignedObject, SignedObjectBuilder};
+use super::names::{NameError, NamePolicy, check_file_name};
+use super::options::{GraceWarning, ValidationOptions};
+use super::sigobj::{
+    SignedObject, SignedObjectBuilder, SignedObjectHeader
+};
 use super::x509::{Serial, Time};
 
 
@@ -56,6 +61,44 @@ impl Manifest {
         Ok(Manifest { signed, content })
     }
 
+    /// Parses only the header of a manifest.
+    ///
+    /// This checks the content type but decodes neither the EE
+    /// certificate nor the content. Use
+    /// [`decode_payload`][Self::decode_payload] to get to the content or
+    /// [`from_header`][Self::from_header] to get a complete manifest.
+    pub fn parse_header_only<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<
+        SignedObjectHeader, DecodeError<<S::Source as Source>::Error>
+    > {
+        SignedObjectHeader::decode_if_type(
+            source, &oid::CT_RPKI_MANIFEST, strict
+        )
+    }
+
+    /// Decodes the content of a manifest from its header.
+    ///
+    /// The content is not validated in any way.
+    #[allow(clippy::redundant_closure)]
+    pub fn decode_payload(
+        header: &SignedObjectHeader
+    ) -> Result<ManifestContent, DecodeError<Infallible>> {
+        header.decode_content(
+            |cons| ManifestContent::take_from(cons)
+        ).map_err(DecodeError::convert)
+    }
+
+    /// Creates a complete manifest from its header.
+    pub fn from_header(
+        header: SignedObjectHeader
+    ) -> Result<Self, DecodeError<Infallible>> {
+        let content = Self::decode_payload(&header)?;
+        let signed = header.into_signed_object()?;
+        Ok(Manifest { signed, content })
+    }
+
     /// Validates the manifest.
     ///
     /// You need to pass in the certificate of the issuing CA. If validation
@@ -79,6 +122,47 @@ impl Manifest {
         Ok((cert, self.content))
     }
 
+    /// Validates the manifest using the given options.
+    ///
+    /// Unlike [`validate`][Self::validate], this also rejects a manifest
+    /// that is stale beyond the grace period given in the options. Any
+    /// grace period that was needed for accepting the manifest is reported
+    /// via the returned warnings.
+    pub fn validate_with_options(
+        self,
+        cert: &ResourceCert,
+        options: &ValidationOptions,
+    ) -> Result<
+        (ResourceCert, ManifestContent, Vec<GraceWarning>), ValidationError
+    > {
+        self.validate_with_options_at(cert, options, Time::now())
+    }
+
+    /// Validates the manifest using the given options at a given time.
+    ///
+    /// This is identical to
+    /// [`validate_with_options`][Self::validate_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn validate_with_options_at(
+        self,
+        cert: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time
+    ) -> Result<
+        (ResourceCert, ManifestContent, Vec<GraceWarning>), ValidationError
+    > {
+        let (cert, warning) = self.signed.validate_with_options_at(
+            cert, options, now
+        )?;
+        let mut warnings: Vec<_> = warning.into_iter().collect();
+        warnings.extend(
+            options.check_manifest_next_update(
+                self.content.next_update(), now
+            )?
+        );
+        Ok((cert, self.content, warnings))
+    }
+
     /// Returns a value encoder for a reference to the manifest.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         self.signed.encode_ref()
@@ -89,11 +173,25 @@ impl Manifest {
         self.encode_ref().to_captured(Mode::Der)
     }
 
+    /// Returns the encoded octets of the manifest.
+    ///
+    /// If the manifest was decoded, these are exactly the octets it was
+    /// decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
     /// Returns a reference to the EE certificate of this manifest.
     pub fn cert(&self) -> &Cert {
         self.signed.cert()
     }
 
+    /// Returns a reference to the signed object of this manifest.
+    #[cfg(feature = "bench")]
+    pub(crate) fn signed_object(&self) -> &SignedObject {
+        &self.signed
+    }
+
     /// Returns a reference to the manifest content.
     pub fn content(&self) -> &ManifestContent {
         &self.content
@@ -299,7 +397,31 @@ impl ManifestContent {
     ///
     /// A manifest is stale if it’s nextUpdate time has passed.
     pub fn is_stale(&self) -> bool {
-        self.next_update < Time::now()
+        self.is_stale_at(Time::now())
+    }
+
+    /// Returns whether the manifest is stale at the given time.
+    pub fn is_stale_at(&self, now: Time) -> bool {
+        self.next_update < now
+    }
+
+    /// Checks that the file names conform to RFC 9286.
+    ///
+    /// Decoding a manifest only rejects names that could refer to files
+    /// outside the publication point. This checks the stricter rules of
+    /// section 4.2.2 of RFC 9286 for all names on the manifest. Depending
+    /// on `policy`, the first non-conforming name is returned as an error
+    /// or all of them are returned as warnings.
+    pub fn check_file_names(
+        &self, policy: NamePolicy,
+    ) -> Result<Vec<NameError>, NameError> {
+        let mut warnings = Vec::new();
+        for item in self.iter() {
+            policy.apply(
+                check_file_name(item.file().as_ref(), None), &mut warnings
+            )?;
+        }
+        Ok(warnings)
     }
 }
 
@@ -423,15 +545,33 @@ impl FileAndHash<Bytes, Bytes> {
         cons: &mut decode::Constructed<S>
     ) -> Result<Option<()>, DecodeError<S::Error>> {
         cons.take_opt_sequence(|cons| {
-            cons.take_value_if(
+            let file = cons.take_value_if(
                 Tag::IA5_STRING,
                 OctetString::from_content
             )?;
+            if !Self::is_valid_file_name(file.to_bytes().as_ref()) {
+                return Err(cons.content_err("invalid file name"))
+            }
             BitString::skip_in(cons)?;
             Ok(())
         })
     }
 
+    /// Returns whether a file name is acceptable.
+    ///
+    /// RFC 9286 limits file names to letters, digits, hyphens, underscores,
+    /// and full stops. The names `.` and `..` are not allowed either as
+    /// they would refer to a different directory.
+    fn is_valid_file_name(name: &[u8]) -> bool {
+        !name.is_empty()
+            && name != b"."
+            && name != b".."
+            && name.iter().all(|&ch| {
+                ch.is_ascii_alphanumeric()
+                    || ch == b'-' || ch == b'_' || ch == b'.'
+            })
+    }
+
     /// Takes an optional value from the beginning of a constructed value.
     fn take_opt_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
@@ -553,6 +693,97 @@ mod test {
         assert!(obj.validate_at(&issuer, false, at).is_err());
     }
 
+    #[test]
+    fn check_file_names() {
+        use crate::repository::names::{NameErrorKind, NamePolicy};
+
+        let content = ManifestContent::new(
+            Serial::from(1u64),
+            Time::utc(2019, 5, 1, 0, 0, 0),
+            Time::utc(2019, 5, 2, 0, 0, 0),
+            DigestAlgorithm::default(),
+            [
+                FileAndHash::new(b"a.roa", b"0"),
+                FileAndHash::new(b"b.txt", b"0"),
+                FileAndHash::new(b"c.crl", b"0"),
+            ].iter()
+        );
+        assert_eq!(
+            content.check_file_names(
+                NamePolicy::Reject
+            ).unwrap_err().kind(),
+            NameErrorKind::UnknownExtension
+        );
+        let warnings = content.check_file_names(NamePolicy::Warn).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].name(), "b.txt");
+    }
+
+    #[test]
+    fn file_names() {
+        assert!(FileAndHash::is_valid_file_name(b"ca1.cer"));
+        assert!(FileAndHash::is_valid_file_name(b"8PLrh-R_iY.roa"));
+        assert!(!FileAndHash::is_valid_file_name(b""));
+        assert!(!FileAndHash::is_valid_file_name(b".."));
+        assert!(!FileAndHash::is_valid_file_name(b"../ca1.cer"));
+        assert!(!FileAndHash::is_valid_file_name(b"a//b.cer"));
+        assert!(!FileAndHash::is_valid_file_name(b"a b.cer"));
+    }
+
+    #[test]
+    fn validate_with_options() {
+        use std::time::Duration;
+        use crate::repository::options::GraceKind;
+
+        let talinfo = TalInfo::from_name("foo".into()).into_arc();
+        let issuer = Cert::decode(
+            include_bytes!("../../test-data/repository/ta.cer").as_ref()
+        ).unwrap();
+        let issuer = issuer.validate_ta_at(
+            talinfo, false, Time::utc(2019, 5, 1, 0, 0, 0)
+        ).unwrap();
+        let obj = Manifest::decode(
+            include_bytes!("../../test-data/repository/ta.mft").as_ref(),
+            false
+        ).unwrap();
+        let mut options = ValidationOptions::default();
+
+        let (_, _, warnings) = obj.clone().validate_with_options_at(
+            &issuer, &options, Time::utc(2019, 5, 1, 0, 0, 0)
+        ).unwrap();
+        assert!(warnings.is_empty());
+
+        // The EE certificate is valid from 2019-02-26 13:14:44.
+        let early = Time::utc(2019, 2, 26, 13, 14, 0);
+        assert!(obj.clone().validate_with_options_at(
+            &issuer, &options, early
+        ).is_err());
+        options.not_yet_valid_grace = Duration::from_secs(60);
+        let (_, _, warnings) = obj.validate_with_options_at(
+            &issuer, &options, early
+        ).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind(), GraceKind::NotYetValid);
+    }
+
+    #[test]
+    fn round_trip() {
+        for der in [
+            include_bytes!("../../test-data/repository/ta.mft").as_ref(),
+            include_bytes!("../../test-data/repository/ca1.mft").as_ref(),
+            include_bytes!(
+                "../../test-data/repository/signature-alg-mismatch.mft"
+            ).as_ref(),
+        ] {
+            let mft = Manifest::decode(der, false).unwrap();
+            assert_eq!(mft.as_bytes(), der);
+            assert_eq!(mft.to_captured().as_slice(), der);
+            assert_eq!(
+                mft.cert().as_bytes(), mft.cert().to_captured().as_slice()
+            );
+        }
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn compat_de_manifest() {
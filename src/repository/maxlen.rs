@@ -0,0 +1,280 @@
+//! A policy for the maxLength of ROAs.
+//!
+//! A ROA with a maxLength considerably larger than the length of its
+//! prefix authorizes the announcement of a great number of more-specific
+//! prefixes, most of which the holder never intends to announce. Each of
+//! them can be hijacked by a forged-origin attack. RFC 9319 therefore
+//! advises against using maxLength unless all the more-specific prefixes
+//! are actually announced.
+//!
+//! The [`MaxLengthPolicy`] is an [object hook][super::hook] that looks at
+//! the route origins of every valid ROA and flags those prefixes whose
+//! maxLength exceeds the prefix length by more than a configurable span,
+//! such as a /8 with a maxLength of 24. Depending on its
+//! [`MaxLengthAction`], the policy either records a warning in the
+//! validation report or rejects the ROA. In both cases, the number of
+//! flagged prefixes is counted in the
+//! [`ROA_EXCESSIVE_MAX_LENGTH`][names::ROA_EXCESSIVE_MAX_LENGTH] metric.
+//!
+//! The policy is disabled unless it is added to the
+//! [`Engine`][super::engine::Engine] via
+//! [`add_hook`][super::engine::Engine::add_hook]. Since the engine keeps
+//! its own metrics, the metrics for the counter need to be given to the
+//! policy separately:
+//!
+//! ```ignore
+//! let mut policy = MaxLengthPolicy::new();
+//! policy.set_action(MaxLengthAction::Reject);
+//! policy.set_metrics(metrics.clone());
+//! let mut engine = Engine::new(&store, &anchors);
+//! engine.set_metrics(metrics);
+//! engine.add_hook(policy);
+//! let report = engine.validate();
+//! ```
+//!
+//! With the default [`MaxLengthAction::Warn`], flagged ROAs are still
+//! accepted and the warnings are available via
+//! [`ValidationReport::warnings`][super::report::ValidationReport::warnings].
+//! With [`MaxLengthAction::Reject`], they are rejected and none of their
+//! payload is used.
+//!
+//! The module is only available if both the `"repository"` and `"rtr"`
+//! features are enabled.
+
+#![cfg(feature = "rtr")]
+
+use crate::metrics::{SharedMetrics, names};
+use crate::resources::addr::MaxLenPrefix;
+use crate::rtr::payload::Payload;
+use super::hook::{HookObject, HookVerdict, ObjectHook};
+
+
+//------------ MaxLengthPolicy -----------------------------------------------
+
+/// A hook flagging ROAs with an excessive maxLength.
+///
+/// A prefix is flagged if the difference between its maxLength and its
+/// prefix length – the span – is larger than the permitted span for its
+/// address family. By default, the permitted span is 8 bits for IPv4 and
+/// 16 bits for IPv6 and flagged ROAs only cause a warning.
+#[derive(Clone, Debug)]
+pub struct MaxLengthPolicy {
+    /// The permitted span for IPv4 prefixes.
+    v4_span: u8,
+
+    /// The permitted span for IPv6 prefixes.
+    v6_span: u8,
+
+    /// What to do with flagged ROAs.
+    action: MaxLengthAction,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+}
+
+impl Default for MaxLengthPolicy {
+    fn default() -> Self {
+        MaxLengthPolicy {
+            v4_span: 8,
+            v6_span: 16,
+            action: MaxLengthAction::default(),
+            metrics: SharedMetrics::default(),
+        }
+    }
+}
+
+impl MaxLengthPolicy {
+    /// Creates a new policy with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the permitted span for IPv4 prefixes.
+    pub fn v4_span(&self) -> u8 {
+        self.v4_span
+    }
+
+    /// Sets the permitted span for IPv4 prefixes.
+    pub fn set_v4_span(&mut self, span: u8) {
+        self.v4_span = span
+    }
+
+    /// Returns the permitted span for IPv6 prefixes.
+    pub fn v6_span(&self) -> u8 {
+        self.v6_span
+    }
+
+    /// Sets the permitted span for IPv6 prefixes.
+    pub fn set_v6_span(&mut self, span: u8) {
+        self.v6_span = span
+    }
+
+    /// Returns what happens to flagged ROAs.
+    pub fn action(&self) -> MaxLengthAction {
+        self.action
+    }
+
+    /// Sets what happens to flagged ROAs.
+    pub fn set_action(&mut self, action: MaxLengthAction) {
+        self.action = action
+    }
+
+    /// Sets the metrics the policy reports to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
+    }
+
+    /// Returns whether the maxLength of a prefix is excessive.
+    pub fn is_excessive(&self, prefix: MaxLenPrefix) -> bool {
+        let span = if prefix.prefix().is_v4() {
+            self.v4_span
+        }
+        else {
+            self.v6_span
+        };
+        prefix.resolved_max_len() - prefix.prefix_len() > span
+    }
+
+    /// Reviews the payload of a ROA.
+    fn review(&self, payload: &[Payload]) -> HookVerdict {
+        let flagged: Vec<_> = payload.iter().filter_map(|item| {
+            match *item {
+                Payload::Origin(origin)
+                    if self.is_excessive(origin.prefix) =>
+                {
+                    Some(origin.prefix.to_string())
+                }
+                _ => None
+            }
+        }).collect();
+        if flagged.is_empty() {
+            return HookVerdict::Accept
+        }
+        self.metrics.counter(
+            names::ROA_EXCESSIVE_MAX_LENGTH,
+            &[("action", self.action.as_str())],
+            flagged.len() as u64
+        );
+        let reason = format!("excessive maxLength: {}", flagged.join(", "));
+        match self.action {
+            MaxLengthAction::Warn => HookVerdict::Warn(reason),
+            MaxLengthAction::Reject => HookVerdict::Reject(reason),
+        }
+    }
+}
+
+impl ObjectHook for MaxLengthPolicy {
+    fn after(
+        &self, object: &HookObject, payload: &[Payload]
+    ) -> HookVerdict {
+        if object.uri().ends_with(".roa") {
+            self.review(payload)
+        }
+        else {
+            HookVerdict::Accept
+        }
+    }
+}
+
+
+//------------ MaxLengthAction -----------------------------------------------
+
+/// What happens to a ROA with an excessive maxLength.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaxLengthAction {
+    /// The ROA is accepted with a warning.
+    #[default]
+    Warn,
+
+    /// The ROA is rejected.
+    Reject,
+}
+
+impl MaxLengthAction {
+    /// Returns the value of the `action` label of the metric.
+    fn as_str(self) -> &'static str {
+        match self {
+            MaxLengthAction::Warn => "warn",
+            MaxLengthAction::Reject => "reject",
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use crate::metrics::Metrics;
+    use crate::rtr::payload::RouteOrigin;
+    use super::*;
+
+    fn origin(s: &str) -> Payload {
+        Payload::Origin(RouteOrigin::new(
+            MaxLenPrefix::from_str(s).unwrap(), 64496.into()
+        ))
+    }
+
+    #[test]
+    fn is_excessive() {
+        let policy = MaxLengthPolicy::new();
+        let check = |s| {
+            policy.is_excessive(MaxLenPrefix::from_str(s).unwrap())
+        };
+        assert!(!check("10.0.0.0/8"));
+        assert!(!check("10.0.0.0/16-24"));
+        assert!(check("10.0.0.0/8-24"));
+        assert!(!check("2001:db8::/32-48"));
+        assert!(check("2001:db8::/32-64"));
+    }
+
+    #[test]
+    fn review() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl Metrics for Recorder {
+            fn counter(
+                &self, name: &str, labels: &[(&str, &str)], value: u64
+            ) {
+                self.0.lock().unwrap().push(
+                    format!("{} {:?} {}", name, labels, value)
+                );
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let mut policy = MaxLengthPolicy::new();
+        policy.set_metrics(recorder.clone().into());
+        assert_eq!(
+            policy.review(&[origin("10.0.0.0/16-24")]), HookVerdict::Accept
+        );
+        assert_eq!(
+            policy.review(&[
+                origin("10.0.0.0/8-24"),
+                origin("10.1.0.0/16"),
+                origin("2001:db8::/32-128"),
+            ]),
+            HookVerdict::Warn(
+                "excessive maxLength: 10.0.0.0/8-24, 2001:db8::/32-128".into()
+            )
+        );
+        policy.set_action(MaxLengthAction::Reject);
+        assert_eq!(
+            policy.review(&[origin("10.0.0.0/8-24")]),
+            HookVerdict::Reject("excessive maxLength: 10.0.0.0/8-24".into())
+        );
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "rpki_roa_excessive_max_length_total \
+                    [(\"action\", \"warn\")] 2",
+                "rpki_roa_excessive_max_length_total \
+                    [(\"action\", \"reject\")] 1",
+            ]
+        );
+    }
+}
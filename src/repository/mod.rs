@@ -17,14 +17,32 @@ pub use self::tal::Tal;
 
 //--- Modules
 //
+pub mod analysis;
 pub mod aspa;
+pub mod bench;
+pub mod cache;
 pub mod cert;
+pub mod covering;
 pub mod crl;
+pub mod describe;
+pub mod engine;
 pub mod error;
+pub mod gbr;
+pub mod hook;
+pub mod lint;
 pub mod manifest;
+pub mod maxlen;
+pub mod names;
+pub mod options;
+pub mod path;
+pub mod provenance;
+pub mod report;
 pub mod resources;
 pub mod roa;
 pub mod rta;
 pub mod sigobj;
+pub mod tak;
 pub mod tal;
+pub mod timeline;
+pub mod tree;
 pub mod x509;
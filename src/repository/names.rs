@@ -0,0 +1,394 @@
+//! Conformance checks for object file names and URIs.
+//!
+//! Section 4.2.2 of [RFC 9286] limits the names of the files listed on a
+//! manifest to a sequence of letters, digits, hyphens, and underscores
+//! followed by a full stop and a three letter extension registered for the
+//! object type. Names outside of this character set are handled very
+//! differently by relying parties – some reject the manifest, some the
+//! object, and some just fail to find the file – so publishing them is a
+//! bad idea even though this crate’s manifest parser accepts some of them.
+//!
+//! This module provides functions for checking names and rsync URIs as
+//! well as a [`NamePolicy`] that decides whether a non-conforming name is
+//! rejected or merely reported as a warning.
+//!
+//! It also provides functions for deriving the conventional names of
+//! objects from key identifiers: certificates are named after their
+//! subject key, CRLs and manifests after the key of the issuing CA, and
+//! signed objects such as ROAs after the key of their EE certificate.
+//! [`KeyNameStyle`] selects how the key identifier is written.
+//!
+//! [RFC 9286]: https://tools.ietf.org/html/rfc9286
+
+use std::{error, fmt};
+use crate::uri;
+use crate::crypto::KeyIdentifier;
+use crate::util::base64;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The file name extensions registered for RPKI objects.
+///
+/// These are the extensions from the IANA “RPKI Repository Name Schemes”
+/// registry.
+pub const RPKI_EXTENSIONS: &[&str] = &[
+    "asa", "cer", "crl", "gbr", "mft", "roa", "sig", "tak",
+];
+
+
+//------------ Functions -----------------------------------------------------
+
+/// Checks that a file name conforms to RFC 9286.
+///
+/// If `expected` is given, the name must have this extension. Otherwise,
+/// it must have one of the [`RPKI_EXTENSIONS`].
+pub fn check_file_name(
+    name: &[u8], expected: Option<&'static str>,
+) -> Result<(), NameError> {
+    let err = |kind| NameError::new(name, kind);
+    let dot = match name.iter().rposition(|&ch| ch == b'.') {
+        Some(dot) => dot,
+        None => return Err(err(NameErrorKind::MissingExtension)),
+    };
+    let (stem, ext) = (&name[..dot], &name[dot + 1..]);
+    if stem.is_empty() {
+        return Err(err(NameErrorKind::EmptyStem))
+    }
+    if !stem.iter().all(|&ch| {
+        ch.is_ascii_alphanumeric() || ch == b'-' || ch == b'_'
+    }) {
+        return Err(err(NameErrorKind::InvalidCharacter))
+    }
+    if ext.len() != 3 || !ext.iter().all(u8::is_ascii_lowercase) {
+        return Err(err(NameErrorKind::InvalidExtension))
+    }
+    match expected {
+        Some(expected) => {
+            if ext != expected.as_bytes() {
+                return Err(err(NameErrorKind::WrongExtension(expected)))
+            }
+        }
+        None => {
+            if !RPKI_EXTENSIONS.iter().any(|known| known.as_bytes() == ext) {
+                return Err(err(NameErrorKind::UnknownExtension))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the file name of an rsync URI conforms to RFC 9286.
+///
+/// The file name is the last segment of the URI’s path. The URI must not
+/// refer to a directory. See [`check_file_name`] for the meaning of
+/// `expected`.
+pub fn check_uri(
+    uri: &uri::Rsync, expected: Option<&'static str>,
+) -> Result<(), NameError> {
+    let path = uri.path();
+    let name = match path.rsplit_once('/') {
+        Some((_, name)) => name,
+        None => path,
+    };
+    check_file_name(name.as_bytes(), expected).map_err(|mut err| {
+        err.name = uri.as_str().into();
+        err
+    })
+}
+
+
+/// Returns the conventional file name of a certificate.
+///
+/// The name is derived from the certificate’s subject key identifier
+/// using [`KeyNameStyle::Hex`].
+pub fn cert_name(subject_key: &KeyIdentifier) -> String {
+    KeyNameStyle::Hex.file_name(subject_key, "cer")
+}
+
+/// Returns the conventional file name of a CA’s CRL.
+///
+/// The name is derived from the CA’s key identifier using
+/// [`KeyNameStyle::Hex`].
+pub fn crl_name(ca_key: &KeyIdentifier) -> String {
+    KeyNameStyle::Hex.file_name(ca_key, "crl")
+}
+
+/// Returns the conventional file name of a CA’s manifest.
+///
+/// The name is derived from the CA’s key identifier using
+/// [`KeyNameStyle::Hex`].
+pub fn manifest_name(ca_key: &KeyIdentifier) -> String {
+    KeyNameStyle::Hex.file_name(ca_key, "mft")
+}
+
+/// Returns the conventional file name of a ROA.
+///
+/// The name is derived from the subject key identifier of the ROA’s EE
+/// certificate using [`KeyNameStyle::Hex`].
+pub fn roa_name(ee_key: &KeyIdentifier) -> String {
+    KeyNameStyle::Hex.file_name(ee_key, "roa")
+}
+
+
+//------------ KeyNameStyle --------------------------------------------------
+
+/// How a key identifier is written in a file name.
+///
+/// Both styles produce names conforming to RFC 9286.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum KeyNameStyle {
+    /// The key identifier in upper case hex digits.
+    ///
+    /// This gives 40 character names and is used by this crate’s
+    /// publication point builder by default.
+    #[default]
+    Hex,
+
+    /// The key identifier in URL-safe Base64 without padding.
+    ///
+    /// This gives 27 character names.
+    Base64Url,
+}
+
+impl KeyNameStyle {
+    /// Returns the file name for a key identifier and extension.
+    pub fn file_name(self, key: &KeyIdentifier, ext: &str) -> String {
+        match self {
+            KeyNameStyle::Hex => format!("{}.{}", key, ext),
+            KeyNameStyle::Base64Url => {
+                format!("{}.{}", base64::Slurm.encode(key.as_slice()), ext)
+            }
+        }
+    }
+}
+
+
+//------------ NamePolicy ----------------------------------------------------
+
+/// What to do with names that don’t conform to RFC 9286.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum NamePolicy {
+    /// Non-conforming names are rejected.
+    #[default]
+    Reject,
+
+    /// Non-conforming names are reported as warnings only.
+    Warn,
+}
+
+impl NamePolicy {
+    /// Applies the policy to the outcome of a check.
+    ///
+    /// If the policy is to warn, a failed check is added to `warnings`
+    /// and `Ok(())` returned. Otherwise the outcome is returned unchanged.
+    pub fn apply(
+        self, res: Result<(), NameError>, warnings: &mut Vec<NameError>,
+    ) -> Result<(), NameError> {
+        match (self, res) {
+            (NamePolicy::Warn, Err(err)) => {
+                warnings.push(err);
+                Ok(())
+            }
+            (_, res) => res
+        }
+    }
+
+    /// Checks a sequence of file names.
+    ///
+    /// Returns the warnings for non-conforming names if the policy is to
+    /// warn or the first error otherwise.
+    pub fn check_file_names<'a>(
+        self,
+        names: impl IntoIterator<Item = &'a [u8]>,
+        expected: Option<&'static str>,
+    ) -> Result<Vec<NameError>, NameError> {
+        let mut warnings = Vec::new();
+        for name in names {
+            self.apply(check_file_name(name, expected), &mut warnings)?;
+        }
+        Ok(warnings)
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ NameError -----------------------------------------------------
+
+/// A file name or URI does not conform to RFC 9286.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameError {
+    /// The offending name or URI.
+    name: String,
+
+    /// What is wrong with it.
+    kind: NameErrorKind,
+}
+
+/// What is wrong with a name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum NameErrorKind {
+    /// The name has no extension.
+    MissingExtension,
+
+    /// The name consists of an extension only.
+    EmptyStem,
+
+    /// The name contains a character outside of the permitted set.
+    InvalidCharacter,
+
+    /// The extension is not three lower case letters.
+    InvalidExtension,
+
+    /// The extension is not one registered for RPKI objects.
+    UnknownExtension,
+
+    /// The extension is not the one expected for the object type.
+    WrongExtension(&'static str),
+}
+
+impl NameError {
+    fn new(name: &[u8], kind: NameErrorKind) -> Self {
+        NameError { name: String::from_utf8_lossy(name).into(), kind }
+    }
+
+    /// Returns the offending name or URI.
+    ///
+    /// Invalid UTF-8 sequences are replaced with the replacement character.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns what is wrong with the name.
+    pub fn kind(&self) -> NameErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid file name '{}': ", self.name)?;
+        match self.kind {
+            NameErrorKind::MissingExtension => {
+                f.write_str("missing extension")
+            }
+            NameErrorKind::EmptyStem => f.write_str("empty name"),
+            NameErrorKind::InvalidCharacter => {
+                f.write_str("invalid character")
+            }
+            NameErrorKind::InvalidExtension => {
+                f.write_str("invalid extension")
+            }
+            NameErrorKind::UnknownExtension => {
+                f.write_str("unknown extension")
+            }
+            NameErrorKind::WrongExtension(expected) => {
+                write!(f, "expected extension '{}'", expected)
+            }
+        }
+    }
+}
+
+impl error::Error for NameError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn kind(
+        name: &str, expected: Option<&'static str>
+    ) -> Option<NameErrorKind> {
+        check_file_name(name.as_bytes(), expected).err().map(|err| {
+            err.kind()
+        })
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(kind("ca1.cer", None), None);
+        assert_eq!(kind("8PLrh-R_iY.roa", Some("roa")), None);
+        assert_eq!(kind("foo", None), Some(NameErrorKind::MissingExtension));
+        assert_eq!(kind(".cer", None), Some(NameErrorKind::EmptyStem));
+        assert_eq!(
+            kind("foo bar.cer", None), Some(NameErrorKind::InvalidCharacter)
+        );
+        assert_eq!(
+            kind("foo.bar.cer", None), Some(NameErrorKind::InvalidCharacter)
+        );
+        assert_eq!(
+            kind("../ca1.cer", None), Some(NameErrorKind::InvalidCharacter)
+        );
+        assert_eq!(
+            kind("foo.CER", None), Some(NameErrorKind::InvalidExtension)
+        );
+        assert_eq!(
+            kind("foo.cerx", None), Some(NameErrorKind::InvalidExtension)
+        );
+        assert_eq!(
+            kind("foo.txt", None), Some(NameErrorKind::UnknownExtension)
+        );
+        assert_eq!(
+            kind("foo.roa", Some("mft")),
+            Some(NameErrorKind::WrongExtension("mft"))
+        );
+    }
+
+    #[test]
+    fn uris() {
+        let uri = |s| uri::Rsync::from_str(s).unwrap();
+        assert!(
+            check_uri(&uri("rsync://example.com/m/ca/a.mft"), Some("mft"))
+            .is_ok()
+        );
+        let err = check_uri(
+            &uri("rsync://example.com/m/ca/a.roa"), Some("mft")
+        ).unwrap_err();
+        assert_eq!(err.name(), "rsync://example.com/m/ca/a.roa");
+        assert!(check_uri(&uri("rsync://example.com/m/ca/"), None).is_err());
+    }
+
+    #[test]
+    fn key_names() {
+        let key = KeyIdentifier::try_from(
+            [
+                0x2a, 0x7d, 0xd1, 0xd7, 0x87, 0xd7, 0x93, 0xe4, 0xc8, 0xaf,
+                0x56, 0xe1, 0x97, 0xd4, 0xee, 0xd9, 0x2a, 0xf6, 0xba, 0x13,
+            ].as_ref()
+        ).unwrap();
+        assert_eq!(
+            cert_name(&key), "2A7DD1D787D793E4C8AF56E197D4EED92AF6BA13.cer"
+        );
+        assert_eq!(
+            KeyNameStyle::Base64Url.file_name(&key, "mft"),
+            "Kn3R14fXk-TIr1bhl9Tu2Sr2uhM.mft"
+        );
+        for name in [
+            cert_name(&key), crl_name(&key), manifest_name(&key),
+            roa_name(&key), KeyNameStyle::Base64Url.file_name(&key, "roa"),
+        ] {
+            assert!(check_file_name(name.as_bytes(), None).is_ok());
+        }
+    }
+
+    #[test]
+    fn policy() {
+        let names: [&[u8]; 3] = [b"a.roa", b"b c.roa", b"d.txt"];
+        assert_eq!(
+            NamePolicy::Reject.check_file_names(
+                names.iter().copied(), None
+            ).unwrap_err().kind(),
+            NameErrorKind::InvalidCharacter
+        );
+        let warnings = NamePolicy::Warn.check_file_names(
+            names.iter().copied(), None
+        ).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[1].name(), "d.txt");
+    }
+}
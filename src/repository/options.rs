@@ -0,0 +1,675 @@
+//! Options for validating repository objects.
+//!
+//! The validation methods of the various object types mostly take a single
+//! `strict` flag. This module provides [`ValidationOptions`] which collects
+//! additional choices a relying party may want to make, currently grace
+//! periods for objects whose time fields just barely fail.
+//!
+//! Publication is not always perfectly punctual: a manifest or CRL may be
+//! re-issued a bit late or a new object may appear before a relying party’s
+//! clock agrees that it is valid. Rejecting these objects outright results
+//! in all-or-nothing behavior for everything that depends on them. With a
+//! grace period, such an object is accepted for a while longer and a
+//! [`GraceWarning`] is reported instead.
+//!
+//! Similarly, [`SigningTimeWindow`] limits how far the signing time given
+//! in the signed attributes of a CMS object may lie in the past or the
+//! future. Violations are reported as a [`SigningTimeError`].
+//!
+//! Finally, [`ValidationLimits`] protects against hostile repositories
+//! trying to exhaust a relying party’s resources with huge objects,
+//! manifests with millions of entries, or endlessly deep or wide CA
+//! trees. Objects exceeding a limit are rejected with a [`LimitError`].
+
+use std::{error, fmt};
+use std::convert::TryFrom;
+use std::time::Duration;
+use chrono::{TimeZone, Utc};
+use crate::crypto::digest::{self, DigestAlgorithm};
+use super::error::VerificationError;
+use super::x509::Time;
+
+
+//------------ ValidationOptions ---------------------------------------------
+
+/// Options for validating repository objects.
+///
+/// The default options use non-strict validation and no grace periods.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ValidationOptions {
+    /// Whether to perform strict validation.
+    ///
+    /// This is the value passed as the `strict` argument to the various
+    /// validation methods.
+    pub strict: bool,
+
+    /// How long to accept a manifest after its next update time passed.
+    pub stale_manifest_grace: Duration,
+
+    /// How long to accept a CRL after its next update time passed.
+    pub stale_crl_grace: Duration,
+
+    /// How long before its not-before time to accept a certificate.
+    pub not_yet_valid_grace: Duration,
+
+    /// The acceptable signing times of signed objects.
+    pub signing_time: SigningTimeWindow,
+
+    /// The limits for the size and shape of the repository.
+    pub limits: ValidationLimits,
+}
+
+impl ValidationOptions {
+    /// Checks the next update time of a manifest.
+    ///
+    /// Returns an error if the manifest is stale beyond the grace period
+    /// and a warning if it is stale but within it.
+    pub fn check_manifest_next_update(
+        &self, next_update: Time, now: Time,
+    ) -> Result<Option<GraceWarning>, VerificationError> {
+        check_deadline(
+            GraceKind::StaleManifest, next_update,
+            self.stale_manifest_grace, now
+        ).map_err(|_| VerificationError::new("stale manifest"))
+    }
+
+    /// Checks the next update time of a CRL.
+    ///
+    /// Returns an error if the CRL is stale beyond the grace period and a
+    /// warning if it is stale but within it.
+    pub fn check_crl_next_update(
+        &self, next_update: Time, now: Time,
+    ) -> Result<Option<GraceWarning>, VerificationError> {
+        check_deadline(
+            GraceKind::StaleCrl, next_update, self.stale_crl_grace, now
+        ).map_err(|_| VerificationError::new("stale CRL"))
+    }
+
+    /// Returns the time to use for validating a certificate.
+    ///
+    /// If the certificate’s not-before time is in the future but within
+    /// the grace period, returns that time and a warning. Validating the
+    /// certificate at this time will then succeed if it is otherwise
+    /// valid. Returns `now` and no warning in all other cases, leaving it
+    /// to the validation to reject a certificate that is not yet valid.
+    ///
+    /// Note that this moves the validation time forward for all checks.
+    /// Since the not-after time is always later than the not-before time,
+    /// this only affects certificates that are not yet valid.
+    pub fn validation_time(
+        &self, not_before: Time, now: Time,
+    ) -> (Time, Option<GraceWarning>) {
+        if not_before <= now {
+            return (now, None)
+        }
+        match not_before.signed_duration_since(*now).to_std() {
+            Ok(early) if early <= self.not_yet_valid_grace => {
+                (not_before, Some(GraceWarning::new(
+                    GraceKind::NotYetValid, not_before
+                )))
+            }
+            _ => (now, None)
+        }
+    }
+
+    /// Returns a fingerprint of the options.
+    ///
+    /// Two sets of options have the same fingerprint if they are equal.
+    /// The fingerprint is stable across runs and platforms, so it can be
+    /// used to identify validation results that were produced with a given
+    /// set of options, e.g., in a
+    /// [`ValidationCache`][super::cache::ValidationCache].
+    pub fn fingerprint(&self) -> u64 {
+        fn limit(ctx: &mut digest::Context, value: Option<usize>) {
+            match value {
+                Some(value) => {
+                    ctx.update(&[1]);
+                    ctx.update(&(value as u64).to_be_bytes());
+                }
+                None => ctx.update(&[0])
+            }
+        }
+
+        fn duration(ctx: &mut digest::Context, value: Option<Duration>) {
+            match value {
+                Some(value) => {
+                    ctx.update(&[1]);
+                    ctx.update(&value.as_secs().to_be_bytes());
+                    ctx.update(&value.subsec_nanos().to_be_bytes());
+                }
+                None => ctx.update(&[0])
+            }
+        }
+
+        let mut ctx = DigestAlgorithm::sha256().start();
+        ctx.update(&[self.strict.into()]);
+        duration(&mut ctx, Some(self.stale_manifest_grace));
+        duration(&mut ctx, Some(self.stale_crl_grace));
+        duration(&mut ctx, Some(self.not_yet_valid_grace));
+        duration(&mut ctx, self.signing_time.max_age);
+        duration(&mut ctx, self.signing_time.max_skew);
+        ctx.update(&[self.signing_time.required.into()]);
+        limit(&mut ctx, self.limits.max_object_size);
+        limit(&mut ctx, self.limits.max_manifest_entries);
+        limit(&mut ctx, self.limits.max_depth);
+        limit(&mut ctx, self.limits.max_children);
+        let mut res = [0u8; 8];
+        res.copy_from_slice(&ctx.finish().as_ref()[..8]);
+        u64::from_be_bytes(res)
+    }
+}
+
+/// Checks that `now` isn’t past `deadline` plus `grace`.
+fn check_deadline(
+    kind: GraceKind, deadline: Time, grace: Duration, now: Time,
+) -> Result<Option<GraceWarning>, ()> {
+    if now <= deadline {
+        return Ok(None)
+    }
+    match now.signed_duration_since(*deadline).to_std() {
+        Ok(late) if late <= grace => {
+            Ok(Some(GraceWarning::new(kind, deadline)))
+        }
+        _ => Err(())
+    }
+}
+
+
+//------------ GraceWarning --------------------------------------------------
+
+/// An object was accepted only because of a grace period.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GraceWarning {
+    /// The reason the object was outside of its validity.
+    kind: GraceKind,
+
+    /// The time the object should have been valid from or until.
+    deadline: Time,
+}
+
+impl GraceWarning {
+    /// Creates a new warning.
+    pub fn new(kind: GraceKind, deadline: Time) -> Self {
+        GraceWarning { kind, deadline }
+    }
+
+    /// Returns the reason for the warning.
+    pub fn kind(&self) -> GraceKind {
+        self.kind
+    }
+
+    /// Returns the time that was missed.
+    ///
+    /// For stale objects, this is their next update time. For objects not
+    /// yet valid, it is their not-before time.
+    pub fn deadline(&self) -> Time {
+        self.deadline
+    }
+}
+
+impl fmt::Display for GraceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            GraceKind::StaleManifest => {
+                write!(f, "manifest stale since {}", *self.deadline)
+            }
+            GraceKind::StaleCrl => {
+                write!(f, "CRL stale since {}", *self.deadline)
+            }
+            GraceKind::NotYetValid => {
+                write!(f,
+                    "certificate not valid before {}", *self.deadline
+                )
+            }
+        }
+    }
+}
+
+
+//------------ GraceKind -----------------------------------------------------
+
+/// The reason an object needed a grace period.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum GraceKind {
+    /// A manifest’s next update time has passed.
+    StaleManifest,
+
+    /// A CRL’s next update time has passed.
+    StaleCrl,
+
+    /// A certificate’s not-before time has not been reached yet.
+    NotYetValid,
+}
+
+
+//------------ SigningTimeWindow ---------------------------------------------
+
+/// The acceptable range for the signing time of a CMS object.
+///
+/// The signed attributes of a CMS object can contain the time of signing
+/// both as a signing-time attribute (RFC 5652) and a binary-signing-time
+/// attribute (RFC 6019). Both are optional. If present, each of them is
+/// checked against the window.
+///
+/// The default window accepts any signing time and also objects without
+/// one.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SigningTimeWindow {
+    /// How far in the past the signing time may be.
+    ///
+    /// If this is `None`, there is no limit.
+    pub max_age: Option<Duration>,
+
+    /// How far in the future the signing time may be.
+    ///
+    /// This allows for clock skew between signer and validator. If this is
+    /// `None`, there is no limit.
+    pub max_skew: Option<Duration>,
+
+    /// Whether to reject objects that don’t have a signing time.
+    pub required: bool,
+}
+
+impl SigningTimeWindow {
+    /// Creates a window accepting signing times around `now`.
+    ///
+    /// The window accepts signing times up to `max_age` in the past and
+    /// `max_skew` in the future and requires a signing time to be present.
+    pub fn new(max_age: Duration, max_skew: Duration) -> Self {
+        SigningTimeWindow {
+            max_age: Some(max_age),
+            max_skew: Some(max_skew),
+            required: true,
+        }
+    }
+
+    /// Checks the signing times of an object.
+    ///
+    /// The arguments are the values of the signing-time and
+    /// binary-signing-time attributes, respectively.
+    pub fn check(
+        &self,
+        signing_time: Option<Time>,
+        binary_signing_time: Option<u64>,
+        now: Time,
+    ) -> Result<(), SigningTimeError> {
+        if signing_time.is_none() && binary_signing_time.is_none() {
+            if self.required {
+                return Err(SigningTimeError::Missing)
+            }
+            return Ok(())
+        }
+        if let Some(time) = signing_time {
+            self.check_time(time, now)?;
+        }
+        if let Some(time) = binary_signing_time {
+            self.check_time(binary_time(time)?, now)?;
+        }
+        Ok(())
+    }
+
+    /// Checks a single signing time.
+    fn check_time(
+        &self, time: Time, now: Time
+    ) -> Result<(), SigningTimeError> {
+        if let Some(max_age) = self.max_age {
+            if exceeds(now, time, max_age) {
+                return Err(SigningTimeError::TooOld(time))
+            }
+        }
+        if let Some(max_skew) = self.max_skew {
+            if exceeds(time, now, max_skew) {
+                return Err(SigningTimeError::FromFuture(time))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `later` is more than `limit` after `earlier`.
+fn exceeds(later: Time, earlier: Time, limit: Duration) -> bool {
+    if later <= earlier {
+        return false
+    }
+    match later.signed_duration_since(*earlier).to_std() {
+        Ok(diff) => diff > limit,
+        Err(_) => true
+    }
+}
+
+/// Converts a binary signing time into a time value.
+///
+/// The binary signing time is the number of seconds since the Unix epoch.
+pub(crate) fn binary_time(time: u64) -> Result<Time, SigningTimeError> {
+    i64::try_from(time).ok().and_then(|time| {
+        Utc.timestamp_opt(time, 0).single()
+    }).map(Time::new).ok_or(SigningTimeError::Invalid(time))
+}
+
+
+//------------ ValidationLimits ----------------------------------------------
+
+/// Limits for the size and shape of a repository.
+///
+/// Each limit is optional. The default limits don’t limit anything.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ValidationLimits {
+    /// The maximum size of an object in octets.
+    pub max_object_size: Option<usize>,
+
+    /// The maximum number of entries on a manifest.
+    pub max_manifest_entries: Option<usize>,
+
+    /// The maximum depth of a CA in the tree.
+    ///
+    /// The trust anchor has depth 0, the CAs it issued certificates to
+    /// have depth 1, and so on.
+    pub max_depth: Option<usize>,
+
+    /// The maximum number of certificates issued by a single CA.
+    pub max_children: Option<usize>,
+}
+
+impl ValidationLimits {
+    /// Checks the size of an object.
+    pub fn check_object_size(&self, size: usize) -> Result<(), LimitError> {
+        check_limit(LimitKind::ObjectSize, size, self.max_object_size)
+    }
+
+    /// Checks the number of entries on a manifest.
+    pub fn check_manifest_entries(
+        &self, entries: usize
+    ) -> Result<(), LimitError> {
+        check_limit(
+            LimitKind::ManifestEntries, entries, self.max_manifest_entries
+        )
+    }
+
+    /// Checks the depth of a CA in the tree.
+    ///
+    /// The trust anchor has depth 0, the CAs it issued certificates to
+    /// have depth 1, and so on.
+    pub fn check_depth(&self, depth: usize) -> Result<(), LimitError> {
+        check_limit(LimitKind::Depth, depth, self.max_depth)
+    }
+
+    /// Checks the number of certificates issued by a CA so far.
+    pub fn check_children(&self, children: usize) -> Result<(), LimitError> {
+        check_limit(LimitKind::Children, children, self.max_children)
+    }
+}
+
+/// Checks that `value` doesn’t exceed `limit`.
+fn check_limit(
+    kind: LimitKind, value: usize, limit: Option<usize>
+) -> Result<(), LimitError> {
+    match limit {
+        Some(limit) if value > limit => {
+            Err(LimitError { kind, value, limit })
+        }
+        _ => Ok(())
+    }
+}
+
+
+//------------ LimitKind -----------------------------------------------------
+
+/// The limit that was exceeded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum LimitKind {
+    /// The object is too large.
+    ObjectSize,
+
+    /// The manifest has too many entries.
+    ManifestEntries,
+
+    /// The CA is too deep down the tree.
+    Depth,
+
+    /// The CA issued too many certificates.
+    Children,
+}
+
+
+//------------ SigningTimeError ----------------------------------------------
+
+/// The signing time of a CMS object is not acceptable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SigningTimeError {
+    /// The object has no signing time but one is required.
+    Missing,
+
+    /// The binary signing time cannot be represented.
+    Invalid(u64),
+
+    /// The object was signed too long ago.
+    TooOld(Time),
+
+    /// The object’s signing time is too far in the future.
+    FromFuture(Time),
+
+    /// The signing time isn’t later than that of a previous object.
+    ///
+    /// This is used to detect replayed messages. The value is the signing
+    /// time of the latest message seen before.
+    NotMonotonic(Time),
+}
+
+impl fmt::Display for SigningTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SigningTimeError::Missing => f.write_str("missing signing time"),
+            SigningTimeError::Invalid(time) => {
+                write!(f, "invalid binary signing time {}", time)
+            }
+            SigningTimeError::TooOld(time) => {
+                write!(f, "signing time {} is too old", *time)
+            }
+            SigningTimeError::FromFuture(time) => {
+                write!(f, "signing time {} is in the future", *time)
+            }
+            SigningTimeError::NotMonotonic(time) => {
+                write!(f,
+                    "signing time not after previous signing time {}", *time
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for SigningTimeError { }
+
+
+//------------ LimitError ----------------------------------------------------
+
+/// A limit of [`ValidationLimits`] was exceeded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LimitError {
+    /// The limit that was exceeded.
+    kind: LimitKind,
+
+    /// The actual value.
+    value: usize,
+
+    /// The value of the limit.
+    limit: usize,
+}
+
+impl LimitError {
+    /// Returns which limit was exceeded.
+    pub fn kind(&self) -> LimitKind {
+        self.kind
+    }
+
+    /// Returns the value that exceeded the limit.
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    /// Returns the limit.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LimitKind::ObjectSize => {
+                write!(f,
+                    "object size of {} octets exceeds limit of {}",
+                    self.value, self.limit
+                )
+            }
+            LimitKind::ManifestEntries => {
+                write!(f,
+                    "{} manifest entries exceed limit of {}",
+                    self.value, self.limit
+                )
+            }
+            LimitKind::Depth => {
+                write!(f,
+                    "CA tree depth of {} exceeds limit of {}",
+                    self.value, self.limit
+                )
+            }
+            LimitKind::Children => {
+                write!(f,
+                    "{} certificates issued by CA exceed limit of {}",
+                    self.value, self.limit
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for LimitError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grace_periods() {
+        let deadline = Time::utc(2019, 5, 1, 0, 0, 0);
+        let options = ValidationOptions {
+            stale_manifest_grace: Duration::from_secs(3600),
+            not_yet_valid_grace: Duration::from_secs(60),
+            .. Default::default()
+        };
+
+        assert_eq!(
+            options.check_manifest_next_update(
+                deadline, Time::utc(2019, 4, 30, 0, 0, 0)
+            ).unwrap(),
+            None
+        );
+        assert_eq!(
+            options.check_manifest_next_update(
+                deadline, Time::utc(2019, 5, 1, 0, 30, 0)
+            ).unwrap(),
+            Some(GraceWarning::new(GraceKind::StaleManifest, deadline))
+        );
+        assert!(
+            options.check_manifest_next_update(
+                deadline, Time::utc(2019, 5, 1, 1, 30, 0)
+            ).is_err()
+        );
+        assert!(
+            options.check_crl_next_update(
+                deadline, Time::utc(2019, 5, 1, 0, 30, 0)
+            ).is_err()
+        );
+
+        let now = Time::utc(2019, 4, 30, 23, 59, 30);
+        assert_eq!(
+            options.validation_time(deadline, now),
+            (
+                deadline,
+                Some(GraceWarning::new(GraceKind::NotYetValid, deadline))
+            )
+        );
+        let now = Time::utc(2019, 4, 30, 23, 58, 0);
+        assert_eq!(options.validation_time(deadline, now), (now, None));
+        let now = Time::utc(2019, 5, 2, 0, 0, 0);
+        assert_eq!(options.validation_time(deadline, now), (now, None));
+    }
+
+    #[test]
+    fn signing_time_window() {
+        let now = Time::utc(2019, 5, 1, 0, 0, 0);
+        let window = SigningTimeWindow::new(
+            Duration::from_secs(3600), Duration::from_secs(60)
+        );
+
+        assert!(window.check(Some(now), None, now).is_ok());
+        assert!(
+            window.check(
+                Some(Time::utc(2019, 4, 30, 23, 30, 0)), None, now
+            ).is_ok()
+        );
+        assert_eq!(
+            window.check(
+                Some(Time::utc(2019, 4, 30, 22, 0, 0)), None, now
+            ),
+            Err(SigningTimeError::TooOld(Time::utc(2019, 4, 30, 22, 0, 0)))
+        );
+        assert!(
+            window.check(Some(Time::utc(2019, 5, 1, 0, 0, 30)), None, now)
+            .is_ok()
+        );
+        assert_eq!(
+            window.check(
+                Some(Time::utc(2019, 5, 1, 0, 5, 0)), None, now
+            ),
+            Err(SigningTimeError::FromFuture(Time::utc(2019, 5, 1, 0, 5, 0)))
+        );
+
+        // Both attributes are checked.
+        assert_eq!(
+            window.check(Some(now), Some(now.timestamp() as u64 + 300), now),
+            Err(SigningTimeError::FromFuture(Time::utc(2019, 5, 1, 0, 5, 0)))
+        );
+        assert!(
+            window.check(None, Some(now.timestamp() as u64), now).is_ok()
+        );
+
+        assert_eq!(
+            window.check(None, None, now), Err(SigningTimeError::Missing)
+        );
+        assert!(SigningTimeWindow::default().check(None, None, now).is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        let limits = ValidationLimits {
+            max_object_size: Some(100),
+            max_children: Some(2),
+            .. Default::default()
+        };
+        assert!(limits.check_object_size(100).is_ok());
+        let err = limits.check_object_size(101).unwrap_err();
+        assert_eq!(err.kind(), LimitKind::ObjectSize);
+        assert_eq!((err.value(), err.limit()), (101, 100));
+        assert!(limits.check_children(2).is_ok());
+        assert_eq!(
+            limits.check_children(3).unwrap_err().to_string(),
+            "3 certificates issued by CA exceed limit of 2"
+        );
+        assert!(limits.check_manifest_entries(usize::MAX).is_ok());
+        assert!(limits.check_depth(usize::MAX).is_ok());
+
+        let options = ValidationOptions {
+            limits, .. Default::default()
+        };
+        assert_ne!(
+            options.fingerprint(), ValidationOptions::default().fingerprint()
+        );
+    }
+}
@@ -0,0 +1,531 @@
+//! Constructing certification paths.
+//!
+//! The RPKI is usually validated top-down: starting at a trust anchor, one
+//! walks down the publication points following the certificates published
+//! there. Sometimes, however, one has a single certificate and a heap of
+//! other certificates and wants to know how – and whether – it chains up to
+//! a trust anchor.
+//!
+//! This module provides the types for this. A [`CertPool`] collects trust
+//! anchor and intermediate CA certificates. Its
+//! [`build_path`][CertPool::build_path] method tries to find a chain from a
+//! given certificate to one of the trust anchors by matching the authority
+//! key identifier and issuer name of a certificate with the subject key
+//! identifier and subject name of its candidate issuer. The result is a
+//! [`CertPath`] which can then be validated in full using the regular
+//! validation methods of [`Cert`].
+//!
+//! If no path can be found or the path found is not valid, a [`PathError`]
+//! describes what exactly went wrong.
+
+use std::{error, fmt};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::crypto::keys::KeyIdentifier;
+use super::cert::{Cert, ResourceCert};
+use super::error::ValidationError;
+use super::tal::TalInfo;
+use super::x509::Time;
+
+
+//------------ CertPool ------------------------------------------------------
+
+/// A collection of certificates to build certification paths from.
+///
+/// The pool contains two kinds of certificates: trust anchor certificates
+/// added via [`add_trust_anchor`][Self::add_trust_anchor] and intermediate
+/// CA certificates added via [`add_cert`][Self::add_cert]. Only paths that
+/// end in a trust anchor certificate are considered complete.
+#[derive(Clone, Debug)]
+pub struct CertPool {
+    /// All certificates in the pool.
+    certs: Vec<PoolCert>,
+
+    /// The indexes into `certs` by subject key identifier.
+    by_ski: HashMap<KeyIdentifier, Vec<usize>>,
+
+    /// The maximum number of certificates in a path.
+    max_depth: usize,
+}
+
+/// A certificate in the pool.
+#[derive(Clone, Debug)]
+struct PoolCert {
+    /// The certificate itself.
+    cert: Cert,
+
+    /// The TAL information if this is a trust anchor certificate.
+    tal: Option<Arc<TalInfo>>,
+}
+
+impl CertPool {
+    /// The default maximum number of certificates in a path.
+    pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+    /// Creates a new, empty pool.
+    pub fn new() -> Self {
+        CertPool {
+            certs: Vec::new(),
+            by_ski: HashMap::new(),
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Sets the maximum number of certificates in a path.
+    ///
+    /// The number includes both the trust anchor certificate and the
+    /// certificate a path is built for.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth
+    }
+
+    /// Adds a trust anchor certificate to the pool.
+    pub fn add_trust_anchor(&mut self, cert: Cert, tal: Arc<TalInfo>) {
+        self.add(PoolCert { cert, tal: Some(tal) })
+    }
+
+    /// Adds an intermediate CA certificate to the pool.
+    pub fn add_cert(&mut self, cert: Cert) {
+        self.add(PoolCert { cert, tal: None })
+    }
+
+    fn add(&mut self, cert: PoolCert) {
+        self.by_ski.entry(
+            cert.cert.subject_key_identifier()
+        ).or_default().push(self.certs.len());
+        self.certs.push(cert);
+    }
+
+    /// Returns the number of certificates in the pool.
+    pub fn len(&self) -> usize {
+        self.certs.len()
+    }
+
+    /// Returns whether the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty()
+    }
+
+    /// Builds a certification path from `cert` to a trust anchor.
+    ///
+    /// The path is constructed by matching identifiers only. Signatures,
+    /// validity, and resources are not checked – use
+    /// [`CertPath::validate`] for that.
+    ///
+    /// If there are several candidate issuers for a certificate, all of
+    /// them are tried in the order they were added to the pool and the
+    /// first complete path is returned. If none of them leads to a trust
+    /// anchor, the error encountered for the first candidate is returned.
+    pub fn build_path(&self, cert: &Cert) -> Result<CertPath, PathError> {
+        let mut path = Vec::new();
+        let tal = self.extend_path(cert, &mut path)?;
+        let mut certs = Vec::with_capacity(path.len() + 1);
+        certs.push(cert.clone());
+        certs.extend(path.into_iter().map(|idx| {
+            self.certs[idx].cert.clone()
+        }));
+        Ok(CertPath { certs, tal })
+    }
+
+    /// Extends `path` with issuers of `cert` up to a trust anchor.
+    ///
+    /// Pushes the indexes of the issuers onto `path` and returns the TAL of
+    /// the trust anchor upon success. Upon error, `path` is left the way it
+    /// was.
+    fn extend_path(
+        &self, cert: &Cert, path: &mut Vec<usize>,
+    ) -> Result<Arc<TalInfo>, PathError> {
+        // A trust anchor at the end of the chain?
+        if path.is_empty() {
+            if let Some(tal) = self.find_anchor(cert) {
+                return Ok(tal)
+            }
+        }
+        if cert.is_self_signed() {
+            return Err(PathError::UntrustedRoot {
+                depth: path.len(),
+                ski: cert.subject_key_identifier(),
+            })
+        }
+        if path.len() + 1 >= self.max_depth {
+            return Err(PathError::TooLong(self.max_depth))
+        }
+
+        let aki = match cert.authority_key_identifier() {
+            Some(aki) => aki,
+            None => {
+                return Err(PathError::MissingAki { depth: path.len() })
+            }
+        };
+        let candidates = match self.by_ski.get(&aki) {
+            Some(candidates) => candidates,
+            None => {
+                return Err(PathError::NoIssuer { depth: path.len(), aki })
+            }
+        };
+
+        let mut first_err = None;
+        for &idx in candidates {
+            let issuer = &self.certs[idx];
+            let err = if !issuer.cert.subject().matches(cert.issuer()) {
+                PathError::IssuerNameMismatch { depth: path.len(), aki }
+            }
+            else if !issuer.cert.is_ca() {
+                PathError::IssuerNotCa { depth: path.len(), aki }
+            }
+            else if path.contains(&idx) {
+                PathError::Loop { depth: path.len(), aki }
+            }
+            else if let Some(tal) = issuer.tal.as_ref() {
+                path.push(idx);
+                return Ok(tal.clone())
+            }
+            else {
+                path.push(idx);
+                match self.extend_path(&issuer.cert, path) {
+                    Ok(tal) => return Ok(tal),
+                    Err(err) => {
+                        path.pop();
+                        err
+                    }
+                }
+            };
+            if first_err.is_none() {
+                first_err = Some(err)
+            }
+        }
+        // candidates is never empty, so there is an error by now.
+        Err(first_err.unwrap_or(PathError::NoIssuer {
+            depth: path.len(), aki
+        }))
+    }
+
+    /// Returns the TAL info if `cert` itself is a trust anchor in the pool.
+    fn find_anchor(&self, cert: &Cert) -> Option<Arc<TalInfo>> {
+        self.by_ski.get(
+            &cert.subject_key_identifier()
+        )?.iter().find_map(|&idx| {
+            let anchor = &self.certs[idx];
+            if anchor.cert.as_bytes() == cert.as_bytes() {
+                anchor.tal.clone()
+            }
+            else {
+                None
+            }
+        })
+    }
+}
+
+impl Default for CertPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+//------------ CertPath ------------------------------------------------------
+
+/// A certification path from a certificate to a trust anchor.
+///
+/// The path is ordered from the certificate the path was built for to the
+/// trust anchor certificate. It always contains at least one certificate.
+/// If the path was built for a trust anchor certificate itself, that is the
+/// only certificate.
+///
+/// Values of this type are created via [`CertPool::build_path`].
+#[derive(Clone, Debug)]
+pub struct CertPath {
+    /// The certificates, starting with the leaf.
+    certs: Vec<Cert>,
+
+    /// The TAL the trust anchor belongs to.
+    tal: Arc<TalInfo>,
+}
+
+impl CertPath {
+    /// Returns the certificate the path was built for.
+    pub fn leaf(&self) -> &Cert {
+        self.certs.first().expect("empty certification path")
+    }
+
+    /// Returns the trust anchor certificate.
+    pub fn trust_anchor(&self) -> &Cert {
+        self.certs.last().expect("empty certification path")
+    }
+
+    /// Returns the TAL of the trust anchor.
+    pub fn tal(&self) -> &Arc<TalInfo> {
+        &self.tal
+    }
+
+    /// Returns the number of certificates in the path.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.certs.len()
+    }
+
+    /// Returns an iterator over the certificates from leaf to trust anchor.
+    pub fn iter(&self) -> impl Iterator<Item = &Cert> + '_ {
+        self.certs.iter()
+    }
+
+    /// Returns a slice with the certificates from leaf to trust anchor.
+    pub fn as_slice(&self) -> &[Cert] {
+        &self.certs
+    }
+
+    /// Validates the path.
+    ///
+    /// See [`validate_at`][Self::validate_at] for details.
+    pub fn validate(
+        &self, strict: bool
+    ) -> Result<Vec<ResourceCert>, PathError> {
+        self.validate_at(strict, Time::now())
+    }
+
+    /// Validates the path at the given time.
+    ///
+    /// Validates the trust anchor certificate and then each certificate
+    /// on the path against its issuer. The leaf certificate is validated
+    /// as a CA certificate if it has the CA flag set or as an EE
+    /// certificate otherwise. CRLs are not checked.
+    ///
+    /// Returns the validated certificates in the same order as the path,
+    /// i.e., starting with the leaf and ending with the trust anchor.
+    pub fn validate_at(
+        &self, strict: bool, now: Time,
+    ) -> Result<Vec<ResourceCert>, PathError> {
+        let mut res = Vec::with_capacity(self.certs.len());
+        let mut iter = self.certs.iter().enumerate().rev();
+        let (depth, anchor) = iter.next().expect("empty certification path");
+        res.push(
+            anchor.clone().validate_ta_at(
+                self.tal.clone(), strict, now
+            ).map_err(|err| PathError::Invalid { depth, err })?
+        );
+        for (depth, cert) in iter {
+            let issuer = res.last().expect("empty result");
+            let cert = if depth == 0 && !cert.is_ca() {
+                cert.clone().validate_ee_at(issuer, strict, now)
+            }
+            else {
+                cert.clone().validate_ca_at(issuer, strict, now)
+            }.map_err(|err| PathError::Invalid { depth, err })?;
+            res.push(cert);
+        }
+        res.reverse();
+        Ok(res)
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ PathError -----------------------------------------------------
+
+/// An error happened while building or validating a certification path.
+///
+/// The `depth` given by most variants is the position of the offending
+/// certificate in the path with the leaf certificate at depth 0.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PathError {
+    /// The certificate at `depth` has no authority key identifier.
+    MissingAki {
+        depth: usize,
+    },
+
+    /// There is no certificate with the authority key identifier.
+    NoIssuer {
+        depth: usize,
+        aki: KeyIdentifier,
+    },
+
+    /// The issuer candidates have a different subject name.
+    IssuerNameMismatch {
+        depth: usize,
+        aki: KeyIdentifier,
+    },
+
+    /// The issuer candidates are not CA certificates.
+    IssuerNotCa {
+        depth: usize,
+        aki: KeyIdentifier,
+    },
+
+    /// The issuer candidate is already part of the path.
+    Loop {
+        depth: usize,
+        aki: KeyIdentifier,
+    },
+
+    /// The path ends in a self-signed certificate that isn’t trusted.
+    UntrustedRoot {
+        depth: usize,
+        ski: KeyIdentifier,
+    },
+
+    /// The path would exceed the given maximum length.
+    TooLong(usize),
+
+    /// The certificate at `depth` failed validation.
+    Invalid {
+        depth: usize,
+        err: ValidationError,
+    },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathError::MissingAki { depth } => {
+                write!(f,
+                    "certificate at depth {} has no authority key identifier",
+                    depth
+                )
+            }
+            PathError::NoIssuer { depth, aki } => {
+                write!(f,
+                    "no issuer with key identifier {} for certificate \
+                     at depth {}",
+                    aki, depth
+                )
+            }
+            PathError::IssuerNameMismatch { depth, aki } => {
+                write!(f,
+                    "issuer with key identifier {} for certificate at \
+                     depth {} has a different subject name",
+                    aki, depth
+                )
+            }
+            PathError::IssuerNotCa { depth, aki } => {
+                write!(f,
+                    "issuer with key identifier {} for certificate at \
+                     depth {} is not a CA certificate",
+                    aki, depth
+                )
+            }
+            PathError::Loop { depth, aki } => {
+                write!(f,
+                    "issuer with key identifier {} for certificate at \
+                     depth {} is already on the path",
+                    aki, depth
+                )
+            }
+            PathError::UntrustedRoot { depth, ski } => {
+                write!(f,
+                    "self-signed certificate {} at depth {} is not \
+                     a trust anchor",
+                    ski, depth
+                )
+            }
+            PathError::TooLong(max) => {
+                write!(f, "path exceeds maximum length of {}", max)
+            }
+            PathError::Invalid { depth, ref err } => {
+                write!(f,
+                    "certificate at depth {} is invalid: {}", depth, err
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for PathError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            PathError::Invalid { ref err, .. } => Some(err),
+            _ => None
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ta() -> Cert {
+        Cert::decode(
+            include_bytes!("../../test-data/repository/ta.cer").as_ref()
+        ).unwrap()
+    }
+
+    fn ca1() -> Cert {
+        Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap()
+    }
+
+    fn tal() -> Arc<TalInfo> {
+        TalInfo::from_name("foo".into()).into_arc()
+    }
+
+    #[test]
+    fn build_and_validate() {
+        let mut pool = CertPool::new();
+        pool.add_trust_anchor(ta(), tal());
+
+        let path = pool.build_path(&ca1()).unwrap();
+        assert_eq!(path.len(), 2);
+        assert_eq!(path.leaf().as_bytes(), ca1().as_bytes());
+        assert_eq!(path.trust_anchor().as_bytes(), ta().as_bytes());
+
+        let now = ca1().validity().not_before();
+        let certs = path.validate_at(false, now).unwrap();
+        assert_eq!(certs.len(), 2);
+        assert_eq!(
+            certs[0].as_cert().as_bytes(), ca1().as_bytes()
+        );
+
+        // The trust anchor itself.
+        let path = pool.build_path(&ta()).unwrap();
+        assert_eq!(path.len(), 1);
+    }
+
+    #[test]
+    fn no_path() {
+        let pool = CertPool::new();
+        assert!(matches!(
+            pool.build_path(&ca1()),
+            Err(PathError::NoIssuer { depth: 0, .. })
+        ));
+        assert!(matches!(
+            pool.build_path(&ta()),
+            Err(PathError::UntrustedRoot { depth: 0, .. })
+        ));
+
+        // The TA as an intermediate isn’t trusted.
+        let mut pool = CertPool::new();
+        pool.add_cert(ta());
+        assert!(matches!(
+            pool.build_path(&ca1()),
+            Err(PathError::UntrustedRoot { depth: 1, .. })
+        ));
+
+        let mut pool = CertPool::new();
+        pool.add_trust_anchor(ta(), tal());
+        pool.set_max_depth(1);
+        assert!(matches!(
+            pool.build_path(&ca1()),
+            Err(PathError::TooLong(1))
+        ));
+    }
+
+    #[test]
+    fn invalid_path() {
+        let mut pool = CertPool::new();
+        pool.add_trust_anchor(ta(), tal());
+        let path = pool.build_path(&ca1()).unwrap();
+        assert!(matches!(
+            path.validate_at(false, Time::utc(2021, 1, 1, 0, 0, 0)),
+            Err(PathError::Invalid { depth: 0, .. })
+        ));
+        assert!(matches!(
+            path.validate_at(false, Time::utc(2017, 1, 1, 0, 0, 0)),
+            Err(PathError::Invalid { depth: 1, .. })
+        ));
+    }
+}
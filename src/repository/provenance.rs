@@ -0,0 +1,131 @@
+//! Where validated payload came from.
+//!
+//! Every payload item produced by validation – route origins, router
+//! keys, ASPAs – is derived from a signed object published by some CA
+//! under some trust anchor. When something looks wrong with the payload,
+//! operators need to find that object. A [`Provenance`] describes the
+//! source of a payload item: the trust anchor, the URI of the issuing
+//! CA’s certificate, the URI of the object itself, its hash as listed on
+//! the manifest, and the validity of the object’s certificate.
+//!
+//! Since a single object often produces many payload items and the same
+//! item can be produced by many objects, provenance is kept as shared
+//! values and associated with payload items by reference. The validation
+//! engine records provenance in its report where it is available via
+//! [`ValidationReport::provenance`][report]. Applications not
+//! interested in it can turn it off or strip it from the report later.
+//!
+//! [report]: super::report::ValidationReport::provenance
+
+use std::fmt;
+use std::sync::Arc;
+use crate::uri;
+use crate::util::hex;
+use super::manifest::ManifestHash;
+use super::tal::TalInfo;
+use super::x509::Validity;
+
+
+//------------ Provenance ----------------------------------------------------
+
+/// The source of a payload item.
+#[derive(Clone, Debug)]
+pub struct Provenance {
+    /// The trust anchor the object was validated under.
+    tal: Arc<TalInfo>,
+
+    /// The URI of the certificate of the issuing CA.
+    ca: uri::Rsync,
+
+    /// The URI of the object.
+    object: uri::Rsync,
+
+    /// The hash of the object as listed on the manifest.
+    hash: ManifestHash,
+
+    /// The validity of the object’s certificate.
+    validity: Validity,
+}
+
+impl Provenance {
+    /// Creates a new provenance value from its components.
+    pub fn new(
+        tal: Arc<TalInfo>,
+        ca: uri::Rsync,
+        object: uri::Rsync,
+        hash: ManifestHash,
+        validity: Validity,
+    ) -> Self {
+        Provenance { tal, ca, object, hash, validity }
+    }
+
+    /// Returns the trust anchor the object was validated under.
+    pub fn tal(&self) -> &Arc<TalInfo> {
+        &self.tal
+    }
+
+    /// Returns the URI of the certificate of the issuing CA.
+    pub fn ca_uri(&self) -> &uri::Rsync {
+        &self.ca
+    }
+
+    /// Returns the URI of the object.
+    pub fn object_uri(&self) -> &uri::Rsync {
+        &self.object
+    }
+
+    /// Returns the hash of the object as listed on the manifest.
+    pub fn hash(&self) -> &ManifestHash {
+        &self.hash
+    }
+
+    /// Returns the validity of the object’s certificate.
+    ///
+    /// For router keys, this is the router certificate itself, for
+    /// other payload the EE certificate of the signed object.
+    pub fn validity(&self) -> Validity {
+        self.validity
+    }
+}
+
+/// Shows the provenance as a single line.
+///
+/// The line contains the TAL name, the object URI, and the hex encoded
+/// object hash, separated by spaces.
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash = self.hash.as_slice();
+        let mut buf = vec![0u8; hash.len() * 2];
+        write!(
+            f, "{} {} {}",
+            self.tal.name(), self.object, hex::encode_lower(hash, &mut buf)
+        )
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::Bytes;
+    use crate::crypto::DigestAlgorithm;
+    use super::*;
+
+    #[test]
+    fn display() {
+        let provenance = Provenance::new(
+            TalInfo::from_name("test".into()).into_arc(),
+            uri::Rsync::from_str("rsync://a.example/ta/ca.cer").unwrap(),
+            uri::Rsync::from_str("rsync://a.example/ca/a.roa").unwrap(),
+            ManifestHash::new(
+                Bytes::from_static(b"\x01\xab"), DigestAlgorithm::sha256()
+            ),
+            Validity::from_secs(86400),
+        );
+        assert_eq!(
+            provenance.to_string(), "test rsync://a.example/ca/a.roa 01ab"
+        );
+    }
+}
@@ -0,0 +1,752 @@
+//! The outcome of a validation run and differences between runs.
+//!
+//! A [`ValidationReport`] collects what a relying party learned from a
+//! single validation run: the payload that was produced, the outcome
+//! for each object that was encountered, the outcome of fetching each
+//! repository, and which parts of the repository tree were validated from
+//! cached data or not at all. Two reports can be compared via
+//! [`ValidationReport::diff`] which produces a [`ReportDiff`] explaining
+//! what changed between the two runs. This is useful for monitoring
+//! systems that want to explain why the validity of routes changed.
+//!
+//! For each payload item, the report can keep the [`Provenance`] of the
+//! objects it was derived from, allowing to trace the item back to its
+//! source.
+//!
+//! Route origins derived from AS0 trust anchors are kept separately from
+//! the regular payload when added via [`ValidationReport::add_origin`].
+//! This allows monitoring their effect independently.
+//!
+//! The module is only available if both the `"repository"` and `"rtr"`
+//! features are enabled since it uses the RTR payload types.
+
+#![cfg(feature = "rtr")]
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::uri;
+use crate::rtr::payload::{Action, Payload};
+use super::covering::{CoverageIndex, Covering, Resource};
+use super::gbr::ContactIndex;
+use super::provenance::Provenance;
+use super::tak::TrustAnchorKeys;
+use super::tal::TaRouteOrigin;
+use super::tree::DelegationTree;
+
+
+//------------ ValidationReport ----------------------------------------------
+
+/// The outcome of a single validation run.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    /// The payload produced by the run.
+    payload: BTreeSet<Payload>,
+
+    /// The route origins derived from AS0 trust anchors.
+    ///
+    /// These may or may not be included in `payload`, too, depending on
+    /// the AS0 policy of their trust anchor.
+    as0_payload: BTreeSet<Payload>,
+
+    /// The sources of each payload item.
+    provenance: HashMap<Payload, Vec<Arc<Provenance>>>,
+
+    /// The outcome for each object encountered during the run.
+    objects: HashMap<uri::Rsync, ObjectOutcome>,
+
+    /// The warnings recorded for objects.
+    warnings: HashMap<uri::Rsync, Vec<String>>,
+
+    /// The outcome of fetching each repository.
+    ///
+    /// The key is the URI of the repository, i.e., the rsync module or
+    /// RRDP notification URI.
+    repositories: HashMap<String, FetchOutcome>,
+
+    /// The decisions made for publication points.
+    ///
+    /// The keys are the rsync URIs of publication points.
+    points: HashMap<uri::Rsync, PointOutcome>,
+
+    /// The subtrees of the repository tree that were flagged.
+    ///
+    /// The keys are the rsync URIs of publication points.
+    subtrees: HashMap<uri::Rsync, SubtreeFlag>,
+
+    /// The validated TAK objects of trust anchors, keyed by TAL name.
+    trust_anchor_keys: HashMap<String, TrustAnchorKeys>,
+
+    /// The validated CAs and their Ghostbusters records.
+    contacts: ContactIndex,
+
+    /// The validated certificates and objects with their resources.
+    coverage: CoverageIndex,
+
+    /// Whether the run was cancelled before it finished.
+    cancelled: bool,
+}
+
+impl ValidationReport {
+    /// Creates a new, empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a payload item to the report.
+    pub fn add_payload(&mut self, payload: impl Into<Payload>) {
+        self.payload.insert(payload.into());
+    }
+
+    /// Adds all payload items from an iterator to the report.
+    pub fn extend_payload<T: Into<Payload>>(
+        &mut self, iter: impl IntoIterator<Item = T>
+    ) {
+        self.payload.extend(iter.into_iter().map(Into::into))
+    }
+
+    /// Adds a route origin with its provenance to the report.
+    ///
+    /// If the origin was derived from an AS0 trust anchor, it is recorded
+    /// as AS0 payload. It is added to the regular payload only if it is
+    /// to be served to routers.
+    pub fn add_origin(&mut self, origin: TaRouteOrigin) {
+        if origin.is_as0() {
+            self.as0_payload.insert(origin.origin.into());
+        }
+        if origin.is_served() {
+            self.payload.insert(origin.origin.into());
+        }
+    }
+
+    /// Adds all route origins from an iterator to the report.
+    pub fn extend_origins(
+        &mut self, iter: impl IntoIterator<Item = TaRouteOrigin>
+    ) {
+        iter.into_iter().for_each(|origin| self.add_origin(origin))
+    }
+
+    /// Records a warning for the object at `uri`.
+    ///
+    /// Warnings don’t change the outcome of an object. They point out
+    /// things an operator may want to look at.
+    pub fn warn(&mut self, uri: uri::Rsync, warning: impl Into<String>) {
+        self.warnings.entry(uri).or_default().push(warning.into())
+    }
+
+    /// Records a source of a payload item.
+    ///
+    /// The same provenance value can be shared between all items derived
+    /// from the same object.
+    pub fn add_provenance(
+        &mut self, payload: impl Into<Payload>, source: Arc<Provenance>
+    ) {
+        self.provenance.entry(payload.into()).or_default().push(source)
+    }
+
+    /// Removes all provenance information from the report.
+    pub fn strip_provenance(&mut self) {
+        self.provenance = HashMap::new()
+    }
+
+    /// Records that the object at `uri` has been accepted.
+    pub fn accept(&mut self, uri: uri::Rsync) {
+        self.objects.insert(uri, ObjectOutcome::Accepted);
+    }
+
+    /// Records that the object at `uri` has been rejected.
+    ///
+    /// The `reason` should be a human readable description of why the
+    /// object was rejected, typically the rendered validation error.
+    pub fn reject(&mut self, uri: uri::Rsync, reason: impl Into<String>) {
+        self.objects.insert(uri, ObjectOutcome::Rejected(reason.into()));
+    }
+
+    /// Records the validated TAK object of a trust anchor.
+    ///
+    /// The trust anchor is identified by the name of its TAL.
+    pub fn set_trust_anchor_keys(
+        &mut self, tal: impl Into<String>, keys: TrustAnchorKeys
+    ) {
+        self.trust_anchor_keys.insert(tal.into(), keys);
+    }
+
+    /// Records the outcome of fetching a repository.
+    ///
+    /// The `repository` should be the URI of the repository as a string,
+    /// i.e., the rsync module or RRDP notification URI.
+    pub fn set_fetch_outcome(
+        &mut self, repository: impl Into<String>, outcome: FetchOutcome
+    ) {
+        self.repositories.insert(repository.into(), outcome);
+    }
+
+    /// Records the decision made for the publication point at `base`.
+    pub fn set_point_outcome(
+        &mut self, base: uri::Rsync, outcome: PointOutcome
+    ) {
+        self.points.insert(base, outcome);
+    }
+
+    /// Flags the subtree starting at the publication point `base`.
+    ///
+    /// This is used to mark publication points which couldn’t be
+    /// refreshed and thus were validated from cached objects or not at
+    /// all. Since all objects of CAs below the publication point are
+    /// affected as well, the flag applies to the whole subtree.
+    pub fn flag_subtree(&mut self, base: uri::Rsync, flag: SubtreeFlag) {
+        self.subtrees.insert(base, flag);
+    }
+
+    /// Records that the run was cancelled before it finished.
+    pub fn set_cancelled(&mut self) {
+        self.cancelled = true
+    }
+
+    /// Returns whether the run was cancelled before it finished.
+    ///
+    /// The report of a cancelled run is incomplete: objects that weren’t
+    /// reached have no outcome and their payload is missing. It should
+    /// not be used in place of a complete report.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Returns an iterator over the payload in canonical order.
+    pub fn payload(&self) -> impl Iterator<Item = &Payload> + '_ {
+        self.payload.iter()
+    }
+
+    /// Returns an iterator over the payload derived from AS0 trust anchors.
+    pub fn as0_payload(&self) -> impl Iterator<Item = &Payload> + '_ {
+        self.as0_payload.iter()
+    }
+
+    /// Returns the sources of a payload item.
+    ///
+    /// Returns an empty slice if there is no provenance for the item,
+    /// either because it isn’t part of the report or because provenance
+    /// wasn’t recorded.
+    pub fn provenance(&self, payload: &Payload) -> &[Arc<Provenance>] {
+        self.provenance.get(payload).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns the outcome for the object at the given URI.
+    pub fn outcome(&self, uri: &uri::Rsync) -> Option<&ObjectOutcome> {
+        self.objects.get(uri)
+    }
+
+    /// Returns an iterator over all objects and their outcomes.
+    ///
+    /// The iterator returns the objects in no particular order.
+    pub fn outcomes(
+        &self
+    ) -> impl Iterator<Item = (&uri::Rsync, &ObjectOutcome)> + '_ {
+        self.objects.iter()
+    }
+
+    /// Returns the warnings recorded for the object at `uri`.
+    pub fn warnings(&self, uri: &uri::Rsync) -> &[String] {
+        self.warnings.get(uri).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns an iterator over all objects with warnings.
+    ///
+    /// The iterator returns the objects in no particular order.
+    pub fn all_warnings(
+        &self
+    ) -> impl Iterator<Item = (&uri::Rsync, &[String])> + '_ {
+        self.warnings.iter().map(|(uri, items)| (uri, items.as_slice()))
+    }
+
+    /// Returns the validated CAs and their Ghostbusters records.
+    pub fn contacts(&self) -> &ContactIndex {
+        &self.contacts
+    }
+
+    /// Returns a mutable reference to the CAs and their records.
+    pub fn contacts_mut(&mut self) -> &mut ContactIndex {
+        &mut self.contacts
+    }
+
+    /// Returns the validated certificates and objects.
+    pub fn coverage(&self) -> &CoverageIndex {
+        &self.coverage
+    }
+
+    /// Returns a mutable reference to the certificates and objects.
+    pub fn coverage_mut(&mut self) -> &mut CoverageIndex {
+        &mut self.coverage
+    }
+
+    /// Returns the certificates and objects covering a resource.
+    ///
+    /// See [`CoverageIndex::find_covering`] for details.
+    pub fn find_covering(
+        &self, resource: impl Into<Resource>
+    ) -> Covering<'_> {
+        self.coverage.find_covering(resource)
+    }
+
+    /// Returns the tree of validated CAs.
+    pub fn delegation_tree(&self) -> DelegationTree {
+        DelegationTree::from_coverage(&self.coverage)
+    }
+
+    /// Returns the validated TAK object of the given trust anchor.
+    pub fn trust_anchor_keys(&self, tal: &str) -> Option<&TrustAnchorKeys> {
+        self.trust_anchor_keys.get(tal)
+    }
+
+    /// Returns the outcome of fetching the given repository.
+    pub fn fetch_outcome(&self, repository: &str) -> Option<&FetchOutcome> {
+        self.repositories.get(repository)
+    }
+
+    /// Returns an iterator over the repositories and their fetch outcomes.
+    ///
+    /// The iterator returns the repositories in no particular order.
+    pub fn fetch_outcomes(
+        &self
+    ) -> impl Iterator<Item = (&str, &FetchOutcome)> + '_ {
+        self.repositories.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Returns the decision made for the publication point at `base`.
+    pub fn point_outcome(&self, base: &uri::Rsync) -> Option<&PointOutcome> {
+        self.points.get(base)
+    }
+
+    /// Returns an iterator over the decisions for all publication points.
+    ///
+    /// The iterator returns the publication points in no particular order.
+    pub fn point_outcomes(
+        &self
+    ) -> impl Iterator<Item = (&uri::Rsync, &PointOutcome)> + '_ {
+        self.points.iter()
+    }
+
+    /// Returns the flag for a publication point’s subtree.
+    pub fn subtree_flag(&self, base: &uri::Rsync) -> Option<&SubtreeFlag> {
+        self.subtrees.get(base)
+    }
+
+    /// Returns the flag of the subtree an object belongs to.
+    ///
+    /// Only subtrees placed directly above the object’s URI are
+    /// considered since the report doesn’t know about the CA hierarchy.
+    /// If there are several, the flag of the closest one is returned.
+    pub fn flag_for(
+        &self, uri: &uri::Rsync
+    ) -> Option<(&uri::Rsync, &SubtreeFlag)> {
+        self.subtrees.iter().filter(|(base, _)| {
+            base.is_parent_of(uri)
+        }).max_by_key(|(base, _)| base.path().len())
+    }
+
+    /// Returns an iterator over all flagged subtrees.
+    ///
+    /// The iterator returns the subtrees in no particular order.
+    pub fn flagged_subtrees(
+        &self
+    ) -> impl Iterator<Item = (&uri::Rsync, &SubtreeFlag)> + '_ {
+        self.subtrees.iter()
+    }
+
+    /// Returns the number of objects in the report.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns the changes necessary to get from this report to `newer`.
+    ///
+    /// All lists in the returned diff are sorted, payload in its canonical
+    /// order and objects by their URI.
+    pub fn diff(&self, newer: &ValidationReport) -> ReportDiff {
+        let mut res = ReportDiff {
+            announced: newer.payload.difference(
+                &self.payload
+            ).cloned().collect(),
+            withdrawn: self.payload.difference(
+                &newer.payload
+            ).cloned().collect(),
+            as0_announced: newer.as0_payload.difference(
+                &self.as0_payload
+            ).cloned().collect(),
+            as0_withdrawn: self.as0_payload.difference(
+                &newer.as0_payload
+            ).cloned().collect(),
+            .. Default::default()
+        };
+
+        for (uri, outcome) in &newer.objects {
+            match self.objects.get(uri) {
+                None => {
+                    res.appeared.push(uri.clone());
+                    if let ObjectOutcome::Rejected(reason) = outcome {
+                        res.rejected.push((uri.clone(), reason.clone()));
+                    }
+                }
+                Some(old) if old == outcome => { }
+                Some(_) => {
+                    match outcome {
+                        ObjectOutcome::Accepted => {
+                            res.recovered.push(uri.clone())
+                        }
+                        ObjectOutcome::Rejected(reason) => {
+                            res.rejected.push((uri.clone(), reason.clone()))
+                        }
+                    }
+                }
+            }
+        }
+        res.disappeared = self.objects.keys().filter(|uri| {
+            !newer.objects.contains_key(uri)
+        }).cloned().collect();
+
+        res.appeared.sort_by(|l, r| l.as_str().cmp(r.as_str()));
+        res.disappeared.sort_by(|l, r| l.as_str().cmp(r.as_str()));
+        res.rejected.sort_by(|l, r| l.0.as_str().cmp(r.0.as_str()));
+        res.recovered.sort_by(|l, r| l.as_str().cmp(r.as_str()));
+        res
+    }
+}
+
+
+//------------ ObjectOutcome -------------------------------------------------
+
+/// The outcome of validating a single object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ObjectOutcome {
+    /// The object was accepted.
+    Accepted,
+
+    /// The object was rejected for the given reason.
+    Rejected(String),
+}
+
+impl ObjectOutcome {
+    /// Returns whether the object was accepted.
+    pub fn is_accepted(&self) -> bool {
+        matches!(*self, ObjectOutcome::Accepted)
+    }
+}
+
+
+//------------ FetchOutcome --------------------------------------------------
+
+/// The outcome of fetching a repository.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FetchOutcome {
+    /// The repository was fetched successfully.
+    Updated,
+
+    /// Fetching failed for the given reason.
+    Failed(String),
+
+    /// The fetch policy did not allow contacting the repository.
+    ///
+    /// Contains the reason given by the policy.
+    PolicyRejected(String),
+
+    /// Fetching was skipped because the repository is considered dead.
+    ///
+    /// Previously fetched objects were used instead.
+    Skipped,
+}
+
+impl FetchOutcome {
+    /// Returns whether the repository was fetched successfully.
+    pub fn is_updated(&self) -> bool {
+        matches!(*self, FetchOutcome::Updated)
+    }
+
+    /// Returns whether the policy rejected contacting the repository.
+    pub fn is_policy_rejected(&self) -> bool {
+        matches!(*self, FetchOutcome::PolicyRejected(_))
+    }
+}
+
+
+//------------ PointOutcome --------------------------------------------------
+
+/// The decision made for a publication point.
+///
+/// Following RFC 9286, the objects of a publication point are only used if
+/// they agree with its manifest: the manifest and CRL have to be valid and
+/// all listed files have to be present with the right hash.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PointOutcome {
+    /// The current objects of the publication point were used.
+    Current,
+
+    /// The current objects were unusable and cached objects were used.
+    ///
+    /// Contains the reason the current objects were unusable.
+    Cached(String),
+
+    /// The publication point failed and none of its objects were used.
+    ///
+    /// Contains the reason the current objects were unusable.
+    Failed(String),
+}
+
+impl PointOutcome {
+    /// Returns whether the current objects were used.
+    pub fn is_current(&self) -> bool {
+        matches!(*self, PointOutcome::Current)
+    }
+
+    /// Returns whether the publication point failed entirely.
+    pub fn is_failed(&self) -> bool {
+        matches!(*self, PointOutcome::Failed(_))
+    }
+}
+
+
+//------------ SubtreeFlag ---------------------------------------------------
+
+/// A flag for a subtree of the repository tree.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SubtreeFlag {
+    /// The subtree was validated from cached objects of the given age.
+    Cached(Duration),
+
+    /// The cached objects were too old and the subtree was not validated.
+    ///
+    /// Contains the age of the cached objects.
+    Expired(Duration),
+
+    /// There were no cached objects and the subtree was not validated.
+    Unavailable,
+}
+
+impl From<crate::store::fallback::Fallback> for SubtreeFlag {
+    fn from(fallback: crate::store::fallback::Fallback) -> Self {
+        use crate::store::fallback::Fallback;
+
+        match fallback {
+            Fallback::Cached(age) => SubtreeFlag::Cached(age),
+            Fallback::Expired(age) => SubtreeFlag::Expired(age),
+            Fallback::Unavailable => SubtreeFlag::Unavailable,
+        }
+    }
+}
+
+
+//------------ ReportDiff ----------------------------------------------------
+
+/// The differences between two validation reports.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReportDiff {
+    /// Payload only present in the newer report.
+    pub announced: Vec<Payload>,
+
+    /// Payload only present in the older report.
+    pub withdrawn: Vec<Payload>,
+
+    /// AS0 payload only present in the newer report.
+    pub as0_announced: Vec<Payload>,
+
+    /// AS0 payload only present in the older report.
+    pub as0_withdrawn: Vec<Payload>,
+
+    /// Objects only present in the newer report.
+    pub appeared: Vec<uri::Rsync>,
+
+    /// Objects only present in the older report.
+    pub disappeared: Vec<uri::Rsync>,
+
+    /// Objects rejected in the newer report with a new reason.
+    ///
+    /// This contains objects that were accepted before or that were
+    /// rejected for a different reason as well as new objects that were
+    /// rejected right away.
+    pub rejected: Vec<(uri::Rsync, String)>,
+
+    /// Objects rejected in the older report but accepted in the newer one.
+    pub recovered: Vec<uri::Rsync>,
+}
+
+impl ReportDiff {
+    /// Returns whether there are no differences at all.
+    pub fn is_empty(&self) -> bool {
+        self.announced.is_empty()
+            && self.withdrawn.is_empty()
+            && self.as0_announced.is_empty()
+            && self.as0_withdrawn.is_empty()
+            && self.appeared.is_empty()
+            && self.disappeared.is_empty()
+            && self.rejected.is_empty()
+            && self.recovered.is_empty()
+    }
+
+    /// Returns an iterator over the payload changes as RTR actions.
+    ///
+    /// Withdrawals are returned before announcements.
+    pub fn actions(&self) -> impl Iterator<Item = (Action, &Payload)> + '_ {
+        self.withdrawn.iter().map(|item| (Action::Withdraw, item)).chain(
+            self.announced.iter().map(|item| (Action::Announce, item))
+        )
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use crate::resources::{Asn, Prefix};
+    use crate::resources::addr::MaxLenPrefix;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn origin(prefix: &str, asn: u32) -> Payload {
+        Payload::origin(
+            MaxLenPrefix::new(Prefix::from_str(prefix).unwrap(), None)
+                .unwrap(),
+            Asn::from_u32(asn)
+        )
+    }
+
+    #[test]
+    fn diff() {
+        let mut old = ValidationReport::new();
+        old.extend_payload([
+            origin("10.0.0.0/8", 64496), origin("10.1.0.0/16", 64497)
+        ]);
+        old.accept(uri("rsync://example.com/m/a.roa"));
+        old.accept(uri("rsync://example.com/m/b.roa"));
+        old.reject(uri("rsync://example.com/m/c.roa"), "expired");
+        old.reject(uri("rsync://example.com/m/d.roa"), "bad signature");
+        assert!(old.diff(&old).is_empty());
+
+        let mut new = ValidationReport::new();
+        new.extend_payload([
+            origin("10.0.0.0/8", 64496), origin("10.2.0.0/16", 64498)
+        ]);
+        new.accept(uri("rsync://example.com/m/a.roa"));
+        new.reject(uri("rsync://example.com/m/b.roa"), "revoked");
+        new.accept(uri("rsync://example.com/m/c.roa"));
+        new.reject(uri("rsync://example.com/m/e.roa"), "stale");
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.announced, [origin("10.2.0.0/16", 64498)]);
+        assert_eq!(diff.withdrawn, [origin("10.1.0.0/16", 64497)]);
+        assert_eq!(diff.appeared, [uri("rsync://example.com/m/e.roa")]);
+        assert_eq!(diff.disappeared, [uri("rsync://example.com/m/d.roa")]);
+        assert_eq!(
+            diff.rejected,
+            [
+                (uri("rsync://example.com/m/b.roa"), "revoked".into()),
+                (uri("rsync://example.com/m/e.roa"), "stale".into()),
+            ]
+        );
+        assert_eq!(diff.recovered, [uri("rsync://example.com/m/c.roa")]);
+
+        let actions: Vec<_> = diff.actions().map(|x| x.0).collect();
+        assert_eq!(actions, [Action::Withdraw, Action::Announce]);
+    }
+
+    #[test]
+    fn as0_origins() {
+        use crate::repository::tal::{As0Policy, TalInfo, TaRouteOrigin};
+        use crate::rtr::payload::RouteOrigin;
+
+        let tal = TalInfo::from_name("as0".into()).into_arc();
+        let make = |prefix: &str, as0| TaRouteOrigin {
+            origin: RouteOrigin::new(
+                MaxLenPrefix::from_str(prefix).unwrap(), Asn::from_u32(0)
+            ),
+            tal: tal.clone(),
+            as0,
+        };
+
+        let mut old = ValidationReport::new();
+        old.extend_origins([
+            make("10.0.0.0/8", None),
+            make("10.1.0.0/16", Some(As0Policy::Serve)),
+            make("10.2.0.0/16", Some(As0Policy::Report)),
+        ]);
+        assert_eq!(old.payload().count(), 2);
+        assert_eq!(old.as0_payload().count(), 2);
+
+        let mut new = ValidationReport::new();
+        new.extend_origins([
+            make("10.0.0.0/8", None),
+            make("10.3.0.0/16", Some(As0Policy::Report)),
+        ]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.withdrawn, [origin("10.1.0.0/16", 0)]);
+        assert!(diff.announced.is_empty());
+        assert_eq!(diff.as0_announced, [origin("10.3.0.0/16", 0)]);
+        assert_eq!(
+            diff.as0_withdrawn,
+            [origin("10.1.0.0/16", 0), origin("10.2.0.0/16", 0)]
+        );
+    }
+
+    #[test]
+    fn fetch_outcomes() {
+        let mut report = ValidationReport::new();
+        report.set_fetch_outcome(
+            "https://rrdp.example.com/notification.xml",
+            FetchOutcome::Updated
+        );
+        report.set_fetch_outcome(
+            "rsync://10.0.0.1/repo/",
+            FetchOutcome::PolicyRejected("private address".into())
+        );
+        assert!(
+            report.fetch_outcome(
+                "https://rrdp.example.com/notification.xml"
+            ).unwrap().is_updated()
+        );
+        assert!(
+            report.fetch_outcome(
+                "rsync://10.0.0.1/repo/"
+            ).unwrap().is_policy_rejected()
+        );
+        report.set_fetch_outcome(
+            "rsync://dead.example.com/repo/", FetchOutcome::Skipped
+        );
+        assert_eq!(report.fetch_outcomes().count(), 3);
+    }
+
+    #[test]
+    fn subtrees() {
+        let mut report = ValidationReport::new();
+        report.flag_subtree(
+            uri("rsync://example.com/m/ca/"),
+            SubtreeFlag::Cached(Duration::from_secs(60))
+        );
+        report.flag_subtree(
+            uri("rsync://example.com/m/ca/child/"), SubtreeFlag::Unavailable
+        );
+        assert_eq!(
+            report.flag_for(&uri("rsync://example.com/m/ca/a.roa")),
+            Some((
+                &uri("rsync://example.com/m/ca/"),
+                &SubtreeFlag::Cached(Duration::from_secs(60))
+            ))
+        );
+        assert_eq!(
+            report.flag_for(
+                &uri("rsync://example.com/m/ca/child/b.roa")
+            ).map(|x| *x.1),
+            Some(SubtreeFlag::Unavailable)
+        );
+        assert!(
+            report.flag_for(&uri("rsync://example.com/m/other/c.roa"))
+                .is_none()
+        );
+        assert_eq!(report.flagged_subtrees().count(), 2);
+    }
+}
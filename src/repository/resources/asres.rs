@@ -536,6 +536,19 @@ impl FromIterator<AsBlock> for AsBlocks {
 }
 
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for AsBlocks {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        let blocks: Vec<AsBlock> = u.arbitrary()?;
+        Ok(Self::from_iter(blocks))
+    }
+}
+
+
 //--- Display
 
 impl fmt::Display for AsBlocks {
@@ -862,6 +875,26 @@ impl fmt::Display for AsBlock {
     }
 }
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for AsBlock {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        if u.arbitrary()? {
+            Ok(AsBlock::Id(u.arbitrary()?))
+        }
+        else {
+            let left: Asn = u.arbitrary()?;
+            let right: Asn = u.arbitrary()?;
+            Ok(AsBlock::Range(
+                AsRange::new(left.min(right), left.max(right))
+            ))
+        }
+    }
+}
+
 
 //------------ AsBlockIter ---------------------------------------------------
 
@@ -1012,6 +1045,7 @@ impl fmt::Display for AsRange {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[allow(clippy::enum_variant_names)]
+#[non_exhaustive]
 pub enum FromStrError {
     BadAsn,
     BadRange,
@@ -656,17 +656,22 @@ fn from_iter_unsorted<T: Block, I: Iterator<Item=T>>(
     }
     res.sort_unstable_by_key(|block| block.min());
 
-    // The strategy may may lead to consecutive blocks. We can’t have those,
-    // so we need to merge them. This is a bit ugly. Not sure if there is a
-    // cleaner way?
+    // The strategy may may lead to consecutive blocks. It can also lead to
+    // overlapping blocks if a block merged into an earlier one now covers
+    // a later one, too. We can’t have either, so we need to merge them.
+    // This is a bit ugly. Not sure if there is a cleaner way?
     if res.len() > 1 {
         let mut tail = 0;
         let mut tail_next = T::next(res[0].max());
         for j in 1..res.len() {
-            if Some(res[j].min()) == tail_next {
-                // Neighbouring. Merge j into tail and continue.
-                res[tail] = T::new(res[tail].min(), res[j].max());
-                tail_next = T::next(res[j].max());
+            // If tail_next is None, tail ends at the very last item and
+            // thus covers j.
+            if tail_next.map(|next| res[j].min() <= next).unwrap_or(true) {
+                // Neighbouring or overlapping. Merge j into tail and
+                // continue.
+                let max = std::cmp::max(res[tail].max(), res[j].max());
+                res[tail] = T::new(res[tail].min(), max);
+                tail_next = T::next(max);
             }
             else {
                 // Not neighbouring. Move to the next tail. If this isn’t j,
@@ -891,6 +896,17 @@ mod test {
             ).as_slice(),
             &[(0, 1), (3, 9), (20, 22)][..]
         );
+        // Unsorted block covering existing blocks
+        assert_eq!(
+            OwnedChain::from([(5,6), (9,10), (1,12)].as_ref()).as_slice(),
+            &[(1, 12)][..]
+        );
+        assert_eq!(
+            OwnedChain::from(
+                [(9,10), (5,6), (4,7), (2,255)].as_ref()
+            ).as_slice(),
+            &[(2, 255)][..]
+        );
     }
 
     #[test]
@@ -550,13 +550,13 @@ impl IpBlocks {
 
     /// Returns an IpBlocksForFamily for IPv4 for this,
     /// to help formatting.
-    pub fn as_v4(&self) -> IpBlocksForFamily {
+    pub fn as_v4(&self) -> IpBlocksForFamily<'_> {
         IpBlocksForFamily::v4(self)
     }
 
     /// Returns an IpBlocksForFamily for IPv4 for this,
     /// to help formatting.
-    pub fn as_v6(&self) -> IpBlocksForFamily {
+    pub fn as_v6(&self) -> IpBlocksForFamily<'_> {
         IpBlocksForFamily::v6(self)
     }
 }
@@ -618,6 +618,31 @@ impl FromIterator<IpBlock> for IpBlocks {
     }
 }
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IpBlocks {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Self::arbitrary_for_family(u, 128)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl IpBlocks {
+    /// Creates arbitrary blocks for addresses of `bits` length.
+    fn arbitrary_for_family(
+        u: &mut arbitrary::Unstructured, bits: u8
+    ) -> arbitrary::Result<Self> {
+        let mut blocks = Vec::new();
+        while u.arbitrary()? {
+            blocks.push(IpBlock::arbitrary_for_family(u, bits)?);
+        }
+        Ok(Self::from_iter(blocks))
+    }
+}
+
 
 //------------ IpBlocksBuilder -----------------------------------------------
 
@@ -888,6 +913,30 @@ impl Block for IpBlock {
     }
 }
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl IpBlock {
+    /// Creates an arbitrary block for addresses of `bits` length.
+    fn arbitrary_for_family(
+        u: &mut arbitrary::Unstructured, bits: u8
+    ) -> arbitrary::Result<Self> {
+        if u.arbitrary()? {
+            Ok(IpBlock::Prefix(Prefix::new(
+                Addr::from_bits(u.arbitrary()?), u.int_in_range(0..=bits)?
+            )))
+        }
+        else {
+            let left: u128 = u.arbitrary()?;
+            let right: u128 = u.arbitrary()?;
+            Ok(IpBlock::Range(AddressRange::new(
+                Addr::from_bits(left.min(right)).to_min(bits),
+                Addr::from_bits(left.max(right)).to_max(bits),
+            )))
+        }
+    }
+}
+
 
 //------------ DisplayV4Block ------------------------------------------------
 
@@ -1790,6 +1839,17 @@ impl From<IpBlocks> for Ipv4Blocks {
     }
 }
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ipv4Blocks {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        IpBlocks::arbitrary_for_family(u, 32).map(Ipv4Blocks)
+    }
+}
+
 //--- Deref
 
 impl std::ops::Deref for Ipv4Blocks {
@@ -1881,6 +1941,17 @@ impl From<IpBlocks> for Ipv6Blocks {
     }
 }
 
+//--- Arbitrary
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ipv6Blocks {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        IpBlocks::arbitrary_for_family(u, 128).map(Ipv6Blocks)
+    }
+}
+
 //--- Deref
 
 impl std::ops::Deref for Ipv6Blocks {
@@ -1937,6 +2008,7 @@ impl fmt::Display for DecodeFamilyError {
 //------------ FromStrError --------------------------------------------------
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum FromStrError {
     Addr(AddrParseError),
     PrefixLen(ParseIntError),
@@ -1973,7 +2045,15 @@ impl fmt::Display for FromStrError {
     }
 }
 
-impl error::Error for FromStrError { }
+impl error::Error for FromStrError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            FromStrError::Addr(ref err) => Some(err),
+            FromStrError::PrefixLen(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 
 //------------ InheritedIpResources ------------------------------------------
@@ -18,6 +18,7 @@ use crate::resources::asn::Asn;
 /// A set of ASN, IPv4 and IPv6 resources.
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ResourceSet {
     asn: AsBlocks,
 
@@ -298,23 +299,24 @@ impl std::error::Error for InheritError {}
 //------------ FromStrError --------------------------------------------------
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum FromStrError {
-    Asn(String),
-    Ipv4(String),
-    Ipv6(String)
+    Asn(super::asres::FromStrError),
+    Ipv4(super::ipres::FromStrError),
+    Ipv6(super::ipres::FromStrError),
 }
 
 impl FromStrError {
     fn asn(e: super::asres::FromStrError) -> Self {
-        FromStrError::Asn(e.to_string())
+        FromStrError::Asn(e)
     }
     
     fn ipv4(e: super::ipres::FromStrError) -> Self {
-        FromStrError::Ipv4(e.to_string())
+        FromStrError::Ipv4(e)
     }
     
     fn ipv6(e: super::ipres::FromStrError) -> Self {
-        FromStrError::Ipv6(e.to_string())
+        FromStrError::Ipv6(e)
     }
 }
 
@@ -331,7 +333,15 @@ impl fmt::Display for FromStrError {
     }
 }
 
-impl std::error::Error for FromStrError {}
+impl std::error::Error for FromStrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FromStrError::Asn(e) => Some(e),
+            FromStrError::Ipv4(e) => Some(e),
+            FromStrError::Ipv6(e) => Some(e),
+        }
+    }
+}
 
 
 //------------ Tests ---------------------------------------------------------
@@ -447,4 +457,52 @@ mod tests {
 
         assert_eq!(set, deser_set);
     }
+
+    #[test]
+    fn from_strs_error_source() {
+        use std::error::Error;
+
+        let err = ResourceSet::from_strs(
+            "AS65000", "10.0.0.0/33", ""
+        ).unwrap_err();
+        assert!(matches!(err, FromStrError::Ipv4(_)));
+        let source = err.source().unwrap();
+        assert!(
+            source.downcast_ref::<super::super::ipres::FromStrError>()
+                .is_some()
+        );
+    }
+
+    /// Returns pseudo-random data for creating arbitrary values.
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_data(seed: u64, len: usize) -> Vec<u8> {
+        // A simple xorshift generator is good enough for this.
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_sets_round_trip() {
+        use arbitrary::Unstructured;
+
+        for seed in 1..200 {
+            let data = arbitrary_data(seed, 1024);
+            let mut u = Unstructured::new(&data);
+            let set: ResourceSet = u.arbitrary().unwrap();
+            assert_eq!(
+                ResourceSet::from_strs(
+                    &set.asn().to_string(),
+                    &set.ipv4().to_string(),
+                    &set.ipv6().to_string(),
+                ).unwrap(),
+                set
+            );
+        }
+    }
 }
@@ -3,6 +3,7 @@
 //! For details, see RFC 6482.
 
 use std::fmt;
+use std::convert::Infallible;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use bcder::{decode, encode};
 use bcder::{Captured, Mode, OctetString, Oid, Tag};
@@ -15,8 +16,12 @@ use crate::crypto::{Signer, SigningError};
 use crate::util::base64;
 use super::cert::{Cert, ResourceCert};
 use super::error::{ValidationError, VerificationError};
+use super::options::{GraceWarning, ValidationOptions};
 use super::resources::{Addr, AddressFamily, Asn, IpResources, Prefix};
-use super::sigobj::{SignedObject, SignedObjectBuilder};
+use super::sigobj::{
+    SignedObject, SignedObjectBuilder, SignedObjectHeader
+};
+use super::x509::Time;
 
 
 //------------ Roa -----------------------------------------------------------
@@ -41,6 +46,43 @@ impl Roa {
         Ok(Roa { signed, content })
     }
 
+    /// Parses only the header of a ROA.
+    ///
+    /// This checks the content type but decodes neither the EE
+    /// certificate nor the content. Use
+    /// [`decode_payload`][Self::decode_payload] to get to the content or
+    /// [`from_header`][Self::from_header] to get a complete ROA.
+    pub fn parse_header_only<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<
+        SignedObjectHeader, DecodeError<<S::Source as Source>::Error>
+    > {
+        SignedObjectHeader::decode_if_type(
+            source, &oid::ROUTE_ORIGIN_AUTHZ, strict,
+        )
+    }
+
+    /// Decodes the content of a ROA from its header.
+    ///
+    /// The content is not validated in any way.
+    pub fn decode_payload(
+        header: &SignedObjectHeader
+    ) -> Result<RouteOriginAttestation, DecodeError<Infallible>> {
+        header.decode_content(|cons| {
+            RouteOriginAttestation::take_from(cons)
+        }).map_err(DecodeError::convert)
+    }
+
+    /// Creates a complete ROA from its header.
+    pub fn from_header(
+        header: SignedObjectHeader
+    ) -> Result<Self, DecodeError<Infallible>> {
+        let content = Self::decode_payload(&header)?;
+        let signed = header.into_signed_object()?;
+        Ok(Roa { signed, content })
+    }
+
     pub fn process<F>(
         mut self,
         issuer: &ResourceCert,
@@ -54,6 +96,70 @@ impl Roa {
         Ok((cert, self.content))
     }
 
+    /// Processes the ROA at the given time.
+    ///
+    /// This is identical to [`process`][Self::process] except that the
+    /// validity of the ROA’s EE certificate is checked against `now`
+    /// instead of the current time.
+    pub fn process_at<F>(
+        mut self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, RouteOriginAttestation), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let cert = self.signed.validate_at(issuer, strict, now)?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert)?;
+        Ok((cert, self.content))
+    }
+
+    /// Processes the ROA using the given options.
+    ///
+    /// Unlike [`process`][Self::process], this checks the signing time
+    /// against the window given in the options and applies the grace
+    /// period for an EE certificate that is not yet valid. If the grace
+    /// period was needed, a warning is returned alongside the result.
+    pub fn process_with_options<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, RouteOriginAttestation, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_with_options_at(
+            issuer, options, Time::now(), check_crl
+        )
+    }
+
+    /// Processes the ROA using the given options at the given time.
+    ///
+    /// This is identical to
+    /// [`process_with_options`][Self::process_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn process_with_options_at<F>(
+        mut self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, RouteOriginAttestation, Option<GraceWarning>),
+        ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let (cert, warning) = self.signed.validate_with_options_at(
+            issuer, options, now
+        )?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert)?;
+        Ok((cert, self.content, warning))
+    }
+
     /// Returns a value encoder for a reference to a ROA.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         self.signed.encode_ref()
@@ -64,11 +170,25 @@ impl Roa {
         self.encode_ref().to_captured(Mode::Der)
     }
 
+    /// Returns the encoded octets of the ROA.
+    ///
+    /// If the ROA was decoded, these are exactly the octets it was
+    /// decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
     /// Returns a reference to the EE certificate of this ROA.
     pub fn cert(&self) -> &Cert {
         self.signed.cert()
     }
 
+    /// Returns a reference to the signed object of this ROA.
+    #[cfg(feature = "bench")]
+    pub(crate) fn signed_object(&self) -> &SignedObject {
+        &self.signed
+    }
+
     /// Returns a reference to the content of the ROA object
     pub fn content(&self) -> &RouteOriginAttestation {
         &self.content
@@ -285,7 +405,7 @@ impl RoaIpAddresses {
         self.0.is_empty()
     }
 
-    pub fn iter(&self) -> RoaIpAddressIter {
+    pub fn iter(&self) -> RoaIpAddressIter<'_> {
         RoaIpAddressIter(self.0.as_slice().into_source())
     }
 
@@ -703,6 +823,7 @@ impl From<UncoveredPrefix> for VerificationError {
 
 #[cfg(test)]
 mod test {
+    use crate::repository::manifest::Manifest;
     use super::*;
 
     #[test]
@@ -717,6 +838,33 @@ mod test {
         )
     }
 
+    #[test]
+    fn round_trip() {
+        let der = include_bytes!(
+            "../../test-data/repository/example-ripe.roa"
+        ).as_ref();
+        let roa = Roa::decode(der, false).unwrap();
+        assert_eq!(roa.as_bytes(), der);
+        assert_eq!(roa.to_captured().as_slice(), der);
+    }
+
+    #[test]
+    fn parse_header_only() {
+        let der = include_bytes!(
+            "../../test-data/repository/example-ripe.roa"
+        ).as_ref();
+        let full = Roa::decode(der, false).unwrap();
+        let header = Roa::parse_header_only(der, false).unwrap();
+        let payload = Roa::decode_payload(&header).unwrap();
+        assert_eq!(payload.as_id(), full.content().as_id());
+        assert_eq!(payload.iter().count(), full.content().iter().count());
+        let roa = Roa::from_header(header).unwrap();
+        assert_eq!(roa.as_bytes(), der);
+        assert!(
+            Manifest::parse_header_only(der, false).is_err()
+        );
+    }
+
     #[test]
     fn decode_illegal_roas() {
         assert!(
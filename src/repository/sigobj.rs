@@ -3,6 +3,7 @@
 // See RFC 6488 and RFC 5652.
 
 use std::{cmp, fmt, io};
+use std::convert::Infallible;
 use bcder::{decode, encode};
 use bcder::{Captured, Mode, OctetString, Oid, Tag};
 use bcder::decode::{ContentError, DecodeError, IntoSource, Source};
@@ -11,10 +12,15 @@ use bcder::string::OctetStringSource;
 use bytes::Bytes;
 use crate::{oid, uri};
 use crate::crypto::{
-    Digest, DigestAlgorithm, KeyIdentifier, RpkiSignature,
+    AlgorithmSuite, Digest, DigestAlgorithm, KeyIdentifier, RpkiSignature,
     RpkiSignatureAlgorithm, Signer, SigningError
 };
+use crate::store::ObjectStore;
 use super::cert::{Cert, KeyUsage, Overclaim, ResourceCert, TbsCert};
+use super::crl::CrlCheck;
+use super::options::{
+    GraceWarning, SigningTimeError, SigningTimeWindow, ValidationOptions
+};
 use super::error::{
     InspectionError, ValidationError, VerificationError
 };
@@ -22,7 +28,7 @@ use super::resources::{
     AsBlocksBuilder, AsResources, AsResourcesBuilder, IpBlocksBuilder,
     IpResources, IpResourcesBuilder
 };
-use super::x509::{Name, Serial, Time, Validity};
+use super::x509::{Encoded, Name, Serial, Time, Validity};
 
 
 //------------ SignedObject --------------------------------------------------
@@ -48,6 +54,10 @@ pub struct SignedObject {
     message_digest: MessageDigest,
     signing_time: Option<Time>,
     binary_signing_time: Option<u64>,
+
+    //--- The complete encoding of the object
+    //
+    encoded: Encoded,
 }
 
 /// # Data Access
@@ -105,6 +115,50 @@ impl SignedObject {
         }.decode(source.into_source(), Self::take_from)
     }
 
+    /// Parses only the header of a signed object.
+    ///
+    /// This skips decoding the certificate and the content. See
+    /// [`SignedObjectHeader`] for details.
+    pub fn parse_header_only<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<
+        SignedObjectHeader, DecodeError<<S::Source as Source>::Error>
+    > {
+        SignedObjectHeader::decode(source, strict)
+    }
+
+    /// Decodes a signed object and reports the leniencies needed.
+    ///
+    /// RFC 6488 requires signed objects to be DER encoded, but objects
+    /// using BER constructs such as indefinite length values in the CMS
+    /// wrapper can be found in the wild. With [`CmsMode::Lenient`], these
+    /// objects are accepted and the returned [`Leniencies`] says which
+    /// deviations were encountered. With [`CmsMode::Strict`], decoding
+    /// fails for them.
+    ///
+    /// In either mode, the eContent of the object is always required to be
+    /// DER encoded when it is decoded via
+    /// [`decode_content`][Self::decode_content].
+    pub fn decode_with_mode(
+        bytes: Bytes,
+        mode: CmsMode,
+    ) -> Result<(Self, Leniencies), DecodeError<Infallible>> {
+        let der_err = match Mode::Der.decode(bytes.clone(), Self::take_from) {
+            Ok(res) => return Ok((res, Leniencies::default())),
+            Err(err) => err,
+        };
+        if mode == CmsMode::Strict {
+            return Err(der_err)
+        }
+        let res = Mode::Ber.decode(bytes, Self::take_from)?;
+        let leniencies = Leniencies {
+            ber_wrapper: true,
+            constructed_content: res.content.as_slice().is_none(),
+        };
+        Ok((res, leniencies))
+    }
+
     /// Decodes a signed object if it has the correct content type.
     pub fn decode_if_type<S: IntoSource>(
         source: S,
@@ -121,79 +175,39 @@ impl SignedObject {
     }
 
     /// Takes a signed object from an encoded constructed value.
+    ///
+    /// The octets the object is taken from are kept and used when encoding
+    /// the object again.
     pub fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
-        cons.take_sequence(|cons| { // ContentInfo
-            oid::SIGNED_DATA.skip_if(cons)?; // contentType
-            cons.take_constructed_if(Tag::CTX_0, |cons| { // content
-                cons.take_sequence(|cons| { // SignedData
-                    cons.skip_u8_if(3)?; // version -- must be 3
-                    let digest_algorithm =
-                        DigestAlgorithm::take_set_from(cons)?;
-                    let (content_type, content) = {
-                        cons.take_sequence(|cons| { // encapContentInfo
-                            Ok((
-                                Oid::take_from(cons)?,
-                                cons.take_constructed_if(
-                                    Tag::CTX_0,
-                                    OctetString::take_from
-                                )?
-                            ))
-                        })?
-                    };
-                    let cert = cons.take_constructed_if( // certificates
-                        Tag::CTX_0,
-                        Cert::take_from
-                    )?;
-                    // no crls
-                    let (sid, attrs, signature) = { // signerInfos
-                        cons.take_set(|cons| {
-                            cons.take_sequence(|cons| {
-                                cons.skip_u8_if(3)?;
-                                let sid = cons.take_value_if(
-                                    Tag::CTX_0, |content| {
-                                        KeyIdentifier::from_content(content)
-                                    }
-                                )?;
-                                let alg = DigestAlgorithm::take_from(cons)?;
-                                if alg != digest_algorithm {
-                                    return Err(cons.content_err(
-                                        "digest algorithm mismatch"
-                                    ))
-                                }
-                                let attrs = SignedAttrs::take_from(cons)?;
-                                if attrs.2 != content_type {
-                                    return Err(cons.content_err(
-                                        "content type in signed attributes \
-                                        differs"
-                                    ))
-                                }
-                                let signature = RpkiSignature::new(
-                                    RpkiSignatureAlgorithm::cms_take_from(
-                                        cons
-                                    )?,
-                                    OctetString::take_from(cons)?.into_bytes()
-                                );
-                                // no unsignedAttributes
-                                Ok((sid, attrs, signature))
-                            })
-                        })?
-                    };
-                    Ok(Self {
-                        digest_algorithm,
-                        content_type,
-                        content,
-                        cert,
-                        sid,
-                        signed_attrs: attrs.0,
-                        signature,
-                        message_digest: attrs.1,
-                        signing_time: attrs.3,
-                        binary_signing_time: attrs.4
-                    })
-                })
-            })
+        let (mut res, encoded) = Encoded::take_from(cons, |cons| {
+            Self::take_fields_from(cons)
+        })?;
+        res.encoded = encoded;
+        Ok(res)
+    }
+
+    /// Takes the fields of a signed object from an encoded value.
+    ///
+    /// The `encoded` field of the returned value is left empty.
+    fn take_fields_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        take_parts_from::<_, Cert>(cons).map(|parts| {
+            Self {
+                digest_algorithm: parts.digest_algorithm,
+                content_type: parts.content_type,
+                content: parts.content,
+                cert: parts.cert,
+                sid: parts.sid,
+                signed_attrs: parts.signed_attrs,
+                signature: parts.signature,
+                message_digest: parts.message_digest,
+                signing_time: parts.signing_time,
+                binary_signing_time: parts.binary_signing_time,
+                encoded: Encoded::default(),
+            }
         })
     }
 
@@ -231,7 +245,97 @@ impl SignedObject {
     ) -> Result<ResourceCert, ValidationError> {
         self.inspect(strict)?;
         self.verify(strict)?;
-        self.cert.validate_ee_at(issuer, strict, now).map_err(Into::into)
+        self.cert.validate_ee_at(issuer, strict, now)
+    }
+
+    /// Validates the signed object using the given options.
+    ///
+    /// This applies the grace period for certificates that are not yet
+    /// valid. If it was needed, a warning is returned alongside the
+    /// validated EE certificate. It also checks the signing time against
+    /// the signing time window of the options.
+    pub fn validate_with_options(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+    ) -> Result<(ResourceCert, Option<GraceWarning>), ValidationError> {
+        self.validate_with_options_at(issuer, options, Time::now())
+    }
+
+    /// Validates the signed object using the given options at a given time.
+    ///
+    /// This is identical to
+    /// [`validate_with_options`][Self::validate_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn validate_with_options_at(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time,
+    ) -> Result<(ResourceCert, Option<GraceWarning>), ValidationError> {
+        self.check_signing_time(&options.signing_time, now)?;
+        let (now, warning) = options.validation_time(
+            self.cert.validity().not_before(), now
+        );
+        self.validate_at(issuer, options.strict, now).map(|cert| {
+            (cert, warning)
+        })
+    }
+
+    /// Checks that the signing time of the object is within `window`.
+    ///
+    /// The signing time is the time in the signed attributes and says
+    /// nothing about the validity of the object. Checking it allows
+    /// rejecting objects that have been signed implausibly long ago or
+    /// claim to have been signed in the future.
+    pub fn check_signing_time(
+        &self, window: &SigningTimeWindow, now: Time,
+    ) -> Result<(), SigningTimeError> {
+        window.check(self.signing_time, self.binary_signing_time, now)
+    }
+
+    /// Validates the signed object including the CRL check.
+    ///
+    /// In addition to [`validate`][Self::validate], this checks that the
+    /// EE certificate of the object has not been revoked by the CRL of
+    /// `issuer`. The CRL is taken from the object store used by `crls`.
+    pub fn validate_with_crl<S: ObjectStore>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        crls: &mut CrlCheck<S>,
+    ) -> Result<ResourceCert, ValidationError> {
+        self.validate_with_crl_at(issuer, strict, crls, Time::now())
+    }
+
+    /// Validates the signed object including the CRL check at a given time.
+    ///
+    /// This is identical to [`validate_with_crl`][Self::validate_with_crl]
+    /// with an explicitly given value for the current time.
+    pub fn validate_with_crl_at<S: ObjectStore>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        crls: &mut CrlCheck<S>,
+        now: Time,
+    ) -> Result<ResourceCert, ValidationError> {
+        let cert = self.validate_at(issuer, strict, now)?;
+        crls.check_at(cert.as_cert(), issuer.as_cert(), now)?;
+        Ok(cert)
+    }
+
+    /// Checks the object and its signature against the contained cert.
+    ///
+    /// This performs the checks of [`validate`][Self::validate] except
+    /// for the validation of the EE certificate. It is used for
+    /// benchmarking.
+    #[cfg(feature = "bench")]
+    pub(crate) fn verify_self(
+        &self, strict: bool
+    ) -> Result<(), ValidationError> {
+        self.inspect(strict)?;
+        self.verify(strict)?;
+        Ok(())
     }
 
     /// Validates that the signed object complies with the specification.
@@ -278,7 +382,25 @@ impl SignedObject {
     }
 
     /// Returns a value encoder for a reference to a signed object.
+    ///
+    /// If the object was decoded, the encoder produces exactly the octets
+    /// it was decoded from.
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        &self.encoded
+    }
+
+    /// Returns the encoded octets of the signed object.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.encoded.as_slice()
+    }
+
+    /// Returns a DER encoded captured value of the signed object.
+    pub fn to_captured(&self) -> Captured {
+        self.encoded.to_captured()
+    }
+
+    /// Returns a value encoder for the fields of the signed object.
+    fn encode_fields(&self) -> impl encode::Values + '_ {
         encode::sequence((
             oid::SIGNED_DATA.encode(), // contentType
             encode::sequence_as(Tag::CTX_0, // content
@@ -316,6 +438,340 @@ impl SignedObject {
 }
 
 
+//------------ SignedObjectHeader --------------------------------------------
+
+/// A signed object with its certificate and content not yet decoded.
+///
+/// Parsing the EE certificate is the most expensive part of decoding a
+/// signed object and decoding the eContent often isn’t cheap, either.
+/// Applications that only need part of the object, such as the digest of
+/// the content, the signing time, or only the payload, can avoid this work
+/// by starting with the header as returned by
+/// [`SignedObject::parse_header_only`] or the `parse_header_only` functions
+/// of the concrete object types.
+///
+/// The header checks the structure of the CMS wrapper as well as the
+/// signed attributes. The certificate can be decoded later via
+/// [`decode_cert`][Self::decode_cert] and the content via
+/// [`decode_content`][Self::decode_content]. A full signed object
+/// necessary for validation can be created via
+/// [`into_signed_object`][Self::into_signed_object].
+#[derive(Clone, Debug)]
+pub struct SignedObjectHeader {
+    /// The parts of the object with the certificate still encoded.
+    parts: Parts<Captured>,
+
+    /// The mode the object was decoded in.
+    mode: Mode,
+
+    /// The complete encoding of the object.
+    encoded: Encoded,
+}
+
+impl SignedObjectHeader {
+    /// Parses the header of a signed object from the given source.
+    pub fn decode<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<Self, DecodeError<<S::Source as Source>::Error>> {
+        let mode = if strict { Mode::Der } else { Mode::Ber };
+        mode.decode(source.into_source(), |cons| {
+            let (parts, encoded) = Encoded::take_from(cons, |cons| {
+                take_parts_from(cons)
+            })?;
+            Ok(SignedObjectHeader { parts, mode, encoded })
+        })
+    }
+
+    /// Parses the header if the object has the correct content type.
+    pub fn decode_if_type<S: IntoSource>(
+        source: S,
+        content_type: &impl PartialEq<Oid>,
+        strict: bool,
+    ) -> Result<Self, DecodeError<<S::Source as Source>::Error>> {
+        let res = Self::decode(source, strict)?;
+        if content_type.ne(res.content_type()) {
+            return Err(DecodeError::content(
+                "invalid content type", Default::default()
+            ))
+        }
+        Ok(res)
+    }
+
+    /// Returns a reference to the object’s content type.
+    pub fn content_type(&self) -> &Oid<Bytes> {
+        &self.parts.content_type
+    }
+
+    /// Returns a reference to the object’s content.
+    pub fn content(&self) -> &OctetString {
+        &self.parts.content
+    }
+
+    /// Decodes the object’s content.
+    pub fn decode_content<F, T>(
+        &self, op: F
+    ) -> Result<T, DecodeError<<OctetStringSource as decode::Source>::Error>>
+    where F: FnOnce(
+        &mut decode::Constructed<OctetStringSource>
+    ) -> Result<T, DecodeError<<OctetStringSource as decode::Source>::Error>> {
+        Mode::Der.decode(self.parts.content.clone(), op)
+    }
+
+    /// Returns the digest algorithm of the object.
+    pub fn digest_algorithm(&self) -> DigestAlgorithm {
+        self.parts.digest_algorithm
+    }
+
+    /// Returns the digest of the content given in the signed attributes.
+    ///
+    /// Note that this value is claimed by the signer and only checked
+    /// when the object is validated.
+    pub fn content_digest(&self) -> &[u8] {
+        self.parts.message_digest.as_ref()
+    }
+
+    /// Returns the key identifier of the signer.
+    pub fn signer_key_identifier(&self) -> KeyIdentifier {
+        self.parts.sid
+    }
+
+    /// Returns the signing time if available.
+    pub fn signing_time(&self) -> Option<Time> {
+        self.parts.signing_time
+    }
+
+    /// Returns the binary signing time if available.
+    pub fn binary_signing_time(&self) -> Option<u64> {
+        self.parts.binary_signing_time
+    }
+
+    /// Returns the encoded certificate the object is signed with.
+    pub fn cert_bytes(&self) -> &[u8] {
+        self.parts.cert.as_slice()
+    }
+
+    /// Decodes the certificate the object is signed with.
+    pub fn decode_cert(&self) -> Result<Cert, DecodeError<Infallible>> {
+        self.mode.decode(
+            self.parts.cert.clone().into_bytes(), Cert::take_from
+        )
+    }
+
+    /// Returns the complete encoding of the object.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.encoded.as_slice()
+    }
+
+    /// Converts the header into a complete signed object.
+    ///
+    /// This decodes the certificate.
+    pub fn into_signed_object(
+        self
+    ) -> Result<SignedObject, DecodeError<Infallible>> {
+        let cert = self.decode_cert()?;
+        let parts = self.parts;
+        Ok(SignedObject {
+            digest_algorithm: parts.digest_algorithm,
+            content_type: parts.content_type,
+            content: parts.content,
+            cert,
+            sid: parts.sid,
+            signed_attrs: parts.signed_attrs,
+            signature: parts.signature,
+            message_digest: parts.message_digest,
+            signing_time: parts.signing_time,
+            binary_signing_time: parts.binary_signing_time,
+            encoded: self.encoded,
+        })
+    }
+}
+
+
+//------------ Parts ---------------------------------------------------------
+
+/// The fields of a signed object with the certificate left open.
+///
+/// This allows sharing the parsing code between [`SignedObject`], which
+/// parses the certificate, and [`SignedObjectHeader`], which doesn’t.
+#[derive(Clone, Debug)]
+struct Parts<C> {
+    digest_algorithm: DigestAlgorithm,
+    content_type: Oid<Bytes>,
+    content: OctetString,
+    cert: C,
+    sid: KeyIdentifier,
+    signed_attrs: SignedAttrs,
+    signature: RpkiSignature,
+    message_digest: MessageDigest,
+    signing_time: Option<Time>,
+    binary_signing_time: Option<u64>,
+}
+
+/// A type the certificate of a signed object can be taken as.
+trait TakeCert: Sized {
+    fn take_cert_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>>;
+}
+
+impl TakeCert for Cert {
+    fn take_cert_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        Cert::take_from(cons)
+    }
+}
+
+impl TakeCert for Captured {
+    fn take_cert_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        cons.capture_one()
+    }
+}
+
+/// Takes the parts of a signed object from an encoded value.
+fn take_parts_from<S: decode::Source, C: TakeCert>(
+    cons: &mut decode::Constructed<S>
+) -> Result<Parts<C>, DecodeError<S::Error>> {
+    cons.take_sequence(|cons| { // ContentInfo
+        oid::SIGNED_DATA.skip_if(cons)?; // contentType
+        cons.take_constructed_if(Tag::CTX_0, |cons| { // content
+            cons.take_sequence(|cons| { // SignedData
+                cons.skip_u8_if(3)?; // version -- must be 3
+                let digest_algorithm =
+                    DigestAlgorithm::take_set_from(cons)?;
+                let (content_type, content) = {
+                    cons.take_sequence(|cons| { // encapContentInfo
+                        Ok((
+                            Oid::take_from(cons)?,
+                            cons.take_constructed_if(
+                                Tag::CTX_0,
+                                OctetString::take_from
+                            )?
+                        ))
+                    })?
+                };
+                let cert = cons.take_constructed_if( // certificates
+                    Tag::CTX_0,
+                    C::take_cert_from
+                )?;
+                // no crls
+                let (sid, attrs, signature) = { // signerInfos
+                    cons.take_set(|cons| {
+                        cons.take_sequence(|cons| {
+                            cons.skip_u8_if(3)?;
+                            let sid = cons.take_value_if(
+                                Tag::CTX_0, |content| {
+                                    KeyIdentifier::from_content(content)
+                                }
+                            )?;
+                            let alg = DigestAlgorithm::take_from(cons)?;
+                            if alg != digest_algorithm {
+                                return Err(cons.content_err(
+                                    "digest algorithm mismatch"
+                                ))
+                            }
+                            let attrs = SignedAttrs::take_from(cons)?;
+                            if attrs.2 != content_type {
+                                return Err(cons.content_err(
+                                    "content type in signed attributes \
+                                    differs"
+                                ))
+                            }
+                            let signature = RpkiSignature::new(
+                                RpkiSignatureAlgorithm::cms_take_from(
+                                    cons
+                                )?,
+                                OctetString::take_from(cons)?.into_bytes()
+                            );
+                            // no unsignedAttributes
+                            Ok((sid, attrs, signature))
+                        })
+                    })?
+                };
+                Ok(Parts {
+                    digest_algorithm,
+                    content_type,
+                    content,
+                    cert,
+                    sid,
+                    signed_attrs: attrs.0,
+                    signature,
+                    message_digest: attrs.1,
+                    signing_time: attrs.3,
+                    binary_signing_time: attrs.4,
+                })
+            })
+        })
+    })
+}
+
+
+//------------ CmsMode -------------------------------------------------------
+
+/// The encoding rules to use when decoding the CMS wrapper of an object.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum CmsMode {
+    /// The CMS wrapper must be DER encoded.
+    ///
+    /// This is what RFC 6488 requires and is useful for conformance
+    /// testing.
+    Strict,
+
+    /// The CMS wrapper may use BER.
+    #[default]
+    Lenient,
+}
+
+
+//------------ Leniencies ----------------------------------------------------
+
+/// The deviations from DER encountered when decoding a signed object.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Leniencies {
+    /// The CMS wrapper was not DER encoded.
+    ber_wrapper: bool,
+
+    /// The eContent octet string was encoded in constructed form.
+    constructed_content: bool,
+}
+
+impl Leniencies {
+    /// Returns whether the object was correctly DER encoded.
+    pub fn is_empty(&self) -> bool {
+        !self.ber_wrapper && !self.constructed_content
+    }
+
+    /// Returns whether the CMS wrapper was not DER encoded.
+    pub fn ber_wrapper(&self) -> bool {
+        self.ber_wrapper
+    }
+
+    /// Returns whether the eContent octet string was constructed.
+    ///
+    /// This is one of the BER constructs in the wrapper and implies
+    /// [`ber_wrapper`][Self::ber_wrapper].
+    pub fn constructed_content(&self) -> bool {
+        self.constructed_content
+    }
+}
+
+impl fmt::Display for Leniencies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none")
+        }
+        f.write_str("BER encoded CMS wrapper")?;
+        if self.constructed_content {
+            f.write_str(", constructed eContent")?;
+        }
+        Ok(())
+    }
+}
+
+
 //------------ SignedAttrs ---------------------------------------------------
 
 /// A private helper type that contains the raw signed attributes content.
@@ -666,6 +1122,11 @@ pub struct SignedObjectBuilder {
     /// By default, this will be the default algorithm.
     digest_algorithm: DigestAlgorithm,
 
+    /// The signature algorithm for the object and its EE certificate.
+    ///
+    /// By default, this will be the default algorithm.
+    signature_algorithm: RpkiSignatureAlgorithm,
+
     /// The serial number of the EE certificate.
     ///
     /// Must be provided.
@@ -739,6 +1200,7 @@ impl SignedObjectBuilder {
     ) -> Self {
         Self {
             digest_algorithm: DigestAlgorithm::default(),
+            signature_algorithm: RpkiSignatureAlgorithm::default(),
             serial_number,
             validity,
             issuer: None,
@@ -762,6 +1224,25 @@ impl SignedObjectBuilder {
         self.digest_algorithm = algorithm
     }
 
+    pub fn signature_algorithm(&self) -> RpkiSignatureAlgorithm {
+        self.signature_algorithm
+    }
+
+    pub fn set_signature_algorithm(
+        &mut self, algorithm: RpkiSignatureAlgorithm
+    ) {
+        self.signature_algorithm = algorithm
+    }
+
+    pub fn algorithm_suite(&self) -> AlgorithmSuite {
+        AlgorithmSuite::new(self.signature_algorithm, self.digest_algorithm)
+    }
+
+    pub fn set_algorithm_suite(&mut self, suite: AlgorithmSuite) {
+        self.signature_algorithm = suite.signature_algorithm();
+        self.digest_algorithm = suite.digest_algorithm();
+    }
+
     pub fn serial_number(&self) -> Serial {
         self.serial_number
     }
@@ -932,7 +1413,7 @@ impl SignedObjectBuilder {
 
         // Sign signed attributes with a one-off key.
         let (signature, key_info) = signer.sign_one_off(
-            RpkiSignatureAlgorithm::default(), &signed_attrs.encode_verify()
+            self.signature_algorithm, &signed_attrs.encode_verify()
         )?;
         let sid = key_info.key_identifier();
 
@@ -946,6 +1427,7 @@ impl SignedObjectBuilder {
             KeyUsage::Ee,
             Overclaim::Refuse,
         );
+        cert.set_signature(self.signature_algorithm);
         cert.set_authority_key_identifier(Some(issuer_pub.key_identifier()));
         cert.set_crl_uri(Some(self.crl_uri));
         cert.set_ca_issuer(Some(self.ca_issuer));
@@ -955,7 +1437,7 @@ impl SignedObjectBuilder {
         cert.set_as_resources(self.as_resources);
         let cert = cert.into_cert(signer, issuer_key)?;
 
-        Ok(SignedObject {
+        let mut res = SignedObject {
             digest_algorithm: self.digest_algorithm,
             content_type,
             content: OctetString::new(content),
@@ -966,7 +1448,10 @@ impl SignedObjectBuilder {
             message_digest,
             signing_time: self.signing_time,
             binary_signing_time: self.binary_signing_time,
-        })
+            encoded: Encoded::default(),
+        };
+        res.encoded = Encoded::from_values(res.encode_fields());
+        Ok(res)
     }
 }
 
@@ -1049,6 +1534,35 @@ mod test {
     use crate::repository::tal::TalInfo;
     use super::*;
 
+    #[test]
+    fn decode_with_mode() {
+        let ber = Bytes::from_static(
+            include_bytes!("../../test-data/repository/example-ripe.roa")
+        );
+        assert!(
+            SignedObject::decode_with_mode(ber.clone(), CmsMode::Strict)
+            .is_err()
+        );
+        let (obj, leniencies) = SignedObject::decode_with_mode(
+            ber, CmsMode::Lenient
+        ).unwrap();
+        assert!(leniencies.ber_wrapper());
+        assert!(!leniencies.is_empty());
+        assert_eq!(obj.content_type(), &oid::ROUTE_ORIGIN_AUTHZ);
+
+        let der = Bytes::from_static(
+            include_bytes!("../../test-data/repository/maxlen-overflow.roa")
+        );
+        let (_, leniencies) = SignedObject::decode_with_mode(
+            der.clone(), CmsMode::Strict
+        ).unwrap();
+        assert!(leniencies.is_empty());
+        let (_, leniencies) = SignedObject::decode_with_mode(
+            der, CmsMode::Lenient
+        ).unwrap();
+        assert!(leniencies.is_empty());
+    }
+
     #[test]
     fn decode() {
         let talinfo = TalInfo::from_name("foo".into()).into_arc();
@@ -1068,6 +1582,71 @@ mod test {
         ).unwrap();
         assert!(obj.validate_at(&issuer, false, at).is_err());
     }
+
+    #[test]
+    fn parse_header_only() {
+        let talinfo = TalInfo::from_name("foo".into()).into_arc();
+        let at = Time::utc(2019, 5, 1, 0, 0, 0);
+        let issuer = Cert::decode(
+            include_bytes!("../../test-data/repository/ta.cer").as_ref()
+        ).unwrap();
+        let issuer = issuer.validate_ta_at(talinfo, false, at).unwrap();
+        let data = include_bytes!("../../test-data/repository/ta.mft");
+        let full = SignedObject::decode(data.as_ref(), false).unwrap();
+        let header = SignedObject::parse_header_only(
+            data.as_ref(), false
+        ).unwrap();
+        assert_eq!(header.content_type(), full.content_type());
+        assert_eq!(header.signing_time(), full.signing_time());
+        assert_eq!(header.content_digest(), full.message_digest.as_ref());
+        assert_eq!(
+            header.signer_key_identifier(),
+            full.cert().subject_key_identifier()
+        );
+        assert_eq!(header.as_bytes(), data.as_ref());
+        assert_eq!(
+            header.decode_cert().unwrap().subject_key_identifier(),
+            full.cert().subject_key_identifier()
+        );
+        let obj = header.into_signed_object().unwrap();
+        assert_eq!(obj.as_bytes(), data.as_ref());
+        obj.validate_at(&issuer, false, at).unwrap();
+
+        assert!(SignedObjectHeader::decode_if_type(
+            data.as_ref(), &oid::ROUTE_ORIGIN_AUTHZ, false
+        ).is_err());
+    }
+
+    #[test]
+    fn validate_with_crl() {
+        use crate::store::MemoryStore;
+
+        let talinfo = TalInfo::from_name("foo".into()).into_arc();
+        let at = Time::utc(2019, 5, 1, 0, 0, 0);
+        let issuer = Cert::decode(
+            include_bytes!("../../test-data/repository/ta.cer").as_ref()
+        ).unwrap();
+        let issuer = issuer.validate_ta_at(talinfo, false, at).unwrap();
+        let obj = SignedObject::decode(
+            include_bytes!("../../test-data/repository/ta.mft").as_ref(),
+            false
+        ).unwrap();
+
+        let mut store = MemoryStore::new();
+        assert!(obj.clone().validate_with_crl_at(
+            &issuer, false, &mut CrlCheck::new(&store), at
+        ).is_err());
+
+        store.insert(
+            obj.cert().crl_uri().unwrap().clone(),
+            Bytes::from_static(
+                include_bytes!("../../test-data/repository/ta.crl")
+            )
+        ).unwrap();
+        obj.validate_with_crl_at(
+            &issuer, false, &mut CrlCheck::new(&store), at
+        ).unwrap();
+    }
 }
 
 #[cfg(all(test, feature="softkeys"))]
@@ -0,0 +1,523 @@
+//! Trust Anchor Keys.
+//!
+//! A Trust Anchor Key (TAK) object is a signed object published by a
+//! trust anchor in its own publication point. It announces the key the
+//! trust anchor currently uses and, optionally, the key it used before
+//! and the key it is going to switch to. This allows relying parties to
+//! learn about a planned key rollover from the repository itself rather
+//! than through some out-of-band channel.
+//!
+//! The object is defined in [RFC 9691]. Each key is given as a [`TaKey`]
+//! which contains everything needed for a TAL – the URIs of the trust
+//! anchor certificate and its public key – so that a successor key can
+//! be turned into a new TAL via [`TaKey::to_tal`].
+//!
+//! Keeping track of announced successors across validation runs is the
+//! job of [`TaKeyMonitor::observe_tak`][super::tal::TaKeyMonitor] while
+//! [`TalSet::roll_over`][super::tal::TalSet::roll_over] switches a trust
+//! anchor to its successor.
+//!
+//! [RFC 9691]: https://tools.ietf.org/html/rfc9691
+
+use bcder::{decode, encode};
+use bcder::{Captured, Ia5String, Mode, OctetString, Oid, Tag, Utf8String};
+use bcder::decode::{DecodeError, IntoSource, Source};
+use bcder::encode::Values;
+use crate::oid;
+use crate::crypto::{PublicKey, Signer, SigningError};
+use super::cert::{Cert, ResourceCert};
+use super::error::{ValidationError, VerificationError};
+use super::options::{GraceWarning, ValidationOptions};
+use super::sigobj::{SignedObject, SignedObjectBuilder};
+use super::tal::{Tal, TalUri};
+use super::x509::Time;
+
+
+//------------ Tak -----------------------------------------------------------
+
+/// A Trust Anchor Key signed object.
+#[derive(Clone, Debug)]
+pub struct Tak {
+    signed: SignedObject,
+    content: TrustAnchorKeys,
+}
+
+impl Tak {
+    /// Decodes a TAK object from a source.
+    pub fn decode<S: IntoSource>(
+        source: S,
+        strict: bool
+    ) -> Result<Self, DecodeError<<S::Source as Source>::Error>> {
+        let signed = SignedObject::decode_if_type(
+            source, &oid::CT_SIGNED_TAL, strict
+        )?;
+        let content = signed.decode_content(|cons| {
+            TrustAnchorKeys::take_from(cons)
+        }).map_err(DecodeError::convert)?;
+        Ok(Tak { signed, content })
+    }
+
+    /// Validates the TAK object.
+    ///
+    /// The `issuer` must be the trust anchor certificate. Besides the
+    /// usual checks for signed objects, the current key of the object
+    /// must be the key of the trust anchor certificate and the EE
+    /// certificate must inherit all its resources.
+    pub fn process<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        check_crl: F
+    ) -> Result<(ResourceCert, TrustAnchorKeys), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_at(issuer, strict, Time::now(), check_crl)
+    }
+
+    /// Validates the TAK object at the given time.
+    ///
+    /// This is identical to [`process`][Self::process] except that the
+    /// validity of the EE certificate is checked against `now` instead of
+    /// the current time.
+    pub fn process_at<F>(
+        self,
+        issuer: &ResourceCert,
+        strict: bool,
+        now: Time,
+        check_crl: F
+    ) -> Result<(ResourceCert, TrustAnchorKeys), ValidationError>
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let cert = self.signed.validate_at(issuer, strict, now)?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert, issuer)?;
+        Ok((cert, self.content))
+    }
+
+    /// Validates the TAK object using the given options.
+    ///
+    /// Unlike [`process`][Self::process], this checks the signing time
+    /// against the window given in the options and applies the grace
+    /// period for an EE certificate that is not yet valid. If the grace
+    /// period was needed, a warning is returned alongside the result.
+    pub fn process_with_options<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, TrustAnchorKeys, Option<GraceWarning>), ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        self.process_with_options_at(
+            issuer, options, Time::now(), check_crl
+        )
+    }
+
+    /// Validates the TAK object using the given options at the given time.
+    ///
+    /// This is identical to
+    /// [`process_with_options`][Self::process_with_options] with an
+    /// explicitly given value for the current time.
+    pub fn process_with_options_at<F>(
+        self,
+        issuer: &ResourceCert,
+        options: &ValidationOptions,
+        now: Time,
+        check_crl: F
+    ) -> Result<
+        (ResourceCert, TrustAnchorKeys, Option<GraceWarning>), ValidationError
+    >
+    where F: FnOnce(&Cert) -> Result<(), ValidationError> {
+        let (cert, warning) = self.signed.validate_with_options_at(
+            issuer, options, now
+        )?;
+        check_crl(cert.as_ref())?;
+        self.content.verify(&cert, issuer)?;
+        Ok((cert, self.content, warning))
+    }
+
+    /// Returns a value encoder for a reference to a TAK object.
+    pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        self.signed.encode_ref()
+    }
+
+    /// Returns a DER encoded Captured for this TAK object.
+    pub fn to_captured(&self) -> Captured {
+        self.encode_ref().to_captured(Mode::Der)
+    }
+
+    /// Returns the encoded octets of the TAK object.
+    ///
+    /// If the object was decoded, these are exactly the octets it was
+    /// decoded from.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
+    /// Returns a reference to the EE certificate of the object.
+    pub fn cert(&self) -> &Cert {
+        self.signed.cert()
+    }
+
+    /// Returns a reference to the content of the object.
+    pub fn content(&self) -> &TrustAnchorKeys {
+        &self.content
+    }
+}
+
+
+//------------ TrustAnchorKeys -----------------------------------------------
+
+/// The content of a TAK object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrustAnchorKeys {
+    /// The key currently used by the trust anchor.
+    current: TaKey,
+
+    /// The key previously used by the trust anchor.
+    predecessor: Option<TaKey>,
+
+    /// The key the trust anchor is going to use next.
+    successor: Option<TaKey>,
+}
+
+impl TrustAnchorKeys {
+    /// Creates a new value from its components.
+    pub fn new(
+        current: TaKey,
+        predecessor: Option<TaKey>,
+        successor: Option<TaKey>,
+    ) -> Self {
+        TrustAnchorKeys { current, predecessor, successor }
+    }
+
+    /// Returns the key currently used by the trust anchor.
+    pub fn current(&self) -> &TaKey {
+        &self.current
+    }
+
+    /// Returns the key previously used by the trust anchor, if any.
+    pub fn predecessor(&self) -> Option<&TaKey> {
+        self.predecessor.as_ref()
+    }
+
+    /// Returns the key the trust anchor is going to use next, if any.
+    pub fn successor(&self) -> Option<&TaKey> {
+        self.successor.as_ref()
+    }
+
+    /// Takes the content from the beginning of an encoded constructed value.
+    fn take_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        cons.take_sequence(|cons| {
+            // version INTEGER DEFAULT 0
+            if let Some(version) = cons.take_opt_u8()? {
+                if version != 0 {
+                    return Err(cons.content_err(
+                        "unsupported TAK version"
+                    ))
+                }
+            }
+            let current = TaKey::take_from(cons)?;
+            let predecessor = cons.take_opt_constructed_if(
+                Tag::CTX_0, TaKey::take_from
+            )?;
+            let successor = cons.take_opt_constructed_if(
+                Tag::CTX_1, TaKey::take_from
+            )?;
+            Ok(TrustAnchorKeys { current, predecessor, successor })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the content.
+    pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            self.current.encode_ref(),
+            self.predecessor.as_ref().map(|key| {
+                encode::sequence_as(Tag::CTX_0, key.encode_ref())
+            }),
+            self.successor.as_ref().map(|key| {
+                encode::sequence_as(Tag::CTX_1, key.encode_ref())
+            }),
+        ))
+    }
+
+    /// Verifies the content against the EE certificate and the TA.
+    fn verify(
+        &self, cert: &ResourceCert, issuer: &ResourceCert
+    ) -> Result<(), ValidationError> {
+        if self.current.key_info != *issuer.subject_public_key_info() {
+            return Err(VerificationError::new(
+                "current key does not match trust anchor certificate"
+            ).into())
+        }
+        let cert = cert.as_cert();
+        if !cert.v4_resources().is_inherited()
+            || !cert.v6_resources().is_inherited()
+            || !cert.as_resources().is_inherited()
+        {
+            return Err(VerificationError::new(
+                "EE certificate does not inherit all resources"
+            ).into())
+        }
+        Ok(())
+    }
+}
+
+
+//------------ TaKey ---------------------------------------------------------
+
+/// A trust anchor key as published in a TAK object.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TaKey {
+    /// Free-form comments for the key.
+    comments: Vec<String>,
+
+    /// The URIs of the trust anchor certificate for the key.
+    uris: Vec<TalUri>,
+
+    /// The public key.
+    key_info: PublicKey,
+}
+
+impl TaKey {
+    /// Creates a new key.
+    pub fn new(
+        comments: Vec<String>, uris: Vec<TalUri>, key_info: PublicKey
+    ) -> Self {
+        TaKey { comments, uris, key_info }
+    }
+
+    /// Returns the comments for the key.
+    ///
+    /// The comments are the same as the comment lines of a TAL.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Returns the URIs of the trust anchor certificate.
+    pub fn uris(&self) -> &[TalUri] {
+        &self.uris
+    }
+
+    /// Returns the public key.
+    pub fn key_info(&self) -> &PublicKey {
+        &self.key_info
+    }
+
+    /// Creates a TAL with the given name for this key.
+    pub fn to_tal(&self, name: String) -> Tal {
+        Tal::new(name, self.uris.clone(), self.key_info.clone())
+    }
+
+    /// Takes a key from the beginning of an encoded constructed value.
+    fn take_from<S: decode::Source>(
+        cons: &mut decode::Constructed<S>
+    ) -> Result<Self, DecodeError<S::Error>> {
+        cons.take_sequence(|cons| {
+            let comments = cons.take_sequence(|cons| {
+                let mut comments = Vec::new();
+                while let Some(comment) = Utf8String::take_opt_from(cons)? {
+                    comments.push(comment.to_string());
+                }
+                Ok(comments)
+            })?;
+            let uris = cons.take_sequence(|cons| {
+                let mut uris = Vec::new();
+                while let Some(uri) = Ia5String::take_opt_from(cons)? {
+                    match TalUri::from_bytes(uri.into_bytes()) {
+                        Ok(uri) => uris.push(uri),
+                        Err(_) => {
+                            return Err(cons.content_err(
+                                "invalid certificate URI"
+                            ))
+                        }
+                    }
+                }
+                if uris.is_empty() {
+                    return Err(cons.content_err(
+                        "missing certificate URIs"
+                    ))
+                }
+                Ok(uris)
+            })?;
+            let key_info = PublicKey::take_from(cons)?;
+            Ok(TaKey { comments, uris, key_info })
+        })
+    }
+
+    /// Returns a value encoder for a reference to the key.
+    pub fn encode_ref(&self) -> impl encode::Values + '_ {
+        encode::sequence((
+            encode::sequence(encode::iter(self.comments.iter().map(
+                |comment| {
+                    OctetString::encode_slice_as(
+                        comment.as_bytes(), Tag::UTF8_STRING
+                    )
+                }
+            ))),
+            encode::sequence(encode::iter(self.uris.iter().map(|uri| {
+                OctetString::encode_slice_as(
+                    uri.as_str().as_bytes(), Tag::IA5_STRING
+                )
+            }))),
+            self.key_info.encode_ref(),
+        ))
+    }
+}
+
+
+//------------ TakBuilder ----------------------------------------------------
+
+/// A builder for TAK objects.
+#[derive(Clone, Debug)]
+pub struct TakBuilder {
+    content: TrustAnchorKeys,
+}
+
+impl TakBuilder {
+    /// Creates a builder for the given current key.
+    pub fn new(current: TaKey) -> Self {
+        TakBuilder {
+            content: TrustAnchorKeys::new(current, None, None)
+        }
+    }
+
+    /// Sets the predecessor key.
+    pub fn set_predecessor(&mut self, key: Option<TaKey>) {
+        self.content.predecessor = key
+    }
+
+    /// Sets the successor key.
+    pub fn set_successor(&mut self, key: Option<TaKey>) {
+        self.content.successor = key
+    }
+
+    /// Finalizes the builder into a TAK object.
+    ///
+    /// The resources of the EE certificate are set to inherit.
+    pub fn finalize<S: Signer>(
+        self,
+        mut sigobj: SignedObjectBuilder,
+        signer: &S,
+        issuer_key: &S::KeyId,
+    ) -> Result<Tak, SigningError<S::Error>> {
+        sigobj.set_v4_resources_inherit();
+        sigobj.set_v6_resources_inherit();
+        sigobj.set_as_resources_inherit();
+        let signed = sigobj.finalize(
+            Oid(oid::CT_SIGNED_TAL.0.into()),
+            self.content.encode_ref().to_captured(Mode::Der).into_bytes(),
+            signer,
+            issuer_key,
+        )?;
+        Ok(Tak { signed, content: self.content })
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(uri: &str) -> TaKey {
+        let tal = include_bytes!("../../test-data/repository/ripe.tal");
+        let tal = Tal::read("ripe.tal", &mut tal.as_ref()).unwrap();
+        TaKey::new(
+            vec!["a comment".into()],
+            vec![TalUri::from_string(uri.into()).unwrap()],
+            tal.key_info().clone()
+        )
+    }
+
+    #[test]
+    fn encode_and_decode_content() {
+        let keys = TrustAnchorKeys::new(
+            key("rsync://example.com/ta/ta.cer"),
+            None,
+            Some(key("https://example.com/ta/new.cer")),
+        );
+        let encoded = keys.encode_ref().to_captured(Mode::Der);
+        let decoded = Mode::Der.decode(encoded.as_slice(), |cons| {
+            TrustAnchorKeys::take_from(cons)
+        }).unwrap();
+        assert_eq!(keys, decoded);
+        assert!(decoded.predecessor().is_none());
+        assert_eq!(
+            decoded.successor().unwrap().uris()[0].as_str(),
+            "https://example.com/ta/new.cer"
+        );
+        assert_eq!(decoded.current().comments(), ["a comment"]);
+
+        let tal = decoded.successor().unwrap().to_tal("new".into());
+        assert_eq!(tal.info().name(), "new");
+        assert_eq!(tal.key_info(), decoded.current().key_info());
+    }
+}
+
+#[cfg(all(test, feature = "softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::uri;
+    use crate::crypto::PublicKeyFormat;
+    use crate::crypto::softsigner::OpenSslSigner;
+    use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::tal::TalInfo;
+    use crate::repository::x509::Validity;
+    use super::*;
+
+    #[test]
+    fn encode_and_validate() {
+        let signer = OpenSslSigner::new();
+        let key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let pubkey = signer.get_key_info(&key).unwrap();
+        let new_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let new_pubkey = signer.get_key_info(&new_key).unwrap();
+        let uri = uri::Rsync::from_str("rsync://example.com/ta/").unwrap();
+
+        let mut cert = TbsCert::new(
+            12u64.into(), pubkey.to_subject_name(),
+            Validity::from_secs(86400), None, pubkey.clone(), KeyUsage::Ca,
+            Overclaim::Trim
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(uri.clone()));
+        cert.set_rpki_manifest(Some(uri.join(b"ta.mft").unwrap()));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(&signer, &key).unwrap().validate_ta(
+            TalInfo::from_name("test".into()).into_arc(), true
+        ).unwrap();
+
+        let ta_key = |key_info: PublicKey| TaKey::new(
+            Vec::new(),
+            vec![TalUri::from_string(
+                "rsync://example.com/tal/ta.cer".into()
+            ).unwrap()],
+            key_info
+        );
+        let sigobj = || SignedObjectBuilder::new(
+            1u64.into(), Validity::from_secs(86400),
+            uri.join(b"ta.crl").unwrap(),
+            uri::Rsync::from_str("rsync://example.com/tal/ta.cer").unwrap(),
+            uri.join(b"ta.tak").unwrap(),
+        );
+
+        let mut tak = TakBuilder::new(ta_key(pubkey.clone()));
+        tak.set_successor(Some(ta_key(new_pubkey.clone())));
+        let tak = tak.finalize(sigobj(), &signer, &key).unwrap();
+        let tak = Tak::decode(tak.to_captured().as_slice(), true).unwrap();
+        let (_, keys) = tak.process(&cert, true, |_| Ok(())).unwrap();
+        assert_eq!(keys.successor().unwrap().key_info(), &new_pubkey);
+
+        // The current key must be the key of the trust anchor.
+        let tak = TakBuilder::new(ta_key(new_pubkey)).finalize(
+            sigobj(), &signer, &key
+        ).unwrap();
+        assert!(tak.process(&cert, true, |_| Ok(())).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! Trust Anchor Locators
 
-use std::{fmt, str};
+use std::{error, fmt, str};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::{Infallible, TryFrom};
 use std::fs::{read_dir, DirEntry, File, ReadDir};
 use std::io::{self, Read};
@@ -12,8 +13,15 @@ use bcder::decode;
 use bcder::decode::IntoSource;
 use log::{debug, error};
 use crate::uri;
-use crate::crypto::PublicKey;
+#[cfg(feature = "rtr")] use crate::rtr::payload::RouteOrigin;
+#[cfg(feature = "rtr")] use crate::rtr::vrp::VrpSet;
+use crate::crypto::{KeyIdentifier, PublicKey};
 use crate::util::base64;
+use super::cert::Cert;
+use super::resources::ResourceSet;
+use super::tak::{TaKey, TrustAnchorKeys};
+#[cfg(feature = "rtr")] use super::roa::RouteOriginAttestation;
+use super::x509::{Serial, Time};
 
 
 //------------ Tal -----------------------------------------------------------
@@ -26,6 +34,15 @@ pub struct Tal {
 }
 
 impl Tal {
+    /// Creates a TAL from its components.
+    pub fn new(name: String, uris: Vec<TalUri>, key_info: PublicKey) -> Self {
+        Tal {
+            uris,
+            key_info,
+            info: Arc::new(TalInfo::from_name(name)),
+        }
+    }
+
     pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<TalIter, io::Error> {
         read_dir(path).map(TalIter)
     }
@@ -107,7 +124,7 @@ impl Tal {
 }
 
 impl Tal {
-    pub fn uris(&self) -> ::std::slice::Iter<TalUri> {
+    pub fn uris(&self) -> ::std::slice::Iter<'_, TalUri> {
         self.uris.iter()
     }
 
@@ -120,6 +137,50 @@ impl Tal {
     }
 }
 
+/// # Trust Anchor Certificates
+impl Tal {
+    /// Checks that a trust anchor certificate matches the TAL’s key.
+    ///
+    /// The certificate should have been retrieved from `uri` which is
+    /// one of the TAL’s URIs. If the certificate’s key matches the key
+    /// of the TAL, returns `None`. Otherwise returns an event describing
+    /// the mismatch. Validity of the certificate is not checked if the
+    /// keys match.
+    pub fn check_ta_key(
+        &self, uri: &TalUri, cert: &Cert, strict: bool
+    ) -> Option<TaKeyEvent> {
+        self.check_ta_key_at(uri, cert, strict, Time::now())
+    }
+
+    /// Checks the key of a trust anchor certificate at the given time.
+    ///
+    /// This is identical to [`Tal::check_ta_key`] with an explicitly
+    /// given value for the current time.
+    pub fn check_ta_key_at(
+        &self, uri: &TalUri, cert: &Cert, strict: bool, now: Time,
+    ) -> Option<TaKeyEvent> {
+        if cert.subject_public_key_info() == &self.key_info {
+            return None
+        }
+        let tal_key = self.key_info.key_identifier();
+        let cert_key = cert.subject_public_key_info().key_identifier();
+        let valid = cert.inspect_ta(strict).is_ok()
+            && cert.verify_ta_ref_at(strict, now).is_ok();
+        Some(if valid {
+            TaKeyEvent::Successor {
+                tal: self.info.clone(), uri: uri.clone(),
+                tal_key, new_key: cert_key,
+            }
+        }
+        else {
+            TaKeyEvent::KeyMismatch {
+                tal: self.info.clone(), uri: uri.clone(),
+                tal_key, cert_key,
+            }
+        })
+    }
+}
+
 
 //------------ TalIter -------------------------------------------------------
 
@@ -269,9 +330,735 @@ impl TalInfo {
 }
 
 
+//------------ TaKeyMonitor --------------------------------------------------
+
+/// Keeps track of the trust anchor certificates seen for a set of TALs.
+///
+/// A trust anchor may roll its key by publishing a new self-signed
+/// certificate at the URIs of its TAL. Relying parties with the old TAL
+/// will then fail to validate the trust anchor. The monitor notices such
+/// changes and reports them as [`TaKeyEvent`]s so the application can
+/// inform its operator rather than silently losing the trust anchor.
+///
+/// Each change is reported only once: a certificate re-issued with the
+/// TAL’s key is reported when its serial number changes and a certificate
+/// with a different key is reported the first time that key is seen.
+///
+/// In addition, the monitor keeps track of successor keys announced by the
+/// trust anchor through a TAK object as described in RFC 9691. These are
+/// fed to it via [`observe_tak`][Self::observe_tak]. RFC 9691 suggests
+/// switching to a successor key only after it has been announced
+/// continuously for [`ACCEPTANCE_DAYS`][Self::ACCEPTANCE_DAYS] which can
+/// be checked via [`is_successor_due_at`][Self::is_successor_due_at].
+#[derive(Clone, Debug, Default)]
+pub struct TaKeyMonitor {
+    /// The serial number of the last matching certificate per TAL name.
+    serials: HashMap<String, Serial>,
+
+    /// The keys of mismatching certificates reported per TAL name.
+    reported: HashMap<String, HashSet<KeyIdentifier>>,
+
+    /// The successor key announced per TAL name and when it was first seen.
+    successors: HashMap<String, (KeyIdentifier, Time)>,
+}
+
+impl TaKeyMonitor {
+    /// Creates a new, empty monitor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a trust anchor certificate retrieved for a TAL.
+    ///
+    /// Returns an event if something changed since the last time a
+    /// certificate was observed for the TAL.
+    pub fn observe(
+        &mut self, tal: &Tal, uri: &TalUri, cert: &Cert, strict: bool
+    ) -> Option<TaKeyEvent> {
+        self.observe_at(tal, uri, cert, strict, Time::now())
+    }
+
+    /// Processes a trust anchor certificate at the given time.
+    pub fn observe_at(
+        &mut self, tal: &Tal, uri: &TalUri, cert: &Cert, strict: bool,
+        now: Time,
+    ) -> Option<TaKeyEvent> {
+        let name = tal.info().name();
+        if let Some(event) = tal.check_ta_key_at(uri, cert, strict, now) {
+            let new = self.reported.entry(name.into()).or_default().insert(
+                cert.subject_public_key_info().key_identifier()
+            );
+            return if new { Some(event) } else { None }
+        }
+        let new_serial = cert.serial_number();
+        match self.serials.insert(name.into(), new_serial) {
+            Some(old_serial) if old_serial != new_serial => {
+                Some(TaKeyEvent::Reissued {
+                    tal: tal.info().clone(), uri: uri.clone(),
+                    old_serial, new_serial,
+                })
+            }
+            _ => None
+        }
+    }
+
+    /// The number of days a successor key needs to be announced.
+    pub const ACCEPTANCE_DAYS: i64 = 30;
+
+    /// Processes the validated content of a TAK object for a TAL.
+    ///
+    /// Returns an event if the TAK object announces a successor key that
+    /// hasn’t been announced before. If the object doesn’t announce a
+    /// successor, any previously announced successor is forgotten. A
+    /// successor without certificate URIs is treated as no successor.
+    pub fn observe_tak(
+        &mut self, tal: &Tal, keys: &TrustAnchorKeys
+    ) -> Option<TaKeyEvent> {
+        self.observe_tak_at(tal, keys, Time::now())
+    }
+
+    /// Processes the content of a TAK object at the given time.
+    pub fn observe_tak_at(
+        &mut self, tal: &Tal, keys: &TrustAnchorKeys, now: Time
+    ) -> Option<TaKeyEvent> {
+        let name = tal.info().name();
+        let successor = match keys.successor() {
+            Some(successor) if !successor.uris().is_empty() => successor,
+            _ => {
+                self.successors.remove(name);
+                return None
+            }
+        };
+        let new_key = successor.key_info().key_identifier();
+        if let Some((key, _)) = self.successors.get(name) {
+            if *key == new_key {
+                return None
+            }
+        }
+        self.successors.insert(name.into(), (new_key, now));
+        Some(TaKeyEvent::TakSuccessor {
+            tal: tal.info().clone(),
+            tal_key: tal.key_info().key_identifier(),
+            new_key,
+            uris: successor.uris().to_vec(),
+        })
+    }
+
+    /// Returns the successor key announced for a TAL.
+    ///
+    /// Returns the key identifier of the successor key and the time it
+    /// was first announced or `None` if no successor is announced.
+    pub fn successor(&self, name: &str) -> Option<(KeyIdentifier, Time)> {
+        self.successors.get(name).copied()
+    }
+
+    /// Returns whether a TAL should be switched to its successor key.
+    ///
+    /// This is the case if a successor has been announced for at least
+    /// [`ACCEPTANCE_DAYS`][Self::ACCEPTANCE_DAYS] at `now`.
+    pub fn is_successor_due_at(&self, name: &str, now: Time) -> bool {
+        match self.successors.get(name) {
+            Some((_, since)) => {
+                now.signed_duration_since(**since)
+                    >= chrono::Duration::days(Self::ACCEPTANCE_DAYS)
+            }
+            None => false
+        }
+    }
+
+    /// Removes all information about the TAL with the given name.
+    ///
+    /// This should be called when the TAL has been replaced, e.g., after
+    /// updating it to the key of a successor.
+    pub fn forget(&mut self, name: &str) {
+        self.serials.remove(name);
+        self.reported.remove(name);
+        self.successors.remove(name);
+    }
+}
+
+
+//------------ TaKeyEvent ----------------------------------------------------
+
+/// A change to the trust anchor certificate published for a TAL.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum TaKeyEvent {
+    /// The certificate was re-issued with the key of the TAL.
+    Reissued {
+        tal: Arc<TalInfo>,
+        uri: TalUri,
+        old_serial: Serial,
+        new_serial: Serial,
+    },
+
+    /// A valid trust anchor certificate with a different key appeared.
+    ///
+    /// This is what a key rollover of the trust anchor looks like. The
+    /// TAL needs to be updated to the new key.
+    Successor {
+        tal: Arc<TalInfo>,
+        uri: TalUri,
+        tal_key: KeyIdentifier,
+        new_key: KeyIdentifier,
+    },
+
+    /// A certificate with a different key that isn’t a valid trust anchor.
+    KeyMismatch {
+        tal: Arc<TalInfo>,
+        uri: TalUri,
+        tal_key: KeyIdentifier,
+        cert_key: KeyIdentifier,
+    },
+
+    /// A TAK object announced a successor key.
+    ///
+    /// The `uris` are those of the trust anchor certificate for the new
+    /// key.
+    TakSuccessor {
+        tal: Arc<TalInfo>,
+        tal_key: KeyIdentifier,
+        new_key: KeyIdentifier,
+        uris: Vec<TalUri>,
+    },
+}
+
+impl TaKeyEvent {
+    /// Returns the TAL the event happened for.
+    pub fn tal(&self) -> &Arc<TalInfo> {
+        match *self {
+            TaKeyEvent::Reissued { ref tal, .. } => tal,
+            TaKeyEvent::Successor { ref tal, .. } => tal,
+            TaKeyEvent::KeyMismatch { ref tal, .. } => tal,
+            TaKeyEvent::TakSuccessor { ref tal, .. } => tal,
+        }
+    }
+
+    /// Returns the URI the certificate was retrieved from.
+    ///
+    /// For a successor announced via a TAK object, this is the first URI
+    /// of the successor’s certificate.
+    pub fn uri(&self) -> &TalUri {
+        match *self {
+            TaKeyEvent::Reissued { ref uri, .. } => uri,
+            TaKeyEvent::Successor { ref uri, .. } => uri,
+            TaKeyEvent::KeyMismatch { ref uri, .. } => uri,
+            TaKeyEvent::TakSuccessor { ref uris, .. } => &uris[0],
+        }
+    }
+
+    /// Returns whether validation of the trust anchor will fail.
+    pub fn is_key_change(&self) -> bool {
+        matches!(
+            *self,
+            TaKeyEvent::Successor { .. } | TaKeyEvent::KeyMismatch { .. }
+        )
+    }
+}
+
+impl fmt::Display for TaKeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TaKeyEvent::Reissued {
+                ref tal, ref uri, ref old_serial, ref new_serial
+            } => {
+                write!(f,
+                    "{}: trust anchor certificate {} re-issued \
+                     (serial {} replaces {})",
+                    tal.name(), uri, new_serial, old_serial
+                )
+            }
+            TaKeyEvent::Successor {
+                ref tal, ref uri, ref tal_key, ref new_key
+            } => {
+                write!(f,
+                    "{}: trust anchor certificate {} has new key {} \
+                     replacing TAL key {}",
+                    tal.name(), uri, new_key, tal_key
+                )
+            }
+            TaKeyEvent::KeyMismatch {
+                ref tal, ref uri, ref tal_key, ref cert_key
+            } => {
+                write!(f,
+                    "{}: certificate {} has key {} instead of TAL key {} \
+                     and is not a valid trust anchor",
+                    tal.name(), uri, cert_key, tal_key
+                )
+            }
+            TaKeyEvent::TakSuccessor {
+                ref tal, ref tal_key, ref new_key, ..
+            } => {
+                write!(f,
+                    "{}: trust anchor announces successor key {} \
+                     for TAL key {}",
+                    tal.name(), new_key, tal_key
+                )
+            }
+        }
+    }
+}
+
+
+//------------ TalSet --------------------------------------------------------
+
+/// A set of trust anchors and their configuration.
+///
+/// The set is the starting point of validation: a relying party loads all
+/// its TALs into a set, adjusts the configuration of individual trust
+/// anchors, and then starts validation from each anchor returned by
+/// [`enabled`][Self::enabled]. Trust anchors are identified by the name of
+/// their TAL, i.e., the [`TalInfo`] all resource certificates derived from
+/// them carry. This allows finding the configuration of the trust anchor
+/// for any object encountered during validation via
+/// [`anchor_for`][Self::anchor_for].
+///
+/// The set keeps the trust anchors in the order they were added.
+#[derive(Clone, Debug, Default)]
+pub struct TalSet {
+    anchors: Vec<TrustAnchor>,
+}
+
+impl TalSet {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a set from all the TALs in a directory.
+    ///
+    /// All trust anchors receive the default configuration. The TALs are
+    /// added in the order of their file names. Returns an error if any
+    /// of the TALs cannot be read.
+    pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<Self, ReadError> {
+        let mut tals = Tal::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        tals.sort_by(|left, right| left.info.name().cmp(right.info.name()));
+        let mut res = Self::new();
+        for tal in tals {
+            res.insert(tal);
+        }
+        Ok(res)
+    }
+
+    /// Adds a TAL with the default configuration.
+    ///
+    /// If a trust anchor with the same name is already present, it is
+    /// replaced and returned.
+    pub fn insert(&mut self, tal: Tal) -> Option<TrustAnchor> {
+        self.insert_with_config(tal, TaConfig::default())
+    }
+
+    /// Adds a TAL with the given configuration.
+    ///
+    /// If a trust anchor with the same name is already present, it is
+    /// replaced and returned.
+    pub fn insert_with_config(
+        &mut self, tal: Tal, config: TaConfig
+    ) -> Option<TrustAnchor> {
+        let anchor = TrustAnchor { tal, config };
+        match self.position(anchor.name()) {
+            Some(pos) => {
+                Some(std::mem::replace(&mut self.anchors[pos], anchor))
+            }
+            None => {
+                self.anchors.push(anchor);
+                None
+            }
+        }
+    }
+
+    /// Removes the trust anchor with the given name.
+    pub fn remove(&mut self, name: &str) -> Option<TrustAnchor> {
+        self.position(name).map(|pos| self.anchors.remove(pos))
+    }
+
+    /// Returns the number of trust anchors in the set.
+    pub fn len(&self) -> usize {
+        self.anchors.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    /// Returns the trust anchor with the given name.
+    pub fn get(&self, name: &str) -> Option<&TrustAnchor> {
+        self.position(name).map(|pos| &self.anchors[pos])
+    }
+
+    /// Returns a mutable reference to the trust anchor with the given name.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut TrustAnchor> {
+        self.position(name).map(move |pos| &mut self.anchors[pos])
+    }
+
+    /// Returns the trust anchor an object derived from `tal` belongs to.
+    pub fn anchor_for(&self, tal: &TalInfo) -> Option<&TrustAnchor> {
+        self.get(tal.name())
+    }
+
+    /// Switches the trust anchor with the given name to a successor key.
+    ///
+    /// The TAL of the trust anchor is replaced with one created from
+    /// `successor` while its configuration is kept. Returns the previous
+    /// TAL or `None` if there is no trust anchor with this name.
+    pub fn roll_over(
+        &mut self, name: &str, successor: &TaKey
+    ) -> Option<Tal> {
+        let anchor = self.get_mut(name)?;
+        let tal = successor.to_tal(name.into());
+        Some(std::mem::replace(&mut anchor.tal, tal))
+    }
+
+    /// Enables or disables the trust anchor with the given name.
+    ///
+    /// Returns whether a trust anchor with this name exists.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.get_mut(name) {
+            Some(anchor) => {
+                anchor.config.set_enabled(enabled);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Returns an iterator over all trust anchors.
+    pub fn iter(&self) -> std::slice::Iter<'_, TrustAnchor> {
+        self.anchors.iter()
+    }
+
+    /// Returns an iterator over the enabled trust anchors.
+    pub fn enabled(&self) -> impl Iterator<Item = &TrustAnchor> + '_ {
+        self.anchors.iter().filter(|anchor| anchor.is_enabled())
+    }
+
+    /// Returns the route origins of a ROA with their provenance.
+    ///
+    /// The ROA must have been validated under the TAL described by `tal`,
+    /// i.e., the resource certificate its content was validated with
+    /// carries this TAL info. If the set does not contain an enabled
+    /// trust anchor for the TAL, nothing is returned. Otherwise, see
+    /// [`TrustAnchor::route_origins`].
+    #[cfg(feature = "rtr")]
+    pub fn route_origins<'a>(
+        &'a self,
+        tal: &TalInfo,
+        roa: &'a RouteOriginAttestation,
+    ) -> impl Iterator<Item = TaRouteOrigin> + 'a {
+        self.anchor_for(tal).filter(|anchor| anchor.is_enabled())
+            .into_iter().flat_map(move |anchor| anchor.route_origins(roa))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.anchors.iter().position(|anchor| anchor.name() == name)
+    }
+}
+
+impl<'a> IntoIterator for &'a TalSet {
+    type Item = &'a TrustAnchor;
+    type IntoIter = std::slice::Iter<'a, TrustAnchor>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+
+//------------ TrustAnchor ---------------------------------------------------
+
+/// A trust anchor in a [`TalSet`].
+///
+/// This is a TAL together with its configuration.
+#[derive(Clone, Debug)]
+pub struct TrustAnchor {
+    tal: Tal,
+    config: TaConfig,
+}
+
+impl TrustAnchor {
+    /// Returns the TAL of the trust anchor.
+    pub fn tal(&self) -> &Tal {
+        &self.tal
+    }
+
+    /// Returns the TAL info of the trust anchor.
+    pub fn info(&self) -> &Arc<TalInfo> {
+        &self.tal.info
+    }
+
+    /// Returns the name of the trust anchor.
+    pub fn name(&self) -> &str {
+        self.tal.info.name()
+    }
+
+    /// Returns the configuration of the trust anchor.
+    pub fn config(&self) -> &TaConfig {
+        &self.config
+    }
+
+    /// Returns a mutable reference to the configuration.
+    pub fn config_mut(&mut self) -> &mut TaConfig {
+        &mut self.config
+    }
+
+    /// Returns whether the trust anchor is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Returns the URIs to fetch the trust anchor certificate from.
+    ///
+    /// The URIs are filtered and ordered according to the configured
+    /// transport.
+    pub fn uris(&self) -> Vec<&TalUri> {
+        let mut res: Vec<_> = self.tal.uris().filter(|uri| {
+            match self.config.transport {
+                TaTransport::Any | TaTransport::PreferHttps => true,
+                TaTransport::HttpsOnly => uri.is_https(),
+                TaTransport::RsyncOnly => uri.is_rsync(),
+            }
+        }).collect();
+        if self.config.transport == TaTransport::PreferHttps {
+            res.sort_by_key(|uri| !uri.is_https());
+        }
+        res
+    }
+
+    /// Returns whether the trust anchor may assert a route origin.
+    ///
+    /// If resources are configured for the trust anchor, the prefix of
+    /// the origin must be covered by them.
+    #[cfg(feature = "rtr")]
+    pub fn permits_origin(&self, origin: &RouteOrigin) -> bool {
+        self.config.permits_prefix(origin.prefix.prefix())
+    }
+
+    /// Returns the route origins of a ROA with their provenance.
+    ///
+    /// The ROA must have been validated under this trust anchor. Origins
+    /// not permitted by the trust anchor’s configuration are skipped.
+    /// Each origin is attached the TAL info of the trust anchor. If the
+    /// trust anchor is an AS0 trust anchor, the origins are tagged with
+    /// its AS0 policy or, if the policy is [`As0Policy::Discard`], not
+    /// returned at all.
+    #[cfg(feature = "rtr")]
+    pub fn route_origins<'a>(
+        &'a self, roa: &'a RouteOriginAttestation,
+    ) -> impl Iterator<Item = TaRouteOrigin> + 'a {
+        let discard = self.config.as0 == Some(As0Policy::Discard);
+        roa.iter_origins().filter(move |origin| {
+            !discard && self.permits_origin(origin)
+        }).map(move |origin| {
+            TaRouteOrigin {
+                origin,
+                tal: self.tal.info.clone(),
+                as0: self.config.as0,
+            }
+        })
+    }
+}
+
+
+//------------ TaConfig ------------------------------------------------------
+
+/// The configuration of a trust anchor.
+///
+/// By default, a trust anchor is enabled, fetched via HTTPS first, is
+/// not restricted in the resources it may assert, and is not treated as
+/// an AS0 trust anchor.
+#[derive(Clone, Debug)]
+pub struct TaConfig {
+    /// Is the trust anchor enabled?
+    enabled: bool,
+
+    /// Which transport to fetch the trust anchor certificate with.
+    transport: TaTransport,
+
+    /// The resources the trust anchor may assert.
+    ///
+    /// If this is `None`, the trust anchor isn’t restricted.
+    resources: Option<ResourceSet>,
+
+    /// The policy for an AS0 trust anchor.
+    ///
+    /// If this is `None`, the trust anchor is a regular trust anchor.
+    as0: Option<As0Policy>,
+}
+
+impl Default for TaConfig {
+    fn default() -> Self {
+        TaConfig {
+            enabled: true,
+            transport: TaTransport::PreferHttps,
+            resources: None,
+            as0: None,
+        }
+    }
+}
+
+impl TaConfig {
+    /// Returns whether the trust anchor is enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables the trust anchor.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled
+    }
+
+    /// Returns the transport to fetch the trust anchor certificate with.
+    pub fn transport(&self) -> TaTransport {
+        self.transport
+    }
+
+    /// Sets the transport to fetch the trust anchor certificate with.
+    pub fn set_transport(&mut self, transport: TaTransport) {
+        self.transport = transport
+    }
+
+    /// Returns the resources the trust anchor may assert.
+    pub fn resources(&self) -> Option<&ResourceSet> {
+        self.resources.as_ref()
+    }
+
+    /// Restricts the resources the trust anchor may assert.
+    ///
+    /// Payload for resources outside of the set will be dropped. Only the
+    /// IP resources of the set are currently considered since ROAs do not
+    /// require holding the AS number. Setting `None` removes the
+    /// restriction.
+    pub fn set_resources(&mut self, resources: Option<ResourceSet>) {
+        self.resources = resources
+    }
+
+    /// Returns the policy if this is an AS0 trust anchor.
+    pub fn as0_policy(&self) -> Option<As0Policy> {
+        self.as0
+    }
+
+    /// Sets the policy for an AS0 trust anchor.
+    ///
+    /// Setting a policy marks the trust anchor as one that publishes AS0
+    /// ROAs for unallocated address space, such as the AS0 TALs of APNIC
+    /// and LACNIC. Setting `None` treats it as a regular trust anchor.
+    pub fn set_as0_policy(&mut self, policy: Option<As0Policy>) {
+        self.as0 = policy
+    }
+
+    /// Returns whether the configuration permits the given prefix.
+    #[cfg(feature = "rtr")]
+    fn permits_prefix(&self, prefix: crate::resources::addr::Prefix) -> bool {
+        let resources = match self.resources.as_ref() {
+            Some(resources) => resources,
+            None => return true,
+        };
+        if prefix.is_v4() {
+            resources.ipv4().contains_block(prefix)
+        }
+        else {
+            resources.ipv6().contains_block(prefix)
+        }
+    }
+}
+
+
+//------------ TaTransport ---------------------------------------------------
+
+/// The transport to fetch a trust anchor certificate with.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TaTransport {
+    /// Use the URIs in the order given in the TAL.
+    Any,
+
+    /// Use HTTPS URIs before rsync URIs.
+    PreferHttps,
+
+    /// Only use HTTPS URIs.
+    HttpsOnly,
+
+    /// Only use rsync URIs.
+    RsyncOnly,
+}
+
+
+//------------ As0Policy -----------------------------------------------------
+
+/// The policy for route origins of an AS0 trust anchor.
+///
+/// Some RIRs operate special trust anchors that only publish ROAs for AS0
+/// covering address space they have not allocated. Since using them can
+/// make routes invalid that are otherwise unknown, relying parties may
+/// want to treat their payload differently from that of the regular trust
+/// anchors.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum As0Policy {
+    /// The route origins are served to routers like any other.
+    Serve,
+
+    /// The route origins are reported but not served to routers.
+    #[default]
+    Report,
+
+    /// The route origins are dropped entirely.
+    Discard,
+}
+
+
+//------------ TaRouteOrigin -------------------------------------------------
+
+/// A route origin together with the trust anchor it was derived from.
+#[cfg(feature = "rtr")]
+#[derive(Clone, Debug)]
+pub struct TaRouteOrigin {
+    /// The route origin.
+    pub origin: RouteOrigin,
+
+    /// The TAL of the trust anchor the origin was derived from.
+    pub tal: Arc<TalInfo>,
+
+    /// The AS0 policy if the trust anchor is an AS0 trust anchor.
+    pub as0: Option<As0Policy>,
+}
+
+#[cfg(feature = "rtr")]
+impl TaRouteOrigin {
+    /// Returns whether the origin was derived from an AS0 trust anchor.
+    pub fn is_as0(&self) -> bool {
+        self.as0.is_some()
+    }
+
+    /// Returns whether the origin should be served to routers.
+    pub fn is_served(&self) -> bool {
+        !matches!(self.as0, Some(As0Policy::Report | As0Policy::Discard))
+    }
+}
+
+/// Adds the origins using the name of their TAL as the source.
+///
+/// Note that this adds all origins regardless of their AS0 policy.
+#[cfg(feature = "rtr")]
+impl Extend<TaRouteOrigin> for VrpSet {
+    fn extend<I: IntoIterator<Item = TaRouteOrigin>>(&mut self, iter: I) {
+        for origin in iter {
+            self.insert_from(origin.origin, origin.tal.name());
+        }
+    }
+}
+
+#[cfg(feature = "rtr")]
+impl From<TaRouteOrigin> for RouteOrigin {
+    fn from(origin: TaRouteOrigin) -> Self {
+        origin.origin
+    }
+}
+
+
 //------------ ReadError -----------------------------------------------------
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ReadError {
     Io(io::Error),
     UnexpectedEof,
@@ -320,6 +1107,18 @@ impl fmt::Display for ReadError {
     }
 }
 
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ReadError::Io(ref err) => Some(err),
+            ReadError::UnexpectedEof => None,
+            ReadError::BadUri(ref err) => Some(err),
+            ReadError::BadKeyInfoEncoding(ref err) => Some(err),
+            ReadError::BadKeyInfo(ref err) => Some(err),
+        }
+    }
+}
+
 
 //============ Testing =======================================================
 
@@ -370,4 +1169,303 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn check_ta_key() {
+        let tal = include_bytes!("../../test-data/repository/ripe.tal");
+        let tal = Tal::read("ripe.tal", &mut tal.as_ref()).unwrap();
+        let uri = tal.uris().next().unwrap().clone();
+        let ta = Cert::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ta.cer")
+        )).unwrap();
+        let ca = Cert::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ca1.cer")
+        )).unwrap();
+        let now = Time::utc(2019, 5, 1, 0, 0, 0);
+
+        assert!(tal.check_ta_key_at(&uri, &ta, true, now).is_none());
+        assert!(matches!(
+            tal.check_ta_key_at(&uri, &ca, true, now),
+            Some(TaKeyEvent::KeyMismatch { cert_key, .. })
+                if cert_key == ca.subject_key_identifier()
+        ));
+
+        let mut monitor = TaKeyMonitor::new();
+        assert!(monitor.observe_at(&tal, &uri, &ta, true, now).is_none());
+        assert!(monitor.observe_at(&tal, &uri, &ta, true, now).is_none());
+        let event = monitor.observe_at(&tal, &uri, &ca, true, now).unwrap();
+        assert!(event.is_key_change());
+        assert_eq!(event.tal().name(), "ripe");
+        assert_eq!(event.uri(), &uri);
+        assert!(monitor.observe_at(&tal, &uri, &ca, true, now).is_none());
+        monitor.forget("ripe");
+        assert!(monitor.observe_at(&tal, &uri, &ca, true, now).is_some());
+    }
+
+    fn named_tal(name: &str) -> Tal {
+        let tal = include_bytes!("../../test-data/repository/ripe.tal");
+        let mut tal = Tal::read_named(
+            name.into(), &mut tal.as_ref()
+        ).unwrap();
+        tal.uris = vec![
+            TalUri::from_slice(b"rsync://a.example.com/1/ta.cer").unwrap(),
+            TalUri::from_slice(b"https://b.example.com/1/ta.cer").unwrap(),
+        ];
+        tal
+    }
+
+    #[test]
+    fn tal_set() {
+        let mut set = TalSet::new();
+        assert!(set.insert(named_tal("ripe")).is_none());
+        assert!(set.insert(named_tal("apnic")).is_none());
+        assert!(set.insert(named_tal("ripe")).is_some());
+        assert_eq!(set.len(), 2);
+        assert_eq!(
+            set.iter().map(|ta| ta.name()).collect::<Vec<_>>(),
+            ["ripe", "apnic"]
+        );
+
+        assert!(set.set_enabled("apnic", false));
+        assert!(!set.set_enabled("arin", false));
+        assert_eq!(
+            set.enabled().map(|ta| ta.name()).collect::<Vec<_>>(),
+            ["ripe"]
+        );
+
+        let ripe = set.get_mut("ripe").unwrap();
+        assert_eq!(
+            ripe.uris().iter().map(|uri| uri.as_str()).collect::<Vec<_>>(),
+            [
+                "https://b.example.com/1/ta.cer",
+                "rsync://a.example.com/1/ta.cer"
+            ]
+        );
+        ripe.config_mut().set_transport(TaTransport::RsyncOnly);
+        assert_eq!(
+            ripe.uris().iter().map(|uri| uri.as_str()).collect::<Vec<_>>(),
+            ["rsync://a.example.com/1/ta.cer"]
+        );
+
+        assert!(set.remove("apnic").is_some());
+        assert!(set.get("apnic").is_none());
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn tak_successor() {
+        use crate::repository::tak::{TaKey, TrustAnchorKeys};
+
+        let tal = named_tal("ripe");
+        let ca = Cert::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ca1.cer")
+        )).unwrap();
+        let current = TaKey::new(
+            Vec::new(), tal.uris.clone(), tal.key_info().clone()
+        );
+        let successor = TaKey::new(
+            Vec::new(),
+            vec![
+                TalUri::from_slice(b"rsync://a.example.com/2/ta.cer").unwrap()
+            ],
+            ca.subject_public_key_info().clone()
+        );
+        let with_successor = TrustAnchorKeys::new(
+            current.clone(), None, Some(successor.clone())
+        );
+        let without = TrustAnchorKeys::new(current, None, None);
+
+        let mut monitor = TaKeyMonitor::new();
+        let start = Time::now();
+        assert!(monitor.observe_tak_at(&tal, &without, start).is_none());
+        let event = monitor.observe_tak_at(
+            &tal, &with_successor, start
+        ).unwrap();
+        assert!(!event.is_key_change());
+        assert_eq!(event.uri().as_str(), "rsync://a.example.com/2/ta.cer");
+        assert!(
+            monitor.observe_tak_at(&tal, &with_successor, start).is_none()
+        );
+        assert_eq!(
+            monitor.successor("ripe"),
+            Some((ca.subject_key_identifier(), start))
+        );
+        assert!(!monitor.is_successor_due_at(
+            "ripe", start + chrono::Duration::days(29)
+        ));
+        assert!(monitor.is_successor_due_at(
+            "ripe", start + chrono::Duration::days(30)
+        ));
+        assert!(monitor.observe_tak_at(&tal, &without, start).is_none());
+        assert!(monitor.successor("ripe").is_none());
+
+        let mut set = TalSet::new();
+        set.insert(tal);
+        set.set_enabled("ripe", false);
+        assert!(set.roll_over("apnic", &successor).is_none());
+        let old = set.roll_over("ripe", &successor).unwrap();
+        assert_ne!(old.key_info(), successor.key_info());
+        let ripe = set.get("ripe").unwrap();
+        assert_eq!(ripe.tal().key_info(), successor.key_info());
+        assert!(!ripe.is_enabled());
+    }
+
+    #[test]
+    #[cfg(feature = "rtr")]
+    fn tal_set_route_origins() {
+        use crate::repository::roa::Roa;
+
+        let roa = Roa::decode(
+            include_bytes!(
+                "../../test-data/repository/example-ripe.roa"
+            ).as_ref(),
+            false
+        ).unwrap();
+        let roa = roa.content();
+
+        let mut set = TalSet::new();
+        let mut config = TaConfig::default();
+        config.set_resources(Some(
+            ResourceSet::from_strs("", "", "2a0c:b642::/32").unwrap()
+        ));
+        set.insert_with_config(named_tal("ripe"), config.clone());
+        config.set_resources(Some(
+            ResourceSet::from_strs("", "", "2001:db8::/32").unwrap()
+        ));
+        set.insert_with_config(named_tal("apnic"), config);
+        set.insert(named_tal("arin"));
+
+        let ripe = set.get("ripe").unwrap().info().clone();
+        let origins: Vec<_> = set.route_origins(&ripe, roa).collect();
+        assert_eq!(origins.len(), 1);
+        assert_eq!(
+            origins[0].origin,
+            roa.iter_origins().next().unwrap()
+        );
+        assert_eq!(origins[0].tal.name(), "ripe");
+
+        let apnic = set.get("apnic").unwrap().info().clone();
+        assert_eq!(set.route_origins(&apnic, roa).count(), 0);
+
+        let arin = set.get("arin").unwrap().info().clone();
+        assert_eq!(set.route_origins(&arin, roa).count(), 1);
+
+        let mut vrps = VrpSet::new();
+        vrps.extend(set.route_origins(&ripe, roa));
+        vrps.extend(set.route_origins(&arin, roa));
+        assert_eq!(vrps.len(), 1);
+        assert_eq!(
+            vrps.sources(&origins[0].origin), ["ripe", "arin"]
+        );
+        set.set_enabled("arin", false);
+        assert_eq!(set.route_origins(&arin, roa).count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "rtr")]
+    fn as0_policy() {
+        use crate::repository::roa::Roa;
+
+        let roa = Roa::decode(
+            include_bytes!(
+                "../../test-data/repository/example-ripe.roa"
+            ).as_ref(),
+            false
+        ).unwrap();
+        let roa = roa.content();
+
+        let mut set = TalSet::new();
+        set.insert(named_tal("ripe"));
+        let info = set.get("ripe").unwrap().info().clone();
+        let origin = set.route_origins(&info, roa).next().unwrap();
+        assert!(!origin.is_as0());
+        assert!(origin.is_served());
+
+        set.get_mut("ripe").unwrap().config_mut().set_as0_policy(
+            Some(As0Policy::Serve)
+        );
+        let origin = set.route_origins(&info, roa).next().unwrap();
+        assert!(origin.is_as0());
+        assert!(origin.is_served());
+
+        set.get_mut("ripe").unwrap().config_mut().set_as0_policy(
+            Some(As0Policy::Report)
+        );
+        let origin = set.route_origins(&info, roa).next().unwrap();
+        assert!(origin.is_as0());
+        assert!(!origin.is_served());
+
+        set.get_mut("ripe").unwrap().config_mut().set_as0_policy(
+            Some(As0Policy::Discard)
+        );
+        assert!(set.route_origins(&info, roa).next().is_none());
+    }
+}
+
+#[cfg(all(test, feature="softkeys"))]
+mod signer_test {
+    use std::str::FromStr;
+    use crate::crypto::{PublicKeyFormat, Signer};
+    use crate::crypto::softsigner::{KeyId, OpenSslSigner};
+    use crate::repository::cert::{KeyUsage, Overclaim, TbsCert};
+    use crate::repository::resources::{Asn, Prefix};
+    use crate::repository::x509::Validity;
+    use super::*;
+
+    fn make_ta(
+        signer: &OpenSslSigner, key: &KeyId, serial: u64
+    ) -> Cert {
+        let pubkey = signer.get_key_info(key).unwrap();
+        let uri = uri::Rsync::from_str("rsync://example.com/m/p").unwrap();
+        let mut cert = TbsCert::new(
+            serial.into(), pubkey.to_subject_name(),
+            Validity::from_secs(86400), None, pubkey, KeyUsage::Ca,
+            Overclaim::Trim
+        );
+        cert.set_basic_ca(Some(true));
+        cert.set_ca_repository(Some(uri.clone()));
+        cert.set_rpki_manifest(Some(uri));
+        cert.build_v4_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_v6_resource_blocks(|b| b.push(Prefix::new(0, 0)));
+        cert.build_as_resource_blocks(|b| b.push((Asn::MIN, Asn::MAX)));
+        let cert = cert.into_cert(signer, key).unwrap().to_captured();
+        Cert::decode(cert.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn key_rollover() {
+        let signer = OpenSslSigner::new();
+        let old_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let new_key = signer.create_key(PublicKeyFormat::Rsa).unwrap();
+        let uri = TalUri::from_slice(b"https://example.com/ta.cer").unwrap();
+        let tal = Tal {
+            uris: vec![uri.clone()],
+            key_info: signer.get_key_info(&old_key).unwrap(),
+            info: TalInfo::from_name("test".into()).into_arc(),
+        };
+
+        let mut monitor = TaKeyMonitor::new();
+        let old = make_ta(&signer, &old_key, 1);
+        assert!(monitor.observe(&tal, &uri, &old, true).is_none());
+
+        let reissued = make_ta(&signer, &old_key, 2);
+        let event = monitor.observe(&tal, &uri, &reissued, true).unwrap();
+        assert!(!event.is_key_change());
+        assert!(matches!(
+            event,
+            TaKeyEvent::Reissued { old_serial, new_serial, .. }
+                if old_serial == old.serial_number()
+                    && new_serial == reissued.serial_number()
+        ));
+
+        let successor = make_ta(&signer, &new_key, 3);
+        let event = monitor.observe(&tal, &uri, &successor, true).unwrap();
+        assert!(matches!(
+            event,
+            TaKeyEvent::Successor { new_key, .. }
+                if new_key == successor.subject_key_identifier()
+        ));
+        assert!(monitor.observe(&tal, &uri, &successor, true).is_none());
+    }
+
 }
@@ -0,0 +1,361 @@
+//! Reporting upcoming expiry of validated objects.
+//!
+//! Objects in the RPKI have a limited life time: certificates expire at
+//! the end of their validity and manifests and CRLs become stale when their
+//! next update time passes. If an object isn’t replaced in time, it and
+//! everything depending on it will fail to validate.
+//!
+//! The [`ExpiryTimeline`] collects the validated objects of a repository
+//! together with the time they expire and the certificate that issued
+//! them. It can then produce a list of [`Expiry`] items sorted by time,
+//! each stating how many objects are affected when the object expires.
+
+use std::{error, fmt};
+use std::collections::HashMap;
+use crate::uri;
+use super::cert::Cert;
+use super::crl::Crl;
+use super::manifest::Manifest;
+use super::x509::Time;
+
+
+//------------ ExpiryTimeline ------------------------------------------------
+
+/// The expiry times of a tree of validated objects.
+///
+/// Objects are identified by their rsync URI. Each object except for trust
+/// anchor certificates refers to the CA certificate that issued it which
+/// has to be added before the object itself.
+#[derive(Clone, Debug, Default)]
+pub struct ExpiryTimeline {
+    /// All the objects in the order they were added.
+    objects: Vec<TimelineObject>,
+
+    /// The index of the objects for their URIs.
+    by_uri: HashMap<uri::Rsync, usize>,
+}
+
+/// A single object of the timeline.
+#[derive(Clone, Debug)]
+struct TimelineObject {
+    kind: ObjectKind,
+    uri: uri::Rsync,
+    expires: Time,
+    issuer: Option<usize>,
+}
+
+impl ExpiryTimeline {
+    /// Creates a new, empty timeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of objects in the timeline.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the timeline is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Adds an object to the timeline.
+    ///
+    /// The object is identified by `uri` and expires at `expires`. If
+    /// `issuer` is given, it is the URI of the CA certificate that issued
+    /// the object. This certificate must already be in the timeline.
+    ///
+    /// If an object with the same URI is already present, its kind and
+    /// expiry time are updated but it stays with its original issuer.
+    pub fn add(
+        &mut self,
+        kind: ObjectKind,
+        uri: uri::Rsync,
+        expires: Time,
+        issuer: Option<&uri::Rsync>,
+    ) -> Result<(), UnknownIssuer> {
+        let issuer = match issuer {
+            Some(issuer) => match self.by_uri.get(issuer) {
+                Some(&idx) => Some(idx),
+                None => return Err(UnknownIssuer(issuer.clone())),
+            },
+            None => None,
+        };
+        if let Some(&idx) = self.by_uri.get(&uri) {
+            let object = &mut self.objects[idx];
+            object.kind = kind;
+            object.expires = expires;
+            return Ok(())
+        }
+        self.by_uri.insert(uri.clone(), self.objects.len());
+        self.objects.push(TimelineObject { kind, uri, expires, issuer });
+        Ok(())
+    }
+
+    /// Adds a certificate.
+    ///
+    /// The certificate expires at the end of its validity.
+    pub fn add_cert(
+        &mut self, uri: uri::Rsync, cert: &Cert, issuer: Option<&uri::Rsync>,
+    ) -> Result<(), UnknownIssuer> {
+        self.add(
+            ObjectKind::Certificate, uri, cert.validity().not_after(), issuer
+        )
+    }
+
+    /// Adds a CRL.
+    ///
+    /// The CRL expires at its next update time.
+    pub fn add_crl(
+        &mut self, uri: uri::Rsync, crl: &Crl, issuer: &uri::Rsync,
+    ) -> Result<(), UnknownIssuer> {
+        self.add(ObjectKind::Crl, uri, crl.next_update(), Some(issuer))
+    }
+
+    /// Adds a manifest.
+    ///
+    /// The manifest expires at its next update time or at the end of the
+    /// validity of its EE certificate, whichever comes first.
+    pub fn add_manifest(
+        &mut self, uri: uri::Rsync, manifest: &Manifest, issuer: &uri::Rsync,
+    ) -> Result<(), UnknownIssuer> {
+        let expires = manifest.next_update().min(
+            manifest.cert().validity().not_after()
+        );
+        self.add(ObjectKind::Manifest, uri, expires, Some(issuer))
+    }
+
+    /// Adds a signed object other than a manifest.
+    ///
+    /// The object expires at the end of the validity of its EE certificate
+    /// `ee`.
+    pub fn add_signed_object(
+        &mut self,
+        kind: ObjectKind,
+        uri: uri::Rsync,
+        ee: &Cert,
+        issuer: &uri::Rsync,
+    ) -> Result<(), UnknownIssuer> {
+        self.add(kind, uri, ee.validity().not_after(), Some(issuer))
+    }
+
+    /// Returns all objects sorted by their expiry time.
+    ///
+    /// Objects expiring at the same time are sorted by their URI.
+    pub fn expiries(&self) -> Vec<Expiry<'_>> {
+        let sizes = self.subtree_sizes();
+        let mut res: Vec<_> = self.objects.iter().enumerate().map(|
+            (idx, object)
+        | {
+            let affected = match (object.kind, object.issuer) {
+                (ObjectKind::Manifest, Some(issuer))
+                | (ObjectKind::Crl, Some(issuer)) => sizes[issuer] - 1,
+                _ => sizes[idx],
+            };
+            Expiry {
+                kind: object.kind,
+                uri: &object.uri,
+                expires: object.expires,
+                affected,
+            }
+        }).collect();
+        res.sort_by(|left, right| {
+            left.expires.cmp(&right.expires).then_with(|| {
+                left.uri.as_str().cmp(right.uri.as_str())
+            })
+        });
+        res
+    }
+
+    /// Returns the objects expiring before the given time.
+    ///
+    /// The objects are sorted by their expiry time.
+    pub fn expiring_before(&self, until: Time) -> Vec<Expiry<'_>> {
+        let mut res = self.expiries();
+        res.retain(|item| item.expires < until);
+        res
+    }
+
+    /// Returns the size of the subtree rooted at each object.
+    ///
+    /// The size includes the object itself. Because an issuer must be
+    /// present before the objects it issued, walking the objects backwards
+    /// visits all children before their parent.
+    fn subtree_sizes(&self) -> Vec<usize> {
+        let mut sizes = vec![1; self.objects.len()];
+        for idx in (0..self.objects.len()).rev() {
+            if let Some(issuer) = self.objects[idx].issuer {
+                sizes[issuer] += sizes[idx];
+            }
+        }
+        sizes
+    }
+}
+
+
+//------------ Expiry --------------------------------------------------------
+
+/// An upcoming expiry of an object.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Expiry<'a> {
+    kind: ObjectKind,
+    uri: &'a uri::Rsync,
+    expires: Time,
+    affected: usize,
+}
+
+impl<'a> Expiry<'a> {
+    /// Returns the kind of the expiring object.
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    /// Returns the URI of the expiring object.
+    pub fn uri(&self) -> &'a uri::Rsync {
+        self.uri
+    }
+
+    /// Returns the time the object expires.
+    pub fn expires(&self) -> Time {
+        self.expires
+    }
+
+    /// Returns the number of objects affected by the expiry.
+    ///
+    /// For a certificate, this is the certificate itself and all the
+    /// objects issued under it, directly or indirectly. For a manifest or
+    /// CRL, it is all the objects issued by its CA since they will all
+    /// fail to validate once the manifest or CRL becomes stale. For all
+    /// other objects, it is one.
+    pub fn affected(&self) -> usize {
+        self.affected
+    }
+}
+
+
+//------------ ObjectKind ----------------------------------------------------
+
+/// The kind of an object in the timeline.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ObjectKind {
+    Certificate,
+    Crl,
+    Manifest,
+    Roa,
+    Aspa,
+    Other,
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ObjectKind::Certificate => "certificate",
+            ObjectKind::Crl => "CRL",
+            ObjectKind::Manifest => "manifest",
+            ObjectKind::Roa => "ROA",
+            ObjectKind::Aspa => "ASPA",
+            ObjectKind::Other => "object",
+        })
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ UnknownIssuer -------------------------------------------------
+
+/// The issuer of an object is not part of the timeline.
+#[derive(Clone, Debug)]
+pub struct UnknownIssuer(uri::Rsync);
+
+impl UnknownIssuer {
+    /// Returns the URI of the missing issuer.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.0
+    }
+}
+
+impl fmt::Display for UnknownIssuer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown issuer {}", self.0)
+    }
+}
+
+impl error::Error for UnknownIssuer { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::Bytes;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn expiries() {
+        let ta = Cert::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ta.cer")
+        )).unwrap();
+        let ca = Cert::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ca1.cer")
+        )).unwrap();
+        let crl = Crl::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ta.crl")
+        )).unwrap();
+        let mft = Manifest::decode(Bytes::from_static(
+            include_bytes!("../../test-data/repository/ta.mft")
+        ), false).unwrap();
+
+        let ta_uri = uri("rsync://example.com/ta/ta.cer");
+        let ca_uri = uri("rsync://example.com/ta/ca1.cer");
+        let mut timeline = ExpiryTimeline::new();
+        assert!(timeline.is_empty());
+        timeline.add_cert(ta_uri.clone(), &ta, None).unwrap();
+        timeline.add_crl(
+            uri("rsync://example.com/ta/ta.crl"), &crl, &ta_uri
+        ).unwrap();
+        timeline.add_manifest(
+            uri("rsync://example.com/ta/ta.mft"), &mft, &ta_uri
+        ).unwrap();
+        timeline.add_cert(ca_uri.clone(), &ca, Some(&ta_uri)).unwrap();
+        timeline.add(
+            ObjectKind::Roa, uri("rsync://example.com/ca1/a.roa"),
+            Time::utc(2019, 6, 1, 0, 0, 0), Some(&ca_uri)
+        ).unwrap();
+        assert!(
+            timeline.add(
+                ObjectKind::Roa, uri("rsync://example.com/ca2/a.roa"),
+                Time::utc(2019, 6, 1, 0, 0, 0),
+                Some(&uri("rsync://example.com/ta/ca2.cer"))
+            ).is_err()
+        );
+        assert_eq!(timeline.len(), 5);
+
+        let expiries = timeline.expiries();
+        let kinds: Vec<_> = expiries.iter().map(|item| {
+            (item.kind(), item.affected())
+        }).collect();
+        assert_eq!(
+            kinds,
+            [
+                (ObjectKind::Crl, 4),
+                (ObjectKind::Manifest, 4),
+                (ObjectKind::Roa, 1),
+                (ObjectKind::Certificate, 2),
+                (ObjectKind::Certificate, 5),
+            ]
+        );
+        assert!(expiries.windows(2).all(|w| w[0].expires <= w[1].expires));
+
+        let soon = timeline.expiring_before(Time::utc(2019, 5, 27, 0, 0, 0));
+        assert_eq!(soon.len(), 2);
+        assert_eq!(soon[0].uri().as_str(), "rsync://example.com/ta/ta.crl");
+    }
+}
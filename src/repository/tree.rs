@@ -0,0 +1,376 @@
+//! Exporting the validated CA hierarchy.
+//!
+//! A [`DelegationTree`] is a snapshot of the tree of CAs encountered
+//! during a validation run: starting with the trust anchor certificates,
+//! each node describes a CA certificate with its resources, its
+//! publication point, and the number of ROAs and ASPAs it issued, followed
+//! by its child CAs.
+//!
+//! The tree is created from the [`CoverageIndex`] of a validation run,
+//! most conveniently via [`ValidationReport::delegation_tree`][report].
+//! It can be serialized via serde if the `"serde"` feature is enabled and
+//! rendered for visualization in the DOT format of Graphviz via
+//! [`DelegationTree::write_dot`] or as GraphML via
+//! [`DelegationTree::write_graphml`].
+//!
+//! The module is only available if both the `"repository"` and `"rtr"`
+//! features are enabled since it is based on the validation report.
+//!
+//! [report]: super::report::ValidationReport::delegation_tree
+
+#![cfg(feature = "rtr")]
+
+use std::io;
+use std::collections::HashMap;
+use crate::uri;
+use crate::crypto::KeyIdentifier;
+use super::covering::{CoverageIndex, CoveringCert};
+
+
+//------------ DelegationTree ------------------------------------------------
+
+/// The tree of validated CAs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DelegationTree {
+    /// The trust anchor certificates, ordered by URI.
+    pub roots: Vec<CaNode>,
+}
+
+impl DelegationTree {
+    /// The maximum depth of the tree.
+    ///
+    /// CAs further down are left out.
+    const MAX_DEPTH: usize = 64;
+
+    /// Creates the tree for the CAs of a coverage index.
+    ///
+    /// Certificates whose issuer isn’t in the index become roots.
+    pub fn from_coverage(index: &CoverageIndex) -> Self {
+        let mut children = HashMap::<_, Vec<_>>::new();
+        let mut roots = Vec::new();
+        for cert in index.certs() {
+            match cert.parent().filter(|key| index.cert(key).is_some()) {
+                Some(parent) => {
+                    children.entry(parent).or_default().push(cert)
+                }
+                None => roots.push(cert)
+            }
+        }
+        let mut roas = HashMap::<_, usize>::new();
+        for roa in index.roas() {
+            if let Some(issuer) = index.cert(roa.issuer()) {
+                *roas.entry(issuer.uri()).or_default() += 1;
+            }
+        }
+        let mut aspas = HashMap::<_, usize>::new();
+        for aspa in index.aspas() {
+            if let Some(issuer) = index.cert(aspa.issuer()) {
+                *aspas.entry(issuer.uri()).or_default() += 1;
+            }
+        }
+        let builder = TreeBuilder { children, roas, aspas };
+        roots.sort_by(|left, right| left.uri().cmp(right.uri()));
+        DelegationTree {
+            roots: roots.into_iter().map(|cert| {
+                builder.node(cert, 0)
+            }).collect()
+        }
+    }
+
+    /// Returns the total number of CAs in the tree.
+    pub fn len(&self) -> usize {
+        let mut res = 0;
+        self.walk(|_, _, _| {
+            res += 1;
+            Ok(())
+        }).expect("infallible");
+        res
+    }
+
+    /// Returns whether the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Writes the tree in Graphviz’s DOT format.
+    ///
+    /// Each CA becomes a node labelled with its URI and resources with
+    /// edges pointing from parents to their children.
+    pub fn write_dot<W: io::Write>(&self, mut target: W) -> io::Result<()> {
+        writeln!(target, "digraph delegation {{")?;
+        writeln!(target, "    node [shape=box];")?;
+        self.walk(|id, parent, node| {
+            let mut label = dot_escape(node.uri.as_str());
+            for item in [&node.ipv4, &node.ipv6, &node.asns] {
+                if !item.is_empty() {
+                    label.push_str("\\n");
+                    label.push_str(&dot_escape(item));
+                }
+            }
+            writeln!(target, "    n{} [label=\"{}\"];", id, label)?;
+            if let Some(parent) = parent {
+                writeln!(target, "    n{} -> n{};", parent, id)?;
+            }
+            Ok(())
+        })?;
+        writeln!(target, "}}")
+    }
+
+    /// Writes the tree as a GraphML document.
+    ///
+    /// All information of a node is provided as GraphML data elements.
+    pub fn write_graphml<W: io::Write>(
+        &self, mut target: W
+    ) -> io::Result<()> {
+        writeln!(target, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            target,
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+        )?;
+        for (key, attr_type) in GRAPHML_KEYS {
+            writeln!(
+                target,
+                "  <key id=\"{0}\" for=\"node\" attr.name=\"{0}\" \
+                 attr.type=\"{1}\"/>",
+                key, attr_type
+            )?;
+        }
+        writeln!(
+            target, "  <graph id=\"delegation\" edgedefault=\"directed\">"
+        )?;
+        self.walk(|id, parent, node| {
+            writeln!(target, "    <node id=\"n{}\">", id)?;
+            let values = [
+                Some(node.uri.as_str()),
+                node.ca_repository.as_ref().map(uri::Rsync::as_str),
+                node.rpki_manifest.as_ref().map(uri::Rsync::as_str),
+                node.rpki_notify.as_ref().map(uri::Https::as_str),
+                Some(node.ipv4.as_str()),
+                Some(node.ipv6.as_str()),
+                Some(node.asns.as_str()),
+            ];
+            for ((key, _), value) in GRAPHML_KEYS.iter().zip(values) {
+                if let Some(value) = value.filter(|value| !value.is_empty()) {
+                    writeln!(
+                        target, "      <data key=\"{}\">{}</data>",
+                        key, xml_escape(value)
+                    )?;
+                }
+            }
+            writeln!(
+                target, "      <data key=\"roas\">{}</data>", node.roas
+            )?;
+            writeln!(
+                target, "      <data key=\"aspas\">{}</data>", node.aspas
+            )?;
+            writeln!(target, "    </node>")?;
+            if let Some(parent) = parent {
+                writeln!(
+                    target, "    <edge source=\"n{}\" target=\"n{}\"/>",
+                    parent, id
+                )?;
+            }
+            Ok(())
+        })?;
+        writeln!(target, "  </graph>")?;
+        writeln!(target, "</graphml>")
+    }
+
+    /// Calls `op` for all nodes in pre-order.
+    ///
+    /// The closure receives a running number for the node, the number of
+    /// its parent, and the node itself.
+    fn walk(
+        &self,
+        mut op: impl FnMut(usize, Option<usize>, &CaNode) -> io::Result<()>
+    ) -> io::Result<()> {
+        let mut next = 0;
+        let mut stack: Vec<_> = self.roots.iter().rev().map(|node| {
+            (None, node)
+        }).collect();
+        while let Some((parent, node)) = stack.pop() {
+            let id = next;
+            next += 1;
+            op(id, parent, node)?;
+            stack.extend(node.children.iter().rev().map(|child| {
+                (Some(id), child)
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// The GraphML keys for node data and their types.
+const GRAPHML_KEYS: [(&str, &str); 9] = [
+    ("uri", "string"),
+    ("caRepository", "string"),
+    ("rpkiManifest", "string"),
+    ("rpkiNotify", "string"),
+    ("ipv4", "string"),
+    ("ipv6", "string"),
+    ("asns", "string"),
+    ("roas", "long"),
+    ("aspas", "long"),
+];
+
+
+//------------ CaNode --------------------------------------------------------
+
+/// A CA in a delegation tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaNode {
+    /// The URI of the CA certificate.
+    pub uri: uri::Rsync,
+
+    /// The URI of the CA’s publication point.
+    pub ca_repository: Option<uri::Rsync>,
+
+    /// The URI of the CA’s manifest.
+    pub rpki_manifest: Option<uri::Rsync>,
+
+    /// The URI of the RRDP notification file of the CA’s repository.
+    pub rpki_notify: Option<uri::Https>,
+
+    /// The IPv4 resources of the CA.
+    pub ipv4: String,
+
+    /// The IPv6 resources of the CA.
+    pub ipv6: String,
+
+    /// The AS resources of the CA.
+    pub asns: String,
+
+    /// The number of validated ROAs issued by the CA.
+    pub roas: usize,
+
+    /// The number of validated ASPAs issued by the CA.
+    pub aspas: usize,
+
+    /// The child CAs, ordered by URI.
+    pub children: Vec<CaNode>,
+}
+
+
+//------------ TreeBuilder ---------------------------------------------------
+
+/// The information necessary for creating the nodes of a tree.
+struct TreeBuilder<'a> {
+    /// The child certificates of each CA.
+    children: HashMap<&'a KeyIdentifier, Vec<&'a CoveringCert>>,
+
+    /// The number of ROAs of each CA.
+    roas: HashMap<&'a uri::Rsync, usize>,
+
+    /// The number of ASPAs of each CA.
+    aspas: HashMap<&'a uri::Rsync, usize>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    fn node(&self, cert: &CoveringCert, depth: usize) -> CaNode {
+        let mut children = Vec::new();
+        if depth < DelegationTree::MAX_DEPTH {
+            if let Some(certs) = self.children.get(cert.key()) {
+                children = certs.iter().map(|child| {
+                    self.node(child, depth + 1)
+                }).collect();
+                children.sort_by(|left: &CaNode, right| {
+                    left.uri.cmp(&right.uri)
+                });
+            }
+        }
+        CaNode {
+            uri: cert.uri().clone(),
+            ca_repository: cert.ca_repository().cloned(),
+            rpki_manifest: cert.rpki_manifest().cloned(),
+            rpki_notify: cert.rpki_notify().cloned(),
+            ipv4: cert.v4_resources().as_v4().to_string(),
+            ipv6: cert.v6_resources().as_v6().to_string(),
+            asns: cert.as_resources().to_string(),
+            roas: self.roas.get(cert.uri()).copied().unwrap_or(0),
+            aspas: self.aspas.get(cert.uri()).copied().unwrap_or(0),
+            children,
+        }
+    }
+}
+
+
+//------------ Helpers -------------------------------------------------------
+
+/// Escapes a string for use in a quoted DOT string.
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a string for use as XML character data.
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn node(uri: &str, children: Vec<CaNode>) -> CaNode {
+        CaNode {
+            uri: uri::Rsync::from_str(uri).unwrap(),
+            ca_repository: None,
+            rpki_manifest: None,
+            rpki_notify: None,
+            ipv4: "10.0.0.0/8".into(),
+            ipv6: String::new(),
+            asns: "AS64496".into(),
+            roas: 1,
+            aspas: 0,
+            children,
+        }
+    }
+
+    fn tree() -> DelegationTree {
+        DelegationTree {
+            roots: vec![
+                node("rsync://a.example/ta/ta.cer", vec![
+                    node("rsync://a.example/ta/a&b.cer", vec![]),
+                    node("rsync://a.example/ta/c.cer", vec![]),
+                ]),
+            ]
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let tree = tree();
+        assert_eq!(tree.len(), 3);
+        let mut out = Vec::new();
+        tree.write_dot(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("digraph delegation {\n"));
+        assert!(out.contains(
+            "    n1 [label=\"rsync://a.example/ta/a&b.cer\\n10.0.0.0/8\
+             \\nAS64496\"];\n"
+        ));
+        assert!(out.contains("    n0 -> n1;\n"));
+        assert!(out.contains("    n0 -> n2;\n"));
+        assert!(out.ends_with("}\n"));
+        assert_eq!(dot_escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn graphml() {
+        let mut out = Vec::new();
+        tree().write_graphml(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "      <data key=\"uri\">rsync://a.example/ta/a&amp;b.cer</data>"
+        ));
+        assert!(out.contains("<edge source=\"n0\" target=\"n2\"/>"));
+        assert!(!out.contains("key=\"ipv6\">"));
+        assert!(out.ends_with("</graphml>\n"));
+    }
+}
@@ -5,20 +5,24 @@ use std::cmp::{min, max};
 use std::str::FromStr;
 use std::time::SystemTime;
 use bcder::{decode, encode};
+use bytes::Bytes;
 use bcder::{
     BitString, Captured, ConstOid, Mode, OctetString, Oid, Tag,
     Unsigned,
 };
-use bcder::decode::{DecodeError, ContentError, IntoSource, Source};
+use bcder::decode::{
+    CaptureSource, DecodeError, ContentError, IntoSource, LimitedSource,
+    Source
+};
 use bcder::encode::PrimitiveContent;
-use bcder::string::{PrintableString, Utf8String};
 use chrono::{
     Datelike, DateTime, Duration, LocalResult, Timelike, TimeZone, Utc
 };
 use crate::oid;
+use crate::clock::Clock;
 use crate::crypto::{
-    PublicKey, RpkiSignatureAlgorithm, Signature, SignatureAlgorithm, Signer,
-    SignatureVerificationError,
+    KeyIdentifier, PublicKey, RpkiSignatureAlgorithm, Signature,
+    SignatureAlgorithm, Signer, SignatureVerificationError,
 };
 use crate::util::base64;
 use super::error::{InspectionError, VerificationError};
@@ -92,128 +96,137 @@ impl Name {
     }
 
     /// Validate the name to conform with resource certificates.
+    ///
+    /// In strict mode, the name must be composed of exactly one common
+    /// name and an optional serial number attribute, both encoded as a
+    /// PrintableString, as required by RFC 6487.
     pub fn inspect_rpki(&self, strict: bool) -> Result<(), InspectionError> {
-        fn inspect_strict<S: decode::Source>(
-            cons: &mut decode::Constructed<S>
-        ) -> Result<(), DecodeError<S::Error>> {
-            let mut cn = false;
-            let mut sn = false;
-            cons.take_sequence(|cons| {
-                while let Some(()) = cons.take_opt_set(|cons| {
-                    while let Some(()) = cons.take_opt_sequence(|cons| {
-                        let id = Oid::take_from(cons)?;
-                        if id == oid::AT_COMMON_NAME {
-                            if cn {
-                                return Err(cons.content_err(
-                                    "multiple common names"
-                                ))
-                            }
-                            let _ = PrintableString::take_from(cons)?;
-                            cn = true;
-                        }
-                        else if id == oid::AT_SERIAL_NUMBER {
-                            if sn {
-                                return Err(cons.content_err(
-                                    "multiple serial numbers"
-                                ))
-                            }
-                            let _ = PrintableString::take_from(cons)?;
-                            sn = true;
-                        }
-                        Ok(())
-                    })? { }
-                    Ok(())
-                })? {}
-                Ok(())
-            })?;
-            // Common name is MUST.
-            if cn {
-                Ok(())
-            }
-            else {
-                Err(cons.content_err("missing common name"))
-            }
-        }
-
         if strict {
-            self.0.clone().decode(
-                inspect_strict
-            ).map_err(InspectionError::new)?
+            self.rpki_attributes(false).map_err(InspectionError::new)?;
         }
         Ok(())
     }
 
     /// Validate the name to conform with BGPSec router certificates.
+    ///
+    /// This is the same as [`inspect_rpki`][Self::inspect_rpki] except
+    /// that RFC 8209 also allows UTF8String encoded attributes.
     pub fn inspect_router(
         &self, strict: bool
     ) -> Result<(), InspectionError> {
-        fn inspect_strict<S: decode::Source>(
-            cons: &mut decode::Constructed<S>
-        ) -> Result<(), DecodeError<S::Error>> {
-            let mut cn = false;
-            let mut sn = false;
-            cons.take_sequence(|cons| {
-                while let Some(()) = cons.take_opt_set(|cons| {
-                    while let Some(()) = cons.take_opt_sequence(|cons| {
-                        let id = Oid::take_from(cons)?;
-                        if id == oid::AT_COMMON_NAME {
-                            if cn {
-                                return Err(cons.content_err(
-                                    "multiple common names"
-                                ))
-                            }
-                            Name::skip_router_string(cons)?;
-                            cn = true;
-                        }
-                        else if id == oid::AT_SERIAL_NUMBER {
-                            if sn {
-                                return Err(cons.content_err(
-                                    "multiple serial numbers"
-                                ))
-                            }
-                            Name::skip_router_string(cons)?;
-                            sn = true;
-                        }
-                        Ok(())
-                    })? { }
-                    Ok(())
-                })? {}
-                Ok(())
-            })?;
-            // Common name is MUST.
-            if cn {
-                Ok(())
-            }
-            else {
-                Err(cons.content_err("missing common name"))
-            }
-        }
-
         if strict {
-            self.0.clone().decode(
-                inspect_strict
-            ).map_err(InspectionError::new)?
+            self.rpki_attributes(true).map_err(InspectionError::new)?;
         }
         Ok(())
     }
 
-    /// Skips a value if it is a PrintableString or UTF8String.
-    fn skip_router_string<S: decode::Source>(
-        cons: &mut decode::Constructed<S>
-    ) -> Result<(), DecodeError<S::Error>> {
-        cons.take_value(|tag, content| {
-            if tag == Tag::PRINTABLE_STRING {
-                PrintableString::from_content(content).map(|_| ())
+    /// Checks that the name follows the restrictions of RFC 6487.
+    ///
+    /// This is the same check as performed by
+    /// [`inspect_rpki`][Self::inspect_rpki] in strict mode but returns
+    /// a more precise error.
+    pub fn check_rpki(&self) -> Result<(), NameError> {
+        self.rpki_attributes(false).map(|_| ())
+    }
+
+    /// Returns the common name if the name follows RFC 6487.
+    pub fn common_name(&self) -> Option<String> {
+        self.rpki_attributes(true).ok().map(|(cn, _)| cn)
+    }
+
+    /// Returns the serial number if present and the name follows RFC 6487.
+    pub fn serial_number(&self) -> Option<String> {
+        self.rpki_attributes(true).ok().and_then(|(_, sn)| sn)
+    }
+
+    /// Returns whether two names match.
+    ///
+    /// If both names follow RFC 6487, their attributes are compared using
+    /// the rules of RFC 5280, section 7.1: case is ignored, leading and
+    /// trailing white space is ignored and internal white space is
+    /// collapsed. Otherwise, the encoded names are compared.
+    ///
+    /// Note that the `PartialEq` implementation always compares the
+    /// encoded names.
+    pub fn matches(&self, other: &Name) -> bool {
+        if self == other {
+            return true
+        }
+        match (self.rpki_attributes(true), other.rpki_attributes(true)) {
+            (Ok((cn, sn)), Ok((other_cn, other_sn))) => {
+                fold_attribute(&cn) == fold_attribute(&other_cn)
+                && sn.as_deref().map(fold_attribute)
+                    == other_sn.as_deref().map(fold_attribute)
             }
-            else if tag == Tag::UTF8_STRING {
-                Utf8String::from_content(content).map(|_| ())
+            _ => false
+        }
+    }
+
+    /// Returns the common name and serial number.
+    ///
+    /// If `utf8` is `true`, the attributes may be UTF8Strings in addition
+    /// to PrintableStrings.
+    fn rpki_attributes(
+        &self, utf8: bool
+    ) -> Result<(String, Option<String>), NameError> {
+        let mut cn = None;
+        let mut sn = None;
+        for (id, tag, value) in self.attributes()? {
+            let target = if id == oid::AT_COMMON_NAME {
+                &mut cn
+            }
+            else if id == oid::AT_SERIAL_NUMBER {
+                &mut sn
             }
             else {
-                Err(content.content_err(
-                    "unpermitted string variant in common name"
-                ))
+                return Err(NameError::DisallowedAttribute(id))
+            };
+            if target.is_some() {
+                return Err(NameError::DuplicateAttribute(id))
             }
-        })
+            let value = if tag == Tag::PRINTABLE_STRING {
+                if !value.iter().copied().all(is_printable) {
+                    return Err(NameError::InvalidString(id))
+                }
+                String::from_utf8(value.to_vec()).ok()
+            }
+            else if utf8 && tag == Tag::UTF8_STRING {
+                String::from_utf8(value.to_vec()).ok()
+            }
+            else {
+                None
+            };
+            match value {
+                Some(value) => *target = Some(value),
+                None => return Err(NameError::InvalidString(id))
+            }
+        }
+        match cn {
+            Some(cn) => Ok((cn, sn)),
+            None => Err(NameError::MissingCommonName)
+        }
+    }
+
+    /// Returns all attributes of the name as OID, tag, and raw value.
+    fn attributes(&self) -> Result<Vec<(Oid<Bytes>, Tag, Bytes)>, NameError> {
+        self.0.clone().decode(|cons| {
+            let mut res = Vec::new();
+            cons.take_sequence(|cons| {
+                while let Some(()) = cons.take_opt_set(|cons| {
+                    while let Some(()) = cons.take_opt_sequence(|cons| {
+                        let id = Oid::take_from(cons)?;
+                        let (tag, value) = cons.take_value(|tag, content| {
+                            Ok((tag, content.as_primitive()?.take_all()?))
+                        })?;
+                        res.push((id, tag, value));
+                        Ok(())
+                    })? { }
+                    Ok(())
+                })? { }
+                Ok(())
+            })?;
+            Ok(res)
+        }).map_err(|_| NameError::Malformed)
     }
 
     /// Derives a name from a public key info.
@@ -233,7 +246,16 @@ impl Name {
     /// the recommendations in RFC6487 sections 4.4, 4.5
     /// and 8.
     pub fn from_pub_key(key_info: &PublicKey) -> Self {
-        let enc = key_info.key_identifier().into_hex();
+        Self::from_key_identifier(key_info.key_identifier())
+    }
+
+    /// Creates a name from a key identifier.
+    ///
+    /// The name consists of a single common name containing the key
+    /// identifier in hex encoding. This is the name derived by
+    /// [`from_pub_key`][Self::from_pub_key].
+    pub fn from_key_identifier(key_id: KeyIdentifier) -> Self {
+        let enc = key_id.into_hex();
         let values = encode::sequence(
             encode::set(
                 encode::sequence((
@@ -245,6 +267,48 @@ impl Name {
         Name(Captured::from_values(Mode::Der, values))
     }
 
+    /// Creates a name from a common name and optional serial number.
+    ///
+    /// This fails if either value contains characters not allowed in a
+    /// PrintableString.
+    pub fn from_common_name(
+        common_name: &str, serial_number: Option<&str>,
+    ) -> Result<Self, NameError> {
+        if !common_name.bytes().all(is_printable) {
+            return Err(NameError::InvalidString(Oid(
+                Bytes::from_static(oid::AT_COMMON_NAME.0)
+            )))
+        }
+        if let Some(sn) = serial_number {
+            if !sn.bytes().all(is_printable) {
+                return Err(NameError::InvalidString(Oid(
+                    Bytes::from_static(oid::AT_SERIAL_NUMBER.0)
+                )))
+            }
+        }
+        let values = encode::sequence((
+            encode::set(
+                encode::sequence((
+                    oid::AT_COMMON_NAME.encode(),
+                    OctetString::encode_slice_as(
+                        common_name.as_bytes(), Tag::PRINTABLE_STRING
+                    ),
+                ))
+            ),
+            serial_number.map(|sn| {
+                encode::set(
+                    encode::sequence((
+                        oid::AT_SERIAL_NUMBER.encode(),
+                        OctetString::encode_slice_as(
+                            sn.as_bytes(), Tag::PRINTABLE_STRING
+                        ),
+                    ))
+                )
+            })
+        ));
+        Ok(Name(Captured::from_values(Mode::Der, values)))
+    }
+
     pub fn encode_ref(&self) -> impl encode::Values + '_ {
         &self.0
     }
@@ -260,6 +324,19 @@ impl PartialEq for Name {
 
 impl Eq for Name {}
 
+//--- Helper functions
+
+/// Returns whether a character is allowed in a PrintableString.
+fn is_printable(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || b" '()+,-./:=?".contains(&ch)
+}
+
+/// Folds an attribute value for comparison according to RFC 5280.
+fn fold_attribute(value: &str) -> String {
+    value.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>()
+        .join(" ")
+}
+
 //--- Deserialize and Serialize
 
 #[cfg(feature = "serde")]
@@ -292,6 +369,9 @@ impl<'de> serde::Deserialize<'de> for Name {
 //------------ Serial --------------------------------------------------------
 
 /// A certificate serial number.
+///
+/// The type is also used for manifest and CRL numbers which share the
+/// restriction to at most 20 octets. Values compare as unsigned integers.
 //
 //  We encode the serial number in 20 octets left padded.
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -299,6 +379,12 @@ impl<'de> serde::Deserialize<'de> for Name {
 pub struct Serial([u8; 20]);
 
 impl Serial {
+    /// The largest serial number that can be encoded in 20 octets.
+    pub const MAX: Serial = Serial([
+        0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ]);
+
     /// Creates a serial number from an octet slice.
     pub fn from_slice(s: &[u8]) -> Result<Self, SerialSliceError> {
         // Empty slice is malformed.
@@ -341,6 +427,44 @@ impl Serial {
         Ok(Self(res))
     }
 
+    /// Adds `rhs` to the serial number.
+    ///
+    /// Returns an error if the result would exceed [`Serial::MAX`].
+    pub fn checked_add(
+        mut self, rhs: u64
+    ) -> Result<Self, SerialOverflowError> {
+        let mut carry = u128::from(rhs);
+        for i in (0..20_usize).rev() {
+            if carry == 0 {
+                break
+            }
+            let step = u128::from(self.0[i]) + (carry & 0xFF);
+            self.0[i] = step as u8;
+            carry = (carry >> 8) + (step >> 8);
+        }
+        if carry == 0 && self.0[0] & 0x80 == 0 {
+            Ok(self)
+        }
+        else {
+            Err(SerialOverflowError)
+        }
+    }
+
+    /// Returns the next serial number.
+    ///
+    /// Returns an error if the serial number already is [`Serial::MAX`].
+    pub fn next(self) -> Result<Self, SerialOverflowError> {
+        self.checked_add(1)
+    }
+
+    /// Returns whether at most `margin` more numbers are left.
+    ///
+    /// This can be used to detect that manifest or CRL numbers are
+    /// approaching the limit and a rollover should be planned.
+    pub fn is_near_max(self, margin: u64) -> bool {
+        self.checked_add(margin).map(|res| res == Self::MAX).unwrap_or(true)
+    }
+
     pub fn take_from<S: decode::Source>(
         cons: &mut decode::Constructed<S>
     ) -> Result<Self, DecodeError<S::Error>> {
@@ -559,6 +683,85 @@ impl<'de> serde::Deserialize<'de> for Serial {
 }
 
 
+//------------ NumberSequence ------------------------------------------------
+
+/// A sequence of manifest or CRL numbers.
+///
+/// RFC 9286 and RFC 5280 require that manifest and CRL numbers increase
+/// with each new manifest or CRL issued by a CA and limit them to 20
+/// octets. This type hands out increasing numbers and reports an error
+/// rather than wrapping around when the limit is reached.
+///
+/// # Rollover
+///
+/// Once the numbers get close to the limit, an operator can start over
+/// with a small number via [`rollover`][Self::rollover]. Relying parties
+/// following section 4.2.1 of RFC 9286 keep track of the highest manifest
+/// number seen for a CA and will treat a manifest with a lower number as
+/// a replay, keeping their cached objects until its next update time has
+/// passed. A rollover should therefore go along with a key rollover of the
+/// CA, since relying parties track numbers per CA certificate and the new
+/// certificate starts a new sequence.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NumberSequence {
+    /// The next number to hand out.
+    ///
+    /// This is `None` if the sequence is exhausted.
+    next: Option<Serial>,
+}
+
+impl NumberSequence {
+    /// Creates a new sequence starting at one.
+    pub fn new() -> Self {
+        NumberSequence { next: Some(Serial::from(1u64)) }
+    }
+
+    /// Creates a sequence continuing after the given number.
+    pub fn after(last: Serial) -> Self {
+        NumberSequence { next: last.next().ok() }
+    }
+
+    /// Returns the number that will be handed out next.
+    ///
+    /// Returns `None` if the sequence is exhausted.
+    pub fn peek(&self) -> Option<Serial> {
+        self.next
+    }
+
+    /// Returns the next number.
+    ///
+    /// Returns an error if the sequence is exhausted, i.e., the number
+    /// would exceed the 20 octet limit.
+    pub fn next_number(&mut self) -> Result<Serial, SerialOverflowError> {
+        let res = self.next.ok_or(SerialOverflowError)?;
+        self.next = res.next().ok();
+        Ok(res)
+    }
+
+    /// Returns whether the sequence has at most `margin` numbers left.
+    pub fn is_near_max(&self, margin: u64) -> bool {
+        match self.next {
+            Some(next) => next.is_near_max(margin),
+            None => true,
+        }
+    }
+
+    /// Starts over so that the next number will be `start`.
+    ///
+    /// See the discussion in the [type documentation][Self] for how
+    /// relying parties will react to this.
+    pub fn rollover(&mut self, start: Serial) {
+        self.next = Some(start)
+    }
+}
+
+impl Default for NumberSequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
 //------------ SignedData ----------------------------------------------------
 
 #[derive(Clone, Debug)]
@@ -656,6 +859,79 @@ impl<'a, Alg> PrimitiveContent for SignatureValueContent<'a, Alg> {
 }
 
 
+//------------ Encoded -------------------------------------------------------
+
+/// The original encoding of an object.
+///
+/// Objects such as certificates or signed objects keep the octets they
+/// were decoded from in a value of this type, so that encoding them again
+/// reproduces exactly these octets and hashes over them stay the same.
+///
+/// The value can be encoded in any mode since its octets are written as is.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Encoded(Bytes);
+
+impl Encoded {
+    /// Takes a value from a constructed value and keeps its encoding.
+    ///
+    /// Runs `op` on `cons` and returns both its result and the encoded
+    /// octets it advanced over.
+    pub fn take_from<S, F, T>(
+        cons: &mut decode::Constructed<S>, op: F
+    ) -> Result<(T, Self), DecodeError<S::Error>>
+    where
+        S: decode::Source,
+        F: FnOnce(
+            &mut decode::Constructed<CaptureSource<LimitedSource<S>>>
+        ) -> Result<T, DecodeError<S::Error>>
+    {
+        let mut res = None;
+        let captured = cons.capture(|cons| {
+            res = Some(op(cons)?);
+            Ok(())
+        })?;
+        match res {
+            Some(res) => Ok((res, Encoded(captured.into_bytes()))),
+            None => unreachable!("capture succeeded without a value")
+        }
+    }
+
+    /// Creates a value by encoding values in DER mode.
+    pub fn from_values<V: encode::Values>(values: V) -> Self {
+        Encoded(Captured::from_values(Mode::Der, values).into_bytes())
+    }
+
+    /// Returns a bytes slice of the encoded octets.
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    /// Returns a bytes value of the encoded octets.
+    pub fn to_bytes(&self) -> Bytes {
+        self.0.clone()
+    }
+
+    /// Returns a DER captured value of the encoded octets.
+    pub fn to_captured(&self) -> Captured {
+        Captured::from_values(Mode::Der, self)
+    }
+}
+
+impl encode::Values for Encoded {
+    fn encoded_len(&self, _: Mode) -> usize {
+        self.0.len()
+    }
+
+    fn write_encoded<W: io::Write>(
+        &self,
+        _: Mode,
+        target: &mut W
+    ) -> Result<(), io::Error> {
+        target.write_all(self.0.as_ref())
+    }
+}
+
+
 //------------ Time ----------------------------------------------------------
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -672,6 +948,11 @@ impl Time {
         Self::new(Utc::now())
     }
 
+    /// Returns the current time according to the given clock.
+    pub fn from_clock<C: Clock + ?Sized>(clock: &C) -> Self {
+        Self::new(clock.now())
+    }
+
     pub fn five_minutes_ago() -> Self {
         Self::now() - Duration::minutes(5)
     }
@@ -1172,6 +1453,21 @@ impl fmt::Display for SerialSliceError {
 impl error::Error for SerialSliceError { }
 
 
+//------------ SerialOverflowError -------------------------------------------
+
+/// A serial number would exceed the 20 octet limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SerialOverflowError;
+
+impl fmt::Display for SerialOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("serial number exceeds 20 octets")
+    }
+}
+
+impl error::Error for SerialOverflowError { }
+
+
 //------------ RepresentationError -------------------------------------------
 
 /// A source value is not correctly formated for converting into a value.
@@ -1187,6 +1483,57 @@ impl fmt::Display for RepresentationError {
 impl error::Error for RepresentationError { }
 
 
+//------------ NameError -----------------------------------------------------
+
+/// A name does not follow the restrictions of RFC 6487.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum NameError {
+    /// The name could not be decoded.
+    Malformed,
+
+    /// The name contains an attribute other than CN and serialNumber.
+    DisallowedAttribute(Oid<Bytes>),
+
+    /// The name contains an attribute more than once.
+    DuplicateAttribute(Oid<Bytes>),
+
+    /// The name does not contain a common name.
+    MissingCommonName,
+
+    /// An attribute value is not a valid string.
+    InvalidString(Oid<Bytes>),
+}
+
+impl From<NameError> for ContentError {
+    fn from(err: NameError) -> Self {
+        ContentError::from_boxed(Box::new(err))
+    }
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NameError::Malformed => f.write_str("malformed name"),
+            NameError::DisallowedAttribute(ref id) => {
+                write!(f, "disallowed attribute {} in name", id)
+            }
+            NameError::DuplicateAttribute(ref id) => {
+                write!(f, "multiple attributes {} in name", id)
+            }
+            NameError::MissingCommonName => {
+                f.write_str("missing common name")
+            }
+            NameError::InvalidString(ref id) => {
+                write!(f, "invalid string for attribute {} in name", id)
+            }
+        }
+    }
+}
+
+impl error::Error for NameError { }
+
+
 //------------ ValidityPeriodError -------------------------------------------
 
 /// An object is outside of its period of validity.
@@ -1349,13 +1696,58 @@ mod test {
         );
     }
 
+    #[test]
+    fn serial_checked_add() {
+        assert_eq!(
+            Serial::from(0xFFu64).checked_add(1),
+            Ok(Serial::from(0x100u64))
+        );
+        assert_eq!(
+            Serial::from(u64::MAX).checked_add(u64::MAX),
+            Ok(Serial::from(u128::from(u64::MAX) * 2))
+        );
+        assert_eq!(Serial::MAX.next(), Err(SerialOverflowError));
+        assert!(Serial::MAX > Serial::from(u128::MAX));
+        assert!(Serial::MAX.is_near_max(0));
+        assert!(!Serial::from(u128::MAX).is_near_max(u64::MAX));
+        assert!(!Serial::from(1u64).is_near_max(1000));
+
+        let mut max = Serial::MAX.0;
+        max[19] = 0xF0;
+        let near = Serial(max);
+        assert!(near.is_near_max(0x0F));
+        assert!(near.is_near_max(0x10));
+        assert!(!near.is_near_max(0x0E));
+        assert_eq!(near.checked_add(0x0F), Ok(Serial::MAX));
+        assert!(near.checked_add(0x10).is_err());
+    }
+
+    #[test]
+    fn number_sequence() {
+        let mut seq = NumberSequence::new();
+        assert_eq!(seq.next_number(), Ok(Serial::from(1u64)));
+        assert_eq!(seq.next_number(), Ok(Serial::from(2u64)));
+
+        let mut max = Serial::MAX.0;
+        max[19] = 0xFE;
+        let mut seq = NumberSequence::after(Serial(max));
+        assert!(seq.is_near_max(1));
+        assert_eq!(seq.next_number(), Ok(Serial::MAX));
+        assert_eq!(seq.peek(), None);
+        assert_eq!(seq.next_number(), Err(SerialOverflowError));
+
+        seq.rollover(Serial::from(1u64));
+        assert!(!seq.is_near_max(1));
+        assert_eq!(seq.next_number(), Ok(Serial::from(1u64)));
+    }
+
     #[test]
     fn next_year() {
         let now = DateTime::parse_from_rfc3339(
             "2014-10-21T16:39:57-00:00"
         ).unwrap();
         let future = Time::years_from_date(
-            1, DateTime::from_utc(now.naive_utc(), Utc)
+            1, now.with_timezone(&Utc)
         );
 
         assert_eq!(future.year(), 2015);
@@ -1372,7 +1764,7 @@ mod test {
             "2020-02-29T16:39:57-00:00"
         ).unwrap();
         let future = Time::years_from_date(
-            10, DateTime::from_utc(now.naive_utc(), Utc)
+            10, now.with_timezone(&Utc)
         );
 
         assert_eq!(future.year(), 2030);
@@ -1382,5 +1774,59 @@ mod test {
         assert_eq!(future.minute(), 39);
         assert_eq!(future.second(), 57);
     }
+
+    #[test]
+    fn rpki_names() {
+        let name = Name::from_common_name("Some CA", Some("0042")).unwrap();
+        name.check_rpki().unwrap();
+        assert_eq!(name.common_name().as_deref(), Some("Some CA"));
+        assert_eq!(name.serial_number().as_deref(), Some("0042"));
+        assert!(name.matches(
+            &Name::from_common_name("some  ca ", Some("0042")).unwrap()
+        ));
+        assert!(!name.matches(
+            &Name::from_common_name("Some CA", None).unwrap()
+        ));
+        assert!(Name::from_common_name("Some CA*", None).is_err());
+
+        let key_id = KeyIdentifier::from([0xaa; 20]);
+        let name = Name::from_key_identifier(key_id);
+        assert_eq!(
+            name.common_name().unwrap().as_bytes(),
+            key_id.into_hex().as_ref()
+        );
+
+        // An organisation attribute isn’t allowed.
+        let name = Name::from_captured(Captured::from_values(Mode::Der,
+            encode::sequence((
+                encode::set(encode::sequence((
+                    oid::AT_COMMON_NAME.encode(),
+                    OctetString::encode_slice_as(
+                        b"foo", Tag::PRINTABLE_STRING
+                    ),
+                ))),
+                encode::set(encode::sequence((
+                    Oid(&[85u8, 4, 10][..]).encode(),
+                    OctetString::encode_slice_as(
+                        b"bar", Tag::PRINTABLE_STRING
+                    ),
+                ))),
+            ))
+        ));
+        assert!(matches!(
+            name.check_rpki(),
+            Err(NameError::DisallowedAttribute(_))
+        ));
+        assert!(name.inspect_rpki(true).is_err());
+        assert!(name.inspect_rpki(false).is_ok());
+        assert!(name.common_name().is_none());
+
+        // Names in test certificates are fine.
+        let cert = crate::repository::Cert::decode(
+            include_bytes!("../../test-data/repository/ca1.cer").as_ref()
+        ).unwrap();
+        cert.subject().check_rpki().unwrap();
+        cert.issuer().check_rpki().unwrap();
+    }
 }
 
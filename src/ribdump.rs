@@ -0,0 +1,558 @@
+//! Reading routes from BGP table dumps.
+//!
+//! In order to score the routes seen in a router’s RIB against RPKI, they
+//! need to be turned into pairs of prefix and origin AS that can be fed
+//! into [`VrpSet::validate_announcements`]. This module provides readers
+//! for the common formats these dumps are available in:
+//!
+//! * [`TextReader`] reads text files with one route per line. Lines can
+//!   either contain a prefix and an AS number separated by white space or
+//!   be in the pipe-separated format produced by `bgpdump -m`.
+//! * [`MrtReader`] reads the `TABLE_DUMP_V2` RIB entries of binary MRT
+//!   files as defined in RFC 6396.
+//!
+//! Both readers are iterators over the routes which can be passed to
+//! [`score`] in order to get the number of routes in each validation state.
+//!
+//! The module is only available if the `"ribdump"` feature is enabled.
+
+#![cfg(feature = "ribdump")]
+
+use std::{error, fmt, io};
+use std::io::Read;
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use crate::resources::addr::Prefix;
+use crate::resources::asn::Asn;
+use crate::rtr::vrp::{RouteValidity, VrpSet};
+
+
+//------------ TextReader ----------------------------------------------------
+
+/// A reader for routes in text form.
+///
+/// Each line contains one route. Empty lines and lines starting with `#`
+/// are ignored. Lines containing a `|` are interpreted as the output of
+/// `bgpdump -m`. In this case the prefix is taken from the sixth field and
+/// the origin from the last AS number of the AS path in the seventh field.
+/// Routes with an AS path ending in an AS set are skipped since they
+/// don’t have a well-defined origin. All other lines must contain a prefix
+/// and an AS number, with or without an `AS` prefix, separated by white
+/// space. Any further fields are ignored.
+pub struct TextReader<R> {
+    /// The underlying reader.
+    reader: R,
+
+    /// A buffer for the current line.
+    line: String,
+
+    /// The number of the current line.
+    line_no: usize,
+}
+
+impl<R> TextReader<R> {
+    /// Creates a new text reader atop a buffered reader.
+    pub fn new(reader: R) -> Self {
+        TextReader { reader, line: String::new(), line_no: 0 }
+    }
+
+    /// Parses a single line.
+    ///
+    /// Returns `Ok(None)` if the line should be skipped.
+    fn parse_line(line: &str) -> Result<Option<(Prefix, Asn)>, &'static str> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None)
+        }
+        if line.contains('|') {
+            let mut fields = line.split('|').skip(5);
+            let prefix = fields.next().ok_or("missing prefix")?;
+            let path = fields.next().ok_or("missing AS path")?;
+            let origin = match path.split_whitespace().last() {
+                Some(origin) => origin,
+                None => return Ok(None),
+            };
+            if origin.ends_with('}') {
+                return Ok(None)
+            }
+            Ok(Some((
+                Prefix::from_str(prefix).map_err(|_| "invalid prefix")?,
+                Asn::from_str(origin).map_err(|_| "invalid origin AS")?,
+            )))
+        }
+        else {
+            let mut fields = line.split_whitespace();
+            let prefix = fields.next().ok_or("missing prefix")?;
+            let origin = fields.next().ok_or("missing origin AS")?;
+            Ok(Some((
+                Prefix::from_str(prefix).map_err(|_| "invalid prefix")?,
+                Asn::from_str(origin).map_err(|_| "invalid origin AS")?,
+            )))
+        }
+    }
+}
+
+impl<R: io::BufRead> Iterator for TextReader<R> {
+    type Item = Result<(Prefix, Asn), ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => { }
+                Err(err) => return Some(Err(err.into())),
+            }
+            self.line_no += 1;
+            match Self::parse_line(&self.line) {
+                Ok(Some(res)) => return Some(Ok(res)),
+                Ok(None) => { }
+                Err(reason) => {
+                    return Some(Err(ReadError::Line {
+                        line: self.line_no, reason
+                    }))
+                }
+            }
+        }
+    }
+}
+
+
+//------------ MrtReader -----------------------------------------------------
+
+/// A reader for routes in MRT files.
+///
+/// The reader only looks at records of type `TABLE_DUMP_V2` with the
+/// subtypes `RIB_IPV4_UNICAST` and `RIB_IPV6_UNICAST`. All other records
+/// are skipped. For each RIB entry of these records, the prefix of the
+/// record is returned together with the origin AS taken from the last
+/// AS number of the entry’s `AS_PATH` attribute. Entries without an AS path
+/// or with an AS path ending in an AS set are skipped.
+///
+/// The reader doesn’t decompress files, so compressed dumps need to be
+/// wrapped in a suitable decoder first.
+pub struct MrtReader<R> {
+    /// The underlying reader.
+    reader: R,
+
+    /// Routes of the current record not yet returned.
+    pending: VecDeque<(Prefix, Asn)>,
+
+    /// Whether the reader has failed and should stop.
+    failed: bool,
+}
+
+/// The MRT type for `TABLE_DUMP_V2`.
+const TABLE_DUMP_V2: u16 = 13;
+
+/// The `TABLE_DUMP_V2` subtype for `RIB_IPV4_UNICAST`.
+const RIB_IPV4_UNICAST: u16 = 2;
+
+/// The `TABLE_DUMP_V2` subtype for `RIB_IPV6_UNICAST`.
+const RIB_IPV6_UNICAST: u16 = 4;
+
+/// The BGP path attribute type code for `AS_PATH`.
+const AS_PATH: u8 = 2;
+
+/// The AS path segment type for `AS_SEQUENCE`.
+const AS_SEQUENCE: u8 = 2;
+
+impl<R> MrtReader<R> {
+    /// Creates a new MRT reader.
+    pub fn new(reader: R) -> Self {
+        MrtReader { reader, pending: VecDeque::new(), failed: false }
+    }
+}
+
+impl<R: io::Read> MrtReader<R> {
+    /// Reads the next record and adds its routes to `self.pending`.
+    ///
+    /// Returns `Ok(false)` if the end of the file has been reached.
+    fn read_record(&mut self) -> Result<bool, ReadError> {
+        let mut header = [0u8; 12];
+        match self.reader.read_exact(&mut header[..1]) {
+            Ok(()) => { }
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(false)
+            }
+            Err(err) => return Err(err.into())
+        }
+        self.reader.read_exact(&mut header[1..])?;
+        let kind = u16::from_be_bytes([header[4], header[5]]);
+        let subtype = u16::from_be_bytes([header[6], header[7]]);
+        let len = u32::from_be_bytes(
+            [header[8], header[9], header[10], header[11]]
+        );
+        let mut body = Vec::new();
+        (&mut self.reader).take(len.into()).read_to_end(&mut body)?;
+        if body.len() != len as usize {
+            return Err(ReadError::Mrt("short record"))
+        }
+        if kind != TABLE_DUMP_V2 {
+            return Ok(true)
+        }
+        let v4 = match subtype {
+            RIB_IPV4_UNICAST => true,
+            RIB_IPV6_UNICAST => false,
+            _ => return Ok(true)
+        };
+        self.parse_rib(&mut Cursor(&body), v4)?;
+        Ok(true)
+    }
+
+    /// Parses a RIB record.
+    fn parse_rib(
+        &mut self, body: &mut Cursor, v4: bool
+    ) -> Result<(), ReadError> {
+        body.take(4)?; // sequence number
+        let prefix_len = body.u8()?;
+        let mut addr = [0u8; 16];
+        let octets = (usize::from(prefix_len) + 7) / 8;
+        if octets > if v4 { 4 } else { 16 } {
+            return Err(ReadError::Mrt("invalid prefix length"))
+        }
+        addr[..octets].copy_from_slice(body.take(octets)?);
+        let addr = if v4 {
+            IpAddr::from(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+        }
+        else {
+            IpAddr::from(Ipv6Addr::from(addr))
+        };
+        let prefix = Prefix::new_relaxed(addr, prefix_len).map_err(|_| {
+            ReadError::Mrt("invalid prefix length")
+        })?;
+        let count = body.u16()?;
+        for _ in 0..count {
+            body.take(6)?; // peer index and originated time
+            let attr_len = usize::from(body.u16()?);
+            let mut attrs = Cursor(body.take(attr_len)?);
+            if let Some(origin) = Self::origin(&mut attrs)? {
+                self.pending.push_back((prefix, origin));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the origin AS from a sequence of path attributes.
+    fn origin(attrs: &mut Cursor) -> Result<Option<Asn>, ReadError> {
+        while !attrs.is_empty() {
+            let flags = attrs.u8()?;
+            let code = attrs.u8()?;
+            let len = if flags & 0x10 != 0 {
+                usize::from(attrs.u16()?)
+            }
+            else {
+                usize::from(attrs.u8()?)
+            };
+            let value = attrs.take(len)?;
+            if code != AS_PATH {
+                continue
+            }
+            let mut path = Cursor(value);
+            let mut res = None;
+            while !path.is_empty() {
+                let kind = path.u8()?;
+                let count = usize::from(path.u8()?);
+                let asns = path.take(count * 4)?;
+                res = if kind == AS_SEQUENCE {
+                    asns.chunks(4).last().map(|asn| {
+                        Asn::from_u32(u32::from_be_bytes(
+                            [asn[0], asn[1], asn[2], asn[3]]
+                        ))
+                    })
+                }
+                else {
+                    None
+                };
+            }
+            return Ok(res)
+        }
+        Ok(None)
+    }
+}
+
+impl<R: io::Read> Iterator for MrtReader<R> {
+    type Item = Result<(Prefix, Asn), ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(res) = self.pending.pop_front() {
+                return Some(Ok(res))
+            }
+            if self.failed {
+                return None
+            }
+            match self.read_record() {
+                Ok(true) => { }
+                Ok(false) => return None,
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err))
+                }
+            }
+        }
+    }
+}
+
+
+//------------ Cursor --------------------------------------------------------
+
+/// A cursor over a slice of octets.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReadError> {
+        if self.0.len() < len {
+            return Err(ReadError::Mrt("short record"))
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReadError> {
+        self.take(1).map(|data| data[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReadError> {
+        self.take(2).map(|data| u16::from_be_bytes([data[0], data[1]]))
+    }
+}
+
+
+//------------ score ---------------------------------------------------------
+
+/// Validates routes and returns the number of routes in each state.
+///
+/// The routes are taken from `routes` which typically is one of the
+/// readers of this module. Validation stops at the first error.
+pub fn score<I>(
+    vrps: &VrpSet, routes: I
+) -> Result<ValiditySummary, ReadError>
+where I: IntoIterator<Item = Result<(Prefix, Asn), ReadError>> {
+    let mut err = None;
+    let mut res = ValiditySummary::default();
+    let routes = routes.into_iter().map_while(|item| {
+        match item {
+            Ok(item) => Some(item),
+            Err(e) => {
+                err = Some(e);
+                None
+            }
+        }
+    });
+    for validity in vrps.validate_announcements(routes) {
+        res.add(validity)
+    }
+    match err {
+        Some(err) => Err(err),
+        None => Ok(res)
+    }
+}
+
+
+//------------ ValiditySummary -----------------------------------------------
+
+/// The number of routes in each validation state.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ValiditySummary {
+    pub valid: usize,
+    pub invalid: usize,
+    pub not_found: usize,
+}
+
+impl ValiditySummary {
+    /// Adds a route with the given state.
+    pub fn add(&mut self, validity: RouteValidity) {
+        match validity {
+            RouteValidity::Valid => self.valid += 1,
+            RouteValidity::Invalid => self.invalid += 1,
+            RouteValidity::NotFound => self.not_found += 1,
+        }
+    }
+
+    /// Returns the total number of routes.
+    pub fn total(&self) -> usize {
+        self.valid + self.invalid + self.not_found
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ ReadError -----------------------------------------------------
+
+/// Reading routes from a dump has failed.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading from the underlying reader failed.
+    Io(io::Error),
+
+    /// A line of a text dump is invalid.
+    Line {
+        /// The number of the line, starting at 1.
+        line: usize,
+
+        /// Why the line is invalid.
+        reason: &'static str,
+    },
+
+    /// An MRT record is invalid.
+    Mrt(&'static str),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref err) => err.fmt(f),
+            ReadError::Line { line, reason } => {
+                write!(f, "line {}: {}", line, reason)
+            }
+            ReadError::Mrt(reason) => {
+                write!(f, "invalid MRT record: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ReadError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use crate::resources::addr::MaxLenPrefix;
+    use crate::rtr::payload::RouteOrigin;
+    use super::*;
+
+    fn route(prefix: &str, asn: u32) -> (Prefix, Asn) {
+        (Prefix::from_str(prefix).unwrap(), Asn::from_u32(asn))
+    }
+
+    #[test]
+    fn text_reader() {
+        let data = "\
+            # comment\n\
+            \n\
+            10.0.0.0/8 64496\n\
+            10.1.0.0/16\tAS64497 extra\n\
+            TABLE_DUMP2|1556668800|B|192.0.2.1|64500|\
+                2001:db8::/32|64500 64501 64499|IGP|192.0.2.1|0|0||NAG||\n\
+            TABLE_DUMP2|1556668800|B|192.0.2.1|64500|\
+                192.0.2.0/24|64500 {64501,64502}|IGP|192.0.2.1|0|0||NAG||\n\
+            10.2.0.0/16\n\
+        ";
+        let mut reader = TextReader::new(data.as_bytes());
+        assert_eq!(
+            reader.next().unwrap().unwrap(), route("10.0.0.0/8", 64496)
+        );
+        assert_eq!(
+            reader.next().unwrap().unwrap(), route("10.1.0.0/16", 64497)
+        );
+        assert_eq!(
+            reader.next().unwrap().unwrap(), route("2001:db8::/32", 64499)
+        );
+        assert!(matches!(
+            reader.next().unwrap(), Err(ReadError::Line { line: 7, .. })
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    fn rib_record(
+        subtype: u16, prefix_len: u8, prefix: &[u8], paths: &[&[u32]]
+    ) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 1, prefix_len];
+        body.extend_from_slice(prefix);
+        body.extend_from_slice(&(paths.len() as u16).to_be_bytes());
+        for path in paths {
+            // ORIGIN attribute followed by AS_PATH.
+            let mut attrs = vec![0x40, 1, 1, 0];
+            attrs.extend_from_slice(&[0x50, AS_PATH]);
+            attrs.extend_from_slice(
+                &((2 + path.len() * 4) as u16).to_be_bytes()
+            );
+            attrs.extend_from_slice(&[AS_SEQUENCE, path.len() as u8]);
+            for asn in *path {
+                attrs.extend_from_slice(&asn.to_be_bytes());
+            }
+            body.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+            body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+            body.extend_from_slice(&attrs);
+        }
+        let mut res = vec![0, 0, 0, 0];
+        res.extend_from_slice(&TABLE_DUMP_V2.to_be_bytes());
+        res.extend_from_slice(&subtype.to_be_bytes());
+        res.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        res.extend_from_slice(&body);
+        res
+    }
+
+    #[test]
+    fn mrt_reader() {
+        let mut data = Vec::new();
+        // A peer index table which is skipped.
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 13, 0, 1, 0, 0, 0, 2, 0, 0]);
+        data.extend(rib_record(
+            RIB_IPV4_UNICAST, 16, &[10, 1], &[&[64500, 64497], &[64496]]
+        ));
+        data.extend(rib_record(
+            RIB_IPV6_UNICAST, 32, &[0x20, 0x01, 0x0d, 0xb8], &[&[64499]]
+        ));
+        let routes: Vec<_> = MrtReader::new(data.as_slice()).map(|item| {
+            item.unwrap()
+        }).collect();
+        assert_eq!(
+            routes,
+            [
+                route("10.1.0.0/16", 64497),
+                route("10.1.0.0/16", 64496),
+                route("2001:db8::/32", 64499),
+            ]
+        );
+
+        data.truncate(data.len() - 1);
+        let mut reader = MrtReader::new(data.as_slice());
+        assert!(reader.by_ref().take(2).all(|item| item.is_ok()));
+        assert!(matches!(reader.next(), Some(Err(ReadError::Mrt(_)))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn score_routes() {
+        let vrps: VrpSet = [
+            RouteOrigin::new(
+                MaxLenPrefix::new(
+                    Prefix::from_str("10.0.0.0/8").unwrap(), Some(16)
+                ).unwrap(),
+                Asn::from_u32(64496)
+            )
+        ].into_iter().collect();
+        let data = "10.1.0.0/16 64496\n10.1.0.0/16 64497\n192.0.2.0/24 1\n";
+        let summary = score(&vrps, TextReader::new(data.as_bytes())).unwrap();
+        assert_eq!(
+            summary,
+            ValiditySummary { valid: 1, invalid: 1, not_found: 1 }
+        );
+        assert_eq!(summary.total(), 3);
+        assert!(score(&vrps, TextReader::new("foo".as_bytes())).is_err());
+    }
+}
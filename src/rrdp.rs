@@ -27,12 +27,16 @@ use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::ops::Deref;
 use bytes::Bytes;
-use log::info;
+use log::{debug, info, trace};
 use ring::digest;
 use uuid::Uuid;
 use crate::{uri, xml};
-use crate::util::base64;
-use crate::xml::decode::{Content, Error as XmlError, Reader, Name};
+use crate::progress::Progress;
+use crate::util::{base64, hex};
+use crate::util::cancel::{CancelToken, Cancelled};
+use crate::xml::decode::{
+    Content, Error as XmlError, Reader, Name, UnknownElement, UnknownElements
+};
 
 #[cfg(feature = "serde")] use std::str::FromStr;
 #[cfg(feature = "serde")] use serde::{
@@ -118,7 +122,7 @@ impl NotificationFile {
     /// In other words, the delta with the largest serial number will
     /// appear at the beginning of the sequence.
     pub fn reverse_sort_deltas(&mut self) {
-        self.deltas.sort_by(|a,b| b.serial.cmp(&a.serial));
+        self.deltas.sort_by_key(|delta| std::cmp::Reverse(delta.serial));
     }
 
     /// Sorts, verifies, and optionally limits the list of deltas.
@@ -138,12 +142,15 @@ impl NotificationFile {
                 }
             }
 
-            let mut last_seen = self.deltas[0].serial();
-            for delta in &self.deltas[1..] {
-                if last_seen + 1 != delta.serial() {
-                    return false;
-                } else {
-                    last_seen = delta.serial()
+            let mut deltas = self.deltas.iter();
+            if let Some(first) = deltas.next() {
+                let mut last_seen = first.serial();
+                for delta in deltas {
+                    if last_seen.checked_add(1) != Some(delta.serial()) {
+                        return false;
+                    } else {
+                        last_seen = delta.serial()
+                    }
                 }
             }
         }
@@ -157,7 +164,36 @@ impl NotificationFile {
 ///
 impl NotificationFile {
     /// Parses the notification file from its XML representation.
+    ///
+    /// Any unknown element will lead to an error.
     pub fn parse<R: io::BufRead>(reader: R) -> Result<Self, XmlError> {
+        Self::parse_with(reader, &mut UnknownElements::strict())
+    }
+
+    /// Parses the notification file skipping unknown elements.
+    ///
+    /// Unknown elements inside the notification element and their content
+    /// are skipped rather than leading to an error. Their names are
+    /// returned alongside the notification file.
+    pub fn parse_lenient<R: io::BufRead>(
+        reader: R
+    ) -> Result<(Self, Vec<UnknownElement>), XmlError> {
+        let mut unknown = UnknownElements::lenient();
+        let res = Self::parse_with(reader, &mut unknown)?;
+        if !unknown.is_empty() {
+            debug!(
+                "RRDP: skipped {} unknown elements in notification file",
+                unknown.as_slice().len()
+            );
+        }
+        Ok((res, unknown.into_vec()))
+    }
+
+    /// Parses the notification file using the given unknown handling.
+    fn parse_with<R: io::BufRead>(
+        reader: R,
+        unknown: &mut UnknownElements,
+    ) -> Result<Self, XmlError> {
         let mut reader = Reader::new(reader);
 
         let mut session_id = None;
@@ -189,6 +225,7 @@ impl NotificationFile {
         let mut snapshot = None;
 
         let mut deltas = vec![];
+        let mut skip = false;
 
         while let Some(mut content) = outer.take_opt_element(&mut reader,
                                                              |element| {
@@ -245,10 +282,18 @@ impl NotificationFile {
                         _ => Err(XmlError::Malformed)
                     }
                 }
-                _ => Err(XmlError::Malformed)
+                _ => {
+                    skip = true;
+                    unknown.add(&element)
+                }
             }
         })? {
-            content.take_end(&mut reader)?;
+            if skip {
+                content.skip(&mut reader)?;
+                skip = false;
+            } else {
+                content.take_end(&mut reader)?;
+            }
         }
 
         outer.take_end(&mut reader)?;
@@ -295,6 +340,19 @@ impl NotificationFile {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for NotificationFile {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(NotificationFile::new(
+            arbitrary_uuid(u)?, u.arbitrary()?, u.arbitrary()?,
+            u.arbitrary()?,
+        ))
+    }
+}
+
+
 //------------ PublishElement ------------------------------------------------
 
 /// Am RPKI object to be published for the first time.
@@ -355,6 +413,16 @@ impl PublishElement {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for PublishElement {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(PublishElement::new(u.arbitrary()?, arbitrary_bytes(u)?))
+    }
+}
+
+
 //------------ UpdateElement -------------------------------------------------
 
 /// An RPKI object to be updated with new content.
@@ -420,6 +488,18 @@ impl UpdateElement {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for UpdateElement {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(UpdateElement::new(
+            u.arbitrary()?, u.arbitrary()?, arbitrary_bytes(u)?
+        ))
+    }
+}
+
+
 //------------ WithdrawElement -----------------------------------------------
 
 /// An RPKI object is to be delete.
@@ -427,6 +507,7 @@ impl UpdateElement {
 /// This type defines an RRDP update element as found in RRDP deltas.  It is
 /// like a [`PublishElement`] except that it removes an existing object.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct WithdrawElement {
     /// The URI of the object to be deleted.
     uri: uri::Rsync,
@@ -475,6 +556,7 @@ impl WithdrawElement {
 
 /// A single element of a RRDP delta.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum DeltaElement {
     /// The element publishes a new object.
     Publish(PublishElement),
@@ -500,7 +582,7 @@ impl DeltaElement {
     }
 }
 
-///--- From
+//--- From
 
 impl From<PublishElement> for DeltaElement {
     fn from(src: PublishElement) -> Self {
@@ -608,6 +690,16 @@ impl Snapshot {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Snapshot {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(Snapshot::new(arbitrary_uuid(u)?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+
 //------------ SnapshotBuilder -----------------------------------------------
 
 struct SnapshotBuilder {
@@ -735,6 +827,10 @@ pub trait ProcessSnapshot {
 
         match (session_id, serial) {
             (Some(session_id), Some(serial)) => {
+                debug!(
+                    "RRDP: processing snapshot for session {}, serial {}",
+                    session_id, serial
+                );
                 self.meta(session_id, serial)?;
             }
             _ => {
@@ -743,6 +839,7 @@ pub trait ProcessSnapshot {
             }
         }
 
+        let mut count = 0usize;
         loop {
             let mut uri = None;
             let inner = outer.take_opt_element(&mut reader, |element| {
@@ -765,17 +862,115 @@ pub trait ProcessSnapshot {
                 Some(inner) => inner,
                 None => break
             };
-            let uri = match uri {
+            let uri: uri::Rsync = match uri {
                 Some(uri) => uri,
                 None => return Err(ProcessError::malformed().into())
             };
-            ObjectReader::process(&mut inner, &mut reader, |reader| {
-                self.publish(uri, reader)
-            })?;
+            trace!("RRDP: snapshot publishes {}", uri);
+            ObjectReader::process(
+                &uri.clone(), &mut inner, &mut reader,
+                |reader| self.publish(uri, reader)
+            )?;
+            count += 1;
         }
 
         outer.take_end(&mut reader).map_err(Into::into)?;
         reader.end().map_err(Into::into)?;
+        debug!("RRDP: processed snapshot with {} objects", count);
+        Ok(())
+    }
+
+    /// Processes a snapshot file unless cancelled.
+    ///
+    /// This is the same as [`process`][Self::process] but checks `cancel`
+    /// before each object. If the token has been cancelled, processing is
+    /// aborted with [`ProcessError::Cancelled`].
+    fn process_cancellable<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        cancel: &CancelToken,
+    ) -> Result<(), Self::Err> {
+        CancellableSnapshot { processor: self, cancel }.process(reader)
+    }
+
+    /// Processes a snapshot file reporting progress.
+    ///
+    /// This is the same as [`process`][Self::process] but reports each
+    /// published object to `progress` under the given repository, which
+    /// should be the URI of the notification file.
+    fn process_with_progress<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        repository: &str,
+        progress: &dyn Progress,
+    ) -> Result<(), Self::Err> {
+        ReportingSnapshot {
+            processor: self, repository, progress
+        }.process(reader)
+    }
+}
+
+
+//------------ CancellableSnapshot -------------------------------------------
+
+/// A snapshot processor checking a cancel token.
+struct CancellableSnapshot<'a, P: ?Sized> {
+    /// The actual processor.
+    processor: &'a mut P,
+
+    /// The token to check.
+    cancel: &'a CancelToken,
+}
+
+impl<'a, P: ProcessSnapshot + ?Sized> ProcessSnapshot
+for CancellableSnapshot<'a, P> {
+    type Err = P::Err;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.cancel.check().map_err(ProcessError::from)?;
+        self.processor.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self, uri: uri::Rsync, data: &mut ObjectReader
+    ) -> Result<(), Self::Err> {
+        self.cancel.check().map_err(ProcessError::from)?;
+        self.processor.publish(uri, data)
+    }
+}
+
+
+//------------ ReportingSnapshot ---------------------------------------------
+
+/// A snapshot processor reporting progress.
+struct ReportingSnapshot<'a, P: ?Sized> {
+    /// The actual processor.
+    processor: &'a mut P,
+
+    /// The repository to report progress for.
+    repository: &'a str,
+
+    /// The progress reporter.
+    progress: &'a dyn Progress,
+}
+
+impl<'a, P: ProcessSnapshot + ?Sized> ProcessSnapshot
+for ReportingSnapshot<'a, P> {
+    type Err = P::Err;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.processor.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self, uri: uri::Rsync, data: &mut ObjectReader
+    ) -> Result<(), Self::Err> {
+        self.processor.publish(uri, data)?;
+        self.progress.objects_processed(self.repository, 1);
         Ok(())
     }
 }
@@ -873,6 +1068,16 @@ impl Delta {
 }
 
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Delta {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>
+    ) -> arbitrary::Result<Self> {
+        Ok(Delta::new(arbitrary_uuid(u)?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+
 //------------ DeltaBuilder --------------------------------------------------
 
 struct DeltaBuilder {
@@ -1040,11 +1245,16 @@ pub trait ProcessDelta {
 
         match (session_id, serial) {
             (Some(session_id), Some(serial)) => {
+                debug!(
+                    "RRDP: processing delta for session {}, serial {}",
+                    session_id, serial
+                );
                 self.meta(session_id, serial)?;
             }
             _ => return Err(ProcessError::malformed().into())
         }
 
+        let (mut published, mut withdrawn) = (0usize, 0usize);
         loop {
             let mut action = None;
             let mut uri = None;
@@ -1071,32 +1281,156 @@ pub trait ProcessDelta {
                 Some(inner) => inner,
                 None => break
             };
-            let uri = match uri {
+            let uri: uri::Rsync = match uri {
                 Some(uri) => uri,
                 None => return Err(ProcessError::malformed().into())
             };
-            match action.unwrap() { // Or we'd have exited already.
+            let action = match action {
+                Some(action) => action,
+                None => return Err(ProcessError::malformed().into())
+            };
+            match action {
                 Action::Publish => {
+                    trace!("RRDP: delta publishes {}", uri);
                     ObjectReader::process(
-                        &mut inner, &mut reader,
+                        &uri.clone(), &mut inner, &mut reader,
                         |reader| self.publish(uri, hash, reader)
                     )?;
+                    published += 1;
                 }
                 Action::Withdraw => {
                     let hash = match hash {
                         Some(hash) => hash,
                         None => return Err(ProcessError::malformed().into())
                     };
+                    trace!("RRDP: delta withdraws {}", uri);
                     self.withdraw(uri, hash)?;
                     inner.take_end(&mut reader).map_err(Into::into)?;
+                    withdrawn += 1;
                 }
             }
         }
         outer.take_end(&mut reader).map_err(Into::into)?;
         reader.end().map_err(Into::into)?;
+        debug!(
+            "RRDP: processed delta with {} published and {} withdrawn objects",
+            published, withdrawn
+        );
         Ok(())
     }
 
+    /// Processes a delta file unless cancelled.
+    ///
+    /// This is the same as [`process`][Self::process] but checks `cancel`
+    /// before each element. If the token has been cancelled, processing is
+    /// aborted with [`ProcessError::Cancelled`].
+    fn process_cancellable<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        cancel: &CancelToken,
+    ) -> Result<(), Self::Err> {
+        CancellableDelta { processor: self, cancel }.process(reader)
+    }
+
+    /// Processes a delta file reporting progress.
+    ///
+    /// This is the same as [`process`][Self::process] but reports each
+    /// published and withdrawn object to `progress` under the given
+    /// repository, which should be the URI of the notification file.
+    fn process_with_progress<R: io::BufRead>(
+        &mut self,
+        reader: R,
+        repository: &str,
+        progress: &dyn Progress,
+    ) -> Result<(), Self::Err> {
+        ReportingDelta {
+            processor: self, repository, progress
+        }.process(reader)
+    }
+}
+
+
+//------------ CancellableDelta ----------------------------------------------
+
+/// A delta processor checking a cancel token.
+struct CancellableDelta<'a, P: ?Sized> {
+    /// The actual processor.
+    processor: &'a mut P,
+
+    /// The token to check.
+    cancel: &'a CancelToken,
+}
+
+impl<'a, P: ProcessDelta + ?Sized> ProcessDelta for CancellableDelta<'a, P> {
+    type Err = P::Err;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.cancel.check().map_err(ProcessError::from)?;
+        self.processor.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<Hash>,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.cancel.check().map_err(ProcessError::from)?;
+        self.processor.publish(uri, hash, data)
+    }
+
+    fn withdraw(
+        &mut self, uri: uri::Rsync, hash: Hash
+    ) -> Result<(), Self::Err> {
+        self.cancel.check().map_err(ProcessError::from)?;
+        self.processor.withdraw(uri, hash)
+    }
+}
+
+
+//------------ ReportingDelta ------------------------------------------------
+
+/// A delta processor reporting progress.
+struct ReportingDelta<'a, P: ?Sized> {
+    /// The actual processor.
+    processor: &'a mut P,
+
+    /// The repository to report progress for.
+    repository: &'a str,
+
+    /// The progress reporter.
+    progress: &'a dyn Progress,
+}
+
+impl<'a, P: ProcessDelta + ?Sized> ProcessDelta for ReportingDelta<'a, P> {
+    type Err = P::Err;
+
+    fn meta(
+        &mut self, session_id: Uuid, serial: u64
+    ) -> Result<(), Self::Err> {
+        self.processor.meta(session_id, serial)
+    }
+
+    fn publish(
+        &mut self,
+        uri: uri::Rsync,
+        hash: Option<Hash>,
+        data: &mut ObjectReader,
+    ) -> Result<(), Self::Err> {
+        self.processor.publish(uri, hash, data)?;
+        self.progress.objects_processed(self.repository, 1);
+        Ok(())
+    }
+
+    fn withdraw(
+        &mut self, uri: uri::Rsync, hash: Hash
+    ) -> Result<(), Self::Err> {
+        self.processor.withdraw(uri, hash)?;
+        self.progress.objects_processed(self.repository, 1);
+        Ok(())
+    }
 }
 
 
@@ -1110,6 +1444,7 @@ pub type SnapshotInfo = UriAndHash;
 
 /// The serial, URI and HASH of a delta in a [`NotificationFile`].
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct DeltaInfo {
     serial: u64,
     uri_and_hash: UriAndHash
@@ -1147,6 +1482,7 @@ impl Deref for DeltaInfo {
 /// expected content of that file, allowing a client to verify they got the
 /// right file.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct UriAndHash {
     /// The URI of the RRDP file.
     uri: uri::Https,
@@ -1193,7 +1529,8 @@ impl UriAndHash {
 /// RRDP exclusively uses SHA-256 and provides no means of choosing a different
 /// algorithm. Consequently, this type is a wrapper around a 32 byte array
 /// holding SHA-256 output.
-#[derive(Clone, Copy, Eq, hash::Hash, PartialEq)]
+#[derive(Clone, Copy, Eq, hash::Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(transparent)] // ensure that size_of::<Hash>() == 32.
 pub struct Hash([u8; 32]);
 
@@ -1262,20 +1599,9 @@ impl str::FromStr for Hash {
             return Err(ParseHashError::BAD_LENGTH)
         }
         let mut res = [0u8; 32];
-        let mut s = s.chars();
-        for octet in &mut res {
-            let first = s.next().ok_or(
-                ParseHashError::BAD_LENGTH
-            )?.to_digit(16).ok_or(
-                ParseHashError::BAD_CHARS
-            )?;
-            let second = s.next().ok_or(
-                ParseHashError::BAD_LENGTH
-            )?.to_digit(16).ok_or(
-                ParseHashError::BAD_CHARS
-            )?;
-            *octet = (first << 4 | second) as u8;
-        }
+        hex::decode(s.as_bytes(), &mut res).map_err(|_| {
+            ParseHashError::BAD_CHARS
+        })?;
         Ok(Hash(res))
     }
 }
@@ -1306,10 +1632,8 @@ impl PartialEq<digest::Digest> for Hash {
 
 impl fmt::Display for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for &ch in self.as_slice() {
-            write!(f, "{:02x}", ch)?;
-        }
-        Ok(())
+        let mut buf = [0u8; 64];
+        f.write_str(hex::encode_lower(self.as_slice(), &mut buf))
     }
 }
 
@@ -1360,10 +1684,15 @@ enum Action {
 ///
 /// The content is included in base64 encoding in the RRDP’s XML. This reader
 /// provides access to the decoded data via the standard `Read` trait.
-pub struct ObjectReader<'a>(
+pub struct ObjectReader<'a> {
     /// The base64 encoded data.
-    base64::XmlDecoderReader<'a>
-);
+    data: base64::XmlDecoderReader<'a>,
+
+    /// The URI of the object.
+    ///
+    /// This is used to report which object had invalid content.
+    uri: uri::Rsync,
+}
 
 impl<'a> ObjectReader<'a> {
     /// Processes an element with optional XML PCDATA as object content.
@@ -1374,6 +1703,7 @@ impl<'a> ObjectReader<'a> {
     /// This method expects the next XML event to either be text or the end
     /// of an element. It will process both.
     fn process<R, T, E, F> (
+        uri: &uri::Rsync,
         content: &mut Content,
         reader: &mut Reader<R>,
         op: F
@@ -1403,7 +1733,10 @@ impl<'a> ObjectReader<'a> {
                 None => Default::default(),
             };
             op(
-                &mut ObjectReader(base64::Xml.decode_reader(b64.as_ref()))
+                &mut ObjectReader {
+                    data: base64::Xml.decode_reader(b64.as_ref()),
+                    uri: uri.clone(),
+                }
             ).map_err(Error::User)
         }).map_err(|err| match err {
             Error::Xml(err) => ProcessError::Xml(err).into(),
@@ -1414,7 +1747,12 @@ impl<'a> ObjectReader<'a> {
 
 impl<'a> io::Read for ObjectReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
-        self.0.read(buf)
+        self.data.read(buf).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                ContentError { uri: self.uri.clone(), err }
+            )
+        })
     }
 }
 
@@ -1429,6 +1767,25 @@ const PUBLISH: Name = Name::qualified(NS, b"publish");
 const WITHDRAW: Name = Name::qualified(NS, b"withdraw");
 
 
+//------------ Arbitrary Helpers ---------------------------------------------
+
+/// Creates an arbitrary session ID.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_uuid(
+    u: &mut arbitrary::Unstructured
+) -> arbitrary::Result<Uuid> {
+    Ok(Uuid::from_bytes(u.arbitrary()?))
+}
+
+/// Creates arbitrary object content.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_bytes(
+    u: &mut arbitrary::Unstructured
+) -> arbitrary::Result<Bytes> {
+    Ok(Bytes::copy_from_slice(u.arbitrary()?))
+}
+
+
 //============ Errors ========================================================
 
 //------------ AlgorithmError ------------------------------------------------
@@ -1446,6 +1803,45 @@ impl fmt::Display for AlgorithmError {
 impl error::Error for AlgorithmError { }
 
 
+//------------ ContentError --------------------------------------------------
+
+/// The content of a published object was not valid Base 64.
+///
+/// This error is returned wrapped in an IO error by the [`ObjectReader`]
+/// and names the URI of the offending object.
+#[derive(Debug)]
+pub struct ContentError {
+    /// The URI of the object.
+    uri: uri::Rsync,
+
+    /// The underlying decoding error.
+    err: io::Error,
+}
+
+impl ContentError {
+    /// Returns the URI of the object with the invalid content.
+    pub fn uri(&self) -> &uri::Rsync {
+        &self.uri
+    }
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid Base64 content for {}: ", self.uri)?;
+        match self.err.get_ref() {
+            Some(inner) => fmt::Display::fmt(inner, f),
+            None => fmt::Display::fmt(&self.err, f),
+        }
+    }
+}
+
+impl error::Error for ContentError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+
 //------------ ParseHashError ------------------------------------------------
 
 /// An error happened while parsing a hash.
@@ -1473,12 +1869,16 @@ impl error::Error for ParseHashError { }
 
 /// An error occurred while processing RRDP data.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ProcessError {
     /// An IO error happened.
     Io(io::Error),
 
     /// The XML was not correctly formed.
     Xml(XmlError),
+
+    /// Processing was cancelled.
+    Cancelled,
 }
 
 impl ProcessError {
@@ -1500,16 +1900,31 @@ impl From<XmlError> for ProcessError {
     }
 }
 
+impl From<Cancelled> for ProcessError {
+    fn from(_: Cancelled) -> Self {
+        ProcessError::Cancelled
+    }
+}
+
 impl fmt::Display for ProcessError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ProcessError::Io(ref inner) => inner.fmt(f),
             ProcessError::Xml(ref inner) => inner.fmt(f),
+            ProcessError::Cancelled => Cancelled.fmt(f),
         }
     }
 }
 
-impl error::Error for ProcessError { }
+impl error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProcessError::Io(ref inner) => Some(inner),
+            ProcessError::Xml(ref inner) => Some(inner),
+            ProcessError::Cancelled => None,
+        }
+    }
+}
 
 
 //============ Tests =========================================================
@@ -1606,6 +2021,31 @@ mod test {
         assert!(!notification_with_gaps.sort_and_verify_deltas(None));
     }
 
+    #[test]
+    fn verify_hostile_deltas() {
+        let snapshot = UriAndHash::new(
+            uri::Https::from_str("https://example.com/snapshot.xml").unwrap(),
+            Hash::from_data(b"")
+        );
+        let delta = |serial| DeltaInfo::new(
+            serial,
+            uri::Https::from_str("https://example.com/delta.xml").unwrap(),
+            Hash::from_data(b"")
+        );
+
+        let mut notification = NotificationFile::new(
+            Uuid::nil(), u64::MAX, snapshot.clone(),
+            vec![delta(u64::MAX), delta(u64::MAX)]
+        );
+        assert!(!notification.sort_and_verify_deltas(None));
+
+        let mut notification = NotificationFile::new(
+            Uuid::nil(), 2, snapshot, vec![delta(1), delta(2)]
+        );
+        assert!(notification.sort_and_verify_deltas(Some(0)));
+        assert!(notification.deltas().is_empty());
+    }
+
     #[test]
     fn limit_notification_deltas() {
         let mut notification_without_gaps =  NotificationFile::parse(
@@ -1664,6 +2104,48 @@ mod test {
         ).unwrap();
     }
 
+    #[test]
+    fn process_cancellable() {
+        let snapshot = include_bytes!("../test-data/rrdp/ripe-snapshot.xml");
+        let delta = include_bytes!("../test-data/rrdp/ripe-delta.xml");
+        let cancel = CancelToken::new();
+        <Test as ProcessSnapshot>::process_cancellable(
+            &mut Test, snapshot.as_ref(), &cancel
+        ).unwrap();
+        <Test as ProcessDelta>::process_cancellable(
+            &mut Test, delta.as_ref(), &cancel
+        ).unwrap();
+        cancel.cancel();
+        assert!(matches!(
+            <Test as ProcessSnapshot>::process_cancellable(
+                &mut Test, snapshot.as_ref(), &cancel
+            ),
+            Err(ProcessError::Cancelled)
+        ));
+        assert!(matches!(
+            <Test as ProcessDelta>::process_cancellable(
+                &mut Test, delta.as_ref(), &cancel
+            ),
+            Err(ProcessError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn process_with_progress() {
+        use crate::progress::ProgressCounter;
+
+        let counter = ProgressCounter::new();
+        <Test as ProcessSnapshot>::process_with_progress(
+            &mut Test,
+            include_bytes!("../test-data/rrdp/ripe-snapshot.xml").as_ref(),
+            "https://rrdp.ripe.net/notification.xml", &counter
+        ).unwrap();
+        let snapshot = Snapshot::parse(
+            include_bytes!("../test-data/rrdp/ripe-snapshot.xml").as_ref()
+        ).unwrap();
+        assert_eq!(counter.objects(), snapshot.elements().len() as u64);
+    }
+
     #[test]
     fn hash_to_hash() {
         use std::str::FromStr;
@@ -1695,6 +2177,28 @@ mod test {
         assert_eq!(notification, notification_parsed);
     }
 
+    #[test]
+    fn notification_lenient() {
+        let xml = r#"<notification xmlns="http://www.ripe.net/rpki/rrdp"
+              version="1" serial="2"
+              session_id="a2d845c4-5b91-4015-a2b7-988c03ce232a">
+            <snapshot uri="https://example.com/snapshot.xml"
+              hash="C047E305FE71F2936720948E129A14C0819DED9CDECF31CFAF02C71200EB6F7C"/>
+            <future><more>text</more></future>
+            <delta serial="2" uri="https://example.com/delta.xml"
+              hash="FA2BDCE6B32DDF7F61F91B4549ABC61B6D6986FA91061B37C72F045FA1B7BA79"/>
+        </notification>"#;
+
+        assert!(NotificationFile::parse(xml.as_bytes()).is_err());
+        let (notification, unknown) = NotificationFile::parse_lenient(
+            xml.as_bytes()
+        ).unwrap();
+        assert_eq!(notification.serial(), 2);
+        assert_eq!(notification.deltas().len(), 1);
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].local(), "future");
+    }
+
     #[test]
     fn snapshot_from_to_xml() {
         let data = include_bytes!("../test-data/rrdp/ripe-snapshot.xml");
@@ -1776,4 +2280,85 @@ mod test {
             )
         );
     }
+
+    #[test]
+    fn snapshot_content_whitespace() {
+        let snapshot = b"<snapshot version=\"1\" \
+            session_id=\"a2d845c4-5b91-4015-a2b7-988c03ce232a\" \
+            serial=\"1742\" xmlns=\"http://www.ripe.net/rpki/rrdp\">\r\n\
+            <publish uri=\"rsync://example.com/some/path\">\r\n\
+            \tZm9v\r\n  IGJh\rcg\n\no=\t\r\n\
+            </publish></snapshot>";
+        let snapshot = Snapshot::parse(&mut snapshot.as_ref()).unwrap();
+        assert_eq!(snapshot.elements.len(), 1);
+        assert_eq!(snapshot.elements[0].data().as_ref(), b"foo bar\n");
+    }
+
+    #[test]
+    fn snapshot_content_invalid() {
+        let snapshot = br#"
+            <snapshot version="1"
+                session_id="a2d845c4-5b91-4015-a2b7-988c03ce232a"
+                serial="1742"
+                xmlns="http://www.ripe.net/rpki/rrdp"
+            >
+                <publish uri="rsync://example.com/some/path">
+                    Zm9vIGJhcgo=
+                </publish>
+                <publish uri="rsync://example.com/some/bad">
+                    Zm9v IGJh*cgo=
+                </publish>
+            </snapshot>
+        "#;
+        let err = match Snapshot::parse(&mut snapshot.as_ref()) {
+            Err(ProcessError::Io(err)) => err,
+            _ => panic!("expected IO error"),
+        };
+        let err = err.get_ref().unwrap().downcast_ref::<ContentError>();
+        assert_eq!(
+            err.unwrap().uri().as_str(), "rsync://example.com/some/bad"
+        );
+        assert!(
+            err.unwrap().to_string().contains("rsync://example.com/some/bad")
+        );
+    }
+
+    /// Returns pseudo-random data for creating arbitrary values.
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_data(seed: u64, len: usize) -> Vec<u8> {
+        // A simple xorshift generator is good enough for this.
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary")]
+    fn arbitrary_files_round_trip() {
+        use arbitrary::Unstructured;
+
+        for seed in 1..100 {
+            let data = arbitrary_data(seed, 4096);
+            let mut u = Unstructured::new(&data);
+
+            let notify: NotificationFile = u.arbitrary().unwrap();
+            let mut xml = Vec::new();
+            notify.write_xml(&mut xml).unwrap();
+            assert_eq!(NotificationFile::parse(xml.as_ref()).unwrap(), notify);
+
+            let snapshot: Snapshot = u.arbitrary().unwrap();
+            let mut xml = Vec::new();
+            snapshot.write_xml(&mut xml).unwrap();
+            assert_eq!(Snapshot::parse(xml.as_ref()).unwrap(), snapshot);
+
+            let delta: Delta = u.arbitrary().unwrap();
+            let mut xml = Vec::new();
+            delta.write_xml(&mut xml).unwrap();
+            assert_eq!(Delta::parse(xml.as_ref()).unwrap(), delta);
+        }
+    }
 }
@@ -0,0 +1,536 @@
+//! Verifying BGPsec paths.
+//!
+//! BGPsec, defined in [RFC 8205], protects the AS path of a route by having
+//! each AS on the path sign the path so far together with the AS it sends
+//! the route to. The keys used for signing are published in the RPKI as
+//! router certificates and are distributed to routers via RTR as
+//! [`RouterKey`] payload.
+//!
+//! This module allows verifying the signatures of a `BGPsec_PATH`
+//! attribute using a set of router keys. The attribute is parsed into a
+//! [`BgpsecPath`] which can then be verified via [`BgpsecPath::verify`]
+//! with the router keys collected into a [`RouterKeySet`]. Only the
+//! algorithm suite defined in [RFC 8208], i.e., ECDSA with curve P-256 and
+//! SHA-256, is supported.
+//!
+//! [RFC 8205]: https://tools.ietf.org/html/rfc8205
+//! [RFC 8208]: https://tools.ietf.org/html/rfc8208
+
+use std::{error, fmt};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use bytes::Bytes;
+use crate::crypto::keys::{KeyIdentifier, PublicKey, PublicKeyFormat};
+use crate::crypto::signature::{BgpsecSignatureAlgorithm, Signature};
+use crate::resources::addr::Prefix;
+use crate::resources::asn::Asn;
+use super::payload::RouterKey;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The algorithm suite identifier for ECDSA P-256 with SHA-256.
+pub const ALGORITHM_SUITE_1: u8 = 1;
+
+/// The SAFI for unicast routes.
+const SAFI_UNICAST: u8 = 1;
+
+
+//------------ RouterKeySet --------------------------------------------------
+
+/// A set of router keys for verifying BGPsec paths.
+///
+/// Keys are indexed by their subject key identifier and AS number. Keys
+/// that aren’t ECDSA P-256 keys are ignored since they can’t be used with
+/// any supported algorithm suite.
+#[derive(Clone, Debug, Default)]
+pub struct RouterKeySet {
+    /// The keys by key identifier and AS number.
+    keys: HashMap<(KeyIdentifier, Asn), Vec<PublicKey>>,
+}
+
+impl RouterKeySet {
+    /// Creates a new, empty key set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a router key to the set.
+    ///
+    /// Returns whether the key was added. This is not the case if its key
+    /// info isn’t a valid ECDSA P-256 subject public key info.
+    pub fn insert(&mut self, key: &RouterKey) -> bool {
+        let public_key = match PublicKey::decode(key.key_info.as_slice()) {
+            Ok(public_key) => public_key,
+            Err(_) => return false
+        };
+        if public_key.algorithm() != PublicKeyFormat::EcdsaP256 {
+            return false
+        }
+        self.keys.entry(
+            (key.key_identifier, key.asn)
+        ).or_default().push(public_key);
+        true
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the keys for the given key identifier and AS number.
+    fn get(&self, ski: &KeyIdentifier, asn: Asn) -> &[PublicKey] {
+        self.keys.get(&(*ski, asn)).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+impl<'a> FromIterator<&'a RouterKey> for RouterKeySet {
+    fn from_iter<I: IntoIterator<Item = &'a RouterKey>>(iter: I) -> Self {
+        let mut res = Self::new();
+        for key in iter {
+            res.insert(key);
+        }
+        res
+    }
+}
+
+
+//------------ BgpsecPath ----------------------------------------------------
+
+/// The content of a `BGPsec_PATH` attribute.
+///
+/// Both the secure path segments and the signature segments are kept in
+/// the order they appear in the attribute, i.e., starting with the most
+/// recently added AS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BgpsecPath {
+    /// The segments of the secure path.
+    secure_path: Vec<SecurePathSegment>,
+
+    /// The signature blocks.
+    blocks: Vec<SignatureBlock>,
+}
+
+impl BgpsecPath {
+    /// Parses the value of a `BGPsec_PATH` attribute.
+    ///
+    /// The data must contain the attribute value only, i.e., without the
+    /// flags, type code, and length of the path attribute.
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let mut data = Cursor(data);
+        let path_len = usize::from(data.u16()?);
+        if path_len < 2 || (path_len - 2) % 6 != 0 {
+            return Err(ParseError("invalid Secure_Path length"))
+        }
+        let mut path = Cursor(data.take(path_len - 2)?);
+        let mut secure_path = Vec::new();
+        while !path.is_empty() {
+            secure_path.push(SecurePathSegment {
+                pcount: path.u8()?,
+                flags: path.u8()?,
+                asn: Asn::from_u32(path.u32()?),
+            });
+        }
+        if secure_path.is_empty() {
+            return Err(ParseError("empty Secure_Path"))
+        }
+
+        let mut blocks = Vec::new();
+        while !data.is_empty() {
+            let block_len = usize::from(data.u16()?);
+            if block_len < 3 {
+                return Err(ParseError("invalid Signature_Block length"))
+            }
+            let mut block = Cursor(data.take(block_len - 2)?);
+            let suite = block.u8()?;
+            let mut segments = Vec::new();
+            while !block.is_empty() {
+                let ski = KeyIdentifier::try_from(block.take(20)?).map_err(
+                    |_| ParseError("invalid SKI")
+                )?;
+                let sig_len = usize::from(block.u16()?);
+                segments.push(SignatureSegment {
+                    ski,
+                    signature: Bytes::copy_from_slice(block.take(sig_len)?),
+                });
+            }
+            blocks.push(SignatureBlock { suite, segments });
+        }
+        if blocks.is_empty() || blocks.len() > 2 {
+            return Err(ParseError("invalid number of Signature_Blocks"))
+        }
+        Ok(BgpsecPath { secure_path, blocks })
+    }
+
+    /// Returns the secure path segments, starting with the most recent.
+    pub fn secure_path(&self) -> &[SecurePathSegment] {
+        &self.secure_path
+    }
+
+    /// Returns the signature blocks.
+    pub fn signature_blocks(&self) -> &[SignatureBlock] {
+        &self.blocks
+    }
+
+    /// Verifies the signatures of the path.
+    ///
+    /// The path is verified for a route for `prefix` received by the AS
+    /// `target`, i.e., the AS performing the verification. All signatures
+    /// of the first signature block with a supported algorithm suite are
+    /// verified as described in section 5.2 of RFC 8205. The signature of
+    /// each segment is accepted if it verifies with any of the keys in
+    /// `keys` for the segment’s key identifier and AS number.
+    pub fn verify(
+        &self, prefix: Prefix, target: Asn, keys: &RouterKeySet
+    ) -> Result<(), VerifyError> {
+        let block = self.blocks.iter().find(|block| {
+            block.suite == ALGORITHM_SUITE_1
+        }).ok_or(VerifyError::UnsupportedAlgorithm)?;
+        if block.segments.len() != self.secure_path.len() {
+            return Err(VerifyError::SegmentCount)
+        }
+        let mut message = Vec::new();
+        for (index, segment) in block.segments.iter().enumerate() {
+            let asn = self.secure_path[index].asn;
+            let target = match index {
+                0 => target,
+                _ => self.secure_path[index - 1].asn,
+            };
+            self.signed_data(block, index, target, prefix, &mut message);
+            let candidates = keys.get(&segment.ski, asn);
+            if candidates.is_empty() {
+                return Err(VerifyError::UnknownKey { index })
+            }
+            let signature = Signature::new(
+                BgpsecSignatureAlgorithm::default(), segment.signature.clone()
+            );
+            if !candidates.iter().any(|key| {
+                key.verify(&message, &signature).is_ok()
+            }) {
+                return Err(VerifyError::BadSignature { index })
+            }
+        }
+        Ok(())
+    }
+
+    /// Constructs the data signed by the segment at the given index.
+    ///
+    /// This is the sequence shown in figure 8 of RFC 8205.
+    fn signed_data(
+        &self,
+        block: &SignatureBlock,
+        index: usize,
+        target: Asn,
+        prefix: Prefix,
+        target_buf: &mut Vec<u8>,
+    ) {
+        target_buf.clear();
+        target_buf.extend_from_slice(&target.into_u32().to_be_bytes());
+        let last = self.secure_path.len() - 1;
+        for i in index..last {
+            block.segments[i + 1].append_to(target_buf);
+            self.secure_path[i].append_to(target_buf);
+        }
+        self.secure_path[last].append_to(target_buf);
+        target_buf.push(block.suite);
+        let (afi, octets) = match prefix.addr() {
+            std::net::IpAddr::V4(addr) => (1u16, addr.octets().to_vec()),
+            std::net::IpAddr::V6(addr) => (2u16, addr.octets().to_vec()),
+        };
+        target_buf.extend_from_slice(&afi.to_be_bytes());
+        target_buf.push(SAFI_UNICAST);
+        target_buf.push(prefix.len());
+        target_buf.extend_from_slice(
+            &octets[..(usize::from(prefix.len()) + 7) / 8]
+        );
+    }
+}
+
+
+//------------ SecurePathSegment ---------------------------------------------
+
+/// A segment of the secure path.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SecurePathSegment {
+    /// The number of repetitions of the AS number.
+    pub pcount: u8,
+
+    /// The flags of the segment.
+    pub flags: u8,
+
+    /// The AS number.
+    pub asn: Asn,
+}
+
+impl SecurePathSegment {
+    fn append_to(&self, target: &mut Vec<u8>) {
+        target.push(self.pcount);
+        target.push(self.flags);
+        target.extend_from_slice(&self.asn.into_u32().to_be_bytes());
+    }
+}
+
+
+//------------ SignatureBlock ------------------------------------------------
+
+/// A signature block of a BGPsec path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignatureBlock {
+    /// The algorithm suite identifier.
+    pub suite: u8,
+
+    /// The signature segments, starting with the most recent.
+    pub segments: Vec<SignatureSegment>,
+}
+
+
+//------------ SignatureSegment ----------------------------------------------
+
+/// A signature segment of a BGPsec path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignatureSegment {
+    /// The subject key identifier of the signing router key.
+    pub ski: KeyIdentifier,
+
+    /// The signature.
+    pub signature: Bytes,
+}
+
+impl SignatureSegment {
+    fn append_to(&self, target: &mut Vec<u8>) {
+        target.extend_from_slice(self.ski.as_slice());
+        target.extend_from_slice(
+            &u16::try_from(self.signature.len()).unwrap_or(u16::MAX)
+                .to_be_bytes()
+        );
+        target.extend_from_slice(&self.signature);
+    }
+}
+
+
+//------------ Cursor --------------------------------------------------------
+
+/// A cursor over a slice of octets.
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        if self.0.len() < len {
+            return Err(ParseError("short attribute"))
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        self.take(1).map(|data| data[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        self.take(2).map(|data| u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        self.take(4).map(|data| {
+            u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+        })
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ ParseError ----------------------------------------------------
+
+/// A `BGPsec_PATH` attribute is malformed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseError(&'static str);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid BGPsec_PATH attribute: {}", self.0)
+    }
+}
+
+impl error::Error for ParseError { }
+
+
+//------------ VerifyError ---------------------------------------------------
+
+/// Verifying a BGPsec path has failed.
+///
+/// Segments are identified by their index in the attribute, i.e., index
+/// 0 is the segment of the most recently added AS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerifyError {
+    /// None of the signature blocks uses a supported algorithm suite.
+    UnsupportedAlgorithm,
+
+    /// The number of signature segments differs from the path length.
+    SegmentCount,
+
+    /// There is no router key for a signature segment.
+    UnknownKey { index: usize },
+
+    /// The signature of a segment doesn’t verify.
+    BadSignature { index: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VerifyError::UnsupportedAlgorithm => {
+                f.write_str("no supported algorithm suite")
+            }
+            VerifyError::SegmentCount => {
+                f.write_str("number of signatures doesn’t match path length")
+            }
+            VerifyError::UnknownKey { index } => {
+                write!(f, "no router key for segment {}", index)
+            }
+            VerifyError::BadSignature { index } => {
+                write!(f, "invalid signature for segment {}", index)
+            }
+        }
+    }
+}
+
+impl error::Error for VerifyError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bcder::{BitString, Mode};
+    use bcder::encode::{self, PrimitiveContent, Values};
+    use ring::rand::SystemRandom;
+    use ring::signature::{
+        EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING
+    };
+    use crate::rtr::pdu::RouterKeyInfo;
+    use super::*;
+
+    struct Router {
+        key: EcdsaKeyPair,
+        ski: KeyIdentifier,
+        info: RouterKey,
+    }
+
+    fn router(asn: u32) -> Router {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING, &rng
+        ).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()
+        ).unwrap();
+        let spki = encode::sequence((
+            PublicKeyFormat::EcdsaP256.encode(),
+            BitString::new(
+                0, Bytes::copy_from_slice(key.public_key().as_ref())
+            ).encode()
+        )).to_captured(Mode::Der).into_bytes();
+        let ski = PublicKey::decode(spki.as_ref()).unwrap().key_identifier();
+        let info = RouterKey::new(
+            ski, Asn::from_u32(asn), RouterKeyInfo::new(spki).unwrap()
+        );
+        Router { key, ski, info }
+    }
+
+    /// Builds a path by having each router sign in turn.
+    ///
+    /// The routers are given starting with the origin. The path is sent to
+    /// `target` by the last router.
+    fn build_path(
+        routers: &[&Router], prefix: Prefix, target: Asn
+    ) -> BgpsecPath {
+        let rng = SystemRandom::new();
+        let mut path = BgpsecPath {
+            secure_path: Vec::new(),
+            blocks: vec![SignatureBlock {
+                suite: ALGORITHM_SUITE_1, segments: Vec::new()
+            }],
+        };
+        for (i, router) in routers.iter().enumerate() {
+            path.secure_path.insert(0, SecurePathSegment {
+                pcount: 1, flags: 0, asn: router.info.asn
+            });
+            path.blocks[0].segments.insert(0, SignatureSegment {
+                ski: router.ski, signature: Bytes::new()
+            });
+            let next = routers.get(i + 1).map(|next| {
+                next.info.asn
+            }).unwrap_or(target);
+            let mut message = Vec::new();
+            path.signed_data(&path.blocks[0], 0, next, prefix, &mut message);
+            path.blocks[0].segments[0].signature = Bytes::copy_from_slice(
+                router.key.sign(&rng, &message).unwrap().as_ref()
+            );
+        }
+        path
+    }
+
+    fn encode_path(path: &BgpsecPath) -> Vec<u8> {
+        let mut res = Vec::new();
+        let len = 2 + 6 * path.secure_path.len();
+        res.extend_from_slice(&(len as u16).to_be_bytes());
+        for segment in &path.secure_path {
+            segment.append_to(&mut res);
+        }
+        for block in &path.blocks {
+            let mut data = vec![block.suite];
+            for segment in &block.segments {
+                segment.append_to(&mut data);
+            }
+            res.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+            res.extend_from_slice(&data);
+        }
+        res
+    }
+
+    #[test]
+    fn verify() {
+        let origin = router(64496);
+        let transit = router(64497);
+        let prefix = Prefix::from_str("192.0.2.0/24").unwrap();
+        let target = Asn::from_u32(64498);
+        let keys: RouterKeySet = [&origin.info, &transit.info].into_iter()
+            .collect();
+
+        let path = build_path(&[&origin, &transit], prefix, target);
+        let parsed = BgpsecPath::parse(&encode_path(&path)).unwrap();
+        assert_eq!(parsed, path);
+        assert_eq!(parsed.secure_path()[0].asn, Asn::from_u32(64497));
+        parsed.verify(prefix, target, &keys).unwrap();
+
+        assert_eq!(
+            parsed.verify(prefix, Asn::from_u32(64499), &keys),
+            Err(VerifyError::BadSignature { index: 0 })
+        );
+        assert_eq!(
+            parsed.verify(
+                Prefix::from_str("192.0.2.0/25").unwrap(), target, &keys
+            ),
+            Err(VerifyError::BadSignature { index: 0 })
+        );
+        let only_transit: RouterKeySet = [&transit.info].into_iter()
+            .collect();
+        assert_eq!(
+            parsed.verify(prefix, target, &only_transit),
+            Err(VerifyError::UnknownKey { index: 1 })
+        );
+
+        let mut data = encode_path(&path);
+        data.truncate(data.len() - 1);
+        assert!(BgpsecPath::parse(&data).is_err());
+        assert!(BgpsecPath::parse(&[0, 2, 0, 3, 2]).is_err());
+    }
+}
@@ -276,7 +276,7 @@ where
         self.sock.flush().await?;
         let start = match self.try_io(FirstReply::read).await? {
             FirstReply::Response(start) => start,
-            FirstReply::Reset(_) => {
+            FirstReply::Reset => {
                 self.state = None;
                 return Ok(None)
             }
@@ -442,7 +442,7 @@ enum FirstReply {
     Response(pdu::CacheResponse),
 
     /// A reset response. We need to retry with a reset query.
-    Reset(pdu::CacheReset),
+    Reset,
 }
 
 impl FirstReply {
@@ -463,7 +463,7 @@ impl FirstReply {
             pdu::CacheReset::PDU => {
                 pdu::CacheReset::read_payload(
                     header, sock
-                ).await.map(FirstReply::Reset)
+                ).await.map(|_| FirstReply::Reset)
             }
             pdu::Error::PDU => {
                 Err(io::Error::new(
@@ -486,6 +486,7 @@ impl FirstReply {
 
 /// A received payload update was not acceptable.
 #[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
 pub enum PayloadError {
     /// A nonexisting record was withdrawn.
     UnknownWithdraw,
@@ -33,10 +33,13 @@ pub use self::client::Client;
 pub use self::payload::{Action, Payload, PayloadRef, PayloadType, Timing};
 pub use self::server::Server;
 pub use self::state::{State, Serial};
+pub use self::vrp::{RouteValidity, VrpSet};
 
+pub mod bgpsec;
 pub mod client;
 pub mod payload;
 pub mod state;
 pub mod server;
+pub mod vrp;
 
 pub mod pdu;
@@ -13,6 +13,7 @@ use std::time::Duration;
 use crate::crypto::keys::KeyIdentifier;
 use crate::resources::addr::MaxLenPrefix;
 use crate::resources::asn::Asn;
+use super::pdu;
 use super::pdu::{ProviderAsns, RouterKeyInfo};
 
 
@@ -119,6 +120,14 @@ impl RouterKey {
     ) -> Self {
         RouterKey { key_identifier, asn, key_info }
     }
+
+    /// Returns the Router Key PDU for the router key.
+    pub fn to_pdu(&self, version: u8, flags: u8) -> pdu::RouterKey {
+        pdu::RouterKey::new(
+            version, flags, self.key_identifier.into(), self.asn,
+            self.key_info.clone()
+        )
+    }
 }
 
 
@@ -204,7 +213,7 @@ impl Payload {
     }
 
     /// Converts a reference to payload into a payload reference.
-    pub fn as_ref(&self) -> PayloadRef {
+    pub fn as_ref(&self) -> PayloadRef<'_> {
         match self {
             Payload::Origin(origin) => PayloadRef::Origin(*origin),
             Payload::RouterKey(key) => PayloadRef::RouterKey(key),
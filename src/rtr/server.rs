@@ -15,6 +15,7 @@ use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::sync::broadcast;
 use tokio::task::spawn;
 use tokio_stream::{Stream, StreamExt};
+use crate::metrics::{SharedMetrics, names};
 use super::pdu;
 use super::payload::{Action, PayloadRef, Timing};
 use super::state::State;
@@ -77,13 +78,13 @@ pub trait PayloadSource: Clone + Sync + Send + 'static {
 /// A type providing access to a complete payload set.
 pub trait PayloadSet: Sync + Send + 'static {
     /// Returns the next element in the payload set.
-    fn next(&mut self) -> Option<PayloadRef>;
+    fn next(&mut self) -> Option<PayloadRef<'_>>;
 }
 
 /// A type providing access to a diff between payload sets.
 pub trait PayloadDiff: Sync + Send + 'static {
     /// Returns the next element in the diff.
-    fn next(&mut self) -> Option<(PayloadRef, Action)>;
+    fn next(&mut self) -> Option<(PayloadRef<'_>, Action)>;
 }
 
 
@@ -128,6 +129,9 @@ pub struct Server<Listener, Source> {
 
     /// The source of VRPs.
     source: Source,
+
+    /// The metrics to report to.
+    metrics: SharedMetrics,
 }
 
 impl<Listener, Source> Server<Listener, Source> {
@@ -135,7 +139,12 @@ impl<Listener, Source> Server<Listener, Source> {
     pub fn new(
         listener: Listener, notify: NotifySender, source: Source
     ) -> Self {
-        Server { listener, notify, source }
+        Server { listener, notify, source, metrics: Default::default() }
+    }
+
+    /// Sets the metrics the server and its connections report to.
+    pub fn set_metrics(&mut self, metrics: SharedMetrics) {
+        self.metrics = metrics
     }
 
     /// Runs the server.
@@ -150,9 +159,12 @@ impl<Listener, Source> Server<Listener, Source> {
         Source: PayloadSource,
     {
         while let Some(sock) = self.listener.next().await {
+            let sock = sock?;
+            self.metrics.counter(names::RTR_CONNECTIONS, &[], 1);
             spawn(
                 Connection::new(
-                    sock?, self.notify.subscribe(), self.source.clone()
+                    sock, self.notify.subscribe(), self.source.clone(),
+                    self.metrics.clone(),
                 ).run()
             );
         }
@@ -174,6 +186,9 @@ struct Connection<Sock, Source> {
     /// The VRP source.
     source: Source,
 
+    /// The metrics to report to.
+    metrics: SharedMetrics,
+
     /// The RTR protocol version this connection is using.
     ///
     /// This will start out as `None` and will only be set once the client
@@ -183,13 +198,23 @@ struct Connection<Sock, Source> {
 
 impl<Sock, Source> Connection<Sock, Source> {
     /// Wraps a socket into a connection value.
-    fn new(sock: Sock, notify: NotifyReceiver, source: Source) -> Self {
+    fn new(
+        sock: Sock, notify: NotifyReceiver, source: Source,
+        metrics: SharedMetrics,
+    ) -> Self {
         Connection {
-            sock, notify, source,
+            sock, notify, source, metrics,
             version: None,
         }
     }
 
+    /// Reports a response to the metrics.
+    fn record_response(&self, response: &str) {
+        self.metrics.counter(
+            names::RTR_RESPONSES, &[("response", response)], 1
+        );
+    }
+
     /// Returns the protocol version we agreed on.
     ///
     /// If there hasn’t been a negotation yet, returns the lowest protocol
@@ -381,7 +406,9 @@ impl<Sock: Socket, Source: PayloadSource> Connection<Sock, Source> {
     /// state. Only returns an error when the socket goes kaputt.
     async fn serial(&mut self, state: State) -> Result<(), io::Error> {
         debug!("RTR server: request for serial {}", state.serial());
+        self.metrics.counter(names::RTR_QUERIES, &[("query", "serial")], 1);
         if !self.source.ready() {
+            self.record_response("not_ready");
             return pdu::Error::new(
                 self.version(), 2, b"", b"Running initial validation"
             ).write(&mut self.sock).await;
@@ -392,23 +419,28 @@ impl<Sock: Socket, Source: PayloadSource> Connection<Sock, Source> {
                 pdu::CacheResponse::new(
                     self.version(), state,
                 ).write(&mut self.sock).await?;
+                let mut sent = 0;
                 while let Some((payload, action)) = diff.next() {
                     if let Some(pdu) = pdu::Payload::new_if_supported(
                         self.version(), action.into_flags(), payload
                     ) {
                         pdu.write(&mut self.sock).await?;
+                        sent += 1;
                     }
                 }
+                self.metrics.counter(names::RTR_PAYLOAD, &[], sent);
                 let timing = self.source.timing();
                 pdu::EndOfData::new(
                     self.version(), state, timing
                 ).write(&mut self.sock).await?;
                 self.sock.flush().await?;
                 self.sock.update(state, false);
+                self.record_response("diff");
                 Ok(())
             }
             None => {
                 debug!("RTR server: source ain't got no diff for that.");
+                self.record_response("cache_reset");
                 pdu::CacheReset::new(self.version()).write(
                     &mut self.sock
                 ).await
@@ -421,7 +453,9 @@ impl<Sock: Socket, Source: PayloadSource> Connection<Sock, Source> {
     /// Responds accordingly based on whether or not the source is ready.
     /// Only returns an error if writing to the socket fails.
     async fn reset(&mut self) -> Result<(), io::Error> {
+        self.metrics.counter(names::RTR_QUERIES, &[("query", "reset")], 1);
         if !self.source.ready() {
+            self.record_response("not_ready");
             return pdu::Error::new(
                 self.version(), 2, "", b"Running initial validation"
             ).write(&mut self.sock).await;
@@ -430,19 +464,23 @@ impl<Sock: Socket, Source: PayloadSource> Connection<Sock, Source> {
         pdu::CacheResponse::new(
             self.version(), state
         ).write(&mut self.sock).await?;
+        let mut sent = 0;
         while let Some(payload) = iter.next() {
             if let Some(pdu) = pdu::Payload::new_if_supported(
                 self.version(), Action::Announce.into_flags(), payload
             ) {
                 pdu.write(&mut self.sock).await?;
+                sent += 1;
             }
         }
+        self.metrics.counter(names::RTR_PAYLOAD, &[], sent);
         let timing = self.source.timing();
         pdu::EndOfData::new(
             self.version(), state, timing
         ).write(&mut self.sock).await?;
         self.sock.flush().await?;
         self.sock.update(state, true);
+        self.record_response("full");
         Ok(())
     }
 
@@ -0,0 +1,695 @@
+//! A set of validated ROA payloads.
+//!
+//! Route origins produced by validation pass through several stages before
+//! they reach a router: local exceptions may be applied, they are served
+//! via RTR, and they are exported in various formats. The [`VrpSet`]
+//! provides a common container for all these stages. It keeps route
+//! origins in their canonical order, allows finding all origins covering
+//! a prefix quickly, and can produce the difference to another set.
+//!
+//! Identical route origins are only kept once. When the same origin is
+//! produced from several sources, e.g., by several trust anchors, the set
+//! can remember all of them. It can also list the origins that
+//! [conflict][VrpSet::conflicts] with each other.
+//!
+//! If the `"serde"` feature is enabled, the set can be serialized as a
+//! sequence of objects with the members `"asn"`, `"prefix"`, and
+//! `"maxLength"`.
+
+use std::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::FromIterator;
+use crate::resources::addr::{MaxLenPrefix, Prefix};
+use crate::resources::asn::Asn;
+use super::payload::{Action, RouteOrigin};
+
+
+//------------ VrpSet --------------------------------------------------------
+
+/// A set of route origins.
+///
+/// The origins are kept in an ordered map keyed by their prefix which
+/// holds the maximum lengths and origin ASes for each prefix. The order of
+/// the map is only used for iterating in canonical order. To find the
+/// origins covering a prefix, the map is probed with the prefix truncated
+/// to each length from zero up to the prefix’s own length, i.e., with at
+/// most 33 lookups for IPv4 and 129 for IPv6 prefixes.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VrpSet {
+    /// The max-length and AS number of the origins keyed by prefix.
+    origins: BTreeMap<Prefix, BTreeSet<(u8, Asn)>>,
+
+    /// The number of origins in the set.
+    len: usize,
+
+    /// The sources of origins added with a source.
+    sources: BTreeMap<(Prefix, u8, Asn), Vec<String>>,
+}
+
+impl VrpSet {
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of route origins in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a route origin to the set.
+    ///
+    /// Returns whether the origin was not yet present.
+    pub fn insert(&mut self, origin: RouteOrigin) -> bool {
+        let res = self.origins.entry(origin.prefix.prefix()).or_default()
+            .insert((origin.prefix.resolved_max_len(), origin.asn));
+        if res {
+            self.len += 1;
+        }
+        res
+    }
+
+    /// Adds a route origin produced by the given source to the set.
+    ///
+    /// The source is a string identifying where the origin came from,
+    /// such as the name of a trust anchor. If the origin is already
+    /// present, the source is added to its list of sources.
+    ///
+    /// Returns whether the origin was not yet present.
+    pub fn insert_from(&mut self, origin: RouteOrigin, source: &str) -> bool {
+        let res = self.insert(origin);
+        let sources = self.sources.entry(Self::key(&origin)).or_default();
+        if !sources.iter().any(|item| item == source) {
+            sources.push(source.into());
+        }
+        res
+    }
+
+    /// Returns the sources of a route origin.
+    ///
+    /// The sources are returned in the order they were first added. Only
+    /// sources given to [`insert_from`][Self::insert_from] are known, so
+    /// the slice is empty for origins added without a source.
+    pub fn sources(&self, origin: &RouteOrigin) -> &[String] {
+        self.sources.get(&Self::key(origin)).map(|sources| {
+            sources.as_slice()
+        }).unwrap_or(&[])
+    }
+
+    /// Removes a route origin from the set.
+    ///
+    /// Returns whether the origin was present.
+    pub fn remove(&mut self, origin: &RouteOrigin) -> bool {
+        let prefix = origin.prefix.prefix();
+        let set = match self.origins.get_mut(&prefix) {
+            Some(set) => set,
+            None => return false
+        };
+        if !set.remove(&(origin.prefix.resolved_max_len(), origin.asn)) {
+            return false
+        }
+        if set.is_empty() {
+            self.origins.remove(&prefix);
+        }
+        self.sources.remove(&Self::key(origin));
+        self.len -= 1;
+        true
+    }
+
+    /// Returns whether the set contains the given route origin.
+    pub fn contains(&self, origin: &RouteOrigin) -> bool {
+        self.origins.get(&origin.prefix.prefix()).map(|set| {
+            set.contains(&(origin.prefix.resolved_max_len(), origin.asn))
+        }).unwrap_or(false)
+    }
+
+    /// Returns an iterator over the route origins in canonical order.
+    pub fn iter(&self) -> impl Iterator<Item = RouteOrigin> + '_ {
+        self.origins.iter().flat_map(|(prefix, set)| {
+            set.iter().map(move |&(max_len, asn)| {
+                Self::origin(*prefix, max_len, asn)
+            })
+        })
+    }
+
+    /// Returns all route origins whose prefix covers the given prefix.
+    ///
+    /// These are the origins relevant for route origin validation of a
+    /// route for `prefix` as defined in RFC 6811. The origins are returned
+    /// from the least specific to the most specific prefix.
+    pub fn lookup(&self, prefix: Prefix) -> Vec<RouteOrigin> {
+        self.covering(prefix).flat_map(|(covering, set)| {
+            set.iter().map(move |&(max_len, asn)| {
+                Self::origin(covering, max_len, asn)
+            })
+        }).collect()
+    }
+
+    /// Returns the route origin validation state of a route.
+    ///
+    /// The state is determined as described in section 2 of RFC 6811. A
+    /// route origin for AS 0 never matches a route.
+    pub fn validate(&self, prefix: Prefix, origin: Asn) -> RouteValidity {
+        let mut res = RouteValidity::NotFound;
+        for (_, set) in self.covering(prefix) {
+            for &(max_len, asn) in set {
+                if asn == origin && asn != Asn::from_u32(0)
+                    && prefix.len() <= max_len
+                {
+                    return RouteValidity::Valid
+                }
+            }
+            res = RouteValidity::Invalid;
+        }
+        res
+    }
+
+    /// Returns the validation state for each of the given routes.
+    ///
+    /// The routes are given as pairs of prefix and origin AS. The
+    /// returned iterator yields the state for each route in the same order
+    /// as the routes. Validation happens lazily and doesn’t allocate, so
+    /// this is suitable for validating the routes of full BGP tables.
+    pub fn validate_announcements<'a, I>(
+        &'a self, announcements: I
+    ) -> impl Iterator<Item = RouteValidity> + 'a
+    where I: IntoIterator<Item = (Prefix, Asn)>, I::IntoIter: 'a {
+        announcements.into_iter().map(move |(prefix, origin)| {
+            self.validate(prefix, origin)
+        })
+    }
+
+    /// Returns the origins of all prefixes covering the given prefix.
+    ///
+    /// The prefixes are returned from the least specific to the most
+    /// specific one.
+    fn covering(
+        &self, prefix: Prefix
+    ) -> impl Iterator<Item = (Prefix, &BTreeSet<(u8, Asn)>)> + '_ {
+        (0..=prefix.len()).filter_map(move |len| {
+            let covering = Prefix::new_relaxed(prefix.addr(), len).ok()?;
+            self.origins.get(&covering).map(|set| (covering, set))
+        })
+    }
+
+    /// Returns the conflicts between route origins in the set.
+    ///
+    /// Origins for the same prefix conflict if they authorize different
+    /// ASNs or different max-lengths for the same ASN. The conflicts are
+    /// returned in canonical order of their prefix. For each prefix, a
+    /// conflict of different ASNs is returned before the conflicts of
+    /// different max-lengths.
+    pub fn conflicts(&self) -> Vec<VrpConflict> {
+        let mut res = Vec::new();
+        for (prefix, set) in &self.origins {
+            if set.len() < 2 {
+                continue
+            }
+            let origins = || {
+                set.iter().map(|&(max_len, asn)| {
+                    Self::origin(*prefix, max_len, asn)
+                })
+            };
+            let asns: BTreeSet<_> = set.iter().map(|item| item.1).collect();
+            if asns.len() > 1 {
+                res.push(VrpConflict {
+                    kind: ConflictKind::Origin,
+                    prefix: *prefix,
+                    origins: origins().collect(),
+                });
+            }
+            for asn in asns {
+                let same: Vec<_> = origins().filter(|origin| {
+                    origin.asn == asn
+                }).collect();
+                if same.len() > 1 {
+                    res.push(VrpConflict {
+                        kind: ConflictKind::MaxLength,
+                        prefix: *prefix,
+                        origins: same,
+                    });
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the changes necessary to get from this set to `newer`.
+    ///
+    /// The diff includes the sources of all origins in `newer` whose
+    /// sources differ from those in this set.
+    pub fn diff(&self, newer: &VrpSet) -> VrpDiff {
+        VrpDiff {
+            announced: newer.iter().filter(|origin| {
+                !self.contains(origin)
+            }).collect(),
+            withdrawn: self.iter().filter(|origin| {
+                !newer.contains(origin)
+            }).collect(),
+            sources: newer.iter().filter_map(|origin| {
+                let sources = newer.sources(&origin);
+                if sources == self.sources(&origin) {
+                    None
+                }
+                else {
+                    Some((origin, sources.to_vec()))
+                }
+            }).collect(),
+        }
+    }
+
+    /// Applies a diff to the set.
+    ///
+    /// Applying the diff between two sets to the older set results in the
+    /// newer set, including the sources of the origins.
+    pub fn apply(&mut self, diff: &VrpDiff) {
+        for origin in &diff.withdrawn {
+            self.remove(origin);
+        }
+        for origin in &diff.announced {
+            self.insert(*origin);
+        }
+        for (origin, sources) in &diff.sources {
+            if !self.contains(origin) {
+                continue
+            }
+            if sources.is_empty() {
+                self.sources.remove(&Self::key(origin));
+            }
+            else {
+                self.sources.insert(Self::key(origin), sources.clone());
+            }
+        }
+    }
+
+    fn key(origin: &RouteOrigin) -> (Prefix, u8, Asn) {
+        (
+            origin.prefix.prefix(),
+            origin.prefix.resolved_max_len(),
+            origin.asn
+        )
+    }
+
+    fn origin(prefix: Prefix, max_len: u8, asn: Asn) -> RouteOrigin {
+        RouteOrigin::new(
+            MaxLenPrefix::saturating_new(prefix, Some(max_len)), asn
+        )
+    }
+}
+
+
+//--- FromIterator and Extend
+
+impl FromIterator<RouteOrigin> for VrpSet {
+    fn from_iter<I: IntoIterator<Item = RouteOrigin>>(iter: I) -> Self {
+        let mut res = Self::new();
+        res.extend(iter);
+        res
+    }
+}
+
+impl Extend<RouteOrigin> for VrpSet {
+    fn extend<I: IntoIterator<Item = RouteOrigin>>(&mut self, iter: I) {
+        for origin in iter {
+            self.insert(origin);
+        }
+    }
+}
+
+
+//--- Deserialize and Serialize
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for VrpSet {
+    fn serialize<S: serde::Serializer>(
+        &self, serializer: S
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(SerdeVrp::from))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for VrpSet {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<Self, D::Error> {
+        let items = <Vec<SerdeVrp> as serde::Deserialize>::deserialize(
+            deserializer
+        )?;
+        items.into_iter().map(|item| {
+            MaxLenPrefix::new(item.prefix, Some(item.max_length)).map(
+                |prefix| RouteOrigin::new(prefix, item.asn)
+            ).map_err(serde::de::Error::custom)
+        }).collect()
+    }
+}
+
+/// The serialized form of a single route origin.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerdeVrp {
+    #[serde(
+        deserialize_with = "Asn::deserialize_from_any",
+        serialize_with = "Asn::serialize_as_str",
+    )]
+    asn: Asn,
+    prefix: Prefix,
+    max_length: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<RouteOrigin> for SerdeVrp {
+    fn from(origin: RouteOrigin) -> Self {
+        SerdeVrp {
+            asn: origin.asn,
+            prefix: origin.prefix.prefix(),
+            max_length: origin.prefix.resolved_max_len(),
+        }
+    }
+}
+
+
+//------------ RouteValidity -------------------------------------------------
+
+/// The route origin validation state of a route.
+///
+/// See section 2 of RFC 6811 for the definition of the states.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RouteValidity {
+    /// At least one route origin matches the route.
+    Valid,
+
+    /// The route is covered by route origins but none of them matches.
+    Invalid,
+
+    /// No route origin covers the route.
+    NotFound,
+}
+
+impl fmt::Display for RouteValidity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            RouteValidity::Valid => "valid",
+            RouteValidity::Invalid => "invalid",
+            RouteValidity::NotFound => "not-found",
+        })
+    }
+}
+
+
+//------------ VrpConflict ---------------------------------------------------
+
+/// A set of route origins for the same prefix that conflict.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VrpConflict {
+    /// The kind of conflict.
+    pub kind: ConflictKind,
+
+    /// The prefix of all the conflicting origins.
+    pub prefix: Prefix,
+
+    /// The conflicting origins in canonical order.
+    pub origins: Vec<RouteOrigin>,
+}
+
+
+//------------ ConflictKind --------------------------------------------------
+
+/// The kind of a conflict between route origins.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ConflictKind {
+    /// The origins authorize different ASNs.
+    Origin,
+
+    /// The origins authorize the same ASN with different max-lengths.
+    MaxLength,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ConflictKind::Origin => "origin",
+            ConflictKind::MaxLength => "max-length",
+        })
+    }
+}
+
+
+//------------ VrpDiff -------------------------------------------------------
+
+/// The differences between two sets of route origins.
+///
+/// All lists are in canonical order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VrpDiff {
+    /// Route origins only present in the newer set.
+    pub announced: Vec<RouteOrigin>,
+
+    /// Route origins only present in the older set.
+    pub withdrawn: Vec<RouteOrigin>,
+
+    /// The sources of route origins whose sources have changed.
+    ///
+    /// This contains the complete list of sources in the newer set for
+    /// every origin of the newer set whose sources differ from the older
+    /// set, including newly announced origins. An empty list means the
+    /// origin has no sources anymore.
+    pub sources: Vec<(RouteOrigin, Vec<String>)>,
+}
+
+impl VrpDiff {
+    /// Returns whether there are no differences in the route origins.
+    ///
+    /// Changes to the sources only are not considered.
+    pub fn is_empty(&self) -> bool {
+        self.announced.is_empty() && self.withdrawn.is_empty()
+    }
+
+    /// Returns an iterator over the changes as RTR actions.
+    ///
+    /// Withdrawals are returned before announcements.
+    pub fn actions(
+        &self
+    ) -> impl Iterator<Item = (Action, RouteOrigin)> + '_ {
+        self.withdrawn.iter().map(|item| (Action::Withdraw, *item)).chain(
+            self.announced.iter().map(|item| (Action::Announce, *item))
+        )
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn origin(prefix: &str, max_len: Option<u8>, asn: u32) -> RouteOrigin {
+        RouteOrigin::new(
+            MaxLenPrefix::new(Prefix::from_str(prefix).unwrap(), max_len)
+                .unwrap(),
+            Asn::from_u32(asn)
+        )
+    }
+
+    fn prefix(s: &str) -> Prefix {
+        Prefix::from_str(s).unwrap()
+    }
+
+    fn sample() -> VrpSet {
+        [
+            origin("10.0.0.0/8", None, 64496),
+            origin("10.1.0.0/16", Some(24), 64497),
+            origin("10.1.0.0/16", Some(16), 64497),
+            origin("10.1.2.0/24", None, 64498),
+            origin("2001:db8::/32", Some(48), 64499),
+        ].into_iter().collect()
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut set = sample();
+        assert_eq!(set.len(), 5);
+        assert!(!set.insert(origin("10.0.0.0/8", Some(8), 64496)));
+        assert!(set.contains(&origin("10.1.0.0/16", None, 64497)));
+        assert!(set.remove(&origin("10.1.2.0/24", None, 64498)));
+        assert!(!set.remove(&origin("10.1.2.0/24", None, 64498)));
+        assert_eq!(set.len(), 4);
+
+        let items: Vec<_> = sample().iter().collect();
+        let mut sorted = items.clone();
+        sorted.sort();
+        assert_eq!(items, sorted);
+    }
+
+    #[test]
+    fn lookup() {
+        let set = sample();
+        assert_eq!(
+            set.lookup(prefix("10.1.2.0/24")),
+            [
+                origin("10.0.0.0/8", None, 64496),
+                origin("10.1.0.0/16", None, 64497),
+                origin("10.1.0.0/16", Some(24), 64497),
+                origin("10.1.2.0/24", None, 64498),
+            ]
+        );
+        assert_eq!(
+            set.lookup(prefix("10.2.0.0/16")),
+            [origin("10.0.0.0/8", None, 64496)]
+        );
+        assert!(set.lookup(prefix("192.0.2.0/24")).is_empty());
+        assert!(set.lookup(prefix("2001:db9::/32")).is_empty());
+        assert_eq!(set.lookup(prefix("2001:db8:1::/48")).len(), 1);
+    }
+
+    #[test]
+    fn validate() {
+        let mut set = sample();
+        set.insert(origin("192.0.2.0/24", None, 0));
+        let routes = [
+            (prefix("10.1.2.0/24"), Asn::from_u32(64497)),
+            (prefix("10.1.2.0/25"), Asn::from_u32(64497)),
+            (prefix("10.1.2.0/24"), Asn::from_u32(64498)),
+            (prefix("10.3.0.0/16"), Asn::from_u32(64497)),
+            (prefix("10.0.0.0/8"), Asn::from_u32(64496)),
+            (prefix("192.0.2.0/24"), Asn::from_u32(0)),
+            (prefix("198.51.100.0/24"), Asn::from_u32(64496)),
+            (prefix("2001:db8:1::/48"), Asn::from_u32(64499)),
+            (prefix("2001:db8:1::/64"), Asn::from_u32(64499)),
+        ];
+        let res: Vec<_> = set.validate_announcements(routes).collect();
+        assert_eq!(
+            res,
+            [
+                RouteValidity::Valid,
+                RouteValidity::Invalid,
+                RouteValidity::Valid,
+                RouteValidity::Invalid,
+                RouteValidity::Valid,
+                RouteValidity::Invalid,
+                RouteValidity::NotFound,
+                RouteValidity::Valid,
+                RouteValidity::Invalid,
+            ]
+        );
+        assert_eq!(RouteValidity::NotFound.to_string(), "not-found");
+    }
+
+    #[test]
+    fn diff() {
+        let old = sample();
+        let mut new = sample();
+        new.remove(&origin("10.0.0.0/8", None, 64496));
+        new.insert(origin("192.0.2.0/24", None, 64500));
+        let diff = old.diff(&new);
+        assert_eq!(diff.announced, [origin("192.0.2.0/24", None, 64500)]);
+        assert_eq!(diff.withdrawn, [origin("10.0.0.0/8", None, 64496)]);
+        assert!(old.diff(&old).is_empty());
+
+        let mut applied = old.clone();
+        applied.apply(&diff);
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn diff_sources() {
+        let mut old = VrpSet::new();
+        old.insert_from(origin("10.0.0.0/8", None, 64496), "ripe");
+        old.insert_from(origin("10.1.0.0/16", None, 64496), "ripe");
+        old.insert(origin("10.2.0.0/16", None, 64496));
+        let mut new = VrpSet::new();
+        new.insert_from(origin("10.0.0.0/8", None, 64496), "ripe");
+        new.insert_from(origin("10.1.0.0/16", None, 64496), "arin");
+        new.insert_from(origin("10.3.0.0/16", None, 64496), "arin");
+        new.insert_from(origin("10.2.0.0/16", None, 64496), "lacnic");
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.sources,
+            [
+                (origin("10.1.0.0/16", None, 64496), vec!["arin".into()]),
+                (origin("10.2.0.0/16", None, 64496), vec!["lacnic".into()]),
+                (origin("10.3.0.0/16", None, 64496), vec!["arin".into()]),
+            ]
+        );
+        let mut applied = old.clone();
+        applied.apply(&diff);
+        assert_eq!(applied, new);
+
+        // Removing and re-adding an origin keeps its sources.
+        let mut applied = new.clone();
+        applied.apply(&new.diff(&old));
+        assert_eq!(applied, old);
+        applied.apply(&old.diff(&new));
+        assert_eq!(applied, new);
+    }
+
+    #[test]
+    fn sources() {
+        let mut set = VrpSet::new();
+        assert!(set.insert_from(origin("10.0.0.0/8", None, 64496), "ripe"));
+        assert!(
+            !set.insert_from(origin("10.0.0.0/8", Some(8), 64496), "arin")
+        );
+        assert!(!set.insert_from(origin("10.0.0.0/8", None, 64496), "ripe"));
+        assert!(set.insert(origin("10.1.0.0/16", None, 64496)));
+        assert_eq!(set.len(), 2);
+        assert_eq!(
+            set.sources(&origin("10.0.0.0/8", None, 64496)), ["ripe", "arin"]
+        );
+        assert!(set.sources(&origin("10.1.0.0/16", None, 64496)).is_empty());
+        assert!(set.remove(&origin("10.0.0.0/8", None, 64496)));
+        assert!(set.sources(&origin("10.0.0.0/8", None, 64496)).is_empty());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut set = sample();
+        assert_eq!(
+            set.conflicts(),
+            [VrpConflict {
+                kind: ConflictKind::MaxLength,
+                prefix: prefix("10.1.0.0/16"),
+                origins: vec![
+                    origin("10.1.0.0/16", Some(16), 64497),
+                    origin("10.1.0.0/16", Some(24), 64497),
+                ]
+            }]
+        );
+        set.insert(origin("10.1.0.0/16", None, 64500));
+        set.remove(&origin("10.1.0.0/16", Some(24), 64497));
+        assert_eq!(
+            set.conflicts(),
+            [VrpConflict {
+                kind: ConflictKind::Origin,
+                prefix: prefix("10.1.0.0/16"),
+                origins: vec![
+                    origin("10.1.0.0/16", Some(16), 64497),
+                    origin("10.1.0.0/16", Some(16), 64500),
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        let set = sample();
+        let json = serde_json::to_string(&set).unwrap();
+        assert!(json.starts_with(
+            r#"[{"asn":"AS64498","prefix":"10.1.2.0/24","maxLength":24}"#
+        ));
+        assert_eq!(serde_json::from_str::<VrpSet>(&json).unwrap(), set);
+        assert!(serde_json::from_str::<VrpSet>(
+            r#"[{"asn":1,"prefix":"10.0.0.0/8","maxLength":7}]"#
+        ).is_err());
+    }
+}
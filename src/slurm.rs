@@ -827,7 +827,14 @@ impl fmt::Display for ParseBase64KeyInfoError {
     }
 }
 
-impl error::Error for ParseBase64KeyInfoError { }
+impl error::Error for ParseBase64KeyInfoError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ParseBase64KeyInfoError::Base64(ref inner) => Some(inner),
+            ParseBase64KeyInfoError::KeyInfo(ref inner) => Some(inner),
+        }
+    }
+}
 
 
 //============ Tests =========================================================
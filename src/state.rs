@@ -0,0 +1,474 @@
+//! Persisting the state of a relying party.
+//!
+//! A relying party that is restarted normally has to fetch all
+//! repositories and validate everything from scratch before it can serve
+//! route origins again. This takes several minutes. Most of the state it
+//! had before the restart is still perfectly good, though: the RRDP
+//! sessions and serial numbers it was at, the validators for conditional
+//! requests of notification files, the cached validation results, and the
+//! route origins it served.
+//!
+//! An [`RpState`] collects all of this into a single value that can be
+//! written to a file when shutting down or periodically and read back on
+//! start. The state is captured from the various components via the
+//! `capture_*` methods and restored into fresh components via the
+//! `restore_*` methods. Objects themselves are not part of the state. They
+//! are kept in the object store, which needs to be persistent itself, for
+//! instance an [`FsStore`][crate::store::fs::FsStore].
+//!
+//! The state is serialized as JSON carrying a format version. Reading a
+//! state with a version other than [`RpState::VERSION`] fails with
+//! [`StateError::UnsupportedVersion`], in which case a relying party
+//! should simply start from scratch.
+//!
+//! The module is only available if the `"state"` feature is enabled.
+
+#![cfg(feature = "state")]
+
+use std::{error, fmt, io};
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+use crate::uri;
+use crate::clock::{Clock, SystemClock};
+use crate::fetch::access::{RepositoryAccess, RrdpStatus};
+use crate::fetch::https::{HttpsFetcher, Validators};
+use crate::repository::cache::{CacheEntry, ValidationCache};
+use crate::rtr::vrp::VrpSet;
+
+
+//------------ RpState -------------------------------------------------------
+
+/// The persistable state of a relying party.
+///
+/// The type argument is the type of the values kept in the validation
+/// cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RpState<T> {
+    /// The time the state was captured.
+    created: DateTime<Utc>,
+
+    /// The status of the RRDP repositories, keyed by notification URI.
+    rrdp: HashMap<uri::Https, RrdpStatus>,
+
+    /// The validators for conditional requests.
+    validators: HashMap<uri::Https, Validators>,
+
+    /// The entries of the validation cache.
+    cache: Vec<CacheEntry<T>>,
+
+    /// The validated route origins.
+    vrps: VrpSet,
+}
+
+impl<T> Default for RpState<T> {
+    fn default() -> Self {
+        Self::new_at(SystemClock.now())
+    }
+}
+
+impl<T> RpState<T> {
+    /// The current version of the serialized format.
+    pub const VERSION: u32 = 1;
+
+    /// Creates a new, empty state created now.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new, empty state created at the given time.
+    ///
+    /// Use this with the time of a [`Clock`] if the state shouldn’t use
+    /// the system time.
+    pub fn new_at(created: DateTime<Utc>) -> Self {
+        RpState {
+            created,
+            rrdp: HashMap::new(),
+            validators: HashMap::new(),
+            cache: Vec::new(),
+            vrps: VrpSet::new(),
+        }
+    }
+
+    /// Returns the time the state was created.
+    ///
+    /// For a state read back from storage, this is the time it was
+    /// originally created.
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    /// Returns the status of the RRDP repositories.
+    pub fn rrdp(
+        &self
+    ) -> impl Iterator<Item = (&uri::Https, &RrdpStatus)> + '_ {
+        self.rrdp.iter()
+    }
+
+    /// Returns the validators for conditional requests.
+    pub fn validators(
+        &self
+    ) -> impl Iterator<Item = (&uri::Https, &Validators)> + '_ {
+        self.validators.iter()
+    }
+
+    /// Returns the entries of the validation cache.
+    pub fn cache_entries(&self) -> &[CacheEntry<T>] {
+        &self.cache
+    }
+
+    /// Returns the validated route origins.
+    pub fn vrps(&self) -> &VrpSet {
+        &self.vrps
+    }
+
+    /// Sets the validated route origins.
+    pub fn set_vrps(&mut self, vrps: VrpSet) {
+        self.vrps = vrps
+    }
+
+    /// Captures the status of all RRDP repositories.
+    pub fn capture_access(&mut self, access: &RepositoryAccess) {
+        self.rrdp = access.iter().map(|(uri, status)| {
+            (uri.clone(), status.clone())
+        }).collect();
+    }
+
+    /// Restores the status of all RRDP repositories.
+    pub fn restore_access(&self, access: &mut RepositoryAccess) {
+        for (uri, status) in &self.rrdp {
+            access.set_status(uri.clone(), status.clone())
+        }
+    }
+
+    /// Captures the validators of an HTTPS fetcher.
+    pub fn capture_fetcher<F>(&mut self, fetcher: &HttpsFetcher<F>) {
+        self.validators = fetcher.all_validators().into_iter().collect();
+    }
+
+    /// Restores the validators of an HTTPS fetcher.
+    pub fn restore_fetcher<F>(&self, fetcher: &HttpsFetcher<F>) {
+        for (uri, validators) in &self.validators {
+            fetcher.set_validators(uri.clone(), Some(validators.clone()))
+        }
+    }
+}
+
+impl<T: Clone> RpState<T> {
+    /// Captures the entries of a validation cache.
+    ///
+    /// Only entries that haven’t expired yet according to the cache’s
+    /// clock are captured.
+    pub fn capture_cache(&mut self, cache: &ValidationCache<T>) {
+        self.cache = cache.entries(cache.now());
+    }
+
+    /// Restores the entries of a validation cache.
+    ///
+    /// Entries that have expired since the state was captured according to
+    /// the cache’s clock are dropped.
+    pub fn restore_cache(&self, cache: &ValidationCache<T>) {
+        let now = cache.now();
+        cache.extend(
+            self.cache.iter().filter(|entry| entry.expires > now).cloned()
+        )
+    }
+}
+
+impl<T: Serialize> RpState<T> {
+    /// Writes the state to a writer.
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<(), StateError> {
+        let mut rrdp: Vec<_> = self.rrdp.iter().map(|(uri, status)| {
+            SerdeRrdp::new(uri, status)
+        }).collect();
+        rrdp.sort_by(|left, right| {
+            left.notify.as_str().cmp(right.notify.as_str())
+        });
+        let mut validators: Vec<_> = self.validators.iter().map(
+            |(uri, validators)| SerdeValidators {
+                uri: uri.clone(),
+                etag: validators.etag.clone(),
+                last_modified: validators.last_modified.clone(),
+            }
+        ).collect();
+        validators.sort_by(|left, right| {
+            left.uri.as_str().cmp(right.uri.as_str())
+        });
+        serde_json::to_writer(writer, &SerdeState {
+            version: Self::VERSION,
+            created: self.created,
+            rrdp,
+            validators,
+            cache: &self.cache,
+            vrps: &self.vrps,
+        }).map_err(StateError::from_json)
+    }
+}
+
+impl<T: DeserializeOwned> RpState<T> {
+    /// Reads a state from a reader.
+    pub fn read<R: io::Read>(mut reader: R) -> Result<Self, StateError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let version = serde_json::from_slice::<SerdeVersion>(
+            &data
+        ).map_err(StateError::from_json)?.version;
+        if version != Self::VERSION {
+            return Err(StateError::UnsupportedVersion(version))
+        }
+        let state = serde_json::from_slice::<SerdeState<
+            Vec<CacheEntry<T>>, VrpSet
+        >>(&data).map_err(StateError::from_json)?;
+        let mut rrdp = HashMap::new();
+        for item in state.rrdp {
+            let (uri, status) = item.into_status()?;
+            rrdp.insert(uri, status);
+        }
+        Ok(RpState {
+            created: state.created,
+            rrdp,
+            validators: state.validators.into_iter().map(|item| {
+                (item.uri, Validators {
+                    etag: item.etag,
+                    last_modified: item.last_modified,
+                })
+            }).collect(),
+            cache: state.cache,
+            vrps: state.vrps,
+        })
+    }
+}
+
+
+//------------ Serialization Helpers -----------------------------------------
+
+/// Only the version of the serialized state.
+#[derive(Deserialize)]
+struct SerdeVersion {
+    version: u32,
+}
+
+/// The serialized state.
+///
+/// The cache and VRPs are generic so we can serialize from references and
+/// deserialize into owned values.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerdeState<C, V> {
+    version: u32,
+    created: DateTime<Utc>,
+    rrdp: Vec<SerdeRrdp>,
+    validators: Vec<SerdeValidators>,
+    cache: C,
+    vrps: V,
+}
+
+/// The serialized status of an RRDP repository.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerdeRrdp {
+    notify: uri::Https,
+    last_success: Option<DateTime<Utc>>,
+    failing_since: Option<DateTime<Utc>>,
+    last_attempt: Option<DateTime<Utc>>,
+    session: Option<String>,
+    serial: Option<u64>,
+    state_changed: Option<DateTime<Utc>>,
+}
+
+impl SerdeRrdp {
+    fn new(notify: &uri::Https, status: &RrdpStatus) -> Self {
+        SerdeRrdp {
+            notify: notify.clone(),
+            last_success: status.last_success,
+            failing_since: status.failing_since,
+            last_attempt: status.last_attempt,
+            session: status.state.map(|(session, _)| session.to_string()),
+            serial: status.state.map(|(_, serial)| serial),
+            state_changed: status.state_changed,
+        }
+    }
+
+    fn into_status(self) -> Result<(uri::Https, RrdpStatus), StateError> {
+        let state = match (self.session, self.serial) {
+            (Some(session), Some(serial)) => {
+                let session = Uuid::parse_str(&session).map_err(|_| {
+                    StateError::Format(
+                        format!("invalid RRDP session '{}'", session)
+                    )
+                })?;
+                Some((session, serial))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(StateError::Format(
+                    format!("incomplete RRDP state for {}", self.notify)
+                ))
+            }
+        };
+        Ok((self.notify, RrdpStatus {
+            last_success: self.last_success,
+            failing_since: self.failing_since,
+            last_attempt: self.last_attempt,
+            state,
+            state_changed: self.state_changed,
+        }))
+    }
+}
+
+/// The serialized validators for a URI.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SerdeValidators {
+    uri: uri::Https,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+
+//============ Errors ========================================================
+
+//------------ StateError ----------------------------------------------------
+
+/// An error happened while reading or writing a state.
+#[derive(Debug)]
+pub enum StateError {
+    /// Reading or writing failed.
+    Io(io::Error),
+
+    /// The serialized state was malformed.
+    Format(String),
+
+    /// The serialized state has an unsupported format version.
+    UnsupportedVersion(u32),
+}
+
+impl StateError {
+    /// Converts a JSON error.
+    fn from_json(err: serde_json::Error) -> Self {
+        if err.is_io() {
+            StateError::Io(err.into())
+        }
+        else {
+            StateError::Format(err.to_string())
+        }
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(err: io::Error) -> Self {
+        StateError::Io(err)
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io(err) => err.fmt(f),
+            StateError::Format(err) => {
+                write!(f, "invalid state: {}", err)
+            }
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported state version {}", version)
+            }
+        }
+    }
+}
+
+impl error::Error for StateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StateError::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use chrono::TimeZone;
+    use crate::crypto::keys::KeyIdentifier;
+    use crate::fetch::access::AccessPolicy;
+    use crate::repository::cache::CacheKey;
+    use crate::resources::addr::MaxLenPrefix;
+    use crate::rtr::payload::RouteOrigin;
+    use super::*;
+
+    struct NoTransport;
+
+    #[test]
+    fn write_and_read() {
+        let notify = uri::Https::from_str(
+            "https://rrdp.example.net/notification.xml"
+        ).unwrap();
+        let mut access = RepositoryAccess::new(AccessPolicy::default());
+        access.set_status(notify.clone(), RrdpStatus {
+            last_success: Some(
+                Utc.timestamp_opt(1_700_000_000, 0).unwrap()
+            ),
+            state: Some((Uuid::from_u128(42), 17)),
+            .. Default::default()
+        });
+        let fetcher = HttpsFetcher::new(NoTransport);
+        fetcher.set_validators(notify.clone(), Some(Validators {
+            etag: Some("\"1\"".into()), last_modified: None,
+        }));
+        let cache = ValidationCache::new();
+        let key = CacheKey::from_parts(
+            [7u8; 32], KeyIdentifier::from([1u8; 20]), [3u8; 32], 0
+        );
+        cache.insert(key, String::from("valid"));
+        let mut vrps = VrpSet::new();
+        vrps.insert(RouteOrigin::new(
+            MaxLenPrefix::from_str("192.0.2.0/24-24").unwrap(), 64496.into()
+        ));
+
+        let mut state = RpState::new();
+        state.capture_access(&access);
+        state.capture_fetcher(&fetcher);
+        state.capture_cache(&cache);
+        state.set_vrps(vrps.clone());
+
+        let mut data = Vec::new();
+        state.write(&mut data).unwrap();
+        let read = RpState::<String>::read(data.as_slice()).unwrap();
+        assert_eq!(read, state);
+
+        let mut access = RepositoryAccess::new(AccessPolicy::default());
+        read.restore_access(&mut access);
+        assert_eq!(
+            access.status(&notify).unwrap().state,
+            Some((Uuid::from_u128(42), 17))
+        );
+        let fetcher = HttpsFetcher::new(NoTransport);
+        read.restore_fetcher(&fetcher);
+        assert_eq!(
+            fetcher.validators(&notify).unwrap().etag.as_deref(),
+            Some("\"1\"")
+        );
+        let cache = ValidationCache::new();
+        read.restore_cache(&cache);
+        assert_eq!(cache.get(&key).as_deref(), Some("valid"));
+        assert_eq!(read.vrps(), &vrps);
+    }
+
+    #[test]
+    fn version() {
+        assert!(matches!(
+            RpState::<String>::read(
+                b"{\"version\": 2, \"whatever\": true}".as_ref()
+            ),
+            Err(StateError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            RpState::<String>::read(b"[]".as_ref()),
+            Err(StateError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+//! A status summary of a relying party.
+//!
+//! Daemons built on this crate typically provide a health endpoint that
+//! monitoring systems poll to learn whether everything is fine. A
+//! [`Status`] collects the information such an endpoint needs into a
+//! single value: when each RRDP repository was last synchronized and at
+//! which session and serial, when each trust anchor was last validated
+//! successfully, how many objects were accepted and rejected and how much
+//! payload was produced by the last validation run, how long that run
+//! took, and the serial number currently served via RTR.
+//!
+//! The status is kept up to date by feeding it the state of the various
+//! components via the `capture_*` methods after each run. Since trust
+//! anchor freshness is tracked across runs, the same value should be
+//! kept and updated rather than a new one created each time. The status
+//! can be serialized as JSON via [`Status::write`] or, as it implements
+//! `Serialize`, through any other Serde serializer.
+//!
+//! The module is only available if the `"state"` feature is enabled.
+
+#![cfg(feature = "state")]
+
+use std::io;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::fetch::access::RepositoryAccess;
+use crate::repository::report::ValidationReport;
+use crate::repository::tal::{TalSet, TalUri};
+use crate::rtr::payload::Payload;
+use crate::rtr::state::Serial;
+
+
+//------------ Status --------------------------------------------------------
+
+/// The status of a relying party.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// The status of all known RRDP repositories, ordered by URI.
+    pub repositories: Vec<RepositoryStatus>,
+
+    /// The status of all trust anchors, ordered by name.
+    pub trust_anchors: Vec<TaStatus>,
+
+    /// The number of objects encountered during the last run.
+    pub objects: ObjectCounts,
+
+    /// The amount of payload produced by the last run.
+    pub payload: PayloadCounts,
+
+    /// Information about the last validation run.
+    ///
+    /// This is `None` if there hasn’t been a run yet.
+    pub last_run: Option<RunStatus>,
+
+    /// The serial number currently served via RTR.
+    pub rtr_serial: Option<u32>,
+}
+
+impl Status {
+    /// Creates a new, empty status.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures the status of all RRDP repositories.
+    pub fn capture_access(&mut self, access: &RepositoryAccess) {
+        self.repositories = access.iter().map(|(uri, status)| {
+            RepositoryStatus {
+                uri: uri.to_string(),
+                last_success: status.last_success,
+                last_attempt: status.last_attempt,
+                failing_since: status.failing_since,
+                session: status.state.map(|(session, _)| {
+                    session.to_string()
+                }),
+                serial: status.state.map(|(_, serial)| serial),
+            }
+        }).collect();
+        self.repositories.sort_by(|left, right| left.uri.cmp(&right.uri));
+    }
+
+    /// Captures the outcome of a validation run.
+    ///
+    /// The run validated the trust anchors of `anchors` and produced
+    /// `report`. It was started at `started` and finished at `finished`.
+    ///
+    /// A trust anchor counts as validated successfully if the certificate
+    /// at the first rsync URI of its TAL was accepted. For trust anchors
+    /// that failed, the time of the last success is kept from earlier
+    /// captures.
+    pub fn capture_validation(
+        &mut self,
+        anchors: &TalSet,
+        report: &ValidationReport,
+        started: DateTime<Utc>,
+        finished: DateTime<Utc>,
+    ) {
+        let mut trust_anchors = Vec::new();
+        for anchor in anchors {
+            let accepted = anchor.uris().into_iter().find_map(|uri| {
+                match uri {
+                    TalUri::Rsync(uri) => Some(uri),
+                    _ => None
+                }
+            }).and_then(|uri| report.outcome(uri)).map(|outcome| {
+                outcome.is_accepted()
+            }).unwrap_or(false);
+            let last_success = if accepted {
+                Some(finished)
+            }
+            else {
+                self.trust_anchor(anchor.name()).and_then(|ta| {
+                    ta.last_success
+                })
+            };
+            trust_anchors.push(TaStatus {
+                name: anchor.name().into(),
+                enabled: anchor.is_enabled(),
+                accepted,
+                last_success,
+            });
+        }
+        trust_anchors.sort_by(|left, right| left.name.cmp(&right.name));
+        self.trust_anchors = trust_anchors;
+
+        self.objects = ObjectCounts::default();
+        for (_, outcome) in report.outcomes() {
+            if outcome.is_accepted() {
+                self.objects.accepted += 1;
+            }
+            else {
+                self.objects.rejected += 1;
+            }
+        }
+
+        self.payload = PayloadCounts::default();
+        for payload in report.payload() {
+            match *payload {
+                Payload::Origin(_) => self.payload.route_origins += 1,
+                Payload::RouterKey(_) => self.payload.router_keys += 1,
+                Payload::Aspa(_) => self.payload.aspas += 1,
+            }
+        }
+
+        self.last_run = Some(RunStatus {
+            started,
+            finished,
+            duration: finished.signed_duration_since(
+                started
+            ).to_std().unwrap_or_default().as_secs_f64(),
+        });
+    }
+
+    /// Sets the serial number currently served via RTR.
+    pub fn set_rtr_serial(&mut self, serial: Serial) {
+        self.rtr_serial = Some(serial.0)
+    }
+
+    /// Returns the status of the trust anchor with the given name.
+    pub fn trust_anchor(&self, name: &str) -> Option<&TaStatus> {
+        self.trust_anchors.iter().find(|ta| ta.name == name)
+    }
+
+    /// Returns the enabled trust anchors that aren’t fresh at `now`.
+    ///
+    /// See [`TaStatus::is_fresh`] for the meaning of `max_age`.
+    pub fn stale_trust_anchors(
+        &self, max_age: Duration, now: DateTime<Utc>
+    ) -> impl Iterator<Item = &TaStatus> + '_ {
+        self.trust_anchors.iter().filter(move |ta| {
+            ta.enabled && !ta.is_fresh(max_age, now)
+        })
+    }
+
+    /// Writes the status as JSON to a writer.
+    pub fn write<W: io::Write>(&self, writer: W) -> Result<(), io::Error> {
+        serde_json::to_writer(writer, self).map_err(Into::into)
+    }
+}
+
+
+//------------ RepositoryStatus ----------------------------------------------
+
+/// The synchronization status of an RRDP repository.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepositoryStatus {
+    /// The URI of the notification file.
+    pub uri: String,
+
+    /// The time of the last successful update.
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The time of the last attempt, successful or not.
+    pub last_attempt: Option<DateTime<Utc>>,
+
+    /// The time of the first failure since the last success.
+    pub failing_since: Option<DateTime<Utc>>,
+
+    /// The session ID of the last successful update.
+    pub session: Option<String>,
+
+    /// The serial number of the last successful update.
+    pub serial: Option<u64>,
+}
+
+
+//------------ TaStatus ------------------------------------------------------
+
+/// The status of a trust anchor.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaStatus {
+    /// The name of the trust anchor.
+    pub name: String,
+
+    /// Whether the trust anchor is enabled.
+    pub enabled: bool,
+
+    /// Whether the trust anchor certificate was accepted in the last run.
+    pub accepted: bool,
+
+    /// The end of the last run that accepted the trust anchor certificate.
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl TaStatus {
+    /// Returns whether the trust anchor is fresh at `now`.
+    ///
+    /// A trust anchor is fresh if it was last validated successfully no
+    /// longer than `max_age` ago.
+    pub fn is_fresh(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        match self.last_success {
+            Some(success) => {
+                now.signed_duration_since(
+                    success
+                ).to_std().unwrap_or_default() <= max_age
+            }
+            None => false
+        }
+    }
+}
+
+
+//------------ ObjectCounts --------------------------------------------------
+
+/// The number of objects encountered during a validation run.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize
+)]
+pub struct ObjectCounts {
+    /// The number of objects accepted.
+    pub accepted: usize,
+
+    /// The number of objects rejected.
+    pub rejected: usize,
+}
+
+
+//------------ PayloadCounts -------------------------------------------------
+
+/// The amount of payload produced by a validation run.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadCounts {
+    /// The number of route origins.
+    pub route_origins: usize,
+
+    /// The number of router keys.
+    pub router_keys: usize,
+
+    /// The number of ASPA units.
+    pub aspas: usize,
+}
+
+
+//------------ RunStatus -----------------------------------------------------
+
+/// Information about a validation run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RunStatus {
+    /// The time the run started.
+    pub started: DateTime<Utc>,
+
+    /// The time the run finished.
+    pub finished: DateTime<Utc>,
+
+    /// The duration of the run in seconds.
+    pub duration: f64,
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+    use crate::uri;
+    use crate::fetch::access::RrdpStatus;
+    use crate::repository::tal::Tal;
+    use crate::resources::addr::MaxLenPrefix;
+    use crate::rtr::payload::RouteOrigin;
+    use super::*;
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    fn anchors() -> TalSet {
+        let mut anchors = TalSet::new();
+        for name in ["a", "b"] {
+            let tal = format!(
+                "rsync://{}.example/ta/ta.cer\n\n{}",
+                name,
+                include_str!("../test-data/repository/ripe.tal")
+                    .lines().skip_while(|line| !line.is_empty()).skip(1)
+                    .collect::<Vec<_>>().join("\n")
+            );
+            anchors.insert(
+                Tal::read_named(name.into(), &mut tal.as_bytes()).unwrap()
+            );
+        }
+        anchors
+    }
+
+    #[test]
+    fn capture() {
+        let mut access = RepositoryAccess::default();
+        access.set_status(
+            uri::Https::from_str("https://rrdp.example/n.xml").unwrap(),
+            RrdpStatus {
+                last_success: Some(time(0)),
+                last_attempt: Some(time(0)),
+                state: Some((Uuid::from_u128(1), 7)),
+                .. Default::default()
+            }
+        );
+        let anchors = anchors();
+        let mut report = ValidationReport::new();
+        report.accept(
+            uri::Rsync::from_str("rsync://a.example/ta/ta.cer").unwrap()
+        );
+        report.reject(
+            uri::Rsync::from_str("rsync://b.example/ta/ta.cer").unwrap(),
+            "expired"
+        );
+        report.add_payload(RouteOrigin::new(
+            MaxLenPrefix::from_str("192.0.2.0/24").unwrap(), 64496.into()
+        ));
+
+        let mut status = Status::new();
+        status.capture_access(&access);
+        status.capture_validation(&anchors, &report, time(10), time(40));
+        status.set_rtr_serial(Serial(12));
+
+        assert_eq!(status.repositories.len(), 1);
+        assert_eq!(status.repositories[0].serial, Some(7));
+        assert_eq!(
+            status.objects, ObjectCounts { accepted: 1, rejected: 1 }
+        );
+        assert_eq!(status.payload.route_origins, 1);
+        assert_eq!(status.last_run.as_ref().unwrap().duration, 30.);
+        assert_eq!(
+            status.trust_anchor("a").unwrap().last_success, Some(time(40))
+        );
+        assert!(!status.trust_anchor("b").unwrap().accepted);
+
+        // A failed run keeps the last success.
+        let report = ValidationReport::new();
+        status.capture_validation(&anchors, &report, time(100), time(110));
+        let a = status.trust_anchor("a").unwrap();
+        assert!(!a.accepted);
+        assert_eq!(a.last_success, Some(time(40)));
+        let stale: Vec<_> = status.stale_trust_anchors(
+            Duration::from_secs(60), time(110)
+        ).map(|ta| ta.name.as_str()).collect();
+        assert_eq!(stale, ["a", "b"]);
+        assert!(a.is_fresh(Duration::from_secs(60), time(100)));
+
+        let mut json = Vec::new();
+        status.write(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["rtrSerial"], 12);
+        assert_eq!(json["trustAnchors"][0]["name"], "a");
+        assert_eq!(
+            json["repositories"][0]["session"],
+            Uuid::from_u128(1).to_string()
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Storage of repository objects.
+//!
+//! Various parts of this crate need to keep the objects of RPKI
+//! repositories around: fetchers put the objects they retrieved somewhere,
+//! the validation walks through them, and a publication server needs to
+//! know what its publishers have published. The [`ObjectStore`] trait
+//! provides a common interface for all these uses. Objects are identified
+//! by their rsync URI and stored as their raw content.
+//!
+//! The module also provides a simple in-memory implementation of the trait
+//! in [`MemoryStore`], an implementation keeping objects in a directory
+//! tree in [`fs::FsStore`], an in-memory implementation that keeps
+//! identical objects only once in [`content::ContentStore`], and one that
+//! keeps every version of every object in [`archive::ArchiveStore`]. The
+//! [`fallback`] module decides when previously fetched objects may be
+//! used if a publication point can’t be refreshed.
+
+use std::{error, fmt};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use bytes::Bytes;
+use crate::uri;
+
+pub mod archive;
+pub mod content;
+pub mod fallback;
+pub mod fs;
+
+
+//------------ ObjectStore ---------------------------------------------------
+
+/// A type that stores repository objects.
+///
+/// Objects are identified by their rsync URI. Their content is kept as
+/// is in a bytes value.
+///
+/// All methods may fail with an error of the associated type `Error`.
+/// Since the store can be backed by all kinds of media, this provides the
+/// chance to report problems with the underlying storage.
+pub trait ObjectStore {
+    /// The error returned by the store.
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Returns the content of the object with the given URI.
+    ///
+    /// Returns `Ok(None)` if there is no such object.
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error>;
+
+    /// Inserts or replaces the object with the given URI.
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error>;
+
+    /// Removes the object with the given URI.
+    ///
+    /// Returns whether there was an object with this URI.
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error>;
+
+    /// Returns the URIs of all objects below the given URI.
+    ///
+    /// The URI `base` should be a directory URI, i.e., it should end in a
+    /// slash. All objects with URIs that have `base` as a parent are
+    /// returned, including those in sub-directories.
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error>;
+
+    /// Returns whether there is an object with the given URI.
+    fn contains(&self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        self.get(uri).map(|res| res.is_some())
+    }
+
+    /// Returns the size in octets of the object with the given URI.
+    ///
+    /// Returns `Ok(None)` if there is no such object. This allows
+    /// rejecting objects that are too large before loading them. The
+    /// default implementation loads the object, so stores that can
+    /// determine the size more cheaply should override it.
+    fn size(&self, uri: &uri::Rsync) -> Result<Option<u64>, Self::Error> {
+        self.get(uri).map(|res| res.map(|data| data.len() as u64))
+    }
+}
+
+
+//------------ MemoryStore ---------------------------------------------------
+
+/// An object store keeping all objects in memory.
+///
+/// Objects are kept in a map ordered by their URI. Since URIs are compared
+/// case-insensitively in their scheme and authority parts, the map uses a
+/// canonical form of the URI as its key.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    /// The objects keyed by their canonical URI.
+    objects: BTreeMap<String, (uri::Rsync, Bytes)>,
+}
+
+impl MemoryStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Returns an iterator over all objects in the store.
+    ///
+    /// The objects are returned ordered by URI.
+    pub fn iter(&self) -> impl Iterator<Item = (&uri::Rsync, &Bytes)> + '_ {
+        self.objects.values().map(|(uri, data)| (uri, data))
+    }
+
+    /// Returns the key used for a URI.
+    ///
+    /// The key is the same for all URIs that refer to the same object.
+    pub(crate) fn key(uri: &uri::Rsync) -> String {
+        let mut res = String::from(uri.canonical_module());
+        res.push_str(uri.path());
+        res
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    type Error = Infallible;
+
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error> {
+        Ok(self.objects.get(&Self::key(uri)).map(|(_, data)| data.clone()))
+    }
+
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error> {
+        self.objects.insert(Self::key(&uri), (uri, data));
+        Ok(())
+    }
+
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        Ok(self.objects.remove(&Self::key(uri)).is_some())
+    }
+
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error> {
+        let start = Self::key(base);
+        Ok(self.objects.range(start.clone()..).take_while(|(key, _)| {
+            key.starts_with(&start)
+        }).filter_map(|(_, (uri, _))| {
+            if base.is_parent_of(uri) {
+                Some(uri.clone())
+            }
+            else {
+                None
+            }
+        }).collect())
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ StoreError ----------------------------------------------------
+
+/// An error happened while accessing an object store.
+///
+/// This type can be used by components that are generic over the object
+/// store to report errors of the store.
+#[derive(Debug)]
+pub struct StoreError(Box<dyn error::Error + Send + Sync>);
+
+impl StoreError {
+    /// Creates a new store error from the store’s own error.
+    pub fn new(err: impl error::Error + Send + Sync + 'static) -> Self {
+        StoreError(Box::new(err))
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "object store error: {}", self.0)
+    }
+}
+
+impl error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut store = MemoryStore::new();
+        store.insert(
+            uri("rsync://example.com/mod/a/one.cer"), Bytes::from("one")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/mod/a/b/two.roa"), Bytes::from("two")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/mod/ab/three.mft"), Bytes::from("three")
+        ).unwrap();
+        assert_eq!(store.len(), 3);
+
+        assert_eq!(
+            store.get(&uri("rsync://EXAMPLE.com/mod/a/one.cer")).unwrap(),
+            Some(Bytes::from("one"))
+        );
+        assert_eq!(
+            store.list(&uri("rsync://example.com/mod/a/")).unwrap(),
+            vec![
+                uri("rsync://example.com/mod/a/b/two.roa"),
+                uri("rsync://example.com/mod/a/one.cer"),
+            ]
+        );
+        assert_eq!(
+            store.list(&uri("rsync://example.com/mod/")).unwrap().len(),
+            3
+        );
+
+        assert!(
+            store.remove(&uri("rsync://example.com/mod/a/one.cer")).unwrap()
+        );
+        assert!(
+            !store.remove(&uri("rsync://example.com/mod/a/one.cer")).unwrap()
+        );
+        assert!(
+            !store.contains(&uri("rsync://example.com/mod/a/one.cer")).unwrap()
+        );
+        assert_eq!(store.len(), 2);
+    }
+}
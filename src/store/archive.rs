@@ -0,0 +1,632 @@
+//! An in-memory store keeping every version of every object.
+//!
+//! Measurement and research projects often want to know what a repository
+//! looked like at some point in the past, e.g., to find out why a route
+//! became invalid last Tuesday. The [`ArchiveStore`] never forgets
+//! anything: each time an object is inserted or removed, it records a new
+//! version for the object’s URI together with the time of the change.
+//! Object content is kept once per distinct content, addressed by its
+//! SHA-256 hash.
+//!
+//! Via the [`ObjectStore`] trait, the store behaves like any other store,
+//! showing the current state of all objects. In addition, the state at
+//! any point in time can be queried via [`ArchiveStore::get_at`] and
+//! [`ArchiveStore::list_at`] or reconstructed in full via
+//! [`ArchiveStore::snapshot_at`]. The latter can then be used to validate
+//! the repository as it was at that time.
+//!
+//! The store lives in memory. It can be written to a file via
+//! [`ArchiveStore::write`] and read back via [`ArchiveStore::read`] in a
+//! simple binary format.
+//!
+//! The module is only available if the `"crypto"` feature is enabled.
+
+#![cfg(feature = "crypto")]
+
+use std::io;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::{Infallible, TryInto};
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use crate::uri;
+use crate::clock::{Clock, SharedClock};
+use crate::crypto::DigestAlgorithm;
+use super::{MemoryStore, ObjectStore};
+
+
+//------------ ArchiveStore --------------------------------------------------
+
+/// An object store keeping all versions of all objects.
+///
+/// The store keeps the history of each URI as a list of versions ordered
+/// by time. A version either records new content for the URI or that the
+/// object was removed. Inserting content identical to the current content
+/// does not create a new version.
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveStore {
+    /// The history of each URI keyed by its canonical URI.
+    uris: BTreeMap<String, (uri::Rsync, Vec<ArchiveVersion>)>,
+
+    /// The content of the objects keyed by their hash.
+    objects: HashMap<ContentHash, Bytes>,
+
+    /// The clock providing the time for changes via `ObjectStore`.
+    clock: SharedClock,
+}
+
+/// The SHA-256 hash of an object’s content.
+type ContentHash = [u8; 32];
+
+impl ArchiveStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the clock providing the time for changes via `ObjectStore`.
+    ///
+    /// By default, the system time is used.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock
+    }
+
+    /// Returns the number of URIs that ever had an object.
+    pub fn len(&self) -> usize {
+        self.uris.len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.uris.is_empty()
+    }
+
+    /// Returns the number of distinct objects ever stored.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns the total size of the distinct objects in octets.
+    pub fn content_size(&self) -> usize {
+        self.objects.values().map(Bytes::len).sum()
+    }
+
+    /// Returns the object with the given SHA-256 hash.
+    ///
+    /// Returns `None` if there is no such object or if `hash` isn’t 32
+    /// octets long.
+    pub fn get_by_hash(&self, hash: &[u8]) -> Option<Bytes> {
+        let hash: ContentHash = hash.try_into().ok()?;
+        self.objects.get(&hash).cloned()
+    }
+
+    /// Returns the history of the given URI.
+    ///
+    /// The versions are ordered by time, oldest first. The slice is empty
+    /// if there never was an object at this URI.
+    pub fn history(&self, uri: &uri::Rsync) -> &[ArchiveVersion] {
+        self.uris.get(&MemoryStore::key(uri)).map(|(_, versions)| {
+            versions.as_slice()
+        }).unwrap_or(&[])
+    }
+
+    /// Inserts an object at the given time.
+    ///
+    /// The time doesn’t need to be later than that of the latest version
+    /// of the object. The version is placed into the history at the right
+    /// place. If the content is identical to that of the object at this
+    /// time, no new version is recorded.
+    pub fn insert_at(
+        &mut self, uri: uri::Rsync, data: Bytes, time: DateTime<Utc>
+    ) {
+        let hash: ContentHash = DigestAlgorithm::sha256().digest(
+            data.as_ref()
+        ).as_ref().try_into().expect("SHA-256 digest is not 32 octets");
+        let version = ArchiveVersion { time, hash: Some(hash) };
+        if self.add_version(uri, version) {
+            self.objects.entry(hash).or_insert(data);
+        }
+    }
+
+    /// Removes an object at the given time.
+    ///
+    /// As with [`insert_at`][Self::insert_at], the time may be earlier
+    /// than that of the latest version. Returns whether there was an
+    /// object with this URI at that time. The content of the object is
+    /// kept.
+    pub fn remove_at(
+        &mut self, uri: &uri::Rsync, time: DateTime<Utc>
+    ) -> bool {
+        if self.hash_at(uri, time).is_none() {
+            return false
+        }
+        self.add_version(uri.clone(), ArchiveVersion { time, hash: None })
+    }
+
+    /// Returns the content of an object at the given time.
+    pub fn get_at(
+        &self, uri: &uri::Rsync, time: DateTime<Utc>
+    ) -> Option<Bytes> {
+        self.hash_at(uri, time).map(|hash| self.objects[&hash].clone())
+    }
+
+    /// Returns the URIs of all objects below `base` at the given time.
+    ///
+    /// See [`ObjectStore::list`] for the details.
+    pub fn list_at(
+        &self, base: &uri::Rsync, time: DateTime<Utc>
+    ) -> Vec<uri::Rsync> {
+        let start = MemoryStore::key(base);
+        self.uris.range(start.clone()..).take_while(|(key, _)| {
+            key.starts_with(&start)
+        }).filter_map(|(_, (uri, versions))| {
+            if base.is_parent_of(uri)
+                && Self::version_at(versions, time).is_some()
+            {
+                Some(uri.clone())
+            }
+            else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Reconstructs the state of all objects at the given time.
+    pub fn snapshot_at(&self, time: DateTime<Utc>) -> MemoryStore {
+        let mut res = MemoryStore::new();
+        for (uri, versions) in self.uris.values() {
+            if let Some(hash) = Self::version_at(versions, time) {
+                // MemoryStore::insert never fails.
+                let _ = res.insert(
+                    uri.clone(), self.objects[&hash].clone()
+                );
+            }
+        }
+        res
+    }
+
+    /// Returns the hash of the object at `uri` at the given time.
+    fn hash_at(
+        &self, uri: &uri::Rsync, time: DateTime<Utc>
+    ) -> Option<ContentHash> {
+        self.uris.get(&MemoryStore::key(uri)).and_then(|(_, versions)| {
+            Self::version_at(versions, time)
+        })
+    }
+
+    /// Returns the hash of the version valid at the given time.
+    fn version_at(
+        versions: &[ArchiveVersion], time: DateTime<Utc>
+    ) -> Option<ContentHash> {
+        let pos = versions.partition_point(|version| version.time <= time);
+        pos.checked_sub(1).and_then(|pos| versions[pos].hash)
+    }
+
+    /// Adds a version to the history of a URI.
+    ///
+    /// Versions are kept ordered by time. A version with the same time as
+    /// existing versions is placed after them. If the version doesn’t
+    /// change the state of the URI, it is not added. If the following
+    /// version doesn’t change the state anymore, it is dropped.
+    ///
+    /// Returns whether the version was added.
+    fn add_version(
+        &mut self, uri: uri::Rsync, version: ArchiveVersion
+    ) -> bool {
+        let versions = &mut self.uris.entry(
+            MemoryStore::key(&uri)
+        ).or_insert_with(|| (uri, Vec::new())).1;
+        let pos = versions.partition_point(|item| item.time <= version.time);
+        let current = pos.checked_sub(1).and_then(|pos| versions[pos].hash);
+        if current == version.hash {
+            return false
+        }
+        if versions.get(pos).map(|next| next.hash) == Some(version.hash) {
+            versions.remove(pos);
+        }
+        versions.insert(pos, version);
+        true
+    }
+}
+
+impl ObjectStore for ArchiveStore {
+    type Error = Infallible;
+
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error> {
+        Ok(self.uris.get(&MemoryStore::key(uri)).and_then(|(_, versions)| {
+            versions.last().and_then(|version| version.hash)
+        }).map(|hash| self.objects[&hash].clone()))
+    }
+
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error> {
+        let time = self.next_time(&uri);
+        self.insert_at(uri, data, time);
+        Ok(())
+    }
+
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        if self.get(uri)?.is_none() {
+            return Ok(false)
+        }
+        Ok(self.remove_at(uri, self.next_time(uri)))
+    }
+
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error> {
+        let start = MemoryStore::key(base);
+        Ok(self.uris.range(start.clone()..).take_while(|(key, _)| {
+            key.starts_with(&start)
+        }).filter_map(|(_, (uri, versions))| {
+            let current = versions.last().and_then(|version| version.hash);
+            if base.is_parent_of(uri) && current.is_some() {
+                Some(uri.clone())
+            }
+            else {
+                None
+            }
+        }).collect())
+    }
+}
+
+/// # Persistence
+///
+/// The store is written as a sequence of records. All integers are in
+/// network byte order. First comes the magic `RPKIARC1`, then the number
+/// of distinct contents as a `u64` followed by each content as its
+/// length as a `u64` and its octets. Next is the number of URIs as a
+/// `u64` and for each URI its length as a `u32`, the URI, the number of
+/// versions as a `u64`, and the versions. Each version is the time as
+/// seconds since the Unix epoch as an `i64` and the nanoseconds as a
+/// `u32` followed by a zero octet for a removal or an octet of one and
+/// the SHA-256 hash of the content.
+impl ArchiveStore {
+    /// The magic at the start of a written store.
+    const MAGIC: &'static [u8] = b"RPKIARC1";
+
+    /// Writes the store to a writer.
+    pub fn write<W: io::Write>(
+        &self, mut writer: W
+    ) -> Result<(), io::Error> {
+        writer.write_all(Self::MAGIC)?;
+        // Sort the content by hash so the output is deterministic.
+        let mut objects: Vec<_> = self.objects.iter().collect();
+        objects.sort_by_key(|(hash, _)| *hash);
+        writer.write_all(&(objects.len() as u64).to_be_bytes())?;
+        for (_, data) in objects {
+            writer.write_all(&(data.len() as u64).to_be_bytes())?;
+            writer.write_all(data.as_ref())?;
+        }
+        writer.write_all(&(self.uris.len() as u64).to_be_bytes())?;
+        for (uri, versions) in self.uris.values() {
+            let uri = uri.as_str().as_bytes();
+            let uri_len = u32::try_from(uri.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "URI too long")
+            })?;
+            writer.write_all(&uri_len.to_be_bytes())?;
+            writer.write_all(uri)?;
+            writer.write_all(&(versions.len() as u64).to_be_bytes())?;
+            for version in versions {
+                writer.write_all(
+                    &version.time.timestamp().to_be_bytes()
+                )?;
+                writer.write_all(
+                    &version.time.timestamp_subsec_nanos().to_be_bytes()
+                )?;
+                match version.hash {
+                    Some(ref hash) => {
+                        writer.write_all(&[1])?;
+                        writer.write_all(hash)?;
+                    }
+                    None => writer.write_all(&[0])?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a store from a reader.
+    ///
+    /// The content of all objects is checked against its hash and the
+    /// history of each URI must be ordered by time and refer to known
+    /// content only. Otherwise, an error of kind
+    /// [`InvalidData`][io::ErrorKind::InvalidData] is returned. The
+    /// returned store uses the system time.
+    pub fn read<R: io::Read>(mut reader: R) -> Result<Self, io::Error> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(invalid_data("not an archive store"))
+        }
+        let mut res = Self::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let len = read_u64(&mut reader)?;
+            let data = read_data(&mut reader, len)?;
+            let hash: ContentHash = DigestAlgorithm::sha256().digest(
+                data.as_ref()
+            ).as_ref().try_into().expect("SHA-256 digest is not 32 octets");
+            res.objects.insert(hash, data);
+        }
+        for _ in 0..read_u64(&mut reader)? {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            let uri = read_data(&mut reader, u32::from_be_bytes(len).into())?;
+            let uri = uri::Rsync::from_bytes(uri).map_err(|_| {
+                invalid_data("invalid URI")
+            })?;
+            let mut versions: Vec<ArchiveVersion> = Vec::new();
+            for _ in 0..read_u64(&mut reader)? {
+                let version = read_version(&mut reader)?;
+                if let Some(hash) = version.hash {
+                    if !res.objects.contains_key(&hash) {
+                        return Err(invalid_data("unknown content"))
+                    }
+                }
+                if let Some(last) = versions.last() {
+                    if last.time > version.time {
+                        return Err(invalid_data("unordered history"))
+                    }
+                }
+                versions.push(version);
+            }
+            res.uris.insert(MemoryStore::key(&uri), (uri, versions));
+        }
+        Ok(res)
+    }
+}
+
+/// Creates an error for malformed data.
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads a `u64` in network byte order.
+fn read_u64(reader: &mut impl io::Read) -> Result<u64, io::Error> {
+    let mut res = [0u8; 8];
+    reader.read_exact(&mut res)?;
+    Ok(u64::from_be_bytes(res))
+}
+
+/// Reads `len` octets.
+///
+/// The data is read in chunks so that a bogus length doesn’t lead to a
+/// huge allocation.
+fn read_data(
+    reader: &mut impl io::Read, len: u64
+) -> Result<Bytes, io::Error> {
+    use std::io::Read;
+
+    let mut res = Vec::new();
+    let read = reader.take(len).read_to_end(&mut res)?;
+    if (read as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+    Ok(res.into())
+}
+
+/// Reads a version.
+fn read_version(
+    reader: &mut impl io::Read
+) -> Result<ArchiveVersion, io::Error> {
+    let mut secs = [0u8; 8];
+    reader.read_exact(&mut secs)?;
+    let mut nanos = [0u8; 4];
+    reader.read_exact(&mut nanos)?;
+    let time = Utc.timestamp_opt(
+        i64::from_be_bytes(secs), u32::from_be_bytes(nanos)
+    ).single().ok_or_else(|| invalid_data("invalid time"))?;
+    let mut flag = [0u8];
+    reader.read_exact(&mut flag)?;
+    let hash = match flag[0] {
+        0 => None,
+        1 => {
+            let mut hash = [0u8; 32];
+            reader.read_exact(&mut hash)?;
+            Some(hash)
+        }
+        _ => return Err(invalid_data("invalid version"))
+    };
+    Ok(ArchiveVersion { time, hash })
+}
+
+impl ArchiveStore {
+    /// Returns the time to use for changes made via `ObjectStore`.
+    ///
+    /// This is the current time or, if the latest version of the URI is
+    /// later, that time so that the change becomes the current state.
+    fn next_time(&self, uri: &uri::Rsync) -> DateTime<Utc> {
+        let now = self.clock.now();
+        match self.history(uri).last() {
+            Some(last) if last.time > now => last.time,
+            _ => now,
+        }
+    }
+}
+
+
+//------------ ArchiveVersion ------------------------------------------------
+
+/// A single version of an object in an [`ArchiveStore`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ArchiveVersion {
+    /// The time the version was recorded.
+    time: DateTime<Utc>,
+
+    /// The hash of the content or `None` if the object was removed.
+    hash: Option<ContentHash>,
+}
+
+impl ArchiveVersion {
+    /// Returns the time the version was recorded.
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    /// Returns the SHA-256 hash of the object’s content.
+    ///
+    /// Returns `None` if the version records the removal of the object.
+    pub fn hash(&self) -> Option<&[u8]> {
+        self.hash.as_ref().map(AsRef::as_ref)
+    }
+
+    /// Returns whether the version records the removal of the object.
+    pub fn is_removal(&self) -> bool {
+        self.hash.is_none()
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use chrono::TimeZone;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn archive_store() {
+        let one = uri("rsync://example.com/mod/a/one.cer");
+        let two = uri("rsync://example.com/mod/a/two.roa");
+
+        let mut store = ArchiveStore::new();
+        store.insert_at(one.clone(), Bytes::from("one-1"), time(100));
+        store.insert_at(two.clone(), Bytes::from("two-1"), time(100));
+        store.insert_at(one.clone(), Bytes::from("one-1"), time(150));
+        store.insert_at(one.clone(), Bytes::from("one-2"), time(200));
+        assert!(store.remove_at(&two, time(300)));
+        assert!(!store.remove_at(&two, time(400)));
+        store.insert_at(two.clone(), Bytes::from("two-1"), time(500));
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.object_count(), 3);
+        assert_eq!(store.history(&one).len(), 2);
+        assert_eq!(store.history(&two).len(), 3);
+        assert!(store.history(&two)[1].is_removal());
+        assert_eq!(
+            store.get_by_hash(store.history(&one)[0].hash().unwrap()),
+            Some(Bytes::from("one-1"))
+        );
+
+        assert_eq!(store.get_at(&one, time(50)), None);
+        assert_eq!(store.get_at(&one, time(199)), Some("one-1".into()));
+        assert_eq!(store.get_at(&one, time(200)), Some("one-2".into()));
+        assert_eq!(store.get_at(&two, time(350)), None);
+        assert_eq!(store.get_at(&two, time(600)), Some("two-1".into()));
+
+        let base = uri("rsync://example.com/mod/");
+        assert_eq!(
+            store.list_at(&base, time(150)), [one.clone(), two.clone()]
+        );
+        assert_eq!(store.list_at(&base, time(350)).len(), 1);
+
+        let snapshot = store.snapshot_at(time(350));
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(
+            snapshot.get(&one).unwrap(), Some(Bytes::from("one-2"))
+        );
+
+        // Changes via ObjectStore become the current state.
+        assert!(store.remove(&one).unwrap());
+        assert!(!store.remove(&one).unwrap());
+        assert_eq!(store.get(&one).unwrap(), None);
+        assert_eq!(store.get(&two).unwrap(), Some(Bytes::from("two-1")));
+        assert_eq!(store.list(&base).unwrap(), [two]);
+        assert_eq!(store.get_at(&one, time(250)), Some("one-2".into()));
+        assert_eq!(store.object_count(), 3);
+    }
+
+    #[test]
+    fn clock() {
+        use crate::clock::ManualClock;
+
+        let one = uri("rsync://example.com/mod/a/one.cer");
+        let clock = ManualClock::new(time(1000));
+        let mut store = ArchiveStore::new();
+        store.set_clock(SharedClock::new(clock.clone()));
+        store.insert(one.clone(), Bytes::from("one-1")).unwrap();
+        clock.advance(std::time::Duration::from_secs(100));
+        store.insert(one.clone(), Bytes::from("one-2")).unwrap();
+        assert_eq!(store.history(&one)[0].time(), time(1000));
+        assert_eq!(store.history(&one)[1].time(), time(1100));
+        assert_eq!(store.get_at(&one, time(1050)), Some("one-1".into()));
+    }
+
+    #[test]
+    fn insert_out_of_order() {
+        let one = uri("rsync://example.com/mod/a/one.cer");
+
+        let mut store = ArchiveStore::new();
+        store.insert_at(one.clone(), Bytes::from("one-2"), time(200));
+        store.insert_at(one.clone(), Bytes::from("one-1"), time(100));
+        assert!(store.remove_at(&one, time(300)));
+        assert!(store.remove_at(&one, time(150)));
+        assert!(!store.remove_at(&one, time(160)));
+        assert_eq!(
+            store.history(&one).iter().map(|v| v.time()).collect::<Vec<_>>(),
+            [time(100), time(150), time(200), time(300)]
+        );
+        assert_eq!(store.get_at(&one, time(120)), Some("one-1".into()));
+        assert_eq!(store.get_at(&one, time(170)), None);
+        assert_eq!(store.get_at(&one, time(250)), Some("one-2".into()));
+        assert_eq!(store.get(&one).unwrap(), None);
+
+        // Inserting the content of the following version makes that
+        // version redundant.
+        store.insert_at(one.clone(), Bytes::from("one-2"), time(170));
+        assert_eq!(
+            store.history(&one).iter().map(|v| v.time()).collect::<Vec<_>>(),
+            [time(100), time(150), time(170), time(300)]
+        );
+        assert_eq!(store.get_at(&one, time(180)), Some("one-2".into()));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let one = uri("rsync://example.com/mod/a/one.cer");
+        let two = uri("rsync://example.com/mod/a/two.roa");
+
+        let mut store = ArchiveStore::new();
+        store.insert_at(one.clone(), Bytes::from("one-1"), time(100));
+        store.insert_at(two.clone(), Bytes::from("two-1"), time(100));
+        store.insert_at(
+            one.clone(), Bytes::from("one-2"),
+            Utc.timestamp_opt(200, 5).unwrap()
+        );
+        assert!(store.remove_at(&two, time(300)));
+
+        let mut data = Vec::new();
+        store.write(&mut data).unwrap();
+        let read = ArchiveStore::read(data.as_slice()).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read.object_count(), 3);
+        assert_eq!(read.history(&one), store.history(&one));
+        assert_eq!(read.history(&two), store.history(&two));
+        assert_eq!(read.get_at(&two, time(150)), Some("two-1".into()));
+        assert_eq!(read.get(&one).unwrap(), Some("one-2".into()));
+
+        let mut again = Vec::new();
+        read.write(&mut again).unwrap();
+        assert_eq!(data, again);
+
+        assert!(ArchiveStore::read(&data[..data.len() - 1]).is_err());
+        assert!(ArchiveStore::read(&b"RPKIARC0"[..]).is_err());
+
+        // Changing content makes its hash unknown.
+        let pos = data.windows(5).position(|w| w == b"one-1").unwrap();
+        data[pos] = b'x';
+        assert_eq!(
+            ArchiveStore::read(data.as_slice()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
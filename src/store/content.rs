@@ -0,0 +1,228 @@
+//! An in-memory store deduplicating identical objects.
+//!
+//! Large repositories often contain the same object at multiple URIs,
+//! for instance when a CA publishes at more than one publication point or
+//! when a relying party keeps objects from RRDP and rsync side by side.
+//! The [`ContentStore`] keeps each distinct object only once, addressed by
+//! the SHA-256 hash of its content, and maps URIs to these hashes. Objects
+//! are reference counted and dropped once no URI refers to them anymore.
+//!
+//! The module is only available if the `"crypto"` feature is enabled.
+
+#![cfg(feature = "crypto")]
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::{Infallible, TryInto};
+use bytes::Bytes;
+use crate::uri;
+use crate::crypto::DigestAlgorithm;
+use super::{MemoryStore, ObjectStore};
+
+
+//------------ ContentStore --------------------------------------------------
+
+/// An object store keeping each distinct object only once.
+///
+/// Like [`MemoryStore`], the store keeps its objects in memory and orders
+/// them by URI. In addition, objects are indexed by the SHA-256 hash of
+/// their content, allowing them to be looked up via
+/// [`get_by_hash`][Self::get_by_hash].
+#[derive(Clone, Debug, Default)]
+pub struct ContentStore {
+    /// The hash of each object keyed by its canonical URI.
+    uris: BTreeMap<String, (uri::Rsync, ContentHash)>,
+
+    /// The objects keyed by their hash.
+    objects: HashMap<ContentHash, StoredObject>,
+}
+
+/// The SHA-256 hash of an object’s content.
+type ContentHash = [u8; 32];
+
+/// A single distinct object.
+#[derive(Clone, Debug)]
+struct StoredObject {
+    /// The content of the object.
+    data: Bytes,
+
+    /// The number of URIs referring to the object.
+    refs: usize,
+}
+
+impl ContentStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of URIs in the store.
+    pub fn len(&self) -> usize {
+        self.uris.len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.uris.is_empty()
+    }
+
+    /// Returns the number of distinct objects in the store.
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Returns the total size of the distinct objects in octets.
+    pub fn content_size(&self) -> usize {
+        self.objects.values().map(|object| object.data.len()).sum()
+    }
+
+    /// Returns the SHA-256 hash of the object with the given URI.
+    pub fn hash(&self, uri: &uri::Rsync) -> Option<&[u8]> {
+        self.uris.get(&MemoryStore::key(uri)).map(|(_, hash)| {
+            hash.as_ref()
+        })
+    }
+
+    /// Returns the object with the given SHA-256 hash.
+    ///
+    /// Returns `None` if there is no such object or if `hash` isn’t 32
+    /// octets long.
+    pub fn get_by_hash(&self, hash: &[u8]) -> Option<Bytes> {
+        let hash: ContentHash = hash.try_into().ok()?;
+        self.objects.get(&hash).map(|object| object.data.clone())
+    }
+
+    /// Returns the number of URIs referring to the object with this hash.
+    pub fn ref_count(&self, hash: &[u8]) -> usize {
+        let hash: ContentHash = match hash.try_into() {
+            Ok(hash) => hash,
+            Err(_) => return 0
+        };
+        self.objects.get(&hash).map(|object| object.refs).unwrap_or(0)
+    }
+
+    /// Returns an iterator over all objects in the store.
+    ///
+    /// The objects are returned ordered by URI. Objects present at multiple
+    /// URIs are returned once for each URI.
+    pub fn iter(&self) -> impl Iterator<Item = (&uri::Rsync, &Bytes)> + '_ {
+        self.uris.values().map(move |(uri, hash)| {
+            (uri, &self.objects[hash].data)
+        })
+    }
+
+    /// Drops a reference to the object with the given hash.
+    fn release(&mut self, hash: &ContentHash) {
+        if let Some(object) = self.objects.get_mut(hash) {
+            object.refs -= 1;
+            if object.refs == 0 {
+                self.objects.remove(hash);
+            }
+        }
+    }
+}
+
+impl ObjectStore for ContentStore {
+    type Error = Infallible;
+
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error> {
+        Ok(self.uris.get(&MemoryStore::key(uri)).map(|(_, hash)| {
+            self.objects[hash].data.clone()
+        }))
+    }
+
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error> {
+        let hash: ContentHash = DigestAlgorithm::sha256().digest(
+            data.as_ref()
+        ).as_ref().try_into().expect("SHA-256 digest is not 32 octets");
+        let old = self.uris.insert(MemoryStore::key(&uri), (uri, hash));
+        if let Some((_, old)) = old {
+            if old == hash {
+                return Ok(())
+            }
+            self.release(&old);
+        }
+        self.objects.entry(hash).or_insert_with(|| {
+            StoredObject { data, refs: 0 }
+        }).refs += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        match self.uris.remove(&MemoryStore::key(uri)) {
+            Some((_, hash)) => {
+                self.release(&hash);
+                Ok(true)
+            }
+            None => Ok(false)
+        }
+    }
+
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error> {
+        let start = MemoryStore::key(base);
+        Ok(self.uris.range(start.clone()..).take_while(|(key, _)| {
+            key.starts_with(&start)
+        }).filter_map(|(_, (uri, _))| {
+            if base.is_parent_of(uri) {
+                Some(uri.clone())
+            }
+            else {
+                None
+            }
+        }).collect())
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn content_store() {
+        let one = uri("rsync://example.com/mod/a/one.cer");
+        let two = uri("rsync://example.com/mod/b/two.cer");
+        let three = uri("rsync://example.com/mod/a/three.roa");
+
+        let mut store = ContentStore::new();
+        store.insert(one.clone(), Bytes::from("same")).unwrap();
+        store.insert(two.clone(), Bytes::from("same")).unwrap();
+        store.insert(three.clone(), Bytes::from("other")).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.object_count(), 2);
+        assert_eq!(store.content_size(), 9);
+
+        let hash = store.hash(&one).unwrap().to_vec();
+        assert_eq!(store.hash(&two).unwrap(), hash.as_slice());
+        assert_eq!(store.ref_count(&hash), 2);
+        assert_eq!(store.get_by_hash(&hash), Some(Bytes::from("same")));
+        assert_eq!(
+            store.get(&uri("rsync://EXAMPLE.com/mod/b/two.cer")).unwrap(),
+            Some(Bytes::from("same"))
+        );
+        assert_eq!(
+            store.list(&uri("rsync://example.com/mod/a/")).unwrap(),
+            [one.clone(), three.clone()]
+        );
+
+        store.insert(one.clone(), Bytes::from("other")).unwrap();
+        assert_eq!(store.ref_count(&hash), 1);
+        assert_eq!(store.object_count(), 2);
+        assert!(store.remove(&two).unwrap());
+        assert!(!store.remove(&two).unwrap());
+        assert_eq!(store.ref_count(&hash), 0);
+        assert_eq!(store.get_by_hash(&hash), None);
+        assert_eq!(store.object_count(), 1);
+        assert_eq!(store.iter().count(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+//! Falling back to previously fetched objects.
+//!
+//! When a publication point can’t be refreshed, a relying party usually
+//! continues to use the objects it fetched last time. This keeps routes
+//! valid during short outages of a repository. Objects kept for too long,
+//! however, may describe a state the CA has long moved on from, so there
+//! should be a limit on how old cached data may get.
+//!
+//! The [`LastKnownGood`] type keeps track of when each publication point
+//! was last refreshed successfully and decides, based on a
+//! [`FallbackPolicy`], whether its cached objects may still be used when
+//! refreshing fails. It can also remove the objects of publication points
+//! whose cached data has become too old from an
+//! [object store][super::ObjectStore].
+//!
+//! Publication points are identified by their rsync directory URI, i.e.,
+//! the URI given in the CA certificate’s SIA.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use crate::uri;
+use super::ObjectStore;
+
+
+//------------ FallbackPolicy ------------------------------------------------
+
+/// The policy for using cached objects.
+///
+/// By default, cached objects are used for at most one day after the last
+/// successful refresh.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FallbackPolicy {
+    /// Whether to use cached objects at all.
+    pub fallback: bool,
+
+    /// The maximum age of cached objects.
+    ///
+    /// The age is the time since the last successful refresh. If this is
+    /// `None`, cached objects can be used forever.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        FallbackPolicy {
+            fallback: true,
+            max_age: Some(Duration::from_secs(86400)),
+        }
+    }
+}
+
+
+//------------ Fallback ------------------------------------------------------
+
+/// What to do with a publication point that couldn’t be refreshed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fallback {
+    /// Use the cached objects.
+    ///
+    /// Contains the age of the cached objects.
+    Cached(Duration),
+
+    /// The cached objects are too old and must not be used.
+    ///
+    /// Contains the age of the cached objects.
+    Expired(Duration),
+
+    /// There are no cached objects.
+    ///
+    /// Either the publication point was never refreshed successfully or
+    /// the policy doesn’t allow using cached objects.
+    Unavailable,
+}
+
+impl Fallback {
+    /// Returns whether cached objects can be used.
+    pub fn is_cached(self) -> bool {
+        matches!(self, Fallback::Cached(_))
+    }
+
+    /// Returns the age of the cached objects if there are any.
+    pub fn age(self) -> Option<Duration> {
+        match self {
+            Fallback::Cached(age) | Fallback::Expired(age) => Some(age),
+            Fallback::Unavailable => None,
+        }
+    }
+}
+
+
+//------------ LastKnownGood -------------------------------------------------
+
+/// Keeps track of when publication points were last refreshed.
+///
+/// See the [module documentation][self] for how to use it.
+#[derive(Clone, Debug, Default)]
+pub struct LastKnownGood {
+    /// The policy to apply.
+    policy: FallbackPolicy,
+
+    /// The time of the last successful refresh of each publication point.
+    refreshed: HashMap<uri::Rsync, DateTime<Utc>>,
+}
+
+impl LastKnownGood {
+    /// Creates a new value using the given policy.
+    pub fn new(policy: FallbackPolicy) -> Self {
+        LastKnownGood { policy, refreshed: HashMap::new() }
+    }
+
+    /// Returns the policy.
+    pub fn policy(&self) -> &FallbackPolicy {
+        &self.policy
+    }
+
+    /// Returns the time of the last successful refresh of a point.
+    pub fn last_refresh(&self, base: &uri::Rsync) -> Option<DateTime<Utc>> {
+        self.refreshed.get(base).copied()
+    }
+
+    /// Returns an iterator over all points and their last refresh.
+    pub fn iter(
+        &self
+    ) -> impl Iterator<Item = (&uri::Rsync, DateTime<Utc>)> + '_ {
+        self.refreshed.iter().map(|(key, value)| (key, *value))
+    }
+
+    /// Records a successful refresh of the publication point at `base`.
+    ///
+    /// This can also be used to restore the state kept from an earlier
+    /// run.
+    pub fn refreshed(&mut self, base: uri::Rsync, now: DateTime<Utc>) {
+        self.refreshed.insert(base, now);
+    }
+
+    /// Decides what to do after refreshing a publication point failed.
+    pub fn fallback(&self, base: &uri::Rsync, now: DateTime<Utc>) -> Fallback {
+        if !self.policy.fallback {
+            return Fallback::Unavailable
+        }
+        let age = match self.refreshed.get(base) {
+            Some(refreshed) => {
+                now.signed_duration_since(*refreshed).to_std()
+                    .unwrap_or_default()
+            }
+            None => return Fallback::Unavailable,
+        };
+        match self.policy.max_age {
+            Some(max_age) if age > max_age => Fallback::Expired(age),
+            _ => Fallback::Cached(age),
+        }
+    }
+
+    /// Removes the objects of points whose cached data is too old.
+    ///
+    /// All objects below publication points with expired cached data are
+    /// removed from `store` and the points are forgotten. Returns the
+    /// URIs of the removed publication points.
+    pub fn expire<S: ObjectStore>(
+        &mut self, store: &mut S, now: DateTime<Utc>
+    ) -> Result<Vec<uri::Rsync>, S::Error> {
+        let expired: Vec<_> = self.refreshed.keys().filter(|base| {
+            matches!(self.fallback(base, now), Fallback::Expired(_))
+        }).cloned().collect();
+        for base in &expired {
+            let objects = store.list(base)?;
+            info!(
+                "{}: removing {} expired cached objects",
+                base, objects.len()
+            );
+            for uri in objects {
+                if !store.remove(&uri)? {
+                    warn!("{}: disappeared while expiring", uri);
+                }
+            }
+            self.refreshed.remove(base);
+        }
+        Ok(expired)
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use bytes::Bytes;
+    use chrono::TimeZone;
+    use crate::store::MemoryStore;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn fallback() {
+        let mut good = LastKnownGood::default();
+        let base = uri("rsync://example.com/mod/ca/");
+        assert_eq!(good.fallback(&base, time(0)), Fallback::Unavailable);
+        good.refreshed(base.clone(), time(0));
+        assert_eq!(
+            good.fallback(&base, time(3600)),
+            Fallback::Cached(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            good.fallback(&uri("rsync://EXAMPLE.com/mod/ca/"), time(86400)),
+            Fallback::Cached(Duration::from_secs(86400))
+        );
+        assert_eq!(
+            good.fallback(&base, time(86401)),
+            Fallback::Expired(Duration::from_secs(86401))
+        );
+
+        let mut good = LastKnownGood::new(FallbackPolicy {
+            fallback: false, .. Default::default()
+        });
+        good.refreshed(base.clone(), time(0));
+        assert_eq!(good.fallback(&base, time(1)), Fallback::Unavailable);
+    }
+
+    #[test]
+    fn expire() {
+        let mut store = MemoryStore::new();
+        store.insert(
+            uri("rsync://example.com/mod/old/a.cer"), Bytes::from("a")
+        ).unwrap();
+        store.insert(
+            uri("rsync://example.com/mod/new/b.cer"), Bytes::from("b")
+        ).unwrap();
+        let mut good = LastKnownGood::default();
+        good.refreshed(uri("rsync://example.com/mod/old/"), time(0));
+        good.refreshed(uri("rsync://example.com/mod/new/"), time(86400));
+        let expired = good.expire(&mut store, time(100000)).unwrap();
+        assert_eq!(expired, [uri("rsync://example.com/mod/old/")]);
+        assert_eq!(store.len(), 1);
+        assert!(good.last_refresh(&expired[0]).is_none());
+        assert!(
+            good.last_refresh(&uri("rsync://example.com/mod/new/")).is_some()
+        );
+    }
+}
@@ -0,0 +1,491 @@
+//! Keeping repository objects in a directory tree.
+//!
+//! The classic way of keeping a copy of an RPKI repository is a directory
+//! tree that mirrors the structure of the rsync URIs of the objects: below
+//! some base directory, there is one directory per host, one directory per
+//! module inside it, and the path of the URI below that. This is the
+//! layout rsync itself produces when mirroring a module and is what the
+//! [rsync fetcher][crate::fetch::rsync] uses for its local mirrors.
+//!
+//! The [`FsStore`] maps between rsync URIs and paths in this layout and
+//! implements [`ObjectStore`] on top of it. Files are written atomically
+//! by first writing to a temporary file in the same directory and then
+//! renaming it, so readers never see partially written objects. On Unix
+//! systems, the write permissions of the file are removed before renaming
+//! it to mark it as finished. When objects are removed, directories that
+//! become empty are removed, too.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bytes::Bytes;
+use crate::uri;
+use crate::util::mmap;
+use super::ObjectStore;
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The prefix of the names of temporary files.
+///
+/// Files with names starting with this prefix are ignored when listing
+/// objects.
+const TMP_PREFIX: &str = ".tmp-";
+
+/// A counter for creating unique temporary file names.
+static TMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+
+//------------ FsStore -------------------------------------------------------
+
+/// An object store keeping objects in a directory tree.
+///
+/// The path of an object is constructed by appending the lowercase
+/// authority, the module name, and the path segments of its URI to the
+/// base directory. See [`local_path`][Self::local_path] for details.
+#[derive(Clone, Debug)]
+pub struct FsStore {
+    /// The base directory of the tree.
+    base: PathBuf,
+
+    /// The size from which on files are memory-mapped when read.
+    mmap_threshold: Option<u64>,
+}
+
+impl FsStore {
+    /// Creates a new store using the given base directory.
+    ///
+    /// The directory doesn’t need to exist yet. It is created when the
+    /// first object is written.
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        FsStore { base: base.into(), mmap_threshold: None }
+    }
+
+    /// Returns the base directory of the store.
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Sets the size from which on files are memory-mapped when read.
+    ///
+    /// If a threshold is set, files of at least this size are mapped into
+    /// memory via [`util::mmap`][crate::util::mmap] rather than read. This
+    /// only has an effect if the `"mmap"` feature is enabled and on Unix
+    /// systems. By default, files are always read.
+    ///
+    /// Since a mapped file must not be truncated while it is in use, only
+    /// files nobody has write permission for are mapped. These are the
+    /// files written by [`write`][Self::write] or otherwise made
+    /// read-only. All other files are read even if they are large.
+    pub fn set_mmap_threshold(&mut self, threshold: Option<u64>) {
+        self.mmap_threshold = threshold
+    }
+
+    /// Returns the path for the given URI.
+    ///
+    /// The path is constructed by appending the lowercase authority, the
+    /// module name, and the path segments of the URI to the base
+    /// directory. If the URI is a directory URI, the path is that of the
+    /// directory.
+    ///
+    /// The method makes sure that the path cannot escape the base
+    /// directory. It returns an error if the authority, the module name,
+    /// or any of the path segments is a single or double full stop or
+    /// contains a character that would be interpreted as a path separator
+    /// or is otherwise not allowed in a file name.
+    pub fn local_path(
+        &self, uri: &uri::Rsync
+    ) -> Result<PathBuf, io::Error> {
+        let authority = uri.canonical_authority();
+        let mut res = self.base.clone();
+        for part in [authority.as_ref(), uri.module_name()] {
+            push_segment(&mut res, part, uri)?;
+        }
+        for segment in uri.path().split('/') {
+            if !segment.is_empty() {
+                push_segment(&mut res, segment, uri)?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the URI for the given path.
+    ///
+    /// This is the reverse of [`local_path`][Self::local_path] for files.
+    /// Returns `None` if the path is not below the base directory or does
+    /// not result in a valid rsync URI.
+    pub fn uri_for_path(&self, path: &Path) -> Option<uri::Rsync> {
+        let path = path.strip_prefix(&self.base).ok()?;
+        let mut res = String::from("rsync://");
+        for (idx, component) in path.components().enumerate() {
+            let segment = match component {
+                Component::Normal(segment) => segment.to_str()?,
+                _ => return None
+            };
+            if idx > 0 {
+                res.push('/');
+            }
+            res.push_str(segment);
+        }
+        uri::Rsync::from_string(res).ok()
+    }
+
+    /// Atomically writes the object with the given URI.
+    ///
+    /// The data is first written to a temporary file in the target
+    /// directory which is then renamed to its final name. On Unix systems,
+    /// the write permissions of the temporary file are removed before
+    /// renaming it.
+    pub fn write(
+        &self, uri: &uri::Rsync, data: &[u8]
+    ) -> Result<(), io::Error> {
+        if uri.path_is_dir() {
+            return Err(bad_uri(uri))
+        }
+        let path = self.local_path(uri)?;
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name),
+            _ => return Err(bad_uri(uri))
+        };
+        fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!(
+            "{}{}-{}-{}", TMP_PREFIX, name.to_string_lossy(),
+            std::process::id(),
+            TMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(err) = fs::write(&tmp, data).and_then(|_| seal(&tmp)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err)
+        }
+        if let Err(err) = fs::rename(&tmp, &path) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err)
+        }
+        Ok(())
+    }
+
+    /// Reads the object with the given URI.
+    ///
+    /// Returns `Ok(None)` if there is no such object.
+    pub fn read(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<Bytes>, io::Error> {
+        let path = self.local_path(uri)?;
+        let res = match self.mmap_threshold {
+            Some(threshold) => mmap::read_file(path, threshold),
+            None => fs::read(path).map(Into::into),
+        };
+        match res {
+            Ok(data) => Ok(Some(data)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Returns the size of the object with the given URI.
+    ///
+    /// The size is taken from the file’s metadata without reading it.
+    pub fn file_size(
+        &self, uri: &uri::Rsync
+    ) -> Result<Option<u64>, io::Error> {
+        match fs::metadata(self.local_path(uri)?) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(None)
+            }
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Deletes the object with the given URI.
+    ///
+    /// Any directories that become empty by removing the file are removed
+    /// as well, up to but excluding the base directory. Returns whether
+    /// there was an object with the URI.
+    pub fn delete(&self, uri: &uri::Rsync) -> Result<bool, io::Error> {
+        let path = self.local_path(uri)?;
+        match fs::remove_file(&path) {
+            Ok(()) => { }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(false)
+            }
+            Err(err) => return Err(err)
+        }
+        let mut dir = path.parent();
+        while let Some(path) = dir {
+            if path == self.base || fs::remove_dir(path).is_err() {
+                break
+            }
+            dir = path.parent();
+        }
+        Ok(true)
+    }
+
+    /// Deletes all objects below `base` for which `keep` returns false.
+    ///
+    /// This can be used to clean up withdrawn objects after updating a
+    /// directory. Returns the number of deleted objects.
+    pub fn retain(
+        &self, base: &uri::Rsync, mut keep: impl FnMut(&uri::Rsync) -> bool
+    ) -> Result<usize, io::Error> {
+        let mut res = 0;
+        for uri in self.list_files(base)? {
+            if !keep(&uri) && self.delete(&uri)? {
+                res += 1;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the URIs of all files below the given directory URI.
+    pub fn list_files(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, io::Error> {
+        let mut res = Vec::new();
+        let path = self.local_path(base)?;
+        list_dir(base, &path, &mut res)?;
+        Ok(res)
+    }
+}
+
+
+//--- ObjectStore
+
+impl ObjectStore for FsStore {
+    type Error = io::Error;
+
+    fn get(&self, uri: &uri::Rsync) -> Result<Option<Bytes>, Self::Error> {
+        self.read(uri)
+    }
+
+    fn insert(
+        &mut self, uri: uri::Rsync, data: Bytes
+    ) -> Result<(), Self::Error> {
+        self.write(&uri, &data)
+    }
+
+    fn remove(&mut self, uri: &uri::Rsync) -> Result<bool, Self::Error> {
+        self.delete(uri)
+    }
+
+    fn list(
+        &self, base: &uri::Rsync
+    ) -> Result<Vec<uri::Rsync>, Self::Error> {
+        self.list_files(base)
+    }
+
+    fn size(&self, uri: &uri::Rsync) -> Result<Option<u64>, Self::Error> {
+        self.file_size(uri)
+    }
+}
+
+
+//------------ Helper Functions ----------------------------------------------
+
+/// Appends a segment to a path checking that it is safe to do so.
+fn push_segment(
+    path: &mut PathBuf, segment: &str, uri: &uri::Rsync
+) -> Result<(), io::Error> {
+    if segment.is_empty()
+        || segment == "."
+        || segment == ".."
+        || segment.starts_with(TMP_PREFIX)
+        || segment.contains(&['/', '\\', '\0'][..])
+    {
+        return Err(bad_uri(uri))
+    }
+    let mut components = Path::new(segment).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => { }
+        _ => return Err(bad_uri(uri))
+    }
+    path.push(segment);
+    Ok(())
+}
+
+/// Adds the URIs of the files in a directory and its sub-directories.
+fn list_dir(
+    uri: &uri::Rsync, path: &Path, res: &mut Vec<uri::Rsync>
+) -> Result<(), io::Error> {
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(())
+        }
+        Err(err) => return Err(err)
+    };
+    for entry in dir {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+        if name.starts_with(TMP_PREFIX) {
+            continue
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if let Ok(dir_uri) = uri.join(format!("{}/", name).as_bytes()) {
+                list_dir(&dir_uri, &entry.path(), res)?;
+            }
+        }
+        else if file_type.is_file() {
+            if let Ok(file_uri) = uri.join(name.as_bytes()) {
+                res.push(file_uri)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Marks a completely written file as finished.
+///
+/// On Unix systems, this removes all write permissions from the file so
+/// it may be memory-mapped safely. On other systems, it does nothing.
+fn seal(path: &Path) -> Result<(), io::Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut perm = fs::metadata(path)?.permissions();
+        perm.set_mode(perm.mode() & !0o222);
+        fs::set_permissions(path, perm)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// Creates the error for a URI that cannot be mapped to a path.
+fn bad_uri(uri: &uri::Rsync) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("URI {} cannot be mapped to a local path", uri)
+    )
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+    use super::*;
+
+    fn uri(s: &str) -> uri::Rsync {
+        uri::Rsync::from_str(s).unwrap()
+    }
+
+    /// Returns a new, empty, temporary directory.
+    fn temp_dir() -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let res = std::env::temp_dir().join(format!(
+            "rpki-fs-store-test-{}-{}",
+            std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&res);
+        fs::create_dir_all(&res).unwrap();
+        res
+    }
+
+    #[test]
+    fn local_path() {
+        let store = FsStore::new("/var/cache/rsync");
+        assert_eq!(
+            store.local_path(
+                &uri("rsync://Example.COM/mod/some/path/")
+            ).unwrap(),
+            Path::new("/var/cache/rsync/example.com/mod/some/path")
+        );
+        assert_eq!(
+            store.uri_for_path(
+                Path::new("/var/cache/rsync/example.com/mod/some/file.cer")
+            ),
+            Some(uri("rsync://example.com/mod/some/file.cer"))
+        );
+        assert_eq!(
+            store.uri_for_path(Path::new("/var/cache/other/file.cer")),
+            None
+        );
+        assert!(store.local_path(&uri("rsync://../mod/path/")).is_err());
+        assert!(store.local_path(&uri("rsync://host/../path/")).is_err());
+        assert!(store.local_path(&uri("rsync://host/m/.tmp-x")).is_err());
+    }
+
+    #[test]
+    fn mmap_read() {
+        let dir = temp_dir();
+        let mut store = FsStore::new(&dir);
+        store.set_mmap_threshold(Some(2));
+        let one = uri("rsync://example.com/mod/one.cer");
+        store.write(&one, b"one").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(store.local_path(&one).unwrap()).unwrap()
+                    .permissions().mode() & 0o222,
+                0
+            );
+        }
+        assert_eq!(store.read(&one).unwrap(), Some(Bytes::from("one")));
+        store.write(&one, b"new one").unwrap();
+        assert_eq!(store.read(&one).unwrap(), Some(Bytes::from("new one")));
+        assert_eq!(
+            store.read(&uri("rsync://example.com/mod/two.cer")).unwrap(),
+            None
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_write() {
+        let dir = temp_dir();
+        let mut store = FsStore::new(&dir);
+        let one = uri("rsync://example.com/mod/a/one.cer");
+        let two = uri("rsync://example.com/mod/a/b/two.roa");
+        store.insert(one.clone(), Bytes::from("one")).unwrap();
+        store.insert(two.clone(), Bytes::from("two")).unwrap();
+        store.insert(two.clone(), Bytes::from("new two")).unwrap();
+        assert!(
+            store.write(&uri("rsync://example.com/mod/a/"), b"x").is_err()
+        );
+
+        assert_eq!(store.get(&one).unwrap(), Some(Bytes::from("one")));
+        assert_eq!(store.get(&two).unwrap(), Some(Bytes::from("new two")));
+        assert_eq!(store.size(&two).unwrap(), Some(7));
+        assert_eq!(
+            store.size(&uri("rsync://example.com/mod/a/none.cer")).unwrap(),
+            None
+        );
+        let mut list = store.list(&uri("rsync://example.com/mod/")).unwrap();
+        list.sort_by(|l, r| l.as_str().cmp(r.as_str()));
+        assert_eq!(list, [two.clone(), one.clone()]);
+
+        // Leftover temporary files are ignored.
+        fs::write(
+            dir.join("example.com/mod/a/.tmp-three.cer-1-1"), b"three"
+        ).unwrap();
+        assert_eq!(
+            store.list(&uri("rsync://example.com/mod/")).unwrap().len(), 2
+        );
+
+        assert_eq!(
+            store.retain(&uri("rsync://example.com/mod/"), |uri| {
+                *uri == one
+            }).unwrap(),
+            1
+        );
+        assert!(!dir.join("example.com/mod/a/b").exists());
+        assert!(store.remove(&one).unwrap());
+        assert!(!store.remove(&one).unwrap());
+        assert!(dir.join("example.com/mod/a").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
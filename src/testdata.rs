@@ -0,0 +1,468 @@
+//! Test vectors for use in tests.
+//!
+//! This module provides the example messages and objects the crate uses in
+//! its own tests so that they can be reused by other crates in theirs. The
+//! data is grouped by the specification it belongs to. Messages of the XML
+//! based protocols are provided as strings, objects and CMS-wrapped
+//! messages as byte slices containing their DER (or, occasionally, BER)
+//! encoding.
+//!
+//! Where a specification has both valid and invalid examples, the invalid
+//! ones are collected in a `bad` sub-module. Each constant’s documentation
+//! describes why the data is invalid.
+//!
+//! The module is only available if the `"testdata"` feature is enabled.
+//! It doesn’t depend on any other features.
+
+#![cfg(feature = "testdata")]
+
+
+//------------ rfc8181 -------------------------------------------------------
+
+/// Messages of the publication protocol defined in RFC 8181.
+pub mod rfc8181 {
+    /// A list query.
+    pub const LIST: &str = include_str!(
+        "../test-data/ca/rfc8181/list.xml"
+    );
+
+    /// A list reply with multiple elements.
+    pub const LIST_REPLY: &str = include_str!(
+        "../test-data/ca/rfc8181/list-reply.xml"
+    );
+
+    /// A list reply with a single element.
+    pub const LIST_REPLY_SINGLE: &str = include_str!(
+        "../test-data/ca/rfc8181/list-reply-single.xml"
+    );
+
+    /// An empty list reply.
+    pub const LIST_REPLY_EMPTY: &str = include_str!(
+        "../test-data/ca/rfc8181/list-reply-empty.xml"
+    );
+
+    /// An empty list reply using an empty-element tag.
+    pub const LIST_REPLY_EMPTY_SHORT: &str = include_str!(
+        "../test-data/ca/rfc8181/list-reply-empty-short.xml"
+    );
+
+    /// A publish query with a single element.
+    pub const PUBLISH_SINGLE: &str = include_str!(
+        "../test-data/ca/rfc8181/publish-single.xml"
+    );
+
+    /// A publish query with publish, update, and withdraw elements.
+    pub const PUBLISH_MULTI: &str = include_str!(
+        "../test-data/ca/rfc8181/publish-multi.xml"
+    );
+
+    /// An empty publish query.
+    pub const PUBLISH_EMPTY: &str = include_str!(
+        "../test-data/ca/rfc8181/publish-empty.xml"
+    );
+
+    /// An empty publish query using an empty-element tag.
+    pub const PUBLISH_EMPTY_SHORT: &str = include_str!(
+        "../test-data/ca/rfc8181/publish-empty-short.xml"
+    );
+
+    /// A success reply.
+    pub const SUCCESS_REPLY: &str = include_str!(
+        "../test-data/ca/rfc8181/success-reply.xml"
+    );
+
+    /// An error reply.
+    pub const ERROR_REPLY: &str = include_str!(
+        "../test-data/ca/rfc8181/error-reply.xml"
+    );
+
+    /// All messages.
+    pub const ALL: &[&str] = &[
+        LIST, LIST_REPLY, LIST_REPLY_SINGLE, LIST_REPLY_EMPTY,
+        LIST_REPLY_EMPTY_SHORT, PUBLISH_SINGLE, PUBLISH_MULTI,
+        PUBLISH_EMPTY, PUBLISH_EMPTY_SHORT, SUCCESS_REPLY, ERROR_REPLY,
+    ];
+}
+
+
+//------------ rfc6492 -------------------------------------------------------
+
+/// Messages of the provisioning protocol defined in RFC 6492.
+///
+/// Most of the messages are provided as the signed CMS messages exchanged
+/// between parent and child. Some are only available as the bare XML
+/// content.
+pub mod rfc6492 {
+    /// A signed list query.
+    pub const LIST: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/list.der"
+    );
+
+    /// A signed list response.
+    ///
+    /// Note that this message is BER encoded.
+    pub const LIST_RESPONSE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/list-response.ber"
+    );
+
+    /// A signed issue query.
+    pub const ISSUE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/issue.der"
+    );
+
+    /// A signed issue response.
+    pub const ISSUE_RESPONSE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/issue-response.der"
+    );
+
+    /// A signed list response as sent by AFRINIC.
+    pub const AFRINIC_RESPONSE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/afrinic-response.der"
+    );
+
+    /// A signed list response as sent by APNIC.
+    pub const APNIC_RESPONSE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/apnic-response.der"
+    );
+
+    /// A signed list response as sent by the APNIC test bed.
+    pub const APNIC_TESTBED_RESPONSE: &[u8] = include_bytes!(
+        "../test-data/ca/rfc6492/apnic-testbed-response.der"
+    );
+
+    /// All signed messages.
+    pub const ALL_SIGNED: &[&[u8]] = &[
+        LIST, LIST_RESPONSE, ISSUE, ISSUE_RESPONSE, AFRINIC_RESPONSE,
+        APNIC_RESPONSE, APNIC_TESTBED_RESPONSE,
+    ];
+
+    /// The XML content of a revoke query.
+    pub const REVOKE: &str = include_str!(
+        "../test-data/ca/rfc6492/revoke-req.xml"
+    );
+
+    /// The XML content of a revoke response.
+    pub const REVOKE_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc6492/revoke-response.xml"
+    );
+
+    /// The XML content of an error response.
+    pub const NOT_PERFORMED_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc6492/not-performed-response.xml"
+    );
+
+    /// All XML messages.
+    pub const ALL_XML: &[&str] = &[
+        REVOKE, REVOKE_RESPONSE, NOT_PERFORMED_RESPONSE,
+    ];
+}
+
+
+//------------ rfc8183 -------------------------------------------------------
+
+/// Out-of-band setup messages defined in RFC 8183.
+pub mod rfc8183 {
+    /// A child request as produced by rpkid.
+    pub const CHILD_REQUEST: &str = include_str!(
+        "../test-data/ca/rfc8183/rpkid-child-id.xml"
+    );
+
+    /// A parent response as produced by rpkid offering repository service.
+    pub const PARENT_RESPONSE_OFFER: &str = include_str!(
+        "../test-data/ca/rfc8183/rpkid-parent-response-offer.xml"
+    );
+
+    /// A parent response as produced by rpkid with a repository referral.
+    pub const PARENT_RESPONSE_REFERRAL: &str = include_str!(
+        "../test-data/ca/rfc8183/rpkid-parent-response-referral.xml"
+    );
+
+    /// A parent response as produced by APNIC.
+    pub const APNIC_PARENT_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc8183/apnic-parent-response.xml"
+    );
+
+    /// A parent response as produced by AFRINIC.
+    pub const AFRINIC_PARENT_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc8183/afrinic-parent-response.xml"
+    );
+
+    /// A parent response as produced by Krill 0.9.
+    pub const KRILL_PARENT_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc8183/krill-0-9-parent-response.xml"
+    );
+
+    /// A publisher request as produced by rpkid.
+    pub const PUBLISHER_REQUEST: &str = include_str!(
+        "../test-data/ca/rfc8183/rpkid-publisher-request.xml"
+    );
+
+    /// A repository response as produced by APNIC.
+    pub const APNIC_REPOSITORY_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc8183/apnic-repository-response.xml"
+    );
+
+    /// A repository response as produced by Krill 0.9.
+    pub const KRILL_REPOSITORY_RESPONSE: &str = include_str!(
+        "../test-data/ca/rfc8183/krill-0-9-repository-response.xml"
+    );
+
+    /// All parent responses.
+    pub const PARENT_RESPONSES: &[&str] = &[
+        PARENT_RESPONSE_OFFER, PARENT_RESPONSE_REFERRAL,
+        APNIC_PARENT_RESPONSE, AFRINIC_PARENT_RESPONSE,
+        KRILL_PARENT_RESPONSE,
+    ];
+
+    /// All repository responses.
+    pub const REPOSITORY_RESPONSES: &[&str] = &[
+        APNIC_REPOSITORY_RESPONSE, KRILL_REPOSITORY_RESPONSE,
+    ];
+}
+
+
+//------------ rfc8182 -------------------------------------------------------
+
+/// RRDP files defined in RFC 8182.
+pub mod rfc8182 {
+    /// A notification file.
+    pub const NOTIFICATION: &[u8] = include_bytes!(
+        "../test-data/rrdp/ripe-notification.xml"
+    );
+
+    /// The notification file, gzip-compressed.
+    pub const NOTIFICATION_GZIP: &[u8] = include_bytes!(
+        "../test-data/rrdp/ripe-notification.xml.gz"
+    );
+
+    /// The notification file with its deltas in reverse order.
+    pub const NOTIFICATION_UNSORTED: &[u8] = include_bytes!(
+        "../test-data/rrdp/ripe-notification-unsorted.xml"
+    );
+
+    /// A snapshot file.
+    pub const SNAPSHOT: &[u8] = include_bytes!(
+        "../test-data/rrdp/ripe-snapshot.xml"
+    );
+
+    /// A delta file.
+    pub const DELTA: &[u8] = include_bytes!(
+        "../test-data/rrdp/ripe-delta.xml"
+    );
+
+    /// Invalid RRDP files.
+    pub mod bad {
+        /// A notification file with gaps in its list of deltas.
+        ///
+        /// The file is well-formed but the deltas cannot be used.
+        pub const NOTIFICATION_WITH_GAPS: &[u8] = include_bytes!(
+            "../test-data/rrdp/ripe-notification-with-gaps.xml"
+        );
+
+        /// A notification file containing an XML entity expansion attack.
+        pub const NOTIFICATION_LOLZ: &[u8] = include_bytes!(
+            "../test-data/rrdp/lolz-notification.xml"
+        );
+    }
+}
+
+
+//------------ repository ----------------------------------------------------
+
+/// RPKI repository objects.
+///
+/// Not all of these objects strictly follow the encoding rules. They
+/// should be decoded with `strict` set to `false`.
+pub mod repository {
+    /// A trust anchor certificate.
+    pub const TA_CER: &[u8] = include_bytes!(
+        "../test-data/repository/ta.cer"
+    );
+
+    /// The CRL of the trust anchor.
+    pub const TA_CRL: &[u8] = include_bytes!(
+        "../test-data/repository/ta.crl"
+    );
+
+    /// The manifest of the trust anchor.
+    pub const TA_MFT: &[u8] = include_bytes!(
+        "../test-data/repository/ta.mft"
+    );
+
+    /// A CA certificate.
+    pub const CA1_CER: &[u8] = include_bytes!(
+        "../test-data/repository/ca1.cer"
+    );
+
+    /// The CRL of the CA.
+    pub const CA1_CRL: &[u8] = include_bytes!(
+        "../test-data/repository/ca1.crl"
+    );
+
+    /// The manifest of the CA.
+    pub const CA1_MFT: &[u8] = include_bytes!(
+        "../test-data/repository/ca1.mft"
+    );
+
+    /// A BGPsec router certificate.
+    pub const ROUTER_CER: &[u8] = include_bytes!(
+        "../test-data/repository/router.cer"
+    );
+
+    /// A ROA.
+    pub const ROA: &[u8] = include_bytes!(
+        "../test-data/repository/example-ripe.roa"
+    );
+
+    /// The content of an ASPA object.
+    pub const ASPA_CONTENT: &[u8] = include_bytes!(
+        "../test-data/repository/aspa-content.der"
+    );
+
+    /// A trust anchor locator.
+    pub const TAL: &[u8] = include_bytes!(
+        "../test-data/repository/ripe.tal"
+    );
+
+    /// The trust anchor certificate referenced by [`TAL`].
+    pub const TAL_CER: &[u8] = TA_CER;
+
+    /// All certificates.
+    pub const CERTS: &[&[u8]] = &[TA_CER, CA1_CER, ROUTER_CER];
+
+    /// All CRLs.
+    pub const CRLS: &[&[u8]] = &[TA_CRL, CA1_CRL];
+
+    /// All manifests.
+    pub const MANIFESTS: &[&[u8]] = &[TA_MFT, CA1_MFT];
+
+    /// Invalid repository objects.
+    pub mod bad {
+        /// A ROA with an address prefix longer than the address.
+        pub const ROA_PREFIX_LEN_OVERFLOW: &[u8] = include_bytes!(
+            "../test-data/repository/prefix-len-overflow.roa"
+        );
+
+        /// A ROA with a max length longer than the address.
+        pub const ROA_MAXLEN_OVERFLOW: &[u8] = include_bytes!(
+            "../test-data/repository/maxlen-overflow.roa"
+        );
+
+        /// A ROA with a max length shorter than the prefix length.
+        pub const ROA_MAXLEN_UNDERFLOW: &[u8] = include_bytes!(
+            "../test-data/repository/maxlen-underflow.roa"
+        );
+
+        /// All ROAs that fail decoding.
+        pub const ROAS: &[&[u8]] = &[
+            ROA_PREFIX_LEN_OVERFLOW, ROA_MAXLEN_OVERFLOW,
+            ROA_MAXLEN_UNDERFLOW,
+        ];
+
+        /// A manifest whose EE certificate uses the wrong signature algorithm.
+        ///
+        /// The manifest decodes but the EE certificate fails inspection.
+        pub const MFT_SIGNATURE_ALG_MISMATCH: &[u8] = include_bytes!(
+            "../test-data/repository/signature-alg-mismatch.mft"
+        );
+
+        /// The content of an ASPA object following draft version 13.
+        ///
+        /// This version of the content is not supported anymore.
+        pub const ASPA_CONTENT_DRAFT_13: &[u8] = include_bytes!(
+            "../test-data/repository/aspa-content-draft-13.der"
+        );
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(feature = "ca")]
+    fn protocol_messages() {
+        use crate::ca::{idexchange, provisioning, publication, sigmsg};
+        use super::*;
+
+        for msg in rfc8181::ALL {
+            publication::Message::decode(msg.as_bytes()).unwrap();
+        }
+        for msg in rfc6492::ALL_SIGNED {
+            let msg = sigmsg::SignedMessage::decode(*msg, false).unwrap();
+            provisioning::Message::decode(
+                msg.content().to_bytes().as_ref()
+            ).unwrap();
+        }
+        for msg in rfc6492::ALL_XML {
+            provisioning::Message::decode(msg.as_bytes()).unwrap();
+        }
+        idexchange::ChildRequest::parse(
+            rfc8183::CHILD_REQUEST.as_bytes()
+        ).unwrap();
+        idexchange::PublisherRequest::parse(
+            rfc8183::PUBLISHER_REQUEST.as_bytes()
+        ).unwrap();
+        for msg in rfc8183::PARENT_RESPONSES {
+            idexchange::ParentResponse::parse(msg.as_bytes()).unwrap();
+        }
+        for msg in rfc8183::REPOSITORY_RESPONSES {
+            idexchange::RepositoryResponse::parse(msg.as_bytes()).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rrdp")]
+    fn rrdp_files() {
+        use crate::rrdp::{Delta, NotificationFile, Snapshot};
+        use super::*;
+
+        NotificationFile::parse(rfc8182::NOTIFICATION).unwrap();
+        NotificationFile::parse(rfc8182::NOTIFICATION_UNSORTED).unwrap();
+        Snapshot::parse(rfc8182::SNAPSHOT).unwrap();
+        Delta::parse(rfc8182::DELTA).unwrap();
+        assert!(
+            !NotificationFile::parse(
+                rfc8182::bad::NOTIFICATION_WITH_GAPS
+            ).unwrap().sort_and_verify_deltas(None)
+        );
+        assert!(
+            NotificationFile::parse(rfc8182::bad::NOTIFICATION_LOLZ).is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "repository")]
+    fn repository_objects() {
+        use bytes::Bytes;
+        use crate::repository::{Cert, Crl, Manifest, Roa};
+        use crate::repository::tal::Tal;
+        use super::*;
+
+        for der in repository::CERTS {
+            Cert::decode(Bytes::from_static(der)).unwrap();
+        }
+        for der in repository::CRLS {
+            Crl::decode(Bytes::from_static(der)).unwrap();
+        }
+        for der in repository::MANIFESTS {
+            Manifest::decode(Bytes::from_static(der), false).unwrap();
+        }
+        Roa::decode(Bytes::from_static(repository::ROA), false).unwrap();
+        let mut tal = repository::TAL;
+        Tal::read("ripe.tal", &mut tal).unwrap();
+
+        for der in repository::bad::ROAS {
+            assert!(Roa::decode(Bytes::from_static(der), false).is_err());
+        }
+        assert!(
+            Manifest::decode(
+                Bytes::from_static(
+                    repository::bad::MFT_SIGNATURE_ALG_MISMATCH
+                ),
+                false
+            ).unwrap().cert().inspect_ee(true).is_err()
+        );
+    }
+}
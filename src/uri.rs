@@ -1,9 +1,10 @@
 //! URIs.
 
-use std::{error, fmt, hash, str};
+use std::{cmp, error, fmt, hash, str};
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use bytes::{BufMut, Bytes, BytesMut};
+use crate::util::intern::Interner;
 
 #[cfg(feature = "repository")] use std::io;
 #[cfg(feature = "repository")] use bcder::encode;
@@ -118,6 +119,18 @@ impl Rsync {
         self.bytes = Bytes::copy_from_slice(self.bytes.as_ref());
     }
 
+    /// Returns a copy of the URI sharing memory via an interner.
+    ///
+    /// The returned URI is identical to this one but its octets are kept
+    /// by the interner and shared with all identical URIs interned before.
+    pub fn intern(&self, interner: &mut Interner) -> Self {
+        Rsync {
+            bytes: interner.bytes(self.bytes.as_ref()),
+            module_start: self.module_start,
+            path_start: self.path_start,
+        }
+    }
+
     /// Checks additional requirements of the path portion.
     ///
     /// This does _not_ check whether the slice consists of allowed characters
@@ -172,7 +185,7 @@ impl Rsync {
     /// Since host names are case-insensitive, the authority part can be
     /// provided in different ways. This returns a version of the authority
     /// with all ASCII letters in lowercase.
-    pub fn canonical_authority(&self) -> Cow<str> {
+    pub fn canonical_authority(&self) -> Cow<'_, str> {
         let authority = self.authority();
         if authority.as_bytes().iter().any(u8::is_ascii_uppercase) {
             Cow::Owned(authority.to_ascii_lowercase())
@@ -198,7 +211,7 @@ impl Rsync {
     ///
     /// This is the same as the module but with the authority in canonical
     /// form.
-    pub fn canonical_module(&self) -> Cow<str> {
+    pub fn canonical_module(&self) -> Cow<'_, str> {
         if self.authority().as_bytes().iter().any(u8::is_ascii_uppercase) {
             let mut res = String::with_capacity(self.path_start);
             res.push_str("rsync://");
@@ -435,6 +448,33 @@ impl<T: AsRef<[u8]>> PartialEq<T> for Rsync {
 impl Eq for Rsync { }
 
 
+//--- PartialOrd and Ord
+
+/// URIs are ordered by their octets except that the scheme and authority
+/// are compared ignoring ASCII case for consistency with equality.
+impl PartialOrd for Rsync {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rsync {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        let left = self.bytes[..self.module_start].iter().map(|ch| {
+            ch.to_ascii_lowercase()
+        });
+        let right = other.bytes[..other.module_start].iter().map(|ch| {
+            ch.to_ascii_lowercase()
+        });
+        left.cmp(right).then_with(|| {
+            self.bytes[self.module_start..].cmp(
+                &other.bytes[other.module_start..]
+            )
+        })
+    }
+}
+
+
 //--- Hash
 
 impl hash::Hash for Rsync {
@@ -469,7 +509,7 @@ impl<'de> Deserialize<'de> for Rsync {
 //--- PrimitiveContent
 
 #[cfg(feature = "repository")]
-impl<'a> encode::PrimitiveContent for &'a Rsync {
+impl encode::PrimitiveContent for &Rsync {
     const TAG: Tag = Tag::IA5_STRING;
 
     fn encoded_len(&self, _: Mode) -> usize {
@@ -551,6 +591,17 @@ impl Https {
         self.uri = Bytes::copy_from_slice(self.uri.as_ref());
     }
 
+    /// Returns a copy of the URI sharing memory via an interner.
+    ///
+    /// The returned URI is identical to this one but its octets are kept
+    /// by the interner and shared with all identical URIs interned before.
+    pub fn intern(&self, interner: &mut Interner) -> Self {
+        Https {
+            uri: interner.bytes(self.uri.as_ref()),
+            path_idx: self.path_idx,
+        }
+    }
+
     /// Returns a octets slice reference of the URI.
     pub fn as_slice(&self) -> &[u8] {
         self.uri.as_ref()
@@ -576,7 +627,7 @@ impl Https {
     /// Since host names are case-insensitive, the authority part can be
     /// provided in different ways. This returns a version of the authority
     /// with all ASCII letters in lowercase.
-    pub fn canonical_authority(&self) -> Cow<str> {
+    pub fn canonical_authority(&self) -> Cow<'_, str> {
         let authority = self.authority();
         if authority.as_bytes().iter().any(u8::is_ascii_uppercase) {
             Cow::Owned(authority.to_ascii_lowercase())
@@ -744,7 +795,7 @@ impl<'de> Deserialize<'de> for Https {
 //--- PrimitiveContent
 
 #[cfg(feature = "repository")]
-impl<'a> encode::PrimitiveContent for &'a Https {
+impl encode::PrimitiveContent for &Https {
     const TAG: Tag = Tag::IA5_STRING;
 
     fn encoded_len(&self, _: Mode) -> usize {
@@ -892,6 +943,7 @@ fn is_u8_uri_ascii(ch: u8) -> bool {
 //------------ Error ---------------------------------------------------------
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Error {
     InvalidCharacters,
     BadUri,
@@ -929,13 +981,18 @@ mod arbitrary {
         fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
             let mut res = String::from("rsync://");
             append_host(&mut res, u)?;
-            let module_start = res.len() + 1;
-            append_path_segment(&mut res, u)?;
-            let path_start = res.len() + 1;
-            for _ in 0..(u8::arbitrary(u)? % MAX_SEGMENTS){
-                append_path_segment(&mut res, u)?;
+            res.push('/');
+            let module_start = res.len();
+            append_segment(&mut res, u)?;
+            res.push('/');
+            let path_start = res.len();
+            for i in 0..(u8::arbitrary(u)? % MAX_SEGMENTS){
+                if i > 0 {
+                    res.push('/');
+                }
+                append_segment(&mut res, u)?;
             }
-            if bool::arbitrary(u)? {
+            if res.len() > path_start && bool::arbitrary(u)? {
                 res.push('/');
             }
             Ok(Self {
@@ -951,7 +1008,8 @@ mod arbitrary {
             append_host(&mut res, u)?;
             let path_idx = res.len();
             for _ in 0..(u8::arbitrary(u)? % MAX_SEGMENTS){
-                append_path_segment(&mut res, u)?;
+                res.push('/');
+                append_segment(&mut res, u)?;
             }
             if bool::arbitrary(u)? {
                 res.push('/');
@@ -966,7 +1024,9 @@ mod arbitrary {
     fn append_host(
         res: &mut String, u: &mut Unstructured
     ) -> arbitrary::Result<()> {
-        // Up to 255 characters of [.0-9A-Za-z-].
+        // Up to 255 characters of [.0-9A-Za-z-] starting with an
+        // alphanumeric character.
+        append_alphanumeric(res, u)?;
         for _ in 1..u8::arbitrary(u)? {
             append_char(res, u)?;
         }
@@ -979,30 +1039,40 @@ mod arbitrary {
         Ok(())
     }
 
-    fn append_path_segment(
+    fn append_segment(
         res: &mut String, u: &mut Unstructured
     ) -> arbitrary::Result<()> {
-        res.push('/');
+        // Segments must not be empty and must not be "." or "..", so we
+        // always start with an alphanumeric character.
+        append_alphanumeric(res, u)?;
         for _ in 1..u8::arbitrary(u)? % MAX_SEGMENT_LEN {
             append_char(res, u)?;
         }
         Ok(())
     }
 
+    const CHARS: [char; 64] = [
+        '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+        'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
+        'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+        'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm',
+        'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+        '-', '.',
+    ];
+
     fn append_char(
         res: &mut String, u: &mut Unstructured
     ) -> arbitrary::Result<()> {
-        const CHARS: [char; 64] = [
-            '-', '.', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
-            'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
-            'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-            'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm',
-            'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
-        ];
-
         res.push(CHARS[usize::arbitrary(u)? % CHARS.len()]);
         Ok(())
     }
+
+    fn append_alphanumeric(
+        res: &mut String, u: &mut Unstructured
+    ) -> arbitrary::Result<()> {
+        res.push(CHARS[usize::arbitrary(u)? % (CHARS.len() - 2)]);
+        Ok(())
+    }
 }
 
 
@@ -1459,6 +1529,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn intern() {
+        let mut interner = Interner::new();
+        let rsync = Rsync::from_str("rsync://host/module/a/b.cer").unwrap();
+        let first = rsync.intern(&mut interner);
+        let second = Rsync::from_str(
+            "rsync://host/module/a/b.cer"
+        ).unwrap().intern(&mut interner);
+        assert_eq!(first, rsync);
+        assert_eq!(first.module_name(), "module");
+        assert_eq!(first.path(), "a/b.cer");
+        assert_eq!(first.as_str().as_ptr(), second.as_str().as_ptr());
+
+        let https = Https::from_str("https://host/rrdp/n.xml").unwrap();
+        let interned = https.intern(&mut interner);
+        assert_eq!(interned, https);
+        assert_eq!(interned.path(), https.path());
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn rsync_ord() {
+        let uri = |s| Rsync::from_str(s).unwrap();
+        assert_eq!(
+            uri("rsync://hOst/module/p").cmp(&uri("rsync://host/module/p")),
+            cmp::Ordering::Equal
+        );
+        assert!(uri("rsync://host/module/a") < uri("rsync://host/module/b"));
+        assert!(uri("rsync://host/module/B") < uri("rsync://host/module/a"));
+        assert!(uri("rsync://Host/module/b") < uri("rsync://hosta/module/a"));
+        assert!(uri("rsync://host/mod/") < uri("rsync://host/module/"));
+    }
+
     #[test]
     fn rsync_hash() {
         fn hash(uri: &str) -> u64 {
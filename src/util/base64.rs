@@ -10,9 +10,9 @@
 //! Each flavor implements a number of methods for encoding and decoding.
 //! These differ slightly between the flavors based on what they are used
 //! for.
-use std::{error, fmt, io, str};
+use std::{cmp, error, fmt, io, str};
 use std::io::{Read, Write};
-use base64::engine::{DecodeEstimate, Engine};
+use base64::engine::Engine;
 use base64::engine::general_purpose::{
     GeneralPurpose, GeneralPurposeConfig, STANDARD
 };
@@ -32,16 +32,9 @@ impl Xml {
     const ENGINE: GeneralPurpose = STANDARD;
 
     pub fn decode(self, input: &str) -> Result<Vec<u8>, XmlDecodeError> {
-        let mut res = Vec::with_capacity(
-            Self::ENGINE.internal_decoded_len_estimate(
-                input.len()
-            ).decoded_len_estimate()
-        );
-        base64::read::DecoderReader::new(
-            SkipWhitespace::new(input),
-            &Self::ENGINE,
-        ).read_to_end(&mut res)?;
-        Ok(res)
+        decode_xml(input).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err).into()
+        })
     }
 
     pub fn decode_bytes(
@@ -61,15 +54,14 @@ impl Xml {
         Self::ENGINE.encode(data)
     }
 
+    /// Returns a reader producing the decoded data.
+    ///
+    /// The input is decoded in one go when the reader is read from for the
+    /// first time, which is a lot faster than decoding it piecemeal.
     pub fn decode_reader(
         self, input: &str,
-    ) -> XmlDecoderReader {
-        XmlDecoderReader(
-            base64::read::DecoderReader::new(
-                SkipWhitespace::new(input),
-                &Self::ENGINE
-            )
-        )
+    ) -> XmlDecoderReader<'_> {
+        XmlDecoderReader { input, data: None }
     }
 
     pub fn encode_writer(
@@ -197,17 +189,169 @@ impl<'a> io::Read for SkipWhitespace<'a> {
 }
 
 
+//------------ decode_xml ----------------------------------------------------
+
+/// Decodes Base 64 with white space in one go.
+///
+/// This is considerably faster than running a decoder over a reader that
+/// skips white space. The input is decoded by a table-driven decoder
+/// working directly on the chunks between white space. Only if this
+/// decoder fails is the input given to the general purpose decoder which
+/// produces a detailed error.
+fn decode_xml(input: &str) -> Result<Vec<u8>, DecodeError> {
+    match decode_standard(input) {
+        Some(res) => Ok(res),
+        None => {
+            let mut stripped = Vec::with_capacity(input.len());
+            SkipWhitespace::new(input).read_to_end(
+                &mut stripped
+            ).expect("reading from str failed");
+            Xml::ENGINE.decode(stripped)
+        }
+    }
+}
+
+/// Decodes canonical, padded Base 64 with the standard alphabet.
+///
+/// ASCII white space is skipped. Returns `None` if the input is invalid
+/// in any way.
+fn decode_standard(input: &str) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(input.len() / 4 * 3);
+    let mut partial = [0u8; 4];
+    let mut partial_len = 0;
+    let mut finished = false;
+    for mut chunk in input.split_ascii_whitespace().map(str::as_bytes) {
+        if finished {
+            return None
+        }
+
+        // Complete a quad started in the previous chunk.
+        if partial_len > 0 {
+            let len = cmp::min(4 - partial_len, chunk.len());
+            partial[partial_len..partial_len + len].copy_from_slice(
+                &chunk[..len]
+            );
+            partial_len += len;
+            chunk = &chunk[len..];
+            if partial_len < 4 {
+                continue
+            }
+            partial_len = 0;
+            if push_quad(&mut res, &partial)? {
+                finished = true;
+                if !chunk.is_empty() {
+                    return None
+                }
+                continue
+            }
+        }
+
+        let mut quads = chunk.chunks_exact(4);
+        for quad in &mut quads {
+            if finished {
+                return None
+            }
+            if let Some(value) = decode_quad(quad) {
+                res.extend_from_slice(&value.to_be_bytes()[1..]);
+            }
+            else {
+                finished = push_quad(&mut res, quad)?;
+            }
+        }
+        let rest = quads.remainder();
+        if !rest.is_empty() {
+            if finished {
+                return None
+            }
+            partial[..rest.len()].copy_from_slice(rest);
+            partial_len = rest.len();
+        }
+    }
+    if partial_len > 0 {
+        return None
+    }
+    Some(res)
+}
+
+/// Decodes a quad of Base 64 characters and appends it to `res`.
+///
+/// Returns whether the quad contained padding and therefore must be the
+/// last one.
+fn push_quad(res: &mut Vec<u8>, quad: &[u8]) -> Option<bool> {
+    if let Some(value) = decode_quad(quad) {
+        res.extend_from_slice(&value.to_be_bytes()[1..]);
+        return Some(false)
+    }
+    let padding = quad.iter().rev().take_while(|&&ch| ch == b'=').count();
+    if padding == 0 || padding > 2 {
+        return None
+    }
+
+    // The padding is replaced by zero sextets which, for canonical
+    // encoding, must also be the value of the unused bits.
+    let mut full = [b'A'; 4];
+    full[..4 - padding].copy_from_slice(&quad[..4 - padding]);
+    let value = decode_quad(&full)?.to_be_bytes();
+    if value[4 - padding..].iter().any(|&octet| octet != 0) {
+        return None
+    }
+    res.extend_from_slice(&value[1..4 - padding]);
+    Some(true)
+}
+
+/// Decodes four Base 64 characters into a 24 bit value.
+fn decode_quad(src: &[u8]) -> Option<u32> {
+    let a = DECODE[usize::from(src[0])];
+    let b = DECODE[usize::from(src[1])];
+    let c = DECODE[usize::from(src[2])];
+    let d = DECODE[usize::from(src[3])];
+    if (a | b | c | d) & 0x80 != 0 {
+        return None
+    }
+    Some(
+        u32::from(a) << 18 | u32::from(b) << 12 | u32::from(c) << 6
+        | u32::from(d)
+    )
+}
+
+/// The value of each octet in the standard alphabet or 0xFF.
+const DECODE: [u8; 256] = {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = [0xFF; 256];
+    let mut i = 0;
+    while i < 64 {
+        res[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    res
+};
+
+
 //------------ XmlDecoderReader ----------------------------------------------
 
-pub struct XmlDecoderReader<'a>(
-    base64::read::DecoderReader<
-        'static, GeneralPurpose, SkipWhitespace<'a>
-    >
-);
+/// A reader providing decoded XML Base 64 data.
+///
+/// Values of this type are returned by [`Xml::decode_reader`].
+pub struct XmlDecoderReader<'a> {
+    /// The encoded input.
+    input: &'a str,
+
+    /// The decoded data once the first read has happened.
+    data: Option<Result<io::Cursor<Vec<u8>>, DecodeError>>,
+}
 
 impl<'a> io::Read for XmlDecoderReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
-        self.0.read(buf)
+        let input = self.input;
+        match self.data.get_or_insert_with(|| {
+            decode_xml(input).map(io::Cursor::new)
+        }) {
+            Ok(data) => data.read(buf),
+            Err(err) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, err.clone()))
+            }
+        }
     }
 }
 
@@ -232,7 +376,11 @@ impl fmt::Display for XmlDecodeError {
     }
 }
 
-impl error::Error for XmlDecodeError { } 
+impl error::Error for XmlDecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
 
 
 //============ Tests =========================================================
@@ -270,7 +418,35 @@ mod test {
         test("foo bar");
         test(" foo bar");
         test(" foo bar ");
+        test("\r\n\tfoo\r\n  bar\rqu\n\n");
     }
 
+    #[test]
+    fn xml_decode() {
+        let data: Vec<u8> = (0..1000u32).map(|x| x as u8).collect();
+        let encoded = Xml.encode(&data);
+        assert_eq!(Xml.decode(&encoded).unwrap(), data);
+        let wrapped: String = encoded.as_bytes().chunks(64).map(|line| {
+            format!("\n    {}", str::from_utf8(line).unwrap())
+        }).collect();
+        assert_eq!(Xml.decode(&wrapped).unwrap(), data);
+
+        let mut res = Vec::new();
+        Xml.decode_reader(&wrapped).read_to_end(&mut res).unwrap();
+        assert_eq!(res, data);
+
+        assert!(Xml.decode("Zm9v!").is_err());
+        assert_eq!(Xml.decode("Zm8=").unwrap(), b"fo");
+        assert_eq!(Xml.decode("Zg==").unwrap(), b"f");
+        assert!(Xml.decode("Zh==").is_err());
+        assert!(Xml.decode("Z===").is_err());
+        assert!(Xml.decode("Zg=a").is_err());
+        assert!(Xml.decode("Zg==Zm9v").is_err());
+        assert!(Xml.decode("").unwrap().is_empty());
+        assert_eq!(Xml.decode(" Z m\n8 = ").unwrap(), b"fo");
+        assert!(Xml.decode("Zm8= Zm8=").is_err());
+        assert!(Xml.decode("Zm8").is_err());
+        assert!(Xml.decode_reader("Zm9").read_to_end(&mut res).is_err());
+    }
 }
 
@@ -0,0 +1,106 @@
+//! Cooperative cancellation of long-running operations.
+//!
+//! Processing an RRDP snapshot or decoding a very large publication
+//! message can take a long time. When a host application shuts down or an
+//! operator aborts an operation, it wants these to stop at the next
+//! convenient point rather than having to kill the thread running them.
+//!
+//! A [`CancelToken`] is shared between the code running an operation and
+//! the code that may want to abort it. The operation checks the token
+//! regularly – typically once for every element or object it processes –
+//! and stops with a [`Cancelled`] error once the token has been cancelled.
+//! Cancelling is cheap and can happen from any thread.
+//!
+//! Cancellation is cooperative: an operation only notices it when it next
+//! checks the token. In particular, an operation blocked reading from a
+//! stalled network connection will only stop once the read returns.
+
+use std::{error, fmt, io};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+//------------ CancelToken ---------------------------------------------------
+
+/// A token signalling that an operation should be cancelled.
+///
+/// Clones of a token share their state: cancelling one of them cancels
+/// all of them. Once cancelled, a token stays cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    /// Whether the token has been cancelled.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new token that hasn’t been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all operations using the token or one of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed)
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error if the token has been cancelled.
+    ///
+    /// This is meant to be used with the question mark operator at the
+    /// points where an operation can be aborted.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ Cancelled -----------------------------------------------------
+
+/// An operation was cancelled via its [`CancelToken`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+
+impl From<Cancelled> for io::Error {
+    fn from(_: Cancelled) -> Self {
+        io::Error::new(io::ErrorKind::Interrupted, Cancelled)
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("operation cancelled")
+    }
+}
+
+impl error::Error for Cancelled { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cancel() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        assert_eq!(clone.check(), Ok(()));
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(clone.check(), Err(Cancelled));
+        assert!(CancelToken::new().check().is_ok());
+    }
+}
@@ -0,0 +1,524 @@
+//! Decoding gzip-compressed data.
+//!
+//! HTTP servers may compress responses using gzip. Since we don’t want to
+//! depend on a compression library for just this, this module contains a
+//! straightforward decoder for the gzip format defined in RFC 1952 and the
+//! DEFLATE format defined in RFC 1951 it is based on.
+//!
+//! The decoder operates on complete data rather than a stream. Because
+//! compressed data can expand quite dramatically, it accepts an optional
+//! limit for the size of the decompressed data.
+
+use std::{error, fmt};
+
+
+//------------ decode --------------------------------------------------------
+
+/// Decodes gzip-compressed data.
+///
+/// The data may consist of multiple gzip members which are decoded and
+/// concatenated. If `limit` is given, decoding fails if the decompressed
+/// data becomes larger than the limit.
+pub fn decode(data: &[u8], limit: Option<usize>) -> Result<Vec<u8>, Error> {
+    let mut res = Vec::new();
+    let mut data = data;
+    loop {
+        data = decode_member(data, &mut res, limit)?;
+        if data.is_empty() {
+            return Ok(res)
+        }
+    }
+}
+
+/// Decodes a single gzip member and appends it to `target`.
+///
+/// Returns the remaining data.
+fn decode_member<'a>(
+    data: &'a [u8], target: &mut Vec<u8>, limit: Option<usize>
+) -> Result<&'a [u8], Error> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if data.len() < 10 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8
+    {
+        return Err(Error::Format)
+    }
+    let flags = data[3];
+    let mut data = &data[10..];
+    if flags & FEXTRA != 0 {
+        if data.len() < 2 {
+            return Err(Error::Format)
+        }
+        let len = usize::from(u16::from_le_bytes([data[0], data[1]]));
+        data = data.get(2 + len..).ok_or(Error::Format)?;
+    }
+    if flags & FNAME != 0 {
+        data = skip_zero_terminated(data)?;
+    }
+    if flags & FCOMMENT != 0 {
+        data = skip_zero_terminated(data)?;
+    }
+    if flags & FHCRC != 0 {
+        data = data.get(2..).ok_or(Error::Format)?;
+    }
+
+    let start = target.len();
+    let used = inflate(data, target, limit)?;
+    let data = &data[used..];
+    if data.len() < 8 {
+        return Err(Error::Format)
+    }
+    let crc = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    let member = &target[start..];
+    if crc32(member) != crc || member.len() as u32 != size {
+        return Err(Error::Format)
+    }
+    Ok(&data[8..])
+}
+
+/// Skips over a zero-terminated field.
+fn skip_zero_terminated(data: &[u8]) -> Result<&[u8], Error> {
+    match data.iter().position(|&ch| ch == 0) {
+        Some(pos) => Ok(&data[pos + 1..]),
+        None => Err(Error::Format)
+    }
+}
+
+
+//------------ inflate -------------------------------------------------------
+
+/// Base lengths for length symbols 257 to 285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+    67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+
+/// Extra bits for length symbols 257 to 285.
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4,
+    5, 5, 5, 5, 0
+];
+
+/// Base distances for distance symbols 0 to 29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385,
+    513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+
+/// Extra bits for distance symbols 0 to 29.
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10,
+    10, 11, 11, 12, 12, 13, 13
+];
+
+/// The order in which code length code lengths are given.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15
+];
+
+/// Decompresses DEFLATE data and appends it to `target`.
+///
+/// Returns the number of octets of `data` used.
+fn inflate(
+    data: &[u8], target: &mut Vec<u8>, limit: Option<usize>
+) -> Result<usize, Error> {
+    let mut bits = BitReader::new(data);
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(&mut bits, target, limit)?,
+            1 => {
+                let (lit, dist) = fixed_codes()?;
+                codes(&mut bits, target, limit, &lit, &dist)?
+            }
+            2 => {
+                let (lit, dist) = dynamic_codes(&mut bits)?;
+                codes(&mut bits, target, limit, &lit, &dist)?
+            }
+            _ => return Err(Error::Format)
+        }
+        if last {
+            return Ok(bits.finish())
+        }
+    }
+}
+
+/// Processes a stored block.
+fn stored(
+    bits: &mut BitReader, target: &mut Vec<u8>, limit: Option<usize>
+) -> Result<(), Error> {
+    bits.align();
+    let header = bits.take(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(Error::Format)
+    }
+    let len = usize::from(len);
+    check_limit(target.len() + len, limit)?;
+    target.extend_from_slice(bits.take(len)?);
+    Ok(())
+}
+
+/// Returns the literal/length and distance codes for fixed blocks.
+fn fixed_codes() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+/// Reads the literal/length and distance codes of a dynamic block.
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let nlen = bits.bits(5)? as usize + 257;
+    let ndist = bits.bits(5)? as usize + 1;
+    let ncode = bits.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(Error::Format)
+    }
+
+    let mut lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..ncode] {
+        lengths[index] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+
+    let mut lengths = [0u8; 316];
+    let mut index = 0;
+    while index < nlen + ndist {
+        let symbol = code.decode(bits)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue
+        }
+        let (value, count) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(Error::Format)
+                }
+                (lengths[index - 1], 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if index + count > nlen + ndist {
+            return Err(Error::Format)
+        }
+        lengths[index..index + count].fill(value);
+        index += count;
+    }
+    if lengths[256] == 0 {
+        return Err(Error::Format)
+    }
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..nlen + ndist])?
+    ))
+}
+
+/// Decodes the compressed data of a block.
+fn codes(
+    bits: &mut BitReader,
+    target: &mut Vec<u8>,
+    limit: Option<usize>,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), Error> {
+    loop {
+        let symbol = lit.decode(bits)?;
+        if symbol < 256 {
+            check_limit(target.len() + 1, limit)?;
+            target.push(symbol as u8);
+            continue
+        }
+        if symbol == 256 {
+            return Ok(())
+        }
+        let symbol = usize::from(symbol - 257);
+        if symbol >= LENGTH_BASE.len() {
+            return Err(Error::Format)
+        }
+        let len = usize::from(LENGTH_BASE[symbol])
+            + bits.bits(LENGTH_EXTRA[symbol].into())? as usize;
+        let symbol = usize::from(dist.decode(bits)?);
+        if symbol >= DIST_BASE.len() {
+            return Err(Error::Format)
+        }
+        let distance = usize::from(DIST_BASE[symbol])
+            + bits.bits(DIST_EXTRA[symbol].into())? as usize;
+        if distance > target.len() {
+            return Err(Error::Format)
+        }
+        check_limit(target.len() + len, limit)?;
+        let start = target.len() - distance;
+        for i in 0..len {
+            target.push(target[start + i]);
+        }
+    }
+}
+
+/// Checks that a length doesn’t exceed the limit.
+fn check_limit(len: usize, limit: Option<usize>) -> Result<(), Error> {
+    match limit {
+        Some(limit) if len > limit => Err(Error::TooLarge),
+        _ => Ok(())
+    }
+}
+
+
+//------------ BitReader -----------------------------------------------------
+
+/// Reads bits from a byte slice, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0, buf: 0, count: 0 }
+    }
+
+    /// Reads `n` bits where `n` must be at most 16.
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        while self.count < n {
+            let octet = *self.data.get(self.pos).ok_or(Error::Format)?;
+            self.pos += 1;
+            self.buf |= u32::from(octet) << self.count;
+            self.count += 8;
+        }
+        let res = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(res)
+    }
+
+    /// Drops the remaining bits of the current octet.
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+
+    /// Takes `len` octets after aligning.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let res = self.data.get(
+            self.pos..self.pos + len
+        ).ok_or(Error::Format)?;
+        self.pos += len;
+        Ok(res)
+    }
+
+    /// Returns the number of octets used.
+    fn finish(self) -> usize {
+        self.pos
+    }
+}
+
+
+//------------ Huffman -------------------------------------------------------
+
+/// A canonical Huffman code.
+///
+/// This uses the same representation as Mark Adler’s puff: the number of
+/// codes for each length and the symbols ordered by their code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Creates the code from the code lengths of the symbols.
+    fn new(lengths: &[u8]) -> Result<Self, Error> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+
+        // Check that the code isn’t over-subscribed. Incomplete codes are
+        // fine, decoding of a missing code will fail.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left <<= 1;
+            left -= i32::from(count);
+            if left < 0 {
+                return Err(Error::Format)
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    /// Decodes a symbol.
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, Error> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = i32::from(count);
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize])
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(Error::Format)
+    }
+}
+
+
+//------------ crc32 ---------------------------------------------------------
+
+/// The lookup table for the CRC-32 checksum.
+///
+/// Entry `n` is the CRC of the single octet `n`, allowing the checksum to
+/// be calculated one octet rather than one bit at a time.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Creates the lookup table for the CRC-32 checksum.
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            }
+            else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// Calculates the CRC-32 checksum used by gzip.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &octet in data {
+        crc = CRC32_TABLE[((crc ^ u32::from(octet)) & 0xFF) as usize]
+            ^ (crc >> 8);
+    }
+    !crc
+}
+
+
+//============ Errors ========================================================
+
+//------------ Error ---------------------------------------------------------
+
+/// An error happened while decoding gzip-compressed data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The data is not correctly encoded.
+    Format,
+
+    /// The decompressed data exceeds the limit.
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Format => f.write_str("invalid gzip data"),
+            Error::TooLarge => {
+                f.write_str("decompressed data exceeds size limit")
+            }
+        }
+    }
+}
+
+impl error::Error for Error { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FIXED: &[u8] = &[
+        31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 203, 72, 205, 201, 201, 87, 200, 64,
+        39, 1, 227, 81, 61, 141, 23, 0, 0, 0
+    ];
+
+    const STORED: &[u8] = &[
+        31, 139, 8, 0, 0, 0, 0, 0, 4, 3, 1, 5, 0, 250, 255, 104, 101, 108,
+        108, 111, 134, 166, 16, 54, 5, 0, 0, 0
+    ];
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(CRC32_TABLE[1], 0x7707_3096);
+        assert_eq!(CRC32_TABLE[255], 0x2D02_EF8D);
+    }
+
+    #[test]
+    fn decode_blocks() {
+        assert_eq!(
+            decode(FIXED, None).unwrap(), b"hello hello hello hello"
+        );
+        assert_eq!(decode(STORED, None).unwrap(), b"hello");
+        assert_eq!(
+            decode(
+                include_bytes!("../../test-data/rrdp/ripe-notification.xml.gz"),
+                None
+            ).unwrap(),
+            include_bytes!("../../test-data/rrdp/ripe-notification.xml")
+        );
+    }
+
+    #[test]
+    fn decode_members() {
+        let mut data = FIXED.to_vec();
+        data.extend_from_slice(STORED);
+        assert_eq!(
+            decode(&data, None).unwrap(), b"hello hello hello hellohello"
+        );
+    }
+
+    #[test]
+    fn decode_limit() {
+        assert_eq!(decode(FIXED, Some(10)), Err(Error::TooLarge));
+        assert!(decode(FIXED, Some(23)).is_ok());
+    }
+
+    #[test]
+    fn decode_broken() {
+        assert_eq!(decode(&FIXED[..20], None), Err(Error::Format));
+        let mut data = FIXED.to_vec();
+        data[20] ^= 0xFF;
+        assert_eq!(decode(&data, None), Err(Error::Format));
+        assert_eq!(decode(b"hello", None), Err(Error::Format));
+    }
+}
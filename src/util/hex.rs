@@ -1,7 +1,12 @@
 //! Converting from and to hex strings.
+//!
+//! Hex strings are mostly used for hashes and key identifiers. Since
+//! these are converted frequently, for instance when processing the
+//! elements of RRDP files, the functions here are table driven and work
+//! on caller provided buffers so they don’t allocate.
 #![allow(dead_code)]
 
-use std::str;
+use std::{error, fmt, str};
 
 
 /// Encodes a octet sequence as a hex string.
@@ -14,10 +19,27 @@ use std::str;
 ///
 /// The function panics if `dest` is shorter than twice the length of `src`.
 pub fn encode<'a>(src: &[u8], dest: &'a mut [u8]) -> &'a str {
+    encode_with(src, dest, DIGITS)
+}
+
+/// Encodes a octet sequence as a lower case hex string.
+///
+/// This is identical to [`encode`] except for the case of the digits.
+///
+/// # Panics
+///
+/// The function panics if `dest` is shorter than twice the length of `src`.
+pub fn encode_lower<'a>(src: &[u8], dest: &'a mut [u8]) -> &'a str {
+    encode_with(src, dest, LOWER_DIGITS)
+}
+
+fn encode_with<'a>(
+    src: &[u8], dest: &'a mut [u8], digits: &[u8; 16]
+) -> &'a str {
     let dest = &mut dest[..src.len() * 2];
-    for (s, d) in src.iter().zip(dest.chunks_mut(2)) {
-        d[0] = DIGITS[usize::from(s >> 4)];
-        d[1] = DIGITS[usize::from(s & 0x0F)];
+    for (s, d) in src.iter().zip(dest.chunks_exact_mut(2)) {
+        d[0] = digits[usize::from(s >> 4)];
+        d[1] = digits[usize::from(s & 0x0F)];
     }
     unsafe { str::from_utf8_unchecked(dest) }
 }
@@ -26,5 +48,98 @@ pub fn encode_u8(ch: u8) -> [u8; 2] {
     [DIGITS[usize::from(ch >> 4)], DIGITS[usize::from(ch & 0x0F)]]
 }
 
-const DIGITS: &[u8] = b"0123456789ABCDEF";
+/// Decodes a hex string into a buffer.
+///
+/// Both upper and lower case digits are accepted. The length of `src`
+/// must be exactly twice the length of `dest`.
+pub fn decode(src: &[u8], dest: &mut [u8]) -> Result<(), DecodeError> {
+    if src.len() != dest.len() * 2 {
+        return Err(DecodeError::BAD_LENGTH)
+    }
+    for (s, d) in src.chunks_exact(2).zip(dest.iter_mut()) {
+        let high = VALUES[usize::from(s[0])];
+        let low = VALUES[usize::from(s[1])];
+        if high | low > 0x0F {
+            return Err(DecodeError::BAD_CHARS)
+        }
+        *d = high << 4 | low;
+    }
+    Ok(())
+}
+
+/// Decodes a hex string into a vec.
+pub fn decode_vec(src: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if src.len() % 2 != 0 {
+        return Err(DecodeError::BAD_LENGTH)
+    }
+    let mut res = vec![0; src.len() / 2];
+    decode(src, &mut res)?;
+    Ok(res)
+}
+
+const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+const LOWER_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// The value of each octet as a hex digit or 0xFF if it isn’t one.
+const VALUES: [u8; 256] = {
+    let mut res = [0xFF; 256];
+    let mut i = 0;
+    while i < 10 {
+        res[b'0' as usize + i] = i as u8;
+        i += 1;
+    }
+    let mut i = 0;
+    while i < 6 {
+        res[b'a' as usize + i] = 10 + i as u8;
+        res[b'A' as usize + i] = 10 + i as u8;
+        i += 1;
+    }
+    res
+};
+
+
+//------------ DecodeError ---------------------------------------------------
+
+/// A hex string was invalid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DecodeError(&'static str);
+
+impl DecodeError {
+    const BAD_LENGTH: Self = DecodeError("invalid length");
+    const BAD_CHARS: Self = DecodeError("invalid characters");
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl error::Error for DecodeError { }
+
+
+//============ Tests =========================================================
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut buf = vec![0; 512];
+        let upper = encode(&data, &mut buf).to_string();
+        assert!(upper.starts_with("000102"));
+        assert!(upper.ends_with("FDFEFF"));
+        assert_eq!(decode_vec(upper.as_bytes()).unwrap(), data);
+        let lower = encode_lower(&data, &mut buf).to_string();
+        assert_eq!(lower, upper.to_ascii_lowercase());
+        assert_eq!(decode_vec(lower.as_bytes()).unwrap(), data);
+
+        assert_eq!(decode_vec(b"abc"), Err(DecodeError::BAD_LENGTH));
+        assert_eq!(decode_vec(b"0g"), Err(DecodeError::BAD_CHARS));
+        let mut short = [0u8; 1];
+        assert_eq!(decode(b"0000", &mut short), Err(DecodeError::BAD_LENGTH));
+    }
+}
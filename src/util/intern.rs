@@ -0,0 +1,157 @@
+//! Sharing memory between repeated strings.
+//!
+//! Large list replies, RRDP snapshots, and validation runs can hold
+//! millions of URIs. Many of them are identical – the same URI appears in
+//! a manifest, in the list of published objects, and in the validation
+//! report – and each of them normally has its own small allocation.
+//!
+//! An [`Interner`] keeps one copy of every string given to it. Interning a
+//! URI via [`uri::Rsync::intern`] or [`uri::Https::intern`] returns a URI
+//! that shares its memory with all other identical URIs interned before.
+//! In addition, the interner packs new strings into large chunks of memory
+//! rather than allocating each one separately, which saves the per
+//! allocation overhead. The interned values are ordinary URIs and can be
+//! used like any other.
+//!
+//! The memory of a chunk is only released once all values referencing it
+//! and the interner itself have been dropped. An interner is therefore
+//! best used for data that is kept around for a while.
+//!
+//! [`uri::Rsync::intern`]: crate::uri::Rsync::intern
+//! [`uri::Https::intern`]: crate::uri::Https::intern
+
+use std::cmp;
+use std::collections::HashSet;
+use std::sync::Arc;
+use bytes::{Bytes, BytesMut};
+
+
+//------------ Interner ------------------------------------------------------
+
+/// A collection of shared strings.
+#[derive(Debug)]
+pub struct Interner {
+    /// The interned octet sequences.
+    bytes: HashSet<Bytes>,
+
+    /// The interned names.
+    names: HashSet<Arc<str>>,
+
+    /// The chunk new octet sequences are added to.
+    chunk: BytesMut,
+
+    /// The size of a new chunk.
+    chunk_size: usize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::with_chunk_size(64 * 1024)
+    }
+}
+
+impl Interner {
+    /// Creates a new, empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new interner using chunks of the given size.
+    ///
+    /// Values larger than the chunk size get a chunk of their own.
+    pub fn with_chunk_size(chunk_size: usize) -> Self {
+        Interner {
+            bytes: HashSet::new(),
+            names: HashSet::new(),
+            chunk: BytesMut::new(),
+            chunk_size,
+        }
+    }
+
+    /// Returns a shared copy of the given octets.
+    ///
+    /// If an identical octet sequence has been interned before, returns
+    /// a reference to that one. Otherwise, the octets are copied into the
+    /// current chunk.
+    pub fn bytes(&mut self, data: &[u8]) -> Bytes {
+        if let Some(res) = self.bytes.get(data) {
+            return res.clone()
+        }
+        if self.chunk.capacity() - self.chunk.len() < data.len() {
+            self.chunk = BytesMut::with_capacity(
+                cmp::max(self.chunk_size, data.len())
+            );
+        }
+        self.chunk.extend_from_slice(data);
+        let res = self.chunk.split_to(data.len()).freeze();
+        self.bytes.insert(res.clone());
+        res
+    }
+
+    /// Returns a shared copy of the given name.
+    ///
+    /// This is used for handles and similar values that are kept as an
+    /// `Arc<str>`.
+    pub fn name(&mut self, name: &str) -> Arc<str> {
+        if let Some(res) = self.names.get(name) {
+            return res.clone()
+        }
+        let res: Arc<str> = name.into();
+        self.names.insert(res.clone());
+        res
+    }
+
+    /// Returns the number of distinct values in the interner.
+    pub fn len(&self) -> usize {
+        self.bytes.len() + self.names.len()
+    }
+
+    /// Returns whether the interner is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty() && self.names.is_empty()
+    }
+
+    /// Removes all values from the interner.
+    ///
+    /// Values handed out earlier remain valid but later values won’t
+    /// share memory with them anymore.
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.names.clear();
+        self.chunk = BytesMut::new();
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intern() {
+        let mut interner = Interner::with_chunk_size(16);
+        let foo = interner.bytes(b"foo");
+        let bar = interner.bytes(b"bar");
+        assert_eq!(foo, b"foo".as_ref());
+        assert_eq!(bar, b"bar".as_ref());
+        assert_eq!(interner.bytes(b"foo").as_ptr(), foo.as_ptr());
+
+        // Both went into the same chunk.
+        assert_eq!(foo.as_ptr() as usize + 3, bar.as_ptr() as usize);
+
+        // Large values get their own chunk.
+        let large = interner.bytes(&[0u8; 100]);
+        assert_eq!(large.len(), 100);
+        assert_eq!(interner.len(), 3);
+
+        let name = interner.name("alice");
+        assert!(Arc::ptr_eq(&name, &interner.name("alice")));
+        assert_eq!(interner.len(), 4);
+
+        interner.clear();
+        assert!(interner.is_empty());
+        assert_eq!(foo, b"foo".as_ref());
+    }
+}
@@ -0,0 +1,258 @@
+//! Media types used in the RPKI.
+//!
+//! The publication and provisioning protocols require specific media
+//! types in the Content-Type header of their HTTP requests and responses.
+//! RPKI objects have registered media types, too, which are useful when
+//! serving them over HTTP. The [`MediaType`] enum collects all of these
+//! along with the constants for their names, so the strings don’t need to
+//! be repeated in integrations.
+//!
+//! When checking the Content-Type header of a request or response, use
+//! [`MediaType::matches`] or [`check_content_type`]. Both ignore any
+//! parameters and the case of the value.
+
+use std::{error, fmt};
+
+
+//------------ Constants -----------------------------------------------------
+
+/// The media type of publication protocol messages defined in RFC 8181.
+pub const RPKI_PUBLICATION: &str = "application/rpki-publication";
+
+/// The media type of provisioning protocol messages defined in RFC 6492.
+pub const RPKI_UPDOWN: &str = "application/rpki-updown";
+
+/// The media type of ROAs defined in RFC 6481.
+pub const RPKI_ROA: &str = "application/rpki-roa";
+
+/// The media type of manifests defined in RFC 6481.
+pub const RPKI_MANIFEST: &str = "application/rpki-manifest";
+
+/// The media type of Ghostbusters records defined in RFC 6493.
+pub const RPKI_GHOSTBUSTERS: &str = "application/rpki-ghostbusters";
+
+/// The media type of certificates defined in RFC 2585.
+pub const PKIX_CERT: &str = "application/pkix-cert";
+
+/// The media type of CRLs defined in RFC 2585.
+pub const PKIX_CRL: &str = "application/pkix-crl";
+
+/// The media type used for RRDP files.
+///
+/// RFC 8182 doesn’t define a media type for its files. This is the
+/// generic type for XML documents which is what most servers use.
+pub const RRDP_XML: &str = "application/xml";
+
+
+//------------ MediaType -----------------------------------------------------
+
+/// A media type used in the RPKI.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum MediaType {
+    /// A publication protocol message.
+    Publication,
+
+    /// A provisioning protocol message.
+    Provisioning,
+
+    /// An RRDP notification, snapshot, or delta file.
+    Rrdp,
+
+    /// A ROA.
+    Roa,
+
+    /// A manifest.
+    Manifest,
+
+    /// A Ghostbusters record.
+    Ghostbusters,
+
+    /// A certificate.
+    Cert,
+
+    /// A CRL.
+    Crl,
+}
+
+impl MediaType {
+    /// Returns the media type to use in a Content-Type header.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaType::Publication => RPKI_PUBLICATION,
+            MediaType::Provisioning => RPKI_UPDOWN,
+            MediaType::Rrdp => RRDP_XML,
+            MediaType::Roa => RPKI_ROA,
+            MediaType::Manifest => RPKI_MANIFEST,
+            MediaType::Ghostbusters => RPKI_GHOSTBUSTERS,
+            MediaType::Cert => PKIX_CERT,
+            MediaType::Crl => PKIX_CRL,
+        }
+    }
+
+    /// Returns the media type of a file based on its extension.
+    ///
+    /// The argument can be a URI, a path, or a file name. Returns `None`
+    /// if the extension is unknown.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        if ext.eq_ignore_ascii_case("roa") {
+            Some(MediaType::Roa)
+        }
+        else if ext.eq_ignore_ascii_case("mft") {
+            Some(MediaType::Manifest)
+        }
+        else if ext.eq_ignore_ascii_case("gbr") {
+            Some(MediaType::Ghostbusters)
+        }
+        else if ext.eq_ignore_ascii_case("cer") {
+            Some(MediaType::Cert)
+        }
+        else if ext.eq_ignore_ascii_case("crl") {
+            Some(MediaType::Crl)
+        }
+        else if ext.eq_ignore_ascii_case("xml") {
+            Some(MediaType::Rrdp)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns the media type given in the value of a Content-Type header.
+    ///
+    /// Returns `None` if the value doesn’t contain a known media type.
+    pub fn from_content_type(value: &str) -> Option<Self> {
+        [
+            MediaType::Publication, MediaType::Provisioning, MediaType::Rrdp,
+            MediaType::Roa, MediaType::Manifest, MediaType::Ghostbusters,
+            MediaType::Cert, MediaType::Crl,
+        ].into_iter().find(|media| media.matches(value))
+    }
+
+    /// Returns whether the value of a Content-Type header is this type.
+    ///
+    /// Parameters and the case of the value are ignored. For RRDP, both
+    /// `application/xml` and `text/xml` are accepted.
+    pub fn matches(self, value: &str) -> bool {
+        let value = value.split(';').next().unwrap_or("").trim();
+        value.eq_ignore_ascii_case(self.as_str()) || (
+            self == MediaType::Rrdp && value.eq_ignore_ascii_case("text/xml")
+        )
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ check_content_type --------------------------------------------
+
+/// Checks the value of a Content-Type header.
+///
+/// Returns an error if there is no value or if it isn’t `expected`.
+pub fn check_content_type(
+    value: Option<&str>, expected: MediaType
+) -> Result<(), ContentTypeError> {
+    match value {
+        Some(value) if expected.matches(value) => Ok(()),
+        _ => {
+            Err(ContentTypeError {
+                expected,
+                received: value.map(Into::into),
+            })
+        }
+    }
+}
+
+
+//------------ ContentTypeError ----------------------------------------------
+
+/// The Content-Type header did not have the expected value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContentTypeError {
+    /// The expected media type.
+    expected: MediaType,
+
+    /// The received value if there was one.
+    received: Option<String>,
+}
+
+impl ContentTypeError {
+    /// Returns the expected media type.
+    pub fn expected(&self) -> MediaType {
+        self.expected
+    }
+
+    /// Returns the received value of the header if there was one.
+    pub fn received(&self) -> Option<&str> {
+        self.received.as_deref()
+    }
+}
+
+impl fmt::Display for ContentTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected content type {}", self.expected)?;
+        if let Some(received) = self.received.as_ref() {
+            write!(f, ", got {}", received)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ContentTypeError { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        assert!(MediaType::Provisioning.matches(
+            "Application/RPKI-updown; charset=utf-8"
+        ));
+        assert!(!MediaType::Provisioning.matches(RPKI_PUBLICATION));
+        assert!(MediaType::Rrdp.matches("text/xml"));
+        assert_eq!(
+            MediaType::from_content_type(" application/rpki-publication"),
+            Some(MediaType::Publication)
+        );
+        assert_eq!(MediaType::from_content_type("text/plain"), None);
+    }
+
+    #[test]
+    fn from_file_name() {
+        assert_eq!(
+            MediaType::from_file_name("rsync://example.com/mod/a.ROA"),
+            Some(MediaType::Roa)
+        );
+        assert_eq!(
+            MediaType::from_file_name("notification.xml"),
+            Some(MediaType::Rrdp)
+        );
+        assert_eq!(MediaType::from_file_name("README"), None);
+    }
+
+    #[test]
+    fn check() {
+        assert!(check_content_type(
+            Some(RPKI_UPDOWN), MediaType::Provisioning
+        ).is_ok());
+        let err = check_content_type(
+            Some("text/plain"), MediaType::Publication
+        ).unwrap_err();
+        assert_eq!(err.received(), Some("text/plain"));
+        assert_eq!(
+            err.to_string(),
+            "expected content type application/rpki-publication, \
+             got text/plain"
+        );
+        assert!(check_content_type(None, MediaType::Publication).is_err());
+    }
+}
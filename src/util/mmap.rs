@@ -0,0 +1,231 @@
+//! Memory-mapped reading of files.
+//!
+//! Local copies of repositories can contain very large files, such as RRDP
+//! snapshots or the manifests and CRLs of big CAs. Reading these into
+//! memory copies the complete file and keeps it alive twice during
+//! processing. Instead, [`map_file`] maps a file into memory and returns
+//! its content as [`Bytes`] which can be processed and sliced like any
+//! other data without copying. The mapping is removed once the last
+//! reference to the data is dropped.
+//!
+//! Memory mapping is only available on Unix systems and only if the
+//! `"mmap"` feature is enabled. On other systems, or if it isn’t enabled,
+//! [`read_file`] simply reads the file.
+//!
+//! Some care is needed when mapping files. The mapping is private, but
+//! this only means that our process doesn’t write to the file. It doesn’t
+//! protect the mapping against changes made by others: if another process
+//! truncates a mapped file, accessing the part of the mapping beyond the
+//! new end of the file raises a `SIGBUS` signal which kills the process.
+//! Files must therefore only be mapped if they are never modified in place.
+//! As a safeguard, [`read_file`] only maps files that nobody has write
+//! permission for. [`FsStore`][crate::store::fs::FsStore] removes the
+//! write permissions from its files before renaming them into place, so
+//! the files it has finished writing can be mapped. Note that this doesn’t
+//! stop processes running as root from modifying files.
+
+use std::{fs, io};
+use std::path::Path;
+use bytes::Bytes;
+
+
+//------------ read_file -----------------------------------------------------
+
+/// Reads the content of a file, mapping it if it is large.
+///
+/// If memory mapping is available, the size of the file is at least
+/// `threshold` bytes, and nobody has write permission for the file, it is
+/// mapped via [`map_file`]. Otherwise, it is read into memory.
+pub fn read_file(
+    path: impl AsRef<Path>, threshold: u64
+) -> Result<Bytes, io::Error> {
+    #[cfg(all(feature = "mmap", unix))]
+    {
+        let file = fs::File::open(path)?;
+        if may_map(&file.metadata()?, threshold) {
+            map_file(&file)
+        }
+        else {
+            use std::io::Read;
+
+            let mut res = Vec::new();
+            (&file).read_to_end(&mut res)?;
+            Ok(res.into())
+        }
+    }
+    #[cfg(not(all(feature = "mmap", unix)))]
+    {
+        let _ = threshold;
+        fs::read(path).map(Into::into)
+    }
+}
+
+
+/// Returns whether a file should be mapped by [`read_file`].
+#[cfg(all(feature = "mmap", unix))]
+fn may_map(metadata: &fs::Metadata, threshold: u64) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.len() >= threshold
+        && metadata.permissions().mode() & 0o222 == 0
+}
+
+
+//------------ map_file ------------------------------------------------------
+
+/// Maps the content of a file into memory.
+///
+/// The file is mapped read-only and privately, so changes to the file
+/// made after mapping may or may not be visible in the returned data. An
+/// empty file results in empty data without creating a mapping.
+///
+/// The caller has to make sure that the file isn’t truncated for as long
+/// as the returned data is alive. If it is, accessing the data raises a
+/// `SIGBUS` signal which kills the process. Only map files that are never
+/// modified in place, e.g., because they are written to a temporary file
+/// first that is then renamed.
+#[cfg(all(feature = "mmap", unix))]
+pub fn map_file(file: &fs::File) -> Result<Bytes, io::Error> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(Bytes::new())
+    }
+    Mmap::new(file, len).map(Bytes::from_owner)
+}
+
+
+//------------ Mmap ----------------------------------------------------------
+
+/// A read-only memory mapping of a file.
+#[cfg(all(feature = "mmap", unix))]
+struct Mmap {
+    /// The start of the mapping.
+    ptr: *mut libc::c_void,
+
+    /// The length of the mapping.
+    len: usize,
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Mmap {
+    /// Maps `len` bytes from the start of the file.
+    ///
+    /// The mapping uses `MAP_PRIVATE`. This keeps our view of the file
+    /// from being changed by writes to the mapping – which we don’t do
+    /// anyway – but pages not yet read still reflect modifications of the
+    /// file by others. If the file shrinks below `len`, accessing the
+    /// pages past its end raises `SIGBUS`. See [`map_file`].
+    fn new(file: &fs::File, len: u64) -> Result<Self, io::Error> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(len).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "file too large")
+        })?;
+
+        // Safety: We pass a null pointer and a non-zero length and check
+        // the result.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(), len, libc::PROT_READ,
+                libc::MAP_PRIVATE, file.as_raw_fd(), 0
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+// Safety: The mapping is read-only and not tied to the thread.
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Send for Mmap { }
+
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Sync for Mmap { }
+
+#[cfg(all(feature = "mmap", unix))]
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        // Safety: The mapping covers `len` readable bytes for as long as
+        // we exist.
+        unsafe {
+            std::slice::from_raw_parts(self.ptr as *const u8, self.len)
+        }
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // Safety: We created the mapping with exactly these values.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "rpki-mmap-test-{}-{}", name, std::process::id()
+        ))
+    }
+
+    #[test]
+    fn read() {
+        let path = temp_path("read");
+        let data: Vec<u8> = (0..100_000u32).map(|x| x as u8).collect();
+        fs::write(&path, &data).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(
+                &path, fs::Permissions::from_mode(0o444)
+            ).unwrap();
+        }
+        assert_eq!(read_file(&path, 0).unwrap(), data);
+        assert_eq!(read_file(&path, u64::MAX).unwrap(), data);
+        let mapped = read_file(&path, 1024).unwrap();
+        assert_eq!(mapped.slice(10..20), data[10..20]);
+
+        // Never modify a file while it is mapped. The mapping goes away
+        // with the last reference to the data.
+        drop(mapped);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "mmap", unix))]
+    fn map_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("read-only");
+        fs::write(&path, b"some data").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(!may_map(&metadata, 4));
+
+        let mut perm = metadata.permissions();
+        perm.set_mode(0o444);
+        fs::set_permissions(&path, perm).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(may_map(&metadata, 4));
+        assert!(!may_map(&metadata, 10));
+        assert_eq!(read_file(&path, 4).unwrap(), b"some data".as_ref());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_empty() {
+        let path = temp_path("empty");
+        fs::write(&path, b"").unwrap();
+        assert!(read_file(&path, 0).unwrap().is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
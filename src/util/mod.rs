@@ -1,2 +1,9 @@
 pub mod base64;
+pub mod cancel;
+pub mod gzip;
 pub mod hex;
+pub mod http;
+pub mod intern;
+pub mod media;
+pub mod mmap;
+pub mod net;
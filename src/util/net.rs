@@ -0,0 +1,343 @@
+//! Establishing TCP connections.
+//!
+//! Validators and CAs increasingly run on hosts that have only IPv6 or
+//! only IPv4 connectivity, while the hosts they talk to may be reachable
+//! via either or both. Simply trying the addresses in the order returned
+//! by the resolver leads to long delays if the preferred address family
+//! is broken. The [`HappyEyeballs`] connector implements the connection
+//! racing of RFC 8305: it resolves both A and AAAA records, interleaves
+//! the addresses of both families, and starts a new connection attempt
+//! whenever the previous one hasn’t succeeded within a short delay. The
+//! first attempt to succeed wins.
+//!
+//! The [`AddressFamily`] allows restricting connections to only IPv4 or
+//! only IPv6. It is also part of the
+//! [`HttpClientConfig`][super::http::HttpClientConfig] so that transports
+//! used with the [HTTPS fetcher][crate::fetch::https::HttpsFetcher] can
+//! honour it.
+
+use std::{error, fmt, io, thread};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+
+
+//------------ AddressFamily -------------------------------------------------
+
+/// The address families to use for connections.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AddressFamily {
+    /// Both IPv4 and IPv6 addresses are used.
+    #[default]
+    Any,
+
+    /// Only IPv4 addresses are used.
+    V4Only,
+
+    /// Only IPv6 addresses are used.
+    V6Only,
+}
+
+impl AddressFamily {
+    /// Returns whether the address belongs to an allowed family.
+    pub fn includes(self, addr: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::V4Only => addr.is_ipv4(),
+            AddressFamily::V6Only => addr.is_ipv6(),
+        }
+    }
+
+    /// Returns the name of the value as used in configuration.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AddressFamily::Any => "any",
+            AddressFamily::V4Only => "ipv4",
+            AddressFamily::V6Only => "ipv6",
+        }
+    }
+}
+
+impl FromStr for AddressFamily {
+    type Err = UnknownAddressFamily;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(AddressFamily::Any),
+            "ipv4" => Ok(AddressFamily::V4Only),
+            "ipv6" => Ok(AddressFamily::V6Only),
+            _ => Err(UnknownAddressFamily)
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+
+//------------ HappyEyeballs -------------------------------------------------
+
+/// A connector racing connection attempts to all addresses of a host.
+///
+/// By default, all address families are used, a new attempt is started
+/// every 250 milliseconds as recommended by RFC 8305, and there is no
+/// timeout for the individual attempts beyond that of the system.
+#[derive(Clone, Copy, Debug)]
+pub struct HappyEyeballs {
+    /// The address families to use.
+    family: AddressFamily,
+
+    /// The time to wait before starting the next attempt.
+    attempt_delay: Duration,
+
+    /// The timeout for an individual connection attempt.
+    connect_timeout: Option<Duration>,
+}
+
+impl Default for HappyEyeballs {
+    fn default() -> Self {
+        HappyEyeballs {
+            family: AddressFamily::Any,
+            attempt_delay: Duration::from_millis(250),
+            connect_timeout: None,
+        }
+    }
+}
+
+impl HappyEyeballs {
+    /// Creates a new connector with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the address families to use.
+    pub fn family(&self) -> AddressFamily {
+        self.family
+    }
+
+    /// Sets the address families to use.
+    pub fn set_family(&mut self, family: AddressFamily) {
+        self.family = family
+    }
+
+    /// Sets the time to wait before starting the next attempt.
+    ///
+    /// RFC 8305 recommends a value between 100 milliseconds and two
+    /// seconds.
+    pub fn set_attempt_delay(&mut self, delay: Duration) {
+        self.attempt_delay = delay
+    }
+
+    /// Sets the timeout for an individual connection attempt.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.connect_timeout = timeout
+    }
+
+    /// Connects to a host and port.
+    ///
+    /// The host can be a domain name or an IP address. IPv6 addresses may
+    /// be enclosed in square brackets as they appear in URIs.
+    pub fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let host = host.strip_prefix('[').and_then(|host| {
+            host.strip_suffix(']')
+        }).unwrap_or(host);
+        let addrs: Vec<_> = (host, port).to_socket_addrs()?.collect();
+        self.connect_addrs(&addrs)
+    }
+
+    /// Connects to one of the given addresses.
+    ///
+    /// The addresses are tried in the order produced by [`sort_addrs`].
+    pub fn connect_addrs(
+        &self, addrs: &[SocketAddr]
+    ) -> io::Result<TcpStream> {
+        let addrs = sort_addrs(addrs, self.family);
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("no {} addresses to connect to", self.family)
+            ))
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut pending = 0;
+        let mut last_err = None;
+        for addr in addrs {
+            let tx = tx.clone();
+            let timeout = self.connect_timeout;
+            thread::spawn(move || {
+                // If the receiver is gone, another attempt has won and we
+                // simply drop our connection.
+                let _ = tx.send(connect_one(addr, timeout));
+            });
+            pending += 1;
+
+            // Start the next attempt after the delay or as soon as one of
+            // the running attempts fails.
+            match rx.recv_timeout(self.attempt_delay) {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(err)) => {
+                    pending -= 1;
+                    last_err = Some(err);
+                }
+                Err(_) => { }
+            }
+        }
+        drop(tx);
+        while pending > 0 {
+            match rx.recv() {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(err)) => {
+                    pending -= 1;
+                    last_err = Some(err);
+                }
+                Err(_) => break,
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::Other, "connection attempts failed")
+        }))
+    }
+}
+
+
+//------------ sort_addrs ----------------------------------------------------
+
+/// Orders addresses for connection attempts.
+///
+/// Drops all addresses not belonging to `family` and interleaves the
+/// remaining addresses by family as described in section 4 of RFC 8305,
+/// starting with the family of the first address. Apart from that, the
+/// order of the addresses is kept.
+pub fn sort_addrs(
+    addrs: &[SocketAddr], family: AddressFamily
+) -> Vec<SocketAddr> {
+    let addrs = addrs.iter().filter(|addr| family.includes(addr));
+    let first_v6 = match addrs.clone().next() {
+        Some(addr) => addr.is_ipv6(),
+        None => return Vec::new()
+    };
+    let mut first = addrs.clone().filter(|addr| addr.is_ipv6() == first_v6);
+    let mut second = addrs.clone().filter(|addr| addr.is_ipv6() != first_v6);
+    let mut res = Vec::new();
+    loop {
+        let (left, right) = (first.next(), second.next());
+        if left.is_none() && right.is_none() {
+            break
+        }
+        res.extend(left);
+        res.extend(right);
+    }
+    res
+}
+
+/// Makes a single connection attempt.
+fn connect_one(
+    addr: SocketAddr, timeout: Option<Duration>
+) -> io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+
+//============ Errors ========================================================
+
+//------------ UnknownAddressFamily ------------------------------------------
+
+/// The name of an address family was unknown.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnknownAddressFamily;
+
+impl fmt::Display for UnknownAddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("unknown address family")
+    }
+}
+
+impl error::Error for UnknownAddressFamily { }
+
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use super::*;
+
+    fn addrs(s: &[&str]) -> Vec<SocketAddr> {
+        s.iter().map(|s| SocketAddr::from_str(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn sort() {
+        let all = addrs(&[
+            "[2001:db8::1]:443", "[2001:db8::2]:443", "[2001:db8::3]:443",
+            "192.0.2.1:443", "192.0.2.2:443",
+        ]);
+        assert_eq!(
+            sort_addrs(&all, AddressFamily::Any),
+            addrs(&[
+                "[2001:db8::1]:443", "192.0.2.1:443",
+                "[2001:db8::2]:443", "192.0.2.2:443",
+                "[2001:db8::3]:443",
+            ])
+        );
+        assert_eq!(
+            sort_addrs(&all, AddressFamily::V4Only),
+            addrs(&["192.0.2.1:443", "192.0.2.2:443"])
+        );
+        assert_eq!(
+            sort_addrs(&all[..3], AddressFamily::V4Only), Vec::new()
+        );
+        assert_eq!(
+            sort_addrs(&addrs(&["192.0.2.1:1", "[::1]:1"]), AddressFamily::Any),
+            addrs(&["192.0.2.1:1", "[::1]:1"])
+        );
+    }
+
+    #[test]
+    fn family_names() {
+        for family in [
+            AddressFamily::Any, AddressFamily::V4Only, AddressFamily::V6Only
+        ] {
+            assert_eq!(
+                AddressFamily::from_str(family.as_str()), Ok(family)
+            );
+        }
+        assert!(AddressFamily::from_str("ipv5").is_err());
+    }
+
+    #[test]
+    fn connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // A closed port first so the race has to fall through to the
+        // listener.
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+
+        let mut connector = HappyEyeballs::new();
+        connector.set_attempt_delay(Duration::from_millis(50));
+        let stream = connector.connect_addrs(&[
+            closed_addr, listener.local_addr().unwrap()
+        ]).unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+
+        let stream = connector.connect("127.0.0.1", port).unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+
+        connector.set_family(AddressFamily::V6Only);
+        assert_eq!(
+            connector.connect("127.0.0.1", port).unwrap_err().kind(),
+            io::ErrorKind::AddrNotAvailable
+        );
+    }
+}
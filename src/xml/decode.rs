@@ -30,8 +30,15 @@ impl<R: io::BufRead> Reader<R> {
     ///
     /// This is like `Content::take_element` except that it also happily
     /// skips over XML and doctype declarations.
+    ///
+    /// Only documents encoded in UTF-8 are supported. A UTF-8 byte order
+    /// mark at the beginning of the document is skipped. If the document
+    /// starts with a UTF-16 byte order mark or looks like UTF-16 otherwise,
+    /// or if the XML declaration names an encoding other than UTF-8 or
+    /// US-ASCII, an [`Error::UnsupportedEncoding`] is returned.
     pub fn start<F, E>(&mut self, op: F) -> Result<Content, E>
     where F: FnOnce(Element) -> Result<(), E>, E: From<Error> {
+        self.check_utf16()?;
         loop {
             self.buf.clear();
             let (ns, event) = self.reader.read_resolved_event_into(
@@ -51,12 +58,34 @@ impl<R: io::BufRead> Reader<R> {
                         Content { empty: true }
                     )
                 }
-                Event::Comment(_) | Event::Decl(_) | Event::DocType(_) => { }
+                Event::Decl(decl) => {
+                    if let Some(encoding) = decl.encoding() {
+                        check_encoding(
+                            encoding.map_err(Error::from)?.as_ref()
+                        )?;
+                    }
+                }
+                Event::Comment(_) | Event::DocType(_) => { }
                 _ => return Err(Error::Malformed.into())
             }
         }
     }
 
+    /// Checks whether the document starts like a UTF-16 document.
+    fn check_utf16(&mut self) -> Result<(), Error> {
+        let start = self.reader.get_mut().fill_buf().map_err(|err| {
+            Error::Xml(err.into())
+        })?;
+        if start.starts_with(b"\xFE\xFF")
+            || start.starts_with(b"\xFF\xFE")
+            || start.starts_with(b"\0<")
+            || start.starts_with(b"<\0")
+        {
+            return Err(Error::UnsupportedEncoding("UTF-16".into()))
+        }
+        Ok(())
+    }
+
     /// Parse the end of the document.
     ///
     /// This checks that the next non-comment event to be the end of file.
@@ -73,6 +102,56 @@ impl<R: io::BufRead> Reader<R> {
 }
 
 
+/// Decodes a document received from an untrusted source.
+///
+/// The closure `op` is given a reader that provides at most `limit`
+/// octets from `reader` and should perform the actual decoding. If the
+/// document turns out to be larger than `limit` octets, an
+/// [`Error::TooLarge`] is returned no matter what `op` returned.
+///
+/// The decoders in this crate never panic on malformed input and only
+/// allocate memory in proportion to the input they have read. Together with
+/// the size limit imposed by this function, it is safe to use them on
+/// input from hostile sources.
+pub fn decode_untrusted<R, F, T, E>(
+    reader: R, limit: usize, op: F
+) -> Result<T, E>
+where
+    R: io::BufRead,
+    F: FnOnce(&mut io::Take<R>) -> Result<T, E>,
+    E: From<Error>,
+{
+    // We allow one extra octet so we can tell whether there was more
+    // data than allowed.
+    let mut reader = io::Read::take(
+        reader,
+        u64::try_from(limit).unwrap_or(u64::MAX).saturating_add(1)
+    );
+    let res = op(&mut reader);
+    if reader.limit() == 0 {
+        return Err(Error::TooLarge(limit).into())
+    }
+    res
+}
+
+
+/// Checks that the encoding named in an XML declaration is supported.
+fn check_encoding(encoding: &[u8]) -> Result<(), Error> {
+    if encoding.eq_ignore_ascii_case(b"UTF-8")
+        || encoding.eq_ignore_ascii_case(b"UTF8")
+        || encoding.eq_ignore_ascii_case(b"US-ASCII")
+        || encoding.eq_ignore_ascii_case(b"ASCII")
+    {
+        Ok(())
+    }
+    else {
+        Err(Error::UnsupportedEncoding(
+            String::from_utf8_lossy(encoding).into_owned()
+        ))
+    }
+}
+
+
 //------------ Element -------------------------------------------------------
 
 /// The start of an element.
@@ -88,7 +167,7 @@ impl<'b, 'n> Element<'b, 'n> {
     }
 
     /// Returns the name of the element.
-    pub fn name(&self) -> Name {
+    pub fn name(&self) -> Name<'_, '_> {
         Name::new(
             self.ns.map(|ns| ns.0),
             self.start.local_name().into_inner()
@@ -324,6 +403,144 @@ impl Content {
             }
         }
     }
+
+    /// Skips over all remaining content including the end of the element.
+    ///
+    /// Any nested elements and text are skipped, too, without checking
+    /// them further.
+    pub fn skip<R: io::BufRead>(
+        &mut self,
+        reader: &mut Reader<R>
+    ) -> Result<(), Error> {
+        if self.empty {
+            return Ok(())
+        }
+
+        let mut depth = 0usize;
+        loop {
+            reader.buf.clear();
+            match reader.reader.read_event_into(&mut reader.buf)? {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => {
+                    if depth == 0 {
+                        self.empty = true;
+                        return Ok(())
+                    }
+                    depth -= 1;
+                }
+                Event::Eof => return Err(Error::Malformed),
+                _ => { }
+            }
+        }
+    }
+}
+
+
+//------------ UnknownElements -----------------------------------------------
+
+/// Collects elements not known to a decoder.
+///
+/// Decoders normally reject any element they don’t know. This is the
+/// strict mode, which is the default. In lenient mode, unknown elements
+/// and all their content are skipped and their names collected instead.
+/// This allows processing messages that contain extensions defined after
+/// the decoder was written.
+#[derive(Clone, Debug, Default)]
+pub struct UnknownElements {
+    /// Whether unknown elements should be skipped.
+    lenient: bool,
+
+    /// The unknown elements encountered so far.
+    elements: Vec<UnknownElement>,
+}
+
+impl UnknownElements {
+    /// Creates a new value that rejects unknown elements.
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new value that skips and collects unknown elements.
+    pub fn lenient() -> Self {
+        UnknownElements { lenient: true, elements: Vec::new() }
+    }
+
+    /// Returns whether unknown elements are skipped.
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Processes an unknown element.
+    ///
+    /// In strict mode, returns an error. Otherwise, records the element.
+    /// The caller is responsible for skipping the element’s content.
+    pub fn add(&mut self, element: &Element) -> Result<(), Error> {
+        if !self.lenient {
+            return Err(Error::Malformed)
+        }
+        self.elements.push(element.name().into());
+        Ok(())
+    }
+
+    /// Returns whether no unknown elements have been encountered.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns a slice of the encountered unknown elements.
+    pub fn as_slice(&self) -> &[UnknownElement] {
+        &self.elements
+    }
+
+    /// Converts the value into a vec of the encountered unknown elements.
+    pub fn into_vec(self) -> Vec<UnknownElement> {
+        self.elements
+    }
+}
+
+
+//------------ UnknownElement ------------------------------------------------
+
+/// The name of an element that was skipped during lenient decoding.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct UnknownElement {
+    /// The namespace of the element if it had one.
+    namespace: Option<String>,
+
+    /// The local name of the element.
+    local: String,
+}
+
+impl UnknownElement {
+    /// Returns the namespace of the element if it had one.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Returns the local name of the element.
+    pub fn local(&self) -> &str {
+        &self.local
+    }
+}
+
+impl<'n, 'l> From<Name<'n, 'l>> for UnknownElement {
+    fn from(name: Name<'n, 'l>) -> Self {
+        UnknownElement {
+            namespace: name.namespace().map(|ns| {
+                String::from_utf8_lossy(ns).into_owned()
+            }),
+            local: String::from_utf8_lossy(name.local()).into_owned(),
+        }
+    }
+}
+
+impl fmt::Display for UnknownElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.namespace {
+            Some(ref ns) => write!(f, "{{{}}}{}", ns, self.local),
+            None => f.write_str(&self.local)
+        }
+    }
 }
 
 
@@ -436,11 +653,11 @@ impl<'a> AttrValue<'a> {
 pub struct Text<'a>(quick_xml::events::BytesText<'a>);
 
 impl<'a> Text<'a> {
-    pub fn to_utf8(&self) -> Result<Cow<str>, Error> {
+    pub fn to_utf8(&self) -> Result<Cow<'_, str>, Error> {
         Ok(self.0.unescape()?)
     }
 
-    pub fn to_ascii(&self) -> Result<Cow<str>, Error> {
+    pub fn to_ascii(&self) -> Result<Cow<'_, str>, Error> {
         // XXX Shouldn’t this reject non-ASCII Unicode?
         Ok(self.0.unescape()?)
     }
@@ -448,7 +665,7 @@ impl<'a> Text<'a> {
     pub fn base64_decode(&self) -> Result<Vec<u8>, Error> {
         base64::Xml.decode(
             self.to_utf8()?.as_ref()
-        ).map_err(|_| Error::Malformed)
+        ).map_err(Into::into)
     }
 }
 
@@ -456,10 +673,14 @@ impl<'a> Text<'a> {
 //------------ Error ---------------------------------------------------------
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Xml(quick_xml::Error),
     XmlAttr(AttrError),
+    Base64(base64::XmlDecodeError),
+    UnsupportedEncoding(String),
     Malformed,
+    TooLarge(usize),
 }
 
 impl From<quick_xml::Error> for Error {
@@ -475,8 +696,8 @@ impl From<AttrError> for Error {
 }
 
 impl From<base64::XmlDecodeError> for Error {
-    fn from(_: base64::XmlDecodeError) -> Self {
-        Self::Malformed
+    fn from(err: base64::XmlDecodeError) -> Self {
+        Error::Base64(err)
     }
 }
 
@@ -485,9 +706,107 @@ impl fmt::Display for Error {
         match *self {
             Error::Xml(ref err) => err.fmt(f),
             Error::XmlAttr(ref err) => err.fmt(f),
+            Error::Base64(ref err) => write!(f, "invalid Base64: {}", err),
+            Error::UnsupportedEncoding(ref encoding) => {
+                write!(
+                    f, "unsupported character encoding '{}', \
+                        only UTF-8 is supported",
+                    encoding
+                )
+            }
             Error::Malformed => f.write_str("malformed XML"),
+            Error::TooLarge(limit) => {
+                write!(f, "document larger than {} octets", limit)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Xml(ref err) => Some(err),
+            Error::XmlAttr(ref err) => Some(err),
+            Error::Base64(ref err) => Some(err),
+            Error::UnsupportedEncoding(_) => None,
+            Error::Malformed => None,
+            Error::TooLarge(_) => None,
         }
     }
 }
 
-impl error::Error for Error { } 
+
+//============ Tests =========================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn start(data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut reader = Reader::new(data);
+        let mut res = Vec::new();
+        reader.start(|element| {
+            res.extend_from_slice(element.name().local());
+            Ok::<_, Error>(())
+        })?;
+        Ok(res)
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(start(b"<a/>").unwrap(), b"a");
+        assert_eq!(start(b"\xEF\xBB\xBF<a/>").unwrap(), b"a");
+        assert_eq!(
+            start(
+                b"\xEF\xBB\xBF<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                  <a/>"
+            ).unwrap(),
+            b"a"
+        );
+        assert_eq!(
+            start(b"<?xml version=\"1.0\" encoding=\"US-ASCII\"?><a/>")
+                .unwrap(),
+            b"a"
+        );
+        assert!(matches!(
+            start(b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a/>"),
+            Err(Error::UnsupportedEncoding(ref enc)) if enc == "ISO-8859-1"
+        ));
+        assert!(matches!(
+            start(b"\xFF\xFE<\0a\0/\0>\0"),
+            Err(Error::UnsupportedEncoding(_))
+        ));
+        assert!(matches!(
+            start(b"<\0a\0/\0>\0"),
+            Err(Error::UnsupportedEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn untrusted() {
+        fn decode(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+            decode_untrusted(data, limit, |reader| {
+                let mut reader = Reader::new(reader);
+                let mut res = Vec::new();
+                let mut content = reader.start(|element| {
+                    res.extend_from_slice(element.name().local());
+                    Ok::<_, Error>(())
+                })?;
+                content.skip(&mut reader)?;
+                reader.end()?;
+                Ok(res)
+            })
+        }
+
+        let data = b"<a><b>some text</b></a>";
+        assert_eq!(decode(data, data.len()).unwrap(), b"a");
+        assert!(matches!(
+            decode(data, data.len() - 1),
+            Err(Error::TooLarge(_))
+        ));
+        assert!(matches!(
+            decode(b"<a><b>some text</b>", 100),
+            Err(Error::Malformed)
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+/*
+ * Checks that rpki.h compiles and matches the Rust definitions.
+ *
+ * This file is only compiled, never linked. The test in src/capi.rs
+ * passes the sizes and offsets of the Rust types as macros.
+ */
+
+#include <stddef.h>
+#include "rpki.h"
+
+_Static_assert(
+    sizeof(RpkiRoaPrefix) == ROA_PREFIX_SIZE, "RpkiRoaPrefix size"
+);
+_Static_assert(
+    offsetof(RpkiRoaPrefix, addr) == ROA_PREFIX_ADDR, "addr offset"
+);
+_Static_assert(
+    offsetof(RpkiRoaPrefix, prefix_len) == ROA_PREFIX_PREFIX_LEN,
+    "prefix_len offset"
+);
+_Static_assert(
+    offsetof(RpkiRoaPrefix, max_len) == ROA_PREFIX_MAX_LEN,
+    "max_len offset"
+);
+_Static_assert(
+    sizeof(RpkiManifestFile) == MANIFEST_FILE_SIZE, "RpkiManifestFile size"
+);
+_Static_assert(
+    offsetof(RpkiManifestFile, hash) == MANIFEST_FILE_HASH, "hash offset"
+);
+
+int check(const uint8_t *cer, size_t cer_len, const uint8_t *roa_data,
+          size_t roa_len, const uint8_t *crl, size_t crl_len,
+          const uint8_t *mft_data, size_t mft_len, int64_t now)
+{
+    RpkiCert *cert = rpki_cert_decode(cer, cer_len);
+    RpkiResourceCert *ta = NULL;
+    RpkiResourceCert *ca = NULL;
+    RpkiRoa *roa = NULL;
+    RpkiManifest *mft = NULL;
+    RpkiRoaPrefix prefix;
+    RpkiManifestFile file;
+    int res = 0;
+    size_t i;
+
+    if (!cert || !rpki_cert_is_ca(cert)
+        || rpki_cert_not_before(cert) > rpki_cert_not_after(cert))
+        goto out;
+    ta = rpki_cert_validate_ta(cert, true, now);
+    ca = rpki_cert_validate_ca(cert, ta, true, now);
+    roa = rpki_roa_decode(roa_data, roa_len, true);
+    if (rpki_roa_as_id(roa) == 0)
+        goto out;
+    for (i = 0; i < rpki_roa_prefix_count(roa); i++) {
+        if (!rpki_roa_prefix(roa, i, &prefix) || prefix.family == 0)
+            goto out;
+    }
+    res += rpki_roa_validate(roa, ta, crl, crl_len, true, now);
+    mft = rpki_manifest_decode(mft_data, mft_len, true);
+    if (rpki_manifest_this_update(mft) > rpki_manifest_next_update(mft))
+        goto out;
+    for (i = 0; i < rpki_manifest_file_count(mft); i++) {
+        if (!rpki_manifest_file(mft, i, &file) || file.name_len == 0)
+            goto out;
+    }
+    res += rpki_manifest_validate(mft, ta, true, now);
+
+out:
+    rpki_manifest_free(mft);
+    rpki_roa_free(roa);
+    rpki_resource_cert_free(ca);
+    rpki_resource_cert_free(ta);
+    rpki_cert_free(cert);
+    return res;
+}